@@ -0,0 +1,44 @@
+//! The event bursts of [`pipeweld::bench`] under criterion, for telling whether a
+//! change made the state store slower with statistics behind it. Each burst is
+//! timed on its own, from a state read as the server was right before it, and only
+//! the time spent applying its events counts.
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use pipeweld::bench::{self, Setup};
+
+/// A laptop with a headset, and a large setup.
+const SETUPS: [Setup; 2] = [
+    Setup {
+        devices: 4,
+        streams: 8,
+    },
+    Setup {
+        devices: 100,
+        streams: 500,
+    },
+];
+
+fn bursts(c: &mut Criterion) {
+    let scripted = bench::script(&SETUPS).expect("scripting the server's answers");
+    let mut group = c.benchmark_group("events through state");
+    for (setup, bursts) in SETUPS.iter().zip(&scripted) {
+        for burst in bursts {
+            group.bench_function(
+                format!(
+                    "{}, {} devices, {} streams",
+                    burst.name, setup.devices, setup.streams
+                ),
+                |b| {
+                    b.iter_custom(|iterations| {
+                        (0..iterations).map(|_| burst.run().took).sum::<Duration>()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bursts);
+criterion_main!(benches);
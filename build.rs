@@ -0,0 +1,15 @@
+//! Compiles `data/` into the GResource bundle `src/resources.rs` embeds, with
+//! `glib-compile-resources` from glib's development files.
+use std::{env, path::PathBuf, process::Command};
+
+fn main() {
+    println!("cargo:rerun-if-changed=data");
+    let out = PathBuf::from(env::var_os("OUT_DIR").expect("cargo sets OUT_DIR"));
+    let status = Command::new("glib-compile-resources")
+        .arg("--sourcedir=data")
+        .arg(format!("--target={}", out.join("pipeweld.gresource").display()))
+        .arg("data/pipeweld.gresource.xml")
+        .status()
+        .expect("running glib-compile-resources, which comes with glib's development files");
+    assert!(status.success(), "glib-compile-resources failed");
+}
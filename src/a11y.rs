@@ -0,0 +1,232 @@
+//! Using the window without a mouse and through a screen reader. Buttons showing
+//! only an icon are named after their tooltip, or what the icon stands for, and
+//! switches and sliders after the label next to them, once the window is built and
+//! again as rows come and go. Panels and the buttons in them get mnemonics, `Alt`
+//! and the underlined letter, picked so panels don't share one. The volume sliders
+//! tell the volume and device in their value text, which screen readers read out as
+//! it changes; GTK before 4.14 has no announcements of its own.
+use std::collections::HashSet;
+
+use gtk::{accessible::Property, accessible::Relation, prelude::*};
+use leptos::*;
+
+use crate::{
+    devices::DeviceKind,
+    extensions::watch,
+    i18n::{marked, tr, tr_with},
+    state::AppState,
+    ui_state,
+};
+
+/// What the icons pipeweld uses on their own stand for.
+fn icon_meaning(icon: &str) -> Option<&'static str> {
+    Some(match icon {
+        "list-remove-symbolic" => marked("Remove"),
+        "list-add-symbolic" => marked("Add"),
+        "audio-volume-muted-symbolic" => marked("Unmute"),
+        "audio-volume-high-symbolic"
+        | "audio-volume-medium-symbolic"
+        | "audio-volume-low-symbolic" => marked("Mute"),
+        "microphone-sensitivity-muted-symbolic" => marked("Unmute microphone"),
+        "microphone-sensitivity-high-symbolic"
+        | "microphone-sensitivity-medium-symbolic"
+        | "microphone-sensitivity-low-symbolic" => marked("Mute microphone"),
+        "media-playback-start-symbolic" => marked("Play"),
+        "media-playback-pause-symbolic" => marked("Pause"),
+        "media-playback-stop-symbolic" => marked("Stop"),
+        "media-skip-backward-symbolic" => marked("Previous"),
+        "media-skip-forward-symbolic" => marked("Next"),
+        "go-previous-symbolic" => marked("Back"),
+        "go-next-symbolic" => marked("Forward"),
+        "view-refresh-symbolic" => marked("Refresh"),
+        "changes-prevent-symbolic" => marked("Unlock"),
+        "changes-allow-symbolic" => marked("Lock"),
+        "system-suspend-symbolic" => marked("Suspend"),
+        "insert-link-symbolic" => marked("Link"),
+        "audio-speakers-symbolic" => marked("Test the speakers"),
+        _ => return None,
+    })
+}
+
+/// The first label among the widgets next to `widget`, like the one a switch or a
+/// slider's row starts with.
+fn neighbouring_label(widget: &gtk::Widget) -> Option<gtk::Label> {
+    let mut sibling = widget.parent()?.first_child();
+    while let Some(candidate) = sibling {
+        if let Ok(label) = candidate.clone().downcast::<gtk::Label>() {
+            return Some(label);
+        }
+        sibling = candidate.next_sibling();
+    }
+    None
+}
+
+/// Names `widget` for screen readers, if it shows nothing naming it.
+fn name(widget: &gtk::Widget) {
+    let tooltip = widget.tooltip_text();
+    if let Some(button) = widget.downcast_ref::<gtk::Button>() {
+        if button.label().is_some() {
+            return;
+        }
+        let icon = button.icon_name();
+        let meaning = icon.as_deref().and_then(icon_meaning).map(tr);
+        if let Some(name) = tooltip.map(|tooltip| tooltip.to_string()).or(meaning) {
+            widget.update_property(&[Property::Label(&name)]);
+        }
+        return;
+    }
+    // the volume sliders are named by `follow_volume`
+    let named = widget.has_css_class("volume-slider");
+    if !named
+        && (widget.is::<gtk::Switch>() || widget.is::<gtk::Scale>() || widget.is::<gtk::DropDown>())
+    {
+        match neighbouring_label(widget) {
+            Some(label) => widget.update_relation(&[Relation::LabelledBy(&[label.upcast_ref()])]),
+            None => {
+                if let Some(tooltip) = &tooltip {
+                    widget.update_property(&[Property::Label(tooltip)]);
+                }
+            }
+        }
+        if let Some(tooltip) = &tooltip {
+            widget.update_property(&[Property::Description(tooltip)]);
+        }
+    }
+}
+
+fn name_all(widget: &gtk::Widget) {
+    name(widget);
+    let mut child = widget.first_child();
+    while let Some(widget) = child {
+        name_all(&widget);
+        child = widget.next_sibling();
+    }
+}
+
+/// `label` with an underline before its first letter not in `taken`, which it's
+/// added to, `None` when every letter is.
+fn mnemonic(label: &str, taken: &mut HashSet<char>) -> Option<String> {
+    let (at, letter) = label.char_indices().find(|(_, char)| {
+        char.is_alphanumeric() && !taken.contains(&char.to_lowercase().next().unwrap_or(*char))
+    })?;
+    taken.insert(letter.to_lowercase().next().unwrap_or(letter));
+    let escaped = |text: &str| text.replace('_', "__");
+    Some(format!(
+        "{}_{}",
+        escaped(&label[..at]),
+        escaped(&label[at..])
+    ))
+}
+
+/// Buttons with a label inside `widget`, not going into panels within it.
+fn buttons(widget: &gtk::Widget, found: &mut Vec<gtk::Button>) {
+    let mut child = widget.first_child();
+    while let Some(widget) = child {
+        match widget.clone().downcast::<gtk::Button>() {
+            Ok(button) if button.label().is_some() && !button.uses_underline() => {
+                found.push(button)
+            }
+            _ if widget.is::<gtk::Expander>() => {}
+            _ => buttons(&widget, found),
+        }
+        child = widget.next_sibling();
+    }
+}
+
+/// Underlines a letter of each button in `scope`, avoiding the letters in `taken`.
+fn button_mnemonics(scope: &gtk::Widget, taken: &HashSet<char>) {
+    let mut found = Vec::new();
+    buttons(scope, &mut found);
+    let mut taken = taken.clone();
+    for button in found {
+        let Some(label) = button.label() else {
+            continue;
+        };
+        if let Some(underlined) = mnemonic(&label, &mut taken) {
+            button.set_use_underline(true);
+            button.set_label(&underlined);
+        }
+    }
+}
+
+/// Names what needs it and adds the mnemonics, once the panels are translated, see
+/// [`crate::i18n::translate_panels`]. Rows built later are named as panels open and
+/// as they're focused.
+pub fn attach(window: &gtk::ApplicationWindow) {
+    let mut panels = Vec::new();
+    ui_state::expanders(window.upcast_ref(), &mut panels);
+    let mut taken = HashSet::new();
+    for panel in &panels {
+        let Some(label) = panel.label() else {
+            continue;
+        };
+        if let Some(underlined) = mnemonic(&label, &mut taken) {
+            panel.set_use_underline(true);
+            panel.set_label(Some(&underlined));
+        }
+    }
+    button_mnemonics(window.upcast_ref(), &taken);
+    for panel in &panels {
+        if let Some(child) = panel.child() {
+            button_mnemonics(&child, &taken);
+        }
+        panel.connect_expanded_notify(|panel| {
+            if let Some(child) = panel.child().filter(|_| panel.is_expanded()) {
+                name_all(&child);
+            }
+        });
+    }
+    name_all(window.upcast_ref());
+    window.connect_focus_widget_notify(|window| {
+        if let Some(focus) = GtkWindowExt::focus(window) {
+            name(&focus);
+        }
+    });
+}
+
+/// Tells the default device's volume through `widget`'s value text, for the
+/// volume bar and slider.
+pub fn follow_volume(cx: Scope, widget: &gtk::Widget, state: AppState, kind: DeviceKind) {
+    widget.update_property(&[Property::Label(&tr(match kind {
+        DeviceKind::Sink => "Output volume",
+        DeviceKind::Source => "Input volume",
+    }))]);
+    let device = state.default_device(kind);
+    watch(cx, widget, move |widget| {
+        let text = device.with(|device| match device {
+            None => tr("No device"),
+            Some(device) if device.mute => {
+                tr_with("{device}: muted", &[("device", &device.description)])
+            }
+            Some(device) => tr_with(
+                "{device}: {percent}%",
+                &[
+                    ("device", &device.description),
+                    ("percent", &device.volume_percent().to_string()),
+                ],
+            ),
+        });
+        widget.update_property(&[Property::ValueText(&text)]);
+    });
+}
+
+/// Puts the keyboard on the output's volume slider when the window opens, rather
+/// than on whatever comes first, like the error bar's close button.
+pub fn focus_volume(window: &gtk::ApplicationWindow) {
+    fn find(widget: &gtk::Widget) -> Option<gtk::Widget> {
+        if widget.is::<gtk::Scale>() && widget.has_css_class("volume-slider") {
+            return Some(widget.clone());
+        }
+        let mut child = widget.first_child();
+        while let Some(widget) = child {
+            if let Some(found) = find(&widget) {
+                return Some(found);
+            }
+            child = widget.next_sibling();
+        }
+        None
+    }
+    if let Some(slider) = find(window.upcast_ref()) {
+        GtkWindowExt::set_focus(window, Some(&slider));
+    }
+}
@@ -0,0 +1,1505 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, Result};
+use gtk::{glib, prelude::*, Application};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{field, info_span, instrument, warn, Span};
+
+use crate::{
+    alsa,
+    app_outputs::ForcedOutput,
+    audible,
+    auto_pause::OutputClass,
+    backend, bit_perfect, bluetooth, bug_report,
+    channel_mix::{self, ChannelMix},
+    cycle, delay,
+    device_settings::DeviceStore,
+    devices::{Device, DeviceKind},
+    equalizer::{self, Equalizer},
+    errors, export, filter_chain, focus_volume, game_mode,
+    graph_file::GraphFile,
+    groups,
+    headset::{Button, ButtonAction},
+    hold,
+    http_stream::HttpStream,
+    jack::JackActions,
+    loopback::{self, Loopback},
+    media_players::{self, PlayerCommand},
+    midi::{Control, MidiMapping, MidiTarget},
+    modules::Module,
+    noise_suppression,
+    osd::{OsdPlacement, OsdStyle},
+    passthrough::{self, Codec},
+    patchbay,
+    power::BatterySaving,
+    presets,
+    priorities::{self, Priority, Suspend},
+    replay,
+    routing::{self, Snapshot},
+    schedule::Schedule,
+    smart_defaults::SmartDefault,
+    startup,
+    state::AppState,
+    streams::Stream,
+    switch_on_connect::{self, SwitchOnConnect},
+    talkback,
+    theme::ColorScheme,
+    timeline::{self, Source},
+    transport::{self, TransportCommand},
+    upmix::{self, Upmix},
+    virtual_devices::{self, VirtualDevice},
+    workspaces::WorkspaceProfile,
+    zones, AudioControls, DiffValue,
+};
+
+/// Everything pipeweld can be asked to do from outside the main window.
+#[derive(Debug, Clone)]
+pub enum Action {
+    ChangeVolume(DiffValue),
+    /// Nudges the volume of the application with window focus.
+    ChangeFocusedVolume(DiffValue),
+    SetVolume(DeviceKind, String, u32),
+    ToggleMute(DeviceKind),
+    SetMute(DeviceKind, String, bool),
+    /// Switches a device over to another of its ports, like headphones.
+    SetPort {
+        kind: DeviceKind,
+        device: String,
+        port: String,
+    },
+    SetDefault(DeviceKind, String),
+    /// Moves everything playing over to the sink called that.
+    MoveStreams(String),
+    /// Moves one playback stream over to the sink called `sink`.
+    MoveStream {
+        stream: u32,
+        sink: String,
+    },
+    /// Makes the next device of the kind the default.
+    CycleDefault(DeviceKind),
+    /// Leaves a device out of cycling, or takes it back in.
+    ToggleHidden(String),
+    /// Has pipeweld put a device back as it was after it resets itself, or stop to.
+    ToggleRecoverResets(String),
+    SetCardProfile {
+        card: String,
+        profile: String,
+    },
+    /// Connects the Bluetooth device at the BlueZ object path `device`, or
+    /// disconnects it.
+    SetBluetoothConnected {
+        device: String,
+        connected: bool,
+    },
+    /// Copies sink `from` to sink `to`, at a volume of its own.
+    CreateMirror {
+        from: String,
+        to: String,
+    },
+    /// Plays an input on the default output to hear it, or stops.
+    SetListening {
+        source: String,
+        enabled: bool,
+    },
+    /// Unloads a loopback by module index.
+    RemoveLoopback(u32),
+    /// Connects an output port to an input port, by id.
+    Link {
+        output: u32,
+        input: u32,
+    },
+    Unlink {
+        output: u32,
+        input: u32,
+    },
+    /// Drives the JACK transport.
+    Transport(TransportCommand),
+    /// Plays, pauses or skips on the MPRIS player at bus name `player`.
+    ControlPlayer {
+        player: String,
+        command: PlayerCommand,
+    },
+    /// Sets the MPRIS player's own volume, in percent.
+    SetPlayerVolume {
+        player: String,
+        percent: u32,
+    },
+    /// Nudges the default input's volume.
+    ChangeInputVolume(DiffValue),
+    /// Sets a playback stream's volume, in percent.
+    SetStreamVolume(u32, u32),
+    SetStreamMute(u32, bool),
+    /// Sets the volume of every stream of an application, by `application.name`.
+    SetApplicationVolume {
+        application: String,
+        percent: u32,
+    },
+    /// Finds the streams that are audible, and mutes them in turn with `one_by_one`,
+    /// see [`crate::audible`].
+    FindPlaying {
+        one_by_one: bool,
+    },
+    /// Stops muting streams in turn, leaving the one muted for now muted with
+    /// `keep_muted`.
+    StopFinding {
+        keep_muted: bool,
+    },
+    /// Sets a recording's volume, in percent, see [`crate::captures`].
+    SetCaptureVolume(u32, u32),
+    SetCaptureMute(u32, bool),
+    /// Moves one recording over to the source called `source`.
+    MoveCapture {
+        capture: u32,
+        source: String,
+    },
+    /// Routes the microphone `source` to `sink` while ducking what plays there, see
+    /// [`crate::talkback`].
+    StartTalkback {
+        source: String,
+        sink: String,
+    },
+    StopTalkback,
+    /// Filters noise out of the default input, or stops, see [`crate::noise_suppression`].
+    SetNoiseSuppression(bool),
+    /// Sets each channel of a device, in the order of its channel map.
+    SetChannelVolumes {
+        kind: DeviceKind,
+        device: String,
+        percents: Vec<u32>,
+    },
+    /// Puts a remix on top of the sink called `device`, or removes it for `None`.
+    SetChannelMix {
+        device: String,
+        mix: Option<ChannelMix>,
+    },
+    /// Holds back playback on the sink called `device`, `None` plays it right away.
+    SetDelay {
+        device: String,
+        delay_ms: Option<u32>,
+    },
+    /// Plays the sink called `device` through an imported preset, or without for `None`.
+    SetPreset {
+        device: String,
+        preset: Option<String>,
+    },
+    /// The preset for `port` of the sink called `device`, `None` to use the sink's own.
+    SetPortPreset {
+        device: String,
+        port: String,
+        preset: Option<String>,
+    },
+    /// Plays the sink called that through the preset of its active port, or its own.
+    ApplyPreset(String),
+    /// Gain in dB in front of the preset of the sink called `device`, `None` for none.
+    SetTrim {
+        device: String,
+        trim_db: Option<i32>,
+    },
+    /// Ceiling in dBFS of a limiter behind the preset of the sink called `device`,
+    /// `None` for none.
+    SetLimiter {
+        device: String,
+        limit_db: Option<i32>,
+    },
+    /// Keeps an EasyEffects preset and plays the default output through it.
+    ImportPreset(PathBuf),
+    /// Equalizer of the sink called `device`, played after its preset, `None` for
+    /// none.
+    SetEqualizer {
+        device: String,
+        equalizer: Option<Equalizer>,
+    },
+    /// Reads an Equalizer APO configuration, like AutoEq's, into the equalizer of the
+    /// default output.
+    ImportEqualizer(PathBuf),
+    /// Upmix settings for the sink called `device` and everything playing on it.
+    SetUpmix {
+        device: String,
+        upmix: Option<Upmix>,
+    },
+    /// Passes upmix settings on to one stream.
+    UpmixStream(u32, Upmix),
+    /// Compressed formats the sink called `device` passes through.
+    SetPassthrough {
+        device: String,
+        codecs: Vec<Codec>,
+    },
+    /// Scheduling priorities of `device`, `None` leaves them to the session manager.
+    SetPriority {
+        device: String,
+        priority: Option<Priority>,
+    },
+    /// Switches one of the default exclusions on or off.
+    SetExclusion {
+        description: String,
+        enabled: bool,
+    },
+    RestartSessionManager,
+    /// When `device` suspends while idle, or the session manager's default for `None`,
+    /// from the next WirePlumber restart on.
+    SetSuspend {
+        device: String,
+        suspend: Option<Suspend>,
+    },
+    /// Bit-perfect playback on the sink called `device`, or the filters back on it.
+    SetBitPerfect {
+        device: String,
+        bit_perfect: bool,
+    },
+    /// Runs the graph at `rate`, or at a rate of its own choosing for 0.
+    ForceRate(u32),
+    /// Runs the graph at `quantum` frames per cycle, or at its own for 0.
+    ForceQuantum(u32),
+    /// Suspends or wakes up `device` right away.
+    SetSuspended {
+        kind: DeviceKind,
+        device: String,
+        suspended: bool,
+    },
+    /// A virtual sink called `name`, unless there is a sink called that already.
+    CreateSink {
+        name: String,
+        description: Option<String>,
+    },
+    /// Links the volumes of sinks `members`.
+    CreateGroup {
+        group: String,
+        members: Vec<String>,
+    },
+    RemoveGroup(String),
+    /// Moves a whole group, keeping the balance between its members.
+    SetGroupVolume {
+        group: String,
+        percent: u32,
+    },
+    /// Plays to sinks `members` together, as zone `zone`.
+    CreateZone {
+        zone: String,
+        members: Vec<String>,
+    },
+    RemoveZone(String),
+    /// Moves all current playback over to a zone.
+    SendToZone(String),
+    /// Holds streams of `application` at `percent`, or lets them go for `None`.
+    SetVolumeLock {
+        application: String,
+        percent: Option<u32>,
+    },
+    /// Has streams of `application` follow the output's volume at `share` percent of
+    /// it, or lets them go for `None`.
+    SetRelativeVolume {
+        application: String,
+        share: Option<u32>,
+    },
+    /// Sends streams of `application` to an output, at a rate, or lets them go for
+    /// `None`.
+    SetForcedOutput {
+        application: String,
+        forced: Option<ForcedOutput>,
+    },
+    /// Saves the current routing as preset `name`, replacing any of that name.
+    SaveRouting(String),
+    ApplyRouting(String),
+    RemoveRouting(String),
+    /// Writes the whole graph to a JSON file, see [`crate::graph_file`].
+    ExportGraph(PathBuf),
+    /// Puts back the routing of a graph file.
+    ImportGraph(PathBuf),
+    /// Applies a routing preset at times of the week, see [`crate::schedule`].
+    AddSchedule(Schedule),
+    /// Removes the schedule at that index.
+    RemoveSchedule(usize),
+    /// Loads `device` as virtual device `name`, replacing any of the same name.
+    CreateVirtualDevice {
+        name: String,
+        device: VirtualDevice,
+    },
+    RemoveVirtualDevice(String),
+    /// Serves the default sink to browsers on the network, on a port.
+    StartHttpStream(u16),
+    StopHttpStream,
+    /// Saves the current setup as a shell script recreating it.
+    ExportScript(PathBuf),
+    /// Sets an ALSA mixer control, when there is no sound server.
+    SetAlsaVolume {
+        card: u32,
+        control: String,
+        percent: u32,
+    },
+    ToggleAlsaMute {
+        card: u32,
+        control: String,
+    },
+    /// Default devices for a workspace or display, `None` leaves them alone there.
+    SetWorkspaceProfile {
+        key: String,
+        profile: Option<WorkspaceProfile>,
+    },
+    /// Default devices to pick again whenever exactly this hardware is connected.
+    LearnSmartDefault(SmartDefault),
+    /// Forgets the default devices learned for this hardware.
+    ForgetSmartDefault(BTreeSet<String>),
+    /// Drops everything pipeweld stores about a device.
+    ForgetDevice(String),
+    ToggleDoNotDisturb,
+    ToggleNightMode,
+    ToggleHdmiAutoSwitch,
+    /// Keeps rules from switching devices for `minutes`, or until released for
+    /// `None`, see [`crate::hold`].
+    HoldRouting {
+        minutes: Option<u32>,
+    },
+    ReleaseRouting,
+    ToggleGameMode,
+    /// What changes while on battery from now on.
+    SetBatterySaving(BatterySaving),
+    ToggleMoveStreams,
+    /// Hides the window instead of closing it, keeping pipeweld in the tray.
+    ToggleCloseToTray,
+    /// Brings up the window, opening it if it's not.
+    ShowWindow,
+    /// Hides the window while it shows, brings it up otherwise.
+    ToggleWindow,
+    /// Maps a headset button to an action, or leaves it to media players for `None`.
+    MapHeadsetButton {
+        button: Button,
+        action: Option<ButtonAction>,
+    },
+    /// Listens to controls of a MIDI port, or to none.
+    SetMidiPort(Option<String>),
+    /// Maps the next MIDI control moved to a target, or stops waiting for one.
+    LearnMidi(Option<MidiTarget>),
+    /// Maps a MIDI control, or forgets it for `None`.
+    MapMidiControl {
+        control: Control,
+        target: Option<MidiTarget>,
+    },
+    SetOsdStyle(OsdStyle),
+    /// Makes the window light or dark, or leaves it to the desktop.
+    SetColorScheme(ColorScheme),
+    /// Switches metering the default output for clipping on or off.
+    SetClippingWarning(bool),
+    /// Where the volume popup goes, see [`crate::osd::OsdPlacement`].
+    SetOsdPlacement(OsdPlacement),
+    /// Keeps the volume popup from showing for changes by `source`, or shows it again.
+    HideOsdFor {
+        source: Source,
+        hidden: bool,
+    },
+    /// Pauses the players when an output of `class` goes away, or stops doing so.
+    PauseOnDisconnect {
+        class: OutputClass,
+        pause: bool,
+    },
+    /// Seconds outputs that went away are kept for, 0 forgets them right away.
+    SetGracePeriod(u64),
+    /// What plugging headphones into a jack does from now on.
+    SetJackActions(JackActions),
+    /// Whether devices connecting become the default and streams follow from now on.
+    SetSwitchOnConnect(SwitchOnConnect),
+    /// Takes the media keys through the desktop portal, or gives them back.
+    SetGlobalShortcuts(bool),
+    /// Puts versions, devices and recent failures on the clipboard, for bug reports.
+    CopyDiagnostics,
+    Quit,
+}
+
+/// A change for the server alone, run off the main loop.
+type Command = Box<dyn FnOnce() -> Result<()> + Send>;
+
+impl Action {
+    /// Name of the variant, like `SetVolume`.
+    pub fn name(&self) -> String {
+        format!("{self:?}")
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect()
+    }
+
+    /// The device, stream or application the action is about, if it's about one.
+    pub fn target(&self) -> Option<String> {
+        Some(match self {
+            Self::ChangeVolume(_) | Self::ChangeFocusedVolume(_) => {
+                DeviceKind::Sink.default_alias().to_owned()
+            }
+            Self::ChangeInputVolume(_) => DeviceKind::Source.default_alias().to_owned(),
+            Self::ToggleMute(kind) | Self::CycleDefault(kind) => kind.default_alias().to_owned(),
+            Self::SetVolume(_, device, _)
+            | Self::SetMute(_, device, _)
+            | Self::SetDefault(_, device)
+            | Self::SetPort { device, .. }
+            | Self::SetChannelVolumes { device, .. }
+            | Self::SetSuspended { device, .. }
+            | Self::SetChannelMix { device, .. }
+            | Self::SetDelay { device, .. }
+            | Self::SetPreset { device, .. }
+            | Self::SetPortPreset { device, .. }
+            | Self::ApplyPreset(device)
+            | Self::SetTrim { device, .. }
+            | Self::SetLimiter { device, .. }
+            | Self::SetEqualizer { device, .. }
+            | Self::SetUpmix { device, .. }
+            | Self::SetPassthrough { device, .. }
+            | Self::SetPriority { device, .. }
+            | Self::SetSuspend { device, .. }
+            | Self::SetBitPerfect { device, .. }
+            | Self::SetBluetoothConnected { device, .. }
+            | Self::SetListening { source: device, .. }
+            | Self::ToggleHidden(device)
+            | Self::ToggleRecoverResets(device)
+            | Self::ForgetDevice(device)
+            | Self::MoveStreams(device) => device.clone(),
+            Self::SetCardProfile { card, .. } => card.clone(),
+            Self::MoveStream { stream, .. }
+            | Self::SetStreamVolume(stream, _)
+            | Self::SetStreamMute(stream, _)
+            | Self::UpmixStream(stream, _) => format!("stream #{stream}"),
+            Self::SetCaptureVolume(capture, _)
+            | Self::SetCaptureMute(capture, _)
+            | Self::MoveCapture { capture, .. } => format!("capture #{capture}"),
+            Self::Link { output, input } | Self::Unlink { output, input } => {
+                format!("{output} -> {input}")
+            }
+            Self::CreateMirror { from, to } => format!("{from} -> {to}"),
+            Self::StartTalkback { source, sink } => format!("{source} -> {sink}"),
+            Self::SetApplicationVolume { application, .. }
+            | Self::SetVolumeLock { application, .. }
+            | Self::SetRelativeVolume { application, .. }
+            | Self::SetForcedOutput { application, .. } => application.clone(),
+            Self::ControlPlayer { player, .. } | Self::SetPlayerVolume { player, .. } => {
+                player.clone()
+            }
+            Self::CreateGroup { group, .. }
+            | Self::RemoveGroup(group)
+            | Self::SetGroupVolume { group, .. } => group.clone(),
+            Self::CreateZone { zone, .. } | Self::RemoveZone(zone) | Self::SendToZone(zone) => {
+                zone.clone()
+            }
+            Self::CreateSink { name, .. }
+            | Self::CreateVirtualDevice { name, .. }
+            | Self::RemoveVirtualDevice(name) => name.clone(),
+            Self::SetAlsaVolume { card, control, .. } | Self::ToggleAlsaMute { card, control } => {
+                format!("card {card} {control}")
+            }
+            _ => return None,
+        })
+    }
+
+    /// What the action comes down to when it only asks the server for a change, to
+    /// run in the background, see [`AudioControls::in_background`]. Settings it
+    /// changes are kept here, on the main loop. Anything else touching pipeweld's
+    /// own state comes back and runs on the main loop.
+    fn command(self, state: AppState) -> Result<Command, Self> {
+        Ok(match self {
+            Self::ChangeVolume(diff) => {
+                let max = state.default_sink_max_volume();
+                Box::new(move || backend::current().change_volume(DeviceKind::Sink, diff, max))
+            }
+            Self::ChangeInputVolume(diff) => {
+                Box::new(move || backend::current().change_volume(DeviceKind::Source, diff, None))
+            }
+            Self::SetVolume(kind, device, percent) => {
+                let percent = match state.device_named(kind, &device) {
+                    Some(found) => state
+                        .device_settings
+                        .with_untracked(|store| store.limit_volume(&found, percent)),
+                    None => percent,
+                };
+                Box::new(move || backend::current().set_volume(kind, &device, percent))
+            }
+            // the server's event shows the new state
+            Self::ToggleMute(kind) => Box::new(move || backend::current().toggle_mute(kind)),
+            Self::SetMute(kind, device, mute) => {
+                Box::new(move || backend::current().set_mute(kind, &device, mute))
+            }
+            Self::SetDefault(kind, name) => {
+                Box::new(move || AudioControls::set_default(kind, &name))
+            }
+            Self::SetCardProfile { card, profile } => {
+                Box::new(move || AudioControls::set_card_profile(&card, &profile))
+            }
+            Self::SetBluetoothConnected { device, connected } => {
+                Box::new(move || bluetooth::set_connected(&device, connected))
+            }
+            Self::MoveStream { stream, sink } => {
+                Box::new(move || AudioControls::move_stream(stream, &sink))
+            }
+            Self::SetStreamVolume(stream, percent) => {
+                Box::new(move || AudioControls::set_stream_volume(stream, percent))
+            }
+            Self::SetStreamMute(stream, mute) => {
+                Box::new(move || AudioControls::set_stream_mute(stream, mute))
+            }
+            Self::SetApplicationVolume {
+                application,
+                percent,
+            } => Box::new(move || set_application_volume(&application, percent)),
+            Self::SetCaptureVolume(capture, percent) => {
+                Box::new(move || AudioControls::set_capture_volume(capture, percent))
+            }
+            Self::SetCaptureMute(capture, mute) => {
+                Box::new(move || AudioControls::set_capture_mute(capture, mute))
+            }
+            Self::MoveCapture { capture, source } => {
+                Box::new(move || AudioControls::move_capture(capture, &source))
+            }
+            Self::SetNoiseSuppression(enabled) => Box::new(move || noise_suppression::set(enabled)),
+            // players answer slowly at times, and not to the server
+            Self::ControlPlayer { player, command } => {
+                Box::new(move || media_players::send(&player, command))
+            }
+            Self::SetPlayerVolume { player, percent } => {
+                Box::new(move || media_players::set_volume(&player, percent))
+            }
+            Self::SetChannelVolumes {
+                kind,
+                device,
+                percents,
+            } => {
+                // the limit holds for every channel
+                let percents = match state.device_named(kind, &device) {
+                    Some(found) => state.device_settings.with_untracked(|store| {
+                        percents
+                            .into_iter()
+                            .map(|percent| store.limit_volume(&found, percent))
+                            .collect()
+                    }),
+                    None => percents,
+                };
+                Box::new(move || AudioControls::set_channel_volumes(kind, &device, &percents))
+            }
+            Self::SetPort { kind, device, port } => {
+                Box::new(move || AudioControls::set_port(kind, &device, &port))
+            }
+            Self::MoveStreams(sink) => Box::new(move || {
+                let index = Device::list(DeviceKind::Sink)?
+                    .into_iter()
+                    .find(|candidate| candidate.name == sink)
+                    .map(|sink| sink.index);
+                Stream::list()?
+                    .iter()
+                    .filter(|stream| Some(stream.sink) != index)
+                    .try_for_each(|stream| AudioControls::move_stream(stream.index, &sink))
+            }),
+            Self::CycleDefault(kind) => {
+                let store = state.device_settings.get_untracked();
+                Box::new(move || cycle::cycle(kind, &store))
+            }
+            Self::CreateMirror { from, to } => {
+                Box::new(move || Loopback::mirror(&from, &to).map(drop))
+            }
+            Self::SetListening { source, enabled } => {
+                Box::new(move || loopback::set_listening(&source, enabled))
+            }
+            Self::RemoveLoopback(module) => Box::new(move || Module::unload(module)),
+            Self::Link { output, input } => Box::new(move || patchbay::link(output, input)),
+            Self::Unlink { output, input } => Box::new(move || patchbay::unlink(output, input)),
+            Self::Transport(command) => Box::new(move || transport::send(command)),
+            // settings are kept right away, applying them waits for the server
+            Self::SetChannelMix { device, mix } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).channel_mix = mix);
+                let saved = state.device_settings.with_untracked(DeviceStore::save);
+                // reconnecting brings it back, see rules::RestoreOnConnect
+                Box::new(move || {
+                    saved?;
+                    match Device::list(DeviceKind::Sink)?
+                        .into_iter()
+                        .find(|sink| sink.name == device)
+                    {
+                        Some(sink) => channel_mix::apply(&sink, mix),
+                        None => Ok(()),
+                    }
+                })
+            }
+            Self::SetDelay { device, delay_ms } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).delay_ms = delay_ms);
+                let saved = state.device_settings.with_untracked(DeviceStore::save);
+                let filters = state.filters.get_value();
+                Box::new(move || {
+                    saved?;
+                    match Device::list(DeviceKind::Sink)?
+                        .into_iter()
+                        .find(|sink| sink.name == device)
+                    {
+                        Some(sink) => delay::apply(&mut filters.lock(), &sink, delay_ms),
+                        None => Ok(()),
+                    }
+                })
+            }
+            Self::UpmixStream(index, upmix) => Box::new(move || {
+                Stream::list()?
+                    .iter()
+                    .find(|stream| stream.index == index)
+                    .map_or(Ok(()), |stream| upmix::apply(stream, &upmix))
+            }),
+            Self::SetPassthrough { device, codecs } => {
+                let formats = passthrough::formats(&codecs);
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).passthrough = Some(codecs));
+                let saved = state.device_settings.with_untracked(DeviceStore::save);
+                Box::new(move || {
+                    saved?;
+                    AudioControls::set_sink_formats(&device, &formats)
+                })
+            }
+            Self::RestartSessionManager => Box::new(priorities::restart_session_manager),
+            Self::ForceRate(rate) => Box::new(move || bit_perfect::set_force_rate(rate)),
+            Self::ForceQuantum(quantum) => Box::new(move || game_mode::set_force_quantum(quantum)),
+            Self::SetSuspended {
+                kind,
+                device,
+                suspended,
+            } => Box::new(move || AudioControls::set_suspended(kind, &device, suspended)),
+            Self::CreateSink { name, description } => {
+                Box::new(move || startup::create_sink(&name, description.as_deref()))
+            }
+            Self::SendToZone(zone) => Box::new(move || zones::send_playback(&zone)),
+            Self::SetVolumeLock {
+                application,
+                percent,
+            } => {
+                state.device_settings.update(|store| match percent {
+                    Some(percent) => {
+                        store.locked_volumes.insert(application.clone(), percent);
+                    }
+                    None => {
+                        store.locked_volumes.remove(&application);
+                    }
+                });
+                let saved = state.device_settings.with_untracked(DeviceStore::save);
+                Box::new(move || {
+                    saved?;
+                    match percent {
+                        Some(percent) => set_application_volume(&application, percent),
+                        None => Ok(()),
+                    }
+                })
+            }
+            Self::SetForcedOutput {
+                application,
+                forced,
+            } => {
+                // what plays moves right away, the rate waits for its next stream
+                let sink = forced.as_ref().map(|forced| forced.sink.clone());
+                state.device_settings.update(|store| match forced {
+                    Some(forced) => drop(store.forced_outputs.insert(application.clone(), forced)),
+                    None => drop(store.forced_outputs.remove(&application)),
+                });
+                let saved = state.device_settings.with_untracked(DeviceStore::save);
+                Box::new(move || {
+                    saved?;
+                    let Some(sink) = sink else {
+                        return Ok(());
+                    };
+                    Stream::list()?
+                        .iter()
+                        .filter(|stream| {
+                            stream.properties.get("application.name") == Some(&application)
+                        })
+                        .try_for_each(|stream| AudioControls::move_stream(stream.index, &sink))
+                })
+            }
+            Self::SetAlsaVolume {
+                card,
+                control,
+                percent,
+            } => Box::new(move || alsa::set_volume(card, &control, percent)),
+            Self::ToggleAlsaMute { card, control } => {
+                Box::new(move || alsa::toggle(card, &control))
+            }
+            Self::ApplyRouting(name) => Box::new(move || Snapshot::load(&name)?.apply()),
+            Self::ExportGraph(path) => Box::new(move || GraphFile::take()?.write(&path)),
+            Self::ImportGraph(path) => Box::new(move || GraphFile::read(&path)?.apply()),
+            action => return Err(action),
+        })
+    }
+
+    #[instrument(skip(app, state), ret, err)]
+    pub fn run(self, app: &Application, state: AppState) -> Result<()> {
+        // what only asks the server for a change runs right away, rather than in the background
+        let action = match self.command(state) {
+            Ok(command) => return command(),
+            Err(action) => action,
+        };
+        match action {
+            Self::ChangeFocusedVolume(diff) => state
+                .focused_window
+                .with_untracked(|window| focus_volume::change_volume(window.as_ref(), diff)),
+            Self::ToggleHidden(name) => {
+                state.device_settings.update(|store| {
+                    let settings = store.entry(&name);
+                    settings.hidden = !settings.hidden;
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::ToggleRecoverResets(name) => {
+                state.device_settings.update(|store| {
+                    let settings = store.entry(&name);
+                    settings.recover_resets = !settings.recover_resets;
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::FindPlaying { one_by_one } => audible::find(state, one_by_one),
+            Self::StopFinding { keep_muted } => audible::stop(state, keep_muted),
+            Self::StartTalkback { source, sink } => talkback::start(state, &source, &sink),
+            Self::StopTalkback => talkback::stop(state),
+            Self::SetPreset { device, preset } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).preset = preset);
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                apply_preset(state, &device)
+            }
+            Self::SetPortPreset {
+                device,
+                port,
+                preset,
+            } => {
+                state.device_settings.update(|store| {
+                    let presets = &mut store.entry(&device).port_presets;
+                    match preset {
+                        Some(preset) => presets.insert(port, preset),
+                        None => presets.remove(&port),
+                    };
+                });
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                apply_preset(state, &device)
+            }
+            Self::ApplyPreset(device) => apply_preset(state, &device),
+            Self::SetTrim { device, trim_db } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).trim_db = trim_db);
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                apply_preset(state, &device)
+            }
+            Self::SetLimiter { device, limit_db } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).limit_db = limit_db);
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                apply_preset(state, &device)
+            }
+            Self::ImportPreset(path) => {
+                let preset = presets::import(&path)?;
+                let Some(device) = state.default_sink.with_untracked(|sink| {
+                    sink.as_ref()
+                        .map(|sink| filter_chain::master_name(sink).to_owned())
+                }) else {
+                    return Ok(());
+                };
+                Self::SetPreset {
+                    device,
+                    preset: Some(preset),
+                }
+                .run(app, state)
+            }
+            Self::SetEqualizer { device, equalizer } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).equalizer = equalizer);
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                apply_preset(state, &device)
+            }
+            Self::ImportEqualizer(path) => {
+                let equalizer = equalizer::import(&path)?;
+                let Some(device) = state.default_sink.with_untracked(|sink| {
+                    sink.as_ref()
+                        .map(|sink| filter_chain::master_name(sink).to_owned())
+                }) else {
+                    return Ok(());
+                };
+                Self::SetEqualizer {
+                    device,
+                    equalizer: Some(equalizer),
+                }
+                .run(app, state)
+            }
+            Self::SetUpmix { device, upmix } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).upmix = upmix);
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                let (Some(upmix), Some(sink)) = (
+                    upmix,
+                    Device::list(DeviceKind::Sink)?
+                        .into_iter()
+                        .find(|sink| sink.name == device),
+                ) else {
+                    return Ok(());
+                };
+                Stream::list()?
+                    .iter()
+                    .filter(|stream| stream.sink == sink.index)
+                    .try_for_each(|stream| upmix::apply(stream, &upmix))
+            }
+            Self::SetPriority { device, priority } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).priority = priority);
+                state.device_settings.with_untracked(|store| {
+                    store.save()?;
+                    priorities::write_rules(store).map(drop)
+                })
+            }
+            Self::SetExclusion {
+                description,
+                enabled,
+            } => {
+                state.device_settings.update(|store| {
+                    store
+                        .exclusions
+                        .0
+                        .iter_mut()
+                        .filter(|exclusion| exclusion.description == description)
+                        .for_each(|exclusion| exclusion.enabled = enabled)
+                });
+                state.device_settings.with_untracked(|store| {
+                    store.save()?;
+                    priorities::write_rules(store).map(drop)
+                })
+            }
+            Self::SetSuspend { device, suspend } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).suspend = suspend);
+                state.device_settings.with_untracked(|store| {
+                    store.save()?;
+                    priorities::write_rules(store).map(drop)
+                })
+            }
+            Self::SetBitPerfect {
+                device,
+                bit_perfect,
+            } => {
+                state
+                    .device_settings
+                    .update(|store| store.entry(&device).bit_perfect = bit_perfect);
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                match Device::list(DeviceKind::Sink)?
+                    .into_iter()
+                    .find(|sink| sink.name == device)
+                {
+                    Some(sink) => bit_perfect::apply(state, &sink, bit_perfect),
+                    None => Ok(()),
+                }
+            }
+            Self::CreateGroup { group, members } => {
+                state
+                    .device_settings
+                    .update(|store| drop(store.groups.insert(group, members)));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::RemoveGroup(group) => {
+                state
+                    .device_settings
+                    .update(|store| drop(store.groups.remove(&group)));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetGroupVolume { group, percent } => {
+                state.device_settings.with_untracked(|store| {
+                    store.groups.get(&group).map_or(Ok(()), |members| {
+                        groups::set_volume(store, members, percent)
+                    })
+                })
+            }
+            Self::CreateZone { zone, members } => {
+                zones::unload(&zone)?;
+                zones::load(&zone, &members)?;
+                state
+                    .device_settings
+                    .update(|store| drop(store.zones.insert(zone, members)));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::RemoveZone(zone) => {
+                zones::unload(&zone)?;
+                state
+                    .device_settings
+                    .update(|store| drop(store.zones.remove(&zone)));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetRelativeVolume { application, share } => {
+                state.device_settings.update(|store| match share {
+                    Some(share) => drop(store.relative_volumes.insert(application, share)),
+                    None => drop(store.relative_volumes.remove(&application)),
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SaveRouting(name) => {
+                Snapshot::take()?.save(&name)?;
+                state.routings.set(routing::saved()?);
+                Ok(())
+            }
+            Self::RemoveRouting(name) => {
+                routing::remove(&name)?;
+                state.routings.set(routing::saved()?);
+                Ok(())
+            }
+            Self::AddSchedule(schedule) => {
+                state
+                    .device_settings
+                    .update(|store| store.schedules.push(schedule));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::RemoveSchedule(index) => {
+                state.device_settings.update(|store| {
+                    if index < store.schedules.len() {
+                        store.schedules.remove(index);
+                    }
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::CreateVirtualDevice { name, device } => {
+                virtual_devices::unload(&name)?;
+                device.load(&name)?;
+                state
+                    .device_settings
+                    .update(|store| drop(store.virtual_devices.insert(name, device)));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::RemoveVirtualDevice(name) => {
+                virtual_devices::unload(&name)?;
+                state
+                    .device_settings
+                    .update(|store| drop(store.virtual_devices.remove(&name)));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::StartHttpStream(port) => {
+                let sink = state
+                    .default_sink
+                    .with_untracked(|sink| sink.as_ref().map(|sink| sink.name.clone()))
+                    .ok_or_else(|| eyre!("there is no default sink to stream"))?;
+                // the old server has to let go of the port first
+                state.http_stream.set_value(None);
+                state.http_stream_port.set(None);
+                let stream = HttpStream::start(&sink, port)?;
+                state.http_stream.set_value(Some(stream));
+                state.http_stream_port.set(Some(port));
+                Ok(())
+            }
+            Self::StopHttpStream => {
+                state.http_stream.set_value(None);
+                state.http_stream_port.set(None);
+                Ok(())
+            }
+            Self::ExportScript(path) => {
+                let mut written = Ok(());
+                state
+                    .filters
+                    .with_value(|filters| written = export::write(&filters.lock(), &path));
+                written
+            }
+            Self::SetWorkspaceProfile { key, profile } => {
+                state.device_settings.update(|store| match profile {
+                    Some(profile) => drop(store.workspaces.insert(key, profile)),
+                    None => drop(store.workspaces.remove(&key)),
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::LearnSmartDefault(learned) => {
+                state.device_settings.update(|store| {
+                    store
+                        .smart_defaults
+                        .retain(|other| other.hardware != learned.hardware);
+                    store.smart_defaults.push(learned);
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::ForgetSmartDefault(hardware) => {
+                state.device_settings.update(|store| {
+                    store
+                        .smart_defaults
+                        .retain(|learned| learned.hardware != hardware)
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::ForgetDevice(name) => {
+                let had_priority = state.device_settings.with_untracked(|store| {
+                    store
+                        .devices
+                        .get(&name)
+                        .is_some_and(|settings| settings.priority.is_some())
+                });
+                state.device_settings.update(|store| store.forget(&name));
+                state.device_settings.with_untracked(|store| {
+                    store.save()?;
+                    match had_priority {
+                        true => priorities::write_rules(store).map(drop),
+                        false => Ok(()),
+                    }
+                })
+            }
+            Self::ToggleDoNotDisturb => {
+                state.do_not_disturb.update(|dnd| *dnd = !*dnd);
+                Ok(())
+            }
+            Self::ToggleNightMode => {
+                state
+                    .night_mode
+                    .update(|night_mode| *night_mode = !*night_mode);
+                Ok(())
+            }
+            Self::ToggleHdmiAutoSwitch => {
+                state.device_settings.update(|store| {
+                    store.hdmi_auto_switch = !store.hdmi_auto_switch;
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::HoldRouting { minutes } => hold::hold(state, minutes),
+            Self::ReleaseRouting => {
+                state.hold.set(hold::Hold::Off);
+                Ok(())
+            }
+            Self::ToggleCloseToTray => {
+                state
+                    .device_settings
+                    .update(|store| store.close_to_tray = !store.close_to_tray);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::ShowWindow => {
+                app.activate();
+                Ok(())
+            }
+            Self::ToggleWindow => {
+                match app.windows().into_iter().find(|window| window.is_visible()) {
+                    Some(window) => window.set_visible(false),
+                    None => app.activate(),
+                }
+                Ok(())
+            }
+            Self::ToggleGameMode => {
+                state
+                    .device_settings
+                    .update(|store| store.game_mode = !store.game_mode);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetBatterySaving(saving) => {
+                state
+                    .device_settings
+                    .update(|store| store.battery_saving = saving);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::ToggleMoveStreams => {
+                state
+                    .device_settings
+                    .update(|store| store.move_streams = !store.move_streams);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::MapHeadsetButton { button, action } => {
+                state.device_settings.update(|store| match action {
+                    Some(action) => {
+                        store.headset_buttons.insert(button, action);
+                    }
+                    None => {
+                        store.headset_buttons.remove(&button);
+                    }
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetMidiPort(port) => {
+                state.device_settings.update(|store| store.midi_port = port);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::LearnMidi(target) => {
+                state.midi_learning.set(target);
+                Ok(())
+            }
+            Self::MapMidiControl { control, target } => {
+                state.device_settings.update(|store| {
+                    store
+                        .midi_mappings
+                        .retain(|mapping| mapping.control != control);
+                    if let Some(target) = target {
+                        store.midi_mappings.push(MidiMapping { control, target });
+                    }
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetOsdStyle(style) => {
+                state.osd_style.set(style);
+                Ok(())
+            }
+            Self::SetColorScheme(scheme) => {
+                state
+                    .device_settings
+                    .update(|store| store.color_scheme = scheme);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetClippingWarning(enabled) => {
+                state
+                    .device_settings
+                    .update(|store| store.clipping_warning = enabled);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetOsdPlacement(placement) => {
+                state
+                    .device_settings
+                    .update(|store| store.osd_placement = placement);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::HideOsdFor { source, hidden } => {
+                state.device_settings.update(|store| {
+                    match hidden {
+                        true => store.osd_hidden_for.insert(source),
+                        false => store.osd_hidden_for.remove(&source),
+                    };
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::PauseOnDisconnect { class, pause } => {
+                state.device_settings.update(|store| {
+                    match pause {
+                        true => store.pause_on_disconnect.insert(class),
+                        false => store.pause_on_disconnect.remove(&class),
+                    };
+                });
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetGracePeriod(seconds) => {
+                state
+                    .device_settings
+                    .update(|store| store.grace_period_secs = Some(seconds));
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetJackActions(actions) => {
+                state
+                    .device_settings
+                    .update(|store| store.headphone_jack = actions);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::SetSwitchOnConnect(picked) => {
+                state
+                    .device_settings
+                    .update(|store| store.switch_on_connect = picked);
+                state.device_settings.with_untracked(DeviceStore::save)?;
+                switch_on_connect::apply(picked)
+            }
+            Self::SetGlobalShortcuts(enabled) => {
+                state
+                    .device_settings
+                    .update(|store| store.global_shortcuts = enabled);
+                state.device_settings.with_untracked(DeviceStore::save)
+            }
+            Self::CopyDiagnostics => bug_report::copy(state),
+            Self::Quit => {
+                // cleaning up is the application's shutdown
+                app.quit();
+                Ok(())
+            }
+            Self::ChangeVolume(_)
+            | Self::ChangeInputVolume(_)
+            | Self::SetVolume(..)
+            | Self::ToggleMute(_)
+            | Self::SetMute(..)
+            | Self::SetDefault(..)
+            | Self::SetCardProfile { .. }
+            | Self::SetBluetoothConnected { .. }
+            | Self::MoveStream { .. }
+            | Self::SetStreamVolume(..)
+            | Self::SetStreamMute(..)
+            | Self::SetApplicationVolume { .. }
+            | Self::SetCaptureVolume(..)
+            | Self::SetCaptureMute(..)
+            | Self::MoveCapture { .. }
+            | Self::SetNoiseSuppression(_)
+            | Self::ControlPlayer { .. }
+            | Self::SetPlayerVolume { .. }
+            | Self::SetChannelVolumes { .. }
+            | Self::SetPort { .. }
+            | Self::MoveStreams(_)
+            | Self::CycleDefault(_)
+            | Self::CreateMirror { .. }
+            | Self::SetListening { .. }
+            | Self::RemoveLoopback(_)
+            | Self::Link { .. }
+            | Self::Unlink { .. }
+            | Self::Transport(_)
+            | Self::SetChannelMix { .. }
+            | Self::SetDelay { .. }
+            | Self::UpmixStream(..)
+            | Self::SetPassthrough { .. }
+            | Self::RestartSessionManager
+            | Self::ForceRate(_)
+            | Self::ForceQuantum(_)
+            | Self::SetSuspended { .. }
+            | Self::CreateSink { .. }
+            | Self::SendToZone(_)
+            | Self::SetVolumeLock { .. }
+            | Self::SetForcedOutput { .. }
+            | Self::SetAlsaVolume { .. }
+            | Self::ToggleAlsaMute { .. }
+            | Self::ApplyRouting(_)
+            | Self::ExportGraph(_)
+            | Self::ImportGraph(_) => unreachable!("ran as a command above"),
+        }
+    }
+}
+
+fn set_application_volume(application: &str, percent: u32) -> Result<()> {
+    Stream::list()?
+        .iter()
+        .filter(|stream| {
+            stream
+                .properties
+                .get("application.name")
+                .map(String::as_str)
+                == Some(application)
+        })
+        .try_for_each(|stream| AudioControls::set_stream_volume(stream.index, percent))
+}
+
+/// Plays the sink called `device` through its preset, trim and limiter as saved.
+fn apply_preset(state: AppState, device: &str) -> Result<()> {
+    let Some(sink) = Device::list(DeviceKind::Sink)?
+        .into_iter()
+        .find(|sink| sink.name == device)
+    else {
+        return Ok(());
+    };
+    let (preset, equalizer, trim_db, limit_db) = state.device_settings.with_untracked(|store| {
+        store
+            .get(&sink)
+            .map(|settings| {
+                let preset = settings.preset_for(sink.active_port.as_deref());
+                (
+                    preset.map(str::to_owned),
+                    settings.equalizer().cloned(),
+                    settings.trim_db,
+                    settings.limit_db,
+                )
+            })
+            .unwrap_or_default()
+    });
+    let mut applied = Ok(());
+    state.filters.with_value(|host| {
+        let host = &mut *host.lock();
+        applied = presets::apply(
+            host,
+            &sink,
+            preset.as_deref(),
+            equalizer.as_ref(),
+            trim_db,
+            limit_db,
+        );
+    });
+    applied
+}
+
+/// How volume steps grow while they come in quick succession, like a held key
+/// repeating. Kept in the device settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Acceleration {
+    /// Steps closer together than this, in the same direction, count as one press.
+    pub within_ms: u64,
+    /// What the step is multiplied by, press after press, the last one from then
+    /// on. Empty or `[1]` keeps steps as they are.
+    pub curve: Vec<u32>,
+}
+
+impl Default for Acceleration {
+    /// 5, 10, then 20 percent for the usual step.
+    fn default() -> Self {
+        Self {
+            within_ms: 400,
+            curve: vec![1, 2, 4],
+        }
+    }
+}
+
+/// The run of volume steps in progress, see [`Acceleration`].
+#[derive(Debug, Default)]
+struct Presses {
+    last: Option<(Instant, bool)>,
+    count: usize,
+}
+
+impl Presses {
+    fn accelerate(&mut self, action: Action, acceleration: &Acceleration) -> Action {
+        let (Action::ChangeVolume(DiffValue(step)) | Action::ChangeFocusedVolume(DiffValue(step))) =
+            action
+        else {
+            return action;
+        };
+        let now = Instant::now();
+        let up = step > 0;
+        let within = Duration::from_millis(acceleration.within_ms);
+        self.count = match self.last {
+            Some((at, direction)) if direction == up && now.duration_since(at) < within => {
+                self.count + 1
+            }
+            _ => 0,
+        };
+        self.last = Some((now, up));
+        let factor = acceleration
+            .curve
+            .get(self.count)
+            .or(acceleration.curve.last())
+            .map_or(1, |factor| *factor as i32);
+        let diff = DiffValue(step.saturating_mul(factor));
+        match action {
+            Action::ChangeFocusedVolume(_) => Action::ChangeFocusedVolume(diff),
+            _ => Action::ChangeVolume(diff),
+        }
+    }
+}
+
+/// Numbers actions in the order they're dispatched, to tell them apart in the logs.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// An action on its way, for its span and the summary of `--trace-actions`.
+struct Traced {
+    id: u64,
+    name: String,
+    target: Option<String>,
+    automated: bool,
+    started: Instant,
+    span: Span,
+}
+
+impl Traced {
+    fn new(action: &Action, automated: bool) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let name = action.name();
+        let target = action.target();
+        let span = info_span!(
+            "action",
+            id,
+            name = %name,
+            target = target.as_deref().unwrap_or(""),
+            automated,
+            outcome = field::Empty,
+        );
+        Self {
+            id,
+            name,
+            target,
+            automated,
+            started: Instant::now(),
+            span,
+        }
+    }
+
+    /// Notes how the action went in its span and, with `--trace-actions`, prints a
+    /// line telling what it was about, how it went and how long it took.
+    fn finish(&self, ran: &Result<()>) {
+        let outcome = match ran {
+            Ok(()) => "ok".to_owned(),
+            Err(error) => format!("failed: {error}"),
+        };
+        self.span.record("outcome", outcome.as_str());
+        if !crate::trace_actions() {
+            return;
+        }
+        let target = self
+            .target
+            .as_deref()
+            .map_or_else(String::new, |target| format!(" {target}"));
+        let by = match self.automated {
+            true => " (automated)",
+            false => "",
+        };
+        eprintln!(
+            "#{} {}{target}{by}: {outcome} in {} ms",
+            self.id,
+            self.name,
+            self.started.elapsed().as_millis()
+        );
+    }
+}
+
+fn record(state: AppState, mut cause: String, ran: Result<()>) {
+    if let Err(error) = ran {
+        errors::report(state, &cause, &error);
+        cause.push_str(&format!(", failed: {error}"));
+    }
+    timeline::record(state, cause);
+}
+
+/// Hands actions over to the GTK main loop, from wherever they originate.
+/// Volume steps are accelerated here, see [`Acceleration`], so every way of
+/// stepping the volume does so alike.
+#[derive(Clone)]
+pub struct Dispatcher {
+    sender: Arc<Mutex<glib::Sender<(Action, bool)>>>,
+    automated: bool,
+}
+
+impl Dispatcher {
+    pub fn attach(app: &Application, state: AppState) -> Self {
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let app = app.clone();
+        let presses = RefCell::new(Presses::default());
+        receiver.attach(None, move |(action, automated): (Action, bool)| {
+            // only steps asked for, rules keep theirs
+            let action = match automated {
+                true => action,
+                false => state.device_settings.with_untracked(|store| {
+                    presses.borrow_mut().accelerate(action, &store.acceleration)
+                }),
+            };
+            let cause = format!("{action:?}");
+            replay::record_action(&cause, automated);
+            let traced = Traced::new(&action, automated);
+            let run = || match action.command(state) {
+                // recorded once the server answered
+                Ok(command) => {
+                    let span = traced.span.clone();
+                    AudioControls::in_background(
+                        move || span.in_scope(command),
+                        move |ran| {
+                            traced.finish(&ran);
+                            record(state, cause, ran);
+                        },
+                    )
+                }
+                Err(action) => {
+                    let ran = traced.span.in_scope(|| action.run(&app, state));
+                    traced.finish(&ran);
+                    record(state, cause, ran);
+                }
+            };
+            match automated {
+                true => crate::automated(run),
+                false => run(),
+            }
+            glib::Continue(true)
+        });
+        Self {
+            sender: Arc::new(Mutex::new(sender)),
+            automated: false,
+        }
+    }
+
+    /// A dispatcher for pipeweld's own automation, whose actions run as
+    /// [`crate::automated`].
+    pub fn automated(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            automated: true,
+        }
+    }
+
+    pub fn dispatch(&self, action: Action) {
+        // a thread panicking while sending leaves the sender as it was
+        let sender = self.sender.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(error) = sender.send((action, self.automated)) {
+            warn!(%error, "main loop is gone, dropping action");
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Plain ALSA, for systems running neither PipeWire nor PulseAudio. Cards come
+//! from `/proc/asound/cards` and their simple mixer controls from `amixer`,
+//! alsa-lib's own mixer tool.
+use std::process::Command;
+
+use eyre::{Result, WrapErr};
+use gtk::{prelude::*, Application, ApplicationWindow, Orientation};
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    checked_output,
+    i18n::tr,
+    run_change,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Card {
+    pub index: u32,
+    pub id: String,
+    pub name: String,
+}
+
+impl Card {
+    /// Parses lines like ` 0 [PCH            ]: HDA-Intel - HDA Intel PCH`.
+    pub fn list() -> Result<Vec<Self>> {
+        let cards = std::fs::read_to_string("/proc/asound/cards").wrap_err("reading ALSA cards")?;
+        Ok(cards
+            .lines()
+            .filter_map(|line| {
+                let (index, rest) = line.trim_start().split_once(' ')?;
+                let (id, rest) = rest.trim_start().strip_prefix('[')?.split_once(']')?;
+                let name = rest.split_once(" - ").map_or(rest, |(_, name)| name).trim();
+                Some(Self {
+                    index: index.parse().ok()?,
+                    id: id.trim().to_owned(),
+                    name: name.to_owned(),
+                })
+            })
+            .collect())
+    }
+}
+
+/// A simple mixer control with a playback volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Control {
+    /// As `amixer` addresses it, e.g. `'Master',0`.
+    pub name: String,
+    pub percent: u32,
+    /// `None` for controls without a switch.
+    pub on: Option<bool>,
+}
+
+fn amixer(card: u32, args: &[&str]) -> Result<String> {
+    let run = match args.contains(&"sset") {
+        true => run_change,
+        false => checked_output,
+    };
+    let stdout = run(Command::new("amixer")
+        .arg("-c")
+        .arg(card.to_string())
+        .args(args))?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+impl Control {
+    pub fn list(card: u32) -> Result<Vec<Self>> {
+        Ok(Self::parse(&amixer(card, &["scontents"])?))
+    }
+
+    /// Reads `amixer scontents`, keeping controls with a playback volume.
+    pub fn parse(contents: &str) -> Vec<Self> {
+        contents
+            .split("Simple mixer control ")
+            .filter_map(|block| {
+                let mut lines = block.lines();
+                let name = lines.next()?.trim().to_owned();
+                let lines = lines.collect::<Vec<_>>();
+                if !lines.iter().any(|line| {
+                    line.trim_start().starts_with("Capabilities:") && line.contains("pvolume")
+                }) {
+                    return None;
+                }
+                // the first channel stands for all of them
+                let levels = lines.iter().find(|line| {
+                    line.contains("Playback") && line.contains('%') && line.contains(':')
+                })?;
+                let fields = levels
+                    .split('[')
+                    .filter_map(|part| part.split_once(']').map(|(inside, _)| inside))
+                    .collect::<Vec<_>>();
+                Some(Self {
+                    name,
+                    percent: fields
+                        .iter()
+                        .find_map(|field| field.strip_suffix('%')?.parse().ok())?,
+                    on: fields.iter().find_map(|field| match *field {
+                        "on" => Some(true),
+                        "off" => Some(false),
+                        _ => None,
+                    }),
+                })
+            })
+            .collect()
+    }
+}
+
+#[instrument(ret, err)]
+pub fn set_volume(card: u32, control: &str, percent: u32) -> Result<()> {
+    amixer(card, &["-q", "sset", control, &format!("{percent}%")]).map(drop)
+}
+
+#[instrument(ret, err)]
+pub fn toggle(card: u32, control: &str) -> Result<()> {
+    amixer(card, &["-q", "sset", control, "toggle"]).map(drop)
+}
+
+fn card_panel(card: &Card, dispatcher: &Dispatcher) -> gtk::Box {
+    let panel = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(4)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let title = gtk::Label::new(Some(&card.name));
+    title.add_css_class("heading");
+    title.set_halign(gtk::Align::Start);
+    panel.append(&title);
+    let controls = Control::list(card.index)
+        .map_err(|error| warn!(?error, "listing controls of {}", card.id))
+        .unwrap_or_default();
+    for control in controls {
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        row.append(&gtk::Label::new(Some(
+            control.name.trim_end_matches(",0").trim_matches('\''),
+        )));
+        let volume = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+        volume.set_hexpand(true);
+        volume.set_value(control.percent.into());
+        volume.connect_value_changed({
+            let dispatcher = dispatcher.clone();
+            let (card, name) = (card.index, control.name.clone());
+            move |volume| {
+                dispatcher.dispatch(Action::SetAlsaVolume {
+                    card,
+                    control: name.clone(),
+                    percent: volume.value() as u32,
+                })
+            }
+        });
+        row.append(&volume);
+        if let Some(on) = control.on {
+            let mute = gtk::CheckButton::with_label(&tr("Muted"));
+            mute.set_active(!on);
+            let dispatcher = dispatcher.clone();
+            let (card, name) = (card.index, control.name.clone());
+            mute.connect_toggled(move |_| {
+                dispatcher.dispatch(Action::ToggleAlsaMute {
+                    card,
+                    control: name.clone(),
+                })
+            });
+            row.append(&mute);
+        }
+        panel.append(&row);
+    }
+    panel
+}
+
+/// The window shown instead of the usual one when there is no sound server to talk to.
+pub fn build_fallback_ui(app: &Application, dispatcher: Dispatcher) {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let notice = gtk::Label::new(Some(
+        "Neither PipeWire nor PulseAudio is running, showing the ALSA mixer controls.",
+    ));
+    notice.set_wrap(true);
+    notice.set_margin_top(12);
+    notice.set_margin_bottom(12);
+    layout.append(&notice);
+    match Card::list() {
+        Ok(cards) if !cards.is_empty() => cards
+            .iter()
+            .for_each(|card| layout.append(&card_panel(card, &dispatcher))),
+        Ok(_) => layout.append(&gtk::Label::new(Some(&tr("No sound cards found.")))),
+        Err(error) => {
+            warn!(?error, "listing ALSA cards");
+            layout.append(&gtk::Label::new(Some(&tr("ALSA isn't available either."))));
+        }
+    }
+    ApplicationWindow::builder()
+        .application(app)
+        .title(clap::crate_name!())
+        .child(&gtk::ScrolledWindow::builder().child(&layout).build())
+        .default_height(400)
+        .build()
+        .present();
+}
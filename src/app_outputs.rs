@@ -0,0 +1,162 @@
+//! Outputs forced on applications, like a DAW always playing on the audio interface
+//! at 96 kHz, never resampled. They're kept by `application.name` with the device
+//! settings, and the `forced-output` rule moves every new stream of the application
+//! to its output and, for a rate, runs the graph at it like bit-perfect playback
+//! does, see [`crate::bit_perfect`]. The rate the graph ran at before is put back
+//! once the last of those streams is gone.
+use std::collections::BTreeSet;
+
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    events::{self, Facility},
+    i18n::tr,
+    pro_audio::RATES,
+    state::AppState,
+    streams::Stream,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForcedOutput {
+    /// Name of the sink the application's streams go to.
+    pub sink: String,
+    /// Rate the graph runs at while they play, `None` leaving it be.
+    pub rate: Option<u32>,
+}
+
+/// Applications that are playing.
+fn playing() -> BTreeSet<String> {
+    Stream::list()
+        .map_err(|error| warn!(?error, "listing streams to force outputs on"))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|stream| stream.properties.get("application.name").cloned())
+        .collect()
+}
+
+fn rate_label(rate: u32) -> String {
+    format!("{} kHz", f64::from(rate) / 1000.)
+}
+
+/// Expander with a row for each application that is playing or has an output forced
+/// on it, picking the output and the rate it plays at.
+pub fn output_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    let current = create_rw_signal(cx, playing());
+    events::subscribe(move |event| {
+        if event.facility == Facility::SinkInput {
+            current.set(playing());
+        }
+    });
+    // only applications, outputs or what's forced changing rebuild the rows
+    let listed = create_memo(cx, move |_| {
+        let forced = state
+            .device_settings
+            .with(|store| store.forced_outputs.clone());
+        let mut applications = current.get();
+        applications.extend(forced.keys().cloned());
+        let sinks = state.sinks.with(|sinks| {
+            sinks
+                .iter()
+                .map(|sink| (sink.name.clone(), sink.description.clone()))
+                .collect::<Vec<_>>()
+        });
+        (applications, forced, sinks)
+    });
+    let layout = rows.clone();
+    create_effect(cx, move |_| {
+        let (applications, forced, sinks) = listed.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        if applications.is_empty() {
+            let empty = gtk::Label::new(Some(&tr("Nothing is playing.")));
+            empty.add_css_class("dim-label");
+            rows.append(&empty);
+        }
+        for application in applications {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            let label = gtk::Label::new(Some(&application));
+            label.set_hexpand(true);
+            label.set_halign(gtk::Align::Start);
+            label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            row.append(&label);
+            let picked = forced.get(&application);
+
+            // the output forced on it stays listed while it's unplugged
+            let mut outputs = sinks.clone();
+            if let Some(picked) = picked {
+                if !outputs.iter().any(|(name, _)| *name == picked.sink) {
+                    outputs.push((picked.sink.clone(), picked.sink.clone()));
+                }
+            }
+            let wherever = tr("Wherever it plays");
+            let labels = std::iter::once(wherever.as_str())
+                .chain(outputs.iter().map(|(_, label)| label.as_str()))
+                .collect::<Vec<_>>();
+            let sink = gtk::DropDown::from_strings(&labels);
+            sink.set_tooltip_text(Some(&tr("Output its streams go to")));
+            sink.set_selected(
+                picked
+                    .and_then(|picked| outputs.iter().position(|(name, _)| *name == picked.sink))
+                    .map_or(0, |position| position as u32 + 1),
+            );
+            row.append(&sink);
+            let labels = std::iter::once(tr("Any rate"))
+                .chain(RATES.iter().copied().map(rate_label))
+                .collect::<Vec<_>>();
+            let rate =
+                gtk::DropDown::from_strings(&labels.iter().map(String::as_str).collect::<Vec<_>>());
+            rate.set_tooltip_text(Some(&tr("Rate the graph runs at while it plays")));
+            rate.set_selected(
+                picked
+                    .and_then(|picked| RATES.iter().position(|rate| Some(*rate) == picked.rate))
+                    .map_or(0, |position| position as u32 + 1),
+            );
+            rate.set_sensitive(picked.is_some());
+            row.append(&rate);
+
+            let set = {
+                let dispatcher = dispatcher.clone();
+                let (sink, rate) = (sink.clone(), rate.clone());
+                move || {
+                    let forced = match sink.selected() {
+                        0 => None,
+                        position => {
+                            outputs
+                                .get(position as usize - 1)
+                                .map(|(name, _)| ForcedOutput {
+                                    sink: name.clone(),
+                                    rate: match rate.selected() {
+                                        0 => None,
+                                        position => RATES.get(position as usize - 1).copied(),
+                                    },
+                                })
+                        }
+                    };
+                    dispatcher.dispatch(Action::SetForcedOutput {
+                        application: application.clone(),
+                        forced,
+                    });
+                }
+            };
+            sink.connect_selected_notify({
+                let set = set.clone();
+                move |_| set()
+            });
+            rate.connect_selected_notify(move |_| set());
+            rows.append(&row);
+        }
+    });
+    gtk::Expander::builder()
+        .label("Application outputs")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,264 @@
+//! Finding what plays, like the browser tab with an ad: every stream that isn't
+//! muted is metered for a moment, and the audible ones are highlighted in the
+//! mixer, loudest first. Going one by one then mutes each of them in turn for a
+//! few seconds, until the user stops at the one it was, keeping it muted or not.
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    time::Duration,
+};
+
+use eyre::Result;
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    extensions::watch,
+    i18n::{tr, tr_with},
+    levels::Meter,
+    notifications::{Notification, Notifier},
+    state::AppState,
+    streams::Stream,
+    AudioControls,
+};
+
+/// How long streams are metered for.
+const LISTEN: Duration = Duration::from_millis(1500);
+/// How long each stream stays muted while going one by one.
+const STEP: Duration = Duration::from_secs(3);
+/// Peaks above this, about -50 dBFS, are audible.
+const AUDIBLE: f32 = 0.003;
+
+/// A stream that played something while listening.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audible {
+    pub stream: u32,
+    pub title: String,
+    pub peak: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Finding {
+    pub listening: bool,
+    /// What was found last, loudest first, `None` before finding anything.
+    pub audible: Option<Vec<Audible>>,
+    /// The stream muted for now, while going one by one.
+    pub muted: Option<u32>,
+}
+
+/// Meters every stream that isn't muted for [`LISTEN`], then hands the audible
+/// ones to `on_done`, loudest first.
+fn listen(on_done: impl FnOnce(Vec<Audible>) + 'static) -> Result<()> {
+    let streams = Stream::list()?
+        .into_iter()
+        .filter(|stream| !stream.mute && stream.volume_percent() > 0)
+        .collect::<Vec<_>>();
+    let peaks = Rc::new(RefCell::new(HashMap::<u32, f32>::new()));
+    let meters = streams
+        .iter()
+        .filter_map(|stream| {
+            let peaks = peaks.clone();
+            let index = stream.index;
+            Meter::stream(index, move |level| {
+                let mut peaks = peaks.borrow_mut();
+                let peak = peaks.entry(index).or_default();
+                *peak = peak.max(level.peak);
+            })
+            .ok()
+        })
+        .collect::<Vec<_>>();
+    glib::timeout_add_local_once(LISTEN, move || {
+        drop(meters);
+        let peaks = peaks.borrow();
+        let mut audible = streams
+            .iter()
+            .filter_map(|stream| {
+                let peak = *peaks.get(&stream.index)?;
+                (peak > AUDIBLE).then(|| Audible {
+                    stream: stream.index,
+                    title: stream.title(),
+                    peak,
+                })
+            })
+            .collect::<Vec<_>>();
+        audible.sort_by(|first, second| second.peak.total_cmp(&first.peak));
+        on_done(audible);
+    });
+    Ok(())
+}
+
+fn notify(notifier: &Option<Notifier>, summary: &str, body: String) {
+    if let Some(notifier) = notifier {
+        notifier.show(
+            Notification::new(summary)
+                .body(body)
+                .icon("audio-volume-high-symbolic"),
+        );
+    }
+}
+
+/// Finds the audible streams, and with `one_by_one` goes on muting them in turn.
+/// Does nothing while already finding.
+pub fn find(state: AppState, one_by_one: bool) -> Result<()> {
+    if state
+        .finding
+        .with_untracked(|finding| finding.listening || finding.muted.is_some())
+    {
+        return Ok(());
+    }
+    // the window may well be hidden when this comes from the tray
+    let notifier = Notifier::new()
+        .map_err(|error| warn!(?error, "what plays won't be notified about"))
+        .ok();
+    listen(move |audible| {
+        info!(?audible, "audible streams");
+        let titles = audible
+            .iter()
+            .map(|audible| audible.title.as_str())
+            .collect::<Vec<_>>();
+        match titles.is_empty() {
+            true => notify(&notifier, &tr("Nothing is playing"), String::new()),
+            false => notify(&notifier, &tr("Playing"), titles.join("\n")),
+        }
+        let queue = audible.iter().map(|audible| audible.stream).collect();
+        state.finding.set(Finding {
+            listening: false,
+            audible: Some(audible),
+            muted: None,
+        });
+        if one_by_one {
+            mute_in_turn(state, queue, notifier);
+        }
+    })?;
+    state.finding.update(|finding| finding.listening = true);
+    Ok(())
+}
+
+/// Mutes the first stream of `queue` for [`STEP`], then unmutes it and goes on with
+/// the others, unless stopped meanwhile.
+fn mute_in_turn(state: AppState, mut queue: VecDeque<u32>, notifier: Option<Notifier>) {
+    let Some(stream) = queue.pop_front() else {
+        return state.finding.update(|finding| finding.muted = None);
+    };
+    if let Err(error) = AudioControls::set_stream_mute(stream, true) {
+        // it went away meanwhile
+        warn!(?error, "muting stream {stream} in turn");
+        return mute_in_turn(state, queue, notifier);
+    }
+    let title = state.finding.with_untracked(|finding| {
+        finding
+            .audible
+            .iter()
+            .flatten()
+            .find(|audible| audible.stream == stream)
+            .map(|audible| audible.title.clone())
+            .unwrap_or_default()
+    });
+    notify(&notifier, &tr("Muted for now"), title);
+    state.finding.update(|finding| finding.muted = Some(stream));
+    glib::timeout_add_local_once(STEP, move || {
+        if state.finding.with_untracked(|finding| finding.muted) != Some(stream) {
+            return;
+        }
+        if let Err(error) = AudioControls::set_stream_mute(stream, false) {
+            warn!(?error, "unmuting stream {stream} in turn");
+        }
+        mute_in_turn(state, queue, notifier);
+    });
+}
+
+/// Stops going one by one at the stream muted for now, which `keep_muted` leaves
+/// muted.
+pub fn stop(state: AppState, keep_muted: bool) -> Result<()> {
+    let muted = state.finding.with_untracked(|finding| finding.muted);
+    state.finding.update(|finding| finding.muted = None);
+    match muted {
+        Some(stream) if !keep_muted => AudioControls::set_stream_mute(stream, false),
+        _ => Ok(()),
+    }
+}
+
+/// Buttons finding what plays, what was found, and while going one by one the
+/// stream muted for now with buttons stopping at it.
+pub fn finder_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    layout.set_margin_top(6);
+    let buttons = gtk::Box::new(Orientation::Horizontal, 6);
+    for (label, one_by_one) in [
+        (tr("Find what's playing"), false),
+        (tr("Mute one by one"), true),
+    ] {
+        let button = gtk::Button::with_label(&label);
+        button.connect_clicked({
+            let dispatcher = dispatcher.clone();
+            move |_| dispatcher.dispatch(Action::FindPlaying { one_by_one })
+        });
+        watch(cx, &button, move |button| {
+            button.set_sensitive(
+                state
+                    .finding
+                    .with(|finding| !finding.listening && finding.muted.is_none()),
+            )
+        });
+        buttons.append(&button);
+    }
+    layout.append(&buttons);
+    let found = gtk::Label::new(None);
+    found.set_wrap(true);
+    found.set_xalign(0.);
+    found.add_css_class("dim-label");
+    layout.append(&found);
+    let muted = gtk::Box::new(Orientation::Horizontal, 6);
+    let title = gtk::Label::new(None);
+    title.set_hexpand(true);
+    title.set_halign(gtk::Align::Start);
+    title.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    muted.append(&title);
+    for (label, keep_muted) in [(tr("That's it"), true), (tr("Stop"), false)] {
+        let button = gtk::Button::with_label(&label);
+        button.connect_clicked({
+            let dispatcher = dispatcher.clone();
+            move |_| dispatcher.dispatch(Action::StopFinding { keep_muted })
+        });
+        muted.append(&button);
+    }
+    layout.append(&muted);
+    watch(cx, &muted, move |muted| {
+        state.finding.with(|finding| {
+            found.set_text(&match (finding.listening, finding.audible.as_deref()) {
+                (true, _) => tr("Listening…"),
+                (false, None) => String::new(),
+                (false, Some([])) => tr("Nothing audible is playing."),
+                (false, Some(audible)) => tr_with(
+                    "Playing: {titles}",
+                    &[(
+                        "titles",
+                        &audible
+                            .iter()
+                            .map(|audible| audible.title.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    )],
+                ),
+            });
+            let current = finding.muted.and_then(|stream| {
+                finding
+                    .audible
+                    .iter()
+                    .flatten()
+                    .find(|audible| audible.stream == stream)
+            });
+            muted.set_visible(current.is_some());
+            if let Some(current) = current {
+                title.set_text(&tr_with(
+                    "Muted for now: {title}",
+                    &[("title", &current.title)],
+                ));
+            }
+        })
+    });
+    layout
+}
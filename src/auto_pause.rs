@@ -0,0 +1,190 @@
+//! Pausing media players when the output they play on goes away, like headphones
+//! pulled out of the jack or a Bluetooth headset running out of battery, instead of
+//! carrying on through the laptop speakers. Which kinds of outputs count is up to
+//! the user. Players are reached over MPRIS.
+use eyre::Result;
+use gtk::{
+    glib::{ToVariant, Variant},
+    prelude::*,
+    Orientation,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    filter_chain, jack,
+    media_players::{self, PLAYER_INTERFACE, PLAYER_PATH},
+    state::AppState,
+    troubleshoot,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputClass {
+    Bluetooth,
+    Usb,
+    /// Headphones or a headset in a jack of a built-in card.
+    Headphones,
+    /// Anything else, like HDMI.
+    Other,
+}
+
+impl OutputClass {
+    pub const ALL: [Self; 4] = [Self::Bluetooth, Self::Usb, Self::Headphones, Self::Other];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Bluetooth => "Bluetooth devices",
+            Self::Usb => "USB devices",
+            Self::Headphones => "Headphones in a jack",
+            Self::Other => "Other outputs",
+        }
+    }
+
+    /// The class of `device` played through `port`.
+    pub fn of(device: &Device, port: Option<&str>) -> Self {
+        if troubleshoot::is_bluetooth(device) {
+            return Self::Bluetooth;
+        }
+        if device.properties.get("device.bus").map(String::as_str) == Some("usb") {
+            return Self::Usb;
+        }
+        match port {
+            Some(port) if jack::is_jack(port) => Self::Headphones,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// The class of the output `previous` was playing on, if it's gone now: the device
+/// disappeared, or the port it played through became unavailable.
+fn vanished(previous: &Device, sinks: &[Device]) -> Option<OutputClass> {
+    let port = previous.active_port.as_deref();
+    let Some(current) = sinks.iter().find(|sink| sink.name == previous.name) else {
+        return Some(OutputClass::of(previous, port));
+    };
+    let unplugged = current.active_port.as_deref() != port
+        && current
+            .ports
+            .iter()
+            .any(|candidate| Some(candidate.name.as_str()) == port && candidate.is_unavailable());
+    unplugged.then(|| OutputClass::of(previous, port))
+}
+
+/// Pauses every MPRIS player that's playing, returning their bus names.
+#[instrument(ret, err)]
+pub fn pause_players() -> Result<Vec<String>> {
+    let connection = media_players::session_bus()?;
+    let mut paused = Vec::new();
+    for name in media_players::player_names(&connection)? {
+        let status = media_players::call(
+            &connection,
+            &name,
+            PLAYER_PATH,
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            Some(&(PLAYER_INTERFACE, "PlaybackStatus").to_variant()),
+        )
+        .map(|status| {
+            status
+                .get::<(Variant,)>()
+                .and_then(|(status,)| status.get::<String>())
+        });
+        match status {
+            Ok(Some(status)) if status == "Playing" => {}
+            Ok(_) => continue,
+            Err(error) => {
+                warn!(?error, "asking a player whether it plays");
+                continue;
+            }
+        }
+        match media_players::call(
+            &connection,
+            &name,
+            PLAYER_PATH,
+            PLAYER_INTERFACE,
+            "Pause",
+            None,
+        ) {
+            Ok(_) => paused.push(name),
+            Err(error) => warn!(?error, "pausing a player"),
+        }
+    }
+    Ok(paused)
+}
+
+/// Follows the real device behind the default output and pauses the players once it
+/// goes away, if it's of a class the user picked.
+pub fn spawn(cx: Scope, state: AppState) {
+    create_effect(cx, move |previous: Option<Option<Device>>| {
+        let master = state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        });
+        let sinks = match Device::list(DeviceKind::Sink) {
+            Ok(sinks) => sinks,
+            Err(error) => {
+                warn!(?error, "following the output for pausing players");
+                return previous.flatten();
+            }
+        };
+        let class = previous
+            .flatten()
+            .and_then(|previous| vanished(&previous, &sinks).map(|class| (previous, class)));
+        if let Some((previous, class)) = class {
+            let pause = state
+                .device_settings
+                .with_untracked(|store| store.pause_on_disconnect.contains(&class));
+            if pause {
+                info!(
+                    ?class,
+                    "{} went away, pausing players", previous.description
+                );
+                std::thread::spawn(pause_players);
+            }
+        }
+        master.and_then(|master| sinks.into_iter().find(|sink| sink.name == master))
+    });
+}
+
+/// Expander picking the kinds of outputs whose going away pauses the players.
+pub fn pause_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let hint = gtk::Label::new(Some(
+        "Pauses media players when the output goes away, rather than playing on through \
+         the next one.",
+    ));
+    hint.set_wrap(true);
+    hint.set_max_width_chars(60);
+    hint.add_css_class("dim-label");
+    layout.append(&hint);
+    for class in OutputClass::ALL {
+        let pause = move || {
+            state
+                .device_settings
+                .with_untracked(|store| store.pause_on_disconnect.contains(&class))
+        };
+        let check = gtk::CheckButton::with_label(class.label());
+        check.set_active(pause());
+        let dispatcher = dispatcher.clone();
+        check.connect_toggled(move |check| {
+            if check.is_active() != pause() {
+                dispatcher.dispatch(Action::PauseOnDisconnect {
+                    class,
+                    pause: check.is_active(),
+                });
+            }
+        });
+        layout.append(&check);
+    }
+    gtk::Expander::builder()
+        .label("Pause when the output goes away")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
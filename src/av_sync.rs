@@ -0,0 +1,228 @@
+//! Beep-and-flash test for lining up sound with the picture. A beep plays on the
+//! default output once a second while the window flashes along with it; the user
+//! shifts the flash until both land together, and the shift tells how far the
+//! sink's delay is off.
+use std::{
+    cell::{Cell, RefCell},
+    path::PathBuf,
+    process::{Child, Command},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use eyre::{Result, WrapErr};
+use gtk::{glib, prelude::*, Application, Orientation};
+use leptos::*;
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    delay::MAX_DELAY_MS,
+    filter_chain,
+    i18n::{tr, tr_with},
+    modules::Module,
+    state::AppState,
+};
+
+pub const SAMPLE_RATE: u32 = 48_000;
+const BEEPS: u32 = 60;
+const BEEP: Duration = Duration::from_millis(60);
+/// How far the flash can be moved either way, in milliseconds.
+const MAX_SHIFT_MS: f64 = 500.;
+
+/// A mono WAV of `samples` at [`SAMPLE_RATE`].
+pub fn wav(samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    samples
+        .iter()
+        .for_each(|sample| wav.extend_from_slice(&sample.to_le_bytes()));
+    wav
+}
+
+/// A mono WAV of `BEEPS` one-second periods, each starting with a 1 kHz beep.
+fn beeps() -> Vec<u8> {
+    let period = SAMPLE_RATE as usize;
+    let beep = (SAMPLE_RATE as f64 * BEEP.as_secs_f64()) as usize;
+    let samples = (0..period * BEEPS as usize).map(|sample| {
+        let within = sample % period;
+        match within < beep {
+            true => {
+                let phase = 2. * std::f64::consts::PI * 1000. * within as f64 / SAMPLE_RATE as f64;
+                (phase.sin() * f64::from(i16::MAX) * 0.5) as i16
+            }
+            false => 0,
+        }
+    });
+    wav(&samples.collect::<Vec<_>>())
+}
+
+fn beeps_path() -> PathBuf {
+    glib::user_runtime_dir()
+        .join(clap::crate_name!())
+        .join("av-sync.wav")
+}
+
+#[instrument(ret, err)]
+fn play(sink: &str) -> Result<Child> {
+    let path = beeps_path();
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&path, beeps()).wrap_err_with(|| format!("writing {}", path.display()))?;
+    }
+    Command::new("paplay")
+        .arg(format!("--device={sink}"))
+        .arg(Module::stream_tag("av-sync"))
+        .arg(&path)
+        .spawn()
+        .wrap_err("playing the test beeps")
+}
+
+/// Delay that makes up for having to move the flash by `shift_ms`, given the current one.
+fn suggestion(current_ms: u32, shift_ms: i32) -> u32 {
+    (i64::from(current_ms) - i64::from(shift_ms)).clamp(0, MAX_DELAY_MS.into()) as u32
+}
+
+/// The test window, for the sink that is the default output when it opens.
+pub fn open(cx: Scope, app: &Application, state: AppState, dispatcher: Dispatcher) {
+    let Some(sink) = state.default_sink.get_untracked() else {
+        return warn!("no output to test");
+    };
+    let master = filter_chain::master_name(&sink).to_owned();
+    let current = state.device_settings.with_untracked(|store| {
+        store
+            .devices
+            .get(&master)
+            .and_then(|settings| settings.delay_ms)
+            .unwrap_or_default()
+    });
+
+    let layout = gtk::Box::new(Orientation::Vertical, 12);
+    layout.set_margin_top(12);
+    layout.set_margin_bottom(12);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    let flash = gtk::Box::builder()
+        .width_request(480)
+        .height_request(270)
+        .hexpand(true)
+        .vexpand(true)
+        .css_classes(["av-sync"])
+        .build();
+    layout.append(&flash);
+    layout.append(&gtk::Label::new(Some(&tr(
+        "Move the flash until it lands on the beep",
+    ))));
+    let shift = gtk::Scale::with_range(Orientation::Horizontal, -MAX_SHIFT_MS, MAX_SHIFT_MS, 5.);
+    shift.set_value(0.);
+    shift.add_mark(0., gtk::PositionType::Bottom, None);
+    layout.append(&shift);
+    let controls = gtk::Box::new(Orientation::Horizontal, 6);
+    let toggle = gtk::ToggleButton::with_label(&tr("Play"));
+    let suggested = gtk::Label::new(None);
+    suggested.set_hexpand(true);
+    let apply = gtk::Button::with_label(&tr("Apply"));
+    controls.append(&toggle);
+    controls.append(&suggested);
+    controls.append(&apply);
+    layout.append(&controls);
+
+    let shift_ms = create_rw_signal(cx, 0);
+    shift.connect_value_changed(move |shift| shift_ms.set(shift.value() as i32));
+    create_effect(cx, {
+        let suggested = suggested.clone();
+        move |_| {
+            suggested.set_label(&tr_with(
+                "Suggested delay: {suggested} ms (now {current} ms)",
+                &[
+                    (
+                        "suggested",
+                        &suggestion(current, shift_ms.get()).to_string(),
+                    ),
+                    ("current", &current.to_string()),
+                ],
+            ))
+        }
+    });
+    apply.connect_clicked({
+        let master = master.clone();
+        move |_| {
+            let delay_ms = suggestion(current, shift_ms.get_untracked());
+            dispatcher.dispatch(Action::SetDelay {
+                device: master.clone(),
+                delay_ms: (delay_ms > 0).then_some(delay_ms),
+            });
+        }
+    });
+
+    let started = Rc::new(Cell::new(None::<Instant>));
+    let player = Rc::new(RefCell::new(None::<Child>));
+    flash.add_tick_callback({
+        let started = started.clone();
+        move |flash, _clock| {
+            // the flash stops together with the beeps instead of going on silently
+            let lit = started.get().is_some_and(|started| {
+                if started.elapsed() > Duration::from_secs(BEEPS.into()) {
+                    return false;
+                }
+                let shifted =
+                    started.elapsed().as_millis() as i64 - i64::from(shift_ms.get_untracked());
+                shifted >= 0 && (shifted % 1000) < BEEP.as_millis() as i64
+            });
+            match lit {
+                true => flash.add_css_class("flash"),
+                false => flash.remove_css_class("flash"),
+            }
+            glib::Continue(true)
+        }
+    });
+    let stop = {
+        let started = started.clone();
+        let player = player.clone();
+        move || {
+            started.set(None);
+            if let Some(mut child) = player.take() {
+                child.kill().and_then(|_| child.wait()).ok();
+            }
+        }
+    };
+    toggle.connect_toggled({
+        let stop = stop.clone();
+        move |toggle| match toggle.is_active() {
+            true => match play(&sink.name) {
+                Ok(child) => {
+                    player.replace(Some(child));
+                    started.set(Some(Instant::now()));
+                }
+                Err(_) => toggle.set_active(false),
+            },
+            false => stop(),
+        }
+    });
+
+    let window = gtk::Window::builder()
+        .application(app)
+        .title(tr("A/V sync test"))
+        .child(&layout)
+        .build();
+    window.connect_close_request(move |_| {
+        stop();
+        glib::signal::Inhibit(false)
+    });
+    window.present();
+}
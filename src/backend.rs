@@ -0,0 +1,704 @@
+//! What pipeweld needs from the sound server to show and change device volumes,
+//! behind a trait so the widgets don't care which tools speak to it. `pactl` works
+//! with PulseAudio and PipeWire's pulse server alike; `wpctl` talks to WirePlumber and
+//! works without `pipewire-pulse` installed.
+//!
+//! The backend is picked once per process: `PIPEWELD_BACKEND=pactl` or `wpctl` if
+//! set, then the `backend` of [`crate::config`], otherwise `pactl` when it's
+//! installed and new enough, and `wpctl` when not.
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::BufReader,
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use gtk::glib;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    checked_output,
+    compat::{self, Feature},
+    config::{self, BackendName},
+    devices::{ChannelVolume, Device, DeviceKind, VOLUME_NORM},
+    events::{self, Event, EventKind, Facility},
+    replay, run_change, tool_output, AudioControls, DiffValue,
+};
+
+pub trait AudioBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Sinks or sources, without the monitors of sinks.
+    fn list(&self, kind: DeviceKind) -> Result<Vec<Device>>;
+
+    /// The current default device of `kind`, if there is one.
+    fn default_of(&self, kind: DeviceKind) -> Result<Option<Device>>;
+
+    /// Volume of `device`, a name or [`DeviceKind::default_alias`], in percent.
+    fn volume(&self, kind: DeviceKind, device: &str) -> Result<Option<u32>> {
+        let device = match device == kind.default_alias() {
+            true => self.default_of(kind)?,
+            false => self
+                .list(kind)?
+                .into_iter()
+                .find(|candidate| candidate.name == device),
+        };
+        Ok(device.map(|device| device.volume_percent()))
+    }
+
+    fn set_volume(&self, kind: DeviceKind, device: &str, percent: u32) -> Result<()>;
+
+    fn set_mute(&self, kind: DeviceKind, device: &str, mute: bool) -> Result<()>;
+
+    /// Mutes the default device of `kind`, or unmutes it.
+    fn toggle_mute(&self, kind: DeviceKind) -> Result<()> {
+        let device = self
+            .default_of(kind)?
+            .ok_or_else(|| eyre!("there is no default {}", kind.noun()))?;
+        self.set_mute(kind, &device.name, !device.mute)
+    }
+
+    /// Steps the volume of the default device of `kind`, louder no further than `max`.
+    fn change_volume(&self, kind: DeviceKind, diff: DiffValue, max: Option<u32>) -> Result<()> {
+        let DiffValue(step) = diff;
+        let current = self
+            .default_of(kind)?
+            .map(|device| device.volume_percent())
+            .unwrap_or_default();
+        let target = current.saturating_add_signed(step);
+        let target = match max {
+            Some(max) if step > 0 => target.min(max),
+            _ => target,
+        };
+        self.set_volume(kind, kind.default_alias(), target)
+    }
+
+    /// Forwards change events until the server goes away, which is an error,
+    /// or until nobody listens anymore, which isn't.
+    fn follow(&self, sender: &glib::Sender<Event>) -> Result<()>;
+}
+
+/// Talks to a PulseAudio server, PipeWire's or the original.
+pub struct Pactl;
+
+impl AudioBackend for Pactl {
+    fn name(&self) -> &'static str {
+        "pactl"
+    }
+
+    fn list(&self, kind: DeviceKind) -> Result<Vec<Device>> {
+        Device::list(kind)
+    }
+
+    fn default_of(&self, kind: DeviceKind) -> Result<Option<Device>> {
+        Device::default_of(kind)
+    }
+
+    fn set_volume(&self, kind: DeviceKind, device: &str, percent: u32) -> Result<()> {
+        AudioControls::set_volume_percent(kind, device, percent)
+    }
+
+    fn set_mute(&self, kind: DeviceKind, device: &str, mute: bool) -> Result<()> {
+        AudioControls::set_mute(kind, device, mute)
+    }
+
+    fn toggle_mute(&self, kind: DeviceKind) -> Result<()> {
+        AudioControls::toggle_mute(kind)
+    }
+
+    fn change_volume(&self, kind: DeviceKind, diff: DiffValue, max: Option<u32>) -> Result<()> {
+        match kind {
+            DeviceKind::Sink => AudioControls::change_volume_percent(diff, max),
+            DeviceKind::Source => AudioControls::change_default_volume(kind, diff),
+        }
+    }
+
+    fn follow(&self, sender: &glib::Sender<Event>) -> Result<()> {
+        events::follow(sender)
+    }
+}
+
+/// Talks to WirePlumber through `wpctl`, and reads the graph with `pw-dump`.
+pub struct Wpctl;
+
+/// One object of `pw-dump`, with only what devices are made of.
+#[derive(Debug, Deserialize)]
+struct Object {
+    id: u32,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    info: Option<Info>,
+    /// Entries of metadata objects, the defaults among them.
+    #[serde(default)]
+    metadata: Vec<Entry>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Info {
+    #[serde(default)]
+    props: HashMap<String, Value>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    params: Params,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Params {
+    #[serde(rename = "Props", default)]
+    props: Vec<Props>,
+}
+
+/// A node's volume controls, linear per channel where `wpctl` shows them cubic.
+#[derive(Debug, Deserialize)]
+struct Props {
+    #[serde(rename = "channelVolumes", default)]
+    channel_volumes: Vec<f64>,
+    #[serde(default)]
+    mute: bool,
+}
+
+impl Info {
+    fn text(&self, key: &str) -> Option<String> {
+        match self.props.get(key)? {
+            Value::String(text) => Some(text.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    key: String,
+    #[serde(default)]
+    value: Value,
+}
+
+const NODE: &str = "PipeWire:Interface:Node";
+
+fn media_class(kind: DeviceKind) -> &'static str {
+    match kind {
+        DeviceKind::Sink => "Audio/Sink",
+        DeviceKind::Source => "Audio/Source",
+    }
+}
+
+/// What `wpctl` calls the default device, it has no idea of `@DEFAULT_SINK@`.
+fn wpctl_default(kind: DeviceKind) -> &'static str {
+    match kind {
+        DeviceKind::Sink => "@DEFAULT_AUDIO_SINK@",
+        DeviceKind::Source => "@DEFAULT_AUDIO_SOURCE@",
+    }
+}
+
+fn dump() -> Result<Vec<Object>> {
+    let stdout = checked_output(&mut Command::new("pw-dump"))?;
+    tool_output::json_list_bytes(&stdout, "pw-dump objects")
+}
+
+/// Parses `Volume: 0.40` or `Volume: 0.40 [MUTED]` into percent and mute.
+fn parse_volume(output: &str) -> Result<(u32, bool)> {
+    let rest = tool_output::field(output, "Volume")
+        .ok_or_else(|| eyre!("unexpected wpctl output {output:?}"))?;
+    let (volume, flags) = rest.split_once(' ').unwrap_or((rest, ""));
+    let volume = tool_output::decimal(volume).ok_or_else(|| eyre!("parsing volume {volume:?}"))?;
+    Ok((
+        (volume * 100.).round() as u32,
+        flags.to_ascii_uppercase().contains("[MUTED]"),
+    ))
+}
+
+/// Volume in percent and mute of a node as `wpctl get-volume` shows them, the
+/// cube root of its channels' average.
+fn node_volume(info: &Info) -> (u32, bool) {
+    let Some(props) = info
+        .params
+        .props
+        .iter()
+        .find(|props| !props.channel_volumes.is_empty())
+    else {
+        return (0, false);
+    };
+    let average = props.channel_volumes.iter().sum::<f64>() / props.channel_volumes.len() as f64;
+    ((average.cbrt() * 100.).round() as u32, props.mute)
+}
+
+impl Wpctl {
+    /// What `wpctl` accepts for `device`: it wants node ids, not names.
+    fn target(kind: DeviceKind, device: &str) -> Result<String> {
+        if device == kind.default_alias() {
+            return Ok(wpctl_default(kind).to_owned());
+        }
+        dump()?
+            .into_iter()
+            .find(|object| {
+                object.kind == NODE
+                    && object.info.as_ref().and_then(|info| info.text("node.name"))
+                        == Some(device.to_owned())
+            })
+            .map(|object| object.id.to_string())
+            .ok_or_else(|| eyre!("no {} named {device}", kind.noun()))
+    }
+
+    fn get_volume(target: &str) -> Result<(u32, bool)> {
+        let stdout = checked_output(Command::new("wpctl").args(["get-volume", target]))?;
+        parse_volume(&String::from_utf8_lossy(&stdout))
+    }
+
+    /// The nodes of `kind` in `objects`.
+    fn nodes(objects: &[Object], kind: DeviceKind) -> Result<Vec<Device>> {
+        objects
+            .iter()
+            .filter(|object| {
+                object.kind == NODE
+                    && object
+                        .info
+                        .as_ref()
+                        .and_then(|info| info.text("media.class"))
+                        == Some(media_class(kind).to_owned())
+            })
+            .map(Self::device)
+            .collect()
+    }
+
+    fn device(object: &Object) -> Result<Device> {
+        let info = object
+            .info
+            .as_ref()
+            .ok_or_else(|| eyre!("node without info"))?;
+        let name = info
+            .text("node.name")
+            .unwrap_or_else(|| object.id.to_string());
+        let (percent, mute) = node_volume(info);
+        Ok(Device {
+            index: object.id,
+            description: info
+                .text("node.description")
+                .unwrap_or_else(|| name.clone()),
+            name,
+            mute,
+            volume: BTreeMap::from([(
+                "mono".to_owned(),
+                ChannelVolume {
+                    value: (u64::from(percent) * u64::from(VOLUME_NORM) / 100) as u32,
+                },
+            )]),
+            properties: info
+                .props
+                .keys()
+                .filter_map(|key| Some((key.clone(), info.text(key)?)))
+                .collect(),
+            formats: Vec::new(),
+            state: info.state.as_deref().unwrap_or_default().to_uppercase(),
+            ports: Vec::new(),
+            active_port: None,
+            sample_specification: String::new(),
+            channel_map: "mono".to_owned(),
+        })
+    }
+}
+
+impl AudioBackend for Wpctl {
+    fn name(&self) -> &'static str {
+        "wpctl"
+    }
+
+    fn list(&self, kind: DeviceKind) -> Result<Vec<Device>> {
+        Self::nodes(&dump()?, kind)
+    }
+
+    fn default_of(&self, kind: DeviceKind) -> Result<Option<Device>> {
+        let key = format!("default.audio.{}", kind.noun());
+        let objects = dump()?;
+        let name = objects
+            .iter()
+            .flat_map(|object| &object.metadata)
+            .find(|entry| entry.key == key)
+            .and_then(|entry| entry.value.get("name")?.as_str());
+        let Some(name) = name else {
+            return Ok(None);
+        };
+        Ok(Self::nodes(&objects, kind)?
+            .into_iter()
+            .find(|device| device.name == name))
+    }
+
+    fn volume(&self, kind: DeviceKind, device: &str) -> Result<Option<u32>> {
+        Self::get_volume(&Self::target(kind, device)?).map(|(percent, _)| Some(percent))
+    }
+
+    #[instrument(skip(self), ret, err)]
+    fn set_volume(&self, kind: DeviceKind, device: &str, percent: u32) -> Result<()> {
+        run_change(Command::new("wpctl").args([
+            "set-volume",
+            &Self::target(kind, device)?,
+            &format!("{}%", percent.min(AudioControls::max_percent())),
+        ]))
+        .map(drop)
+    }
+
+    #[instrument(skip(self), ret, err)]
+    fn set_mute(&self, kind: DeviceKind, device: &str, mute: bool) -> Result<()> {
+        run_change(Command::new("wpctl").args([
+            "set-mute",
+            &Self::target(kind, device)?,
+            &u8::from(mute).to_string(),
+        ]))
+        .map(drop)
+    }
+
+    /// Follows `pw-dump --monitor`, which prints the changed objects as a JSON array
+    /// each time. PipeWire has no facilities, they're told apart by what the objects are.
+    fn follow(&self, sender: &glib::Sender<Event>) -> Result<()> {
+        let mut child = Command::new("pw-dump")
+            .arg("--monitor")
+            .env("LC_ALL", "C")
+            .stdout(Stdio::piped())
+            .spawn()
+            .wrap_err("spawning pw-dump --monitor")?;
+        let stdout = child.stdout.take().ok_or_else(|| eyre!("no stdout"))?;
+        // removed objects come without a type, so remember what each id was
+        let mut known = HashMap::<u32, Facility>::new();
+        let batches =
+            serde_json::Deserializer::from_reader(BufReader::new(stdout)).into_iter::<Vec<Value>>();
+        for (batch, objects) in batches.enumerate() {
+            let objects = objects.wrap_err("reading pw-dump --monitor")?;
+            for object in objects {
+                let Ok(object) = serde_json::from_value::<Object>(object) else {
+                    continue;
+                };
+                let event = match &object.info {
+                    None => known
+                        .remove(&object.id)
+                        .map(|facility| (EventKind::Remove, facility)),
+                    Some(info) => facility(&object.kind, info).map(|facility| {
+                        let kind = match known.insert(object.id, facility) {
+                            Some(_) => EventKind::Change,
+                            None => EventKind::New,
+                        };
+                        (kind, facility)
+                    }),
+                };
+                let Some((kind, facility)) = event else {
+                    continue;
+                };
+                // the first batch is everything there already is
+                if batch == 0 {
+                    continue;
+                }
+                let event = Event {
+                    kind,
+                    facility,
+                    index: Some(object.id),
+                };
+                debug!(?event, "pw-dump event");
+                if sender.send(event).is_err() {
+                    child.kill().ok();
+                    return Ok(());
+                }
+            }
+        }
+        let status = child.wait().wrap_err("waiting for pw-dump --monitor")?;
+        Err(eyre!("pw-dump --monitor exited with {status}"))
+    }
+}
+
+/// The facility a PipeWire object would be on a pulse server.
+fn facility(kind: &str, info: &Info) -> Option<Facility> {
+    match kind {
+        NODE => match info.text("media.class")?.as_str() {
+            "Audio/Sink" => Some(Facility::Sink),
+            "Audio/Source" => Some(Facility::Source),
+            "Stream/Output/Audio" => Some(Facility::SinkInput),
+            "Stream/Input/Audio" => Some(Facility::SourceOutput),
+            _ => None,
+        },
+        "PipeWire:Interface:Device" => Some(Facility::Card),
+        "PipeWire:Interface:Client" => Some(Facility::Client),
+        "PipeWire:Interface:Module" => Some(Facility::Module),
+        // the defaults live in metadata
+        "PipeWire:Interface:Metadata" => Some(Facility::Server),
+        _ => None,
+    }
+}
+
+fn installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+fn pick() -> Result<&'static dyn AudioBackend> {
+    if replay::replaying() {
+        return Ok(&replay::Replayed);
+    }
+    match std::env::var("PIPEWELD_BACKEND").ok().as_deref() {
+        Some("pactl") => Ok(&Pactl),
+        Some("wpctl") => Ok(&Wpctl),
+        Some(other) => bail!("unknown backend {other:?}, pick pactl or wpctl"),
+        None if config::get().backend == Some(BackendName::Pactl) => Ok(&Pactl),
+        None if config::get().backend == Some(BackendName::Wpctl) => Ok(&Wpctl),
+        None if !installed("pactl") && installed("wpctl") => Ok(&Wpctl),
+        // older pactl prints no JSON, see [`compat`]
+        None if !compat::get().supports(Feature::PactlJson) && installed("wpctl") => Ok(&Wpctl),
+        None => Ok(&Pactl),
+    }
+}
+
+/// The backend of this process, see the module docs for how it's picked.
+pub fn current() -> &'static dyn AudioBackend {
+    static CURRENT: OnceLock<&'static dyn AudioBackend> = OnceLock::new();
+    *CURRENT.get_or_init(|| {
+        let backend = pick().unwrap_or_else(|error| {
+            warn!(%error, "PIPEWELD_BACKEND, falling back to pactl");
+            &Pactl
+        });
+        info!(backend = backend.name(), "picked audio backend");
+        backend
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A server kept in memory, for what's built on [`AudioBackend`] alone.
+    struct Memory {
+        devices: Mutex<Vec<(DeviceKind, Device)>>,
+        defaults: HashMap<DeviceKind, String>,
+    }
+
+    fn device(index: u32, name: &str, percent: u32) -> Device {
+        Device {
+            index,
+            name: name.to_owned(),
+            description: name.to_owned(),
+            mute: false,
+            volume: BTreeMap::from([(
+                "mono".to_owned(),
+                ChannelVolume {
+                    value: percent * VOLUME_NORM / 100,
+                },
+            )]),
+            properties: HashMap::new(),
+            formats: Vec::new(),
+            state: "RUNNING".to_owned(),
+            ports: Vec::new(),
+            active_port: None,
+            sample_specification: String::new(),
+            channel_map: "mono".to_owned(),
+        }
+    }
+
+    impl Memory {
+        fn new() -> Self {
+            Self {
+                devices: Mutex::new(vec![
+                    (DeviceKind::Sink, device(1, "speakers", 40)),
+                    (DeviceKind::Sink, device(2, "headphones", 75)),
+                    (DeviceKind::Source, device(3, "microphone", 100)),
+                ]),
+                defaults: HashMap::from([
+                    (DeviceKind::Sink, "headphones".to_owned()),
+                    (DeviceKind::Source, "microphone".to_owned()),
+                ]),
+            }
+        }
+
+        fn change(
+            &self,
+            kind: DeviceKind,
+            device: &str,
+            change: impl FnOnce(&mut Device),
+        ) -> Result<()> {
+            let name = match device == kind.default_alias() {
+                true => self.defaults[&kind].as_str(),
+                false => device,
+            };
+            let mut devices = self.devices.lock().unwrap();
+            let (_, device) = devices
+                .iter_mut()
+                .find(|(of, device)| *of == kind && device.name == name)
+                .ok_or_else(|| eyre!("no {} named {name}", kind.noun()))?;
+            change(device);
+            Ok(())
+        }
+    }
+
+    impl AudioBackend for Memory {
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+
+        fn list(&self, kind: DeviceKind) -> Result<Vec<Device>> {
+            Ok(self
+                .devices
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(of, _)| *of == kind)
+                .map(|(_, device)| device.clone())
+                .collect())
+        }
+
+        fn default_of(&self, kind: DeviceKind) -> Result<Option<Device>> {
+            let name = &self.defaults[&kind];
+            Ok(self
+                .list(kind)?
+                .into_iter()
+                .find(|device| &device.name == name))
+        }
+
+        fn set_volume(&self, kind: DeviceKind, device: &str, percent: u32) -> Result<()> {
+            self.change(kind, device, |device| {
+                for channel in device.volume.values_mut() {
+                    channel.value = percent * VOLUME_NORM / 100;
+                }
+            })
+        }
+
+        fn set_mute(&self, kind: DeviceKind, device: &str, mute: bool) -> Result<()> {
+            self.change(kind, device, |device| device.mute = mute)
+        }
+
+        fn follow(&self, _sender: &glib::Sender<Event>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn volume_of_the_default_alias_is_the_default_device() {
+        let backend = Memory::new();
+        assert_eq!(
+            backend.volume(DeviceKind::Sink, "@DEFAULT_SINK@").unwrap(),
+            Some(75)
+        );
+        assert_eq!(
+            backend
+                .volume(DeviceKind::Source, "@DEFAULT_SOURCE@")
+                .unwrap(),
+            Some(100)
+        );
+    }
+
+    #[test]
+    fn volume_of_a_name_is_that_device_if_there_is_one() {
+        let backend = Memory::new();
+        assert_eq!(
+            backend.volume(DeviceKind::Sink, "speakers").unwrap(),
+            Some(40)
+        );
+        assert_eq!(
+            backend.volume(DeviceKind::Sink, "microphone").unwrap(),
+            None
+        );
+        assert_eq!(backend.volume(DeviceKind::Sink, "hdmi").unwrap(), None);
+    }
+
+    #[test]
+    fn changes_read_back_through_the_trait() {
+        let backend: &dyn AudioBackend = &Memory::new();
+        backend
+            .set_volume(DeviceKind::Sink, "@DEFAULT_SINK@", 30)
+            .unwrap();
+        backend
+            .set_mute(DeviceKind::Sink, "speakers", true)
+            .unwrap();
+        assert_eq!(
+            backend.volume(DeviceKind::Sink, "headphones").unwrap(),
+            Some(30)
+        );
+        let speakers = backend.list(DeviceKind::Sink).unwrap().remove(0);
+        assert!(speakers.mute);
+        assert_eq!(speakers.volume_percent(), 40);
+        assert!(backend.set_mute(DeviceKind::Source, "hdmi", true).is_err());
+        backend.toggle_mute(DeviceKind::Sink).unwrap();
+        backend
+            .change_volume(DeviceKind::Sink, DiffValue(15), Some(40))
+            .unwrap();
+        let headphones = backend.default_of(DeviceKind::Sink).unwrap().unwrap();
+        assert!(headphones.mute);
+        assert_eq!(headphones.volume_percent(), 40);
+        backend
+            .change_volume(DeviceKind::Sink, DiffValue(-50), Some(40))
+            .unwrap();
+        assert_eq!(
+            backend.volume(DeviceKind::Sink, "headphones").unwrap(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn reads_node_volumes_from_pw_dump() {
+        let objects = tool_output::json_list::<Object>(
+            r#"[
+              {
+                "id": 56,
+                "type": "PipeWire:Interface:Node",
+                "info": {
+                  "state": "running",
+                  "props": { "media.class": "Audio/Sink", "node.name": "speakers" },
+                  "params": {
+                    "Props": [
+                      { "volume": 1.0, "mute": true, "channelVolumes": [ 0.064, 0.064 ] },
+                      { "params": [] }
+                    ]
+                  }
+                }
+              },
+              {
+                "id": 57,
+                "type": "PipeWire:Interface:Node",
+                "info": { "props": { "media.class": "Audio/Sink", "node.name": "dummy" } }
+              },
+              {
+                "id": 58,
+                "type": "PipeWire:Interface:Node",
+                "info": { "props": { "media.class": "Audio/Source", "node.name": "microphone" } }
+              }
+            ]"#,
+            "pw-dump objects",
+        )
+        .unwrap();
+        let sinks = Wpctl::nodes(&objects, DeviceKind::Sink).unwrap();
+        assert_eq!(
+            sinks
+                .iter()
+                .map(|sink| (sink.name.as_str(), sink.volume_percent(), sink.mute))
+                .collect::<Vec<_>>(),
+            [("speakers", 40, true), ("dummy", 0, false)]
+        );
+        assert_eq!(sinks[0].state, "RUNNING");
+    }
+
+    #[test]
+    fn reads_wpctl_volumes() {
+        let cases = [
+            ("Volume: 0.40", Ok((40, false))),
+            ("Volume: 0.40 [MUTED]", Ok((40, true))),
+            ("Volume: 1.00 [muted]", Ok((100, true))),
+            ("volume:   0,75\n", Ok((75, false))),
+            ("Volume: 1.53", Ok((153, false))),
+            ("Volume: 0.00 [MUTED] [OTHER]", Ok((0, true))),
+            ("Volume: loud", Err(())),
+            ("Mute: yes", Err(())),
+            ("", Err(())),
+        ];
+        for (output, expected) in cases {
+            assert_eq!(parse_volume(output).map_err(drop), expected, "{output:?}");
+        }
+        for percent in 0..=150 {
+            let output = format!("Volume: {}.{:02}", percent / 100, percent % 100);
+            assert_eq!(parse_volume(&output).unwrap(), (percent, false), "{output}");
+        }
+    }
+}
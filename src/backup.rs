@@ -0,0 +1,157 @@
+//! Everything pipeweld keeps in its configuration directory, bundled into one file
+//! for moving to another machine: the configuration, the device settings with
+//! their limits, presets per device and port, workspace profiles and the rules'
+//! settings, the imported effects presets and the pad mapping.
+//!
+//! ```sh
+//! pipeweld settings export pipeweld-settings.toml
+//! pipeweld settings import pipeweld-settings.toml
+//! ```
+//!
+//! The bundle is TOML, paths relative to the directory mapping to contents.
+//! Importing replaces the files it brings along and keeps the others.
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{config::Config, device_settings::DeviceStore, priorities};
+
+/// Bumped whenever bundles change in a way older versions can't import.
+const VERSION: u32 = 1;
+
+pub fn directory() -> PathBuf {
+    glib::user_config_dir().join(clap::crate_name!())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    /// Contents by path, relative to [`directory`], with `/` between components.
+    pub files: BTreeMap<String, String>,
+}
+
+fn collect(root: &Path, directory: &Path, files: &mut BTreeMap<String, String>) -> Result<()> {
+    let entries = std::fs::read_dir(directory)
+        .wrap_err_with(|| format!("listing {}", directory.display()))?;
+    for entry in entries {
+        let path = entry
+            .wrap_err_with(|| format!("listing {}", directory.display()))?
+            .path();
+        if path.is_dir() {
+            collect(root, &path, files)?;
+            continue;
+        }
+        let relative = path
+            .strip_prefix(root)
+            .expect("listed below the root")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                files.insert(relative, contents);
+            }
+            // everything pipeweld writes is text
+            Err(error) if error.kind() == std::io::ErrorKind::InvalidData => {
+                warn!("leaving out {}, it's not text", path.display());
+            }
+            Err(error) => {
+                return Err(error).wrap_err_with(|| format!("reading {}", path.display()))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where `relative` goes below `root`, refusing anything that would end up elsewhere.
+fn target(root: &Path, relative: &str) -> Result<PathBuf> {
+    let path = Path::new(relative);
+    if relative.is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("{relative:?} is not a path within the settings");
+    }
+    Ok(root.join(path))
+}
+
+impl Bundle {
+    /// Bundles up the settings as they are now.
+    pub fn collect() -> Result<Self> {
+        let root = directory();
+        let mut files = BTreeMap::new();
+        if root.is_dir() {
+            collect(&root, &root, &mut files)?;
+        }
+        Ok(Self {
+            version: VERSION,
+            files,
+        })
+    }
+
+    #[instrument(skip(self), err)]
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string_pretty(self).wrap_err("serializing the settings")?;
+        std::fs::write(path, contents).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+
+    #[instrument(err)]
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("reading {}", path.display()))?;
+        let bundle: Self =
+            toml::from_str(&contents).wrap_err_with(|| format!("parsing {}", path.display()))?;
+        if bundle.version > VERSION {
+            bail!(
+                "{} was exported by a newer pipeweld, with version {} of the format",
+                path.display(),
+                bundle.version
+            );
+        }
+        Ok(bundle)
+    }
+
+    /// Writes the bundled files over the settings, returning their paths. Nothing is
+    /// written unless the whole bundle is valid, the device settings and the
+    /// configuration included. WirePlumber's rules follow the new device settings.
+    pub fn unpack(&self) -> Result<Vec<PathBuf>> {
+        let root = directory();
+        let targets = self
+            .files
+            .iter()
+            .map(|(relative, contents)| Ok((target(&root, relative)?, contents)))
+            .collect::<Result<Vec<_>>>()?;
+        let store = match self.files.get("devices.toml") {
+            Some(contents) => Some(
+                toml::from_str::<DeviceStore>(contents)
+                    .wrap_err("parsing the bundled device settings")?,
+            ),
+            None => None,
+        };
+        if let Some(contents) = self.files.get("config.toml") {
+            Config::parse(contents).wrap_err("parsing the bundled configuration")?;
+        }
+        for (path, contents) in &targets {
+            let parent = path
+                .parent()
+                .ok_or_else(|| eyre!("{} has no directory", path.display()))?;
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+            std::fs::write(path, contents)
+                .wrap_err_with(|| format!("writing {}", path.display()))?;
+            info!("imported {}", path.display());
+        }
+        if let Some(store) = store {
+            priorities::sync_rules(&store);
+        }
+        Ok(targets.into_iter().map(|(path, _)| path).collect())
+    }
+}
@@ -0,0 +1,213 @@
+//! Balance between the left and right channels of the default output, and the
+//! volume of each of its channels for surround setups. Both set all the channels
+//! at once, as `pactl set-sink-volume <sink> <left> <right>` does, with the volume
+//! of the loudest channel staying what the main slider shows. Setting the volume
+//! outright, with the main slider or `pipeweld volume 40`, evens the channels out
+//! again.
+use std::{cell::RefCell, rc::Rc};
+
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    extensions::*,
+    i18n::tr,
+    state::AppState,
+};
+
+/// Which side a channel plays on, by its name like `front-left`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+    /// Center, LFE, mono and the like.
+    Both,
+}
+
+fn side(channel: &str) -> Side {
+    match (channel.contains("left"), channel.contains("right")) {
+        (true, false) => Side::Left,
+        (false, true) => Side::Right,
+        _ => Side::Both,
+    }
+}
+
+/// From -1 for only the left side playing to 1 for only the right one.
+pub fn balance(device: &Device) -> f64 {
+    let loudest = |wanted| {
+        device
+            .channels()
+            .into_iter()
+            .filter(|(channel, _)| side(channel) == wanted)
+            .map(|(_, percent)| percent)
+            .max()
+    };
+    match (loudest(Side::Left), loudest(Side::Right)) {
+        (Some(left), Some(right)) if left.max(right) > 0 => {
+            (f64::from(right) - f64::from(left)) / f64::from(left.max(right))
+        }
+        _ => 0.,
+    }
+}
+
+/// Volumes of the channels of `device`, in order, for `balance` at its current
+/// volume. The side leaned away from is turned down, the other plays at the volume.
+pub fn balanced(device: &Device, balance: f64) -> Vec<u32> {
+    let volume = f64::from(device.volume_percent());
+    let balance = balance.clamp(-1., 1.);
+    device
+        .channels()
+        .into_iter()
+        .map(|(channel, _)| {
+            let factor = match side(&channel) {
+                Side::Left => 1. - balance.max(0.),
+                Side::Right => 1. + balance.min(0.),
+                Side::Both => 1.,
+            };
+            (volume * factor).round() as u32
+        })
+        .collect()
+}
+
+/// A balance slider for the default output, with the volume of each channel in an
+/// expander below it. Hidden for outputs with a single channel.
+pub fn balance_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let layout = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    let sink = state.default_sink;
+    let set = {
+        let dispatcher = dispatcher.clone();
+        move |percents: Vec<u32>| {
+            let Some(name) =
+                sink.with_untracked(|sink| sink.as_ref().map(|sink| sink.name.clone()))
+            else {
+                return;
+            };
+            dispatcher.dispatch(Action::SetChannelVolumes {
+                kind: DeviceKind::Sink,
+                device: name,
+                percents,
+            });
+        }
+    };
+    let slider = gtk::Scale::in_scope(cx)
+        .constant(|scale| {
+            scale.set_range(-100., 100.);
+            scale.set_round_digits(0);
+            scale.add_mark(0., gtk::PositionType::Bottom, Some(&tr("Center")));
+            scale.set_tooltip_text(Some(&tr("Balance between left and right")));
+        })
+        .model(
+            move || sink.with(|sink| sink.as_ref().map_or(0., |sink| balance(sink) * 100.)),
+            {
+                let set = set.clone();
+                move |picked| {
+                    if let Some(percents) = sink.with_untracked(|sink| {
+                        sink.as_ref().map(|sink| balanced(sink, picked / 100.))
+                    }) {
+                        set(percents);
+                    }
+                }
+            },
+        )
+        .as_ref()
+        .clone();
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    row.append(&gtk::Label::new(Some(&tr("Balance"))));
+    slider.set_hexpand(true);
+    row.append(&slider);
+    layout.append(&row);
+
+    let channels = gtk::Box::new(Orientation::Vertical, 2);
+    let advanced = gtk::Expander::builder()
+        .label("Channels")
+        .child(&channels)
+        .margin_bottom(6)
+        .build();
+    layout.append(&advanced);
+    let names = create_memo(cx, move |_| {
+        sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| {
+                    sink.channels()
+                        .into_iter()
+                        .map(|(channel, _)| channel)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        })
+    });
+    let sliders = Rc::new(RefCell::new(Vec::<gtk::Scale>::new()));
+    create_effect(cx, {
+        let layout = layout.clone();
+        let sliders = sliders.clone();
+        move |_| {
+            let names = names.get();
+            layout.set_visible(names.len() > 1);
+            while let Some(child) = channels.first_child() {
+                channels.remove(&child);
+            }
+            let mut built = Vec::new();
+            for (position, channel) in names.iter().enumerate() {
+                let row = gtk::Box::new(Orientation::Horizontal, 6);
+                let label = gtk::Label::new(Some(channel));
+                label.set_width_chars(14);
+                label.set_xalign(0.);
+                row.append(&label);
+                let scale = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+                scale.set_hexpand(true);
+                scale.connect_change_value({
+                    let set = set.clone();
+                    move |scale, _, picked| {
+                        let adjustment = scale.adjustment();
+                        let picked = picked.clamp(adjustment.lower(), adjustment.upper()).round();
+                        let percents = sink.with_untracked(|sink| {
+                            sink.as_ref().map(|sink| {
+                                sink.channels()
+                                    .into_iter()
+                                    .enumerate()
+                                    .map(|(other, (_, percent))| match other == position {
+                                        true => picked as u32,
+                                        false => percent,
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                        });
+                        if let Some(percents) = percents {
+                            set(percents);
+                        }
+                        glib::signal::Inhibit(false)
+                    }
+                });
+                row.append(&scale);
+                channels.append(&row);
+                built.push(scale);
+            }
+            sliders.replace(built);
+        }
+    });
+    // after the sliders are built, and whenever the server reports new volumes
+    create_effect(cx, move |_| {
+        names.track();
+        let percents = sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| {
+                    sink.channels()
+                        .into_iter()
+                        .map(|(_, percent)| percent)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        });
+        for (scale, percent) in sliders.borrow().iter().zip(percents) {
+            scale.set_value(percent.into());
+        }
+    });
+    layout
+}
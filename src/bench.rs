@@ -0,0 +1,247 @@
+//! Event bursts through the state store, for keeping event handling fast as it grows.
+//! [`script`] scripts what a setup answers, see [`replay::script`], and each of
+//! three bursts reads what its events changed as fast as the state store takes it,
+//! the way [`AppState::apply`] does but on the spot: every device and stream
+//! showing up, the server going away, and it coming back with everything under
+//! new indices. Nothing talks to a server, so runs compare from one build to the
+//! next. `cargo bench --bench state_store` runs them under criterion. The meters
+//! have their own, see [`crate::levels::bench`].
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use eyre::Result;
+use leptos::*;
+use serde_json::{json, Value};
+
+use crate::{
+    devices::DeviceKind,
+    events::{Event, EventKind, Facility},
+    replay::{self, Answer, Entry, Recorded},
+    state::{AppState, Part},
+};
+
+/// `devices` sinks and as many sources, with `streams` playing on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setup {
+    pub devices: u32,
+    pub streams: u32,
+}
+
+/// How long one burst took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Burst {
+    pub name: &'static str,
+    pub events: usize,
+    pub took: Duration,
+}
+
+/// A burst of a [`Setup`], with the server's answers scripted around it.
+#[derive(Debug, Clone)]
+pub struct Scripted {
+    pub name: &'static str,
+    events: Vec<Event>,
+    /// When on the script's clock the events arrive.
+    at: u64,
+}
+
+impl Scripted {
+    /// Times this burst alone, on a state read as the server was right before it.
+    pub fn run(&self) -> Burst {
+        let Self { name, events, at } = self.clone();
+        let runtime = create_runtime();
+        let burst = run_scope(runtime, move |cx| {
+            replay::set_clock(at - 1);
+            let state = AppState::new(cx);
+            replay::set_clock(at);
+            let started = Instant::now();
+            for event in &events {
+                state.refresh_now(Part::changed_by(*event));
+            }
+            Burst {
+                name,
+                events: events.len(),
+                took: started.elapsed(),
+            }
+        });
+        runtime.dispose();
+        burst
+    }
+}
+
+fn volume(percent: u32) -> Value {
+    let value = percent * 65536 / 100;
+    json!({
+        "front-left": { "value": value },
+        "front-right": { "value": value },
+    })
+}
+
+fn device(kind: DeviceKind, index: u32) -> Value {
+    let noun = kind.noun();
+    json!({
+        "index": index,
+        "name": format!("bench_{noun}.{index}"),
+        "description": format!("Bench {noun} {index}"),
+        "mute": false,
+        "volume": volume(60),
+        "properties": {
+            "device.class": "sound",
+            "node.name": format!("bench_{noun}.{index}"),
+        },
+        "state": "IDLE",
+        "ports": [],
+        "active_port": null,
+        "sample_specification": "s32le 2ch 48000Hz",
+        "channel_map": "front-left,front-right",
+    })
+}
+
+fn stream(index: u32, sink: u32) -> Value {
+    json!({
+        "index": index,
+        "sink": sink,
+        "mute": false,
+        "volume": volume(100),
+        "properties": {
+            "application.name": format!("Bench player {}", index % 40),
+            "media.name": format!("Track {index}"),
+        },
+        "format": "pcm",
+        "sample_specification": "float32le 2ch 48000Hz",
+        "channel_map": "front-left,front-right",
+    })
+}
+
+fn pactl(at: u64, args: &[&str], stdout: String) -> Entry {
+    Entry {
+        at,
+        recorded: Recorded::Command {
+            program: "pactl".to_owned(),
+            args: args.iter().map(|arg| (*arg).to_owned()).collect(),
+            answer: Answer::Stdout(stdout),
+        },
+    }
+}
+
+/// What the server answers at `at` with `devices` sinks and as many sources, and
+/// `streams` playing on them, indices starting from `first`.
+fn answers(at: u64, devices: u32, streams: u32, first: u32) -> Vec<Entry> {
+    let list = |kind| {
+        Value::Array(
+            (first..first + devices)
+                .map(|index| device(kind, index))
+                .collect(),
+        )
+        .to_string()
+    };
+    let inputs = Value::Array(
+        (first..first + streams)
+            .map(|index| stream(index, first + index % devices.max(1)))
+            .collect(),
+    );
+    let default = |kind: DeviceKind| match devices {
+        0 => String::new(),
+        _ => format!("bench_{}.{first}\n", kind.noun()),
+    };
+    vec![
+        pactl(
+            at,
+            &["--format=json", "list", "sinks"],
+            list(DeviceKind::Sink),
+        ),
+        pactl(
+            at,
+            &["--format=json", "list", "sources"],
+            list(DeviceKind::Source),
+        ),
+        pactl(
+            at,
+            &["--format=json", "list", "sink-inputs"],
+            inputs.to_string(),
+        ),
+        pactl(
+            at,
+            &["--format=json", "list", "source-outputs"],
+            "[]".to_owned(),
+        ),
+        pactl(at, &["list", "short", "modules"], String::new()),
+        pactl(at, &["get-default-sink"], default(DeviceKind::Sink)),
+        pactl(at, &["get-default-source"], default(DeviceKind::Source)),
+    ]
+}
+
+fn each(kind: EventKind, facility: Facility, indices: Range<u32>) -> Vec<Event> {
+    indices
+        .map(|index| Event {
+            kind,
+            facility,
+            index: Some(index),
+        })
+        .collect()
+}
+
+/// The `devices` and `streams` showing up or going away, as the server tells it.
+fn burst(kind: EventKind, devices: Range<u32>, streams: Range<u32>) -> Vec<Event> {
+    let mut burst = each(kind, Facility::Sink, devices.clone());
+    burst.extend(each(kind, Facility::Source, devices));
+    burst.extend(each(kind, Facility::SinkInput, streams));
+    burst
+}
+
+/// Scripts the bursts of each of `setups`, one after the other on the script's
+/// clock. A process only gets to script once, see [`replay::script`].
+pub fn script(setups: &[Setup]) -> Result<Vec<Vec<Scripted>>> {
+    let server = Event {
+        kind: EventKind::Change,
+        facility: Facility::Server,
+        index: None,
+    };
+    let mut entries = Vec::new();
+    let mut scripted = Vec::new();
+    for (number, &Setup { devices, streams }) in setups.iter().enumerate() {
+        // indices once the server is back, as a restarted server counts on
+        let again = devices.max(streams) + 1;
+        let mut startup = burst(EventKind::New, 0..devices, 0..streams);
+        startup.push(server);
+        let mut gone = vec![server];
+        gone.extend(burst(EventKind::Remove, 0..devices, 0..streams));
+        let mut back = vec![server];
+        back.extend(burst(
+            EventKind::New,
+            again..again + devices,
+            again..again + streams,
+        ));
+        // nothing there yet, then each burst's answers right after its events, see
+        // `replay::answer`
+        let start = number as u64 * 10;
+        entries.extend(answers(start, 0, 0, 0));
+        let bursts = [
+            ("startup", startup, answers(start + 2, devices, streams, 0)),
+            ("server gone", gone, answers(start + 4, 0, 0, 0)),
+            (
+                "server back",
+                back,
+                answers(start + 6, devices, streams, again),
+            ),
+        ];
+        scripted.push(
+            bursts
+                .into_iter()
+                .enumerate()
+                .map(|(burst, (name, events, answers))| {
+                    let at = start + burst as u64 * 2 + 1;
+                    entries.extend(events.iter().map(|event| Entry {
+                        at,
+                        recorded: Recorded::Event { event: *event },
+                    }));
+                    entries.extend(answers);
+                    Scripted { name, events, at }
+                })
+                .collect(),
+        );
+    }
+    replay::script(entries)?;
+    Ok(scripted)
+}
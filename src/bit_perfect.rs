@@ -0,0 +1,376 @@
+//! Bit-perfect playback: the sink runs at the rate of what plays on it, without
+//! pipeweld's filters and remixes in between and at full volume, so the samples reach
+//! the device as the player sent them. The filters stay in the device settings and
+//! come back once it's off. [`checks`] tell whether the chain really is untouched.
+use std::time::Duration;
+
+use eyre::Result;
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    channel_mix, delay,
+    devices::{Device, DeviceKind},
+    filter_chain, game_mode,
+    i18n::{tr, tr_with},
+    presets,
+    state::AppState,
+    streams::Stream,
+    AudioControls,
+};
+
+const FORCE_RATE: &str = "clock.force-rate";
+
+/// How often the panel checks the chain while expanded.
+const REFRESH: Duration = Duration::from_secs(2);
+
+/// The rate the graph is forced to, 0 when it picks its own.
+pub fn force_rate() -> Result<u32> {
+    game_mode::setting(FORCE_RATE)
+}
+
+#[instrument(ret, err)]
+pub fn set_force_rate(rate: u32) -> Result<()> {
+    game_mode::set_setting(FORCE_RATE, rate)
+}
+
+/// Rate of the first stream playing on `sink`, or on something on top of it.
+pub fn playing_rate(sink: &Device) -> Result<Option<u32>> {
+    let sinks = Device::list(DeviceKind::Sink)?;
+    Ok(Stream::list()?
+        .iter()
+        .filter(|stream| {
+            sinks
+                .iter()
+                .find(|candidate| candidate.index == stream.sink)
+                .is_some_and(|candidate| filter_chain::master_name(candidate) == sink.name)
+        })
+        .find_map(|stream| stream.sample_spec())
+        .map(|spec| spec.rate))
+}
+
+/// Takes pipeweld's filters and remixes off `sink` and pins the rate to what plays,
+/// or puts the filters back as saved in the device settings.
+pub fn apply(state: AppState, sink: &Device, bit_perfect: bool) -> Result<()> {
+    let settings = state
+        .device_settings
+        .with_untracked(|store| store.get(sink).cloned())
+        .unwrap_or_default();
+    let (mix, delay_ms, preset, equalizer, trim_db, limit_db) = match bit_perfect {
+        true => (None, None, None, None, None, None),
+        false => (
+            settings.channel_mix,
+            settings.delay_ms,
+            settings
+                .preset_for(sink.active_port.as_deref())
+                .map(str::to_owned),
+            settings.equalizer().cloned(),
+            settings.trim_db,
+            settings.limit_db,
+        ),
+    };
+    channel_mix::apply(sink, mix)?;
+    let mut applied = Ok(());
+    state.filters.with_value(|host| {
+        let host = &mut *host.lock();
+        applied = delay::apply(host, sink, delay_ms).and_then(|()| {
+            presets::apply(
+                host,
+                sink,
+                preset.as_deref(),
+                equalizer.as_ref(),
+                trim_db,
+                limit_db,
+            )
+        });
+    });
+    applied?;
+    if !bit_perfect {
+        return set_force_rate(0);
+    }
+    AudioControls::set_volume_percent(DeviceKind::Sink, &sink.name, 100)?;
+    match playing_rate(sink)? {
+        Some(rate) => set_force_rate(rate),
+        None => Ok(()),
+    }
+}
+
+/// One thing that has to hold for playback to be bit-perfect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub description: String,
+    pub passed: bool,
+}
+
+impl Check {
+    fn new(passed: bool, description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            passed,
+        }
+    }
+}
+
+/// What stands between the streams playing on `master` and the device, from what
+/// the server reports. Pipeweld can't see resampling inside the device's driver.
+pub fn checks(
+    state: AppState,
+    master: &Device,
+    sinks: &[Device],
+    streams: &[Stream],
+) -> Vec<Check> {
+    let sink_of = |stream: &Stream| sinks.iter().find(|sink| sink.index == stream.sink);
+    let on_top = sinks
+        .iter()
+        .filter(|sink| sink.name != master.name && filter_chain::master_name(sink) == master.name)
+        .map(|sink| sink.description.as_str())
+        .collect::<Vec<_>>();
+    let upmix = state
+        .device_settings
+        .with_untracked(|store| store.get(master).and_then(|settings| settings.upmix));
+    let mut checks = vec![
+        match on_top.is_empty() {
+            true => Check::new(true, tr("No filters or remixes on the device")),
+            false => Check::new(
+                false,
+                tr_with("Filtered by {filters}", &[("filters", &on_top.join(", "))]),
+            ),
+        },
+        Check::new(
+            upmix.is_none(),
+            match upmix {
+                None => tr("No upmixing"),
+                Some(_) => tr("Upmixes what plays"),
+            },
+        ),
+        Check::new(
+            master.volume_percent() == 100 && !master.mute,
+            tr_with(
+                "Device volume at {volume}%",
+                &[("volume", &master.volume_percent().to_string())],
+            ),
+        ),
+    ];
+    let playing = streams
+        .iter()
+        .filter(|stream| {
+            sink_of(stream).is_some_and(|sink| filter_chain::master_name(sink) == master.name)
+        })
+        .collect::<Vec<_>>();
+    if playing.is_empty() {
+        checks.push(Check::new(
+            false,
+            tr("Nothing plays, start playback to check the rest"),
+        ));
+    }
+    let device = master.sample_spec();
+    for stream in playing {
+        let title = stream.title();
+        if stream.sink != master.index {
+            let through = sink_of(stream).map_or("", |sink| sink.description.as_str());
+            checks.push(Check::new(
+                false,
+                tr_with(
+                    "{title} plays through {through}",
+                    &[("title", &title), ("through", through)],
+                ),
+            ));
+        }
+        checks.push(Check::new(
+            stream.volume_percent() == 100 && !stream.mute,
+            tr_with(
+                "{title}: volume at {volume}%",
+                &[
+                    ("title", &title),
+                    ("volume", &stream.volume_percent().to_string()),
+                ],
+            ),
+        ));
+        let (Some(spec), Some(device)) = (stream.sample_spec(), &device) else {
+            checks.push(Check::new(
+                false,
+                tr_with("{title}: format unknown", &[("title", &title)]),
+            ));
+            continue;
+        };
+        checks.push(match spec.rate == device.rate {
+            true => Check::new(
+                true,
+                tr_with(
+                    "{title}: {rate} Hz, as the device runs",
+                    &[("title", &title), ("rate", &spec.rate.to_string())],
+                ),
+            ),
+            false => Check::new(
+                false,
+                tr_with(
+                    "{title}: resampled from {from} to {to} Hz",
+                    &[
+                        ("title", &title),
+                        ("from", &spec.rate.to_string()),
+                        ("to", &device.rate.to_string()),
+                    ],
+                ),
+            ),
+        });
+        checks.push(match spec.format == device.format {
+            true => Check::new(
+                true,
+                tr_with(
+                    "{title}: {format}, as the device takes",
+                    &[("title", &title), ("format", &spec.format.to_string())],
+                ),
+            ),
+            false => Check::new(
+                false,
+                tr_with(
+                    "{title}: converted from {from} to {to}",
+                    &[
+                        ("title", &title),
+                        ("from", &spec.format.to_string()),
+                        ("to", &device.format.to_string()),
+                    ],
+                ),
+            ),
+        });
+        checks.push(Check::new(
+            spec.channels == device.channels,
+            tr_with(
+                "{title}: {channels} channels on a {device}-channel device",
+                &[
+                    ("title", &title),
+                    ("channels", &spec.channels.to_string()),
+                    ("device", &device.channels.to_string()),
+                ],
+            ),
+        ));
+    }
+    checks
+}
+
+fn check_row(check: &Check) -> gtk::Box {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    row.append(&gtk::Image::from_icon_name(match check.passed {
+        true => "emblem-ok-symbolic",
+        false => "dialog-warning-symbolic",
+    }));
+    let label = gtk::Label::new(Some(&check.description));
+    label.set_halign(gtk::Align::Start);
+    label.set_wrap(true);
+    row.append(&label);
+    row
+}
+
+/// Expander switching bit-perfect playback of the default output on and off, and
+/// listing what would keep it from being bit-perfect.
+pub fn bit_perfect_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let enabled = gtk::CheckButton::with_label(&tr("Bit-perfect playback"));
+    enabled.set_tooltip_text(Some(&tr(
+        "Runs the device at the rate of what plays, without filters, remixes or volume changes",
+    )));
+    layout.append(&enabled);
+    let summary = gtk::Label::new(None);
+    summary.set_halign(gtk::Align::Start);
+    summary.add_css_class("heading");
+    layout.append(&summary);
+    let rows = gtk::Box::new(Orientation::Vertical, 2);
+    layout.append(&rows);
+    let expander = gtk::Expander::builder()
+        .label("Bit-perfect playback")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+
+    // the real device behind the default output, as last checked
+    let master = store_value(cx, None::<String>);
+    let refresh = {
+        let enabled = enabled.clone();
+        move || {
+            let listed = Device::list(DeviceKind::Sink)
+                .and_then(|sinks| Stream::list().map(|streams| (sinks, streams)));
+            let (sinks, streams) = match listed {
+                Ok(listed) => listed,
+                Err(error) => return warn!(?error, "checking for bit-perfect playback"),
+            };
+            while let Some(row) = rows.first_child() {
+                rows.remove(&row);
+            }
+            let device = state.default_sink.with_untracked(|sink| {
+                let name = filter_chain::master_name(sink.as_ref()?);
+                sinks
+                    .iter()
+                    .find(|candidate| candidate.name == name)
+                    .cloned()
+            });
+            let Some(device) = device else {
+                master.set_value(None);
+                enabled.set_sensitive(false);
+                return summary.set_text(&tr("No output"));
+            };
+            master.set_value(Some(device.name.clone()));
+            enabled.set_sensitive(true);
+            enabled.set_active(state.device_settings.with_untracked(|store| {
+                store
+                    .get(&device)
+                    .is_some_and(|settings| settings.bit_perfect)
+            }));
+            let checks = checks(state, &device, &sinks, &streams);
+            summary.set_text(&match checks.iter().all(|check| check.passed) {
+                true => tr_with(
+                    "{device} plays untouched",
+                    &[("device", &device.description)],
+                ),
+                false => tr_with(
+                    "{device} doesn't play bit-perfect",
+                    &[("device", &device.description)],
+                ),
+            });
+            checks
+                .iter()
+                .map(check_row)
+                .for_each(|row| rows.append(&row));
+        }
+    };
+    enabled.connect_toggled(move |enabled| {
+        let Some(device) = master.get_value() else {
+            return;
+        };
+        // refreshing sets it to what's saved
+        let saved = state.device_settings.with_untracked(|store| {
+            store
+                .devices
+                .get(&device)
+                .is_some_and(|settings| settings.bit_perfect)
+        });
+        if enabled.is_active() != saved {
+            dispatcher.dispatch(Action::SetBitPerfect {
+                device,
+                bit_perfect: enabled.is_active(),
+            });
+        }
+    });
+    expander.connect_expanded_notify({
+        let refresh = refresh.clone();
+        move |expander| {
+            if expander.is_expanded() {
+                refresh();
+            }
+        }
+    });
+    let weak = expander.downgrade();
+    glib::timeout_add_local(REFRESH, move || {
+        let Some(expander) = weak.upgrade() else {
+            return glib::Continue(false);
+        };
+        if expander.is_expanded() {
+            refresh();
+        }
+        glib::Continue(true)
+    });
+    expander
+}
@@ -0,0 +1,233 @@
+//! Bluetooth audio devices, as paired with BlueZ: connecting and disconnecting
+//! them, and picking their card's profile once connected. The profiles are where
+//! the codec is picked too, PipeWire offers one for each of SBC, AAC, LDAC and the
+//! others the device takes, next to headset mode with the microphone.
+use eyre::{Result, WrapErr};
+use gtk::{gio, glib::VariantDict, prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    cards::{self, Card},
+    events::{self, Facility},
+    extensions::watch,
+    i18n::tr,
+};
+
+const BLUEZ: &str = "org.bluez";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+/// Connecting goes through pairing the profiles, which takes a while.
+const CONNECT_TIMEOUT_MS: i32 = 30_000;
+
+/// Services of devices that play or record audio: A2DP sink and source, and the
+/// headset and hands-free units.
+const AUDIO_SERVICES: [&str; 4] = [
+    "0000110b-0000-1000-8000-00805f9b34fb",
+    "0000110a-0000-1000-8000-00805f9b34fb",
+    "00001108-0000-1000-8000-00805f9b34fb",
+    "0000111e-0000-1000-8000-00805f9b34fb",
+];
+
+/// A paired device, as BlueZ tells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BluetoothDevice {
+    /// Object path of the device, like `/org/bluez/hci0/dev_00_11_22_33_44_55`.
+    pub path: String,
+    pub name: String,
+    pub address: String,
+    pub connected: bool,
+}
+
+impl BluetoothDevice {
+    /// The paired audio devices.
+    pub fn list() -> Result<Vec<Self>> {
+        let reply = system_bus()?
+            .call_sync(
+                Some(BLUEZ),
+                "/",
+                "org.freedesktop.DBus.ObjectManager",
+                "GetManagedObjects",
+                None,
+                None,
+                gio::DBusCallFlags::NONE,
+                -1,
+                gio::Cancellable::NONE,
+            )
+            .wrap_err("listing BlueZ's devices")?;
+        let mut devices = Vec::new();
+        for object in reply.child_value(0).iter() {
+            let Some(path) = object.child_value(0).str().map(str::to_owned) else {
+                continue;
+            };
+            for interface in object.child_value(1).iter() {
+                if interface.child_value(0).str() != Some(DEVICE_INTERFACE) {
+                    continue;
+                }
+                let properties = VariantDict::new(Some(&interface.child_value(1)));
+                let text = |key| properties.lookup::<String>(key).ok().flatten();
+                let flag = |key| {
+                    properties
+                        .lookup::<bool>(key)
+                        .ok()
+                        .flatten()
+                        .unwrap_or(false)
+                };
+                let services = properties
+                    .lookup::<Vec<String>>("UUIDs")
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default();
+                let audio = text("Icon").is_some_and(|icon| icon.starts_with("audio-"))
+                    || services
+                        .iter()
+                        .any(|service| AUDIO_SERVICES.contains(&service.to_lowercase().as_str()));
+                let Some(address) = text("Address").filter(|_| audio && flag("Paired")) else {
+                    continue;
+                };
+                devices.push(Self {
+                    path: path.clone(),
+                    name: text("Alias").unwrap_or_else(|| address.clone()),
+                    address,
+                    connected: flag("Connected"),
+                });
+            }
+        }
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    /// The card the server made for the device, while it's connected.
+    pub fn card<'cards>(&self, cards: &'cards [Card]) -> Option<&'cards Card> {
+        let name = format!("bluez_card.{}", self.address.replace(':', "_"));
+        cards.iter().find(|card| {
+            card.name == name || card.properties.get("api.bluez5.address") == Some(&self.address)
+        })
+    }
+}
+
+fn system_bus() -> Result<gio::DBusConnection> {
+    gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE)
+        .wrap_err("connecting to the system bus")
+}
+
+/// Connects the device at `path`, or disconnects it, waiting until BlueZ is done.
+#[instrument(ret, err)]
+pub fn set_connected(path: &str, connected: bool) -> Result<()> {
+    let method = match connected {
+        true => "Connect",
+        false => "Disconnect",
+    };
+    system_bus()?
+        .call_sync(
+            Some(BLUEZ),
+            path,
+            DEVICE_INTERFACE,
+            method,
+            None,
+            None,
+            gio::DBusCallFlags::NONE,
+            CONNECT_TIMEOUT_MS,
+            gio::Cancellable::NONE,
+        )
+        .map(drop)
+        .wrap_err_with(|| format!("{method} {path}"))
+}
+
+fn row(device: &BluetoothDevice, cards: &[Card], dispatcher: Dispatcher) -> gtk::Box {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(&device.name));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    label.set_tooltip_text(Some(&device.address));
+    row.append(&label);
+    if let Some(card) = device.card(cards).filter(|_| device.connected) {
+        row.append(&cards::profile_picker(card, dispatcher.clone()));
+    }
+    let toggle = gtk::Button::with_label(match device.connected {
+        true => "Disconnect",
+        false => "Connect",
+    });
+    let (path, connected) = (device.path.clone(), device.connected);
+    toggle.connect_clicked(move |toggle| {
+        // BlueZ tells when it's done, which rebuilds the row
+        toggle.set_sensitive(false);
+        dispatcher.dispatch(Action::SetBluetoothConnected {
+            device: path.clone(),
+            connected: !connected,
+        });
+    });
+    row.append(&toggle);
+    row
+}
+
+/// Expander with the paired audio devices, following BlueZ and the server.
+pub fn bluetooth_panel(cx: Scope, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let list = || {
+        let devices = BluetoothDevice::list()
+            .map_err(|error| warn!(?error, "listing Bluetooth devices"))
+            .unwrap_or_default();
+        let cards = Card::list()
+            .map_err(|error| warn!(?error, "listing cards for Bluetooth devices"))
+            .unwrap_or_default();
+        (devices, cards)
+    };
+    let listed = create_rw_signal(cx, list());
+    events::subscribe(move |event| {
+        if event.facility == Facility::Card {
+            listed.set(list());
+        }
+    });
+    match system_bus() {
+        Ok(bus) => {
+            // connecting and pairing show up as changed properties, new devices as
+            // new objects
+            let changed = move || listed.set(list());
+            bus.signal_subscribe(
+                Some(BLUEZ),
+                Some("org.freedesktop.DBus.Properties"),
+                Some("PropertiesChanged"),
+                None,
+                Some(DEVICE_INTERFACE),
+                gio::DBusSignalFlags::NONE,
+                move |_, _, _, _, _, _| changed(),
+            );
+            for member in ["InterfacesAdded", "InterfacesRemoved"] {
+                bus.signal_subscribe(
+                    Some(BLUEZ),
+                    Some("org.freedesktop.DBus.ObjectManager"),
+                    Some(member),
+                    None,
+                    None,
+                    gio::DBusSignalFlags::NONE,
+                    move |_, _, _, _, _, _| changed(),
+                );
+            }
+        }
+        Err(error) => info!(?error, "Bluetooth devices won't be followed"),
+    }
+    watch(cx, &layout, move |layout| {
+        while let Some(row) = layout.first_child() {
+            layout.remove(&row);
+        }
+        listed.with(|(devices, cards)| {
+            if devices.is_empty() {
+                let empty = gtk::Label::new(Some(&tr("No Bluetooth audio devices are paired")));
+                empty.add_css_class("dim-label");
+                layout.append(&empty);
+            }
+            for device in devices {
+                layout.append(&row(device, cards, dispatcher.clone()));
+            }
+        });
+    });
+    gtk::Expander::builder()
+        .label("Bluetooth")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
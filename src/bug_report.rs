@@ -0,0 +1,151 @@
+//! Diagnostics to paste into a bug report, copied from the About panel: versions of
+//! pipeweld and what it runs on, the backend in use, the devices and the latest
+//! failures. The home directory, user name and host name are replaced, as in
+//! session recordings, and so are Bluetooth addresses, which device names carry.
+use std::fmt::Write;
+
+use eyre::{eyre, Result};
+use gtk::{gdk, prelude::*};
+use leptos::*;
+use tracing::info;
+
+use crate::{backend, compat, devices::Device, errors, replay, state::AppState, streams::Stream};
+
+/// Whether `text` is a Bluetooth address, hex pairs joined by `:` or `_`.
+fn is_address(text: &str) -> bool {
+    let pairs = text.split([':', '_']).collect::<Vec<_>>();
+    pairs.len() == 6
+        && pairs
+            .iter()
+            .all(|pair| pair.len() == 2 && pair.chars().all(|char| char.is_ascii_hexdigit()))
+}
+
+/// `text` without private names and Bluetooth addresses in it.
+pub fn redact(text: &str) -> String {
+    const ADDRESS: usize = "00:00:00:00:00:00".len();
+    let text = replay::sanitize(text);
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while !rest.is_empty() {
+        match rest.get(..ADDRESS).filter(|start| is_address(start)) {
+            Some(_) => {
+                redacted.push_str("XX:XX:XX:XX:XX:XX");
+                rest = &rest[ADDRESS..];
+            }
+            None => {
+                let next = rest.chars().next().map_or(1, char::len_utf8);
+                redacted.push_str(&rest[..next]);
+                rest = &rest[next..];
+            }
+        }
+    }
+    redacted
+}
+
+fn devices(report: &mut String, heading: &str, devices: &[Device], default: Option<&str>) {
+    _ = writeln!(report, "\n{heading} ({})", devices.len());
+    for device in devices {
+        let marker = match Some(device.name.as_str()) == default {
+            true => '*',
+            false => '-',
+        };
+        let api = device
+            .properties
+            .get("device.api")
+            .map_or("virtual", String::as_str);
+        _ = writeln!(
+            report,
+            "{marker} {} ({}): {api}, {}, {}, {}%{}",
+            device.description,
+            device.name,
+            device.sample_specification,
+            device.state,
+            device.volume_percent(),
+            if device.mute { ", muted" } else { "" },
+        );
+        if let Some(port) = &device.active_port {
+            _ = writeln!(report, "  port {port}");
+        }
+    }
+}
+
+/// The diagnostics, redacted.
+pub fn generate(state: AppState) -> String {
+    let versions = compat::redetect();
+    let unknown = || "unknown".to_owned();
+    let show = |version: Option<compat::Version>| version.map_or_else(unknown, |v| v.to_string());
+    let mut report = String::new();
+    _ = writeln!(report, "pipeweld {}", clap::crate_version!());
+    _ = writeln!(
+        report,
+        "GTK {}.{}.{}",
+        gtk::major_version(),
+        gtk::minor_version(),
+        gtk::micro_version()
+    );
+    _ = writeln!(report, "PipeWire {}", show(versions.pipewire));
+    _ = writeln!(report, "WirePlumber {}", show(versions.wireplumber));
+    _ = writeln!(report, "pactl {}", show(versions.pactl));
+    _ = writeln!(
+        report,
+        "Pulse server {}, protocol {}",
+        versions.pulse_server.clone().unwrap_or_else(unknown),
+        versions
+            .pulse_protocol
+            .map_or_else(unknown, |protocol| protocol.to_string())
+    );
+    _ = writeln!(report, "Backend {}", backend::current().name());
+    if versions.flat_volumes {
+        _ = writeln!(report, "Flat volumes on");
+    }
+    for missing in versions.missing() {
+        _ = writeln!(report, "Missing: {missing}");
+    }
+
+    let name = |device: RwSignal<Option<Device>>| {
+        device.with_untracked(|device| device.as_ref().map(|device| device.name.clone()))
+    };
+    let (sink, source) = (name(state.default_sink), name(state.default_source));
+    state
+        .sinks
+        .with_untracked(|sinks| devices(&mut report, "Outputs", sinks, sink.as_deref()));
+    state
+        .sources
+        .with_untracked(|sources| devices(&mut report, "Inputs", sources, source.as_deref()));
+    match Stream::list() {
+        Ok(streams) => {
+            // what plays is left out, titles tell more than a report needs
+            _ = writeln!(report, "\nStreams ({})", streams.len());
+            for stream in streams {
+                let application = stream
+                    .properties
+                    .get("application.name")
+                    .map_or("unnamed", String::as_str);
+                _ = writeln!(report, "- {application} on sink {}", stream.sink);
+            }
+        }
+        Err(error) => _ = writeln!(report, "\nStreams: {error:#}"),
+    }
+    state.captures.with_untracked(|captures| {
+        _ = writeln!(report, "Recordings: {}", captures.len());
+    });
+
+    let failures = errors::recent();
+    _ = writeln!(report, "\nRecent errors ({})", failures.len());
+    for failure in failures {
+        _ = writeln!(report, "- {}", failure.summary);
+        for line in failure.details.lines() {
+            _ = writeln!(report, "  {line}");
+        }
+    }
+    redact(&report)
+}
+
+/// Puts the diagnostics on the clipboard.
+pub fn copy(state: AppState) -> Result<()> {
+    let display = gdk::Display::default().ok_or_else(|| eyre!("no display to copy to"))?;
+    let report = generate(state);
+    display.clipboard().set_text(&report);
+    info!(bytes = report.len(), "copied the diagnostics");
+    Ok(())
+}
@@ -0,0 +1,257 @@
+//! Applications recording from a microphone, i.e. what `pactl` calls source
+//! outputs. They're listed with a volume, mute and the microphone they record
+//! from each, and while any is around a red indicator tops the window and the
+//! tray's icon gets a microphone. Recording the monitors of outputs, as
+//! pipeweld's own level meters do, doesn't count.
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use eyre::Result;
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{ChannelVolume, Device, DeviceKind, VOLUME_NORM},
+    extensions::watch,
+    i18n::{tr, tr_with},
+    state::AppState,
+    tool_output, AudioControls,
+};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Capture {
+    pub index: u32,
+    pub source: u32,
+    pub mute: bool,
+    #[serde(default)]
+    pub volume: BTreeMap<String, ChannelVolume>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+impl Capture {
+    /// Every source output, monitors and pipeweld's own included.
+    pub fn list_all() -> Result<Vec<Self>> {
+        let output = AudioControls::pactl(["--format=json", "list", "source-outputs"])?;
+        tool_output::json_list(&output, "source outputs")
+    }
+
+    /// Applications other than pipeweld recording from a microphone.
+    pub fn list() -> Result<Vec<Self>> {
+        let microphones = Device::list(DeviceKind::Source)?
+            .into_iter()
+            .map(|source| source.index)
+            .collect::<Vec<_>>();
+        let own = std::process::id().to_string();
+        Ok(Self::list_all()?
+            .into_iter()
+            .filter(|capture| microphones.contains(&capture.source))
+            .filter(|capture| capture.properties.get("application.process.id") != Some(&own))
+            .collect())
+    }
+
+    /// What is recording: the application, and what it records for.
+    pub fn title(&self) -> String {
+        let property = |key| self.properties.get(key).map(String::as_str);
+        match (property("application.name"), property("media.name")) {
+            (Some(application), Some(media)) if application != media => {
+                format!("{application}: {media}")
+            }
+            (Some(name), _) | (None, Some(name)) => name.to_owned(),
+            (None, None) => format!("Recording #{}", self.index),
+        }
+    }
+
+    /// Volume of the loudest channel, in percent.
+    pub fn volume_percent(&self) -> u32 {
+        let loudest = self
+            .volume
+            .values()
+            .map(|channel| channel.value)
+            .max()
+            .unwrap_or(0);
+        ((u64::from(loudest) * 100 + u64::from(VOLUME_NORM) / 2) / u64::from(VOLUME_NORM)) as u32
+    }
+}
+
+/// A red "Microphone in use" line naming the applications recording, hidden while
+/// nothing is.
+pub fn indicator(cx: Scope, state: AppState) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Horizontal, 6);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    layout.set_margin_top(12);
+    let icon = gtk::Image::from_icon_name("microphone-sensitivity-high-symbolic");
+    icon.add_css_class("error");
+    layout.append(&icon);
+    let label = gtk::Label::new(None);
+    label.set_wrap(true);
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    label.add_css_class("error");
+    layout.append(&label);
+    watch(cx, &layout, move |layout| {
+        state.captures.with(|captures| {
+            layout.set_visible(!captures.is_empty());
+            let mut applications = captures
+                .iter()
+                .map(|capture| {
+                    capture
+                        .properties
+                        .get("application.name")
+                        .cloned()
+                        .unwrap_or_else(|| capture.title())
+                })
+                .collect::<Vec<_>>();
+            applications.sort();
+            applications.dedup();
+            label.set_text(&tr_with(
+                "Microphone in use by {applications}",
+                &[("applications", &applications.join(", "))],
+            ));
+        })
+    });
+    layout
+}
+
+struct Row {
+    capture: u32,
+    volume: gtk::Scale,
+    mute: gtk::ToggleButton,
+}
+
+/// Expander with a row for each application recording, with its volume, mute and
+/// the microphone it records from.
+pub fn capture_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    // only recordings coming and going, or moving, rebuild the rows, so the volume
+    // picked isn't reset while picking it
+    let listed = create_memo(cx, move |_| {
+        state.captures.with(|captures| {
+            captures
+                .iter()
+                .map(|capture| (capture.index, capture.title(), capture.source))
+                .collect::<Vec<_>>()
+        })
+    });
+    let built = Rc::new(RefCell::new(Vec::<Row>::new()));
+    let layout = rows.clone();
+    create_effect(cx, {
+        let built = built.clone();
+        move |_| {
+            let listed = listed.get();
+            while let Some(row) = rows.first_child() {
+                rows.remove(&row);
+            }
+            if listed.is_empty() {
+                let empty = gtk::Label::new(Some(&tr("Nothing is recording.")));
+                empty.add_css_class("dim-label");
+                rows.append(&empty);
+            }
+            let sources = match listed.is_empty() {
+                true => Vec::new(),
+                false => Device::list(DeviceKind::Source)
+                    .map_err(|error| warn!(?error, "listing sources to record from"))
+                    .unwrap_or_default(),
+            };
+            let mut widgets = Vec::new();
+            for (capture, title, source) in listed {
+                let row = gtk::Box::new(Orientation::Vertical, 2);
+                let label = gtk::Label::new(Some(&title));
+                label.set_halign(gtk::Align::Start);
+                label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+                row.append(&label);
+                let controls = gtk::Box::new(Orientation::Horizontal, 6);
+                let volume = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+                volume.set_hexpand(true);
+                volume.connect_change_value({
+                    let dispatcher = dispatcher.clone();
+                    move |volume, _, picked| {
+                        let adjustment = volume.adjustment();
+                        let picked = picked.clamp(adjustment.lower(), adjustment.upper());
+                        dispatcher.dispatch(Action::SetCaptureVolume(capture, picked as u32));
+                        glib::signal::Inhibit(false)
+                    }
+                });
+                controls.append(&volume);
+                let mute = gtk::ToggleButton::new();
+                mute.set_icon_name("microphone-sensitivity-muted-symbolic");
+                mute.set_tooltip_text(Some(&tr("Mute the recording")));
+                mute.connect_toggled({
+                    let dispatcher = dispatcher.clone();
+                    move |mute| {
+                        // following the server toggles it too
+                        let muted = state.captures.with_untracked(|captures| {
+                            captures
+                                .iter()
+                                .find(|candidate| candidate.index == capture)
+                                .is_some_and(|capture| capture.mute)
+                        });
+                        if mute.is_active() != muted {
+                            dispatcher.dispatch(Action::SetCaptureMute(capture, mute.is_active()));
+                        }
+                    }
+                });
+                controls.append(&mute);
+                let picker = gtk::DropDown::from_strings(
+                    &sources
+                        .iter()
+                        .map(|source| source.description.as_str())
+                        .collect::<Vec<_>>(),
+                );
+                picker.set_tooltip_text(Some(&tr("Microphone it records from")));
+                if let Some(position) = sources.iter().position(|other| other.index == source) {
+                    picker.set_selected(position as u32);
+                }
+                picker.connect_selected_notify({
+                    let dispatcher = dispatcher.clone();
+                    let sources = sources.clone();
+                    move |picker| match sources.get(picker.selected() as usize) {
+                        Some(picked) if picked.index != source => {
+                            dispatcher.dispatch(Action::MoveCapture {
+                                capture,
+                                source: picked.name.clone(),
+                            })
+                        }
+                        _ => {}
+                    }
+                });
+                controls.append(&picker);
+                row.append(&controls);
+                rows.append(&row);
+                widgets.push(Row {
+                    capture,
+                    volume,
+                    mute,
+                });
+            }
+            built.replace(widgets);
+        }
+    });
+    // after the rows are built, and whenever the server reports new volumes
+    create_effect(cx, move |_| {
+        listed.track();
+        state.captures.with(|captures| {
+            for row in built.borrow().iter() {
+                if let Some(capture) = captures.iter().find(|capture| capture.index == row.capture)
+                {
+                    row.volume.set_value(capture.volume_percent().into());
+                    row.mute.set_active(capture.mute);
+                }
+            }
+        })
+    });
+    gtk::Expander::builder()
+        .label("Recording")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,159 @@
+//! Sound cards and their profiles, like HDMI against the analog outputs of a
+//! laptop, or a Bluetooth headset's high quality playback against its headset mode.
+use std::collections::HashMap;
+
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::Device,
+    events::{self, Facility},
+    extensions::watch,
+    tool_output, AudioControls,
+};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Profile {
+    pub description: String,
+    #[serde(default)]
+    pub priority: u32,
+    #[serde(default = "available_by_default")]
+    pub available: bool,
+}
+
+fn available_by_default() -> bool {
+    true
+}
+
+/// A sound card as reported by `pactl --format=json list cards`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Card {
+    pub index: u32,
+    pub name: String,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    pub active_profile: Option<String>,
+}
+
+impl Card {
+    pub fn list() -> Result<Vec<Self>> {
+        let output = AudioControls::pactl(["--format=json", "list", "cards"])?;
+        tool_output::json_list(&output, "cards")
+    }
+
+    /// Finds the card a device belongs to.
+    ///
+    /// PipeWire points at the card with `device.id`, plain PulseAudio only shares
+    /// the ALSA card number between the two.
+    pub fn owning<'cards>(device: &Device, cards: &'cards [Self]) -> Option<&'cards Self> {
+        let property =
+            |properties: &HashMap<String, String>, key: &str| properties.get(key).cloned();
+        cards.iter().find(|card| {
+            property(&device.properties, "device.id") == Some(card.index.to_string())
+                || property(&device.properties, "alsa.card").is_some_and(|alsa_card| {
+                    property(&card.properties, "alsa.card") == Some(alsa_card)
+                })
+        })
+    }
+
+    pub fn description(&self) -> &str {
+        self.properties
+            .get("device.description")
+            .unwrap_or(&self.name)
+    }
+
+    /// Available profiles, best first.
+    pub fn profiles(&self) -> Vec<(&str, &Profile)> {
+        let mut profiles = self
+            .profiles
+            .iter()
+            .filter(|(_, profile)| profile.available)
+            .map(|(name, profile)| (name.as_str(), profile))
+            .collect::<Vec<_>>();
+        profiles.sort_by(|(a_name, a), (b_name, b)| {
+            b.priority.cmp(&a.priority).then(a_name.cmp(b_name))
+        });
+        profiles
+    }
+}
+
+/// Drop down switching `card` to another of its available profiles.
+pub fn profile_picker(card: &Card, dispatcher: Dispatcher) -> gtk::DropDown {
+    let profiles = card
+        .profiles()
+        .into_iter()
+        .map(|(name, profile)| (name.to_owned(), profile.description.clone()))
+        .collect::<Vec<_>>();
+    let choice = gtk::DropDown::from_strings(
+        &profiles
+            .iter()
+            .map(|(_, description)| description.as_str())
+            .collect::<Vec<_>>(),
+    );
+    // one that's unavailable now may still be active
+    let active = profiles
+        .iter()
+        .position(|(name, _)| card.active_profile.as_ref() == Some(name));
+    choice.set_selected(active.map_or(gtk::INVALID_LIST_POSITION, |active| active as u32));
+    let card = card.name.clone();
+    choice.connect_selected_notify(move |choice| {
+        if let Some((profile, _)) = profiles.get(choice.selected() as usize) {
+            dispatcher.dispatch(Action::SetCardProfile {
+                card: card.clone(),
+                profile: profile.clone(),
+            });
+        }
+    });
+    choice
+}
+
+/// One row per card, picking its profile.
+fn row(card: &Card, dispatcher: Dispatcher) -> gtk::Box {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(card.description()));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    row.append(&label);
+    row.append(&profile_picker(card, dispatcher));
+    row
+}
+
+/// Expander picking the profile of every card, following changes made elsewhere.
+pub fn card_panel(cx: Scope, dispatcher: Dispatcher) -> gtk::Expander {
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    let list = || {
+        Card::list()
+            .map_err(|error| warn!(?error, "listing cards"))
+            .unwrap_or_default()
+    };
+    let cards = create_rw_signal(cx, list());
+    events::subscribe(move |event| {
+        if event.facility == Facility::Card {
+            cards.set(list());
+        }
+    });
+    watch(cx, &rows, move |rows| {
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        cards.with(|cards| {
+            for card in cards {
+                rows.append(&row(card, dispatcher.clone()));
+            }
+        });
+    });
+    gtk::Expander::builder()
+        .label("Cards")
+        .child(&rows)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,129 @@
+//! Remixing a sink's channels, e.g. folding stereo down to mono or swapping sides. The remix is a
+//! `module-remap-sink` on top of the real sink, created and removed as the
+//! setting changes and recreated whenever the sink reconnects.
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    devices::{Device, DeviceKind},
+    modules::{self, Module},
+    AudioControls,
+};
+
+/// Prefix of the object name of remap sinks, `channel-mix:<mix>:<master>`.
+const CHANNEL_MIX: &str = "channel-mix";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChannelMix {
+    /// Both channels carry the sum of all of them, for single-sided hearing.
+    Mono,
+    /// Left and right trade places, for reversed wiring or headphones worn backwards.
+    Swap,
+}
+
+impl ChannelMix {
+    fn key(self) -> &'static str {
+        match self {
+            Self::Mono => "mono",
+            Self::Swap => "swap",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "mono" => Some(Self::Mono),
+            "swap" => Some(Self::Swap),
+            _ => None,
+        }
+    }
+
+    /// Arguments of `module-remap-sink` producing this mix.
+    fn remap(self) -> &'static [&'static str] {
+        match self {
+            Self::Mono => &["channels=2", "channel_map=mono,mono"],
+            Self::Swap => &[
+                "channels=2",
+                "channel_map=front-left,front-right",
+                "master_channel_map=front-right,front-left",
+                "remix=no",
+            ],
+        }
+    }
+}
+
+/// Name of the remap sink applying `mix` to `master`.
+fn sink_name(master: &str, mix: ChannelMix) -> String {
+    format!("{master}.{}-{}", clap::crate_name!(), mix.key())
+}
+
+/// Parses `channel-mix:<mix>:<master>`.
+fn parse_object(object: &str) -> Option<(ChannelMix, &str)> {
+    let (mix, master) = object
+        .strip_prefix(CHANNEL_MIX)?
+        .strip_prefix(':')?
+        .split_once(':')?;
+    Some((ChannelMix::from_key(mix)?, master))
+}
+
+/// The real sink underneath, when `device` is one of pipeweld's remap sinks.
+pub fn master_of(device: &Device) -> Option<&str> {
+    device
+        .properties
+        .get(modules::OBJECT_TAG)
+        .and_then(|object| parse_object(object))
+        .map(|(_, master)| master)
+}
+
+/// Puts `mix` on top of `master`, replacing any other one, or takes it off for `None`.
+/// Whichever of them was the default output stays that way.
+#[instrument(skip(master), fields(master = %master.name), ret, err)]
+pub fn apply(master: &Device, mix: Option<ChannelMix>) -> Result<()> {
+    let default = AudioControls::default_device(DeviceKind::Sink)?;
+    let mut follow = default == master.name;
+    let mut loaded = false;
+    for module in Module::list()? {
+        let Some((current, name)) = module.object().and_then(parse_object) else {
+            continue;
+        };
+        if name != master.name {
+            continue;
+        }
+        if Some(current) == mix {
+            loaded = true;
+            continue;
+        }
+        follow |= default == sink_name(name, current);
+        Module::unload(module.index)?;
+    }
+    match mix {
+        Some(mix) if !loaded => {
+            let object = format!("{CHANNEL_MIX}:{}:{}", mix.key(), master.name);
+            let remap = sink_name(&master.name, mix);
+            let description = format!("{} ({})", master.description, mix.key()).replace('\'', "");
+            Module::load(
+                "module-remap-sink",
+                &mix.remap()
+                    .iter()
+                    .copied()
+                    .map(str::to_owned)
+                    .chain([
+                        format!("master={}", master.name),
+                        format!("sink_name={remap}"),
+                        format!(
+                            "sink_properties=\"device.description='{description}' {}\"",
+                            Module::tag(&object)
+                        ),
+                    ])
+                    .collect::<Vec<_>>(),
+            )?;
+            if follow {
+                AudioControls::set_default(DeviceKind::Sink, &remap)?;
+            }
+        }
+        None if follow => AudioControls::set_default(DeviceKind::Sink, &master.name)?,
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,637 @@
+//! Subcommands running a single change and exiting, without starting GTK, for
+//! keybindings and scripts, as in `pipeweld volume +5` or `pipeweld mute toggle`,
+//! or for looking around, as in `pipeweld list streams --columns name,output`.
+//! Without a subcommand pipeweld starts as usual.
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use eyre::{bail, eyre, Result};
+use serde_json::Value;
+
+use crate::{
+    actions::Action,
+    backup::{self, Bundle},
+    cycle,
+    device_settings::DeviceStore,
+    devices::{Device, DeviceKind},
+    graph_file::GraphFile,
+    instance, levels, logs, resources,
+    routing::{self, Snapshot},
+    status::{self, Shape},
+    streams::Stream,
+    table::{Align, Color, Emphasis, Table},
+    tui, AudioControls, DiffValue,
+};
+
+#[derive(Debug, Parser)]
+#[command(name = clap::crate_name!(), version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints the default device's volume, or changes it, as in +5, -5 or 40.
+    Volume {
+        #[arg(allow_hyphen_values = true)]
+        change: Option<Volume>,
+        /// Of the default input rather than the output.
+        #[arg(long)]
+        input: bool,
+    },
+    /// Prints whether the default device is muted, or mutes it.
+    Mute {
+        mute: Option<Mute>,
+        /// Of the default input rather than the output.
+        #[arg(long)]
+        input: bool,
+    },
+    /// Prints the default device, or makes another one the default.
+    Default {
+        /// Name of the device, or `next` for the one after the current default.
+        device: Option<String>,
+        /// The default input rather than the output.
+        #[arg(long)]
+        input: bool,
+    },
+    /// Lists the outputs, inputs or streams, as a table or as JSON.
+    List {
+        #[arg(value_enum, default_value_t = Listed::Outputs)]
+        what: Listed,
+        /// Columns to show, in order, as in `name,volume,port`.
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Vec<Column>,
+        /// Prints a JSON object for each row instead, keyed by column.
+        #[arg(long)]
+        json: bool,
+        #[arg(long, value_enum, default_value_t)]
+        color: Color,
+    },
+    /// Prints the default device's volume as JSON for status bars, with `--follow`
+    /// a line whenever it changes.
+    Status {
+        /// Keeps printing until the bar goes away.
+        #[arg(long)]
+        follow: bool,
+        /// Of the default input rather than the output.
+        #[arg(long)]
+        input: bool,
+        #[arg(long, value_enum, default_value_t)]
+        shape: Shape,
+    },
+    /// Opens a mixer in the terminal, for SSH sessions and the like.
+    Tui,
+    /// Raises the window of the running pipeweld, or starts it.
+    Show,
+    /// Runs synthetic level meters for a while and prints what they cost, for
+    /// checking the meter pipeline stays cheap.
+    #[command(hide = true)]
+    BenchMeters {
+        #[arg(long, default_value_t = 20)]
+        meters: usize,
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+    },
+    /// Moves all of pipeweld's settings into or out of one file, for another machine.
+    Settings {
+        #[command(subcommand)]
+        command: Settings,
+    },
+    /// Saves the routing under a name, or puts it back as it was saved.
+    Preset {
+        #[command(subcommand)]
+        command: Preset,
+    },
+    /// Writes the nodes, ports, links and volumes out as JSON, or puts them back.
+    Graph {
+        #[command(subcommand)]
+        command: GraphCommand,
+    },
+    /// Packs the recent logs, what the server says about itself and the graph into
+    /// a tarball to attach to a bug report, private names replaced.
+    DebugBundle {
+        /// Where to write it, `pipeweld-debug.tar.gz` when left out.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Installs the desktop entry and the icons, so launchers list pipeweld and the
+    /// tray shows its icon.
+    InstallDesktopFiles {
+        /// Data directory to install into, `$XDG_DATA_HOME` when left out.
+        #[arg(long)]
+        prefix: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum Settings {
+    /// Bundles the settings up into `file`.
+    Export { file: PathBuf },
+    /// Replaces the settings with those bundled in `file`.
+    Import { file: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum GraphCommand {
+    /// Writes the graph into `file`.
+    Export { file: PathBuf },
+    /// Puts back the links, defaults and volumes saved in `file`.
+    Import { file: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum Preset {
+    /// Saves the defaults, volumes, streams and links as preset `name`.
+    Save {
+        name: String,
+    },
+    /// Puts the routing of preset `name` back.
+    Apply {
+        name: String,
+    },
+    /// Prints the names of the saved presets.
+    List,
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Volume {
+    Change(DiffValue),
+    Set(u32),
+}
+
+impl std::str::FromStr for Volume {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let number = value.trim_end_matches('%');
+        let invalid = |_| format!("{value} is not a volume, as in +5, -5 or 40");
+        match number.starts_with(['+', '-']) {
+            true => number.parse().map(|diff| Self::Change(DiffValue(diff))),
+            false => number.parse().map(Self::Set),
+        }
+        .map_err(invalid)
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mute {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Listed {
+    Outputs,
+    Inputs,
+    Streams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Column {
+    Index,
+    Name,
+    Description,
+    Volume,
+    Mute,
+    /// The active port.
+    Port,
+    /// Running, idle or suspended.
+    State,
+    Format,
+    /// Whether it's the default device.
+    Default,
+    /// Where a stream plays.
+    Output,
+}
+
+impl Listed {
+    fn kind(self) -> Option<DeviceKind> {
+        match self {
+            Self::Outputs => Some(DeviceKind::Sink),
+            Self::Inputs => Some(DeviceKind::Source),
+            Self::Streams => None,
+        }
+    }
+
+    /// Columns shown unless others are asked for.
+    fn columns(self) -> &'static [Column] {
+        match self {
+            Self::Outputs | Self::Inputs => &[
+                Column::Default,
+                Column::Name,
+                Column::Description,
+                Column::Volume,
+                Column::Mute,
+                Column::Port,
+            ],
+            Self::Streams => &[
+                Column::Index,
+                Column::Name,
+                Column::Volume,
+                Column::Mute,
+                Column::Output,
+            ],
+        }
+    }
+
+    fn offers(self, column: Column) -> bool {
+        match self {
+            Self::Outputs | Self::Inputs => column != Column::Output,
+            Self::Streams => !matches!(
+                column,
+                Column::Description | Column::Port | Column::State | Column::Default
+            ),
+        }
+    }
+}
+
+impl Column {
+    /// As given to `--columns`, and the key in JSON.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Index => "index",
+            Self::Name => "name",
+            Self::Description => "description",
+            Self::Volume => "volume",
+            Self::Mute => "mute",
+            Self::Port => "port",
+            Self::State => "state",
+            Self::Format => "format",
+            Self::Default => "default",
+            Self::Output => "output",
+        }
+    }
+
+    fn align(self) -> Align {
+        match self {
+            Self::Index | Self::Volume => Align::Right,
+            _ => Align::Left,
+        }
+    }
+
+    fn of_device(self, device: &Device, default: Option<&str>) -> Value {
+        match self {
+            Self::Index => device.index.into(),
+            Self::Name => device.name.clone().into(),
+            Self::Description => device.description.clone().into(),
+            Self::Volume => device.volume_percent().into(),
+            Self::Mute => device.mute.into(),
+            Self::Port => device.active_port.clone().into(),
+            Self::State => device.state.to_lowercase().into(),
+            Self::Format => device.sample_specification.clone().into(),
+            Self::Default => (default == Some(device.name.as_str())).into(),
+            Self::Output => Value::Null,
+        }
+    }
+
+    fn of_stream(self, stream: &Stream, sinks: &HashMap<u32, String>) -> Value {
+        match self {
+            Self::Index => stream.index.into(),
+            Self::Name => stream.title().into(),
+            Self::Volume => stream.volume_percent().into(),
+            Self::Mute => stream.mute.into(),
+            Self::Format => stream.sample_specification.clone().into(),
+            Self::Output => sinks.get(&stream.sink).cloned().into(),
+            _ => Value::Null,
+        }
+    }
+
+    /// How `value` of this column shows in a table.
+    fn text(self, value: &Value) -> String {
+        match (self, value) {
+            (Self::Mute, Value::Bool(true)) => "muted".to_owned(),
+            (Self::Default, Value::Bool(true)) => "*".to_owned(),
+            (_, Value::Bool(_)) => String::new(),
+            (_, Value::Null) => "-".to_owned(),
+            (Self::Volume, value) => format!("{value}%"),
+            (_, Value::String(text)) => text.clone(),
+            (_, value) => value.to_string(),
+        }
+    }
+}
+
+fn list(what: Listed, columns: Vec<Column>, json: bool, color: Color) -> Result<()> {
+    let columns = match columns.is_empty() {
+        true => what.columns().to_vec(),
+        false => columns,
+    };
+    if let Some(column) = columns.iter().find(|column| !what.offers(**column)) {
+        bail!(
+            "there is no {} column for {}",
+            column.name(),
+            what.to_possible_value()
+                .expect("nothing is skipped")
+                .get_name()
+        );
+    }
+    // cells for each row, muted ones fade and the default device stands out
+    let rows = match what.kind() {
+        Some(kind) => {
+            let default = AudioControls::default_device(kind).ok();
+            Device::list(kind)?
+                .iter()
+                .map(|device| {
+                    let cells = columns
+                        .iter()
+                        .map(|column| column.of_device(device, default.as_deref()))
+                        .collect::<Vec<_>>();
+                    let emphasis = match device.mute {
+                        true => Emphasis::Faded,
+                        false if default.as_deref() == Some(device.name.as_str()) => {
+                            Emphasis::Highlight
+                        }
+                        false => Emphasis::Plain,
+                    };
+                    (cells, emphasis)
+                })
+                .collect::<Vec<_>>()
+        }
+        None => {
+            let sinks = Device::list(DeviceKind::Sink)?
+                .into_iter()
+                .map(|sink| (sink.index, sink.name))
+                .collect();
+            Stream::list()?
+                .iter()
+                .map(|stream| {
+                    let cells = columns
+                        .iter()
+                        .map(|column| column.of_stream(stream, &sinks))
+                        .collect::<Vec<_>>();
+                    let emphasis = match stream.mute {
+                        true => Emphasis::Faded,
+                        false => Emphasis::Plain,
+                    };
+                    (cells, emphasis)
+                })
+                .collect()
+        }
+    };
+    if json {
+        let objects = rows
+            .into_iter()
+            .map(|(cells, _)| {
+                Value::Object(
+                    columns
+                        .iter()
+                        .map(|column| column.name().to_owned())
+                        .zip(cells)
+                        .collect(),
+                )
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&Value::Array(objects))?);
+        return Ok(());
+    }
+    let mut table = Table::new(
+        columns
+            .iter()
+            .map(|column| (column.name().to_uppercase(), column.align())),
+    );
+    for (cells, emphasis) in rows {
+        table.push(
+            columns
+                .iter()
+                .zip(&cells)
+                .map(|(column, value)| column.text(value))
+                .collect(),
+            emphasis,
+        );
+    }
+    print!("{}", table.render(color.enabled()));
+    Ok(())
+}
+
+fn kind(input: bool) -> DeviceKind {
+    match input {
+        true => DeviceKind::Source,
+        false => DeviceKind::Sink,
+    }
+}
+
+fn default_device(kind: DeviceKind) -> Result<Device> {
+    Device::default_of(kind)?.ok_or_else(|| eyre!("there is no default {}", kind.noun()))
+}
+
+impl Settings {
+    fn run(self) -> Result<()> {
+        match self {
+            Self::Export { file } => {
+                let bundle = Bundle::collect()?;
+                bundle.write(&file)?;
+                println!(
+                    "exported {} files from {}",
+                    bundle.files.len(),
+                    backup::directory().display()
+                );
+            }
+            Self::Import { file } => {
+                for path in Bundle::read(&file)?.unpack()? {
+                    println!("{}", path.display());
+                }
+                println!("restart pipeweld to pick the settings up");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Preset {
+    fn run(self) -> Result<()> {
+        match self {
+            Self::Save { name } => Snapshot::take()?.save(&name)?,
+            Self::Apply { name } => Snapshot::load(&name)?.apply()?,
+            Self::List => {
+                for name in routing::saved()? {
+                    println!("{name}");
+                }
+            }
+            Self::Remove { name } => routing::remove(&name)?,
+        }
+        Ok(())
+    }
+}
+
+impl GraphCommand {
+    fn run(self) -> Result<()> {
+        match self {
+            Self::Export { file } => {
+                let graph = GraphFile::take()?;
+                graph.write(&file)?;
+                println!(
+                    "exported {} nodes and {} links",
+                    graph.nodes.len(),
+                    graph.links.len()
+                );
+            }
+            Self::Import { file } => GraphFile::read(&file)?.apply()?,
+        }
+        Ok(())
+    }
+}
+
+impl Command {
+    fn run(self) -> Result<()> {
+        // the device settings are loaded where needed, importing may fix broken ones
+        match self {
+            Self::Volume { change, input } => {
+                let kind = kind(input);
+                let device = default_device(kind)?;
+                // the limits set in the window hold here too
+                let store = DeviceStore::load()?;
+                match change {
+                    None => println!("{}", device.volume_percent()),
+                    Some(Volume::Change(diff)) if kind == DeviceKind::Sink => {
+                        AudioControls::change_volume_percent(diff, store.max_volume(&device))?
+                    }
+                    Some(Volume::Change(diff)) => AudioControls::change_default_volume(kind, diff)?,
+                    Some(Volume::Set(percent)) => AudioControls::set_volume_percent(
+                        kind,
+                        &device.name,
+                        store.limit_volume(&device, percent),
+                    )?,
+                }
+            }
+            Self::Mute { mute, input } => {
+                let kind = kind(input);
+                match mute {
+                    None => println!(
+                        "{}",
+                        match AudioControls::is_muted(kind)? {
+                            true => "muted",
+                            false => "unmuted",
+                        }
+                    ),
+                    Some(Mute::Toggle) => AudioControls::toggle_mute(kind)?,
+                    Some(mute) => AudioControls::set_mute(
+                        kind,
+                        kind.default_alias(),
+                        matches!(mute, Mute::On),
+                    )?,
+                }
+            }
+            Self::Default { device, input } => {
+                let kind = kind(input);
+                match device.as_deref() {
+                    None => println!("{}", AudioControls::default_device(kind)?),
+                    Some("next") => cycle::cycle(kind, &DeviceStore::load()?)?,
+                    Some(name) => AudioControls::set_default(kind, name)?,
+                }
+            }
+            Self::List {
+                what,
+                columns,
+                json,
+                color,
+            } => list(what, columns, json, color)?,
+            Self::Status {
+                follow,
+                input,
+                shape,
+            } => status::run(kind(input), shape, follow)?,
+            Self::Tui => tui::run()?,
+            Self::Show => instance::show()?,
+            Self::BenchMeters { meters, seconds } => {
+                println!("{}", levels::bench(meters, Duration::from_secs(seconds))?);
+            }
+            Self::Settings { command } => command.run()?,
+            Self::Preset { command } => command.run()?,
+            Self::Graph { command } => command.run()?,
+            Self::DebugBundle { output } => {
+                let output = output.unwrap_or_else(|| PathBuf::from("pipeweld-debug.tar.gz"));
+                let names = logs::bundle(&output)?;
+                println!("wrote {} with {}", output.display(), names.join(", "));
+            }
+            Self::InstallDesktopFiles { prefix } => {
+                resources::register()?;
+                let prefix = prefix.unwrap_or_else(resources::default_prefix);
+                for path in resources::install(&prefix)? {
+                    println!("installed {}", path.display());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// What the subcommand changes as an action for the running instance, `None`
+    /// for those only printing or working on their own. A bare `mute` toggles,
+    /// there's nowhere to print to.
+    fn action(self) -> Option<Action> {
+        Some(match self {
+            Self::Volume {
+                change: Some(change),
+                input,
+            } => match (change, kind(input)) {
+                (Volume::Change(diff), DeviceKind::Sink) => Action::ChangeVolume(diff),
+                (Volume::Change(diff), DeviceKind::Source) => Action::ChangeInputVolume(diff),
+                (Volume::Set(percent), kind) => {
+                    Action::SetVolume(kind, kind.default_alias().to_owned(), percent)
+                }
+            },
+            Self::Mute { mute, input } => {
+                let kind = kind(input);
+                match mute {
+                    None | Some(Mute::Toggle) => Action::ToggleMute(kind),
+                    Some(mute) => Action::SetMute(
+                        kind,
+                        kind.default_alias().to_owned(),
+                        matches!(mute, Mute::On),
+                    ),
+                }
+            }
+            Self::Default {
+                device: Some(device),
+                input,
+            } => match device.as_str() {
+                "next" => Action::CycleDefault(kind(input)),
+                _ => Action::SetDefault(kind(input), device),
+            },
+            Self::Show => Action::ShowWindow,
+            _ => return None,
+        })
+    }
+}
+
+/// Runs the subcommand in `args`, if there is one, returning the exit code. GTK's
+/// own options are left alone, the subcommand is the first argument named after one.
+pub fn run(args: &[String]) -> Option<i32> {
+    let subcommands = Cli::command()
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_owned())
+        .chain(["help".to_owned()])
+        .collect::<Vec<_>>();
+    let start = args
+        .iter()
+        .skip(1)
+        .position(|arg| subcommands.contains(arg))?
+        + 1;
+    let cli = match Cli::try_parse_from(args[..1].iter().chain(&args[start..])) {
+        Ok(cli) => cli,
+        Err(error) => {
+            error.print().ok();
+            return Some(error.exit_code());
+        }
+    };
+    match cli.command.run() {
+        Ok(()) => Some(0),
+        Err(error) => {
+            eprintln!("[ERROR] {error:#}");
+            Some(1)
+        }
+    }
+}
+
+/// The action `line` asks the running instance for, in the words of the subcommands
+/// above, as in `volume +5` or `mute toggle --input`, see [`crate::fifo`].
+pub fn action(line: &str) -> Result<Action> {
+    let cli =
+        Cli::try_parse_from(std::iter::once(clap::crate_name!()).chain(line.split_whitespace()))?;
+    cli.command.action().ok_or_else(|| {
+        eyre!("{line:?} changes nothing, as in volume +5, mute toggle or default next")
+    })
+}
@@ -0,0 +1,310 @@
+//! Which versions of the servers and tools pipeweld talks to, detected when it
+//! connects, and what follows from them. What isn't there on older versions is
+//! worked around where there's another way and refused with a clear error where
+//! there's none:
+//!
+//! - `pactl` before 16 prints no JSON, so volumes go through `wpctl` when it's
+//!   installed, see [`crate::backend`];
+//! - PipeWire before 0.3.33 has no `clock.force-rate` and `clock.force-quantum` in
+//!   its settings metadata, so game mode and bit-perfect playback can't force them;
+//! - PulseAudio may use flat volumes, which the mixer warns about, see
+//!   [`crate::flat_volumes`].
+//!
+//! Versions that can't be told, as with a server that isn't running, don't hold
+//! anything back.
+use std::{
+    fmt,
+    process::Command,
+    sync::{Mutex, PoisonError},
+};
+
+use eyre::{bail, Result};
+use gtk::{prelude::*, Orientation};
+use tracing::{debug, info};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    backend, checked_output, flat_volumes,
+    i18n::tr,
+    tool_output,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub micro: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, micro: u32) -> Self {
+        Self {
+            major,
+            minor,
+            micro,
+        }
+    }
+
+    /// The first version number in `text`, like `16.1` in `pactl 16.1` or `1.0.5`
+    /// in `"1.0.5"`, with missing parts as 0.
+    pub fn parse(text: &str) -> Option<Self> {
+        let start = text.find(|char: char| char.is_ascii_digit())?;
+        let mut parts = text[start..]
+            .split(|char: char| !char.is_ascii_digit() && char != '.')
+            .next()?
+            .split('.')
+            .map(|part| part.parse::<u32>().ok());
+        let major = parts.next()??;
+        let mut next = || parts.next().flatten().unwrap_or(0);
+        Some(Self::new(major, next(), next()))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.micro)
+    }
+}
+
+/// Something that needs a newer version than some have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// `pactl --format=json`.
+    PactlJson,
+    /// `clock.force-rate` and `clock.force-quantum` in PipeWire's settings.
+    GraphSettings,
+}
+
+impl Feature {
+    pub const ALL: [Self; 2] = [Self::PactlJson, Self::GraphSettings];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::PactlJson => "Reading devices and streams as JSON",
+            Self::GraphSettings => "Forcing the graph's rate and quantum",
+        }
+    }
+
+    /// The tool and the version it's there since.
+    fn needs(self) -> (&'static str, Version) {
+        match self {
+            Self::PactlJson => ("pactl", Version::new(16, 0, 0)),
+            Self::GraphSettings => ("PipeWire", Version::new(0, 3, 33)),
+        }
+    }
+}
+
+/// What was detected, `None` for what couldn't be told.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Versions {
+    pub pactl: Option<Version>,
+    /// Like `PulseAudio (on PipeWire 1.0.5)`.
+    pub pulse_server: Option<String>,
+    /// The PulseAudio version the server speaks as, 15.0.0 for any PipeWire.
+    pub pulse: Option<Version>,
+    pub pulse_protocol: Option<u32>,
+    pub pipewire: Option<Version>,
+    /// The library the session manager is linked with.
+    pub wireplumber: Option<Version>,
+    /// Whether the server uses flat volumes, see [`crate::flat_volumes`].
+    pub flat_volumes: bool,
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    checked_output(Command::new(program).args(args))
+        .map_err(|error| debug!(%error, program, "detecting the version"))
+        .ok()
+        .map(|stdout| String::from_utf8_lossy(&stdout).into_owned())
+}
+
+impl Versions {
+    pub fn detect() -> Self {
+        let pactl = output("pactl", &["--version"]).and_then(|version| Version::parse(&version));
+        let info = output("pactl", &["info"]).unwrap_or_default();
+        let pulse_server = tool_output::field(&info, "Server Name").map(str::to_owned);
+        let pulse = tool_output::field(&info, "Server Version").and_then(Version::parse);
+        let pulse_protocol = tool_output::field(&info, "Server Protocol Version")
+            .and_then(|protocol| protocol.parse().ok());
+        // the core's own version, or what PipeWire's pulse server says it runs on
+        let pipewire = output("pw-cli", &["info", "0"])
+            .and_then(|core| {
+                core.lines()
+                    .map(|line| {
+                        line.trim_start_matches(|char: char| char == '*' || char.is_whitespace())
+                    })
+                    .find_map(|line| line.strip_prefix("version:"))
+                    .and_then(Version::parse)
+            })
+            .or_else(|| {
+                let (_, on) = pulse_server.as_deref()?.split_once("PipeWire")?;
+                Version::parse(on)
+            });
+        // `Linked with libwireplumber 0.4.17` below the one it was compiled with
+        let wireplumber = output("wireplumber", &["--version"]).and_then(|version| {
+            version
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("Linked with"))
+                .and_then(Version::parse)
+                .or_else(|| Version::parse(&version))
+        });
+        let flat_volumes = flat_volumes::detect(pulse_server.as_deref());
+        let versions = Self {
+            pactl,
+            pulse_server,
+            pulse,
+            pulse_protocol,
+            pipewire,
+            wireplumber,
+            flat_volumes,
+        };
+        info!(?versions, "detected versions");
+        versions
+    }
+
+    fn version_of(&self, tool: &str) -> Option<Version> {
+        match tool {
+            "pactl" => self.pactl,
+            "PipeWire" => self.pipewire,
+            _ => None,
+        }
+    }
+
+    /// Whether `feature` can be used, true when the version isn't known.
+    pub fn supports(&self, feature: Feature) -> bool {
+        let (tool, since) = feature.needs();
+        self.version_of(tool).is_none_or(|version| version >= since)
+    }
+
+    /// What can't be used, and why.
+    pub fn missing(&self) -> Vec<String> {
+        Feature::ALL
+            .into_iter()
+            .filter_map(|feature| {
+                let (tool, since) = feature.needs();
+                let version = self.version_of(tool).filter(|version| *version < since)?;
+                Some(format!(
+                    "{}: needs {tool} {since}, this is {version}.",
+                    feature.label()
+                ))
+            })
+            .collect()
+    }
+}
+
+static DETECTED: Mutex<Option<Versions>> = Mutex::new(None);
+
+/// The versions as detected on connecting.
+pub fn get() -> Versions {
+    let mut detected = DETECTED.lock().unwrap_or_else(PoisonError::into_inner);
+    detected.get_or_insert_with(Versions::detect).clone()
+}
+
+/// Detects the versions again, after the server was upgraded and restarted.
+pub fn redetect() -> Versions {
+    let versions = Versions::detect();
+    *DETECTED.lock().unwrap_or_else(PoisonError::into_inner) = Some(versions.clone());
+    versions
+}
+
+/// Fails with what's needed when `feature` can't be used.
+pub fn require(feature: Feature) -> Result<()> {
+    let (tool, since) = feature.needs();
+    if let Some(version) = get().version_of(tool).filter(|version| *version < since) {
+        bail!(
+            "{} needs {tool} {since} or newer, this is {version}",
+            feature.label()
+        );
+    }
+    Ok(())
+}
+
+/// Detects the versions off the main thread, so they're known by the time they're
+/// asked for.
+pub fn spawn() {
+    std::thread::spawn(|| drop(get()));
+}
+
+fn fill(card: &gtk::Box, versions: &Versions) {
+    while let Some(child) = card.first_child() {
+        card.remove(&child);
+    }
+    let unknown = || "unknown".to_owned();
+    let show = |version: Option<Version>| version.map_or_else(unknown, |v| v.to_string());
+    let rows = [
+        ("pipeweld", clap::crate_version!().to_owned()),
+        ("Backend", backend::current().name().to_owned()),
+        ("pactl", show(versions.pactl)),
+        (
+            "Pulse server",
+            versions.pulse_server.clone().unwrap_or_else(unknown),
+        ),
+        ("Speaking as PulseAudio", show(versions.pulse)),
+        (
+            "Pulse protocol",
+            versions
+                .pulse_protocol
+                .map_or_else(unknown, |protocol| protocol.to_string()),
+        ),
+        ("PipeWire", show(versions.pipewire)),
+        ("WirePlumber", show(versions.wireplumber)),
+        (
+            "Flat volumes",
+            match versions.flat_volumes {
+                true => "on",
+                false => "off",
+            }
+            .to_owned(),
+        ),
+    ];
+    let grid = gtk::Grid::builder()
+        .row_spacing(2)
+        .column_spacing(12)
+        .build();
+    for (row, (name, value)) in rows.into_iter().enumerate() {
+        let name = gtk::Label::new(Some(name));
+        name.set_halign(gtk::Align::Start);
+        name.add_css_class("dim-label");
+        let value = gtk::Label::new(Some(&value));
+        value.set_halign(gtk::Align::Start);
+        value.set_selectable(true);
+        grid.attach(&name, 0, row as i32, 1, 1);
+        grid.attach(&value, 1, row as i32, 1, 1);
+    }
+    card.append(&grid);
+    for line in versions.missing() {
+        let label = gtk::Label::new(Some(&format!("• {line}")));
+        label.set_wrap(true);
+        label.set_max_width_chars(60);
+        label.set_halign(gtk::Align::Start);
+        label.add_css_class("warning");
+        card.append(&label);
+    }
+}
+
+/// Expander with the detected versions and what they hold back, detected again on
+/// opening, and a button copying diagnostics for bug reports.
+pub fn about_panel(dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let card = gtk::Box::new(Orientation::Vertical, 4);
+    layout.append(&card);
+    let copy = gtk::Button::with_label(&tr("Copy diagnostics"));
+    copy.set_tooltip_text(Some(&tr(
+        "Versions, devices and recent errors, for pasting into a bug report",
+    )));
+    copy.set_halign(gtk::Align::Start);
+    copy.connect_clicked(move |_| dispatcher.dispatch(Action::CopyDiagnostics));
+    layout.append(&copy);
+    let expander = gtk::Expander::builder()
+        .label("About")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    expander.connect_expanded_notify(move |expander| {
+        if expander.is_expanded() {
+            fill(&card, &redetect());
+        }
+    });
+    expander
+}
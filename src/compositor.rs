@@ -0,0 +1,613 @@
+//! What the window system has in focus, delivered on the GTK main loop like server
+//! events are. Workspace profiles, game mode and the focused application's volume
+//! all follow it through [`WindowSystem`], implemented over sway's and Hyprland's
+//! IPC and over EWMH properties on X11. The same IPC places the volume popup.
+use std::{
+    io::{BufRead, BufReader},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::glib;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::checked_output;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Workspace {
+    pub name: String,
+    /// The display showing the workspace, like `HDMI-A-1`.
+    pub output: String,
+}
+
+/// The application behind a window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub pid: Option<u32>,
+    /// Wayland app id, or X11 class.
+    pub app_id: Option<String>,
+}
+
+/// A display and where it sits in the layout, in logical pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Has the focus, or is the primary display on X11, which has no focused one.
+    pub focused: bool,
+}
+
+impl Output {
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Workspace(Workspace),
+    /// Whether the focused window now covers its whole display.
+    Fullscreen(bool),
+    /// Another window got focus, or none has it anymore.
+    Focus(Option<Window>),
+}
+
+/// A source of focus changes.
+pub trait WindowSystem: Send {
+    fn name(&self) -> &'static str;
+
+    fn focused_workspace(&self) -> Result<Workspace>;
+
+    /// Forwards what changes about the focus until the window system goes away,
+    /// which is an error, or nobody listens anymore.
+    fn follow(&self, sender: &glib::Sender<Change>) -> Result<()>;
+
+    fn outputs(&self) -> Result<Vec<Output>>;
+
+    /// Where the pointer is, for window systems telling.
+    fn cursor(&self) -> Result<Option<(i32, i32)>> {
+        Ok(None)
+    }
+
+    /// Moves pipeweld's own window titled `title`, top left corner first, making it
+    /// float where windows are tiled.
+    fn move_window(&self, title: &str, x: i32, y: i32) -> Result<()>;
+}
+
+/// The window system of the session pipeweld runs in, if it is one it can talk to.
+pub fn detect() -> Option<Box<dyn WindowSystem>> {
+    let set = |variable| std::env::var_os(variable).is_some();
+    if set("SWAYSOCK") {
+        Some(Box::new(Sway))
+    } else if set("HYPRLAND_INSTANCE_SIGNATURE") {
+        Some(Box::new(Hyprland))
+    } else if set("DISPLAY") && !set("WAYLAND_DISPLAY") {
+        Some(Box::new(X11))
+    } else {
+        None
+    }
+}
+
+/// Output of a command, failing on a non-zero exit.
+fn output(program: &str, args: &[&str]) -> Result<Vec<u8>> {
+    checked_output(Command::new(program).args(args))
+}
+
+pub struct Sway;
+
+impl WindowSystem for Sway {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn focused_workspace(&self) -> Result<Workspace> {
+        #[derive(Deserialize)]
+        struct SwayWorkspace {
+            #[serde(flatten)]
+            workspace: Workspace,
+            focused: bool,
+        }
+        let workspaces: Vec<SwayWorkspace> =
+            serde_json::from_slice(&output("swaymsg", &["-t", "get_workspaces", "-r"])?)
+                .wrap_err("parsing sway workspaces")?;
+        workspaces
+            .into_iter()
+            .find(|workspace| workspace.focused)
+            .map(|focused| focused.workspace)
+            .ok_or_else(|| eyre!("no workspace has focus"))
+    }
+
+    fn follow(&self, sender: &glib::Sender<Change>) -> Result<()> {
+        #[derive(Deserialize)]
+        struct WindowProperties {
+            class: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Container {
+            #[serde(default)]
+            fullscreen_mode: u8,
+            pid: Option<u32>,
+            /// Wayland clients only, XWayland ones have a class instead.
+            app_id: Option<String>,
+            window_properties: Option<WindowProperties>,
+        }
+        #[derive(Deserialize)]
+        struct Event {
+            change: String,
+            current: Option<Workspace>,
+            container: Option<Container>,
+        }
+        let mut child = Command::new("swaymsg")
+            .args(["-t", "subscribe", "-m", r#"["workspace", "window"]"#])
+            .stdout(Stdio::piped())
+            .spawn()
+            .wrap_err("spawning swaymsg subscribe")?;
+        let stdout = child.stdout.take().ok_or_else(|| eyre!("no stdout"))?;
+        for line in BufReader::new(stdout).lines() {
+            let line = line.wrap_err("reading swaymsg subscribe")?;
+            let event = match serde_json::from_str::<Event>(&line) {
+                Ok(event) => event,
+                Err(error) => {
+                    debug!(?error, line, "skipping sway event");
+                    continue;
+                }
+            };
+            let changes = match (event.change.as_str(), event.current, event.container) {
+                ("focus", Some(current), _) => vec![Change::Workspace(current)],
+                // the rest are window events
+                ("focus", _, Some(container)) => vec![
+                    Change::Fullscreen(container.fullscreen_mode != 0),
+                    Change::Focus(Some(Window {
+                        pid: container.pid,
+                        app_id: container
+                            .app_id
+                            .or_else(|| container.window_properties?.class),
+                    })),
+                ],
+                ("fullscreen_mode", _, Some(container)) => {
+                    vec![Change::Fullscreen(container.fullscreen_mode != 0)]
+                }
+                // a closed fullscreen window leaves the display to others
+                ("close", _, Some(container)) if container.fullscreen_mode != 0 => {
+                    vec![Change::Fullscreen(false)]
+                }
+                _ => continue,
+            };
+            if changes
+                .into_iter()
+                .any(|change| sender.send(change).is_err())
+            {
+                child.kill().ok();
+                return Ok(());
+            }
+        }
+        let status = child.wait().wrap_err("waiting for swaymsg subscribe")?;
+        Err(eyre!("swaymsg subscribe exited with {status}"))
+    }
+
+    fn outputs(&self) -> Result<Vec<Output>> {
+        #[derive(Deserialize)]
+        struct Rect {
+            x: i32,
+            y: i32,
+            width: i32,
+            height: i32,
+        }
+        #[derive(Deserialize)]
+        struct SwayOutput {
+            name: String,
+            active: bool,
+            focused: bool,
+            rect: Rect,
+        }
+        let outputs: Vec<SwayOutput> =
+            serde_json::from_slice(&output("swaymsg", &["-t", "get_outputs", "-r"])?)
+                .wrap_err("parsing sway outputs")?;
+        Ok(outputs
+            .into_iter()
+            .filter(|output| output.active)
+            .map(|output| Output {
+                name: output.name,
+                x: output.rect.x,
+                y: output.rect.y,
+                width: output.rect.width,
+                height: output.rect.height,
+                focused: output.focused,
+            })
+            .collect())
+    }
+
+    fn move_window(&self, title: &str, x: i32, y: i32) -> Result<()> {
+        output(
+            "swaymsg",
+            &[&format!(
+                "[title=\"^{title}$\"] floating enable, sticky enable, move absolute position {x} {y}"
+            )],
+        )
+        .map(drop)
+    }
+}
+
+pub struct Hyprland;
+
+impl Hyprland {
+    /// The event socket, which moved into the runtime directory in 0.40.
+    fn socket() -> Result<PathBuf> {
+        let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+            .wrap_err("reading HYPRLAND_INSTANCE_SIGNATURE")?;
+        let runtime = glib::user_runtime_dir().join("hypr").join(&signature);
+        [runtime, PathBuf::from("/tmp/hypr").join(&signature)]
+            .into_iter()
+            .map(|directory| directory.join(".socket2.sock"))
+            .find(|socket| socket.exists())
+            .ok_or_else(|| eyre!("no Hyprland event socket for {signature}"))
+    }
+
+    /// The window with focus, which the events only name by class.
+    fn focused_window() -> Result<Option<Window>> {
+        #[derive(Deserialize)]
+        struct HyprlandWindow {
+            pid: Option<i64>,
+            class: Option<String>,
+        }
+        // an empty object when nothing has focus
+        let window: HyprlandWindow =
+            serde_json::from_slice(&output("hyprctl", &["activewindow", "-j"])?)
+                .wrap_err("parsing Hyprland's active window")?;
+        Ok(
+            (window.pid.is_some() || window.class.is_some()).then(|| Window {
+                pid: window.pid.and_then(|pid| pid.try_into().ok()),
+                app_id: window.class.filter(|class| !class.is_empty()),
+            }),
+        )
+    }
+}
+
+impl WindowSystem for Hyprland {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn focused_workspace(&self) -> Result<Workspace> {
+        #[derive(Deserialize)]
+        struct HyprlandWorkspace {
+            name: String,
+            monitor: String,
+        }
+        let workspace: HyprlandWorkspace =
+            serde_json::from_slice(&output("hyprctl", &["activeworkspace", "-j"])?)
+                .wrap_err("parsing Hyprland's active workspace")?;
+        Ok(Workspace {
+            name: workspace.name,
+            output: workspace.monitor,
+        })
+    }
+
+    fn follow(&self, sender: &glib::Sender<Change>) -> Result<()> {
+        // workspace events leave out the display, keep track of it
+        let mut focused = self.focused_workspace()?;
+        let socket = Self::socket()?;
+        let stream = UnixStream::connect(&socket)
+            .wrap_err_with(|| format!("connecting to {}", socket.display()))?;
+        // lines like `workspace>>3`, `focusedmon>>DP-1,3` and `fullscreen>>1`
+        for line in BufReader::new(stream).lines() {
+            let line = line.wrap_err("reading Hyprland events")?;
+            let Some((event, data)) = line.split_once(">>") else {
+                continue;
+            };
+            let change = match event {
+                "workspace" => Workspace {
+                    name: data.to_owned(),
+                    output: focused.output.clone(),
+                },
+                "focusedmon" => {
+                    let Some((output, name)) = data.split_once(',') else {
+                        continue;
+                    };
+                    Workspace {
+                        name: name.to_owned(),
+                        output: output.to_owned(),
+                    }
+                }
+                "fullscreen" => {
+                    if sender.send(Change::Fullscreen(data == "1")).is_err() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                "activewindow" => {
+                    let window = Self::focused_window()
+                        .map_err(|error| warn!(?error, "looking up the focused window"))
+                        .unwrap_or_default();
+                    if sender.send(Change::Focus(window)).is_err() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if change != focused {
+                focused = change.clone();
+                if sender.send(Change::Workspace(change)).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        Err(eyre!("Hyprland closed its event socket"))
+    }
+
+    fn outputs(&self) -> Result<Vec<Output>> {
+        #[derive(Deserialize)]
+        struct Monitor {
+            name: String,
+            x: i32,
+            y: i32,
+            /// In device pixels, unlike the position.
+            width: i32,
+            height: i32,
+            scale: f64,
+            focused: bool,
+        }
+        let monitors: Vec<Monitor> =
+            serde_json::from_slice(&output("hyprctl", &["monitors", "-j"])?)
+                .wrap_err("parsing Hyprland's monitors")?;
+        Ok(monitors
+            .into_iter()
+            .map(|monitor| Output {
+                name: monitor.name,
+                x: monitor.x,
+                y: monitor.y,
+                width: (f64::from(monitor.width) / monitor.scale).round() as i32,
+                height: (f64::from(monitor.height) / monitor.scale).round() as i32,
+                focused: monitor.focused,
+            })
+            .collect())
+    }
+
+    fn cursor(&self) -> Result<Option<(i32, i32)>> {
+        #[derive(Deserialize)]
+        struct Position {
+            x: i32,
+            y: i32,
+        }
+        let position: Position = serde_json::from_slice(&output("hyprctl", &["cursorpos", "-j"])?)
+            .wrap_err("parsing Hyprland's cursor position")?;
+        Ok(Some((position.x, position.y)))
+    }
+
+    fn move_window(&self, title: &str, x: i32, y: i32) -> Result<()> {
+        let window = format!("title:^({title})$");
+        output(
+            "hyprctl",
+            &[
+                "--batch",
+                &format!(
+                    "dispatch setfloating {window}; dispatch pin {window}; dispatch movewindowpixel exact {x} {y},{window}"
+                ),
+            ],
+        )
+        .map(drop)
+    }
+}
+
+/// Window managers following EWMH, through `xprop`. Only the root window's
+/// properties are followed, so a window going fullscreen is only noticed
+/// when it gets focus.
+pub struct X11;
+
+impl X11 {
+    /// The value of `property` in a line of `xprop` output, like
+    /// `_NET_CURRENT_DESKTOP(CARDINAL) = 1`.
+    fn value<'a>(line: &'a str, property: &str) -> Option<&'a str> {
+        let rest = line.strip_prefix(property)?.strip_prefix('(')?;
+        let (_, value) = rest.split_once(") = ")?;
+        Some(value.trim())
+    }
+
+    /// `"1", "2", "media"`, as xprop prints lists of strings.
+    fn strings(value: &str) -> Vec<String> {
+        value
+            .split(", ")
+            .map(|item| item.trim_matches('"').to_owned())
+            .collect()
+    }
+
+    fn root(properties: &[&str]) -> Result<String> {
+        let args = ["-root"].into_iter().chain(properties.iter().copied());
+        Ok(String::from_utf8_lossy(&output("xprop", &args.collect::<Vec<_>>())?).into_owned())
+    }
+
+    fn desktop(index: usize) -> Result<Workspace> {
+        let names = Self::root(&["_NET_DESKTOP_NAMES"])?;
+        let name = names
+            .lines()
+            .find_map(|line| Self::value(line, "_NET_DESKTOP_NAMES"))
+            .and_then(|names| Self::strings(names).into_iter().nth(index))
+            // unnamed desktops go by their number like pagers show them, from 1
+            .unwrap_or_else(|| (index + 1).to_string());
+        Ok(Workspace {
+            name,
+            output: std::env::var("DISPLAY").unwrap_or_default(),
+        })
+    }
+
+    /// The application behind window `id`, and whether it is fullscreen.
+    fn window(id: &str) -> Result<(Window, bool)> {
+        let properties = output(
+            "xprop",
+            &["-id", id, "_NET_WM_PID", "WM_CLASS", "_NET_WM_STATE"],
+        )?;
+        let properties = String::from_utf8_lossy(&properties);
+        let mut window = Window {
+            pid: None,
+            app_id: None,
+        };
+        let mut fullscreen = false;
+        for line in properties.lines() {
+            if let Some(pid) = Self::value(line, "_NET_WM_PID") {
+                window.pid = pid.parse().ok();
+            } else if let Some(class) = Self::value(line, "WM_CLASS") {
+                // instance first, then class
+                window.app_id = Self::strings(class).pop();
+            } else if let Some(state) = Self::value(line, "_NET_WM_STATE") {
+                fullscreen = state.contains("_NET_WM_STATE_FULLSCREEN");
+            }
+        }
+        Ok((window, fullscreen))
+    }
+}
+
+impl WindowSystem for X11 {
+    fn name(&self) -> &'static str {
+        "X11"
+    }
+
+    fn focused_workspace(&self) -> Result<Workspace> {
+        let current = Self::root(&["_NET_CURRENT_DESKTOP"])?;
+        let index = current
+            .lines()
+            .find_map(|line| Self::value(line, "_NET_CURRENT_DESKTOP")?.parse().ok())
+            .ok_or_else(|| eyre!("the window manager has no _NET_CURRENT_DESKTOP"))?;
+        Self::desktop(index)
+    }
+
+    fn follow(&self, sender: &glib::Sender<Change>) -> Result<()> {
+        let mut child = Command::new("xprop")
+            .args([
+                "-root",
+                "-spy",
+                "_NET_ACTIVE_WINDOW",
+                "_NET_CURRENT_DESKTOP",
+            ])
+            .stdout(Stdio::piped())
+            .spawn()
+            .wrap_err("spawning xprop -spy")?;
+        let stdout = child.stdout.take().ok_or_else(|| eyre!("no stdout"))?;
+        for line in BufReader::new(stdout).lines() {
+            let line = line.wrap_err("reading xprop -spy")?;
+            let changes = if let Some(index) = Self::value(&line, "_NET_CURRENT_DESKTOP") {
+                let Ok(index) = index.parse() else {
+                    continue;
+                };
+                vec![Change::Workspace(Self::desktop(index)?)]
+            } else if line.starts_with("_NET_ACTIVE_WINDOW") {
+                // `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3400003`, 0x0 for none
+                match line.rsplit_once(' ').map(|(_, id)| id) {
+                    Some("0x0") | None => vec![Change::Fullscreen(false), Change::Focus(None)],
+                    Some(id) => match Self::window(id) {
+                        Ok((window, fullscreen)) => {
+                            vec![Change::Fullscreen(fullscreen), Change::Focus(Some(window))]
+                        }
+                        Err(error) => {
+                            debug!(?error, id, "skipping a window that went away");
+                            continue;
+                        }
+                    },
+                }
+            } else {
+                continue;
+            };
+            if changes
+                .into_iter()
+                .any(|change| sender.send(change).is_err())
+            {
+                child.kill().ok();
+                return Ok(());
+            }
+        }
+        let status = child.wait().wrap_err("waiting for xprop -spy")?;
+        Err(eyre!("xprop -spy exited with {status}"))
+    }
+
+    fn outputs(&self) -> Result<Vec<Output>> {
+        let monitors = output("xrandr", &["--listmonitors"])?;
+        // ` 0: +*DP-1 2560/597x1440/336+0+0  DP-1`, after a `Monitors: 2` line
+        Ok(String::from_utf8_lossy(&monitors)
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace().skip(1);
+                let flags = fields.next()?;
+                // sizes come with the physical size in millimetres, after a slash
+                let (width, rest) = fields.next()?.split_once('x')?;
+                let (height, rest) = rest.split_once('+')?;
+                let (x, y) = rest.split_once('+')?;
+                let pixels = |size: &str| size.split('/').next()?.parse().ok();
+                Some(Output {
+                    name: fields.last()?.to_owned(),
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                    width: pixels(width)?,
+                    height: pixels(height)?,
+                    focused: flags.contains('*'),
+                })
+            })
+            .collect())
+    }
+
+    fn cursor(&self) -> Result<Option<(i32, i32)>> {
+        let location = output("xdotool", &["getmouselocation", "--shell"])?;
+        let location = String::from_utf8_lossy(&location);
+        let coordinate = |name: &str| {
+            location
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('=')?.parse().ok())
+        };
+        Ok(coordinate("X").zip(coordinate("Y")))
+    }
+
+    fn move_window(&self, title: &str, x: i32, y: i32) -> Result<()> {
+        output(
+            "xdotool",
+            &[
+                "search",
+                "--name",
+                &format!("^{title}$"),
+                "windowmove",
+                &x.to_string(),
+                &y.to_string(),
+            ],
+        )
+        .map(drop)
+    }
+}
+
+/// Follows the focus in a background thread, under a window system pipeweld can
+/// talk to, and calls `callback` for every change, starting with the current workspace.
+pub fn subscribe<F: FnMut(Change) + 'static>(mut callback: F) {
+    let Some(system) = detect() else {
+        return info!("no window system to talk to, not following window focus");
+    };
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, move |change| {
+        callback(change);
+        glib::Continue(true)
+    });
+    std::thread::spawn(move || loop {
+        let followed = system.focused_workspace().and_then(|workspace| {
+            match sender.send(Change::Workspace(workspace)) {
+                Ok(()) => system.follow(&sender),
+                // nobody listens anymore
+                Err(_) => Ok(()),
+            }
+        });
+        match followed {
+            Ok(()) => break,
+            Err(error) => {
+                warn!(
+                    ?error,
+                    system = system.name(),
+                    "focus subscription interrupted, retrying"
+                );
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+    });
+}
@@ -0,0 +1,529 @@
+//! How the window looks and which backend to use, from
+//! `$XDG_CONFIG_HOME/pipeweld/config.toml`, written by hand rather than from the
+//! window like the device settings. Everything left out keeps its default, as does
+//! everything when there's no file:
+//!
+//! ```toml
+//! steps = [5, 10]
+//! backend = "wpctl"
+//!
+//! [panels]
+//! inputs = false
+//! hidden = ["patchbay", "jack-transport"]
+//!
+//! [window_size]
+//! width = 480
+//! height = 720
+//!
+//! [notifications]
+//! default_changed = true
+//! device_added = true
+//!
+//! [signals]
+//! usr1 = "toggle-microphone-mute"
+//!
+//! [volume]
+//! max = 150
+//!
+//! [shutdown]
+//! remove_objects = false
+//!
+//! [http_stream]
+//! address = "127.0.0.1"
+//! ```
+//!
+//! The file is checked against what pipeweld knows before it's read. Unknown keys,
+//! values of the wrong type and deprecated options are reported with their line,
+//! see [`problems`], and whatever's wrong is left out rather than the whole file.
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+use eyre::{Result, WrapErr};
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{migrations::Format, signals::SignalAction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendName {
+    Pactl,
+    Wpctl,
+}
+
+/// What pipeweld sends desktop notifications about, nothing unless turned on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    /// Another output or input became the default.
+    pub default_changed: bool,
+    /// A device connected, like headphones or a Bluetooth headset.
+    pub device_added: bool,
+    /// The output's volume reached the limit set for it.
+    pub volume_capped: bool,
+    /// An application played or recorded for the first time, see
+    /// [`crate::first_use`].
+    pub first_use: bool,
+}
+
+/// What the signals pipeweld handles do, see [`crate::signals`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Signals {
+    pub usr1: Option<SignalAction>,
+    pub usr2: Option<SignalAction>,
+}
+
+impl Default for Signals {
+    fn default() -> Self {
+        Self {
+            usr1: Some(SignalAction::ToggleMute),
+            usr2: Some(SignalAction::ToggleWindow),
+        }
+    }
+}
+
+/// How loud volumes may go, held to by [`crate::AudioControls`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Volume {
+    /// Percent no volume is set above, past 100 amplifying what plays.
+    pub max: u32,
+    /// Volume steps read the volume first and stop at `max`. Left to the server
+    /// they're quicker, but may go past it.
+    pub limit_steps: bool,
+    /// The mixer shows applications as shares of their output when the server uses
+    /// flat volumes, see [`crate::flat_volumes`].
+    pub compensate_flat_volumes: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            max: 100,
+            limit_steps: true,
+            compensate_flat_volumes: false,
+        }
+    }
+}
+
+/// What happens when pipeweld quits, see [`crate::shutdown`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Shutdown {
+    /// Unload the virtual devices and loopbacks pipeweld loaded into the server.
+    /// Left in place they keep playing, and pipeweld takes them over when it starts
+    /// again.
+    pub remove_objects: bool,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            remove_objects: true,
+        }
+    }
+}
+
+/// Who can listen to the output over HTTP, see [`crate::http_stream`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpStream {
+    /// Address the server listens on, every interface unless only this machine
+    /// should reach it, at `127.0.0.1`.
+    pub address: IpAddr,
+    /// Listeners at once, each encoding the output anew.
+    pub max_listeners: u32,
+}
+
+impl Default for HttpStream {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            max_listeners: 4,
+        }
+    }
+}
+
+/// Which parts of the window show.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Panels {
+    pub outputs: bool,
+    pub inputs: bool,
+    /// The per-application mixer.
+    pub applications: bool,
+    /// Panels left out of the window, by the names they're styled by, like
+    /// `routing-presets` for "Routing presets", see [`crate::registry`].
+    pub hidden: Vec<String>,
+}
+
+impl Default for Panels {
+    fn default() -> Self {
+        Self {
+            outputs: true,
+            inputs: true,
+            applications: true,
+            hidden: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Percent the volume buttons step by, a pair of buttons for each.
+    pub steps: Vec<u32>,
+    pub panels: Panels,
+    /// Size the window opens at, as small as its contents allow when left out, until
+    /// pipeweld remembers the size it was left at, see [`crate::ui_state`].
+    pub window_size: Option<WindowSize>,
+    /// Picked by what's installed when left out, `PIPEWELD_BACKEND` goes first.
+    pub backend: Option<BackendName>,
+    pub notifications: Notifications,
+    pub signals: Signals,
+    pub volume: Volume,
+    pub shutdown: Shutdown,
+    pub http_stream: HttpStream,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            steps: vec![5],
+            panels: Panels::default(),
+            window_size: None,
+            backend: None,
+            notifications: Notifications::default(),
+            signals: Signals::default(),
+            volume: Volume::default(),
+            shutdown: Shutdown::default(),
+            http_stream: HttpStream::default(),
+        }
+    }
+}
+
+/// Something wrong with the configuration that didn't keep it from being read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Line of the file, counting from 1, when it could be told.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// What a key takes.
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Bool,
+    Number,
+    Numbers,
+    Strings,
+    /// An IPv4 or IPv6 address, as a string.
+    Address,
+    /// One of these strings.
+    Choice(&'static [&'static str]),
+    /// With every one of its keys given, or none.
+    Table(&'static [(&'static str, Kind)]),
+    /// Keys of which may be left out.
+    Partial(&'static [(&'static str, Kind)]),
+}
+
+const SCHEMA: &[(&str, Kind)] = &[
+    ("version", Kind::Number),
+    ("steps", Kind::Numbers),
+    (
+        "panels",
+        Kind::Partial(&[
+            ("outputs", Kind::Bool),
+            ("inputs", Kind::Bool),
+            ("applications", Kind::Bool),
+            ("hidden", Kind::Strings),
+        ]),
+    ),
+    (
+        "window_size",
+        Kind::Table(&[("width", Kind::Number), ("height", Kind::Number)]),
+    ),
+    ("backend", Kind::Choice(&["pactl", "wpctl"])),
+    (
+        "notifications",
+        Kind::Partial(&[
+            ("default_changed", Kind::Bool),
+            ("device_added", Kind::Bool),
+            ("volume_capped", Kind::Bool),
+            ("first_use", Kind::Bool),
+        ]),
+    ),
+    (
+        "signals",
+        Kind::Partial(&[
+            ("usr1", Kind::Choice(SignalAction::NAMES)),
+            ("usr2", Kind::Choice(SignalAction::NAMES)),
+        ]),
+    ),
+    (
+        "volume",
+        Kind::Partial(&[
+            ("max", Kind::Number),
+            ("limit_steps", Kind::Bool),
+            ("compensate_flat_volumes", Kind::Bool),
+        ]),
+    ),
+    ("shutdown", Kind::Partial(&[("remove_objects", Kind::Bool)])),
+    (
+        "http_stream",
+        Kind::Partial(&[("address", Kind::Address), ("max_listeners", Kind::Number)]),
+    ),
+];
+
+fn is_number(value: &toml::Value) -> bool {
+    value
+        .as_integer()
+        .is_some_and(|number| u32::try_from(number).is_ok())
+}
+
+impl Kind {
+    fn describe(self) -> String {
+        match self {
+            Self::Bool => "true or false".to_owned(),
+            Self::Number => "a whole number".to_owned(),
+            Self::Numbers => "a list of whole numbers, as in [5, 10]".to_owned(),
+            Self::Strings => "a list of strings, as in [\"patchbay\"]".to_owned(),
+            Self::Address => "an IP address, as in \"127.0.0.1\"".to_owned(),
+            Self::Choice(choices) => {
+                let quoted = choices
+                    .iter()
+                    .map(|choice| format!("{choice:?}"))
+                    .collect::<Vec<_>>();
+                match quoted.split_last() {
+                    Some((last, [])) => last.clone(),
+                    Some((last, others)) => format!("{} or {last}", others.join(", ")),
+                    None => "nothing".to_owned(),
+                }
+            }
+            Self::Table(_) | Self::Partial(_) => "a table".to_owned(),
+        }
+    }
+
+    fn matches(self, value: &toml::Value) -> bool {
+        match self {
+            Self::Bool => value.is_bool(),
+            Self::Number => is_number(value),
+            Self::Numbers => value
+                .as_array()
+                .is_some_and(|numbers| numbers.iter().all(is_number)),
+            Self::Strings => value
+                .as_array()
+                .is_some_and(|strings| strings.iter().all(toml::Value::is_str)),
+            Self::Address => value
+                .as_str()
+                .is_some_and(|address| address.parse::<IpAddr>().is_ok()),
+            Self::Choice(choices) => value.as_str().is_some_and(|value| choices.contains(&value)),
+            Self::Table(_) | Self::Partial(_) => value.is_table(),
+        }
+    }
+}
+
+/// Line of `key` in `table`, `""` for the top level, in `contents`.
+fn line_of(contents: &str, table: &str, key: &str) -> Option<usize> {
+    let dotted = format!("{table}.{key}");
+    let mut current = "";
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[') {
+            current = header.trim_matches(['[', ']', ' ']);
+            continue;
+        }
+        let Some((name, _)) = line.split_once('=') else {
+            continue;
+        };
+        let name = name.trim().trim_matches('"');
+        if (current == table && name == key) || (current.is_empty() && name == dotted) {
+            return Some(number + 1);
+        }
+    }
+    None
+}
+
+/// Takes everything out of `table` that doesn't fit `schema`, telling what it was.
+fn validate(
+    contents: &str,
+    path: &str,
+    table: &mut toml::Table,
+    schema: &[(&str, Kind)],
+    problems: &mut Vec<Problem>,
+) {
+    let mut problem = |key: &str, message: String| {
+        problems.push(Problem {
+            line: line_of(contents, path, key),
+            message,
+        })
+    };
+    let name = |key: &str| match path {
+        "" => key.to_owned(),
+        path => format!("{path}.{key}"),
+    };
+    for key in table.keys().cloned().collect::<Vec<_>>() {
+        let Some((_, kind)) = schema.iter().find(|(known, _)| *known == key) else {
+            problem(&key, format!("unknown option {}, left out", name(&key)));
+            table.remove(&key);
+            continue;
+        };
+        if !kind.matches(&table[&key]) {
+            problem(
+                &key,
+                format!("{} should be {}, left out", name(&key), kind.describe()),
+            );
+            table.remove(&key);
+        }
+    }
+    for (key, kind) in schema {
+        let Some(toml::Value::Table(inner)) = table.get_mut(*key) else {
+            continue;
+        };
+        let (Kind::Table(inner_schema) | Kind::Partial(inner_schema)) = kind else {
+            continue;
+        };
+        let path = name(key);
+        validate(contents, &path, inner, inner_schema, problems);
+        if let Kind::Table(inner_schema) = kind {
+            let missing = inner_schema
+                .iter()
+                .filter(|(inner_key, _)| !inner.contains_key(*inner_key))
+                .map(|(inner_key, _)| *inner_key)
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                problems.push(Problem {
+                    line: line_of(contents, "", key).or_else(|| {
+                        contents
+                            .lines()
+                            .position(|line| line.trim() == format!("[{key}]"))
+                            .map(|line| line + 1)
+                    }),
+                    message: format!("{path} needs {} as well, left out", missing.join(" and ")),
+                });
+                table.remove(*key);
+            }
+        }
+    }
+}
+
+/// Version 1 has a list of `steps` in place of a single `step`.
+fn steps_list(table: &mut toml::Table) -> Result<()> {
+    if let Some(step) = table.remove("step") {
+        if !table.contains_key("steps") {
+            table.insert("steps".to_owned(), toml::Value::Array(vec![step]));
+        }
+    }
+    Ok(())
+}
+
+pub const FORMAT: Format = Format {
+    name: "config.toml",
+    steps: &[steps_list],
+};
+
+/// Options still read for now, each with what replaces it, for configurations
+/// that weren't upgraded, like bundled ones.
+fn migrate(contents: &str, table: &mut toml::Table, problems: &mut Vec<Problem>) {
+    if !table.contains_key("step") {
+        return;
+    }
+    let message = match table.contains_key("steps") {
+        true => "step is deprecated and steps is given, left out",
+        false => "step is deprecated, use steps = [...] instead",
+    };
+    problems.push(Problem {
+        line: line_of(contents, "", "step"),
+        message: message.to_owned(),
+    });
+    steps_list(table).expect("renaming never fails");
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        glib::user_config_dir()
+            .join(clap::crate_name!())
+            .join("config.toml")
+    }
+
+    /// Reads the configuration from `contents`, leaving out and telling about what's
+    /// wrong with it. Only a file that isn't TOML at all fails.
+    pub fn parse(contents: &str) -> Result<(Self, Vec<Problem>)> {
+        let mut table = toml::from_str::<toml::Table>(contents)?;
+        let mut problems = Vec::new();
+        migrate(contents, &mut table, &mut problems);
+        validate(contents, "", &mut table, SCHEMA, &mut problems);
+        problems.sort_by_key(|problem| problem.line);
+        let config = toml::Value::Table(table).try_into()?;
+        Ok((config, problems))
+    }
+
+    /// Reads the configuration, upgraded to the current version, the defaults when
+    /// there is none.
+    pub fn load() -> Result<(Self, Vec<Problem>)> {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                let contents = FORMAT.upgrade_file(&path, contents)?;
+                Self::parse(&contents).wrap_err_with(|| format!("parsing {}", path.display()))
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                Ok((Self::default(), Vec::new()))
+            }
+            Err(error) => Err(error).wrap_err_with(|| format!("reading {}", path.display())),
+        }
+    }
+}
+
+fn loaded() -> &'static (Config, Vec<Problem>) {
+    static CONFIG: OnceLock<(Config, Vec<Problem>)> = OnceLock::new();
+    CONFIG.get_or_init(|| match Config::load() {
+        Ok((config, problems)) => {
+            info!(?config, "loaded configuration");
+            for problem in &problems {
+                warn!("{}: {problem}", Config::path().display());
+            }
+            (config, problems)
+        }
+        Err(error) => {
+            warn!(?error, "using the default configuration");
+            let problem = Problem {
+                line: None,
+                message: format!("{error:#}, using the defaults"),
+            };
+            (Config::default(), vec![problem])
+        }
+    })
+}
+
+/// The configuration of this process, read once. A broken file is reported and
+/// left out, rather than keeping pipeweld from starting.
+pub fn get() -> &'static Config {
+    &loaded().0
+}
+
+/// What's wrong with the configuration [`get`] returns, to show the user.
+pub fn problems() -> &'static [Problem] {
+    &loaded().1
+}
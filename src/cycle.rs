@@ -0,0 +1,74 @@
+//! Stepping the default device through the connected ones, for a single hotkey.
+//! The popups follow the defaults, so they show where the step went.
+use eyre::Result;
+use gtk::{gio, prelude::*, Application};
+use tracing::instrument;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    device_settings::DeviceStore,
+    devices::{Device, DeviceKind},
+    AudioControls,
+};
+
+/// Application actions and command line switches, as in
+/// `gapplication action it.niedzwiedz.pipeweld cycle-sink` or `pipeweld --cycle-sink`.
+const ACTIONS: [(&str, DeviceKind); 2] = [
+    ("cycle-sink", DeviceKind::Sink),
+    ("cycle-source", DeviceKind::Source),
+];
+
+/// The device after `current` among the ones of `devices` not left out of cycling,
+/// in the order of the store's list first and the server's after that.
+pub fn next<'a>(devices: &'a [Device], current: &str, store: &DeviceStore) -> Option<&'a Device> {
+    let mut candidates = devices
+        .iter()
+        .filter(|device| {
+            device.name == current || !store.get(device).is_some_and(|settings| settings.hidden)
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|device| {
+        store
+            .cycle_order
+            .iter()
+            .position(|name| *name == device.name)
+            .unwrap_or(usize::MAX)
+    });
+    let position = candidates.iter().position(|device| device.name == current);
+    let next = position.map_or(0, |position| position + 1) % candidates.len().max(1);
+    candidates
+        .get(next)
+        .copied()
+        .filter(|device| device.name != current)
+}
+
+#[instrument(skip(store), ret, err)]
+pub fn cycle(kind: DeviceKind, store: &DeviceStore) -> Result<()> {
+    let devices = Device::list(kind)?;
+    let current = AudioControls::default_device(kind)?;
+    match next(&devices, &current, store) {
+        Some(device) => AudioControls::set_default(kind, &device.name),
+        None => Ok(()),
+    }
+}
+
+/// Exposes the actions on the application, reachable over D-Bus while pipeweld runs.
+pub fn register(app: &Application, dispatcher: Dispatcher) {
+    for (name, kind) in ACTIONS {
+        let action = gio::SimpleAction::new(name, None);
+        let dispatcher = dispatcher.clone();
+        action.connect_activate(move |_, _| dispatcher.dispatch(Action::CycleDefault(kind)));
+        app.add_action(&action);
+    }
+}
+
+/// Adds the command line switches, which hand the action to the running instance and exit.
+pub fn add_options(app: &Application) {
+    crate::add_action_switches(
+        app,
+        ACTIONS
+            .iter()
+            .map(|(name, kind)| (*name, format!("Make the next {} the default", kind.noun())))
+            .collect(),
+    );
+}
@@ -0,0 +1,102 @@
+//! Per-sink playback delay, for speakers that lag the picture less than the
+//! picture lags them. Unlike a latency offset, which only tells clients how late
+//! the sink is, this really holds the audio back, in a filter-chain `delay` node.
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::Device,
+    filter_chain::{self, Filter, Host},
+    i18n::tr,
+    state::AppState,
+};
+
+/// Longest delay offered, in milliseconds.
+pub const MAX_DELAY_MS: u32 = 2000;
+
+fn object(master: &str) -> String {
+    format!("delay:{master}")
+}
+
+fn filter(master: &Device, delay_ms: u32) -> Filter {
+    let seconds = f64::from(delay_ms) / 1000.;
+    let max_seconds = f64::from(MAX_DELAY_MS) / 1000.;
+    Filter {
+        object: object(&master.name),
+        description: format!("{} (delayed {delay_ms} ms)", master.description),
+        master: master.name.clone(),
+        graph: format!(
+            r#"{{ nodes = [ {{ type = builtin name = delay label = delay config = {{ "max-delay" = {max_seconds} }} control = {{ "Delay (s)" = {seconds} }} }} ] }}"#
+        ),
+    }
+}
+
+/// Delays everything played on `master` by `delay_ms`, or stops doing so for `None`.
+pub fn apply(host: &mut Host, master: &Device, delay_ms: Option<u32>) -> Result<()> {
+    match delay_ms.filter(|delay_ms| *delay_ms > 0) {
+        Some(delay_ms) => {
+            let filter = filter(master, delay_ms.min(MAX_DELAY_MS));
+            if host.get(&filter.object) == Some(&filter) {
+                return Ok(());
+            }
+            host.start(&filter)
+        }
+        None => {
+            host.stop(&object(&master.name));
+            Ok(())
+        }
+    }
+}
+
+/// Spin button for the delay of the real sink behind the default output.
+pub fn delay_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let panel = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    panel.append(&gtk::Label::new(Some(&tr("Lip sync delay (ms)"))));
+    let delay = gtk::SpinButton::with_range(0., MAX_DELAY_MS.into(), 10.);
+    delay.set_hexpand(true);
+    panel.append(&delay);
+    let apply = gtk::Button::with_label(&tr("Apply"));
+    panel.append(&apply);
+
+    let master = create_memo(cx, move |_| {
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    create_effect(cx, {
+        let delay = delay.clone();
+        let apply = apply.clone();
+        move |_| {
+            let master = master.get();
+            apply.set_sensitive(master.is_some());
+            let current = master.and_then(|master| {
+                state.device_settings.with(|store| {
+                    store
+                        .devices
+                        .get(&master)
+                        .and_then(|settings| settings.delay_ms)
+                })
+            });
+            delay.set_value(current.unwrap_or_default().into());
+        }
+    });
+    apply.connect_clicked(move |_| {
+        if let Some(device) = master.get_untracked() {
+            let delay_ms = delay.value() as u32;
+            dispatcher.dispatch(Action::SetDelay {
+                device,
+                delay_ms: (delay_ms > 0).then_some(delay_ms),
+            });
+        }
+    });
+    panel
+}
@@ -0,0 +1,345 @@
+//! Per-device preferences, kept in `$XDG_CONFIG_HOME/pipeweld/devices.toml`
+//! and keyed by the device's server-side name.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use eyre::{Result, WrapErr};
+use gtk::glib;
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Acceleration, Action, Dispatcher},
+    app_outputs::ForcedOutput,
+    auto_pause::OutputClass,
+    channel_mix::{self, ChannelMix},
+    delay,
+    devices::{Device, DeviceKind},
+    equalizer::Equalizer,
+    errors::OrReport,
+    headset::{Button, ButtonAction},
+    jack::JackActions,
+    midi::MidiMapping,
+    migrations::Format,
+    osd::OsdPlacement,
+    passthrough::{self, Codec},
+    power::BatterySaving,
+    presets,
+    priorities::{self, Exclusions, Priority, Suspend},
+    schedule::Schedule,
+    smart_defaults::SmartDefault,
+    startup::StartupAction,
+    state::AppState,
+    switch_on_connect::{self, SwitchOnConnect},
+    theme::ColorScheme,
+    timeline::Source,
+    upmix::Upmix,
+    virtual_devices::{self, VirtualDevice},
+    workspaces::WorkspaceProfile,
+    zones, AudioControls,
+};
+
+/// Everything pipeweld remembers about one device. Forgetting a device drops
+/// the whole entry, so new per-device state belongs in here.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    /// Highest volume, in percent, the device may be set to.
+    pub max_volume: Option<u32>,
+    /// Volume, in percent, to start at whenever the device (re)appears.
+    pub connect_volume: Option<u32>,
+    /// Remix applied on top of the device, sinks only.
+    pub channel_mix: Option<ChannelMix>,
+    /// Milliseconds to hold back playback by, sinks only.
+    pub delay_ms: Option<u32>,
+    /// Upmixing of whatever plays on the device, sinks only.
+    pub upmix: Option<Upmix>,
+    /// Compressed formats passed through as-is, sinks only.
+    pub passthrough: Option<Vec<Codec>>,
+    /// Imported EasyEffects preset played through, sinks only.
+    pub preset: Option<String>,
+    /// Presets of single ports, in place of `preset` while the port plays, like
+    /// headphones and speakers of the same card. An empty name for none at all.
+    pub port_presets: BTreeMap<String, String>,
+    /// Parametric equalizer played after the preset, sinks only. See
+    /// [`crate::equalizer`].
+    pub equalizer: Option<Equalizer>,
+    /// Gain in dB in front of the preset, apart from the volume, sinks only.
+    pub trim_db: Option<i32>,
+    /// Ceiling in dBFS of a limiter behind the preset, sinks only.
+    pub limit_db: Option<i32>,
+    /// Scheduling priorities, handed to the session manager as rules.
+    pub priority: Option<Priority>,
+    /// Skipped when cycling through the devices.
+    pub hidden: bool,
+    /// When it's suspended while nothing plays, the session manager's own timeout when
+    /// left out. Handed to the session manager along with the priorities.
+    pub suspend: Option<Suspend>,
+    /// Played at the rate of what plays, without filters, remixes or upmixing, sinks
+    /// only. See [`crate::bit_perfect`].
+    pub bit_perfect: bool,
+    /// Given its volume, port and filters back when it resets itself, see
+    /// [`crate::rules::ResetRecovery`].
+    pub recover_resets: bool,
+}
+
+impl DeviceSettings {
+    /// The preset to play through while `port` is active.
+    pub fn preset_for(&self, port: Option<&str>) -> Option<&str> {
+        match port.and_then(|port| self.port_presets.get(port)) {
+            Some(preset) => Some(preset.as_str()).filter(|preset| !preset.is_empty()),
+            None => self.preset.as_deref(),
+        }
+    }
+
+    /// The equalizer, unless it's turned off.
+    pub fn equalizer(&self) -> Option<&Equalizer> {
+        self.equalizer
+            .as_ref()
+            .filter(|equalizer| equalizer.enabled)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceStore {
+    /// Also pull back volume raised above the limit by other applications.
+    pub clamp_external: bool,
+    /// Switch cards over to HDMI when a display gets connected, and back when it's gone.
+    pub hdmi_auto_switch: bool,
+    /// Lower the latency and bypass filters while a fullscreen window has focus.
+    pub game_mode: bool,
+    pub devices: BTreeMap<String, DeviceSettings>,
+    /// Linked volume groups, by name, listing their sinks.
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// Multi-room zones, by name, listing their sinks.
+    pub zones: BTreeMap<String, Vec<String>>,
+    /// Null sinks, loopbacks and combined outputs, by name, see [`crate::virtual_devices`].
+    pub virtual_devices: BTreeMap<String, VirtualDevice>,
+    /// Kinds of devices kept from becoming the default automatically.
+    pub exclusions: Exclusions,
+    /// Names of devices in the order cycling goes through them, ahead of unlisted ones.
+    pub cycle_order: Vec<String>,
+    /// Default devices per workspace name, or per display for `output:<display>`.
+    pub workspaces: BTreeMap<String, WorkspaceProfile>,
+    /// Default devices learned per set of connected hardware, see
+    /// [`crate::smart_defaults`].
+    pub smart_defaults: Vec<SmartDefault>,
+    /// Run whenever pipeweld starts, in order.
+    pub startup: Vec<StartupAction>,
+    /// Take what plays along when picking another output in the window.
+    pub move_streams: bool,
+    /// What each headset button does, unmapped ones are left to media players.
+    pub headset_buttons: BTreeMap<Button, ButtonAction>,
+    /// Where the volume popup goes.
+    pub osd_placement: OsdPlacement,
+    /// Sources whose changes the volume popup doesn't show, like rules ducking
+    /// what plays.
+    pub osd_hidden_for: BTreeSet<Source>,
+    /// How volume steps grow when repeated quickly.
+    pub acceleration: Acceleration,
+    /// Meter the default output and warn when it clips, see [`crate::levels`].
+    pub clipping_warning: bool,
+    /// Kinds of outputs whose going away pauses the media players.
+    pub pause_on_disconnect: BTreeSet<OutputClass>,
+    /// Seconds an output that went away stays listed and gets restored if it comes
+    /// back, see [`crate::grace`].
+    pub grace_period_secs: Option<u64>,
+    /// Closing the window hides it, pipeweld stays in the tray.
+    pub close_to_tray: bool,
+    /// What plugging headphones into a jack does.
+    pub headphone_jack: JackActions,
+    /// Whether devices connecting become the default and streams follow, see
+    /// [`crate::switch_on_connect`].
+    pub switch_on_connect: SwitchOnConnect,
+    /// Routing presets applied at times of the week, see [`crate::schedule`].
+    pub schedules: Vec<Schedule>,
+    /// What changes while on battery, see [`crate::power`].
+    pub battery_saving: BatterySaving,
+    /// Take the media keys through the desktop portal, see [`crate::shortcuts`].
+    pub global_shortcuts: bool,
+    /// Volumes, in percent, that streams of these applications are held at, by
+    /// `application.name`.
+    pub locked_volumes: BTreeMap<String, u32>,
+    /// Shares, in percent of the output's volume, that streams of these applications
+    /// play at, by `application.name`. See [`crate::relative_volume`].
+    pub relative_volumes: BTreeMap<String, u32>,
+    /// Outputs and rates streams of these applications are held to, by
+    /// `application.name`. See [`crate::app_outputs`].
+    pub forced_outputs: BTreeMap<String, ForcedOutput>,
+    /// Sequencer port of the MIDI control surface, `client:port`, see [`crate::midi`].
+    pub midi_port: Option<String>,
+    /// What its controls are mapped to, a control each.
+    pub midi_mappings: Vec<MidiMapping>,
+    /// Light or dark regardless of the desktop, see [`crate::theme`].
+    pub color_scheme: ColorScheme,
+}
+
+/// Version 1 tells when a device suspends, `never_suspend = true` becoming
+/// `suspend = "never"`.
+fn suspend_policy(table: &mut toml::Table) -> Result<()> {
+    let Some(devices) = table.get_mut("devices").and_then(toml::Value::as_table_mut) else {
+        return Ok(());
+    };
+    for settings in devices
+        .iter_mut()
+        .filter_map(|(_, settings)| settings.as_table_mut())
+    {
+        if settings
+            .remove("never_suspend")
+            .and_then(|never| never.as_bool())
+            == Some(true)
+        {
+            settings.insert("suspend".to_owned(), "never".into());
+        }
+    }
+    Ok(())
+}
+
+/// Fields that came along later default when left out, those that changed are upgraded.
+pub const FORMAT: Format = Format {
+    name: "devices.toml",
+    steps: &[suspend_policy],
+};
+
+impl DeviceStore {
+    pub fn path() -> PathBuf {
+        glib::user_config_dir()
+            .join(clap::crate_name!())
+            .join("devices.toml")
+    }
+
+    /// Reads the store, upgraded to the current version, starting out empty when
+    /// there is none yet.
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                let contents = FORMAT.upgrade_file(&path, contents)?;
+                toml::from_str(&contents).wrap_err_with(|| format!("parsing {}", path.display()))
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error).wrap_err_with(|| format!("reading {}", path.display())),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+        }
+        let contents = FORMAT.serialize(self)?;
+        std::fs::write(&path, contents).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+
+    pub fn get(&self, device: &Device) -> Option<&DeviceSettings> {
+        self.devices.get(&device.name)
+    }
+
+    /// Removes all settings of the device called `name`.
+    pub fn forget(&mut self, name: &str) {
+        self.devices.remove(name);
+    }
+
+    /// The settings of `name`, created empty when there are none yet.
+    pub fn entry(&mut self, name: &str) -> &mut DeviceSettings {
+        self.devices.entry(name.to_owned()).or_default()
+    }
+
+    pub fn max_volume(&self, device: &Device) -> Option<u32> {
+        self.get(device).and_then(|settings| settings.max_volume)
+    }
+
+    /// `percent`, as far as the limit the user set for `device` lets it go. Setting
+    /// a volume comes down to [`Action::SetVolume`] or the command line, both of
+    /// which go through here.
+    pub fn limit_volume(&self, device: &Device, percent: u32) -> u32 {
+        self.max_volume(device)
+            .map_or(percent, |max| percent.min(max))
+    }
+}
+
+/// Pulls sinks back under their limits whenever something else pushed them over.
+pub fn enforce_limits(cx: Scope, state: AppState, dispatcher: Dispatcher) {
+    create_effect(cx, move |_| {
+        let over = state.device_settings.with(|store| {
+            if !store.clamp_external {
+                return Vec::new();
+            }
+            state.sinks.with(|sinks| {
+                sinks
+                    .iter()
+                    .filter_map(|sink| {
+                        let max_volume = store.max_volume(sink)?;
+                        (sink.volume_percent() > max_volume)
+                            .then(|| (sink.name.clone(), max_volume))
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+        for (sink, max_volume) in over {
+            info!(max_volume, "{sink} went over its volume limit");
+            dispatcher.dispatch(Action::SetVolume(DeviceKind::Sink, sink, max_volume));
+        }
+    });
+}
+
+/// Rebuilds what pipeweld puts on top of every connected sink. None of it outlives
+/// the server, or pipeweld itself for filters.
+pub fn restore(state: AppState) {
+    let sinks = match Device::list(DeviceKind::Sink) {
+        Ok(sinks) => sinks,
+        Err(error) => return warn!(?error, "restoring device settings"),
+    };
+    state.device_settings.with_untracked(|store| {
+        priorities::sync_rules(store);
+        zones::restore(&store.zones);
+        virtual_devices::restore(&store.virtual_devices);
+        switch_on_connect::apply(store.switch_on_connect)
+            .or_report(state, "restoring switching on connect");
+        for sink in &sinks {
+            let Some(settings) = store.get(sink) else {
+                continue;
+            };
+            info!(?settings, "restoring {}", sink.name);
+            if let Some(mix) = settings.channel_mix {
+                channel_mix::apply(sink, Some(mix)).or_report(state, "restoring the channel mix");
+            }
+            if let Some(codecs) = &settings.passthrough {
+                AudioControls::set_sink_formats(&sink.name, &passthrough::formats(codecs))
+                    .or_report(state, "restoring passthrough");
+            }
+            if let Some(delay_ms) = settings.delay_ms {
+                state.filters.with_value(|host| {
+                    let host = &mut *host.lock();
+                    delay::apply(host, sink, Some(delay_ms))
+                        .or_report(state, "restoring the delay");
+                });
+            }
+            let preset = settings.preset_for(sink.active_port.as_deref());
+            let equalizer = settings.equalizer();
+            if preset.is_some()
+                || equalizer.is_some()
+                || settings.trim_db.is_some()
+                || settings.limit_db.is_some()
+            {
+                state.filters.with_value(|host| {
+                    let host = &mut *host.lock();
+                    presets::apply(
+                        host,
+                        sink,
+                        preset,
+                        equalizer,
+                        settings.trim_db,
+                        settings.limit_db,
+                    )
+                    .or_report(state, "restoring the effects preset");
+                });
+            }
+        }
+    });
+}
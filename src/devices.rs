@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, HashMap};
+
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    easyeffects,
+    extensions::watch,
+    i18n::tr,
+    state::AppState,
+    streams::SampleSpec,
+    tool_output, AudioControls,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Sink,
+    Source,
+}
+
+impl DeviceKind {
+    /// Noun used by `pactl` subcommands, as in `set-sink-mute`.
+    pub fn noun(self) -> &'static str {
+        match self {
+            Self::Sink => "sink",
+            Self::Source => "source",
+        }
+    }
+
+    /// Special name `pactl` resolves to the current default device.
+    pub fn default_alias(self) -> &'static str {
+        match self {
+            Self::Sink => "@DEFAULT_SINK@",
+            Self::Source => "@DEFAULT_SOURCE@",
+        }
+    }
+}
+
+/// Volume pactl considers 100%.
+pub const VOLUME_NORM: u32 = 0x10000;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ChannelVolume {
+    pub value: u32,
+}
+
+/// A jack or connector of a device, e.g. speakers or headphones.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Port {
+    pub name: String,
+    pub description: String,
+    /// `available`, `not available` or `availability unknown`.
+    #[serde(default)]
+    pub availability: String,
+}
+
+impl Port {
+    pub fn is_unavailable(&self) -> bool {
+        self.availability == "not available"
+    }
+}
+
+/// A sink or source as reported by `pactl --format=json list`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Device {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub mute: bool,
+    #[serde(default)]
+    pub volume: BTreeMap<String, ChannelVolume>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+    /// Formats a sink accepts, as printed by `pactl`.
+    #[serde(default)]
+    pub formats: Vec<String>,
+    /// `RUNNING`, `IDLE` or `SUSPENDED`.
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub ports: Vec<Port>,
+    pub active_port: Option<String>,
+    /// As printed by `pactl`, like `s32le 2ch 48000Hz`.
+    #[serde(default)]
+    pub sample_specification: String,
+    /// Names of the channels in the order volumes are set in, like
+    /// `front-left,front-right`.
+    #[serde(default)]
+    pub channel_map: String,
+}
+
+impl Device {
+    pub fn list(kind: DeviceKind) -> Result<Vec<Self>> {
+        let output = AudioControls::pactl(["--format=json", "list", &format!("{}s", kind.noun())])?;
+        tool_output::json_list::<Self>(&output, &format!("{}s", kind.noun())).map(|devices| {
+            devices
+                .into_iter()
+                // monitors of sinks show up as sources, but nobody wants to pick them as a mic
+                .filter(|device| {
+                    device.properties.get("device.class").map(String::as_str) != Some("monitor")
+                })
+                .collect()
+        })
+    }
+
+    /// The current default device of `kind`, if there is one.
+    pub fn default_of(kind: DeviceKind) -> Result<Option<Self>> {
+        let name = AudioControls::default_device(kind)?;
+        Self::list(kind).map(|devices| devices.into_iter().find(|device| device.name == name))
+    }
+
+    pub fn sample_spec(&self) -> Option<SampleSpec> {
+        self.sample_specification.parse().ok()
+    }
+
+    /// Each channel with its volume in percent, in the order of the channel map.
+    pub fn channels(&self) -> Vec<(String, u32)> {
+        let percent = |channel: &ChannelVolume| {
+            ((u64::from(channel.value) * 100 + u64::from(VOLUME_NORM) / 2) / u64::from(VOLUME_NORM))
+                as u32
+        };
+        let mapped = self
+            .channel_map
+            .split(',')
+            .filter_map(|name| Some((name.to_owned(), percent(self.volume.get(name)?))))
+            .collect::<Vec<_>>();
+        match mapped.len() == self.volume.len() {
+            true => mapped,
+            // without a map to go by, as from wpctl
+            false => self
+                .volume
+                .iter()
+                .map(|(name, channel)| (name.clone(), percent(channel)))
+                .collect(),
+        }
+    }
+
+    /// Volume of the loudest channel, in percent.
+    pub fn volume_percent(&self) -> u32 {
+        let loudest = self
+            .volume
+            .values()
+            .map(|channel| channel.value)
+            .max()
+            .unwrap_or(0);
+        ((u64::from(loudest) * 100 + u64::from(VOLUME_NORM) / 2) / u64::from(VOLUME_NORM)) as u32
+    }
+}
+
+/// Drop-down switching the default output, with a switch for taking what plays along.
+pub fn output_picker(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Horizontal, 6);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    layout.set_margin_bottom(12);
+    let picker = sink_picker(cx, state, dispatcher.clone());
+    picker.set_hexpand(true);
+    layout.append(&picker);
+    let move_streams = gtk::CheckButton::with_label(&tr("Move what plays"));
+    move_streams.set_active(
+        state
+            .device_settings
+            .with_untracked(|store| store.move_streams),
+    );
+    move_streams.connect_toggled({
+        let dispatcher = dispatcher.clone();
+        move |check| {
+            if check.is_active()
+                != state
+                    .device_settings
+                    .with_untracked(|store| store.move_streams)
+            {
+                dispatcher.dispatch(Action::ToggleMoveStreams);
+            }
+        }
+    });
+    layout.append(&move_streams);
+    layout
+}
+
+/// Keeps `picker` listing `items`, read from the state like [`AppState::sinks`], as
+/// they come, go or get renamed, by the name and label `entry` gives each. The one
+/// picked stays picked meanwhile, `prefer` picks one when none was, or it's gone.
+/// Returns the items in the order of the picker, for telling which one is picked.
+pub fn keep_listed<T: Clone + PartialEq + 'static>(
+    cx: Scope,
+    picker: &gtk::DropDown,
+    items: impl Fn() -> Vec<T> + 'static,
+    entry: impl Fn(&T) -> (String, String) + 'static,
+    prefer: impl Fn(&[T]) -> Option<usize> + 'static,
+) -> Memo<Vec<T>> {
+    let items = create_memo(cx, move |_| items());
+    // volumes change all the time, the list only rebuilds for its entries
+    let entries = create_memo(cx, move |_| {
+        items.with(|items| items.iter().map(&entry).collect::<Vec<_>>())
+    });
+    let shown = store_value(cx, Vec::<String>::new());
+    watch(cx, picker, move |picker| {
+        entries.with(|entries| {
+            let picked = shown.with_value(|names| names.get(picker.selected() as usize).cloned());
+            let position = picked
+                .and_then(|picked| entries.iter().position(|(name, _)| *name == picked))
+                .or_else(|| items.with_untracked(|items| prefer(items)))
+                .unwrap_or(0);
+            shown.set_value(entries.iter().map(|(name, _)| name.clone()).collect());
+            let labels = entries
+                .iter()
+                .map(|(_, label)| label.as_str())
+                .collect::<Vec<_>>();
+            picker.set_model(Some(&gtk::StringList::new(&labels)));
+            picker.set_selected(position as u32);
+            // a new model doesn't notify when the same position stays selected
+            picker.notify("selected");
+        })
+    });
+    items
+}
+
+/// Drop-down following the default output and switching it, taking what plays along
+/// if the user asked to.
+pub fn sink_picker(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::DropDown {
+    let picker = gtk::DropDown::from_strings(&[]);
+    picker.set_tooltip_text(Some(&tr("Output")));
+    // volumes change all the time, the list only rebuilds for the sinks themselves
+    let sinks = create_memo(cx, move |_| {
+        state.sinks.with(|sinks| {
+            sinks
+                .iter()
+                .map(|sink| (sink.name.clone(), easyeffects::label(sink)))
+                .collect::<Vec<_>>()
+        })
+    });
+    let default_name = move || {
+        state
+            .default_sink
+            .with(|sink| sink.as_ref().map(|sink| sink.name.clone()))
+    };
+    watch(cx, &picker, move |picker| {
+        let default = default_name();
+        sinks.with(|sinks| {
+            let labels = sinks
+                .iter()
+                .map(|(_, label)| label.as_str())
+                .collect::<Vec<_>>();
+            picker.set_model(Some(&gtk::StringList::new(&labels)));
+            picker.set_selected(
+                sinks
+                    .iter()
+                    .position(|(name, _)| Some(name) == default.as_ref())
+                    .map_or(gtk::INVALID_LIST_POSITION, |position| position as u32),
+            );
+        });
+    });
+    picker.connect_selected_notify(move |picker| {
+        let Some(name) = sinks.with_untracked(|sinks| {
+            sinks
+                .get(picker.selected() as usize)
+                .map(|(name, _)| name.clone())
+        }) else {
+            return;
+        };
+        // following the default selects it too
+        if state
+            .default_sink
+            .with_untracked(|sink| sink.as_ref().is_some_and(|sink| sink.name == name))
+        {
+            return;
+        }
+        dispatcher.dispatch(Action::SetDefault(DeviceKind::Sink, name.clone()));
+        if state
+            .device_settings
+            .with_untracked(|store| store.move_streams)
+        {
+            dispatcher.dispatch(Action::MoveStreams(name));
+        }
+    });
+    picker
+}
@@ -0,0 +1,225 @@
+//! Checks behind the usual "crackling audio" reports. Realtime scheduling comes
+//! either from RealtimeKit or from resource limits, and PipeWire's data thread
+//! only gets it if one of them allows it.
+use std::path::{Path, PathBuf};
+
+use gtk::{gio, glib::Variant, prelude::*, Orientation};
+use tracing::warn;
+
+use crate::i18n::tr;
+
+/// Scheduling policies from `sched.h`.
+const SCHED_FIFO: u32 = 1;
+const SCHED_RR: u32 = 2;
+
+/// What RealtimeKit is willing to hand out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtKit {
+    pub max_priority: i32,
+    pub max_rttime_usec: i64,
+}
+
+impl RtKit {
+    fn query() -> Option<Self> {
+        let connection = gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE)
+            .map_err(|error| warn!(%error, "connecting to the system bus"))
+            .ok()?;
+        let property = |name: &str| -> Option<Variant> {
+            connection
+                .call_sync(
+                    Some("org.freedesktop.RealtimeKit1"),
+                    "/org/freedesktop/RealtimeKit1",
+                    "org.freedesktop.DBus.Properties",
+                    "Get",
+                    Some(&("org.freedesktop.RealtimeKit1", name).to_variant()),
+                    None,
+                    gio::DBusCallFlags::NONE,
+                    1000,
+                    gio::Cancellable::NONE,
+                )
+                .ok()?
+                .child_value(0)
+                .as_variant()
+        };
+        Some(Self {
+            max_priority: property("MaxRealtimePriority")?.get()?,
+            max_rttime_usec: property("RTTimeUSecMax")?.get()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtStatus {
+    /// `None` when RealtimeKit isn't running.
+    pub rtkit: Option<RtKit>,
+    /// Soft `RLIMIT_RTPRIO` of this session, 0 when realtime isn't allowed directly.
+    pub rtprio_limit: Option<u64>,
+    /// Whether one of PipeWire's threads runs with a realtime policy, `None` if
+    /// no PipeWire process could be found.
+    pub pipewire_realtime: Option<bool>,
+}
+
+/// Soft limit `name` from `/proc/self/limits`, `None` for unlimited or missing.
+fn soft_limit(limits: &str, name: &str) -> Option<u64> {
+    limits
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|soft| soft.parse().ok())
+}
+
+/// Scheduling policy of a thread, field 41 of its `stat`.
+fn policy(stat: &Path) -> Option<u32> {
+    let stat = std::fs::read_to_string(stat).ok()?;
+    // the command name can contain anything, the fields after it are fixed
+    let fields = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    fields.get(41 - 3)?.parse().ok()
+}
+
+/// `/proc` directory of a process running `command`.
+fn process(command: &str) -> Option<PathBuf> {
+    let processes = std::fs::read_dir("/proc").ok()?;
+    processes
+        .flatten()
+        .find(|process| {
+            std::fs::read_to_string(process.path().join("comm"))
+                .is_ok_and(|comm| comm.trim() == command)
+        })
+        .map(|process| process.path())
+}
+
+/// Whether a process running `command` is around, like `wireplumber`.
+pub fn is_running(command: &str) -> bool {
+    process(command).is_some()
+}
+
+fn pipewire_realtime() -> Option<bool> {
+    let pipewire = process("pipewire")?;
+    let threads = std::fs::read_dir(pipewire.join("task")).ok()?;
+    Some(threads.flatten().any(|thread| {
+        matches!(
+            policy(&thread.path().join("stat")),
+            Some(SCHED_FIFO | SCHED_RR)
+        )
+    }))
+}
+
+impl RtStatus {
+    pub fn check() -> Self {
+        let limits = std::fs::read_to_string("/proc/self/limits").unwrap_or_default();
+        Self {
+            rtkit: RtKit::query(),
+            rtprio_limit: soft_limit(&limits, "Max realtime priority"),
+            pipewire_realtime: pipewire_realtime(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.pipewire_realtime == Some(true)
+    }
+
+    /// One line per finding.
+    pub fn findings(&self) -> Vec<String> {
+        let mut findings = Vec::new();
+        findings.push(match self.pipewire_realtime {
+            Some(true) => "PipeWire is running with realtime scheduling.".to_owned(),
+            Some(false) => "PipeWire is running without realtime scheduling.".to_owned(),
+            None => "No running PipeWire process was found.".to_owned(),
+        });
+        findings.push(match self.rtkit {
+            Some(rtkit) => format!(
+                "RealtimeKit is available, up to priority {}.",
+                rtkit.max_priority
+            ),
+            None => "RealtimeKit is not available.".to_owned(),
+        });
+        findings.push(match self.rtprio_limit {
+            Some(0) | None => "This session may not use realtime priorities by itself.".to_owned(),
+            Some(limit) => format!("This session may use realtime priorities up to {limit}."),
+        });
+        findings
+    }
+
+    /// What to do about it, empty when everything is in order.
+    pub fn remedies(&self) -> Vec<&'static str> {
+        if self.is_ok() {
+            return Vec::new();
+        }
+        let mut remedies = Vec::new();
+        if self.rtkit.is_none() && matches!(self.rtprio_limit, Some(0) | None) {
+            remedies
+                .push("Install RealtimeKit (the rtkit package) and make sure rtkit-daemon starts.");
+            remedies.push(
+                "Or add yourself to the pipewire group (sudo usermod -aG pipewire $USER), which \
+                 distributions grant realtime limits in /etc/security/limits.d, then log in again.",
+            );
+        }
+        if self.pipewire_realtime == Some(false) {
+            remedies.push(
+                "Check that libpipewire-module-rt is loaded in pipewire.conf, then restart \
+                 PipeWire: systemctl --user restart pipewire pipewire-pulse.",
+            );
+        }
+        if self.pipewire_realtime.is_none() {
+            remedies.push("Start PipeWire: systemctl --user start pipewire pipewire-pulse.");
+        }
+        remedies
+    }
+}
+
+fn fill(card: &gtk::Box, status: &RtStatus) {
+    while let Some(child) = card.first_child() {
+        card.remove(&child);
+    }
+    let headline = gtk::Label::new(Some(match status.is_ok() {
+        true => "Realtime scheduling is working",
+        false => "Realtime scheduling is not working, expect crackling under load",
+    }));
+    headline.add_css_class(if status.is_ok() { "success" } else { "warning" });
+    headline.set_halign(gtk::Align::Start);
+    card.append(&headline);
+    for line in status.findings().into_iter().chain(
+        status
+            .remedies()
+            .into_iter()
+            .map(|remedy| format!("• {remedy}")),
+    ) {
+        let label = gtk::Label::new(Some(&line));
+        label.set_wrap(true);
+        label.set_max_width_chars(60);
+        label.set_halign(gtk::Align::Start);
+        label.set_selectable(true);
+        card.append(&label);
+    }
+}
+
+/// Expander with the current status and how to fix it, checked on opening.
+pub fn rt_panel() -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let card = gtk::Box::new(Orientation::Vertical, 4);
+    layout.append(&card);
+    let recheck = gtk::Button::with_label(&tr("Check again"));
+    recheck.set_halign(gtk::Align::Start);
+    layout.append(&recheck);
+    recheck.connect_clicked({
+        let card = card.clone();
+        move |_| fill(&card, &RtStatus::check())
+    });
+    let expander = gtk::Expander::builder()
+        .label("Realtime scheduling")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    expander.connect_expanded_notify(move |expander| {
+        if expander.is_expanded() {
+            fill(&card, &RtStatus::check());
+        }
+    });
+    expander
+}
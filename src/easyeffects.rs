@@ -0,0 +1,71 @@
+//! Living next to EasyEffects. While it runs it adds a sink and a source of its own,
+//! processes whatever is played to them and passes it on to a real device. Effects
+//! of pipeweld's on top of that would process everything twice.
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    state::AppState,
+};
+
+pub const SINK: &str = "easyeffects_sink";
+pub const SOURCE: &str = "easyeffects_source";
+
+/// Whether EasyEffects' sink is around.
+pub fn is_running() -> Result<bool> {
+    Ok(Device::list(DeviceKind::Sink)?
+        .iter()
+        .any(|sink| sink.name == SINK))
+}
+
+/// Whether `device` is one of EasyEffects' virtual devices.
+pub fn is_virtual(device: &Device) -> bool {
+    device.name == SINK || device.name == SOURCE
+}
+
+/// What to call `device` in menus, spelling out what EasyEffects' devices do.
+pub fn label(device: &Device) -> String {
+    match device.name.as_str() {
+        SINK => "EasyEffects (output effects)".to_owned(),
+        SOURCE => "EasyEffects (microphone effects)".to_owned(),
+        _ => device.description.clone(),
+    }
+}
+
+/// Offers to play through EasyEffects while it runs and the default output bypasses it.
+pub fn route_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let panel = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let label = gtk::Label::new(Some(&tr(
+        "EasyEffects is running, but the output bypasses it",
+    )));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    label.set_wrap(true);
+    panel.append(&label);
+    let route = gtk::Button::with_label(&tr("Play through EasyEffects"));
+    route.connect_clicked(move |_| {
+        dispatcher.dispatch(Action::SetDefault(DeviceKind::Sink, SINK.to_owned()))
+    });
+    panel.append(&route);
+
+    create_effect(cx, {
+        let panel = panel.clone();
+        move |_| {
+            let bypassed = state
+                .default_sink
+                .with(|sink| sink.as_ref().is_some_and(|sink| sink.name != SINK));
+            panel.set_visible(state.easyeffects.get() && bypassed);
+        }
+    });
+    panel
+}
@@ -0,0 +1,486 @@
+//! A parametric equalizer for the real sink behind the default output, played as
+//! biquads of the filter chain along with its effects preset, see [`crate::presets`].
+//! Bands are adjusted with sliders, or imported from the text files Equalizer APO
+//! reads, which AutoEq publishes its headphone corrections as and EasyEffects
+//! imports too:
+//!
+//! ```text
+//! Preamp: -6.2 dB
+//! Filter 1: ON LSC Fc 105 Hz Gain 5.5 dB Q 0.71
+//! Filter 2: ON PK Fc 1740 Hz Gain -2.1 dB Q 1.41
+//! ```
+//!
+//! Each output keeps the equalizer it was left with, turned off included.
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    filter_chain,
+    i18n::{marked, tr, tr_with},
+    presets::{Band, Effect},
+    registry::{Context, Panel},
+    state::AppState,
+};
+
+/// Lowest and highest gain the sliders offer, in dB.
+const GAIN_RANGE_DB: (f64, f64) = (-18., 18.);
+/// How long sliders rest before the filter chain is started over with them.
+const SETTLE: Duration = Duration::from_millis(400);
+/// Centers of the bands of a flat equalizer, an octave apart.
+const OCTAVES: [f64; 10] = [
+    31., 62., 125., 250., 500., 1000., 2000., 4000., 8000., 16000.,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BandKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+    Notch,
+    AllPass,
+}
+
+impl BandKind {
+    /// The filter chain's builtin biquad.
+    fn biquad(self) -> &'static str {
+        match self {
+            Self::Peaking => "bq_peaking",
+            Self::LowShelf => "bq_lowshelf",
+            Self::HighShelf => "bq_highshelf",
+            Self::LowPass => "bq_lowpass",
+            Self::HighPass => "bq_highpass",
+            Self::Notch => "bq_notch",
+            Self::AllPass => "bq_allpass",
+        }
+    }
+
+    /// Equalizer APO's filter types, `LSC` as well as `LS` and so on.
+    fn from_apo(code: &str) -> Option<Self> {
+        Some(match code {
+            "PK" | "PEQ" | "Modal" => Self::Peaking,
+            "LS" | "LSC" | "LSQ" => Self::LowShelf,
+            "HS" | "HSC" | "HSQ" => Self::HighShelf,
+            "LP" | "LPQ" => Self::LowPass,
+            "HP" | "HPQ" => Self::HighPass,
+            "NO" => Self::Notch,
+            "AP" => Self::AllPass,
+            _ => return None,
+        })
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Peaking => "",
+            Self::LowShelf => marked("low shelf"),
+            Self::HighShelf => marked("high shelf"),
+            Self::LowPass => marked("low pass"),
+            Self::HighPass => marked("high pass"),
+            Self::Notch => marked("notch"),
+            Self::AllPass => marked("all pass"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EqBand {
+    pub kind: BandKind,
+    /// Center, or corner, in Hz.
+    pub frequency: f64,
+    pub gain_db: f64,
+    pub q: f64,
+}
+
+impl EqBand {
+    /// Like "105 Hz" or "1.7 kHz, low shelf".
+    fn describe(&self) -> String {
+        let frequency = match self.frequency >= 1000. {
+            true => tr_with(
+                "{frequency} kHz",
+                &[(
+                    "frequency",
+                    &((self.frequency / 100.).round() / 10.).to_string(),
+                )],
+            ),
+            false => tr_with(
+                "{frequency} Hz",
+                &[("frequency", &self.frequency.round().to_string())],
+            ),
+        };
+        match self.kind.label() {
+            "" => frequency,
+            kind => tr_with(
+                "{frequency}, {kind}",
+                &[("frequency", &frequency), ("kind", &tr(kind))],
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Equalizer {
+    /// Off keeps the bands for turning it back on.
+    pub enabled: bool,
+    /// Where the bands came from, like the file they were imported from.
+    pub name: Option<String>,
+    /// Gain in front of the bands, in dB, negative to leave room for their boosts.
+    pub preamp_db: f64,
+    pub bands: Vec<EqBand>,
+}
+
+impl Equalizer {
+    /// Ten bands an octave apart, at 0 dB.
+    pub fn flat() -> Self {
+        Self {
+            enabled: true,
+            name: None,
+            preamp_db: 0.,
+            bands: OCTAVES
+                .iter()
+                .map(|frequency| EqBand {
+                    kind: BandKind::Peaking,
+                    frequency: *frequency,
+                    gain_db: 0.,
+                    q: 1.41,
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads an Equalizer APO configuration, skipping the filters turned off and what
+    /// the filter chain can't play, like graphic EQ lines.
+    pub fn parse_apo(name: &str, text: &str) -> Result<Self> {
+        let mut equalizer = Self {
+            enabled: true,
+            name: Some(name.to_owned()),
+            preamp_db: 0.,
+            bands: Vec::new(),
+        };
+        for line in text.lines().map(str::trim) {
+            let Some((key, rest)) = line.split_once(':') else {
+                continue;
+            };
+            let words = rest.split_whitespace().collect::<Vec<_>>();
+            if key.trim() == "Preamp" {
+                equalizer.preamp_db += words
+                    .first()
+                    .and_then(|gain| gain.parse::<f64>().ok())
+                    .ok_or_else(|| eyre!("unexpected preamp {line:?}"))?;
+                continue;
+            }
+            // "Filter 1: ON PK Fc 105 Hz Gain -2.6 dB Q 0.70", the number left out too
+            if !key.starts_with("Filter") || words.first() != Some(&"ON") {
+                continue;
+            }
+            let Some(kind) = words.get(1).and_then(|code| BandKind::from_apo(code)) else {
+                continue;
+            };
+            let value = |name: &str| {
+                words
+                    .iter()
+                    .position(|word| *word == name)
+                    .and_then(|position| words.get(position + 1)?.parse::<f64>().ok())
+            };
+            let Some(frequency) = value("Fc") else {
+                bail!("{line:?} has no frequency");
+            };
+            equalizer.bands.push(EqBand {
+                kind,
+                frequency,
+                gain_db: value("Gain").unwrap_or(0.),
+                // shelves given by their slope alone are taken at the usual one
+                q: value("Q").unwrap_or(0.71),
+            });
+        }
+        if equalizer.bands.is_empty() {
+            bail!("{name} has no filters pipeweld can play");
+        }
+        Ok(equalizer)
+    }
+
+    /// What the filter chain plays.
+    pub fn effect(&self) -> Effect {
+        Effect::Equalizer {
+            gain: self.preamp_db,
+            bands: self
+                .bands
+                .iter()
+                .map(|band| Band {
+                    kind: band.kind.biquad(),
+                    frequency: band.frequency,
+                    gain: band.gain_db,
+                    q: band.q,
+                })
+                .collect(),
+        }
+    }
+
+    /// Like "Sennheiser HD 600" or "equalizer".
+    pub fn describe(&self) -> String {
+        self.name.clone().unwrap_or_else(|| "equalizer".to_owned())
+    }
+}
+
+/// Reads the Equalizer APO configuration at `path`.
+#[instrument(err)]
+pub fn import(path: &std::path::Path) -> Result<Equalizer> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| eyre!("{} has no usable name", path.display()))?;
+    // AutoEq names its files like "Sennheiser HD 600 ParametricEQ.txt"
+    let name = name.trim_end_matches("ParametricEQ").trim();
+    let text =
+        std::fs::read_to_string(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+    Equalizer::parse_apo(name, &text)
+}
+
+fn slider() -> gtk::Scale {
+    let (lowest, highest) = GAIN_RANGE_DB;
+    let scale = gtk::Scale::with_range(Orientation::Horizontal, lowest, highest, 0.1);
+    scale.set_hexpand(true);
+    scale.set_digits(1);
+    scale.set_draw_value(true);
+    scale.set_value_pos(gtk::PositionType::Right);
+    scale.add_mark(0., gtk::PositionType::Bottom, None);
+    scale
+}
+
+/// The equalizer as one of the window's panels.
+pub struct EqualizerPanel;
+
+impl Panel for EqualizerPanel {
+    fn title(&self) -> &'static str {
+        "Equalizer"
+    }
+
+    fn build(&self, context: &Context) -> gtk::Widget {
+        equalizer_panel(context.cx, context.state, context.dispatcher.clone()).upcast()
+    }
+}
+
+/// Expander with the equalizer of the real sink behind the default output: a switch,
+/// a slider for the preamp and each band, and buttons flattening it and importing
+/// an AutoEq correction.
+pub fn equalizer_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let controls = gtk::Box::new(Orientation::Horizontal, 6);
+    let enabled = gtk::Switch::new();
+    enabled.set_valign(gtk::Align::Center);
+    enabled.set_tooltip_text(Some(&tr("Play the output through the equalizer")));
+    controls.append(&enabled);
+    let name = gtk::Label::new(None);
+    name.set_hexpand(true);
+    name.set_halign(gtk::Align::Start);
+    name.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    name.add_css_class("dim-label");
+    controls.append(&name);
+    let flat = gtk::Button::with_label(&tr("Flat"));
+    flat.set_tooltip_text(Some(&tr("Ten bands an octave apart, all at 0 dB")));
+    controls.append(&flat);
+    let import = gtk::Button::with_label(&tr("Import AutoEq preset…"));
+    controls.append(&import);
+    layout.append(&controls);
+    let preamp_row = gtk::Box::new(Orientation::Horizontal, 6);
+    let preamp_label = gtk::Label::new(Some(&tr("Preamp")));
+    preamp_label.set_width_chars(16);
+    preamp_label.set_xalign(0.);
+    preamp_row.append(&preamp_label);
+    let preamp = slider();
+    preamp_row.append(&preamp);
+    layout.append(&preamp_row);
+    let bands = gtk::Box::new(Orientation::Vertical, 2);
+    layout.append(&bands);
+
+    let master = create_memo(cx, move |_| {
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    let saved = create_memo(cx, move |_| {
+        let master = master.get()?;
+        state.device_settings.with(|store| {
+            store
+                .devices
+                .get(&master)
+                .and_then(|settings| settings.equalizer.clone())
+        })
+    });
+    // what the sliders show, saved once they rest
+    let shown = Rc::new(RefCell::new(Equalizer::flat()));
+    let pending = Rc::new(RefCell::new(None::<glib::SourceId>));
+    let save = {
+        let shown = shown.clone();
+        let dispatcher = dispatcher.clone();
+        move |equalizer: Equalizer| {
+            let Some(device) = master.get_untracked() else {
+                return;
+            };
+            shown.replace(equalizer.clone());
+            dispatcher.dispatch(Action::SetEqualizer {
+                device,
+                equalizer: Some(equalizer),
+            });
+        }
+    };
+    // sliders save after resting, so dragging doesn't restart the filter chain
+    let settle = {
+        let shown = shown.clone();
+        let save = save.clone();
+        move || {
+            if let Some(previous) = pending.take() {
+                previous.remove();
+            }
+            let (shown, save, cleared) = (shown.clone(), save.clone(), pending.clone());
+            pending.replace(Some(glib::timeout_add_local_once(SETTLE, move || {
+                cleared.take();
+                let equalizer = shown.borrow().clone();
+                save(equalizer);
+            })));
+        }
+    };
+    preamp.connect_change_value({
+        let (shown, settle) = (shown.clone(), settle.clone());
+        move |_, _, value| {
+            shown.borrow_mut().preamp_db = value;
+            settle();
+            glib::signal::Inhibit(false)
+        }
+    });
+    enabled.connect_state_set({
+        let (shown, save) = (shown.clone(), save.clone());
+        move |_, on| {
+            let mut equalizer = shown.borrow().clone();
+            if equalizer.enabled != on {
+                equalizer.enabled = on;
+                save(equalizer);
+            }
+            glib::signal::Inhibit(false)
+        }
+    });
+    flat.connect_clicked({
+        let save = save.clone();
+        move |_| save(Equalizer::flat())
+    });
+
+    // only bands coming, going or moving rebuild the sliders, not their gains
+    let layout_of = create_memo(cx, move |_| {
+        saved.with(|saved| {
+            saved
+                .as_ref()
+                .unwrap_or(&Equalizer::flat())
+                .bands
+                .iter()
+                .map(|band| (band.kind, band.frequency.to_bits(), band.q.to_bits()))
+                .collect::<Vec<_>>()
+        })
+    });
+    let sliders = Rc::new(RefCell::new(Vec::<gtk::Scale>::new()));
+    create_effect(cx, {
+        let (shown, sliders) = (shown.clone(), sliders.clone());
+        move |_| {
+            layout_of.track();
+            while let Some(row) = bands.first_child() {
+                bands.remove(&row);
+            }
+            let equalizer = saved.get_untracked().unwrap_or_else(Equalizer::flat);
+            let built = equalizer
+                .bands
+                .iter()
+                .enumerate()
+                .map(|(index, band)| {
+                    let row = gtk::Box::new(Orientation::Horizontal, 6);
+                    let label = gtk::Label::new(Some(&band.describe()));
+                    label.set_width_chars(16);
+                    label.set_xalign(0.);
+                    label.set_tooltip_text(Some(&format!("Q {}", band.q)));
+                    row.append(&label);
+                    let gain = slider();
+                    // passes and notches have no gain
+                    gain.set_sensitive(!matches!(
+                        band.kind,
+                        BandKind::LowPass
+                            | BandKind::HighPass
+                            | BandKind::Notch
+                            | BandKind::AllPass
+                    ));
+                    gain.connect_change_value({
+                        let (shown, settle) = (shown.clone(), settle.clone());
+                        move |_, _, value| {
+                            if let Some(band) = shown.borrow_mut().bands.get_mut(index) {
+                                band.gain_db = value;
+                            }
+                            settle();
+                            glib::signal::Inhibit(false)
+                        }
+                    });
+                    row.append(&gain);
+                    bands.append(&row);
+                    gain
+                })
+                .collect();
+            sliders.replace(built);
+        }
+    });
+    create_effect(cx, {
+        let layout = layout.clone();
+        move |_| {
+            layout.set_sensitive(master.with(Option::is_some));
+            let equalizer = saved.get().unwrap_or_else(|| Equalizer {
+                enabled: false,
+                ..Equalizer::flat()
+            });
+            // first, setting the switch tells its handler too
+            shown.replace(equalizer.clone());
+            enabled.set_active(equalizer.enabled);
+            name.set_text(&equalizer.describe());
+            preamp.set_value(equalizer.preamp_db);
+            for (gain, band) in sliders.borrow().iter().zip(&equalizer.bands) {
+                gain.set_value(band.gain_db);
+            }
+        }
+    });
+
+    // GTK doesn't keep native dialogs alive by itself
+    let open = RefCell::new(None::<gtk::FileChooserNative>);
+    import.connect_clicked(move |button| {
+        let parent = button.root().and_downcast::<gtk::Window>();
+        let dialog = gtk::FileChooserNative::new(
+            Some(&tr("Import AutoEq preset")),
+            parent.as_ref(),
+            gtk::FileChooserAction::Open,
+            Some(&tr("Import")),
+            Some(&tr("Cancel")),
+        );
+        let text = gtk::FileFilter::new();
+        text.set_name(Some(&tr("Equalizer APO configurations")));
+        text.add_suffix("txt");
+        dialog.add_filter(&text);
+        let dispatcher = dispatcher.clone();
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    dispatcher.dispatch(Action::ImportEqualizer(path));
+                }
+            }
+        });
+        dialog.show();
+        open.replace(Some(dialog));
+    });
+    gtk::Expander::builder()
+        .label("Equalizer")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
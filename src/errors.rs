@@ -0,0 +1,120 @@
+//! Failures the user gets to see, rather than only the log: whatever failed is
+//! reported into [`AppState::errors`] and shows in a bar on top of the window, the
+//! whole report behind "Details".
+//!
+//! ```no_run
+//! # use pipeweld::{errors::OrReport, state::AppState, AudioControls};
+//! # fn example(state: AppState) {
+//! AudioControls::set_default(pipeweld::devices::DeviceKind::Sink, "speakers")
+//!     .or_report(state, "switching to the speakers");
+//! # }
+//! ```
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, PoisonError},
+};
+
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::warn;
+
+use crate::{extensions::watch, i18n::tr, state::AppState};
+
+/// Failures beyond this many push out the oldest.
+const KEPT: usize = 10;
+
+/// The latest failures, dismissed ones too, for bug reports.
+static RECENT: Mutex<VecDeque<Failure>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// What was being done, and the outermost error.
+    pub summary: String,
+    /// The report with every cause.
+    pub details: String,
+}
+
+/// Shows that `what` failed with `error`, `what` in the user's language, see
+/// [`crate::i18n`].
+pub fn report(state: AppState, what: &str, error: &eyre::Report) {
+    warn!(?error, "{what}");
+    let failure = Failure {
+        summary: format!("{}: {error}", tr(what)),
+        details: format!("{error:?}"),
+    };
+    {
+        let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+        if recent.len() == KEPT {
+            recent.pop_front();
+        }
+        recent.push_back(failure.clone());
+    }
+    state.errors.update(|errors| {
+        if errors.len() == KEPT {
+            errors.remove(0);
+        }
+        errors.push(failure);
+    });
+}
+
+/// The latest failures, oldest first, whether or not they were dismissed.
+pub fn recent() -> Vec<Failure> {
+    RECENT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .cloned()
+        .collect()
+}
+
+/// Shows the error, in place of discarding it with `.ok()`.
+pub trait OrReport<T> {
+    fn or_report(self, state: AppState, what: &str) -> Option<T>;
+}
+
+impl<T> OrReport<T> for eyre::Result<T> {
+    fn or_report(self, state: AppState, what: &str) -> Option<T> {
+        self.map_err(|error| report(state, what, &error)).ok()
+    }
+}
+
+/// Bar with the latest failure, hidden while there are none, until dismissed.
+pub fn error_bar(cx: Scope, state: AppState) -> gtk::InfoBar {
+    let bar = gtk::InfoBar::new();
+    bar.set_message_type(gtk::MessageType::Error);
+    bar.set_show_close_button(true);
+    bar.set_margin_start(12);
+    bar.set_margin_end(12);
+    bar.set_margin_top(12);
+    bar.connect_response(move |_, _| state.errors.set(Vec::new()));
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let summary = gtk::Label::new(None);
+    summary.set_wrap(true);
+    summary.set_xalign(0.);
+    layout.append(&summary);
+    let details = gtk::Label::new(None);
+    details.set_wrap(true);
+    details.set_xalign(0.);
+    details.set_selectable(true);
+    details.add_css_class("monospace");
+    let expander = gtk::Expander::builder()
+        .label("Details")
+        .child(&details)
+        .build();
+    layout.append(&expander);
+    bar.add_child(&layout);
+    watch(cx, &bar, move |bar| {
+        state.errors.with(|errors| {
+            bar.set_visible(!errors.is_empty());
+            let Some(latest) = errors.last() else {
+                return;
+            };
+            summary.set_text(&match errors.len() {
+                1 => latest.summary.clone(),
+                count => format!("{} (and {} more)", latest.summary, count - 1),
+            });
+            details.set_text(&latest.details);
+        })
+    });
+    bar
+}
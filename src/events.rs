@@ -0,0 +1,233 @@
+//! Change notifications from the server, delivered on the GTK main loop. They're
+//! parsed from `pactl subscribe`, other backends translate theirs into the same events.
+use std::{
+    cell::{Cell, RefCell},
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::backend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    New,
+    Change,
+    Remove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Facility {
+    Sink,
+    Source,
+    SinkInput,
+    SourceOutput,
+    Module,
+    Client,
+    SampleCache,
+    Server,
+    Card,
+    /// Not the server's: the compositor focused another workspace, see
+    /// [`crate::compositor::subscribe`].
+    Workspace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub facility: Facility,
+    pub index: Option<u32>,
+}
+
+impl Event {
+    pub fn workspace_changed() -> Self {
+        Self {
+            kind: EventKind::Change,
+            facility: Facility::Workspace,
+            index: None,
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = eyre::Report;
+
+    /// Parses lines like `Event 'change' on sink #55`.
+    fn from_str(line: &str) -> Result<Self> {
+        let (kind, rest) = line
+            .strip_prefix("Event '")
+            .and_then(|rest| rest.split_once("' on "))
+            .ok_or_else(|| eyre!("not an event line"))?;
+        let (facility, index) = rest
+            .rsplit_once(" #")
+            .ok_or_else(|| eyre!("missing index"))?;
+        let kind = match kind {
+            "new" => EventKind::New,
+            "change" => EventKind::Change,
+            "remove" => EventKind::Remove,
+            other => bail!("unknown event kind {other:?}"),
+        };
+        let facility = match facility {
+            "sink" => Facility::Sink,
+            "source" => Facility::Source,
+            "sink-input" => Facility::SinkInput,
+            "source-output" => Facility::SourceOutput,
+            "module" => Facility::Module,
+            "client" => Facility::Client,
+            "sample-cache" => Facility::SampleCache,
+            "server" => Facility::Server,
+            "card" => Facility::Card,
+            other => bail!("unknown facility {other:?}"),
+        };
+        Ok(Self {
+            kind,
+            facility,
+            // the server reports itself as #4294967295
+            index: index.trim().parse().ok().filter(|index| *index != u32::MAX),
+        })
+    }
+}
+
+/// Forwards events until `pactl subscribe` dies, which is an error,
+/// or until nobody listens anymore, which isn't.
+pub(crate) fn follow(sender: &glib::Sender<Event>) -> Result<()> {
+    let mut child = Command::new("pactl")
+        .arg("subscribe")
+        // the event lines are translated, and we need to parse them
+        .env("LC_ALL", "C")
+        .stdout(Stdio::piped())
+        .spawn()
+        .wrap_err("spawning pactl subscribe")?;
+    let stdout = child.stdout.take().ok_or_else(|| eyre!("no stdout"))?;
+    for line in BufReader::new(stdout).lines() {
+        let line = line.wrap_err("reading pactl subscribe")?;
+        match line.parse::<Event>() {
+            Ok(event) => {
+                if sender.send(event).is_err() {
+                    child.kill().ok();
+                    return Ok(());
+                }
+            }
+            Err(error) => debug!(?error, line, "skipping event line"),
+        }
+    }
+    let status = child.wait().wrap_err("waiting for pactl subscribe")?;
+    Err(eyre!("pactl subscribe exited with {status}"))
+}
+
+type Listener = Box<dyn FnMut(Event) -> glib::Continue>;
+
+thread_local! {
+    /// Whether the thread following the server runs, started by the first listener.
+    static FOLLOWING: Cell<bool> = const { Cell::new(false) };
+    static LISTENERS: RefCell<Vec<Listener>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Hands `event` to every listener, dropping those done listening. Listeners may
+/// subscribe others, which get the next event.
+fn deliver(event: Event) {
+    let mut listeners = LISTENERS.with(|listeners| std::mem::take(&mut *listeners.borrow_mut()));
+    listeners.retain_mut(|listener| listener(event).0);
+    LISTENERS.with(|current| {
+        let mut current = current.borrow_mut();
+        let added = std::mem::replace(&mut *current, listeners);
+        current.extend(added);
+    });
+}
+
+/// Follows the events of [`backend::current`] in a background thread for the lifetime
+/// of the process, restarting whenever the server goes away, and calls `callback` for
+/// every event. There's one subscription to the server however many listen.
+pub fn subscribe<F: FnMut(Event) + 'static>(mut callback: F) {
+    subscribe_while(move |event| {
+        callback(event);
+        glib::Continue(true)
+    });
+}
+
+/// Like [`subscribe`], until `callback` returns `Continue(false)`, as it does once
+/// what it updates is gone.
+pub fn subscribe_while<F: FnMut(Event) -> glib::Continue + 'static>(callback: F) {
+    LISTENERS.with(|listeners| listeners.borrow_mut().push(Box::new(callback)));
+    if FOLLOWING.with(|following| following.replace(true)) {
+        return;
+    }
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, move |event| {
+        deliver(event);
+        glib::Continue(true)
+    });
+    std::thread::spawn(move || {
+        while let Err(error) = backend::current().follow(&sender) {
+            warn!(?error, "event subscription interrupted, retrying");
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn listen(listener: impl FnMut(Event) -> glib::Continue + 'static) {
+        LISTENERS.with(|listeners| listeners.borrow_mut().push(Box::new(listener)));
+    }
+
+    #[test]
+    fn listeners_done_listening_are_dropped() {
+        let heard = Rc::new(Cell::new(0));
+        listen({
+            let heard = heard.clone();
+            move |_| {
+                heard.set(heard.get() + 1);
+                glib::Continue(false)
+            }
+        });
+        deliver(Event::workspace_changed());
+        deliver(Event::workspace_changed());
+        assert_eq!(heard.get(), 1);
+        assert_eq!(LISTENERS.with(|listeners| listeners.borrow().len()), 0);
+    }
+
+    #[test]
+    fn listeners_subscribed_while_delivering_get_the_next_event() {
+        let heard = Rc::new(Cell::new(0));
+        listen({
+            let heard = heard.clone();
+            move |_| {
+                let heard = heard.clone();
+                listen(move |_| {
+                    heard.set(heard.get() + 1);
+                    glib::Continue(true)
+                });
+                glib::Continue(false)
+            }
+        });
+        deliver(Event::workspace_changed());
+        assert_eq!(heard.get(), 0);
+        deliver(Event::workspace_changed());
+        assert_eq!(heard.get(), 1);
+    }
+
+    #[test]
+    fn parses_pactl_event_lines() {
+        let event = "Event 'change' on sink-input #42".parse::<Event>().unwrap();
+        assert_eq!(event.kind, EventKind::Change);
+        assert_eq!(event.facility, Facility::SinkInput);
+        assert_eq!(event.index, Some(42));
+        let server = "Event 'change' on server #4294967295"
+            .parse::<Event>()
+            .unwrap();
+        assert_eq!(server.index, None);
+    }
+}
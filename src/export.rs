@@ -0,0 +1,132 @@
+//! The current setup as a shell script, for baking it into dotfiles. It recreates
+//! pipeweld's virtual devices, then sets volumes, mutes and the defaults.
+use std::{cell::RefCell, fmt::Write, os::unix::fs::PermissionsExt, path::Path};
+
+use eyre::{Result, WrapErr};
+use gtk::prelude::*;
+use tracing::instrument;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    filter_chain::Host,
+    i18n::tr,
+    modules::Module,
+    AudioControls,
+};
+
+/// `value` as a single shell word.
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+pub fn script(filters: &Host) -> Result<String> {
+    let mut script = format!(
+        "#!/bin/sh\n# Audio setup exported by {}\nset -e\n",
+        clap::crate_name!()
+    );
+    let modules = Module::list()?
+        .into_iter()
+        .filter(|module| module.object().is_some())
+        .collect::<Vec<_>>();
+    if !modules.is_empty() {
+        script.push_str("\n# virtual devices\n");
+    }
+    // writing to a String can't fail, hence the ok()s
+    for module in modules {
+        writeln!(
+            script,
+            "pactl load-module {} {}",
+            module.name,
+            sh_quote(&module.argument)
+        )
+        .ok();
+    }
+
+    let mut filters = filters.filters().collect::<Vec<_>>();
+    filters.sort_by(|a, b| a.object.cmp(&b.object));
+    if !filters.is_empty() {
+        script.push_str(
+            "\n# filters, each hosted by a pipewire process of its own\n\
+             conf=\"${XDG_RUNTIME_DIR:-/tmp}/pipeweld\"\n\
+             mkdir -p \"$conf\"\n",
+        );
+    }
+    for (index, filter) in filters.iter().enumerate() {
+        writeln!(
+            script,
+            "cat > \"$conf/filter-{index}.conf\" <<'EOF'\n{}EOF\n\
+             pipewire -c \"$conf/filter-{index}.conf\" &",
+            filter.config()
+        )
+        .ok();
+    }
+    if !filters.is_empty() {
+        // their sinks have to exist before they can be made the default
+        script.push_str("sleep 1\n");
+    }
+
+    for kind in [DeviceKind::Sink, DeviceKind::Source] {
+        writeln!(script, "\n# {}s", kind.noun()).ok();
+        for device in Device::list(kind)? {
+            let name = sh_quote(&device.name);
+            writeln!(
+                script,
+                "pactl set-{noun}-volume {name} {}%\npactl set-{noun}-mute {name} {}",
+                device.volume_percent(),
+                u8::from(device.mute),
+                noun = kind.noun(),
+            )
+            .ok();
+        }
+        writeln!(
+            script,
+            "pactl set-default-{} {}",
+            kind.noun(),
+            sh_quote(&AudioControls::default_device(kind)?)
+        )
+        .ok();
+    }
+    Ok(script)
+}
+
+/// Writes the script to `path`, ready to run.
+#[instrument(skip(filters), ret, err)]
+pub fn write(filters: &Host, path: &Path) -> Result<()> {
+    std::fs::write(path, script(filters)?)
+        .wrap_err_with(|| format!("writing {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .wrap_err_with(|| format!("making {} executable", path.display()))
+}
+
+/// Button asking where to save the script.
+pub fn export_button(dispatcher: Dispatcher) -> gtk::Button {
+    let button = gtk::Button::with_label(&tr("Export as shell script…"));
+    button.set_margin_start(12);
+    button.set_margin_end(12);
+    button.set_margin_bottom(12);
+    // GTK doesn't keep native dialogs alive by itself
+    let open = RefCell::new(None::<gtk::FileChooserNative>);
+    button.connect_clicked(move |button| {
+        let parent = button.root().and_downcast::<gtk::Window>();
+        let dialog = gtk::FileChooserNative::new(
+            Some("Export audio setup"),
+            parent.as_ref(),
+            gtk::FileChooserAction::Save,
+            Some("Export"),
+            Some("Cancel"),
+        );
+        dialog.set_current_name(&format!("{}-setup.sh", clap::crate_name!()));
+        let dispatcher = dispatcher.clone();
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    dispatcher.dispatch(Action::ExportScript(path));
+                }
+            }
+        });
+        dialog.show();
+        open.replace(Some(dialog));
+    });
+    button
+}
@@ -0,0 +1,87 @@
+//! A named pipe taking commands, one per line, for window managers and scripts
+//! that can't speak D-Bus:
+//!
+//! ```sh
+//! echo "volume +5" > $XDG_RUNTIME_DIR/pipeweld.fifo
+//! echo "mute toggle --input" > $XDG_RUNTIME_DIR/pipeweld.fifo
+//! ```
+//!
+//! The commands are those of the command line, read by [`crate::cli`]: `volume +5`,
+//! `volume -5` or `volume 40`, `mute on`, `off` or `toggle`, `default <device>` or
+//! `default next`, each for the default input with `--input`, and `show` for the
+//! window.
+use std::{
+    io::{BufRead, BufReader},
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use eyre::{bail, Result, WrapErr};
+use gtk::glib;
+use tracing::{info, warn};
+
+use crate::{actions::Dispatcher, cli};
+
+pub fn path() -> PathBuf {
+    glib::user_runtime_dir().join(format!("{}.fifo", clap::crate_name!()))
+}
+
+/// Makes the pipe, unless an earlier instance left one behind.
+fn create(path: &Path) -> Result<()> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => return Ok(()),
+        Ok(_) => bail!("{} is there already, and not a pipe", path.display()),
+        Err(_) => {}
+    }
+    let status = Command::new("mkfifo")
+        .args(["--mode=600"])
+        .arg(path)
+        .status()
+        .wrap_err("running mkfifo")?;
+    if !status.success() {
+        bail!("mkfifo {} exited with {status}", path.display());
+    }
+    Ok(())
+}
+
+/// Reads commands from the pipe for the lifetime of the process.
+pub fn spawn(dispatcher: Dispatcher) {
+    let path = path();
+    if let Err(error) = create(&path) {
+        return warn!(?error, "there'll be no control pipe");
+    }
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, move |line: String| {
+        match cli::action(&line) {
+            Ok(action) => {
+                info!(line, ?action, "control pipe");
+                dispatcher.dispatch(action);
+            }
+            Err(error) => warn!(%error, "control pipe"),
+        }
+        glib::Continue(true)
+    });
+    std::thread::spawn(move || loop {
+        // blocks until something writes, one writer closing ends the lines
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(error) => return warn!(%error, path = %path.display(), "opening the control pipe"),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    warn!(%error, "reading the control pipe");
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+}
@@ -0,0 +1,195 @@
+//! Filters inserted in front of a sink. Each one is a `libpipewire-module-filter-chain`
+//! hosted by a `pipewire -c <config>` child process: it shows up as a virtual sink
+//! and plays whatever it processed into its master.
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::{Child, Command},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use eyre::{Result, WrapErr};
+use gtk::glib;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    channel_mix,
+    devices::{Device, DeviceKind},
+    modules, AudioControls,
+};
+
+/// Property naming the sink a filter plays into.
+pub const MASTER_TAG: &str = "pipeweld.master";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    /// Unique name, also what the virtual sink is tagged with.
+    pub object: String,
+    pub description: String,
+    /// Name of the sink the processed audio goes to.
+    pub master: String,
+    /// SPA-JSON `filter.graph`, with as many ports as the sink has channels or just one,
+    /// which the filter chain then runs once per channel.
+    pub graph: String,
+}
+
+/// Quotes `value` as an SPA-JSON string.
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Filter {
+    /// Name of the virtual sink the filter creates.
+    pub fn sink_name(&self) -> String {
+        format!("{}.{}", clap::crate_name!(), self.object)
+    }
+
+    /// The `pipewire -c` configuration hosting the filter.
+    pub fn config(&self) -> String {
+        let description = quote(&self.description);
+        format!(
+            r#"context.properties = {{ log.level = 0 }}
+context.spa-libs = {{
+    audio.convert.* = audioconvert/libspa-audioconvert
+    support.*       = support/libspa-support
+}}
+context.modules = [
+    {{ name = libpipewire-module-rt flags = [ ifexists nofail ] }}
+    {{ name = libpipewire-module-protocol-native }}
+    {{ name = libpipewire-module-client-node }}
+    {{ name = libpipewire-module-adapter }}
+    {{ name = libpipewire-module-filter-chain
+        args = {{
+            node.description = {description}
+            media.name = {description}
+            audio.channels = 2
+            audio.position = [ FL FR ]
+            filter.graph = {graph}
+            capture.props = {{
+                node.name = {sink}
+                media.class = Audio/Sink
+                {object_tag} = {object}
+                {master_tag} = {master}
+            }}
+            playback.props = {{
+                node.name = {playback}
+                node.passive = true
+                target.object = {master}
+            }}
+        }}
+    }}
+]
+"#,
+            graph = self.graph,
+            sink = quote(&self.sink_name()),
+            playback = quote(&format!("{}.output", self.sink_name())),
+            object_tag = modules::OBJECT_TAG,
+            object = quote(&self.object),
+            master_tag = MASTER_TAG,
+            master = quote(&self.master),
+        )
+    }
+
+    fn config_path(&self) -> PathBuf {
+        glib::user_runtime_dir()
+            .join(clap::crate_name!())
+            .join(format!("{}.conf", self.object.replace(['/', ':'], "_")))
+    }
+}
+
+/// The sink a pipeweld filter or remix plays into, `None` for real devices.
+pub fn master_of(device: &Device) -> Option<&str> {
+    device
+        .properties
+        .get(MASTER_TAG)
+        .map(String::as_str)
+        .or_else(|| channel_mix::master_of(device))
+}
+
+/// Name of the real device behind whatever pipeweld put on top of it.
+pub fn master_name(device: &Device) -> &str {
+    master_of(device).unwrap_or(&device.name)
+}
+
+/// Keeps the filter processes, which go away together with pipeweld.
+#[derive(Debug, Default)]
+pub struct Host {
+    running: HashMap<String, (Filter, Child)>,
+}
+
+impl Host {
+    /// Starts `filter`, replacing a running one of the same object.
+    #[instrument(skip(self, filter), fields(object = %filter.object), err)]
+    pub fn start(&mut self, filter: &Filter) -> Result<()> {
+        self.stop(&filter.object);
+        if crate::dry_run() {
+            info!("dry run, not starting the filter");
+            return Ok(());
+        }
+        let path = filter.config_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&path, filter.config())
+            .wrap_err_with(|| format!("writing {}", path.display()))?;
+        let child = Command::new("pipewire")
+            .arg("-c")
+            .arg(&path)
+            .spawn()
+            .wrap_err("starting the filter chain")?;
+        info!(pid = child.id(), "filter started");
+        self.running
+            .insert(filter.object.clone(), (filter.clone(), child));
+        Ok(())
+    }
+
+    /// Stops the filter called `object`, handing the default output back to its master.
+    pub fn stop(&mut self, object: &str) {
+        let Some((filter, mut child)) = self.running.remove(object) else {
+            return;
+        };
+        match AudioControls::default_device(DeviceKind::Sink) {
+            Ok(default) if default == filter.sink_name() => {
+                AudioControls::set_default(DeviceKind::Sink, &filter.master).ok();
+            }
+            _ => {}
+        }
+        if let Err(error) = child.kill().and_then(|_| child.wait()) {
+            warn!(?error, object, "stopping filter");
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        let objects = self.running.keys().cloned().collect::<Vec<_>>();
+        for object in objects {
+            self.stop(&object);
+        }
+    }
+
+    pub fn get(&self, object: &str) -> Option<&Filter> {
+        self.running.get(object).map(|(filter, _)| filter)
+    }
+
+    pub fn filters(&self) -> impl Iterator<Item = &Filter> {
+        self.running.values().map(|(filter, _)| filter)
+    }
+}
+
+/// A [`Host`] filters start on from the main loop and the audio command thread
+/// alike, see [`AudioControls::in_background`].
+#[derive(Debug, Clone, Default)]
+pub struct SharedHost(Arc<Mutex<Host>>);
+
+impl SharedHost {
+    pub fn lock(&self) -> MutexGuard<'_, Host> {
+        // a panic while starting a filter leaves the others running as they were
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
@@ -0,0 +1,236 @@
+//! A notification the first time each application plays or records this session,
+//! like "Zoom started using your microphone", with buttons to mute it or move it to
+//! another device. Off unless the configuration turns it on:
+//!
+//! ```toml
+//! [notifications]
+//! first_use = true
+//! ```
+//!
+//! Applications are told apart by `application.name`. What plays or records when
+//! pipeweld starts counts as seen, and pipeweld's own streams and those of modules
+//! don't count. What plays is listed off the main loop, as other commands are.
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    captures::Capture,
+    config,
+    devices::{Device, DeviceKind},
+    events::{self, EventKind, Facility},
+    filter_chain,
+    i18n::{tr, tr_with},
+    modules,
+    notifications::{self, Notification, Notifier},
+    streams::Stream,
+    AudioControls,
+};
+
+/// A stream or a recording, as far as telling its application and device goes.
+#[derive(Debug, Clone)]
+struct Use {
+    kind: DeviceKind,
+    index: u32,
+    application: String,
+    icon: Option<String>,
+    /// Index of the sink or source it's on.
+    device: u32,
+}
+
+impl Use {
+    /// What applications play, or record for `Source`.
+    fn list(kind: DeviceKind) -> Vec<Self> {
+        let own = std::process::id().to_string();
+        let listed = match kind {
+            DeviceKind::Sink => Stream::list().map(|streams| {
+                streams
+                    .into_iter()
+                    .map(|stream| (stream.index, stream.sink, stream.properties))
+                    .collect::<Vec<_>>()
+            }),
+            DeviceKind::Source => Capture::list().map(|captures| {
+                captures
+                    .into_iter()
+                    .map(|capture| (capture.index, capture.source, capture.properties))
+                    .collect()
+            }),
+        };
+        let listed = match listed {
+            Ok(listed) => listed,
+            Err(error) => {
+                warn!(?error, "listing what uses the {}s", kind.noun());
+                return Vec::new();
+            }
+        };
+        listed
+            .into_iter()
+            .filter(|(_, _, properties)| {
+                !properties.contains_key("pulse.module.id")
+                    && !properties.contains_key(modules::OBJECT_TAG)
+                    && properties.get("application.process.id") != Some(&own)
+            })
+            .filter_map(|(index, device, mut properties)| {
+                Some(Self {
+                    kind,
+                    index,
+                    application: properties.remove("application.name")?,
+                    icon: properties.remove("application.icon_name"),
+                    device,
+                })
+            })
+            .collect()
+    }
+
+    fn summary(&self) -> String {
+        let application = [("application", self.application.as_str())];
+        match self.kind {
+            DeviceKind::Sink => tr_with("{application} started playing sound", &application),
+            DeviceKind::Source => {
+                tr_with("{application} started using your microphone", &application)
+            }
+        }
+    }
+
+    fn mute(&self) -> Action {
+        match self.kind {
+            DeviceKind::Sink => Action::SetStreamMute(self.index, true),
+            DeviceKind::Source => Action::SetCaptureMute(self.index, true),
+        }
+    }
+
+    fn move_to(&self, device: &Device) -> Action {
+        match self.kind {
+            DeviceKind::Sink => Action::MoveStream {
+                stream: self.index,
+                sink: device.name.clone(),
+            },
+            DeviceKind::Source => Action::MoveCapture {
+                capture: self.index,
+                source: device.name.clone(),
+            },
+        }
+    }
+}
+
+/// The device `used` is on, and the one after it to move it to, leaving out
+/// pipeweld's filters.
+fn devices(used: &Use) -> (Option<Device>, Option<Device>) {
+    let devices = match Device::list(used.kind) {
+        Ok(devices) => devices
+            .into_iter()
+            .filter(|device| filter_chain::master_of(device).is_none())
+            .collect::<Vec<_>>(),
+        Err(error) => {
+            warn!(?error, "listing {}s to move to", used.kind.noun());
+            return (None, None);
+        }
+    };
+    let at = devices
+        .iter()
+        .position(|device| device.index == used.device);
+    let next = devices
+        .iter()
+        .cycle()
+        .skip(at.map_or(0, |at| at + 1))
+        .take(devices.len())
+        .find(|device| device.index != used.device)
+        .cloned();
+    (at.map(|at| devices[at].clone()), next)
+}
+
+/// Notifies about `used`, once the devices it's on and could move to are listed.
+fn notify(notifier: Notifier, dispatcher: Dispatcher, used: Use) {
+    let listed = used.clone();
+    AudioControls::in_background(
+        move || Ok(devices(&listed)),
+        move |devices| {
+            let (on, next) = devices.unwrap_or_default();
+            show(&notifier, dispatcher, used, on, next);
+        },
+    );
+}
+
+fn show(
+    notifier: &Notifier,
+    dispatcher: Dispatcher,
+    used: Use,
+    on: Option<Device>,
+    next: Option<Device>,
+) {
+    let mut notification = Notification::new(used.summary())
+        .icon(
+            used.icon
+                .clone()
+                .unwrap_or_else(|| notifications::icon_name(used.kind).to_owned()),
+        )
+        .action("mute", tr("Mute"));
+    if let Some(on) = on {
+        notification = notification.body(on.description);
+    }
+    if let Some(next) = &next {
+        notification = notification.action(
+            "move",
+            tr_with("Move to {device}", &[("device", &next.description)]),
+        );
+    }
+    notifier.show_then(notification, move |key| match (key, &next) {
+        ("mute", _) => dispatcher.dispatch(used.mute()),
+        ("move", Some(next)) => dispatcher.dispatch(used.move_to(next)),
+        _ => {}
+    });
+}
+
+/// Notifies about applications playing and recording for the first time, when
+/// [`config::Notifications::first_use`] is on.
+pub fn spawn(dispatcher: Dispatcher) {
+    if !config::get().notifications.first_use {
+        return;
+    }
+    let notifier = match Notifier::new() {
+        Ok(notifier) => notifier,
+        Err(error) => return warn!(?error, "first uses won't be notified about"),
+    };
+    AudioControls::in_background(
+        || {
+            Ok([DeviceKind::Sink, DeviceKind::Source]
+                .into_iter()
+                .flat_map(Use::list)
+                .map(|used| (used.kind, used.application))
+                .collect::<HashSet<_>>())
+        },
+        move |seen| follow(notifier, dispatcher, seen.unwrap_or_default()),
+    );
+}
+
+/// Notifies about each application starting to play or record that isn't in `seen`.
+fn follow(notifier: Notifier, dispatcher: Dispatcher, seen: HashSet<(DeviceKind, String)>) {
+    let seen = Rc::new(RefCell::new(seen));
+    events::subscribe(move |event| {
+        let kind = match (event.kind, event.facility) {
+            (EventKind::New, Facility::SinkInput) => DeviceKind::Sink,
+            (EventKind::New, Facility::SourceOutput) => DeviceKind::Source,
+            _ => return,
+        };
+        let (notifier, dispatcher, seen) = (notifier.clone(), dispatcher.clone(), seen.clone());
+        AudioControls::in_background(
+            move || {
+                Ok(Use::list(kind)
+                    .into_iter()
+                    .find(|used| Some(used.index) == event.index))
+            },
+            move |used| {
+                let Ok(Some(used)) = used else {
+                    return;
+                };
+                if seen
+                    .borrow_mut()
+                    .insert((used.kind, used.application.clone()))
+                {
+                    notify(notifier, dispatcher, used);
+                }
+            },
+        );
+    });
+}
@@ -0,0 +1,173 @@
+//! PulseAudio's flat volumes, turned on with `flat-volumes = yes` in its
+//! `daemon.conf`: an application's volume is then its share of the card's volume
+//! rather than of the output's, and the output plays as loud as its loudest
+//! application. Raising an application above the output raises the output with it,
+//! and lowering the loudest one lowers it. PipeWire's pulse server never does this.
+//!
+//! The mixer warns on the rows that move the output, and explains why. It can also
+//! hide the server's semantics, showing and setting applications as shares of the
+//! output they play on, where raising one never goes past the output:
+//!
+//! ```toml
+//! [volume]
+//! compensate_flat_volumes = true
+//! ```
+//!
+//! What rules and presets set stays in the server's terms, so they keep restoring
+//! what they saved.
+use std::path::{Path, PathBuf};
+
+use eyre::Result;
+use gtk::glib;
+use tracing::debug;
+
+use crate::{
+    compat, config,
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    relative_volume,
+    streams::Stream,
+    AudioControls,
+};
+
+/// What `text`, a `daemon.conf`, sets `flat-volumes` to, the last setting winning.
+fn setting(text: &str) -> Option<bool> {
+    text.lines().rev().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        if key.trim() != "flat-volumes" {
+            return None;
+        }
+        match value.trim().to_ascii_lowercase().as_str() {
+            "yes" | "true" | "on" | "1" => Some(true),
+            "no" | "false" | "off" | "0" => Some(false),
+            _ => None,
+        }
+    })
+}
+
+/// The `daemon.conf` PulseAudio reads, the user's in place of the system's, and the
+/// files in the `daemon.conf.d` beside it in order.
+fn files() -> Vec<PathBuf> {
+    let candidates = [
+        std::env::var_os("PULSE_CONFIG").map(PathBuf::from),
+        Some(glib::user_config_dir().join("pulse").join("daemon.conf")),
+        Some(Path::new("/etc/pulse/daemon.conf").to_owned()),
+    ];
+    let Some(main) = candidates.into_iter().flatten().find(|path| path.exists()) else {
+        return Vec::new();
+    };
+    let mut included = std::fs::read_dir(main.with_extension("conf.d"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "conf")
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    included.sort();
+    std::iter::once(main).chain(included).collect()
+}
+
+/// Whether the server as named by `pactl info` uses flat volumes, off unless its
+/// configuration turns them on.
+pub fn detect(pulse_server: Option<&str>) -> bool {
+    if pulse_server.is_some_and(|server| server.contains("PipeWire")) {
+        return false;
+    }
+    // the later files go over the earlier ones
+    files()
+        .iter()
+        .rev()
+        .find_map(|path| {
+            let text = std::fs::read_to_string(path)
+                .map_err(|error| debug!(%error, path = %path.display(), "reading daemon.conf"))
+                .ok()?;
+            setting(&text)
+        })
+        .unwrap_or(false)
+}
+
+/// Whether flat volumes are in effect, as detected on connecting.
+pub fn in_effect() -> bool {
+    compat::get().flat_volumes
+}
+
+/// Whether the mixer shows and sets applications as shares of their output.
+pub fn compensating() -> bool {
+    config::get().volume.compensate_flat_volumes && in_effect()
+}
+
+fn output_volume(stream: &Stream, sinks: &[Device]) -> Option<u32> {
+    sinks
+        .iter()
+        .find(|sink| sink.index == stream.sink)
+        .map(Device::volume_percent)
+}
+
+/// The volume the mixer shows for `stream`.
+pub fn shown(stream: &Stream, sinks: &[Device]) -> u32 {
+    let volume = stream.volume_percent();
+    match compensating() {
+        true => output_volume(stream, sinks)
+            .and_then(|output| relative_volume::share(volume, output))
+            .unwrap_or(volume),
+        false => volume,
+    }
+}
+
+/// The volume to ask the server for when the mixer sets `stream` to `percent`, no
+/// more than its output's when compensating.
+pub fn to_server(stream: &Stream, percent: u32, sinks: &[Device]) -> u32 {
+    match compensating() {
+        true => output_volume(stream, sinks).map_or(percent, |output| {
+            relative_volume::target(output, percent.min(100))
+        }),
+        false => percent,
+    }
+}
+
+/// Whether moving `stream`'s volume moves its output's too: it's the loudest on the
+/// output, or would be above it, which compensating rules out.
+pub fn moves_output(stream: &Stream, sinks: &[Device]) -> bool {
+    in_effect()
+        && !compensating()
+        && output_volume(stream, sinks).is_some_and(|output| stream.volume_percent() >= output)
+}
+
+/// The sinks [`to_server`] needs, none when it doesn't need any.
+pub fn sinks() -> Result<Vec<Device>> {
+    match compensating() {
+        true => Device::list(DeviceKind::Sink),
+        false => Ok(Vec::new()),
+    }
+}
+
+/// Sets `stream` to `percent` as the mixer shows it.
+pub fn set_stream_volume(stream: u32, percent: u32) -> Result<()> {
+    if !compensating() {
+        return AudioControls::set_stream_volume(stream, percent);
+    }
+    let sinks = sinks()?;
+    let percent = match Stream::list()?.iter().find(|listed| listed.index == stream) {
+        Some(listed) => to_server(listed, percent, &sinks),
+        None => percent,
+    };
+    AudioControls::set_stream_volume(stream, percent)
+}
+
+/// What the mixer says about flat volumes, while they're in effect.
+pub fn explainer() -> Option<String> {
+    in_effect().then(|| match compensating() {
+        true => tr(
+            "The server uses flat volumes. Application volumes are shown as shares of \
+             their output, so changing one never changes the output.",
+        ),
+        false => tr(
+            "The server uses flat volumes: an output plays as loud as its loudest \
+             application, so raising an application above it raises the output too.",
+        ),
+    })
+}
@@ -0,0 +1,121 @@
+//! Volume of whichever application has window focus, so one pair of hotkeys
+//! covers whatever is being looked at.
+use eyre::{eyre, Result};
+use gtk::{gio, prelude::*, Application};
+use tracing::instrument;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    compositor::Window,
+    streams::Stream,
+    AudioControls, DiffValue,
+};
+
+/// Application actions and command line switches, as in
+/// `gapplication action it.niedzwiedz.pipeweld focused-volume-up`.
+const ACTIONS: [(&str, DiffValue); 2] = [
+    ("focused-volume-up", DiffValue(5)),
+    ("focused-volume-down", DiffValue(-5)),
+];
+
+/// How far up the process tree a stream may be from the window, for
+/// applications playing from helper processes like browsers and Wine do.
+const MAX_DEPTH: usize = 16;
+
+fn parent(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // `pid (comm) state ppid …`, where comm may contain anything
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+fn descends_from(mut pid: u32, ancestor: u32) -> bool {
+    for _ in 0..MAX_DEPTH {
+        if pid == ancestor {
+            return true;
+        }
+        match parent(pid) {
+            Some(parent) if parent > 1 => pid = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+fn property<'a>(stream: &'a Stream, key: &str) -> Option<&'a str> {
+    stream.properties.get(key).map(String::as_str)
+}
+
+/// Streams played by the application behind `window`, by process and failing
+/// that by name.
+pub fn streams_of<'a>(window: &Window, streams: &'a [Stream]) -> Vec<&'a Stream> {
+    let by_process = streams
+        .iter()
+        .filter(|stream| {
+            let pid = property(stream, "application.process.id").and_then(|pid| pid.parse().ok());
+            matches!((pid, window.pid), (Some(pid), Some(window)) if descends_from(pid, window))
+        })
+        .collect::<Vec<_>>();
+    if !by_process.is_empty() {
+        return by_process;
+    }
+    let Some(app_id) = window.app_id.as_deref() else {
+        return Vec::new();
+    };
+    streams
+        .iter()
+        .filter(|stream| {
+            [
+                "application.id",
+                "application.process.binary",
+                "application.name",
+            ]
+            .into_iter()
+            .filter_map(|key| property(stream, key))
+            .any(|name| name.eq_ignore_ascii_case(app_id))
+        })
+        .collect()
+}
+
+#[instrument(ret, err)]
+pub fn change_volume(window: Option<&Window>, diff: DiffValue) -> Result<()> {
+    let window = window.ok_or_else(|| eyre!("no window has focus"))?;
+    let streams = Stream::list()?;
+    let playing = streams_of(window, &streams);
+    if playing.is_empty() {
+        return Err(eyre!("the focused application isn't playing anything"));
+    }
+    playing
+        .into_iter()
+        .try_for_each(|stream| AudioControls::change_stream_volume(stream.index, diff))
+}
+
+/// Exposes the actions on the application, reachable over D-Bus while pipeweld runs.
+pub fn register(app: &Application, dispatcher: Dispatcher) {
+    for (name, diff) in ACTIONS {
+        let action = gio::SimpleAction::new(name, None);
+        let dispatcher = dispatcher.clone();
+        action.connect_activate(move |_, _| dispatcher.dispatch(Action::ChangeFocusedVolume(diff)));
+        app.add_action(&action);
+    }
+}
+
+/// Adds `--focused-volume-up` and `--focused-volume-down`.
+pub fn add_options(app: &Application) {
+    crate::add_action_switches(
+        app,
+        ACTIONS
+            .iter()
+            .map(|(name, diff)| {
+                (
+                    *name,
+                    format!("Change the focused application's volume by {diff}"),
+                )
+            })
+            .collect(),
+    );
+}
@@ -0,0 +1,330 @@
+//! Guided gain staging, for setting up a microphone and an output chain before a
+//! session. The microphone is metered while the user speaks at their loudest and
+//! its volume picked for peaks to land around [`INPUT_PEAK_DB`]; then a sweep plays
+//! through the output's effects, whose monitor tells how much they boost at most,
+//! and the trim in front of them makes up for it, with a limiter catching what's
+//! left. What's recommended is applied like the user set it, the trim and limiter
+//! going into the output's filter chain, see [`crate::presets`].
+use std::{
+    cell::{Cell, RefCell},
+    path::PathBuf,
+    process::{Child, Command},
+    rc::Rc,
+    time::Duration,
+};
+
+use eyre::{Result, WrapErr};
+use gtk::{glib, prelude::*, Application, Orientation};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    av_sync::{self, SAMPLE_RATE},
+    devices::DeviceKind,
+    filter_chain,
+    i18n::{tr, tr_with},
+    levels::{decibels, Meter},
+    modules::Module,
+    presets::TRIM_RANGE_DB,
+    state::AppState,
+};
+
+/// How long the microphone is listened to.
+const LISTEN: Duration = Duration::from_secs(5);
+/// Where the microphone's loudest peaks should land, in dBFS, leaving headroom.
+pub const INPUT_PEAK_DB: f64 = -12.;
+/// Highest microphone volume recommended, in percent.
+const MAX_INPUT_PERCENT: u32 = 150;
+/// Length and level of the sweep, in dBFS, and the frequencies it goes through.
+const SWEEP: Duration = Duration::from_secs(4);
+const SWEEP_DB: f64 = -12.;
+const SWEEP_FROM_HZ: f64 = 20.;
+const SWEEP_TO_HZ: f64 = 20_000.;
+/// Ceiling of the limiter recommended, in dBFS.
+pub const LIMIT_DB: i32 = -1;
+/// Below this, in dBFS, nothing was heard.
+const SILENCE_DB: f64 = -60.;
+
+/// What the wizard found worth changing, `None` for what's fine as it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Recommendation {
+    pub input_percent: Option<u32>,
+    pub trim_db: Option<i32>,
+    pub limit_db: Option<i32>,
+}
+
+/// Microphone volume bringing a peak of `peak_db` at `current` percent to
+/// [`INPUT_PEAK_DB`], volumes going by the cube of the gain.
+pub fn input_volume(current: u32, peak_db: f64) -> u32 {
+    let percent = f64::from(current) * 10f64.powf((INPUT_PEAK_DB - peak_db) / 60.);
+    (percent.round() as u32).clamp(1, MAX_INPUT_PERCENT)
+}
+
+/// Trim keeping the effects from boosting anything above where it went in, given
+/// the trim they were measured with and the most they boosted by.
+pub fn trim(current_db: i32, gain_db: f64) -> i32 {
+    let (lowest, _) = TRIM_RANGE_DB;
+    ((f64::from(current_db) - gain_db).floor().min(0.) as i32).max(lowest)
+}
+
+/// A mono WAV of a logarithmic sweep through the audible range at [`SWEEP_DB`].
+fn sweep() -> Vec<u8> {
+    let amplitude = 10f64.powf(SWEEP_DB / 20.) * f64::from(i16::MAX);
+    let length = SWEEP.as_secs_f64();
+    let ratio = (SWEEP_TO_HZ / SWEEP_FROM_HZ).ln();
+    let samples = (0..(f64::from(SAMPLE_RATE) * length) as usize)
+        .map(|sample| {
+            let time = sample as f64 / f64::from(SAMPLE_RATE);
+            let phase = 2. * std::f64::consts::PI * SWEEP_FROM_HZ * length / ratio
+                * ((time / length * ratio).exp() - 1.);
+            (phase.sin() * amplitude) as i16
+        })
+        .collect::<Vec<_>>();
+    av_sync::wav(&samples)
+}
+
+fn sweep_path() -> PathBuf {
+    glib::user_runtime_dir()
+        .join(clap::crate_name!())
+        .join("gain-staging.wav")
+}
+
+#[instrument(ret, err)]
+fn play_sweep(sink: &str) -> Result<Child> {
+    let path = sweep_path();
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&path, sweep()).wrap_err_with(|| format!("writing {}", path.display()))?;
+    }
+    Command::new("paplay")
+        .arg(format!("--device={sink}"))
+        .arg(Module::stream_tag("gain-staging"))
+        .arg(&path)
+        .spawn()
+        .wrap_err("playing the test sweep")
+}
+
+/// Meters the source `target` for `duration`, then hands the loudest peak, in
+/// dBFS, to `on_done`.
+fn measure(target: &str, duration: Duration, on_done: impl FnOnce(f64) + 'static) -> Result<()> {
+    let peak = Rc::new(Cell::new(0f32));
+    let meter = Meter::start(target, {
+        let peak = peak.clone();
+        move |level| peak.set(peak.get().max(level.peak))
+    })?;
+    glib::timeout_add_local_once(duration, move || {
+        drop(meter);
+        on_done(decibels(peak.get()));
+    });
+    Ok(())
+}
+
+/// A step of the wizard: a heading, what to do, a button starting it and a line
+/// with what came out. Returns the step, its button and the line.
+fn step(heading: &str, instructions: &str, button: &str) -> (gtk::Box, gtk::Button, gtk::Label) {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let title = gtk::Label::new(Some(heading));
+    title.set_halign(gtk::Align::Start);
+    title.add_css_class("heading");
+    layout.append(&title);
+    let text = gtk::Label::new(Some(instructions));
+    text.set_wrap(true);
+    text.set_xalign(0.);
+    layout.append(&text);
+    let start = gtk::Button::with_label(button);
+    start.set_halign(gtk::Align::Start);
+    layout.append(&start);
+    let result = gtk::Label::new(None);
+    result.set_wrap(true);
+    result.set_xalign(0.);
+    result.add_css_class("dim-label");
+    layout.append(&result);
+    (layout, start, result)
+}
+
+/// The wizard, for the default input and output when it opens.
+pub fn open(cx: Scope, app: &Application, state: AppState, dispatcher: Dispatcher) {
+    let (Some(source), Some(sink)) = (
+        state.default_source.get_untracked(),
+        state.default_sink.get_untracked(),
+    ) else {
+        return warn!("gain staging takes an input and an output");
+    };
+    let master = filter_chain::master_name(&sink).to_owned();
+    let current_trim = state.device_settings.with_untracked(|store| {
+        store
+            .devices
+            .get(&master)
+            .and_then(|settings| settings.trim_db)
+            .unwrap_or_default()
+    });
+    let recommendation = create_rw_signal(cx, Recommendation::default());
+
+    let layout = gtk::Box::new(Orientation::Vertical, 18);
+    layout.set_margin_top(12);
+    layout.set_margin_bottom(12);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    let (input, listen, heard) = step(
+        &format!("1. {}", source.description),
+        &tr("Speak, sing or play as loud as you will during the session."),
+        &tr("Listen for 5 seconds"),
+    );
+    layout.append(&input);
+    let (output, play, boosted) = step(
+        &format!("2. {}", sink.description),
+        &tr(
+            "A sweep plays through the output's effects. Turn the volume down first, and \
+             stop anything else playing.",
+        ),
+        &tr("Play test sweep"),
+    );
+    layout.append(&output);
+
+    listen.connect_clicked({
+        let source = source.clone();
+        move |listen| {
+            listen.set_sensitive(false);
+            heard.set_text(&tr("Listening…"));
+            let (button, result) = (listen.clone(), heard.clone());
+            let current = source.volume_percent();
+            let measured = measure(&source.name, LISTEN, move |peak_db| {
+                button.set_sensitive(true);
+                if peak_db < SILENCE_DB {
+                    result.set_text(&tr("Nothing was heard, is the microphone muted?"));
+                    return recommendation.update(|found| found.input_percent = None);
+                }
+                let percent = input_volume(current, peak_db);
+                info!(peak_db, percent, "microphone measured");
+                result.set_text(&tr_with(
+                    "Loudest peak {peak} dBFS at {current}%, {percent}% brings it to about \
+                     {target} dBFS.",
+                    &[
+                        ("peak", &format!("{peak_db:.1}")),
+                        ("current", &current.to_string()),
+                        ("percent", &percent.to_string()),
+                        ("target", &INPUT_PEAK_DB.to_string()),
+                    ],
+                ));
+                recommendation
+                    .update(|found| found.input_percent = (percent != current).then_some(percent));
+            });
+            if let Err(error) = measured {
+                warn!(?error, "listening to the microphone");
+                heard.set_text(&tr("The microphone couldn't be listened to."));
+                listen.set_sensitive(true);
+            }
+        }
+    });
+
+    let player = Rc::new(RefCell::new(None::<Child>));
+    let stop = {
+        let player = player.clone();
+        move || {
+            if let Some(mut child) = player.take() {
+                child.kill().and_then(|_| child.wait()).ok();
+            }
+        }
+    };
+    play.connect_clicked({
+        let master = master.clone();
+        let stop = stop.clone();
+        move |play| {
+            let child = match play_sweep(&sink.name) {
+                Ok(child) => child,
+                Err(_) => return boosted.set_text(&tr("The sweep couldn't be played.")),
+            };
+            player.replace(Some(child));
+            play.set_sensitive(false);
+            boosted.set_text(&tr("Playing…"));
+            let (button, result, done) = (play.clone(), boosted.clone(), stop.clone());
+            // a little longer, for the sweep's way through the effects
+            let duration = SWEEP + Duration::from_millis(500);
+            let measured = measure(&format!("{master}.monitor"), duration, move |peak_db| {
+                done();
+                button.set_sensitive(true);
+                if peak_db < SILENCE_DB {
+                    result.set_text(&tr("Nothing came out, is the output muted?"));
+                    return;
+                }
+                let gain_db = peak_db - SWEEP_DB;
+                let trim_db = trim(current_trim, gain_db);
+                info!(gain_db, trim_db, "output measured");
+                let limit_db = (gain_db > 0.).then_some(LIMIT_DB);
+                result.set_text(&match limit_db {
+                    Some(_) => tr_with(
+                        "The effects boost by up to {gain} dB, a trim of {trim} dB makes up \
+                         for it, and a limiter at {limit} dBFS catches the rest.",
+                        &[
+                            ("gain", &format!("{gain_db:+.1}")),
+                            ("trim", &format!("{trim_db:+}")),
+                            ("limit", &LIMIT_DB.to_string()),
+                        ],
+                    ),
+                    None => tr_with(
+                        "The effects don't boost anything, a trim of {trim} dB plays them at \
+                         full level.",
+                        &[("trim", &format!("{trim_db:+}"))],
+                    ),
+                });
+                recommendation.update(|found| {
+                    found.trim_db = (trim_db != current_trim).then_some(trim_db);
+                    found.limit_db = limit_db;
+                });
+            });
+            if let Err(error) = measured {
+                warn!(?error, "listening to the output");
+                boosted.set_text(&tr("The output couldn't be listened to."));
+                play.set_sensitive(true);
+                stop();
+            }
+        }
+    });
+
+    let apply = gtk::Button::with_label(&tr("Apply recommendations"));
+    apply.set_halign(gtk::Align::End);
+    layout.append(&apply);
+    create_effect(cx, {
+        let apply = apply.clone();
+        move |_| apply.set_sensitive(recommendation.get() != Recommendation::default())
+    });
+    apply.connect_clicked(move |_| {
+        let found = recommendation.get_untracked();
+        if let Some(percent) = found.input_percent {
+            dispatcher.dispatch(Action::SetVolume(
+                DeviceKind::Source,
+                source.name.clone(),
+                percent,
+            ));
+        }
+        if let Some(trim_db) = found.trim_db {
+            dispatcher.dispatch(Action::SetTrim {
+                device: master.clone(),
+                trim_db: (trim_db != 0).then_some(trim_db),
+            });
+        }
+        if let Some(limit_db) = found.limit_db {
+            dispatcher.dispatch(Action::SetLimiter {
+                device: master.clone(),
+                limit_db: Some(limit_db),
+            });
+        }
+        recommendation.set(Recommendation::default());
+    });
+
+    let window = gtk::Window::builder()
+        .application(app)
+        .title(tr("Gain staging"))
+        .default_width(420)
+        .child(&layout)
+        .build();
+    window.connect_close_request(move |_| {
+        stop();
+        glib::signal::Inhibit(false)
+    });
+    window.present();
+}
@@ -0,0 +1,141 @@
+//! Game mode: while a fullscreen window has focus, the graph runs at a small
+//! quantum for low latency and without the filter chains that add to it. All of
+//! it is put back once the window is gone.
+use std::process::Command;
+
+use eyre::Result;
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    checked_output,
+    compat::{self, Feature},
+    devices::{Device, DeviceKind},
+    errors::OrReport,
+    presets, run_change,
+    state::AppState,
+};
+
+/// Frames per cycle while gaming, about 1.3ms at 48kHz.
+pub const QUANTUM: u32 = 64;
+
+const FORCE_QUANTUM: &str = "clock.force-quantum";
+
+fn pw_metadata(args: &[&str]) -> Result<String> {
+    // with a value after the key it sets the key
+    let run = match args.len() {
+        1 => checked_output,
+        _ => run_change,
+    };
+    let stdout = run(Command::new("pw-metadata")
+        .args(["-n", "settings", "0"])
+        .args(args))?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// A number in the graph's settings, 0 when unset.
+pub(crate) fn setting(key: &str) -> Result<u32> {
+    // `update: id:0 key:'clock.force-quantum' value:'256' type:''`
+    Ok(pw_metadata(&[key])?
+        .lines()
+        .find_map(|line| {
+            line.split_once("value:'")?
+                .1
+                .split_once('\'')?
+                .0
+                .parse()
+                .ok()
+        })
+        .unwrap_or(0))
+}
+
+/// The quantum the graph is forced to, 0 when it picks its own.
+pub fn force_quantum() -> Result<u32> {
+    setting(FORCE_QUANTUM)
+}
+
+/// Sets a number in the graph's settings, 0 unsets it.
+pub(crate) fn set_setting(key: &str, value: u32) -> Result<()> {
+    compat::require(Feature::GraphSettings)?;
+    pw_metadata(&[key, &value.to_string()]).map(drop)
+}
+
+#[instrument(ret, err)]
+pub fn set_force_quantum(quantum: u32) -> Result<()> {
+    set_setting(FORCE_QUANTUM, quantum)
+}
+
+/// What game mode changed, to undo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Saved {
+    quantum: u32,
+    night_mode: bool,
+}
+
+/// Puts the presets of every connected sink on or off, as the user picked them.
+pub(crate) fn bypass_presets(state: AppState, bypass: bool) {
+    let sinks = Device::list(DeviceKind::Sink)
+        .map_err(|error| warn!(?error, "listing sinks for game mode"))
+        .unwrap_or_default();
+    state.device_settings.with_untracked(|store| {
+        for sink in &sinks {
+            let Some(settings) = store.get(sink) else {
+                continue;
+            };
+            let preset = settings.preset_for(sink.active_port.as_deref());
+            let equalizer = settings.equalizer();
+            // bit-perfect playback keeps them off
+            if settings.bit_perfect
+                || (preset.is_none()
+                    && equalizer.is_none()
+                    && settings.trim_db.is_none()
+                    && settings.limit_db.is_none())
+            {
+                continue;
+            }
+            let (preset, equalizer, trim_db, limit_db) = match bypass {
+                true => (None, None, None, None),
+                false => (preset, equalizer, settings.trim_db, settings.limit_db),
+            };
+            state.filters.with_value(|host| {
+                let host = &mut *host.lock();
+                presets::apply(host, sink, preset, equalizer, trim_db, limit_db)
+                    .or_report(state, "switching presets for game mode");
+            });
+        }
+    });
+}
+
+/// Switches game mode on and off with the focused window going fullscreen and back.
+pub fn spawn(cx: Scope, state: AppState) {
+    let active = create_memo(cx, move |_| {
+        state.fullscreen.get() && state.device_settings.with(|store| store.game_mode)
+    });
+    create_effect(cx, move |saved: Option<Option<Saved>>| {
+        match (active.get(), saved.flatten()) {
+            (true, None) => {
+                info!("game mode on");
+                let saved = Saved {
+                    quantum: force_quantum()
+                        .map_err(|error| warn!(?error, "reading the quantum"))
+                        .unwrap_or(0),
+                    night_mode: state.night_mode.get_untracked(),
+                };
+                set_force_quantum(QUANTUM).or_report(state, "lowering the latency");
+                state.night_mode.set(false);
+                bypass_presets(state, true);
+                Some(saved)
+            }
+            (false, Some(saved)) => {
+                info!("game mode off");
+                set_force_quantum(saved.quantum).or_report(state, "restoring the latency");
+                if saved.night_mode {
+                    state.night_mode.set(true);
+                }
+                bypass_presets(state, false);
+                None
+            }
+            (_, saved) => saved,
+        }
+    });
+}
@@ -0,0 +1,237 @@
+//! A grace period for outputs that drop out, like Bluetooth headsets losing the
+//! connection for a moment. A device that went away stays listed, greyed out, for
+//! a while, and if it's back before then it gets its volume and the streams that
+//! played on it back.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    events::{self, EventKind, Facility},
+    extensions::watch,
+    filter_chain,
+    hold::Hold,
+    i18n::{tr, tr_with},
+    state::AppState,
+    streams::Stream,
+};
+
+/// Grace period until the user picks one, in seconds.
+pub const DEFAULT_GRACE_SECS: u64 = 30;
+/// Longest grace period offered, in seconds.
+pub const MAX_GRACE_SECS: u64 = 600;
+
+/// An output that went away within the grace period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Departed {
+    /// As it was last seen, volume included.
+    pub device: Device,
+    pub at: Instant,
+    /// Streams that played on it.
+    pub streams: Vec<u32>,
+}
+
+fn grace_period(state: AppState) -> Duration {
+    Duration::from_secs(
+        state
+            .device_settings
+            .with_untracked(|store| store.grace_period_secs)
+            .unwrap_or(DEFAULT_GRACE_SECS),
+    )
+}
+
+fn list_sinks() -> Vec<Device> {
+    Device::list(DeviceKind::Sink)
+        .map_err(|error| warn!(?error, "listing sinks for the grace period"))
+        .unwrap_or_default()
+}
+
+/// Where each stream plays, and where it played before that, since the server may
+/// move streams off a device before telling it's gone.
+fn follow_streams(streams: &mut HashMap<u32, (u32, Option<u32>)>) {
+    let listed = match Stream::list() {
+        Ok(listed) => listed,
+        Err(error) => return warn!(?error, "listing streams for the grace period"),
+    };
+    *streams = listed
+        .into_iter()
+        .map(|stream| {
+            let before =
+                streams
+                    .get(&stream.index)
+                    .and_then(|(sink, before)| match *sink == stream.sink {
+                        true => *before,
+                        false => Some(*sink),
+                    });
+            (stream.index, (stream.sink, before))
+        })
+        .collect();
+}
+
+fn depart(state: AppState, device: Device, streams: &HashMap<u32, (u32, Option<u32>)>) {
+    let grace = grace_period(state);
+    let streams = streams
+        .iter()
+        .filter(|(_, (sink, before))| *sink == device.index || *before == Some(device.index))
+        .map(|(stream, _)| *stream)
+        .collect();
+    info!(
+        ?streams,
+        "{} went away, keeping it for {grace:?}", device.name
+    );
+    state.departed.update(|departed| {
+        departed.retain(|other| other.device.name != device.name);
+        departed.push(Departed {
+            device,
+            at: Instant::now(),
+            streams,
+        });
+    });
+    glib::timeout_add_local_once(grace, move || {
+        state.departed.update(|departed| {
+            departed.retain(|departed| departed.at.elapsed() < grace);
+        });
+    });
+}
+
+/// The actions giving `device`, back within the grace period, what it had.
+fn restore(
+    departed: &Departed,
+    device: &Device,
+    streams: &HashMap<u32, (u32, Option<u32>)>,
+) -> Vec<Action> {
+    let mut actions = vec![Action::SetVolume(
+        DeviceKind::Sink,
+        device.name.clone(),
+        departed.device.volume_percent(),
+    )];
+    if departed.device.mute != device.mute {
+        actions.push(Action::SetMute(
+            DeviceKind::Sink,
+            device.name.clone(),
+            departed.device.mute,
+        ));
+    }
+    actions.extend(
+        departed
+            .streams
+            .iter()
+            .filter(|stream| streams.contains_key(stream))
+            .map(|stream| Action::MoveStream {
+                stream: *stream,
+                sink: device.name.clone(),
+            }),
+    );
+    actions
+}
+
+/// Keeps outputs that went away in [`AppState::departed`] for the grace period,
+/// restoring them when they come back in time.
+pub fn spawn(state: AppState, dispatcher: Dispatcher) {
+    let mut sinks = list_sinks();
+    let mut streams = HashMap::new();
+    follow_streams(&mut streams);
+    events::subscribe(move |event| match (event.kind, event.facility) {
+        (EventKind::Remove, Facility::Sink) => {
+            let gone = sinks
+                .iter()
+                .find(|sink| Some(sink.index) == event.index)
+                // pipeweld's own filters come and go with their settings
+                .filter(|sink| filter_chain::master_of(sink).is_none())
+                .cloned();
+            if let Some(device) = gone.filter(|_| !grace_period(state).is_zero()) {
+                depart(state, device, &streams);
+            }
+            sinks = list_sinks();
+        }
+        (EventKind::New, Facility::Sink) => {
+            sinks = list_sinks();
+            let Some(device) = sinks.iter().find(|sink| Some(sink.index) == event.index) else {
+                return;
+            };
+            let departed = state.departed.with_untracked(|departed| {
+                departed
+                    .iter()
+                    .find(|departed| departed.device.name == device.name)
+                    .cloned()
+            });
+            let Some(departed) = departed else {
+                return;
+            };
+            info!("{} is back, restoring it", device.name);
+            state.departed.update(|departed| {
+                departed.retain(|departed| departed.device.name != device.name);
+            });
+            follow_streams(&mut streams);
+            // the streams stay where they are while the routing is held
+            let held = state.hold.with_untracked(Hold::is_on);
+            for action in restore(&departed, device, &streams) {
+                if !(held && matches!(action, Action::MoveStream { .. })) {
+                    dispatcher.dispatch(action);
+                }
+            }
+        }
+        (_, Facility::Sink) => sinks = list_sinks(),
+        (_, Facility::SinkInput) => follow_streams(&mut streams),
+        _ => {}
+    });
+}
+
+/// Greyed out rows for the outputs within their grace period, hidden while there
+/// are none.
+pub fn departed_list(cx: Scope, state: AppState) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Vertical, 2);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    layout.set_margin_bottom(12);
+    watch(cx, &layout, move |layout| {
+        while let Some(row) = layout.first_child() {
+            layout.remove(&row);
+        }
+        state.departed.with(|departed| {
+            layout.set_visible(!departed.is_empty());
+            for departed in departed {
+                let label = gtk::Label::new(Some(&tr_with(
+                    "{device}: disconnected, restored if it's back soon",
+                    &[("device", &departed.device.description)],
+                )));
+                label.set_halign(gtk::Align::Start);
+                label.set_sensitive(false);
+                layout.append(&label);
+            }
+        });
+    });
+    layout
+}
+
+/// Expander setting the grace period.
+pub fn grace_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Horizontal, 6);
+    layout.append(&gtk::Label::new(Some(&tr(
+        "Keep disconnected outputs for (s)",
+    ))));
+    let seconds = gtk::SpinButton::with_range(0., MAX_GRACE_SECS as f64, 5.);
+    seconds.set_hexpand(true);
+    seconds.set_tooltip_text(Some(&tr("0 forgets them right away")));
+    seconds.set_value(grace_period(state).as_secs_f64());
+    layout.append(&seconds);
+    let apply = gtk::Button::with_label(&tr("Apply"));
+    apply.connect_clicked(move |_| {
+        dispatcher.dispatch(Action::SetGracePeriod(seconds.value() as u64));
+    });
+    layout.append(&apply);
+    gtk::Expander::builder()
+        .label("Disconnected outputs")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,182 @@
+//! The whole audio graph as JSON: every node with its ports, every link by the names
+//! of its ends, and the routing a preset would save, see [`crate::routing`]. It's
+//! for keeping a studio's wiring in a file, or attaching to a bug report:
+//!
+//! ```sh
+//! pipeweld graph export studio.json
+//! pipeweld graph import studio.json
+//! ```
+//!
+//! Importing puts the routing back like applying a preset does, recreating the
+//! links that are missing between what's around. Nodes and the links of streams are
+//! there for reading, as the server and session manager make those themselves.
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use eyre::{bail, Result, WrapErr};
+use gtk::{prelude::*, Orientation};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    patchbay::{Direction, Graph},
+    routing::{self, NamedLink, Snapshot},
+};
+
+/// Version of the file layout, bumped when older pipeweld couldn't read it.
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeEntry {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_class: Option<String>,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphFile {
+    pub version: u32,
+    #[serde(default)]
+    pub nodes: Vec<NodeEntry>,
+    /// All links, those of streams too.
+    #[serde(default)]
+    pub links: Vec<NamedLink>,
+    /// What importing puts back.
+    #[serde(default)]
+    pub routing: Snapshot,
+}
+
+impl GraphFile {
+    /// The graph as it is now.
+    pub fn take() -> Result<Self> {
+        let graph = Graph::dump()?;
+        let nodes = graph
+            .nodes
+            .iter()
+            .map(|node| {
+                let ports = |direction| {
+                    graph
+                        .ports
+                        .iter()
+                        .filter(|port| port.node == node.id && port.direction == direction)
+                        .map(|port| port.name.clone())
+                        .collect()
+                };
+                NodeEntry {
+                    name: node.name.clone(),
+                    description: node.description.clone(),
+                    media_class: node.media_class.clone(),
+                    inputs: ports(Direction::In),
+                    outputs: ports(Direction::Out),
+                }
+            })
+            .collect();
+        let mut links = graph
+            .ports
+            .iter()
+            .filter(|port| port.direction == Direction::Out)
+            .flat_map(|port| graph.peers(port).map(move |peer| (port, peer)))
+            .filter_map(|(port, peer)| {
+                Some(NamedLink {
+                    output: routing::port_name(&graph, port)?,
+                    input: routing::port_name(&graph, peer)?,
+                })
+            })
+            .collect::<Vec<_>>();
+        links.sort();
+        links.dedup();
+        Ok(Self {
+            version: VERSION,
+            nodes,
+            links,
+            routing: Snapshot::take()?,
+        })
+    }
+
+    #[instrument(skip(self), err)]
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self).wrap_err("serializing the graph")?;
+        std::fs::write(path, contents + "\n")
+            .wrap_err_with(|| format!("writing {}", path.display()))
+    }
+
+    #[instrument(err)]
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("reading {}", path.display()))?;
+        let file: Self = serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("parsing {}", path.display()))?;
+        if file.version > VERSION {
+            bail!(
+                "{} was exported by a newer pipeweld, graph version {}",
+                path.display(),
+                file.version
+            );
+        }
+        Ok(file)
+    }
+
+    /// Puts the routing back, see [`Snapshot::apply`].
+    pub fn apply(&self) -> Result<()> {
+        self.routing.apply()
+    }
+}
+
+/// Buttons exporting the graph to a file and importing one.
+pub fn graph_buttons(dispatcher: Dispatcher) -> gtk::Box {
+    let buttons = gtk::Box::new(Orientation::Horizontal, 6);
+    // GTK doesn't keep native dialogs alive by itself
+    let open = Rc::new(RefCell::new(None::<gtk::FileChooserNative>));
+    for (label, title, save) in [
+        ("Export graph as JSON…", "Export audio graph", true),
+        ("Import graph…", "Import audio graph", false),
+    ] {
+        let button = gtk::Button::with_label(label);
+        let dispatcher = dispatcher.clone();
+        let open = open.clone();
+        button.connect_clicked(move |button| {
+            let parent = button.root().and_downcast::<gtk::Window>();
+            let dialog = gtk::FileChooserNative::new(
+                Some(title),
+                parent.as_ref(),
+                match save {
+                    true => gtk::FileChooserAction::Save,
+                    false => gtk::FileChooserAction::Open,
+                },
+                Some(match save {
+                    true => "Export",
+                    false => "Import",
+                }),
+                Some("Cancel"),
+            );
+            let filter = gtk::FileFilter::new();
+            filter.set_name(Some("JSON"));
+            filter.add_pattern("*.json");
+            dialog.add_filter(&filter);
+            if save {
+                dialog.set_current_name(&format!("{}-graph.json", clap::crate_name!()));
+            }
+            let dispatcher = dispatcher.clone();
+            dialog.connect_response(move |dialog, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                        dispatcher.dispatch(match save {
+                            true => Action::ExportGraph(path),
+                            false => Action::ImportGraph(path),
+                        });
+                    }
+                }
+            });
+            dialog.show();
+            open.replace(Some(dialog));
+        });
+        buttons.append(&button);
+    }
+    buttons
+}
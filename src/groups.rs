@@ -0,0 +1,160 @@
+//! Linked volume groups: several sinks, e.g. a pair of mono speakers, under one
+//! slider. Moving it scales every member by the same factor, so their balance stays.
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    device_settings::DeviceStore,
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    state::AppState,
+    AudioControls,
+};
+
+/// The group's level, that of its loudest member.
+pub fn level(members: &[String], sinks: &[Device]) -> u32 {
+    sinks
+        .iter()
+        .filter(|sink| members.contains(&sink.name))
+        .map(Device::volume_percent)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Brings the loudest member to `percent` and the others along proportionally,
+/// each within its own volume limit.
+#[instrument(skip(store), ret, err)]
+pub fn set_volume(store: &DeviceStore, members: &[String], percent: u32) -> Result<()> {
+    let sinks = Device::list(DeviceKind::Sink)?;
+    let loudest = level(members, &sinks);
+    sinks
+        .iter()
+        .filter(|sink| members.contains(&sink.name))
+        .try_for_each(|sink| {
+            let target = match loudest {
+                // nothing to scale from, line them all up
+                0 => percent,
+                loudest => (sink.volume_percent() * percent + loudest / 2) / loudest,
+            };
+            AudioControls::set_volume_percent(
+                DeviceKind::Sink,
+                &sink.name,
+                store.limit_volume(sink, target),
+            )
+        })
+}
+
+fn describe(sinks: &[Device], name: &str) -> String {
+    sinks
+        .iter()
+        .find(|sink| sink.name == name)
+        .map_or_else(|| name.to_owned(), |sink| sink.description.clone())
+}
+
+/// Expander with a master slider per group and a form for making new ones.
+pub fn group_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&rows);
+
+    let sinks = Device::list(DeviceKind::Sink)
+        .map_err(|error| warn!(?error, "listing sinks to group"))
+        .unwrap_or_default();
+    let form = gtk::Box::new(Orientation::Vertical, 4);
+    let name = gtk::Entry::builder()
+        .placeholder_text(tr("Group name"))
+        .build();
+    form.append(&name);
+    let choices = sinks
+        .iter()
+        .map(|sink| {
+            let choice = gtk::CheckButton::with_label(&sink.description);
+            form.append(&choice);
+            (sink.name.clone(), choice)
+        })
+        .collect::<Vec<_>>();
+    let create = gtk::Button::with_label(&tr("Create group"));
+    create.set_halign(gtk::Align::Start);
+    form.append(&create);
+    layout.append(&form);
+    create.connect_clicked({
+        let dispatcher = dispatcher.clone();
+        move |_| {
+            let members = choices
+                .iter()
+                .filter(|(_, choice)| choice.is_active())
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            let group = name.text().trim().to_owned();
+            if group.is_empty() || members.len() < 2 {
+                return warn!("a group needs a name and at least two outputs");
+            }
+            choices
+                .iter()
+                .for_each(|(_, choice)| choice.set_active(false));
+            name.set_text("");
+            dispatcher.dispatch(Action::CreateGroup { group, members });
+        }
+    });
+
+    // only a change to the groups themselves rebuilds the sliders, not to other settings
+    let groups = create_memo(cx, move |_| {
+        state.device_settings.with(|store| store.groups.clone())
+    });
+    create_effect(cx, move |_| {
+        let groups = groups.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        for (group, members) in groups {
+            let row = gtk::Box::new(Orientation::Vertical, 2);
+            let title = gtk::Box::new(Orientation::Horizontal, 6);
+            let label = gtk::Label::new(Some(&format!(
+                "{group}: {}",
+                members
+                    .iter()
+                    .map(|member| describe(&sinks, member))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+            label.set_hexpand(true);
+            label.set_halign(gtk::Align::Start);
+            title.append(&label);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            remove.connect_clicked({
+                let dispatcher = dispatcher.clone();
+                let group = group.clone();
+                move |_| dispatcher.dispatch(Action::RemoveGroup(group.clone()))
+            });
+            title.append(&remove);
+            row.append(&title);
+            let volume = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+            let current = Device::list(DeviceKind::Sink)
+                .map(|sinks| level(&members, &sinks))
+                .unwrap_or_default();
+            volume.set_value(current.into());
+            volume.connect_value_changed({
+                let dispatcher = dispatcher.clone();
+                move |volume| {
+                    dispatcher.dispatch(Action::SetGroupVolume {
+                        group: group.clone(),
+                        percent: volume.value() as u32,
+                    })
+                }
+            });
+            row.append(&volume);
+            rows.append(&row);
+        }
+    });
+
+    gtk::Expander::builder()
+        .label("Volume groups")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,306 @@
+//! Buttons of Bluetooth headsets, mapped to pipeweld's actions. BlueZ hands the
+//! play/pause, next and previous buttons to MPRIS players through `mpris-proxy`, so
+//! while any button is mapped pipeweld poses as a player and turns presses into
+//! actions. The volume buttons already reach the sink through the Bluetooth
+//! transport, that's the server's to handle.
+use std::sync::{Arc, Mutex};
+
+use gtk::{
+    gio,
+    glib::{ToVariant, Variant},
+    prelude::*,
+    Orientation,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::DeviceKind,
+    state::AppState,
+    DiffValue,
+};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.pipeweld";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+const INTROSPECTION: &str = r#"
+<node>
+  <interface name="org.mpris.MediaPlayer2">
+    <method name="Raise"/>
+    <method name="Quit"/>
+    <property name="CanQuit" type="b" access="read"/>
+    <property name="CanRaise" type="b" access="read"/>
+    <property name="HasTrackList" type="b" access="read"/>
+    <property name="Identity" type="s" access="read"/>
+    <property name="SupportedUriSchemes" type="as" access="read"/>
+    <property name="SupportedMimeTypes" type="as" access="read"/>
+  </interface>
+  <interface name="org.mpris.MediaPlayer2.Player">
+    <method name="Next"/>
+    <method name="Previous"/>
+    <method name="Pause"/>
+    <method name="PlayPause"/>
+    <method name="Stop"/>
+    <method name="Play"/>
+    <property name="PlaybackStatus" type="s" access="read"/>
+    <property name="CanGoNext" type="b" access="read"/>
+    <property name="CanGoPrevious" type="b" access="read"/>
+    <property name="CanPlay" type="b" access="read"/>
+    <property name="CanPause" type="b" access="read"/>
+    <property name="CanControl" type="b" access="read"/>
+  </interface>
+</node>
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Button {
+    /// Headsets send play, pause or both from the same button.
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+}
+
+impl Button {
+    pub const ALL: [Self; 4] = [Self::PlayPause, Self::Next, Self::Previous, Self::Stop];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::PlayPause => "Play/pause",
+            Self::Next => "Next",
+            Self::Previous => "Previous",
+            Self::Stop => "Stop",
+        }
+    }
+
+    fn of_method(method: &str) -> Option<Self> {
+        match method {
+            "PlayPause" | "Play" | "Pause" => Some(Self::PlayPause),
+            "Next" => Some(Self::Next),
+            "Previous" => Some(Self::Previous),
+            "Stop" => Some(Self::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// What a button can be mapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ButtonAction {
+    ToggleMute,
+    ToggleMicrophoneMute,
+    VolumeUp,
+    VolumeDown,
+    NextOutput,
+    ToggleNightMode,
+    ToggleDoNotDisturb,
+}
+
+impl ButtonAction {
+    pub const ALL: [Self; 7] = [
+        Self::ToggleMute,
+        Self::ToggleMicrophoneMute,
+        Self::VolumeUp,
+        Self::VolumeDown,
+        Self::NextOutput,
+        Self::ToggleNightMode,
+        Self::ToggleDoNotDisturb,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ToggleMute => "Mute the output",
+            Self::ToggleMicrophoneMute => "Mute the microphone",
+            Self::VolumeUp => "Volume up",
+            Self::VolumeDown => "Volume down",
+            Self::NextOutput => "Next output",
+            Self::ToggleNightMode => "Night mode",
+            Self::ToggleDoNotDisturb => "Do not disturb",
+        }
+    }
+
+    pub fn action(self) -> Action {
+        match self {
+            Self::ToggleMute => Action::ToggleMute(DeviceKind::Sink),
+            Self::ToggleMicrophoneMute => Action::ToggleMute(DeviceKind::Source),
+            Self::VolumeUp => Action::ChangeVolume(DiffValue(5)),
+            Self::VolumeDown => Action::ChangeVolume(DiffValue(-5)),
+            Self::NextOutput => Action::CycleDefault(DeviceKind::Sink),
+            Self::ToggleNightMode => Action::ToggleNightMode,
+            Self::ToggleDoNotDisturb => Action::ToggleDoNotDisturb,
+        }
+    }
+}
+
+fn property(interface: &str, property: &str) -> Variant {
+    match (interface, property) {
+        (ROOT_INTERFACE, "Identity") => clap::crate_name!().to_variant(),
+        (ROOT_INTERFACE, "SupportedUriSchemes" | "SupportedMimeTypes") => {
+            Vec::<String>::new().to_variant()
+        }
+        (PLAYER_INTERFACE, "PlaybackStatus") => "Stopped".to_variant(),
+        (PLAYER_INTERFACE, _) => true.to_variant(),
+        _ => false.to_variant(),
+    }
+}
+
+/// The player while it's on the bus, to take off again.
+#[derive(Default)]
+struct Player {
+    owner: Option<gio::OwnerId>,
+    objects: Vec<(gio::DBusConnection, gio::RegistrationId)>,
+}
+
+impl Player {
+    fn remove(&mut self) {
+        for (connection, object) in self.objects.drain(..) {
+            connection.unregister_object(object).ok();
+        }
+        if let Some(owner) = self.owner.take() {
+            gio::bus_unown_name(owner);
+        }
+    }
+}
+
+fn register(
+    connection: &gio::DBusConnection,
+    state: AppState,
+    dispatcher: Dispatcher,
+) -> Vec<gio::RegistrationId> {
+    let node = match gio::DBusNodeInfo::for_xml(INTROSPECTION) {
+        Ok(node) => node,
+        Err(error) => {
+            warn!(%error, "parsing MPRIS introspection");
+            return Vec::new();
+        }
+    };
+    let mut objects = Vec::new();
+    for name in [ROOT_INTERFACE, PLAYER_INTERFACE] {
+        let interface = node.lookup_interface(name).expect("interface is declared");
+        let dispatcher = dispatcher.clone();
+        connection
+            .register_object(
+                PATH,
+                &interface,
+                move |_, _, _, _, method, _, invocation| {
+                    let mapped = Button::of_method(method).and_then(|button| {
+                        state
+                            .device_settings
+                            .with_untracked(|store| store.headset_buttons.get(&button).copied())
+                    });
+                    if let Some(mapped) = mapped {
+                        info!(method, ?mapped, "headset button");
+                        dispatcher.dispatch(mapped.action());
+                    }
+                    invocation.return_value(None);
+                },
+                |_, _, _, interface, name| property(interface, name),
+                |_, _, _, _, _, _| false,
+            )
+            .map_err(|error| warn!(%error, name, "exporting MPRIS interface"))
+            .map(|object| objects.push(object))
+            .ok();
+    }
+    objects
+}
+
+/// Poses as an MPRIS player while any headset button is mapped, so other players
+/// keep the buttons otherwise.
+pub fn spawn(cx: Scope, state: AppState, dispatcher: Dispatcher) {
+    let mapped = create_memo(cx, move |_| {
+        state
+            .device_settings
+            .with(|store| !store.headset_buttons.is_empty())
+    });
+    let player = Arc::new(Mutex::new(Player::default()));
+    create_effect(cx, move |_| {
+        player
+            .lock()
+            .expect("headset player lock poisoned")
+            .remove();
+        if !mapped.get() {
+            return;
+        }
+        let dispatcher = dispatcher.clone();
+        let registered = player.clone();
+        let owner = gio::bus_own_name(
+            gio::BusType::Session,
+            BUS_NAME,
+            gio::BusNameOwnerFlags::NONE,
+            move |connection, _| {
+                let objects = register(&connection, state, dispatcher.clone());
+                registered
+                    .lock()
+                    .expect("headset player lock poisoned")
+                    .objects
+                    .extend(
+                        objects
+                            .into_iter()
+                            .map(|object| (connection.clone(), object)),
+                    );
+            },
+            |_, name| info!(name, "taking headset buttons"),
+            |_, name| warn!(name, "lost MPRIS bus name, headset buttons won't be mapped"),
+        );
+        player.lock().expect("headset player lock poisoned").owner = Some(owner);
+    });
+}
+
+/// Expander mapping each headset button to an action, or to nothing.
+pub fn button_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let hint = gtk::Label::new(Some(
+        "Needs BlueZ's mpris-proxy running. Mapped buttons no longer reach media players.",
+    ));
+    hint.set_wrap(true);
+    hint.set_max_width_chars(60);
+    hint.add_css_class("dim-label");
+    layout.append(&hint);
+    let choices = std::iter::once("Not mapped")
+        .chain(ButtonAction::ALL.iter().map(|action| action.label()))
+        .collect::<Vec<_>>();
+    for button in Button::ALL {
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        let label = gtk::Label::new(Some(button.label()));
+        label.set_hexpand(true);
+        label.set_halign(gtk::Align::Start);
+        row.append(&label);
+        let picker = gtk::DropDown::from_strings(&choices);
+        let mapped = move || {
+            state
+                .device_settings
+                .with_untracked(|store| store.headset_buttons.get(&button).copied())
+        };
+        let position = |action: Option<ButtonAction>| {
+            action
+                .and_then(|action| ButtonAction::ALL.iter().position(|other| *other == action))
+                .map_or(0, |position| position as u32 + 1)
+        };
+        picker.set_selected(position(mapped()));
+        let dispatcher = dispatcher.clone();
+        picker.connect_selected_notify(move |picker| {
+            let action = picker
+                .selected()
+                .checked_sub(1)
+                .and_then(|index| ButtonAction::ALL.get(index as usize).copied());
+            if action != mapped() {
+                dispatcher.dispatch(Action::MapHeadsetButton { button, action });
+            }
+        });
+        row.append(&picker);
+        layout.append(&row);
+    }
+    gtk::Expander::builder()
+        .label("Headset buttons")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,256 @@
+//! Checks of what pipeweld relies on, run once as the window opens: a sound server
+//! answering, PipeWire's session manager, realtime scheduling for PipeWire, and
+//! layer surfaces for `--panel` on compositors it could dock to. What's wrong shows
+//! in a bar atop the window saying what to do about it, with a way to the panel or
+//! page that helps, until the bar is dismissed.
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, warn};
+
+use crate::{
+    compat, compositor,
+    diagnostics::{self, RtStatus},
+    extensions::watch,
+    i18n::{tr, tr_with},
+    panel, theme, ui_state,
+};
+
+/// Groups distributions grant realtime limits to.
+const REALTIME_GROUPS: [&str; 3] = ["realtime", "pipewire", "audio"];
+
+/// Where to go about a problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    /// One of the window's panels, by its label.
+    Panel(&'static str),
+    Uri(&'static str),
+}
+
+/// Something found wrong, in English until shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Problem {
+    pub summary: &'static str,
+    pub remedy: &'static str,
+    pub link: Option<Link>,
+}
+
+/// Whether this session is in one of [`REALTIME_GROUPS`], and whether `/etc/group`
+/// lists the user in one the session isn't in yet, as until logging in again.
+fn realtime_groups() -> (bool, bool) {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let session = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let user = glib::user_name().to_string_lossy().into_owned();
+    let groups = std::fs::read_to_string("/etc/group").unwrap_or_default();
+    let (mut in_session, mut listed) = (false, false);
+    for line in groups.lines() {
+        let fields = line.split(':').collect::<Vec<_>>();
+        let [name, _, id, members] = fields[..] else {
+            continue;
+        };
+        if !REALTIME_GROUPS.contains(&name) {
+            continue;
+        }
+        in_session |= session.contains(&id);
+        listed |= members.split(',').any(|member| member == user);
+    }
+    (in_session, listed && !in_session)
+}
+
+fn server(problems: &mut Vec<Problem>) {
+    let versions = compat::get();
+    let pipewire = diagnostics::is_running("pipewire");
+    if versions.pulse_server.is_none() {
+        problems.push(Problem {
+            summary: "No sound server answers",
+            remedy: match pipewire {
+                true => {
+                    "PipeWire runs, but its pulse server doesn't: systemctl --user enable --now \
+                     pipewire-pulse."
+                }
+                false => {
+                    "Start PipeWire: systemctl --user enable --now pipewire pipewire-pulse \
+                     wireplumber."
+                }
+            },
+            link: None,
+        });
+    }
+    let on_pipewire = pipewire
+        || versions
+            .pulse_server
+            .as_deref()
+            .is_some_and(|server| server.contains("PipeWire"));
+    if !on_pipewire {
+        return;
+    }
+    let wireplumber = diagnostics::is_running("wireplumber");
+    let media_session = diagnostics::is_running("pipewire-media-session");
+    let (summary, remedy) = match (wireplumber, media_session) {
+        (true, false) => return,
+        (true, true) => (
+            "Two session managers are running",
+            "WirePlumber and pipewire-media-session fight over the devices, stop the \
+             latter: systemctl --user disable --now pipewire-media-session.",
+        ),
+        (false, true) => (
+            "pipewire-media-session is no longer maintained",
+            "Switch to WirePlumber: systemctl --user disable --now pipewire-media-session, \
+             then systemctl --user enable --now wireplumber.",
+        ),
+        (false, false) => (
+            "No session manager is running",
+            "Without one, devices don't show up and nothing gets linked: systemctl --user \
+             enable --now wireplumber.",
+        ),
+    };
+    problems.push(Problem {
+        summary,
+        remedy,
+        link: Some(Link::Uri(
+            "https://pipewire.pages.freedesktop.org/wireplumber/",
+        )),
+    });
+}
+
+fn realtime(problems: &mut Vec<Problem>) {
+    let status = RtStatus::check();
+    if status.pipewire_realtime != Some(false) {
+        return;
+    }
+    let (in_group, pending) = realtime_groups();
+    problems.push(Problem {
+        summary: "PipeWire runs without realtime scheduling, expect crackling under load",
+        remedy: match (pending, in_group || status.rtkit.is_some()) {
+            (true, _) => "You were added to a realtime group since logging in, log in again.",
+            (false, true) => "Realtime is allowed, but PipeWire doesn't ask for it.",
+            (false, false) => {
+                "Neither RealtimeKit runs nor are you in the realtime or pipewire group."
+            }
+        },
+        link: Some(Link::Panel("Realtime scheduling")),
+    });
+}
+
+/// Runs the checks off the main thread, save for layer surfaces, which GTK answers.
+fn check(found: impl FnOnce(Vec<Problem>) + 'static) {
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let mut found = Some(found);
+    receiver.attach(
+        None,
+        move |(mut problems, docking): (Vec<Problem>, bool)| {
+            if docking && !panel::docks_as_layer() {
+                problems.push(Problem {
+                    summary: "--panel can't dock as a bar here",
+                    remedy: match cfg!(feature = "layer-shell") {
+                        true => "The compositor offers no layer surfaces.",
+                        false => {
+                            "pipeweld was built without the layer-shell feature, the panel \
+                         floats as a window."
+                        }
+                    },
+                    link: Some(Link::Uri("https://github.com/wmww/gtk4-layer-shell")),
+                });
+            }
+            info!(?problems, "checked the environment");
+            if let Some(found) = found.take() {
+                found(problems);
+            }
+            glib::Continue(false)
+        },
+    );
+    std::thread::spawn(move || {
+        let mut problems = Vec::new();
+        server(&mut problems);
+        realtime(&mut problems);
+        // wlroots compositors are where the panel stands in for a desktop's applet
+        let docking =
+            compositor::detect().is_some_and(|system| matches!(system.name(), "sway" | "Hyprland"));
+        if sender.send((problems, docking)).is_err() {
+            warn!("the window closed before the environment was checked");
+        }
+    });
+}
+
+/// Expands and focuses the panel labelled `label` in `window`.
+fn open_panel(window: &gtk::Widget, label: &str) {
+    let name = theme::slug(label);
+    let mut found = Vec::new();
+    ui_state::expanders(window, &mut found);
+    match found.into_iter().find(|panel| panel.widget_name() == name) {
+        Some(panel) => {
+            panel.set_expanded(true);
+            panel.grab_focus();
+        }
+        None => warn!(label, "no such panel to open"),
+    }
+}
+
+fn row(problem: Problem) -> gtk::Box {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    let text = gtk::Box::new(Orientation::Vertical, 2);
+    text.set_hexpand(true);
+    let summary = gtk::Label::new(Some(&tr(problem.summary)));
+    summary.set_wrap(true);
+    summary.set_xalign(0.);
+    text.append(&summary);
+    let remedy = gtk::Label::new(Some(&tr(problem.remedy)));
+    remedy.set_wrap(true);
+    remedy.set_xalign(0.);
+    remedy.set_selectable(true);
+    remedy.add_css_class("caption");
+    text.append(&remedy);
+    row.append(&text);
+    match problem.link {
+        Some(Link::Panel(label)) => {
+            let open = gtk::Button::with_label(&tr_with("Open {panel}", &[("panel", &tr(label))]));
+            open.set_valign(gtk::Align::Center);
+            open.connect_clicked(move |open| {
+                if let Some(window) = open.root() {
+                    open_panel(window.upcast_ref(), label);
+                }
+            });
+            row.append(&open);
+        }
+        Some(Link::Uri(uri)) => {
+            let link = gtk::LinkButton::with_label(uri, &tr("Learn more"));
+            link.set_valign(gtk::Align::Center);
+            row.append(&link);
+        }
+        None => {}
+    }
+    row
+}
+
+/// Bar with what the checks found, hidden while they run, when they find nothing
+/// and once dismissed.
+pub fn health_bar(cx: Scope) -> gtk::InfoBar {
+    let bar = gtk::InfoBar::new();
+    bar.set_message_type(gtk::MessageType::Warning);
+    bar.set_show_close_button(true);
+    bar.set_margin_start(12);
+    bar.set_margin_end(12);
+    bar.set_margin_top(12);
+    bar.set_visible(false);
+    let problems = create_rw_signal(cx, Vec::<Problem>::new());
+    bar.connect_response(move |bar, _| bar.set_visible(false));
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    bar.add_child(&layout);
+    watch(cx, &bar, move |bar| {
+        problems.with(|problems| {
+            while let Some(child) = layout.first_child() {
+                layout.remove(&child);
+            }
+            for problem in problems {
+                layout.append(&row(*problem));
+            }
+            bar.set_visible(!problems.is_empty());
+        })
+    });
+    check(move |found| problems.set(found));
+    bar
+}
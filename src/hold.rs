@@ -0,0 +1,122 @@
+//! Holding the current routing, say during a presentation: for a while, or until
+//! released, the rules that switch devices or profiles on their own leave it
+//! alone, see [`crate::rules::Rule::switches`], and outputs coming back within
+//! their grace period don't get their streams back. Choices the user makes still
+//! go through, as does whatever the session manager does on its own.
+use std::time::Duration;
+
+use eyre::{Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::info;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    extensions::watch,
+    i18n::{marked, tr, tr_with},
+    state::AppState,
+};
+
+/// What holding can last, in minutes, `None` until released.
+pub const DURATIONS: [(&str, Option<u32>); 4] = [
+    (marked("For 30 minutes"), Some(30)),
+    (marked("For an hour"), Some(60)),
+    (marked("For two hours"), Some(120)),
+    (marked("Until released"), None),
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Hold {
+    #[default]
+    Off,
+    UntilReleased,
+    Until(glib::DateTime),
+}
+
+impl Hold {
+    pub fn is_on(&self) -> bool {
+        *self != Self::Off
+    }
+
+    /// Like "Routing held until 15:30", `None` while it isn't.
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            Self::Off => None,
+            Self::UntilReleased => Some(tr("Routing held")),
+            Self::Until(until) => Some(match until.format("%H:%M") {
+                Ok(time) => tr_with("Routing held until {time}", &[("time", &time)]),
+                Err(_) => tr("Routing held"),
+            }),
+        }
+    }
+}
+
+/// Holds the routing for `minutes`, or until released for `None`, replacing any
+/// hold from before.
+pub fn hold(state: AppState, minutes: Option<u32>) -> Result<()> {
+    let Some(minutes) = minutes else {
+        info!("holding the routing until released");
+        state.hold.set(Hold::UntilReleased);
+        return Ok(());
+    };
+    let until = glib::DateTime::now_local()
+        .and_then(|now| now.add_minutes(minutes as i32))
+        .wrap_err("reading the time")?;
+    let hold = Hold::Until(until);
+    info!(?hold, "holding the routing");
+    state.hold.set(hold.clone());
+    glib::timeout_add_local_once(Duration::from_secs(u64::from(minutes) * 60), move || {
+        // unless released or held anew meanwhile
+        if state.hold.with_untracked(|current| *current == hold) {
+            info!("the routing isn't held anymore");
+            state.hold.set(Hold::Off);
+        }
+    });
+    Ok(())
+}
+
+/// Line telling the routing is held and until when, with a button releasing it,
+/// hidden while it isn't.
+pub fn hold_bar(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Horizontal, 6);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    layout.set_margin_top(12);
+    layout.append(&gtk::Image::from_icon_name("changes-prevent-symbolic"));
+    let label = gtk::Label::new(None);
+    label.set_wrap(true);
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    layout.append(&label);
+    let release = gtk::Button::with_label(&tr("Release"));
+    release.connect_clicked(move |_| dispatcher.dispatch(Action::ReleaseRouting));
+    layout.append(&release);
+    watch(cx, &layout, move |layout| {
+        state.hold.with(|hold| {
+            layout.set_visible(hold.is_on());
+            label.set_text(&hold.describe().unwrap_or_default());
+        })
+    });
+    layout
+}
+
+/// A duration picker and a button holding the routing for it.
+pub fn hold_picker(dispatcher: Dispatcher) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(&tr("Hold current routing")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    layout.append(&label);
+    let labels = DURATIONS.map(|(label, _)| tr(label));
+    let duration = gtk::DropDown::from_strings(&labels.each_ref().map(String::as_str));
+    layout.append(&duration);
+    let hold = gtk::Button::with_label(&tr("Hold"));
+    hold.set_tooltip_text(Some(&tr("Keep rules from switching devices or profiles")));
+    hold.connect_clicked(move |_| {
+        if let Some((_, minutes)) = DURATIONS.get(duration.selected() as usize) {
+            dispatcher.dispatch(Action::HoldRouting { minutes: *minutes });
+        }
+    });
+    layout.append(&hold);
+    layout
+}
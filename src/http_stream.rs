@@ -0,0 +1,268 @@
+//! Whole-home audio the quick way: the default sink's monitor, encoded to Ogg Opus
+//! and served over plain HTTP so any browser on the network can listen in. Every
+//! listener gets its own `parec | opusenc` pipeline, up to
+//! [`config::HttpStream::max_listeners`] at once. The address carries a token made
+//! up as the server starts, so only those shown it can listen, and the server
+//! listens on the interfaces `[http_stream] address` says, every one unless set.
+use std::{
+    fs::File,
+    io::{Read, Write},
+    net::{IpAddr, TcpListener, TcpStream, UdpSocket},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    config,
+    i18n::{tr, tr_with},
+    modules::Module,
+    state::AppState,
+};
+
+pub const DEFAULT_PORT: u16 = 8910;
+/// How often the accept loop looks whether it should stop.
+const POLL: Duration = Duration::from_millis(200);
+/// How long a client may take to send its request, or to take the audio sent.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A running server, stopped when dropped.
+#[derive(Debug)]
+pub struct HttpStream {
+    pub port: u16,
+    /// Path the stream is served at, unguessable.
+    token: String,
+    stop: Arc<AtomicBool>,
+    server: Option<JoinHandle<()>>,
+}
+
+/// 128 random bits from the kernel, as hex.
+fn token() -> Result<String> {
+    let mut bytes = [0; 16];
+    File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut bytes))
+        .wrap_err("reading /dev/urandom")?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+impl HttpStream {
+    /// Serves the monitor of sink `sink` on the configured address.
+    #[instrument(err)]
+    pub fn start(sink: &str, port: u16) -> Result<Self> {
+        let config = &config::get().http_stream;
+        let listener = TcpListener::bind((config.address, port))
+            .wrap_err_with(|| format!("listening on {}:{port}", config.address))?;
+        listener
+            .set_nonblocking(true)
+            .wrap_err("making the listener non-blocking")?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let token = token()?;
+        let source = format!("{sink}.monitor");
+        let max_listeners = config.max_listeners;
+        let listeners = Arc::new(AtomicU32::new(0));
+        let server = std::thread::spawn({
+            let stop = stop.clone();
+            let token = token.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((client, address)) => {
+                            info!(%address, "listener connected");
+                            let stop = stop.clone();
+                            let source = source.clone();
+                            let token = token.clone();
+                            let listeners = listeners.clone();
+                            std::thread::spawn(move || {
+                                let served = match listeners.fetch_add(1, Ordering::Relaxed) {
+                                    count if count >= max_listeners => {
+                                        refuse(client, "503 Service Unavailable")
+                                    }
+                                    _ => serve(client, &token, &source, &stop),
+                                };
+                                listeners.fetch_sub(1, Ordering::Relaxed);
+                                if let Err(error) = served {
+                                    info!(?error, %address, "listener gone");
+                                }
+                            });
+                        }
+                        Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                            std::thread::sleep(POLL)
+                        }
+                        Err(error) => warn!(%error, "accepting listener"),
+                    }
+                }
+            }
+        });
+        info!(port, "streaming {sink} over http");
+        Ok(Self {
+            port,
+            token,
+            stop,
+            server: Some(server),
+        })
+    }
+}
+
+impl HttpStream {
+    /// Where to listen from other devices, token included.
+    pub fn url(&self) -> String {
+        let host = local_address().map_or_else(|| "localhost".to_owned(), |ip| ip.to_string());
+        format!("http://{host}:{}/{}", self.port, self.token)
+    }
+}
+
+impl Drop for HttpStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(server) = self.server.take() {
+            server.join().ok();
+        }
+    }
+}
+
+/// Kills the encoding pipeline once the listener is done with it.
+struct Pipeline(Vec<Child>);
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        for child in &mut self.0 {
+            child.kill().and_then(|_| child.wait()).ok();
+        }
+    }
+}
+
+fn refuse(mut client: TcpStream, status: &str) -> Result<()> {
+    client
+        .write_all(format!("HTTP/1.0 {status}\r\nContent-Length: 0\r\n\r\n").as_bytes())
+        .wrap_err("refusing the listener")?;
+    Err(eyre!("refused with {status}"))
+}
+
+fn serve(mut client: TcpStream, token: &str, source: &str, stop: &AtomicBool) -> Result<()> {
+    client
+        .set_nonblocking(false)
+        .wrap_err("making the connection blocking")?;
+    client
+        .set_read_timeout(Some(CLIENT_TIMEOUT))
+        .and_then(|_| client.set_write_timeout(Some(CLIENT_TIMEOUT)))
+        .wrap_err("setting the connection's timeouts")?;
+    let mut request = [0u8; 1024];
+    let read = client.read(&mut request).wrap_err("reading the request")?;
+    // only the path matters, as in `GET /<token> HTTP/1.1`
+    let request = String::from_utf8_lossy(&request[..read]);
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+    if path.trim_matches('/') != token {
+        return refuse(client, "404 Not Found");
+    }
+    // built first, so parec doesn't outlive an opusenc that won't start
+    let mut pipeline = Pipeline(Vec::new());
+    let mut recorder = Command::new("parec")
+        .arg(format!("--device={source}"))
+        .arg(Module::stream_tag("http-stream"))
+        .args(["--raw", "--format=s16le", "--rate=48000", "--channels=2"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .wrap_err("starting parec")?;
+    let recorded = recorder.stdout.take();
+    pipeline.0.push(recorder);
+    let recorded = recorded.ok_or_else(|| eyre!("parec has no stdout"))?;
+    let mut encoder = Command::new("opusenc")
+        .args([
+            "--quiet",
+            "--raw",
+            "--raw-rate",
+            "48000",
+            "--raw-chan",
+            "2",
+            "-",
+            "-",
+        ])
+        .stdin(Stdio::from(recorded))
+        .stdout(Stdio::piped())
+        .spawn()
+        .wrap_err("starting opusenc")?;
+    let encoded = encoder.stdout.take();
+    pipeline.0.push(encoder);
+    let mut encoded = encoded.ok_or_else(|| eyre!("opusenc has no stdout"))?;
+
+    client
+        .write_all(b"HTTP/1.0 200 OK\r\nContent-Type: audio/ogg\r\nCache-Control: no-cache\r\n\r\n")
+        .wrap_err("writing the response head")?;
+    let mut chunk = [0u8; 4096];
+    while !stop.load(Ordering::Relaxed) {
+        let read = encoded.read(&mut chunk).wrap_err("reading encoded audio")?;
+        if read == 0 {
+            return Err(eyre!("encoder stopped"));
+        }
+        client
+            .write_all(&chunk[..read])
+            .wrap_err("sending encoded audio")?;
+    }
+    Ok(())
+}
+
+/// Address other devices on the network reach this one at. Connecting a UDP socket
+/// sends nothing, it only picks the interface a route goes out of.
+fn local_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    socket.connect(("192.0.2.1", 9)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Expander with a switch for the server and the address to open elsewhere.
+pub fn stream_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(&tr("Serve the output to browsers on the network")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    row.append(&label);
+    let switch = gtk::Switch::new();
+    row.append(&switch);
+    layout.append(&row);
+    let address = gtk::Label::new(None);
+    address.set_selectable(true);
+    address.set_halign(gtk::Align::Start);
+    layout.append(&address);
+
+    switch.connect_state_set(move |_, on| {
+        dispatcher.dispatch(match on {
+            true => Action::StartHttpStream(DEFAULT_PORT),
+            false => Action::StopHttpStream,
+        });
+        gtk::Inhibit(false)
+    });
+    create_effect(cx, move |_| {
+        let port = state.http_stream_port.get();
+        if switch.is_active() != port.is_some() {
+            switch.set_active(port.is_some());
+        }
+        match port.and_then(|_| {
+            state
+                .http_stream
+                .with_value(|stream| stream.as_ref().map(HttpStream::url))
+        }) {
+            Some(url) => address.set_text(&tr_with("Listen at {url}", &[("url", &url)])),
+            None => address.set_text(""),
+        }
+        address.set_visible(port.is_some());
+    });
+
+    gtk::Expander::builder()
+        .label("Listen on another device")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,195 @@
+//! Translations of what the window shows, gettext style: strings are written in
+//! English in the code, wrapped in [`tr`], and looked up in the `.po` catalog of the
+//! user's language, the English staying when there's no translation. The language
+//! comes from `LANGUAGE`, `LC_ALL`, `LC_MESSAGES` and `LANG`, as glib reads them,
+//! and its catalog is the first `<language>.po` found in `$PIPEWELD_LOCALE_DIR`,
+//! then `pipeweld/locale` in the user's and the system's data directories:
+//!
+//! ```sh
+//! msginit --input=po/pipeweld.pot --locale=pl_PL --output=pl.po
+//! PIPEWELD_LOCALE_DIR=. LANGUAGE=pl pipeweld
+//! ```
+//!
+//! The template, `po/pipeweld.pot`, is made again whenever strings change, with
+//! `label` for the window's panels and `marked` for strings in constants:
+//!
+//! ```sh
+//! xgettext --language=C --keyword=tr --keyword=tr_with --keyword=or_report:2 \
+//!     --keyword=report:2 --keyword=label --keyword=marked --from-code=UTF-8 \
+//!     --package-name=pipeweld -o po/pipeweld.pot src/*.rs 2>/dev/null
+//! ```
+//!
+//! What failed, in error reports, is translated too. Placeholders like `{device}`
+//! are filled in after translating, so translations keep them as they are, in
+//! whatever order the language needs. Panels are translated by their label once the
+//! window is built, see [`translate_panels`]. Plural forms aren't supported, the
+//! first `msgstr` is taken.
+use std::{collections::HashMap, path::PathBuf, sync::OnceLock};
+
+use gtk::{glib, prelude::*};
+use tracing::{info, warn};
+
+use crate::ui_state;
+
+type Catalog = HashMap<String, String>;
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Where catalogs are looked for, in order.
+fn directories() -> Vec<PathBuf> {
+    let own = |dir: PathBuf| dir.join(clap::crate_name!()).join("locale");
+    std::env::var_os("PIPEWELD_LOCALE_DIR")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(std::iter::once(own(glib::user_data_dir())))
+        .chain(glib::system_data_dirs().into_iter().map(own))
+        .collect()
+}
+
+/// A quoted `.po` string without its quotes and escapes.
+fn unquote(quoted: &str) -> Option<String> {
+    let inner = quoted.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            text.push(char);
+            continue;
+        }
+        match chars.next()? {
+            'n' => text.push('\n'),
+            't' => text.push('\t'),
+            other => text.push(other),
+        }
+    }
+    Some(text)
+}
+
+/// Translations of a `.po` file, leaving out fuzzy and untranslated ones.
+fn parse(contents: &str) -> Catalog {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Part {
+        None,
+        Id,
+        Text,
+    }
+    let mut catalog = Catalog::new();
+    let (mut id, mut text, mut fuzzy, mut part) = (String::new(), String::new(), false, Part::None);
+    let mut finish = |id: &mut String, text: &mut String, fuzzy: &mut bool| {
+        if !id.is_empty() && !text.is_empty() && !*fuzzy {
+            catalog.insert(std::mem::take(id), std::mem::take(text));
+        }
+        id.clear();
+        text.clear();
+        *fuzzy = false;
+    };
+    for line in contents.lines().map(str::trim) {
+        // comments and the `msgid` start the next entry
+        if part == Part::Text && (line.starts_with('#') || line.starts_with("msgid ")) {
+            finish(&mut id, &mut text, &mut fuzzy);
+            part = Part::None;
+        }
+        if let Some(flags) = line.strip_prefix("#,") {
+            fuzzy = flags.split(',').any(|flag| flag.trim() == "fuzzy");
+        } else if let Some(rest) = line.strip_prefix("msgid ") {
+            id = unquote(rest).unwrap_or_default();
+            part = Part::Id;
+        } else if let Some(rest) = line.strip_prefix("msgstr ").or_else(|| {
+            // `msgstr[0]`, the singular
+            line.strip_prefix("msgstr[0] ")
+        }) {
+            text = unquote(rest).unwrap_or_default();
+            part = Part::Text;
+        } else if line.starts_with('"') {
+            let continued = unquote(line).unwrap_or_default();
+            match part {
+                Part::Id => id.push_str(&continued),
+                Part::Text => text.push_str(&continued),
+                Part::None => {}
+            }
+        }
+    }
+    finish(&mut id, &mut text, &mut fuzzy);
+    catalog
+}
+
+/// The `{placeholders}` of `text`, sorted.
+fn placeholders(text: &str) -> Vec<&str> {
+    let mut found = text
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}'))
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    found.sort_unstable();
+    found
+}
+
+fn load() -> Catalog {
+    let languages = glib::language_names();
+    for language in languages.iter().filter(|language| language.as_str() != "C") {
+        for directory in directories() {
+            let path = directory.join(format!("{language}.po"));
+            let Ok(contents) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let catalog = parse(&contents);
+            for (id, text) in &catalog {
+                if placeholders(id) != placeholders(text) {
+                    warn!(id, text, "the translation doesn't keep the placeholders");
+                }
+            }
+            info!(path = %path.display(), translations = catalog.len(), "loaded translations");
+            return catalog;
+        }
+    }
+    if let Some(language) = languages
+        .first()
+        .filter(|language| !language.starts_with("en"))
+    {
+        info!(%language, "no translations, the window stays in English");
+    }
+    Catalog::new()
+}
+
+/// Picks the language and loads its catalog, before anything is shown.
+pub fn init() {
+    CATALOG.get_or_init(load);
+}
+
+/// `text` in the user's language.
+pub fn tr(text: &str) -> String {
+    CATALOG
+        .get_or_init(load)
+        .get(text)
+        .cloned()
+        .unwrap_or_else(|| text.to_owned())
+}
+
+/// Marks `text` for the template where it can't go through [`tr`] yet, like in
+/// constants. It's translated where it's shown.
+pub const fn marked(text: &'static str) -> &'static str {
+    text
+}
+
+/// `text` in the user's language, with each `{name}` replaced by its value.
+pub fn tr_with(text: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(tr(text), |translated, (name, value)| {
+        translated.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// Translates the labels of the window's panels. It goes after the panels are
+/// named and their state restored, which both go by the English label.
+pub fn translate_panels(window: &gtk::ApplicationWindow) {
+    let mut found = Vec::new();
+    ui_state::expanders(window.upcast_ref(), &mut found);
+    for expander in found {
+        if let Some(label) = expander.label() {
+            let translated = tr(&label);
+            if translated != label.as_str() {
+                expander.set_label(Some(&translated));
+            }
+        }
+    }
+}
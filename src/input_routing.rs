@@ -0,0 +1,187 @@
+//! Picking which inputs of a multichannel interface make up a source, like "mic on
+//! input 3" for software that only ever records the first channels. A small matrix
+//! maps the interface's inputs to the channels of a mono or stereo source, which is
+//! a virtual device, [`VirtualDevice::Remap`], so it's restored on startup and
+//! listed and removed with the others.
+use std::{cell::RefCell, rc::Rc};
+
+use gtk::{prelude::*, Orientation};
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    i18n::{tr, tr_with},
+    virtual_devices::VirtualDevice,
+};
+
+/// Inputs with more channels than this count as interfaces.
+const STEREO: usize = 2;
+
+/// Sources with more than two channels.
+fn interfaces() -> Vec<Device> {
+    Device::list(DeviceKind::Source)
+        .map_err(|error| warn!(?error, "listing interfaces to route"))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|source| source.channels().len() > STEREO)
+        .collect()
+}
+
+/// Like "Input 3 (aux2)", inputs counting from 1.
+fn input_label(position: usize, channel: &str) -> String {
+    tr_with(
+        "Input {position} ({channel})",
+        &[
+            ("position", &(position + 1).to_string()),
+            ("channel", channel),
+        ],
+    )
+}
+
+/// The matrix: a row per input of `interface` and a column per channel of the new
+/// source, with one input picked per column. Returns the buttons of each column.
+fn fill(grid: &gtk::Grid, interface: &Device, stereo: bool) -> Vec<Vec<gtk::CheckButton>> {
+    while let Some(child) = grid.first_child() {
+        grid.remove(&child);
+    }
+    let outputs = match stereo {
+        true => vec![tr("Left"), tr("Right")],
+        false => vec![tr("Mono")],
+    };
+    let inputs = interface.channels();
+    let mut columns = Vec::new();
+    for (column, output) in outputs.iter().enumerate() {
+        let heading = gtk::Label::new(Some(output));
+        heading.add_css_class("heading");
+        grid.attach(&heading, column as i32 + 1, 0, 1, 1);
+        let mut buttons = Vec::<gtk::CheckButton>::new();
+        for (row, _) in inputs.iter().enumerate() {
+            let button = gtk::CheckButton::new();
+            button.set_halign(gtk::Align::Center);
+            if let Some(first) = buttons.first() {
+                button.set_group(Some(first));
+            }
+            // the first inputs in order, until the user picks others
+            button.set_active(row == column);
+            grid.attach(&button, column as i32 + 1, row as i32 + 1, 1, 1);
+            buttons.push(button);
+        }
+        columns.push(buttons);
+    }
+    for (row, (channel, _)) in inputs.iter().enumerate() {
+        let label = gtk::Label::new(Some(&input_label(row, channel)));
+        label.set_halign(gtk::Align::Start);
+        label.set_hexpand(true);
+        grid.attach(&label, 0, row as i32 + 1, 1, 1);
+    }
+    columns
+}
+
+/// Expander with an interface picker, the routing matrix and a form creating the
+/// source. Interfaces are listed again whenever it's expanded.
+pub fn input_routing_panel(dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let empty = gtk::Label::new(Some(&tr("No input has more than two channels.")));
+    empty.add_css_class("dim-label");
+    layout.append(&empty);
+    let form = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&form);
+    let interface = gtk::DropDown::from_strings(&[]);
+    interface.set_tooltip_text(Some(&tr("Interface to take the inputs from")));
+    form.append(&interface);
+    let channels = gtk::DropDown::from_strings(&[&tr("Mono source"), &tr("Stereo source")]);
+    form.append(&channels);
+    let grid = gtk::Grid::builder()
+        .row_spacing(2)
+        .column_spacing(12)
+        .build();
+    form.append(&grid);
+    let name = gtk::Entry::builder()
+        .placeholder_text(tr("Source name, like Guitar"))
+        .build();
+    form.append(&name);
+    let create = gtk::Button::with_label(&tr("Create source"));
+    create.set_halign(gtk::Align::Start);
+    form.append(&create);
+
+    let listed = Rc::new(RefCell::new(Vec::<Device>::new()));
+    let columns = Rc::new(RefCell::new(Vec::<Vec<gtk::CheckButton>>::new()));
+    let rebuild = {
+        let listed = listed.clone();
+        let columns = columns.clone();
+        let (interface, channels) = (interface.clone(), channels.clone());
+        move || {
+            let listed = listed.borrow();
+            let built = match listed.get(interface.selected() as usize) {
+                Some(device) => fill(&grid, device, channels.selected() == 1),
+                None => Vec::new(),
+            };
+            columns.replace(built);
+        }
+    };
+    let refresh = {
+        let listed = listed.clone();
+        let interface = interface.clone();
+        let rebuild = rebuild.clone();
+        move || {
+            let found = interfaces();
+            empty.set_visible(found.is_empty());
+            form.set_visible(!found.is_empty());
+            let labels = found
+                .iter()
+                .map(|device| device.description.as_str())
+                .collect::<Vec<_>>();
+            listed.replace(found.clone());
+            // a new model doesn't notify when the first one stays selected
+            interface.set_model(Some(&gtk::StringList::new(&labels)));
+            rebuild();
+        }
+    };
+    interface.connect_selected_notify({
+        let rebuild = rebuild.clone();
+        move |_| rebuild()
+    });
+    channels.connect_selected_notify(move |_| rebuild());
+    create.connect_clicked(move |_| {
+        let source_name = name.text().trim().to_owned();
+        let Some(device) = listed.borrow().get(interface.selected() as usize).cloned() else {
+            return;
+        };
+        let picked = device.channels();
+        let inputs = columns
+            .borrow()
+            .iter()
+            .filter_map(|buttons| {
+                let row = buttons.iter().position(|button| button.is_active())?;
+                picked.get(row).map(|(channel, _)| channel.clone())
+            })
+            .collect::<Vec<_>>();
+        if source_name.is_empty() || inputs.len() != columns.borrow().len() {
+            return warn!("a routed source needs a name, and an input for each channel");
+        }
+        name.set_text("");
+        dispatcher.dispatch(Action::CreateVirtualDevice {
+            name: source_name,
+            device: VirtualDevice::Remap {
+                source: device.name,
+                inputs,
+            },
+        });
+    });
+
+    refresh();
+    let expander = gtk::Expander::builder()
+        .label("Input routing")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    expander.connect_expanded_notify(move |expander| {
+        if expander.is_expanded() {
+            refresh();
+        }
+    });
+    expander
+}
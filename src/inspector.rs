@@ -0,0 +1,264 @@
+//! A window listing the application state as it changes, for finding out why a
+//! widget doesn't react. Ctrl+Shift+I opens it, there's no button for it. It also
+//! records the state as actions and events come in, see [`crate::timeline`].
+use std::{cell::RefCell, path::PathBuf, rc::Rc, time::Duration};
+
+use gtk::{gio, glib, prelude::*, Application, Orientation};
+use leptos::*;
+use tracing::warn;
+
+use crate::{
+    extensions::watch,
+    i18n::{tr, tr_with},
+    state::AppState,
+    timeline::Recording,
+};
+
+pub const ACTION: &str = "inspector";
+
+/// Stored values and the recording aren't signals, so they're read again this often.
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Exposes [`ACTION`] on the application and binds it to Ctrl+Shift+I.
+pub fn register(cx: Scope, app: &Application, state: AppState) {
+    let action = gio::SimpleAction::new(ACTION, None);
+    action.connect_activate({
+        let app = app.clone();
+        move |_, _| open(cx, &app, state)
+    });
+    app.add_action(&action);
+    app.set_accels_for_action(&format!("app.{ACTION}"), &["<Control><Shift>i"]);
+}
+
+/// Asks for a file to export to or import from, then hands it to `chosen`.
+fn choose_file(
+    parent: &gtk::Window,
+    action: gtk::FileChooserAction,
+    chosen: impl Fn(PathBuf) + 'static,
+) {
+    let (title, accept) = match action {
+        gtk::FileChooserAction::Save => (tr("Export recording"), tr("Export")),
+        _ => (tr("Import recording"), tr("Import")),
+    };
+    let dialog = gtk::FileChooserNative::new(
+        Some(&title),
+        Some(parent),
+        action,
+        Some(&accept),
+        Some(&tr("Cancel")),
+    );
+    if action == gtk::FileChooserAction::Save {
+        dialog.set_current_name(&format!("{}-recording.json", clap::crate_name!()));
+    }
+    // GTK doesn't keep native dialogs alive by itself
+    let kept = Rc::new(RefCell::new(None::<gtk::FileChooserNative>));
+    dialog.connect_response({
+        let kept = kept.clone();
+        move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    chosen(path);
+                }
+            }
+            kept.take();
+        }
+    });
+    dialog.show();
+    kept.replace(Some(dialog));
+}
+
+pub fn open(cx: Scope, app: &Application, state: AppState) {
+    let layout = gtk::Box::new(Orientation::Vertical, 12);
+    layout.set_margin_top(12);
+    layout.set_margin_bottom(12);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    let controls = gtk::Box::new(Orientation::Horizontal, 6);
+    let record = gtk::ToggleButton::with_label(&tr("Record"));
+    record.set_active(state.recording.with_value(Recording::is_recording));
+    controls.append(&record);
+    let back = gtk::Button::from_icon_name("go-previous-symbolic");
+    back.set_tooltip_text(Some(&tr("Step back")));
+    controls.append(&back);
+    let forward = gtk::Button::from_icon_name("go-next-symbolic");
+    forward.set_tooltip_text(Some(&tr("Step forward")));
+    controls.append(&forward);
+    let live = gtk::Button::with_label(&tr("Live"));
+    controls.append(&live);
+    let position_label = gtk::Label::builder()
+        .hexpand(true)
+        .halign(gtk::Align::Start)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .build();
+    controls.append(&position_label);
+    let import = gtk::Button::with_label(&tr("Import…"));
+    controls.append(&import);
+    let export = gtk::Button::with_label(&tr("Export…"));
+    controls.append(&export);
+    layout.append(&controls);
+    let grid = gtk::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .build();
+    layout.append(
+        &gtk::ScrolledWindow::builder()
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .vexpand(true)
+            .child(&grid)
+            .build(),
+    );
+    let window = gtk::Window::builder()
+        .application(app)
+        .title(tr("State inspector"))
+        .default_width(720)
+        .default_height(720)
+        .child(&layout)
+        .build();
+
+    // everything shown goes away with the window
+    let ((), disposer) =
+        cx.run_child_scope(|cx| {
+            let tick = create_trigger(cx);
+            let weak = window.downgrade();
+            glib::timeout_add_local(REFRESH, move || {
+                if weak.upgrade().is_none() {
+                    return glib::Continue(false);
+                }
+                tick.notify();
+                glib::Continue(true)
+            });
+            // the recorded entry shown instead of the live state
+            let position = create_rw_signal(cx, None::<usize>);
+            let recorded = move || {
+                state
+                    .recording
+                    .with_value(|recording| recording.entries.len())
+            };
+
+            record.connect_toggled(move |record| {
+                state
+                    .recording
+                    .update_value(|recording| match record.is_active() {
+                        true => recording.start(),
+                        false => recording.stop(),
+                    });
+                position.set(None);
+            });
+            back.connect_clicked(move |_| match (position.get_untracked(), recorded()) {
+                (_, 0) => {}
+                (None, recorded) => position.set(Some(recorded - 1)),
+                (Some(current), _) => position.set(Some(current.saturating_sub(1))),
+            });
+            forward.connect_clicked(move |_| {
+                if let Some(current) = position.get_untracked() {
+                    position.set((current + 1 < recorded()).then_some(current + 1));
+                }
+            });
+            live.connect_clicked(move |_| position.set(None));
+            // the window holds on to the buttons, not the other way around
+            let weak = window.downgrade();
+            let weak_record = record.downgrade();
+            import.connect_clicked(move |_| {
+                let Some(window) = weak.upgrade() else {
+                    return;
+                };
+                let record = weak_record.clone();
+                choose_file(&window, gtk::FileChooserAction::Open, move |path| {
+                    match Recording::import(&path) {
+                        Ok(imported) => {
+                            if let Some(record) = record.upgrade() {
+                                record.set_active(false);
+                            }
+                            state.recording.set_value(imported);
+                            position.set((recorded() > 0).then_some(0));
+                        }
+                        Err(error) => warn!(?error, "importing a recording"),
+                    }
+                })
+            });
+            let weak = window.downgrade();
+            export.connect_clicked(move |_| {
+                let Some(window) = weak.upgrade() else {
+                    return;
+                };
+                choose_file(&window, gtk::FileChooserAction::Save, move |path| {
+                    state
+                        .recording
+                        .with_value(|recording| recording.export(&path))
+                        .ok();
+                })
+            });
+
+            watch(cx, &position_label, move |label| {
+                tick.track();
+                let text = state.recording.with_value(|recording| {
+                    match position
+                        .get()
+                        .and_then(|current| Some((current, recording.entries.get(current)?)))
+                    {
+                        None => tr_with(
+                            "Live, {recorded} recorded",
+                            &[("recorded", &recording.entries.len().to_string())],
+                        ),
+                        Some((current, entry)) => format!(
+                            "{}/{} at {:.1} s: {}{}",
+                            current + 1,
+                            recording.entries.len(),
+                            entry.at_ms as f64 / 1000.,
+                            entry.cause,
+                            entry
+                                .source
+                                .map(|source| format!(", by {}", source.label()))
+                                .unwrap_or_default()
+                        ),
+                    }
+                });
+                label.set_text(&text);
+                label.set_tooltip_text(Some(&text));
+            });
+            for (row, (name, value)) in (0..).zip(state.inspect()) {
+                let name_label = gtk::Label::builder()
+                    .label(name)
+                    .halign(gtk::Align::Start)
+                    .valign(gtk::Align::Start)
+                    .css_classes(["heading"])
+                    .build();
+                grid.attach(&name_label, 0, row, 1, 1);
+                let label = gtk::Label::builder()
+                    .halign(gtk::Align::Start)
+                    .hexpand(true)
+                    .wrap(true)
+                    .wrap_mode(gtk::pango::WrapMode::WordChar)
+                    .selectable(true)
+                    .css_classes(["monospace"])
+                    .build();
+                grid.attach(&label, 1, row, 1, 1);
+                watch(cx, &label, move |label| {
+                    tick.track();
+                    let value = match position.get() {
+                        None => value(),
+                        Some(current) => state.recording.with_value(|recording| {
+                            recording
+                                .entries
+                                .get(current)
+                                .and_then(|entry| entry.value(name))
+                                .unwrap_or_default()
+                                .to_owned()
+                        }),
+                    };
+                    // setting the same text again would drop the selection
+                    if label.text() != value {
+                        label.set_text(&value);
+                    }
+                });
+            }
+        });
+    let disposer = RefCell::new(Some(disposer));
+    window.connect_close_request(move |_| {
+        if let Some(disposer) = disposer.take() {
+            disposer.dispose();
+        }
+        glib::signal::Inhibit(false)
+    });
+    window.present();
+}
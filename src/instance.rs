@@ -0,0 +1,87 @@
+//! One pipeweld per session. GTK owns [`crate::app_id`] on the session bus for the
+//! first instance, and launching pipeweld again hands over to it: the new process
+//! asks it to activate, which raises its window, and exits before it starts
+//! listening to the server or applying anything. `pipeweld show` does the same from
+//! the command line without starting GTK, over the `org.freedesktop.Application`
+//! interface GTK exports, and starts pipeweld when it isn't running yet.
+use std::process::{Command, Stdio};
+
+use eyre::{Result, WrapErr};
+use gtk::{gio, glib, prelude::*};
+use tracing::info;
+
+use crate::app_id;
+
+/// Where GTK exports the application, as in `/it/niedzwiedz/pipeweld`.
+fn object_path() -> String {
+    format!("/{}", app_id().replace('.', "/"))
+}
+
+fn session_bus() -> Result<gio::DBusConnection> {
+    gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)
+        .wrap_err("connecting to the session bus")
+}
+
+/// Whether an instance owns the application's name on the session bus.
+pub fn running() -> Result<bool> {
+    let reply = session_bus()?
+        .call_sync(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "NameHasOwner",
+            Some(&(app_id(),).to_variant()),
+            Some(glib::VariantTy::new("(b)").expect("a valid type")),
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        )
+        .wrap_err("asking the session bus for pipeweld")?;
+    Ok(reply.get::<(bool,)>().is_some_and(|(owned,)| owned))
+}
+
+/// Asks the running instance to raise its window, `false` when none is running.
+/// The activation token of whoever launched this is passed on, which Wayland
+/// compositors want before they let a window take focus.
+pub fn activate() -> Result<bool> {
+    if !running()? {
+        return Ok(false);
+    }
+    let platform_data = glib::VariantDict::new(None);
+    for (variable, key) in [
+        ("XDG_ACTIVATION_TOKEN", "activation-token"),
+        ("DESKTOP_STARTUP_ID", "desktop-startup-id"),
+    ] {
+        if let Ok(token) = std::env::var(variable) {
+            platform_data.insert(key, token);
+        }
+    }
+    session_bus()?
+        .call_sync(
+            Some(&app_id()),
+            &object_path(),
+            "org.freedesktop.Application",
+            "Activate",
+            Some(&glib::Variant::tuple_from_iter([platform_data.end()])),
+            None,
+            gio::DBusCallFlags::NONE,
+            5000,
+            gio::Cancellable::NONE,
+        )
+        .wrap_err("activating the running pipeweld")?;
+    info!("raised the running instance");
+    Ok(true)
+}
+
+/// Raises the running instance's window, or starts pipeweld in the background.
+pub fn show() -> Result<()> {
+    if activate()? {
+        return Ok(());
+    }
+    let program = std::env::current_exe().wrap_err("finding pipeweld's executable")?;
+    Command::new(&program)
+        .stdin(Stdio::null())
+        .spawn()
+        .wrap_err_with(|| format!("starting {}", program.display()))?;
+    Ok(())
+}
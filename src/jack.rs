@@ -0,0 +1,143 @@
+//! What plugging headphones into a jack does, on top of the server switching the
+//! card's port over: make them the default output, set their volume and play them
+//! through an effects preset. The rules engine notices the plugging, see
+//! [`crate::rules::HeadphoneJack`].
+use std::collections::HashSet;
+
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    device_settings::DeviceStore,
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    presets,
+    state::AppState,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JackActions {
+    /// Make the sink with the jack the default output, on the jack's port.
+    pub switch: bool,
+    /// Volume, in percent, to set them to.
+    pub volume: Option<u32>,
+    /// Effects preset to play them through, the sink's own comes back once they're
+    /// unplugged.
+    pub preset: Option<String>,
+}
+
+/// Whether `port` is a headphones or headset jack, next to speakers or line outs.
+pub fn is_jack(port: &str) -> bool {
+    port.contains("headphones") || port.contains("headset")
+}
+
+/// Jack ports of `sink` with something plugged in.
+pub fn plugged(sink: &Device) -> HashSet<String> {
+    sink.ports
+        .iter()
+        .filter(|port| is_jack(&port.name) && port.availability == "available")
+        .map(|port| port.name.clone())
+        .collect()
+}
+
+/// The actions the user picked for headphones plugged into `port` of `sink`.
+pub fn on_plug(store: &DeviceStore, sink: &Device, port: &str) -> Vec<Action> {
+    let settings = &store.headphone_jack;
+    let mut actions = Vec::new();
+    if settings.switch {
+        if sink.active_port.as_deref() != Some(port) {
+            actions.push(Action::SetPort {
+                kind: DeviceKind::Sink,
+                device: sink.name.clone(),
+                port: port.to_owned(),
+            });
+        }
+        actions.push(Action::SetDefault(DeviceKind::Sink, sink.name.clone()));
+    }
+    if let Some(volume) = settings.volume {
+        actions.push(Action::SetVolume(
+            DeviceKind::Sink,
+            sink.name.clone(),
+            volume,
+        ));
+    }
+    if let Some(preset) = &settings.preset {
+        actions.push(Action::SetPreset {
+            device: sink.name.clone(),
+            preset: Some(preset.clone()),
+        });
+    }
+    actions
+}
+
+/// Expander picking what plugging in headphones does.
+pub fn jack_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let current = state
+        .device_settings
+        .with_untracked(|store| store.headphone_jack.clone());
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let switch = gtk::CheckButton::with_label(&tr("Make them the default output"));
+    switch.set_active(current.switch);
+    layout.append(&switch);
+
+    let volume_row = gtk::Box::new(Orientation::Horizontal, 6);
+    let set_volume = gtk::CheckButton::with_label(&tr("Set the volume to (%)"));
+    set_volume.set_active(current.volume.is_some());
+    volume_row.append(&set_volume);
+    let volume = gtk::SpinButton::with_range(0., 100., 5.);
+    volume.set_hexpand(true);
+    volume.set_value(current.volume.unwrap_or(50).into());
+    set_volume
+        .bind_property("active", &volume, "sensitive")
+        .sync_create()
+        .build();
+    volume_row.append(&volume);
+    layout.append(&volume_row);
+
+    let preset_row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(&tr("Effects preset")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    preset_row.append(&label);
+    let names = presets::list();
+    let preset = gtk::DropDown::from_strings(
+        &std::iter::once("Keep the device's")
+            .chain(names.iter().map(String::as_str))
+            .collect::<Vec<_>>(),
+    );
+    let selected = current
+        .preset
+        .as_ref()
+        .and_then(|current| names.iter().position(|name| name == current))
+        .map_or(0, |position| position + 1);
+    preset.set_selected(selected as u32);
+    preset_row.append(&preset);
+    layout.append(&preset_row);
+
+    let apply = gtk::Button::with_label(&tr("Apply"));
+    apply.set_halign(gtk::Align::End);
+    apply.connect_clicked(move |_| {
+        let preset = match preset.selected() {
+            0 | gtk::INVALID_LIST_POSITION => None,
+            selected => names.get(selected as usize - 1).cloned(),
+        };
+        dispatcher.dispatch(Action::SetJackActions(JackActions {
+            switch: switch.is_active(),
+            volume: set_volume
+                .is_active()
+                .then(|| volume.value().round() as u32),
+            preset,
+        }));
+    });
+    layout.append(&apply);
+    gtk::Expander::builder()
+        .label("Headphones plugged in")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,678 @@
+//! Peak level meters with a few seconds of history, for spotting clipping or a
+//! source that went quiet, and a level bar of the default output. Each meter
+//! records its device through `parec`, so they only run while they show.
+//!
+//! A meter's thread reads its recording into one buffer and puts each interval's
+//! [`Level`] into a [`Ring`] of its own, neither allocating nor waking the main loop.
+//! The main loop takes the levels of all meters at once, about 30 times a second,
+//! so twenty meters cost it no more wakeups than one. `pipeweld bench-meters` runs
+//! synthetic meters through the same path and prints what they cost.
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt,
+    io::Read,
+    process::{Child, Command, Stdio},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    extensions::watch,
+    filter_chain,
+    i18n::{tr, tr_with},
+    modules::Module,
+    notifications::{Notification, Notifier},
+    state::AppState,
+};
+
+const RATE: usize = 48_000;
+const CHANNELS: usize = 2;
+/// Peaks per second.
+const RESOLUTION: usize = 20;
+/// Peaks kept and drawn, ten seconds worth.
+const POINTS: usize = RESOLUTION * 10;
+/// Peaks at or above this count as clipping.
+const CLIPPING: f32 = 0.99;
+/// Clipping is sustained once this many of the last [`CLIP_WINDOW`] peaks clip,
+/// rather than a stray peak.
+const SUSTAINED: usize = RESOLUTION / 2;
+const CLIP_WINDOW: usize = RESOLUTION * 2;
+/// How long the warning stays after the last clipping peak.
+const CLIP_HOLD: Duration = Duration::from_secs(5);
+/// Notifications go out at most this often.
+const NOTIFY_EVERY: Duration = Duration::from_secs(60);
+/// What's left of the meter's peak after each interval, so it falls back smoothly.
+const FALLOFF: f64 = 0.85;
+/// Below this, in dBFS, the meter reads as silence.
+const SILENCE_DB: f64 = -60.;
+/// How often the main loop takes what the meters measured.
+const DRAIN_EVERY: Duration = Duration::from_millis(33);
+/// Levels a meter holds for the main loop, over a second's worth. A power of two,
+/// so the counts wrapping around don't skip a slot.
+const RING: usize = 32;
+
+/// Recent peaks, oldest first, from 0 to 1.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    peaks: VecDeque<f32>,
+}
+
+impl History {
+    pub fn push(&mut self, peak: f32) {
+        if self.peaks.len() == POINTS {
+            self.peaks.pop_front();
+        }
+        self.peaks.push_back(peak.clamp(0., 1.));
+    }
+
+    fn draw(&self, cairo: &gtk::cairo::Context, width: i32, height: i32) {
+        let step = f64::from(width) / POINTS as f64;
+        // newest to the right, however much history there is yet
+        let offset = POINTS - self.peaks.len();
+        for (index, peak) in self.peaks.iter().enumerate() {
+            match *peak >= CLIPPING {
+                true => cairo.set_source_rgb(0.9, 0.2, 0.2),
+                false => cairo.set_source_rgb(0.3, 0.7, 0.4),
+            }
+            let bar = f64::from(*peak) * f64::from(height);
+            cairo.rectangle(
+                (offset + index) as f64 * step,
+                f64::from(height) - bar,
+                step.max(1.),
+                bar,
+            );
+            cairo.fill().ok();
+        }
+    }
+}
+
+/// Level of one interval of a recording, from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    pub peak: f32,
+    pub rms: f32,
+}
+
+impl Level {
+    fn of(samples: impl Iterator<Item = f32>) -> Self {
+        let (mut peak, mut squares, mut count) = (0f32, 0f64, 0usize);
+        for sample in samples {
+            peak = peak.max(sample.abs());
+            squares += f64::from(sample) * f64::from(sample);
+            count += 1;
+        }
+        Self {
+            peak,
+            rms: (squares / count.max(1) as f64).sqrt() as f32,
+        }
+    }
+}
+
+/// The levels of one meter on their way from its thread to the main loop, without
+/// locks or allocations. Only the meter's thread pushes and only the main loop pops.
+struct Ring {
+    /// Peak and RMS bits of each level.
+    slots: [AtomicU64; RING],
+    /// Levels pushed and popped so far, slots go by them modulo [`RING`].
+    pushed: AtomicUsize,
+    popped: AtomicUsize,
+    /// Set once the meter is dropped, for its thread and the main loop to let go.
+    closed: AtomicBool,
+}
+
+impl Ring {
+    fn new() -> Self {
+        Self {
+            slots: std::array::from_fn(|_| AtomicU64::new(0)),
+            pushed: AtomicUsize::new(0),
+            popped: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Leaves `level` out when the main loop is a whole ring behind, as when it's
+    /// busy, rather than holding up the recording.
+    fn push(&self, level: Level) {
+        let pushed = self.pushed.load(Ordering::Relaxed);
+        if pushed.wrapping_sub(self.popped.load(Ordering::Acquire)) >= RING {
+            return;
+        }
+        let bits = u64::from(level.peak.to_bits()) << 32 | u64::from(level.rms.to_bits());
+        self.slots[pushed % RING].store(bits, Ordering::Relaxed);
+        self.pushed.store(pushed.wrapping_add(1), Ordering::Release);
+    }
+
+    fn pop(&self) -> Option<Level> {
+        let popped = self.popped.load(Ordering::Relaxed);
+        if popped == self.pushed.load(Ordering::Acquire) {
+            return None;
+        }
+        let bits = self.slots[popped % RING].load(Ordering::Relaxed);
+        self.popped.store(popped.wrapping_add(1), Ordering::Release);
+        Some(Level {
+            peak: f32::from_bits((bits >> 32) as u32),
+            rms: f32::from_bits(bits as u32),
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+/// A meter's ring and who gets its levels on the main loop.
+struct Subscriber {
+    ring: Arc<Ring>,
+    on_level: Box<dyn FnMut(Level)>,
+}
+
+thread_local! {
+    static SUBSCRIBERS: RefCell<Vec<Subscriber>> = const { RefCell::new(Vec::new()) };
+    /// Whether the main loop takes levels, which it does while meters run.
+    static DRAINING: Cell<bool> = const { Cell::new(false) };
+    /// Times the main loop took levels, for the benchmark.
+    static DRAINS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Hands every running meter's levels to it, stopping with the last meter.
+fn drain() -> glib::Continue {
+    DRAINS.with(|drains| drains.set(drains.get() + 1));
+    // taken out, so those getting levels can start and stop meters meanwhile
+    let mut subscribers = SUBSCRIBERS.with(|subscribers| subscribers.take());
+    for subscriber in &mut subscribers {
+        while let Some(level) = subscriber.ring.pop() {
+            if subscriber.ring.is_closed() {
+                break;
+            }
+            (subscriber.on_level)(level);
+        }
+    }
+    subscribers.retain(|subscriber| !subscriber.ring.is_closed());
+    SUBSCRIBERS.with(|current| {
+        let started = current.replace(subscribers);
+        current.borrow_mut().extend(started);
+    });
+    let more = SUBSCRIBERS.with(|subscribers| !subscribers.borrow().is_empty());
+    DRAINING.with(|draining| draining.set(more));
+    glib::Continue(more)
+}
+
+/// Reads float samples from `recording` an interval at a time, into the same buffer
+/// every time, and pushes each interval's level.
+fn measure(mut recording: impl Read, ring: &Ring) {
+    let mut chunk = [0u8; RATE / RESOLUTION * CHANNELS * 4];
+    while !ring.is_closed() && recording.read_exact(&mut chunk).is_ok() {
+        ring.push(Level::of(chunk.chunks_exact(4).map(|sample| {
+            f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]])
+        })));
+    }
+}
+
+/// `level`, from 0 to 1, in dBFS.
+pub fn decibels(level: f32) -> f64 {
+    20. * f64::from(level).max(f64::MIN_POSITIVE).log10()
+}
+
+/// A running recording of one device, stopped when dropped.
+pub struct Meter {
+    /// `None` for the benchmark's, which record nothing.
+    child: Option<Child>,
+    ring: Arc<Ring>,
+}
+
+impl Meter {
+    /// Records `target`, a source name, handing a level to `on_level` on the main
+    /// loop `RESOLUTION` times a second.
+    #[instrument(skip(on_level), err)]
+    pub fn start<F: FnMut(Level) + 'static>(target: &str, on_level: F) -> Result<Self> {
+        Self::record(format!("--device={target}"), on_level)
+    }
+
+    /// Records what the playback stream with index `stream` plays, like
+    /// [`Self::start`] does a source.
+    #[instrument(skip(on_level), err)]
+    pub fn stream<F: FnMut(Level) + 'static>(stream: u32, on_level: F) -> Result<Self> {
+        Self::record(format!("--monitor-stream={stream}"), on_level)
+    }
+
+    fn record<F: FnMut(Level) + 'static>(what: String, on_level: F) -> Result<Self> {
+        let mut child = Command::new("parec")
+            .arg(what)
+            .arg(Module::stream_tag("levels"))
+            .args(["--raw", "--format=float32le"])
+            .arg(format!("--rate={RATE}"))
+            .arg(format!("--channels={CHANNELS}"))
+            .arg(format!("--latency-msec={}", 1000 / RESOLUTION))
+            .stdout(Stdio::piped())
+            .spawn()
+            .wrap_err("starting parec")?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| eyre!("parec has no stdout"))?;
+        Ok(Self::attach(stdout, Some(child), on_level))
+    }
+
+    /// Measures `recording` on a thread of its own, handing the levels to
+    /// `on_level` as the main loop takes them.
+    fn attach<F: FnMut(Level) + 'static>(
+        recording: impl Read + Send + 'static,
+        child: Option<Child>,
+        on_level: F,
+    ) -> Self {
+        let ring = Arc::new(Ring::new());
+        std::thread::spawn({
+            let ring = ring.clone();
+            move || measure(recording, &ring)
+        });
+        SUBSCRIBERS.with(|subscribers| {
+            subscribers.borrow_mut().push(Subscriber {
+                ring: ring.clone(),
+                on_level: Box::new(on_level),
+            })
+        });
+        if !DRAINING.with(|draining| draining.replace(true)) {
+            glib::timeout_add_local(DRAIN_EVERY, drain);
+        }
+        Self { child, ring }
+    }
+}
+
+impl Drop for Meter {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+        let Some(child) = &mut self.child else {
+            return;
+        };
+        if let Err(error) = child.kill().and_then(|_| child.wait()) {
+            warn!(?error, "stopping level meter");
+        }
+    }
+}
+
+/// A sawtooth at half of full scale, as fast as a recording would come in.
+struct Synthetic {
+    sample: u32,
+    due: Instant,
+}
+
+impl Read for Synthetic {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(wait) = self.due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+        let samples = buffer.len() / 4;
+        for bytes in buffer.chunks_exact_mut(4) {
+            self.sample = self.sample.wrapping_add(1);
+            let value = (self.sample % 96) as f32 / 96. - 0.5;
+            bytes.copy_from_slice(&value.to_le_bytes());
+        }
+        self.due += Duration::from_secs_f64(samples as f64 / (RATE * CHANNELS) as f64);
+        Ok(samples * 4)
+    }
+}
+
+/// What [`bench`] measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchReport {
+    pub meters: usize,
+    pub duration: Duration,
+    /// Levels handed to the meters' owners.
+    pub levels: u64,
+    /// Times the main loop woke up for them.
+    pub wakeups: u64,
+    /// CPU time of the whole process, the meters' threads too.
+    pub cpu: Duration,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.duration.as_secs_f64();
+        write!(
+            f,
+            "{} meters for {seconds:.1} s: {} levels in {} main loop wakeups ({:.0}/s), \
+             {:.2}% of one core",
+            self.meters,
+            self.levels,
+            self.wakeups,
+            self.wakeups as f64 / seconds,
+            self.cpu.as_secs_f64() / seconds * 100.,
+        )
+    }
+}
+
+/// CPU time this process took so far, user and system, from `/proc/self/stat`.
+fn cpu_time() -> Result<Duration> {
+    let stat = std::fs::read_to_string("/proc/self/stat").wrap_err("reading /proc/self/stat")?;
+    // the command name can contain anything, the fields after it are fixed
+    let fields = stat
+        .rsplit_once(')')
+        .map(|(_, fields)| fields.split_whitespace().collect::<Vec<_>>())
+        .unwrap_or_default();
+    // fields 14 and 15, in clock ticks, which are 1/100 s to user space on Linux
+    let ticks = [14, 15]
+        .into_iter()
+        .map(|field| {
+            fields
+                .get(field - 3)
+                .and_then(|ticks| ticks.parse::<u64>().ok())
+                .ok_or_else(|| eyre!("no field {field} in /proc/self/stat"))
+        })
+        .sum::<Result<u64>>()?;
+    Ok(Duration::from_millis(ticks * 10))
+}
+
+/// Runs `meters` synthetic meters through the same path as real ones for
+/// `duration`, on a main loop of its own, and measures what they took.
+pub fn bench(meters: usize, duration: Duration) -> Result<BenchReport> {
+    let context = glib::MainContext::default();
+    let _owner = context
+        .acquire()
+        .map_err(|_| eyre!("the main context is taken"))?;
+    let main_loop = glib::MainLoop::new(Some(&context), false);
+    let levels = Rc::new(Cell::new(0u64));
+    let cpu_before = cpu_time()?;
+    let drains_before = DRAINS.with(Cell::get);
+    let started = Instant::now();
+    let running = (0..meters)
+        .map(|_| {
+            let levels = levels.clone();
+            let recording = Synthetic {
+                sample: 0,
+                due: started,
+            };
+            Meter::attach(recording, None, move |_| levels.set(levels.get() + 1))
+        })
+        .collect::<Vec<_>>();
+    glib::timeout_add_local_once(duration, {
+        let main_loop = main_loop.clone();
+        move || main_loop.quit()
+    });
+    main_loop.run();
+    let elapsed = started.elapsed();
+    let report = BenchReport {
+        meters,
+        duration: elapsed,
+        levels: levels.get(),
+        wakeups: DRAINS.with(Cell::get) - drains_before,
+        cpu: cpu_time()?.saturating_sub(cpu_before),
+    };
+    drop(running);
+    Ok(report)
+}
+
+/// One row per device: its name above a scrolling graph of its peaks.
+fn row(device: &Device, target: &str) -> (gtk::Box, Option<Meter>) {
+    let row = gtk::Box::new(Orientation::Vertical, 2);
+    let label = gtk::Label::new(Some(&device.description));
+    label.set_halign(gtk::Align::Start);
+    row.append(&label);
+    let graph = gtk::DrawingArea::builder()
+        .height_request(32)
+        .hexpand(true)
+        .build();
+    let history = Rc::new(RefCell::new(History::default()));
+    graph.set_draw_func({
+        let history = history.clone();
+        move |_, cairo, width, height| history.borrow().draw(cairo, width, height)
+    });
+    row.append(&graph);
+    let meter = Meter::start(target, move |level| {
+        history.borrow_mut().push(level.peak);
+        graph.queue_draw();
+    })
+    .ok();
+    (row, meter)
+}
+
+/// Sustained clipping heard on a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clipping {
+    pub device: String,
+    /// The preset played through, whose preamp to lower.
+    pub preset: Option<String>,
+}
+
+impl Clipping {
+    pub fn advice(&self) -> String {
+        match &self.preset {
+            Some(preset) => tr_with(
+                "{device} is clipping. Lower its trim or the preamp of the {preset} preset.",
+                &[("device", &self.device), ("preset", preset)],
+            ),
+            None => tr_with(
+                "{device} is clipping. Lower its trim or the volume of what plays.",
+                &[("device", &self.device)],
+            ),
+        }
+    }
+}
+
+/// Meters the device behind the default output while clipping warnings are on,
+/// keeping [`AppState::clipping`] up and notifying when clipping starts.
+pub fn watch_clipping(cx: Scope, state: AppState) {
+    let master = create_memo(cx, move |_| {
+        if !state.device_settings.with(|store| store.clipping_warning) {
+            return None;
+        }
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    let notifier = Rc::new(
+        Notifier::new()
+            .map_err(|error| warn!(?error, "clipping won't be notified about"))
+            .ok(),
+    );
+    let notified = Rc::new(RefCell::new(None::<Instant>));
+    create_effect(cx, move |previous: Option<Option<Meter>>| {
+        // one recording at a time
+        drop(previous);
+        state.clipping.set(None);
+        let master = master.get()?;
+        let sink = Device::list(DeviceKind::Sink)
+            .ok()
+            .and_then(|sinks| sinks.into_iter().find(|sink| sink.name == master));
+        let port = sink.as_ref().and_then(|sink| sink.active_port.clone());
+        let preset = state.device_settings.with_untracked(|store| {
+            store
+                .devices
+                .get(&master)
+                .and_then(|settings| settings.preset_for(port.as_deref()).map(str::to_owned))
+        });
+        let device = sink.map_or_else(|| master.clone(), |sink| sink.description);
+        let clipping = Clipping { device, preset };
+        let notifier = notifier.clone();
+        let notified = notified.clone();
+        let mut recent = VecDeque::with_capacity(CLIP_WINDOW);
+        let mut last_clip = None::<Instant>;
+        // the monitor of the real device carries what the filters made of it
+        Meter::start(&format!("{master}.monitor"), move |level| {
+            if recent.len() == CLIP_WINDOW {
+                recent.pop_front();
+            }
+            recent.push_back(level.peak >= CLIPPING);
+            let now = Instant::now();
+            if recent.iter().filter(|clipped| **clipped).count() >= SUSTAINED {
+                last_clip = Some(now);
+                if state.clipping.with_untracked(Option::is_some) {
+                    return;
+                }
+                info!(device = clipping.device, "clipping");
+                let due = notified
+                    .borrow()
+                    .is_none_or(|at| now.duration_since(at) >= NOTIFY_EVERY);
+                if let Some(notifier) = Option::as_ref(&notifier).filter(|_| due) {
+                    if !state.do_not_disturb.get_untracked() {
+                        notifier.show(
+                            Notification::new(tr("Clipping"))
+                                .body(clipping.advice())
+                                .icon("dialog-warning-symbolic"),
+                        );
+                        notified.replace(Some(now));
+                    }
+                }
+                state.clipping.set(Some(clipping.clone()));
+            } else if last_clip.is_some_and(|at| now.duration_since(at) >= CLIP_HOLD) {
+                last_clip = None;
+                state.clipping.set(None);
+            }
+        })
+        .ok()
+    });
+}
+
+/// Level bar of what the default output plays, with its RMS and peak in dBFS, to
+/// see at a glance whether anything plays at all. It only records while it shows.
+pub fn vu_meter(cx: Scope, state: AppState) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Vertical, 2);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    let bar = gtk::LevelBar::new();
+    bar.set_min_value(0.);
+    bar.set_max_value(1.);
+    // the stock offsets go by the low end, these by the high one
+    for offset in ["low", "high", "full"] {
+        bar.remove_offset_value(Some(offset));
+    }
+    bar.add_offset_value("low", 0.5);
+    bar.add_offset_value("high", f64::from(CLIPPING));
+    bar.add_offset_value("full", 1.);
+    layout.append(&bar);
+    let reading = gtk::Label::new(Some(&tr("Nothing playing")));
+    reading.set_halign(gtk::Align::Start);
+    reading.add_css_class("dim-label");
+    reading.add_css_class("caption");
+    layout.append(&reading);
+
+    let shown = create_rw_signal(cx, false);
+    layout.connect_map(move |_| shown.set(true));
+    layout.connect_unmap(move |_| shown.set(false));
+    let master = create_memo(cx, move |_| {
+        if !shown.get() {
+            return None;
+        }
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    let bar = bar.downgrade();
+    let reading = reading.downgrade();
+    create_effect(cx, move |previous: Option<Option<Meter>>| {
+        drop(previous);
+        let (bar, reading) = (bar.clone(), reading.clone());
+        let master = master.get()?;
+        let mut shown_peak = 0.;
+        // what the reading says, so it's only written out again when that changes
+        let mut shown_reading = None::<Option<(i32, i32)>>;
+        Meter::start(&format!("{master}.monitor"), move |level| {
+            let (Some(bar), Some(reading)) = (bar.upgrade(), reading.upgrade()) else {
+                return;
+            };
+            shown_peak = f64::from(level.peak).max(shown_peak * FALLOFF);
+            bar.set_value(shown_peak.min(1.));
+            let (rms, peak) = (decibels(level.rms), decibels(level.peak));
+            let rounded = (peak >= SILENCE_DB).then_some((rms.round() as i32, peak.round() as i32));
+            if shown_reading == Some(rounded) {
+                return;
+            }
+            shown_reading = Some(rounded);
+            reading.set_text(&match rounded {
+                None => tr("Nothing playing"),
+                Some((rms, peak)) => format!("RMS {rms} dB, peak {peak} dB"),
+            });
+        })
+        .ok()
+    });
+    layout
+}
+
+/// Warning shown while the default output clips, hidden otherwise.
+pub fn clipping_warning(cx: Scope, state: AppState) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Horizontal, 6);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    layout.set_margin_top(12);
+    layout.append(&gtk::Image::from_icon_name("dialog-warning-symbolic"));
+    let label = gtk::Label::new(None);
+    label.set_wrap(true);
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    label.add_css_class("warning");
+    layout.append(&label);
+    watch(cx, &layout, move |layout| {
+        state.clipping.with(|clipping| {
+            layout.set_visible(clipping.is_some());
+            label.set_text(&clipping.as_ref().map(Clipping::advice).unwrap_or_default());
+        })
+    });
+    layout
+}
+
+/// Expander with a level history for every output and input, metering only while
+/// open, and the switch for clipping warnings.
+pub fn level_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let warn_clipping =
+        gtk::CheckButton::with_label(&tr("Warn about clipping on the default output"));
+    let enabled = move || {
+        state
+            .device_settings
+            .with_untracked(|store| store.clipping_warning)
+    };
+    warn_clipping.set_active(enabled());
+    warn_clipping.connect_toggled(move |warn_clipping| {
+        if warn_clipping.is_active() != enabled() {
+            dispatcher.dispatch(Action::SetClippingWarning(warn_clipping.is_active()));
+        }
+    });
+    layout.append(&warn_clipping);
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&rows);
+    let expander = gtk::Expander::builder()
+        .label("Levels")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let meters = Rc::new(RefCell::new(Vec::new()));
+    expander.connect_expanded_notify(move |expander| {
+        meters.borrow_mut().clear();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        if !expander.is_expanded() {
+            return;
+        }
+        for kind in [DeviceKind::Sink, DeviceKind::Source] {
+            let devices = Device::list(kind)
+                .map_err(|error| warn!(?error, "listing {}s to meter", kind.noun()))
+                .unwrap_or_default();
+            for device in devices {
+                let target = match kind {
+                    DeviceKind::Sink => format!("{}.monitor", device.name),
+                    DeviceKind::Source => device.name.clone(),
+                };
+                let (row, meter) = row(&device, &target);
+                rows.append(&row);
+                meters.borrow_mut().extend(meter);
+            }
+        }
+    });
+    expander
+}
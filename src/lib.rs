@@ -0,0 +1,1381 @@
+//! The pieces the pipeweld application is made of. Other GTK applications can
+//! embed its mixer through [`widgets`].
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex, OnceLock, PoisonError,
+    },
+    time::Instant,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::{gio, glib, prelude::*};
+use gtk::{Application, ApplicationWindow, Button};
+use leptos::*;
+use tracing::{info, instrument, warn};
+pub mod a11y;
+pub mod actions;
+pub mod alsa;
+pub mod app_outputs;
+pub mod audible;
+pub mod auto_pause;
+pub mod av_sync;
+pub mod backend;
+pub mod backup;
+pub mod balance;
+pub mod bench;
+pub mod bit_perfect;
+pub mod bluetooth;
+pub mod bug_report;
+pub mod captures;
+pub mod cards;
+pub mod channel_mix;
+pub mod cli;
+pub mod compat;
+pub mod compositor;
+pub mod config;
+pub mod cycle;
+pub mod delay;
+pub mod device_settings;
+pub mod devices;
+pub mod diagnostics;
+pub mod easyeffects;
+pub mod equalizer;
+pub mod errors;
+pub mod events;
+pub mod export;
+pub mod fifo;
+pub mod filter_chain;
+pub mod first_use;
+pub mod flat_volumes;
+pub mod focus_volume;
+pub mod gain_staging;
+pub mod game_mode;
+pub mod grace;
+pub mod graph_file;
+pub mod groups;
+pub mod headset;
+pub mod health;
+pub mod hold;
+pub mod http_stream;
+pub mod i18n;
+pub mod input_routing;
+pub mod inspector;
+pub mod instance;
+pub mod jack;
+pub mod levels;
+pub mod logs;
+pub mod loopback;
+pub mod media_players;
+pub mod midi;
+pub mod migrations;
+pub mod modules;
+pub mod night_mode;
+pub mod noise_suppression;
+pub mod notifications;
+pub mod osd;
+pub mod pads;
+pub mod panel;
+pub mod passthrough;
+pub mod patchbay;
+pub mod power;
+pub mod presets;
+pub mod priorities;
+pub mod pro_audio;
+pub mod profiler;
+pub mod registry;
+pub mod relative_volume;
+pub mod replay;
+pub mod resources;
+pub mod routing;
+pub mod rules;
+pub mod schedule;
+pub mod service;
+pub mod session;
+pub mod shortcuts;
+pub mod shutdown;
+pub mod signals;
+pub mod smart_defaults;
+pub mod speaker_test;
+pub mod startup;
+pub mod state;
+pub mod status;
+pub mod streams;
+pub mod switch_on_connect;
+pub mod table;
+pub mod talkback;
+pub mod theme;
+pub mod timeline;
+pub mod tool_output;
+pub mod transport;
+pub mod tray;
+pub mod troubleshoot;
+pub mod tui;
+pub mod ui_state;
+pub mod upmix;
+pub mod virtual_devices;
+pub mod volume_lock;
+pub mod volume_panels;
+pub mod widgets;
+pub mod window_input;
+pub mod workspaces;
+pub mod zones;
+pub mod extensions {
+    use std::{
+        cell::{Cell, Ref},
+        collections::HashSet,
+        hash::Hash,
+        rc::Rc,
+    };
+
+    use super::*;
+
+    /// A widget made in a scope, whose effects and signal handlers go away along
+    /// with the scope, like the child scopes [`suspense`] renders into.
+    pub struct Reactive<T> {
+        inner: T,
+        cx: Scope,
+    }
+
+    pub trait InScope: Sized {
+        fn in_scope(cx: Scope) -> Reactive<Self>;
+    }
+
+    /// Runs `effect` with `widget` whenever a signal it reads changes, for as long
+    /// as both the scope and the widget live. An effect holding on to the widget
+    /// itself would keep it alive after it's taken out of its window.
+    pub fn watch<W: IsA<glib::Object>>(cx: Scope, widget: &W, effect: impl Fn(&W) + 'static) {
+        let widget = widget.downgrade();
+        create_effect(cx, move |_| {
+            if let Some(widget) = widget.upgrade() {
+                effect(&widget);
+            }
+        });
+    }
+
+    /// Disconnects `handler` from `widget` once the scope is disposed.
+    pub fn disconnect_on_cleanup<W: IsA<glib::Object>>(
+        cx: Scope,
+        widget: &W,
+        handler: glib::SignalHandlerId,
+    ) {
+        let widget = widget.downgrade();
+        on_cleanup(cx, move || {
+            if let Some(widget) = widget.upgrade() {
+                widget.disconnect(handler);
+            }
+        });
+    }
+
+    /// Keeps the property called `property` of `widget` at `value`, for properties
+    /// without a binding of their own:
+    ///
+    /// ```no_run
+    /// # use leptos::*;
+    /// # use pipeweld::extensions::*;
+    /// # fn mute(cx: Scope, muted: RwSignal<bool>) -> gtk::ToggleButton {
+    /// let button = gtk::ToggleButton::new();
+    /// bind_property(cx, &button, "active", move || muted.get());
+    /// button
+    /// # }
+    /// ```
+    ///
+    /// Panics if there's no such property, or it takes another type, like GTK does.
+    pub fn bind_property<W, V>(
+        cx: Scope,
+        widget: &W,
+        property: &'static str,
+        value: impl Fn() -> V + 'static,
+    ) where
+        W: IsA<glib::Object>,
+        V: ToValue,
+    {
+        watch(cx, widget, move |widget| {
+            widget.set_property(property, &value())
+        });
+    }
+
+    /// Keeps the label of `widget` at `label`, for every widget with one, like
+    /// labels, buttons, check buttons and expanders.
+    pub fn bind_label<W: IsA<glib::Object>>(
+        cx: Scope,
+        widget: &W,
+        label: impl Fn() -> String + 'static,
+    ) {
+        bind_property(cx, widget, "label", label);
+    }
+
+    /// Connects `handler` to the signal called `signal` of `widget` until the scope
+    /// is disposed, for signals without a `connect_` method of their own. It gets
+    /// the signal's arguments, the widget first, and returns what the signal does.
+    ///
+    /// ```no_run
+    /// # use gtk::prelude::*;
+    /// # use leptos::*;
+    /// # use pipeweld::extensions::*;
+    /// # fn apply(cx: Scope, applied: RwSignal<usize>) -> gtk::Button {
+    /// let button = gtk::Button::with_label("Apply");
+    /// on_signal(cx, &button, "clicked", move |_| {
+    ///     applied.update(|applied| *applied += 1);
+    ///     None
+    /// });
+    /// button
+    /// # }
+    /// ```
+    pub fn on_signal<W: IsA<glib::Object>>(
+        cx: Scope,
+        widget: &W,
+        signal: &str,
+        handler: impl Fn(&[glib::Value]) -> Option<glib::Value> + 'static,
+    ) {
+        let handler = widget.connect_local(signal, false, handler);
+        disconnect_on_cleanup(cx, widget, handler);
+    }
+
+    // Widgets are reference counted, what the closures get is the widget itself, not
+    // a copy of it.
+    impl<T: IsA<glib::Object>> Reactive<T> {
+        /// Runs `modifier` with the widget whenever a signal it reads changes.
+        pub fn reactive<F: Fn(&T) + 'static>(self, modifier: F) -> Self {
+            watch(self.cx, &self.inner, modifier);
+            self
+        }
+        /// Runs `modifier` with the widget once, for what never changes.
+        pub fn constant<F: FnOnce(&T)>(self, modifier: F) -> Self {
+            modifier(&self.inner);
+            self
+        }
+        /// Connects a signal handler, as in `.on(|button| button.connect_clicked(…))`,
+        /// until the scope is disposed.
+        pub fn on(self, connect: impl FnOnce(&T) -> glib::SignalHandlerId) -> Self {
+            let handler = connect(&self.inner);
+            disconnect_on_cleanup(self.cx, &self.inner, handler);
+            self
+        }
+        /// Like [`bind_property`].
+        pub fn bind_property<V: ToValue>(
+            self,
+            property: &'static str,
+            value: impl Fn() -> V + 'static,
+        ) -> Self {
+            bind_property(self.cx, &self.inner, property, value);
+            self
+        }
+        /// Like [`bind_label`].
+        pub fn bind_label(self, label: impl Fn() -> String + 'static) -> Self {
+            bind_label(self.cx, &self.inner, label);
+            self
+        }
+        /// Like [`on_signal`].
+        pub fn on_signal(
+            self,
+            signal: &str,
+            handler: impl Fn(&[glib::Value]) -> Option<glib::Value> + 'static,
+        ) -> Self {
+            on_signal(self.cx, &self.inner, signal, handler);
+            self
+        }
+    }
+    impl<I> AsRef<I> for Reactive<I> {
+        fn as_ref(&self) -> &I {
+            &self.inner
+        }
+    }
+
+    macro_rules! in_scope {
+        ($ty:ty) => {
+            impl InScope for $ty {
+                fn in_scope(cx: Scope) -> Reactive<Self> {
+                    Reactive {
+                        inner: Self::builder().build(),
+                        cx,
+                    }
+                }
+            }
+        };
+    }
+
+    impl Reactive<ApplicationWindow> {
+        pub fn in_scope(cx: Scope, application: &Application) -> Self {
+            Self {
+                inner: ApplicationWindow::builder()
+                    .application(application)
+                    .build(),
+                cx,
+            }
+        }
+    }
+
+    in_scope!(Button);
+    in_scope!(gtk::Box);
+    in_scope!(gtk::Label);
+    in_scope!(gtk::Scale);
+    in_scope!(gtk::Switch);
+    in_scope!(gtk::DropDown);
+    in_scope!(gtk::ProgressBar);
+    in_scope!(gtk::ListBox);
+    in_scope!(gtk::CheckButton);
+    in_scope!(gtk::Expander);
+
+    impl<T: IsA<glib::Object>> Reactive<T> {
+        fn bind<V: 'static>(self, value: impl Fn() -> V + 'static, set: fn(&T, V)) -> Self {
+            self.reactive(move |inner| set(inner, value()))
+        }
+    }
+
+    macro_rules! binding {
+        ($ty:ty, $(#[$doc:meta])* $name:ident: $value:ty => $set:expr) => {
+            impl Reactive<$ty> {
+                $(#[$doc])*
+                pub fn $name(self, value: impl Fn() -> $value + 'static) -> Self {
+                    self.bind(value, $set)
+                }
+            }
+        };
+    }
+
+    binding!(gtk::Scale,
+        /// Keeps the position at `value`, e.g. a volume in percent:
+        ///
+        /// ```no_run
+        /// # use gtk::prelude::*;
+        /// # use leptos::*;
+        /// # use pipeweld::extensions::*;
+        /// # fn slider(cx: Scope, volume: RwSignal<u32>) -> gtk::Scale {
+        /// gtk::Scale::in_scope(cx)
+        ///     .constant(|scale| scale.set_range(0., 100.))
+        ///     .value(move || volume.get().into())
+        ///     .as_ref()
+        ///     .clone()
+        /// # }
+        /// ```
+        ///
+        /// Setting the value emits `value-changed`, so handlers writing back to the
+        /// signal belong on `change-value`, which only the user emits.
+        value: f64 => |widget, value| widget.set_value(value)
+    );
+
+    impl Reactive<gtk::Scale> {
+        /// Binds the position both ways: it follows `value`, and positions picked by
+        /// the user go to `set`, kept within the range. Once `set` changes what
+        /// `value` reads the scale moves there, without calling `set` again.
+        ///
+        /// ```no_run
+        /// # use gtk::prelude::*;
+        /// # use leptos::*;
+        /// # use pipeweld::extensions::*;
+        /// # fn slider(cx: Scope, volume: RwSignal<u32>) -> gtk::Scale {
+        /// gtk::Scale::in_scope(cx)
+        ///     .constant(|scale| scale.set_range(0., 100.))
+        ///     .model(
+        ///         move || volume.get().into(),
+        ///         move |picked| volume.set(picked.round() as u32),
+        ///     )
+        ///     .as_ref()
+        ///     .clone()
+        /// # }
+        /// ```
+        pub fn model(self, value: impl Fn() -> f64 + 'static, set: impl Fn(f64) + 'static) -> Self {
+            self.value(value).on(move |scale| {
+                scale.connect_change_value(move |scale, _, picked| {
+                    let adjustment = scale.adjustment();
+                    set(picked.clamp(adjustment.lower(), adjustment.upper()));
+                    glib::signal::Inhibit(false)
+                })
+            })
+        }
+    }
+    binding!(gtk::Switch,
+        /// Keeps the switch on while `active` is true.
+        ///
+        /// ```no_run
+        /// # use gtk::prelude::*;
+        /// # use leptos::*;
+        /// # use pipeweld::extensions::*;
+        /// # fn switch(cx: Scope, muted: RwSignal<bool>) -> gtk::Switch {
+        /// gtk::Switch::in_scope(cx)
+        ///     .active(move || !muted.get())
+        ///     .as_ref()
+        ///     .clone()
+        /// # }
+        /// ```
+        active: bool => |widget, value| widget.set_active(value)
+    );
+    binding!(gtk::DropDown,
+        /// Keeps the `selected` position chosen, [`gtk::INVALID_LIST_POSITION`]
+        /// for none.
+        ///
+        /// ```no_run
+        /// # use gtk::prelude::*;
+        /// # use leptos::*;
+        /// # use pipeweld::extensions::*;
+        /// # fn profiles(cx: Scope, names: &[&str], active: RwSignal<Option<u32>>) -> gtk::DropDown {
+        /// let model = gtk::StringList::new(names);
+        /// gtk::DropDown::in_scope(cx)
+        ///     .constant(move |dropdown| dropdown.set_model(Some(&model)))
+        ///     .selected(move || active.get().unwrap_or(gtk::INVALID_LIST_POSITION))
+        ///     .as_ref()
+        ///     .clone()
+        /// # }
+        /// ```
+        selected: u32 => |widget, value| widget.set_selected(value)
+    );
+    binding!(gtk::Label,
+        /// Keeps the text at `label`.
+        ///
+        /// ```no_run
+        /// # use gtk::prelude::*;
+        /// # use leptos::*;
+        /// # use pipeweld::extensions::*;
+        /// # fn counter(cx: Scope, streams: RwSignal<usize>) -> gtk::Label {
+        /// gtk::Label::in_scope(cx)
+        ///     .label(move || format!("{} streams", streams.get()))
+        ///     .as_ref()
+        ///     .clone()
+        /// # }
+        /// ```
+        label: String => |widget, value| widget.set_label(&value)
+    );
+    binding!(gtk::ProgressBar,
+        /// Keeps the bar filled to `fraction`, between 0 and 1, e.g. for a level meter.
+        ///
+        /// ```no_run
+        /// # use gtk::prelude::*;
+        /// # use leptos::*;
+        /// # use pipeweld::extensions::*;
+        /// # fn meter(cx: Scope, peak: RwSignal<f32>) -> gtk::ProgressBar {
+        /// gtk::ProgressBar::in_scope(cx)
+        ///     .fraction(move || peak.get().into())
+        ///     .as_ref()
+        ///     .clone()
+        /// # }
+        /// ```
+        fraction: f64 => |widget, value| widget.set_fraction(value)
+    );
+
+    /// Mirrors `items` into a list store for [`gtk::ListView`] and [`gtk::ColumnView`],
+    /// whose factories read them back with [`list_item`]. Items are matched up by
+    /// `key`, so only those that came, went, moved or changed are touched, and the
+    /// other rows keep their widgets and selection:
+    ///
+    /// ```no_run
+    /// # use gtk::prelude::*;
+    /// # use leptos::*;
+    /// # use pipeweld::{extensions::*, streams::Stream};
+    /// # fn stream_list(cx: Scope, streams: RwSignal<Vec<Stream>>) -> gtk::ListView {
+    /// let store = list_store(cx, move || streams.get(), |stream| stream.index);
+    /// let factory = gtk::SignalListItemFactory::new();
+    /// factory.connect_setup(|_, item| {
+    ///     let item = item.downcast_ref::<gtk::ListItem>().unwrap();
+    ///     item.set_child(Some(&gtk::Label::new(None)));
+    /// });
+    /// factory.connect_bind(|_, item| {
+    ///     let item = item.downcast_ref::<gtk::ListItem>().unwrap();
+    ///     let stream = list_item::<Stream>(&item.item().unwrap()).index;
+    ///     let label = item.child().and_downcast::<gtk::Label>().unwrap();
+    ///     label.set_text(&format!("Stream #{stream}"));
+    /// });
+    /// gtk::ListView::new(Some(gtk::NoSelection::new(Some(store))), Some(factory))
+    /// # }
+    /// ```
+    pub fn list_store<T, K>(
+        cx: Scope,
+        items: impl Fn() -> Vec<T> + 'static,
+        key: impl Fn(&T) -> K + 'static,
+    ) -> gio::ListStore
+    where
+        T: Clone + PartialEq + 'static,
+        K: Eq + Hash + 'static,
+    {
+        let store = gio::ListStore::new(glib::BoxedAnyObject::static_type());
+        let weak = store.downgrade();
+        create_effect(cx, move |mirrored: Option<Vec<T>>| {
+            let items = items();
+            let mut mirrored = mirrored.unwrap_or_default();
+            let Some(store) = weak.upgrade() else {
+                return mirrored;
+            };
+            let keys = items.iter().map(&key).collect::<HashSet<_>>();
+            for position in (0..mirrored.len()).rev() {
+                if !keys.contains(&key(&mirrored[position])) {
+                    mirrored.remove(position);
+                    store.remove(position as u32);
+                }
+            }
+            // what's left is in the new order already, but for moves and additions
+            for (position, item) in items.iter().enumerate() {
+                let object = || glib::BoxedAnyObject::new(item.clone());
+                let item_key = key(item);
+                match mirrored[position..]
+                    .iter()
+                    .position(|mirrored| key(mirrored) == item_key)
+                {
+                    Some(0) if mirrored[position] == *item => {}
+                    Some(0) => {
+                        mirrored[position] = item.clone();
+                        store.splice(position as u32, 1, &[object()]);
+                    }
+                    Some(offset) => {
+                        mirrored.remove(position + offset);
+                        store.remove((position + offset) as u32);
+                        mirrored.insert(position, item.clone());
+                        store.insert(position as u32, &object());
+                    }
+                    None => {
+                        mirrored.insert(position, item.clone());
+                        store.insert(position as u32, &object());
+                    }
+                }
+            }
+            mirrored
+        });
+        store
+    }
+
+    /// The item of a [`list_store`] at a position of its list, e.g. a factory's
+    /// [`gtk::ListItem::item`].
+    pub fn list_item<T: 'static>(object: &glib::Object) -> Ref<'_, T> {
+        object
+            .downcast_ref::<glib::BoxedAnyObject>()
+            .expect("items of list_store are boxed")
+            .borrow()
+    }
+
+    /// How far a [`resource`] got.
+    #[derive(Debug, Clone)]
+    pub enum Loaded<T> {
+        Loading,
+        Failed(Rc<eyre::Report>),
+        Ready(T),
+    }
+
+    /// A value fetched off the main loop, like a list of the server's devices.
+    #[derive(Debug)]
+    pub struct Resource<T: 'static> {
+        pub state: RwSignal<Loaded<T>>,
+        trigger: Trigger,
+    }
+
+    impl<T> Clone for Resource<T> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+
+    impl<T> Copy for Resource<T> {}
+
+    impl<T: Clone> Resource<T> {
+        /// The value, once there is one.
+        pub fn ready(&self) -> Option<T> {
+            self.state.with(|state| match state {
+                Loaded::Ready(value) => Some(value.clone()),
+                _ => None,
+            })
+        }
+
+        /// Fetches the value again, e.g. after a server event.
+        pub fn refetch(&self) {
+            self.trigger.notify();
+        }
+    }
+
+    /// Runs `fetch` on a thread of its own with what `source` returns, and again
+    /// whenever a signal `source` reads changes. While it runs again, the resource
+    /// keeps the last value, and results of runs overtaken by a newer one are dropped:
+    ///
+    /// ```no_run
+    /// # use leptos::*;
+    /// # use pipeweld::{devices::{Device, DeviceKind}, extensions::*};
+    /// # fn sinks(cx: Scope) -> gtk::Box {
+    /// let sinks = resource(cx, || DeviceKind::Sink, Device::list);
+    /// suspense(cx, sinks, |_, sinks| {
+    ///     gtk::Label::new(Some(&format!("{} outputs", sinks.len())))
+    /// })
+    /// # }
+    /// ```
+    pub fn resource<S, T>(
+        cx: Scope,
+        source: impl Fn() -> S + 'static,
+        fetch: fn(S) -> Result<T>,
+    ) -> Resource<T>
+    where
+        S: Send + 'static,
+        T: Send + 'static,
+    {
+        let resource = Resource {
+            state: create_rw_signal(cx, Loaded::Loading),
+            trigger: create_trigger(cx),
+        };
+        let generation = Rc::new(Cell::new(0_u64));
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        receiver.attach(None, {
+            let generation = generation.clone();
+            move |(fetched, result): (u64, Result<T>)| {
+                if fetched == generation.get() {
+                    resource.state.set(match result {
+                        Ok(value) => Loaded::Ready(value),
+                        Err(error) => Loaded::Failed(Rc::new(error)),
+                    });
+                }
+                glib::Continue(true)
+            }
+        });
+        create_effect(cx, move |_| {
+            resource.trigger.track();
+            let source = source();
+            generation.set(generation.get() + 1);
+            let fetching = generation.get();
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                sender.send((fetching, fetch(source))).ok();
+            });
+        });
+        resource
+    }
+
+    /// Shows a spinner until `resource` is fetched, then what `ready` builds out of
+    /// it, or why fetching failed. `ready` runs again in a new child scope, disposing
+    /// of the last one, when the resource or a signal `ready` reads changes.
+    pub fn suspense<T, W>(
+        cx: Scope,
+        resource: Resource<T>,
+        ready: impl Fn(Scope, &T) -> W + 'static,
+    ) -> gtk::Box
+    where
+        W: IsA<gtk::Widget>,
+    {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        let weak = container.downgrade();
+        create_effect(cx, move |rendered: Option<Option<ScopeDisposer>>| {
+            if let Some(rendered) = rendered.flatten() {
+                rendered.dispose();
+            }
+            let container = weak.upgrade()?;
+            while let Some(child) = container.first_child() {
+                container.remove(&child);
+            }
+            let ((), rendered) = cx.run_child_scope(|cx| {
+                resource.state.with(|state| match state {
+                    Loaded::Loading => {
+                        let spinner = gtk::Spinner::new();
+                        spinner.start();
+                        container.append(&spinner);
+                    }
+                    Loaded::Failed(error) => {
+                        let label = gtk::Label::new(Some(&error.to_string()));
+                        label.set_wrap(true);
+                        label.add_css_class("dim-label");
+                        container.append(&label);
+                    }
+                    Loaded::Ready(value) => container.append(&ready(cx, value)),
+                })
+            });
+            Some(rendered)
+        });
+        container
+    }
+
+    /// Widgets [`view!`](crate::view) can put others into.
+    pub trait Children {
+        fn add_child(&self, child: &impl IsA<gtk::Widget>);
+    }
+
+    impl Children for gtk::Box {
+        fn add_child(&self, child: &impl IsA<gtk::Widget>) {
+            self.append(child);
+        }
+    }
+
+    macro_rules! single_child {
+        ($ty:ty) => {
+            impl Children for $ty {
+                fn add_child(&self, child: &impl IsA<gtk::Widget>) {
+                    self.set_child(Some(child));
+                }
+            }
+        };
+    }
+
+    single_child!(ApplicationWindow);
+    single_child!(gtk::Expander);
+    single_child!(gtk::ScrolledWindow);
+    single_child!(gtk::Frame);
+
+    /// Builds a tree of widgets. Each one is written as its type, then optionally
+    /// builder properties in parentheses, attributes in braces and children in brackets:
+    ///
+    /// ```no_run
+    /// # use gtk::{prelude::*, Orientation};
+    /// # use leptos::*;
+    /// # fn panel(cx: Scope, muted: RwSignal<bool>) -> gtk::Box {
+    /// pipeweld::view! { cx,
+    ///     gtk::Box(orientation: Orientation::Vertical, spacing: 6) [
+    ///         gtk::Label(label: "Output") { add_css_class("heading") },
+    ///         gtk::Button(label: "Mute") {
+    ///             bind set_sensitive: !muted.get(),
+    ///             on connect_clicked: move |_| muted.set(true),
+    ///         },
+    ///         (gtk::Separator::new(Orientation::Horizontal)),
+    ///     ]
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// - `method(args)` calls the method once.
+    /// - `bind method: value` calls it with `value` again whenever a signal read by
+    ///   `value` changes, until the scope is disposed.
+    /// - `on connect_signal: handler` connects a handler until the scope is disposed.
+    /// - `(expression)` as a child adds an already built widget.
+    #[macro_export]
+    macro_rules! view {
+        (@tree $cx:ident,
+            $($ty:ident)::+
+            $( ( $($property:ident : $value:expr),* $(,)? ) )?
+            $( { $($attributes:tt)* } )?
+            $( [ $($children:tt)* ] )?
+        ) => {{
+            let widget = <$($ty)::+>::builder() $($(.$property($value))*)? .build();
+            $crate::view!(@attributes widget $cx, $($($attributes)*)?);
+            $crate::view!(@children widget $cx, $($($children)*)?);
+            widget
+        }};
+
+        (@attributes $widget:ident $cx:ident,) => {};
+        (@attributes $widget:ident $cx:ident,
+            bind $method:ident : $value:expr $(, $($rest:tt)*)?
+        ) => {
+            $crate::extensions::watch($cx, &$widget, move |$widget| {
+                $widget.$method($value);
+            });
+            $crate::view!(@attributes $widget $cx, $($($rest)*)?);
+        };
+        (@attributes $widget:ident $cx:ident,
+            on $signal:ident : $handler:expr $(, $($rest:tt)*)?
+        ) => {
+            $crate::extensions::disconnect_on_cleanup($cx, &$widget, $widget.$signal($handler));
+            $crate::view!(@attributes $widget $cx, $($($rest)*)?);
+        };
+        (@attributes $widget:ident $cx:ident,
+            $method:ident ( $($argument:expr),* $(,)? ) $(, $($rest:tt)*)?
+        ) => {
+            $widget.$method($($argument),*);
+            $crate::view!(@attributes $widget $cx, $($($rest)*)?);
+        };
+
+        (@children $parent:ident $cx:ident,) => {};
+        (@children $parent:ident $cx:ident,
+            ( $child:expr ) $(, $($rest:tt)*)?
+        ) => {
+            $crate::extensions::Children::add_child(&$parent, &$child);
+            $crate::view!(@children $parent $cx, $($($rest)*)?);
+        };
+        (@children $parent:ident $cx:ident,
+            $($ty:ident)::+
+            $( ( $($properties:tt)* ) )?
+            $( { $($attributes:tt)* } )?
+            $( [ $($children:tt)* ] )?
+            $(, $($rest:tt)*)?
+        ) => {
+            $crate::extensions::Children::add_child(
+                &$parent,
+                &$crate::view!(@tree $cx,
+                    $($ty)::+
+                    $( ( $($properties)* ) )?
+                    $( { $($attributes)* } )?
+                    $( [ $($children)* ] )?
+                ),
+            );
+            $crate::view!(@children $parent $cx, $($($rest)*)?);
+        };
+
+        ($cx:expr, $($tree:tt)+) => {{
+            let cx: ::leptos::Scope = $cx;
+            $crate::view!(@tree cx, $($tree)+)
+        }};
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{
+            panic::{self, UnwindSafe},
+            sync::{mpsc, Mutex, OnceLock},
+        };
+
+        use super::*;
+
+        type Job = Box<dyn FnOnce() + Send>;
+
+        /// Runs `test` on the one thread GTK is initialized on, which without a
+        /// display it can't be, and the test is skipped.
+        fn on_gtk_thread(test: impl FnOnce() + Send + UnwindSafe + 'static) {
+            static WORKER: OnceLock<Option<Mutex<mpsc::Sender<Job>>>> = OnceLock::new();
+            let worker = WORKER.get_or_init(|| {
+                let (started, initialized) = mpsc::channel();
+                let (sender, jobs) = mpsc::channel::<Job>();
+                std::thread::spawn(move || {
+                    let ready = gtk::init().is_ok();
+                    started.send(ready).ok();
+                    if ready {
+                        for job in jobs {
+                            job();
+                        }
+                    }
+                });
+                initialized
+                    .recv()
+                    .unwrap_or(false)
+                    .then(|| Mutex::new(sender))
+            });
+            let Some(worker) = worker else {
+                return eprintln!("no display to initialize GTK with, skipping");
+            };
+            let (done, result) = mpsc::channel();
+            worker
+                .lock()
+                .unwrap()
+                .send(Box::new(move || {
+                    done.send(panic::catch_unwind(test)).ok();
+                }))
+                .unwrap();
+            if let Err(panic) = result.recv().unwrap() {
+                panic::resume_unwind(panic);
+            }
+        }
+
+        fn slider(cx: Scope) -> Reactive<gtk::Scale> {
+            gtk::Scale::in_scope(cx).constant(|scale| scale.set_range(0., 100.))
+        }
+
+        #[test]
+        fn bindings_follow_their_signals() {
+            on_gtk_thread(|| {
+                run_scope(create_runtime(), |cx| {
+                    let volume = create_rw_signal(cx, 30u32);
+                    let scale = slider(cx).value(move || volume.get().into());
+                    let on = create_rw_signal(cx, false);
+                    let switch = gtk::Switch::in_scope(cx).active(move || on.get());
+                    let streams = create_rw_signal(cx, 1usize);
+                    let label = gtk::Label::in_scope(cx)
+                        .label(move || format!("{} streams", streams.get()));
+                    assert_eq!(scale.as_ref().value(), 30.);
+                    assert!(!switch.as_ref().is_active());
+                    assert_eq!(label.as_ref().label(), "1 streams");
+
+                    volume.set(70);
+                    on.set(true);
+                    streams.set(3);
+                    assert_eq!(scale.as_ref().value(), 70.);
+                    assert!(switch.as_ref().is_active());
+                    assert_eq!(label.as_ref().label(), "3 streams");
+                });
+            });
+        }
+
+        #[test]
+        fn bindings_stop_with_their_scope() {
+            on_gtk_thread(|| {
+                run_scope(create_runtime(), |cx| {
+                    let volume = create_rw_signal(cx, 30u32);
+                    let (scale, disposer) = cx.run_child_scope(|cx| {
+                        slider(cx)
+                            .value(move || volume.get().into())
+                            .as_ref()
+                            .clone()
+                    });
+                    disposer.dispose();
+                    volume.set(70);
+                    assert_eq!(scale.value(), 30.);
+                });
+            });
+        }
+
+        #[test]
+        fn scale_model_sends_picked_positions_within_the_range() {
+            on_gtk_thread(|| {
+                run_scope(create_runtime(), |cx| {
+                    let volume = create_rw_signal(cx, 30u32);
+                    let picked = create_rw_signal(cx, Vec::<f64>::new());
+                    let scale = slider(cx)
+                        .model(
+                            move || volume.get().into(),
+                            move |position| {
+                                picked.update(|picked| picked.push(position));
+                                volume.set(position.round() as u32);
+                            },
+                        )
+                        .as_ref()
+                        .clone();
+                    let pick = |position: f64| {
+                        scale.emit_by_name::<bool>(
+                            "change-value",
+                            &[&gtk::ScrollType::Jump, &position],
+                        );
+                    };
+                    pick(42.4);
+                    assert_eq!(volume.get(), 42);
+                    assert_eq!(scale.value(), 42.);
+                    pick(150.);
+                    assert_eq!(volume.get(), 100);
+                    assert_eq!(scale.value(), 100.);
+                    // following `value` doesn't count as picking
+                    volume.set(10);
+                    assert_eq!(scale.value(), 10.);
+                    assert_eq!(picked.get(), vec![42.4, 100.]);
+                });
+            });
+        }
+    }
+}
+use devices::DeviceKind;
+
+pub fn app_id() -> String {
+    format!("it.niedzwiedz.{}", clap::crate_name!())
+}
+
+/// Adds command line switches named after application actions, with their help
+/// texts. Each hands its action to the running instance and exits.
+pub fn add_action_switches(app: &Application, switches: Vec<(&'static str, String)>) {
+    for (name, description) in &switches {
+        app.add_main_option(
+            name,
+            glib::Char::from(0),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::None,
+            description,
+            None,
+        );
+    }
+    app.connect_handle_local_options(move |app, options| {
+        let Some((name, _)) = switches.iter().find(|(name, _)| options.contains(name)) else {
+            // carry on starting up, or leave it to the other switches
+            return -1;
+        };
+        if let Err(error) = app.register(gio::Cancellable::NONE) {
+            eprintln!("[ERROR] Reaching {}: {error}", clap::crate_name!());
+            return 1;
+        }
+        app.activate_action(name, None);
+        0
+    });
+}
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Whether changes to the audio setup are only logged, see [`add_dry_run_option`].
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Adds `--dry-run`, which keeps the instance it starts from changing the audio
+/// setup, for trying out rules and presets. Whatever would have changed is logged.
+pub fn add_dry_run_option(app: &Application) {
+    app.add_main_option(
+        "dry-run",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Log changes to the audio setup instead of making them",
+        None,
+    );
+    app.connect_handle_local_options(|_, options| {
+        if options.contains("dry-run") {
+            DRY_RUN.store(true, Ordering::Relaxed);
+            info!("dry run, the audio setup stays as it is");
+        }
+        -1
+    });
+}
+
+static TRACE_ACTIONS: AtomicBool = AtomicBool::new(false);
+
+/// Whether a line is printed for every action, see [`add_trace_actions_option`].
+pub fn trace_actions() -> bool {
+    TRACE_ACTIONS.load(Ordering::Relaxed)
+}
+
+/// Adds `--trace-actions`, printing a line for every action as it finishes: what it
+/// was about, how it went and how long it took. Each action also runs in a
+/// tracing span with the same, for `RUST_LOG` to show.
+pub fn add_trace_actions_option(app: &Application) {
+    app.add_main_option(
+        "trace-actions",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Print what each action was about, how it went and how long it took",
+        None,
+    );
+    app.connect_handle_local_options(|_, options| {
+        if options.contains("trace-actions") {
+            TRACE_ACTIONS.store(true, Ordering::Relaxed);
+        }
+        -1
+    });
+}
+
+thread_local! {
+    // per thread, commands run in the background as well
+    static AUTOMATED: Cell<bool> = const { Cell::new(false) };
+}
+static LAST_CHANGE: Mutex<Option<(Instant, bool)>> = Mutex::new(None);
+
+/// When pipeweld last changed the audio setup, to tell its own changes from
+/// everyone else's, and whether it did so by itself, see [`automated`].
+pub fn last_change() -> Option<(Instant, bool)> {
+    *LAST_CHANGE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs `changes` as pipeweld's own doing, like rules reacting to the server,
+/// rather than something it was asked for.
+pub fn automated<T>(changes: impl FnOnce() -> T) -> T {
+    let outer = AUTOMATED.with(|automated| automated.replace(true));
+    let result = changes();
+    AUTOMATED.with(|automated| automated.set(outer));
+    result
+}
+
+/// Like [`checked_output`], for commands changing the audio setup, which a dry run
+/// only logs.
+pub fn run_change(command: &mut std::process::Command) -> Result<Vec<u8>> {
+    if let Some(answer) = replay::answer(command, true) {
+        return answer;
+    }
+    if dry_run() {
+        info!(?command, "dry run, not running");
+        return Ok(Vec::new());
+    }
+    // before running, the server may tell about the change before the command exits
+    *LAST_CHANGE.lock().unwrap_or_else(PoisonError::into_inner) =
+        Some((Instant::now(), AUTOMATED.with(Cell::get)));
+    checked_output(command)
+}
+
+/// Stdout of `command`, failing with what it printed to stderr if it exits
+/// unsuccessfully, since a bare "failed" gives nothing to act on.
+pub fn checked_output(command: &mut std::process::Command) -> Result<Vec<u8>> {
+    if let Some(answer) = replay::answer(command, false) {
+        return answer;
+    }
+    let output = spawn_checked(command);
+    replay::record_command(command, &output);
+    output
+}
+
+fn spawn_checked(command: &mut std::process::Command) -> Result<Vec<u8>> {
+    // what's printed is read, not shown, see [`tool_output`]
+    command.env("LC_ALL", "C");
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .wrap_err_with(|| format!("running {program}"))?;
+    if output.status.success() {
+        return Ok(output.stdout);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.trim() {
+        "" => Err(eyre!("{program} failed ({})", output.status)),
+        stderr => Err(eyre!("{program} failed ({}): {stderr}", output.status)),
+    }
+}
+
+pub struct AudioControls;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The thread audio commands queue up on, started with the first one.
+fn worker() -> mpsc::Sender<Job> {
+    static WORKER: OnceLock<Mutex<mpsc::Sender<Job>>> = OnceLock::new();
+    WORKER
+        .get_or_init(|| {
+            let (sender, receiver) = mpsc::channel::<Job>();
+            std::thread::Builder::new()
+                .name("audio-commands".to_owned())
+                .spawn(move || receiver.into_iter().for_each(|job| job()))
+                .expect("starting the audio command thread");
+            Mutex::new(sender)
+        })
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DiffValue(pub i32);
+
+impl std::fmt::Display for DiffValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self(diff) = self;
+        diff.gt(&0)
+            .then(|| write!(f, "+{diff}%"))
+            .unwrap_or_else(|| write!(f, "{diff}%"))
+    }
+}
+impl AudioControls {
+    /// Runs `command` off the main loop and hands its result to `done` back on it.
+    /// Commands run one after another, so volume steps land in order, and a slow or
+    /// hung server holds up the queue rather than the window. Commands queued while
+    /// [`automated`] run as such.
+    pub fn in_background<T: Send + 'static>(
+        command: impl FnOnce() -> Result<T> + Send + 'static,
+        done: impl FnOnce(Result<T>) + 'static,
+    ) {
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let mut done = Some(done);
+        receiver.attach(None, move |result| {
+            if let Some(done) = done.take() {
+                done(result);
+            }
+            glib::Continue(false)
+        });
+        let queued_automated = AUTOMATED.with(Cell::get);
+        let job: Job = Box::new(move || {
+            let result = match queued_automated {
+                true => automated(command),
+                false => command(),
+            };
+            // the main loop may be gone already, when quitting
+            sender.send(result).ok();
+        });
+        if worker().send(job).is_err() {
+            warn!("audio command thread is gone, dropping command");
+        }
+    }
+
+    /// Like [`Self::in_background`], for commands nobody waits for, logging failures.
+    pub fn detach(command: impl FnOnce() -> Result<()> + Send + 'static) {
+        Self::in_background(command, |result| {
+            if let Err(error) = result {
+                warn!(?error, "audio command failed");
+            }
+        });
+    }
+
+    /// Runs `pactl` with `args`, returning its stdout on success. Anything but
+    /// reading the server's state is skipped in a dry run.
+    pub fn pactl<I, S>(args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let mut command = std::process::Command::new("pactl");
+        command.args(args);
+        let reads = command
+            .get_args()
+            .find(|arg| !arg.to_string_lossy().starts_with('-'))
+            .is_some_and(|subcommand| {
+                let subcommand = subcommand.to_string_lossy();
+                ["list", "info", "stat"].contains(&subcommand.as_ref())
+                    || subcommand.starts_with("get-")
+            });
+        match reads {
+            true => checked_output(&mut command),
+            false => run_change(&mut command),
+        }
+        .map(|stdout| String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    /// Percent no volume goes above, see [`config::Volume`].
+    pub fn max_percent() -> u32 {
+        config::get().volume.max
+    }
+
+    /// Nudges the default sink's volume, never raising it above `max` percent.
+    #[instrument(ret, err)]
+    pub fn change_volume_percent(diff: DiffValue, max: Option<u32>) -> Result<()> {
+        let DiffValue(step) = diff;
+        match max {
+            Some(max) if step > 0 => {
+                let current = devices::Device::default_of(DeviceKind::Sink)?
+                    .map(|sink| sink.volume_percent())
+                    .unwrap_or_default();
+                let target = current.saturating_add_signed(step).min(max);
+                Self::set_volume_percent(DeviceKind::Sink, DeviceKind::Sink.default_alias(), target)
+            }
+            _ => Self::change_default_volume(DeviceKind::Sink, diff),
+        }
+    }
+
+    /// Nudges the volume of the default device of `kind`, up to the cap unless
+    /// steps are left to the server.
+    #[instrument(ret, err)]
+    pub fn change_default_volume(kind: DeviceKind, diff: DiffValue) -> Result<()> {
+        let DiffValue(step) = diff;
+        if step > 0 && config::get().volume.limit_steps {
+            let current = devices::Device::default_of(kind)?
+                .map(|device| device.volume_percent())
+                .unwrap_or_default();
+            return Self::set_volume_percent(
+                kind,
+                kind.default_alias(),
+                current.saturating_add_signed(step),
+            );
+        }
+        Self::pactl([
+            &format!("set-{}-volume", kind.noun()),
+            kind.default_alias(),
+            &diff.to_string(),
+        ])
+        .map(drop)
+    }
+
+    /// Sets the volume of `device`, held to the cap.
+    #[instrument(ret, err)]
+    pub fn set_volume_percent(kind: DeviceKind, device: &str, percent: u32) -> Result<()> {
+        Self::pactl([
+            &format!("set-{}-volume", kind.noun()),
+            device,
+            &format!("{}%", percent.min(Self::max_percent())),
+        ])
+        .map(drop)
+    }
+
+    /// Sets each channel of `device` on its own, `percents` in the order of its
+    /// channel map.
+    #[instrument(ret, err)]
+    pub fn set_channel_volumes(kind: DeviceKind, device: &str, percents: &[u32]) -> Result<()> {
+        Self::pactl(
+            [format!("set-{}-volume", kind.noun()), device.to_owned()]
+                .into_iter()
+                .chain(
+                    percents
+                        .iter()
+                        .map(|percent| format!("{}%", (*percent).min(Self::max_percent()))),
+                ),
+        )
+        .map(drop)
+    }
+
+    /// Suspends `device`, or wakes it up. The session manager suspends it again once
+    /// it idles long enough.
+    #[instrument(ret, err)]
+    pub fn set_suspended(kind: DeviceKind, device: &str, suspended: bool) -> Result<()> {
+        Self::pactl([
+            &format!("suspend-{}", kind.noun()),
+            device,
+            &u8::from(suspended).to_string(),
+        ])
+        .map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_stream_volume(stream: u32, percent: u32) -> Result<()> {
+        Self::pactl([
+            "set-sink-input-volume",
+            &stream.to_string(),
+            &format!("{}%", percent.min(Self::max_percent())),
+        ])
+        .map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_stream_mute(stream: u32, mute: bool) -> Result<()> {
+        Self::pactl([
+            "set-sink-input-mute".to_owned(),
+            stream.to_string(),
+            u8::from(mute).to_string(),
+        ])
+        .map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn change_stream_volume(stream: u32, diff: DiffValue) -> Result<()> {
+        Self::pactl([
+            "set-sink-input-volume",
+            &stream.to_string(),
+            &diff.to_string(),
+        ])
+        .map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_sink_formats(sink: &str, formats: &str) -> Result<()> {
+        Self::pactl(["set-sink-formats", sink, formats]).map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_capture_volume(capture: u32, percent: u32) -> Result<()> {
+        Self::pactl([
+            "set-source-output-volume",
+            &capture.to_string(),
+            &format!("{}%", percent.min(Self::max_percent())),
+        ])
+        .map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_capture_mute(capture: u32, mute: bool) -> Result<()> {
+        Self::pactl([
+            "set-source-output-mute".to_owned(),
+            capture.to_string(),
+            u8::from(mute).to_string(),
+        ])
+        .map(drop)
+    }
+
+    /// Moves a recording over to another source.
+    #[instrument(ret, err)]
+    pub fn move_capture(capture: u32, source: &str) -> Result<()> {
+        Self::pactl(["move-source-output", &capture.to_string(), source]).map(drop)
+    }
+
+    /// Moves a playback stream over to another sink.
+    #[instrument(ret, err)]
+    pub fn move_stream(stream: u32, sink: &str) -> Result<()> {
+        Self::pactl(["move-sink-input", &stream.to_string(), sink]).map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_mute(kind: DeviceKind, device: &str, mute: bool) -> Result<()> {
+        Self::pactl([
+            format!("set-{}-mute", kind.noun()),
+            device.to_owned(),
+            u8::from(mute).to_string(),
+        ])
+        .map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_port(kind: DeviceKind, device: &str, port: &str) -> Result<()> {
+        Self::pactl([&format!("set-{}-port", kind.noun()), device, port]).map(drop)
+    }
+
+    #[instrument(ret, err)]
+    pub fn toggle_mute(kind: DeviceKind) -> Result<()> {
+        Self::pactl([
+            &format!("set-{}-mute", kind.noun()),
+            kind.default_alias(),
+            "toggle",
+        ])
+        .map(drop)
+    }
+
+    /// Whether the default device of `kind` is muted, as the server has it right now.
+    #[instrument(ret, err)]
+    pub fn is_muted(kind: DeviceKind) -> Result<bool> {
+        let output = Self::pactl([&format!("get-{}-mute", kind.noun()), kind.default_alias()])?;
+        tool_output::field(&output, "Mute")
+            .and_then(tool_output::flag)
+            .ok_or_else(|| eyre!("unexpected mute state: {}", output.trim()))
+    }
+
+    #[instrument(ret, err)]
+    pub fn default_device(kind: DeviceKind) -> Result<String> {
+        Self::pactl([format!("get-default-{}", kind.noun())]).map(|name| name.trim().to_owned())
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_default(kind: DeviceKind, name: &str) -> Result<()> {
+        Self::pactl([&format!("set-default-{}", kind.noun()), name]).map(drop)
+    }
+
+    /// Whether a PulseAudio server, PipeWire's or the original, answers.
+    pub fn server_available() -> bool {
+        Self::pactl(["info"]).is_ok()
+    }
+
+    #[instrument(ret, err)]
+    pub fn set_card_profile(card: &str, profile: &str) -> Result<()> {
+        Self::pactl(["set-card-profile", card, profile]).map(drop)
+    }
+}
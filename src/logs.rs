@@ -0,0 +1,212 @@
+//! The log, kept in `$XDG_STATE_HOME/pipeweld/logs` besides going to stderr, so
+//! what happened before a bug showed up is still there to attach. `pipeweld.log`
+//! is moved to `pipeweld.log.1` once it grows past [`MAX_BYTES`], the older ones
+//! shifting up, and [`KEPT`] of them are kept. `pipeweld debug-bundle` packs the
+//! latest logs up with what the server says about itself and the graph, see
+//! [`bundle`].
+use std::{
+    fs::{DirBuilder, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, PoisonError},
+};
+
+use eyre::{Result, WrapErr};
+use gtk::glib;
+use tracing::warn;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::{backend, bug_report, checked_output, graph_file::GraphFile, ui_state, AudioControls};
+
+/// Size the log is rotated at.
+pub const MAX_BYTES: u64 = 4 * 1024 * 1024;
+/// Rotated logs kept besides the current one.
+pub const KEPT: usize = 4;
+/// Name of the current log.
+const NAME: &str = "pipeweld.log";
+
+pub fn directory() -> PathBuf {
+    ui_state::state_directory().join("logs")
+}
+
+/// The current log, then the rotated ones from the newest.
+fn files(directory: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    (0..=KEPT).map(move |number| match number {
+        0 => directory.join(NAME),
+        number => directory.join(format!("{NAME}.{number}")),
+    })
+}
+
+#[derive(Debug)]
+struct Open {
+    directory: PathBuf,
+    /// `None` until the first line, and after the file couldn't be opened.
+    file: Option<File>,
+    written: u64,
+    /// Set once opening failed, which is said only once.
+    failed: bool,
+}
+
+impl Open {
+    fn open(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            std::fs::create_dir_all(&self.directory)?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.directory.join(NAME))?;
+            self.written = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("just opened"))
+    }
+
+    /// Shifts every log up by one, dropping the oldest.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let files = files(&self.directory).collect::<Vec<_>>();
+        for pair in files.windows(2).rev() {
+            if pair[0].exists() {
+                std::fs::rename(&pair[0], &pair[1])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.written + line.len() as u64 > MAX_BYTES && self.written > 0 {
+            self.rotate()?;
+        }
+        self.open()?.write_all(line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Appends what's logged to the rotating file, for [`tracing_subscriber`]'s `fmt`
+/// layer. Failing to write doesn't fail logging, stderr still gets everything.
+#[derive(Debug)]
+pub struct LogFile {
+    open: Mutex<Open>,
+}
+
+impl LogFile {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            open: Mutex::new(Open {
+                directory,
+                file: None,
+                written: 0,
+                failed: false,
+            }),
+        }
+    }
+}
+
+impl Write for &LogFile {
+    fn write(&mut self, line: &[u8]) -> io::Result<usize> {
+        let mut open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(error) = open.write(line) {
+            open.file = None;
+            if !std::mem::replace(&mut open.failed, true) {
+                eprintln!(
+                    "[ERROR] writing the log into {}: {error}",
+                    open.directory.display()
+                );
+            }
+        }
+        Ok(line.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        match &mut open.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = &'a LogFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+/// Writes `contents` into `name` in `directory`, redacted as diagnostics are.
+fn add(directory: &Path, name: &str, contents: &str) -> Result<()> {
+    let path = directory.join(name);
+    std::fs::write(&path, bug_report::redact(contents))
+        .wrap_err_with(|| format!("writing {}", path.display()))
+}
+
+/// Packs the logs, `pactl info` and the graph into a gzipped tarball at `output`,
+/// returning the names put in. What couldn't be gathered is noted in its place, as
+/// with the graph when the server isn't running.
+pub fn bundle(output: &Path) -> Result<Vec<String>> {
+    // only the user can get at the runtime directory, and the staging directory is
+    // made here or not at all, rather than one someone else put there first
+    let staging = glib::user_runtime_dir().join(format!("pipeweld-debug-{}", std::process::id()));
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .wrap_err_with(|| format!("creating {}", staging.display()))?;
+    let gathered = gather(&staging).and_then(|names| {
+        checked_output(
+            Command::new("tar")
+                .arg("-czf")
+                .arg(output)
+                .arg("-C")
+                .arg(&staging)
+                .args(&names),
+        )
+        .wrap_err_with(|| format!("packing {}", output.display()))?;
+        Ok(names)
+    });
+    if let Err(error) = std::fs::remove_dir_all(&staging) {
+        warn!(?error, "removing {}", staging.display());
+    }
+    gathered
+}
+
+fn gather(staging: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for path in files(&directory()).filter(|path| path.exists()) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let contents =
+            std::fs::read(&path).wrap_err_with(|| format!("reading {}", path.display()))?;
+        add(staging, &name, &String::from_utf8_lossy(&contents))?;
+        names.push(name);
+    }
+    let info = AudioControls::pactl(["info"]).unwrap_or_else(|error| format!("{error:#}\n"));
+    add(
+        staging,
+        "backend.txt",
+        &format!(
+            "pipeweld {}\nbackend {}\n\n{info}",
+            clap::crate_version!(),
+            backend::current().name()
+        ),
+    )?;
+    names.push("backend.txt".to_owned());
+    let graph = GraphFile::take()
+        .and_then(|graph| serde_json::to_string_pretty(&graph).wrap_err("serializing the graph"));
+    match graph {
+        Ok(graph) => {
+            add(staging, "graph.json", &graph)?;
+            names.push("graph.json".to_owned());
+        }
+        Err(error) => {
+            add(staging, "graph-error.txt", &format!("{error:?}\n"))?;
+            names.push("graph-error.txt".to_owned());
+        }
+    }
+    Ok(names)
+}
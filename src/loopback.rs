@@ -0,0 +1,338 @@
+//! Loopbacks pipeweld manages on the user's behalf. Each one is a
+//! `module-loopback` whose stream carries a [`modules::OBJECT_TAG`] naming it,
+//! so the volume of the copy can be set independently of either end. Besides
+//! mirrors of one output on another there's listening to an input on the default
+//! output, to hear how the microphone sounds, which stops when pipeweld quits.
+use std::{cell::Cell, rc::Rc};
+
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    modules::{self, Module},
+    state::AppState,
+    streams::Stream,
+};
+
+/// Prefix of the object name of sink mirrors, `mirror:<from>:<to>`.
+const MIRROR: &str = "mirror";
+/// Prefix of the object name of inputs listened to, `listen:<source>`.
+const LISTEN: &str = "listen";
+/// Latency of listening, short so one's voice isn't heard late.
+const LISTEN_LATENCY_MS: u32 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loopback {
+    pub module: u32,
+    pub object: String,
+    /// The loopback's playback stream, gone for a moment while the module starts.
+    pub stream: Option<Stream>,
+}
+
+impl Loopback {
+    /// Every loopback pipeweld loaded, including ones from an earlier run.
+    pub fn list() -> Result<Vec<Self>> {
+        let streams = Stream::list()?;
+        Ok(Module::list()?
+            .into_iter()
+            .filter(|module| module.name == "module-loopback")
+            .filter_map(|module| {
+                let object = module.object()?.to_owned();
+                let stream = streams
+                    .iter()
+                    .find(|stream| stream.properties.get(modules::OBJECT_TAG) == Some(&object))
+                    .cloned();
+                Some(Self {
+                    module: module.index,
+                    object,
+                    stream,
+                })
+            })
+            .collect())
+    }
+
+    /// Copies everything played on sink `from` to sink `to`.
+    #[instrument(ret, err)]
+    pub fn mirror(from: &str, to: &str) -> Result<u32> {
+        let object = format!("{MIRROR}:{from}:{to}");
+        Module::load(
+            "module-loopback",
+            &[
+                format!("source={from}.monitor"),
+                format!("sink={to}"),
+                "source_dont_move=true".to_owned(),
+                "sink_dont_move=true".to_owned(),
+                format!("sink_input_properties={}", Module::tag(&object)),
+            ],
+        )
+    }
+
+    /// Plays source `source` on the default sink, following it to another default.
+    #[instrument(ret, err)]
+    pub fn listen(source: &str) -> Result<u32> {
+        let object = format!("{LISTEN}:{source}");
+        Module::load(
+            "module-loopback",
+            &[
+                format!("source={source}"),
+                "source_dont_move=true".to_owned(),
+                format!("latency_msec={LISTEN_LATENCY_MS}"),
+                format!("sink_input_properties={}", Module::tag(&object)),
+            ],
+        )
+    }
+
+    /// Name of the source listened to, `None` for other kinds of loopback.
+    pub fn listened(&self) -> Option<&str> {
+        self.object.strip_prefix(LISTEN)?.strip_prefix(':')
+    }
+
+    /// Sink names of a mirror, `None` for other kinds of loopback.
+    pub fn mirrored(&self) -> Option<(&str, &str)> {
+        let mut parts = self
+            .object
+            .strip_prefix(MIRROR)?
+            .strip_prefix(':')?
+            .split(':');
+        Some((parts.next()?, parts.next()?))
+    }
+}
+
+/// Listens to `source` or stops, doing nothing when it's as asked already.
+pub fn set_listening(source: &str, enabled: bool) -> Result<()> {
+    let loaded = Loopback::list()?
+        .into_iter()
+        .filter(|loopback| loopback.listened() == Some(source))
+        .map(|loopback| loopback.module)
+        .collect::<Vec<_>>();
+    match (loaded.is_empty(), enabled) {
+        (true, true) => Loopback::listen(source).map(drop),
+        (false, false) => loaded.into_iter().try_for_each(Module::unload),
+        _ => Ok(()),
+    }
+}
+
+/// Stops listening to every input, for when nobody's left to switch it off.
+pub fn stop_listening() -> Result<()> {
+    Loopback::list()?
+        .into_iter()
+        .filter(|loopback| loopback.listened().is_some())
+        .try_for_each(|loopback| Module::unload(loopback.module))
+}
+
+fn describe(sinks: &[Device], name: &str) -> String {
+    sinks
+        .iter()
+        .find(|sink| sink.name == name)
+        .map_or_else(|| name.to_owned(), |sink| sink.description.clone())
+}
+
+/// Lists sink mirrors with a volume slider each, and lets the user add one
+/// copying the default sink to another.
+pub fn mirror_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let panel = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    panel.append(&gtk::Label::new(Some(&tr("Mirrors"))));
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    panel.append(&rows);
+
+    let sinks = Device::list(DeviceKind::Sink)
+        .map_err(|error| warn!(?error, "listing sinks to mirror to"))
+        .unwrap_or_default();
+    let targets = gtk::DropDown::from_strings(
+        &sinks
+            .iter()
+            .map(|sink| sink.description.as_str())
+            .collect::<Vec<_>>(),
+    );
+    let add = gtk::Button::with_label(&tr("Mirror default output here"));
+    add.connect_clicked({
+        let dispatcher = dispatcher.clone();
+        let targets = targets.clone();
+        let names = sinks
+            .iter()
+            .map(|sink| sink.name.clone())
+            .collect::<Vec<_>>();
+        move |_| {
+            let from = state
+                .default_sink
+                .with_untracked(|sink| sink.as_ref().map(|sink| sink.name.clone()));
+            let to = names.get(targets.selected() as usize);
+            match (from, to) {
+                (Some(from), Some(to)) if from != *to => {
+                    dispatcher.dispatch(Action::CreateMirror {
+                        from,
+                        to: to.clone(),
+                    })
+                }
+                _ => warn!("pick an output other than the default one to mirror to"),
+            }
+        }
+    });
+    let add_row = gtk::Box::new(Orientation::Horizontal, 6);
+    add_row.append(&targets);
+    add_row.append(&add);
+    panel.append(&add_row);
+
+    create_effect(cx, move |previous: Option<Vec<(u32, Option<u32>)>>| {
+        let shown = state.loopbacks.with(|loopbacks| {
+            loopbacks
+                .iter()
+                .map(|loopback| (loopback.module, loopback.stream.as_ref().map(|s| s.index)))
+                .collect()
+        });
+        // volume changes come back as events, rebuilding then would fight a dragged slider
+        if previous.as_ref() == Some(&shown) {
+            return shown;
+        }
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        state.loopbacks.with_untracked(|loopbacks| {
+            for loopback in loopbacks {
+                let Some((from, to)) = loopback.mirrored() else {
+                    continue;
+                };
+                let row = gtk::Box::new(Orientation::Horizontal, 6);
+                row.append(&gtk::Label::new(Some(&format!(
+                    "{} → {}",
+                    describe(&sinks, from),
+                    describe(&sinks, to)
+                ))));
+                let volume = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+                volume.set_hexpand(true);
+                if let Some(stream) = &loopback.stream {
+                    volume.set_value(stream.volume_percent().into());
+                    let dispatcher = dispatcher.clone();
+                    let index = stream.index;
+                    volume.connect_value_changed(move |volume| {
+                        dispatcher.dispatch(Action::SetStreamVolume(index, volume.value() as u32))
+                    });
+                } else {
+                    volume.set_sensitive(false);
+                }
+                row.append(&volume);
+                let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+                let dispatcher = dispatcher.clone();
+                let module = loopback.module;
+                remove
+                    .connect_clicked(move |_| dispatcher.dispatch(Action::RemoveLoopback(module)));
+                row.append(&remove);
+                rows.append(&row);
+            }
+        });
+        shown
+    });
+    panel
+}
+
+/// Expander with a switch per input to hear it on the default output, and the
+/// volume it's heard at while switched on.
+pub fn listen_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    // rebuilt as inputs and listening come and go, not as the volume moves
+    let listed = create_memo(cx, move |_| {
+        let sources = state.sources.with(|sources| {
+            sources
+                .iter()
+                .map(|source| (source.name.clone(), source.description.clone()))
+                .collect::<Vec<_>>()
+        });
+        let listening = state.loopbacks.with(|loopbacks| {
+            loopbacks
+                .iter()
+                .filter_map(|loopback| {
+                    let source = loopback.listened()?.to_owned();
+                    Some((source, loopback.stream.as_ref().map(|stream| stream.index)))
+                })
+                .collect::<Vec<_>>()
+        });
+        (sources, listening)
+    });
+    let layout = rows.clone();
+    create_effect(cx, move |_| {
+        let (sources, listening) = listed.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        if sources.is_empty() {
+            let empty = gtk::Label::new(Some(&tr("No inputs.")));
+            empty.add_css_class("dim-label");
+            rows.append(&empty);
+        }
+        for (name, description) in sources {
+            let row = gtk::Box::new(Orientation::Vertical, 2);
+            let top = gtk::Box::new(Orientation::Horizontal, 6);
+            let label = gtk::Label::new(Some(&description));
+            label.set_hexpand(true);
+            label.set_halign(gtk::Align::Start);
+            top.append(&label);
+            let heard = listening
+                .iter()
+                .find(|(source, _)| *source == name)
+                .map(|(_, stream)| *stream);
+            let enabled = gtk::Switch::new();
+            enabled.set_tooltip_text(Some(&tr("Listen to this device")));
+            enabled.set_valign(gtk::Align::Center);
+            enabled.set_active(heard.is_some());
+            // set before connecting, the rows are built again once it took
+            let dispatched = Rc::new(Cell::new(false));
+            enabled.connect_active_notify({
+                let dispatcher = dispatcher.clone();
+                let name = name.clone();
+                move |enabled| {
+                    if !dispatched.replace(true) {
+                        dispatcher.dispatch(Action::SetListening {
+                            source: name.clone(),
+                            enabled: enabled.is_active(),
+                        });
+                    }
+                }
+            });
+            top.append(&enabled);
+            row.append(&top);
+            if let Some(stream) = heard {
+                let volume = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+                volume.set_tooltip_text(Some(&tr("Volume it's heard at")));
+                match stream {
+                    Some(index) => {
+                        let percent = state.loopbacks.with_untracked(|loopbacks| {
+                            loopbacks
+                                .iter()
+                                .filter_map(|loopback| loopback.stream.as_ref())
+                                .find(|stream| stream.index == index)
+                                .map(Stream::volume_percent)
+                        });
+                        volume.set_value(percent.unwrap_or(100).into());
+                        let dispatcher = dispatcher.clone();
+                        volume.connect_value_changed(move |volume| {
+                            dispatcher
+                                .dispatch(Action::SetStreamVolume(index, volume.value() as u32))
+                        });
+                    }
+                    None => volume.set_sensitive(false),
+                }
+                row.append(&volume);
+            }
+            rows.append(&row);
+        }
+    });
+
+    gtk::Expander::builder()
+        .label("Listen to inputs")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
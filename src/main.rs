@@ -1,103 +1,17 @@
-use eyre::{eyre, Result, WrapErr};
-use gtk::{prelude::*, Orientation};
-use gtk::{Application, ApplicationWindow, Button};
-use leptos::*;
-use tracing::{info, instrument};
-pub mod extensions {
-    use super::*;
-    pub struct Reactive<T> {
-        inner: T,
-        cx: Scope,
-    }
-
-    pub trait InScope: Sized {
-        fn in_scope(cx: Scope) -> Reactive<Self>;
-    }
-
-    impl<T: Clone + 'static> Reactive<T> {
-        pub fn reactive<F: Fn(&mut T) + 'static>(self, modifier: F) -> Self {
-            let inner = self.inner.clone();
-            create_effect(self.cx, move |_| {
-                let mut inner = inner.clone();
-                modifier(&mut inner);
-            });
-            self
-        }
-        pub fn constant<F: Fn(&mut T) + 'static>(mut self, modifier: F) -> Self {
-            modifier(&mut self.inner);
-            self
-        }
-    }
-    impl<I> AsRef<I> for Reactive<I> {
-        fn as_ref(&self) -> &I {
-            &self.inner
-        }
-    }
-
-    macro_rules! in_scope {
-        ($ty:ty) => {
-            impl InScope for $ty {
-                fn in_scope(cx: Scope) -> Reactive<Self> {
-                    Reactive {
-                        inner: Self::builder().build(),
-                        cx,
-                    }
-                }
-            }
-        };
-    }
-
-    impl Reactive<ApplicationWindow> {
-        pub fn in_scope(cx: Scope, application: &Application) -> Self {
-            Self {
-                inner: ApplicationWindow::builder()
-                    .application(application)
-                    .build(),
-                cx,
-            }
-        }
-    }
+use std::rc::Rc;
 
-    in_scope!(Button);
-    in_scope!(gtk::Box);
-}
-use extensions::*;
-use tracing_subscriber::EnvFilter;
-
-fn app_id() -> String {
-    format!("it.niedzwiedz.{}", clap::crate_name!())
-}
-
-pub struct AudioControls;
-
-#[derive(Debug, Clone, Copy)]
-pub struct DiffValue(i32);
-
-impl std::fmt::Display for DiffValue {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self(diff) = self;
-        diff.gt(&0)
-            .then(|| write!(f, "+{diff}%"))
-            .unwrap_or_else(|| write!(f, "{diff}%"))
-    }
-}
-impl AudioControls {
-    #[instrument(ret, err)]
-    pub fn change_volume_percent(diff: DiffValue) -> Result<()> {
-        std::process::Command::new("pactl")
-            .arg("set-sink-volume")
-            .arg("@DEFAULT_SINK@")
-            .arg(format!("{diff}"))
-            .output()
-            .wrap_err("running the command")
-            .and_then(|out| {
-                out.status
-                    .success()
-                    .then_some(())
-                    .ok_or_else(|| eyre!("command failed"))
-            })
-    }
-}
+use eyre::{Result, WrapErr};
+use gtk::prelude::*;
+use gtk::{Application, ApplicationWindow};
+use leptos::*;
+use pipeweld::{
+    a11y, actions, alsa, app_id, auto_pause, cli, compat, compositor, config, cycle,
+    device_settings, errors, events, fifo, first_use, focus_volume, game_mode, grace, headset,
+    i18n, inspector, logs, midi, night_mode, notifications, osd, pads, panel, power, registry,
+    replay, resources, rules, schedule, service, session, shortcuts, shutdown, signals, startup,
+    state, theme, timeline, tray, ui_state, window_input, AudioControls,
+};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 fn setup_tracing_subscriber() -> Result<()> {
     // Check if the RUST_LOG environment variable is set.
@@ -112,10 +26,16 @@ fn setup_tracing_subscriber() -> Result<()> {
         })
         .or_else(|_| EnvFilter::try_new("pipeweld=info,warn"))?;
 
-    // Set up the tracing subscriber with the composed filter and pretty-printing of spans.
-    let subscriber = tracing_subscriber::fmt()
-        .with_env_filter(env_filter)
-        .finish();
+    // Set up the tracing subscriber with the composed filter, printing to stderr and
+    // into the rotating log file.
+    let subscriber = tracing_subscriber::registry()
+        .with(env_filter)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(
+            fmt::layer()
+                .with_ansi(false)
+                .with_writer(logs::LogFile::new(logs::directory())),
+        );
 
     // Set the global default tracing subscriber.
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber.");
@@ -129,44 +49,168 @@ fn main() {
             eprintln!("[ERROR] Setting up logging: {message}");
         }
     }
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Err(message) = session::select_server(&args) {
+        eprintln!("[ERROR] {message}");
+        std::process::exit(1);
+    }
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+    if let Err(message) = replay::start(&args) {
+        eprintln!("[ERROR] {message}");
+        std::process::exit(1);
+    }
+    i18n::init();
+    if let Err(message) = resources::register() {
+        eprintln!("[ERROR] {message:?}");
+    }
     _ = create_scope(create_runtime(), |cx| {
         // Create a new application
         let app = Application::builder().application_id(app_id()).build();
+        pipeweld::add_dry_run_option(&app);
+        pipeweld::add_trace_actions_option(&app);
+        session::add_option(&app);
+        replay::add_options(&app);
+        cycle::add_options(&app);
+        focus_volume::add_options(&app);
+        panel::add_options(&app);
+        // Only the first instance starts up, a second launch hands over to it and
+        // exits before reading anything from the server. The tray icon and the
+        // volume popup outlive any window.
+        app.connect_startup(move |app| {
+            let state = state::AppState::new(cx);
+            let dispatcher = actions::Dispatcher::attach(app, state);
+            let rules = Rc::new(rules::Engine::new(dispatcher.clone()));
+            for problem in config::problems() {
+                errors::report(state, "reading config.toml", &eyre::eyre!("{problem}"));
+            }
+            device_settings::restore(state);
+            startup::spawn(state, dispatcher.automated());
+            grace::spawn(state, dispatcher.automated());
+            events::subscribe({
+                let rules = rules.clone();
+                move |event| {
+                    replay::record_event(event);
+                    let rules = rules.clone();
+                    state.apply(event, move || {
+                        rules.apply(event, state);
+                        timeline::record_event(state, event);
+                    });
+                }
+            });
+            compositor::subscribe(move |change| match change {
+                compositor::Change::Workspace(workspace) => {
+                    if state
+                        .workspace
+                        .with_untracked(|focused| *focused != Some(workspace.clone()))
+                    {
+                        state.workspace.set(Some(workspace));
+                        rules.apply(events::Event::workspace_changed(), state);
+                    }
+                }
+                compositor::Change::Fullscreen(fullscreen) => {
+                    if state.fullscreen.get_untracked() != fullscreen {
+                        state.fullscreen.set(fullscreen);
+                    }
+                }
+                compositor::Change::Focus(window) => state.focused_window.set(window),
+            });
+            state.spawn_reconciliation();
+            device_settings::enforce_limits(cx, state, dispatcher.automated());
+            night_mode::spawn(cx, state);
+            auto_pause::spawn(cx, state);
+            game_mode::spawn(cx, state);
+            power::spawn(cx, state, dispatcher.automated());
+            compat::spawn();
+            tray::spawn(cx, state, dispatcher.clone());
+            headset::spawn(cx, state, dispatcher.clone());
+            midi::spawn(cx, state, dispatcher.clone());
+            shortcuts::spawn(cx, state, dispatcher.clone());
+            pads::spawn(state, dispatcher.clone());
+            fifo::spawn(dispatcher.clone());
+            schedule::spawn(state, dispatcher.clone());
+            signals::spawn(dispatcher.clone());
+            shutdown::spawn(app);
+            resources::apply();
+            osd::spawn(cx, state);
+            theme::spawn(cx, state);
+            notifications::spawn(cx, state);
+            first_use::spawn(dispatcher.clone());
+            night_mode::register(app, dispatcher.clone());
+            cycle::register(app, dispatcher.clone());
+            focus_volume::register(app, dispatcher.clone());
+            inspector::register(cx, app, state);
+            service::register(cx, app, state, dispatcher.clone());
+            registry::builtin().spawn(&registry::Context {
+                cx,
+                app: app.clone(),
+                state,
+                dispatcher: dispatcher.clone(),
+            });
+
+            // Connect to "activate" signal of `app`
+            app.connect_activate(move |app| {
+                // launching again raises the window the first instance has open
+                match app.active_window() {
+                    Some(window) => window.present(),
+                    None => match panel::edge() {
+                        Some(edge) => panel::open(cx, app, state, dispatcher.clone(), edge),
+                        None => build_ui(cx, app, state, dispatcher.clone()),
+                    },
+                }
+            });
 
-        // Connect to "activate" signal of `app`
-        app.connect_activate(move |app| build_ui(cx, app));
+            // closing the window saves it too, quitting from the tray doesn't close it
+            app.connect_shutdown(move |_| {
+                ui_state::save(state);
+                shutdown::clean_up(state);
+            });
+        });
 
         // Run the application
         app.run();
     });
 }
 
-fn build_ui(cx: Scope, app: &Application) {
-    let diff_volume_button = move |diff: DiffValue| {
-        Button::in_scope(cx).constant(move |btn| {
-            btn.set_margin_top(12);
-            btn.set_margin_bottom(12);
-            btn.set_margin_start(12);
-            btn.set_margin_end(12);
-            btn.connect_clicked(move |_| {
-                AudioControls::change_volume_percent(diff).ok();
-            });
-            btn.set_label(&format!("{diff}"));
-        })
+fn build_ui(cx: Scope, app: &Application, state: state::AppState, dispatcher: actions::Dispatcher) {
+    if !AudioControls::server_available() {
+        return alsa::build_fallback_ui(app, dispatcher);
+    }
+    let context = registry::Context {
+        cx,
+        app: app.clone(),
+        state,
+        dispatcher: dispatcher.clone(),
+    };
+    let window = pipeweld::view! { cx,
+        ApplicationWindow(application: app) [
+            (registry::builtin().build(&context))
+        ]
     };
 
-    let window = Reactive::<ApplicationWindow>::in_scope(cx, app).constant(move |window| {
-        window.set_child(Some(
-            gtk::Box::in_scope(cx)
-                .constant(move |gtk_box| {
-                    gtk_box.set_orientation(Orientation::Vertical);
-                    gtk_box.append(diff_volume_button(DiffValue(-5)).as_ref());
-                    gtk_box.append(diff_volume_button(DiffValue(5)).as_ref());
-                })
-                .as_ref(),
-        ))
+    window.connect_close_request(move |window| {
+        ui_state::save(state);
+        if state
+            .device_settings
+            .with_untracked(|store| store.close_to_tray)
+        {
+            // the tray brings it back, see `Action::ShowWindow`
+            window.set_visible(false);
+            return gtk::Inhibit(true);
+        }
+        gtk::Inhibit(false)
     });
+    if let Some(size) = config::get().window_size {
+        window.set_default_size(size.width, size.height);
+    }
+    theme::name_panels(&window);
+    ui_state::restore(state, &window);
+    i18n::translate_panels(&window);
+    a11y::attach(&window);
+    a11y::focus_volume(&window);
+    window_input::attach(window.upcast_ref(), dispatcher);
 
     // Present window
-    window.as_ref().present();
+    window.present();
 }
@@ -0,0 +1,378 @@
+//! Media players on the session bus, reached over MPRIS: what each one plays, its
+//! play/pause, next and previous buttons, and its own volume for players that keep
+//! one. Pipeweld's own player, see [`crate::headset`], is left out.
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, Result};
+use gtk::{
+    gio,
+    glib::{self, ToVariant, Variant, VariantDict},
+    prelude::*,
+    Orientation,
+};
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    i18n::tr,
+};
+
+pub const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+pub const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+pub const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+/// How long a player gets to answer.
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the players are read while the panel is expanded.
+const REFRESH: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerCommand {
+    PlayPause,
+    Next,
+    Previous,
+}
+
+impl PlayerCommand {
+    fn method(self) -> &'static str {
+        match self {
+            Self::PlayPause => "PlayPause",
+            Self::Next => "Next",
+            Self::Previous => "Previous",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    pub bus_name: String,
+    /// What the player calls itself, like "Firefox".
+    pub identity: String,
+    pub playing: bool,
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    /// From 0 to 1, `None` for players without a volume of their own.
+    pub volume: Option<f64>,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
+}
+
+pub fn session_bus() -> Result<gio::DBusConnection> {
+    gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)
+        .map_err(|error| eyre!("connecting to the session bus: {error}"))
+}
+
+pub fn call(
+    connection: &gio::DBusConnection,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    method: &str,
+    parameters: Option<&Variant>,
+) -> Result<Variant> {
+    connection
+        .call_sync(
+            Some(destination),
+            path,
+            interface,
+            method,
+            parameters,
+            None,
+            gio::DBusCallFlags::NONE,
+            TIMEOUT.as_millis() as i32,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|error| eyre!("calling {method} on {destination}: {error}"))
+}
+
+/// Bus names of the players, apart from pipeweld's own.
+pub fn player_names(connection: &gio::DBusConnection) -> Result<Vec<String>> {
+    let (names,) = call(
+        connection,
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "ListNames",
+        None,
+    )?
+    .get::<(Vec<String>,)>()
+    .ok_or_else(|| eyre!("unexpected bus names"))?;
+    let own = format!("{PLAYER_PREFIX}{}", clap::crate_name!());
+    Ok(names
+        .into_iter()
+        .filter(|name| name.starts_with(PLAYER_PREFIX) && !name.starts_with(&own))
+        .collect())
+}
+
+impl Player {
+    /// Every player on the session bus, skipping those that don't answer.
+    pub fn list() -> Result<Vec<Self>> {
+        let connection = session_bus()?;
+        let mut players = player_names(&connection)?
+            .into_iter()
+            .filter_map(|name| {
+                Self::read(&connection, &name)
+                    .map_err(|error| warn!(?error, "reading player {name}"))
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        players.sort_by(|first, second| first.identity.cmp(&second.identity));
+        Ok(players)
+    }
+
+    fn read(connection: &gio::DBusConnection, name: &str) -> Result<Self> {
+        let reply = call(
+            connection,
+            name,
+            PLAYER_PATH,
+            PROPERTIES,
+            "GetAll",
+            Some(&(PLAYER_INTERFACE,).to_variant()),
+        )?;
+        let properties = VariantDict::new(Some(&reply.child_value(0)));
+        let flag = |key| {
+            properties
+                .lookup::<bool>(key)
+                .ok()
+                .flatten()
+                .unwrap_or(false)
+        };
+        let metadata = VariantDict::new(properties.lookup_value("Metadata", None).as_ref());
+        let identity = call(
+            connection,
+            name,
+            PLAYER_PATH,
+            PROPERTIES,
+            "Get",
+            Some(&(ROOT_INTERFACE, "Identity").to_variant()),
+        )
+        .ok()
+        .and_then(|identity| identity.get::<(Variant,)>())
+        .and_then(|(identity,)| identity.get::<String>())
+        .unwrap_or_else(|| name.trim_start_matches(PLAYER_PREFIX).to_owned());
+        Ok(Self {
+            bus_name: name.to_owned(),
+            identity,
+            playing: properties
+                .lookup::<String>("PlaybackStatus")
+                .ok()
+                .flatten()
+                .as_deref()
+                == Some("Playing"),
+            title: metadata
+                .lookup::<String>("xesam:title")
+                .ok()
+                .flatten()
+                .filter(|title| !title.is_empty()),
+            artists: metadata
+                .lookup::<Vec<String>>("xesam:artist")
+                .ok()
+                .flatten()
+                .unwrap_or_default(),
+            volume: properties.lookup::<f64>("Volume").ok().flatten(),
+            can_go_next: flag("CanGoNext"),
+            can_go_previous: flag("CanGoPrevious"),
+        })
+    }
+
+    /// Like "Artist – Title", or just the title.
+    pub fn track(&self) -> String {
+        match (self.artists.is_empty(), &self.title) {
+            (_, None) => tr("Nothing playing"),
+            (true, Some(title)) => title.clone(),
+            (false, Some(title)) => format!("{} – {title}", self.artists.join(", ")),
+        }
+    }
+}
+
+#[instrument(ret, err)]
+pub fn send(player: &str, command: PlayerCommand) -> Result<()> {
+    call(
+        &session_bus()?,
+        player,
+        PLAYER_PATH,
+        PLAYER_INTERFACE,
+        command.method(),
+        None,
+    )
+    .map(drop)
+}
+
+#[instrument(ret, err)]
+pub fn set_volume(player: &str, percent: u32) -> Result<()> {
+    let volume = f64::from(percent) / 100.;
+    call(
+        &session_bus()?,
+        player,
+        PLAYER_PATH,
+        PROPERTIES,
+        "Set",
+        Some(&(PLAYER_INTERFACE, "Volume", volume.to_variant()).to_variant()),
+    )
+    .map(drop)
+}
+
+/// The widgets of one player's row, updated in place as it's read again.
+struct Row {
+    layout: gtk::Box,
+    track: gtk::Label,
+    play: gtk::Button,
+    previous: gtk::Button,
+    next: gtk::Button,
+    volume: gtk::Scale,
+    /// Set while the scale follows the player, which notifies too.
+    syncing: Rc<Cell<bool>>,
+    /// When the user last moved the scale, before which the player may not have
+    /// caught up yet.
+    moved: Rc<Cell<Option<Instant>>>,
+}
+
+impl Row {
+    fn new(player: &Player, dispatcher: &Dispatcher) -> Self {
+        let layout = gtk::Box::new(Orientation::Vertical, 2);
+        let heading = gtk::Label::new(Some(&player.identity));
+        heading.set_halign(gtk::Align::Start);
+        heading.add_css_class("heading");
+        layout.append(&heading);
+        let track = gtk::Label::new(None);
+        track.set_halign(gtk::Align::Start);
+        track.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        layout.append(&track);
+        let controls = gtk::Box::new(Orientation::Horizontal, 6);
+        let button = |icon: &str, command: PlayerCommand| {
+            let button = gtk::Button::from_icon_name(icon);
+            let dispatcher = dispatcher.clone();
+            let bus_name = player.bus_name.clone();
+            button.connect_clicked(move |_| {
+                dispatcher.dispatch(Action::ControlPlayer {
+                    player: bus_name.clone(),
+                    command,
+                })
+            });
+            controls.append(&button);
+            button
+        };
+        let previous = button("media-skip-backward-symbolic", PlayerCommand::Previous);
+        let play = button("media-playback-start-symbolic", PlayerCommand::PlayPause);
+        let next = button("media-skip-forward-symbolic", PlayerCommand::Next);
+        let volume = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+        volume.set_hexpand(true);
+        volume.set_tooltip_text(Some(&tr("The player's own volume")));
+        controls.append(&volume);
+        layout.append(&controls);
+        let syncing = Rc::new(Cell::new(false));
+        let moved = Rc::new(Cell::new(None));
+        volume.connect_value_changed({
+            let (syncing, moved) = (syncing.clone(), moved.clone());
+            let dispatcher = dispatcher.clone();
+            let bus_name = player.bus_name.clone();
+            move |volume| {
+                if syncing.get() {
+                    return;
+                }
+                moved.set(Some(Instant::now()));
+                dispatcher.dispatch(Action::SetPlayerVolume {
+                    player: bus_name.clone(),
+                    percent: volume.value() as u32,
+                });
+            }
+        });
+        let row = Self {
+            layout,
+            track,
+            play,
+            previous,
+            next,
+            volume,
+            syncing,
+            moved,
+        };
+        row.update(player);
+        row
+    }
+
+    fn update(&self, player: &Player) {
+        self.track.set_text(&player.track());
+        self.play.set_icon_name(match player.playing {
+            true => "media-playback-pause-symbolic",
+            false => "media-playback-start-symbolic",
+        });
+        self.previous.set_sensitive(player.can_go_previous);
+        self.next.set_sensitive(player.can_go_next);
+        self.volume.set_visible(player.volume.is_some());
+        let moving = self
+            .moved
+            .get()
+            .is_some_and(|moved| moved.elapsed() < REFRESH * 2);
+        if let Some(volume) = player.volume.filter(|_| !moving) {
+            self.syncing.set(true);
+            self.volume.set_value((volume * 100.).round());
+            self.syncing.set(false);
+        }
+    }
+}
+
+/// Expander with a row per player: what it plays, its buttons and its volume, read
+/// again every [`REFRESH`] while open.
+pub fn player_panel(dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 12);
+    let empty = gtk::Label::new(Some(&tr("No media player is running.")));
+    empty.add_css_class("dim-label");
+    layout.append(&empty);
+    let expander = gtk::Expander::builder()
+        .label("Media players")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+
+    let rows = RefCell::new(HashMap::<String, Row>::new());
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, move |players: Vec<Player>| {
+        let mut rows = rows.borrow_mut();
+        rows.retain(|bus_name, row| {
+            let running = players.iter().any(|player| player.bus_name == *bus_name);
+            if !running {
+                layout.remove(&row.layout);
+            }
+            running
+        });
+        for player in &players {
+            match rows.get(&player.bus_name) {
+                Some(row) => row.update(player),
+                None => {
+                    let row = Row::new(player, &dispatcher);
+                    layout.append(&row.layout);
+                    rows.insert(player.bus_name.clone(), row);
+                }
+            }
+        }
+        empty.set_visible(players.is_empty());
+        glib::Continue(true)
+    });
+    let weak = expander.downgrade();
+    glib::timeout_add_local(REFRESH, move || {
+        let Some(expander) = weak.upgrade() else {
+            return glib::Continue(false);
+        };
+        if expander.is_expanded() {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                let players = Player::list()
+                    .map_err(|error| warn!(?error, "listing media players"))
+                    .unwrap_or_default();
+                sender.send(players).ok();
+            });
+        }
+        glib::Continue(true)
+    });
+    expander
+}
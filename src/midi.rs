@@ -0,0 +1,469 @@
+//! Faders, knobs and buttons of a MIDI control surface, mapped to volumes and mutes.
+//! Control changes are read with `aseqdump` from the ALSA sequencer, where
+//! PipeWire's MIDI bridge puts its ports alongside the hardware's, so the surface
+//! may be plugged in or be another application. A mapping is learned by picking what
+//! it controls and moving the control, and is kept with the device settings.
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    checked_output,
+    devices::DeviceKind,
+    i18n::tr,
+    state::AppState,
+    streams::Stream,
+};
+
+/// How long moves of a fader are gathered into one volume change, as it sends
+/// dozens a second.
+const SETTLE: Duration = Duration::from_millis(30);
+/// Largest value of a control change.
+const MAX_VALUE: u8 = 127;
+
+/// A control change number on a channel, as the surface sends it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Control {
+    /// From 0, shown from 1.
+    pub channel: u8,
+    pub controller: u8,
+}
+
+impl Control {
+    pub fn label(self) -> String {
+        format!("CC {} on channel {}", self.controller, self.channel + 1)
+    }
+}
+
+/// What a control is mapped to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "target", rename_all = "kebab-case")]
+pub enum MidiTarget {
+    /// Volume of a device, the default one by [`DeviceKind::default_alias`].
+    Volume { kind: DeviceKind, device: String },
+    /// Toggles the mute of a device on each press.
+    Mute { kind: DeviceKind, device: String },
+    /// Volume of all streams of an application, by `application.name`.
+    StreamVolume { application: String },
+}
+
+impl MidiTarget {
+    pub fn label(&self, state: AppState) -> String {
+        let device = |kind: DeviceKind, device: &str| {
+            if device == kind.default_alias() {
+                return match kind {
+                    DeviceKind::Sink => "the output".to_owned(),
+                    DeviceKind::Source => "the microphone".to_owned(),
+                };
+            }
+            state.devices(kind).with_untracked(|devices| {
+                devices
+                    .iter()
+                    .find(|candidate| candidate.name == device)
+                    .map_or_else(|| device.to_owned(), |found| found.description.clone())
+            })
+        };
+        match self {
+            Self::Volume { kind, device: name } => format!("Volume of {}", device(*kind, name)),
+            Self::Mute { kind, device: name } => format!("Mute {}", device(*kind, name)),
+            Self::StreamVolume { application } => format!("Volume of {application}"),
+        }
+    }
+
+    /// What the control at `value` does, if anything: faders set the volume from 0
+    /// to 100%, buttons toggle when pressed and not when let go.
+    fn action(&self, value: u8, state: AppState) -> Option<Action> {
+        let percent = u32::from(value) * 100 / u32::from(MAX_VALUE);
+        match self {
+            Self::Volume { kind, device } => {
+                Some(Action::SetVolume(*kind, device.clone(), percent))
+            }
+            Self::Mute { .. } if value == 0 => None,
+            Self::Mute { kind, device } if device == kind.default_alias() => {
+                Some(Action::ToggleMute(*kind))
+            }
+            Self::Mute { kind, device } => {
+                let muted = state.devices(*kind).with_untracked(|devices| {
+                    devices
+                        .iter()
+                        .find(|candidate| candidate.name == *device)
+                        .map(|found| found.mute)
+                })?;
+                Some(Action::SetMute(*kind, device.clone(), !muted))
+            }
+            Self::StreamVolume { application } => Some(Action::SetApplicationVolume {
+                application: application.clone(),
+                percent,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    #[serde(flatten)]
+    pub control: Control,
+    #[serde(flatten)]
+    pub target: MidiTarget,
+}
+
+/// A sequencer port controls can be read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MidiPort {
+    /// `client:port`, by the client's name, which outlives its number.
+    pub address: String,
+    pub label: String,
+}
+
+/// Ports of the sequencer, apart from its own.
+pub fn ports() -> Result<Vec<MidiPort>> {
+    let output = checked_output(Command::new("aseqdump").arg("--list"))?;
+    let output = String::from_utf8_lossy(&output);
+    Ok(output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (number, rest) = line.trim().split_once(char::is_whitespace)?;
+            let (client_number, port) = number.split_once(':')?;
+            let (client, name) = rest.trim().split_once("  ")?;
+            // the system's timer and announcements
+            if client_number == "0" {
+                return None;
+            }
+            Some(MidiPort {
+                address: format!("{client}:{port}"),
+                label: name.trim().to_owned(),
+            })
+        })
+        .collect())
+}
+
+/// Parses lines like ` 20:0   Control change   0, controller 7, value 100`.
+fn parse(line: &str) -> Option<(Control, u8)> {
+    let data = line.split_once("Control change")?.1;
+    let mut fields = data.split(',').map(str::trim);
+    let channel = fields.next()?.parse().ok()?;
+    let controller = fields.next()?.strip_prefix("controller ")?.parse().ok()?;
+    let value = fields.next()?.strip_prefix("value ")?.parse().ok()?;
+    Some((
+        Control {
+            channel,
+            controller,
+        },
+        value,
+    ))
+}
+
+/// Reads control changes of `port` until `aseqdump` is killed or dies.
+fn follow(
+    port: &str,
+    child: &Mutex<Option<Child>>,
+    sender: &glib::Sender<(Control, u8)>,
+) -> Result<()> {
+    let mut spawned = Command::new("aseqdump")
+        .args(["--port", port])
+        .env("LC_ALL", "C")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .wrap_err("spawning aseqdump")?;
+    let stdout = spawned.stdout.take().ok_or_else(|| eyre!("no stdout"))?;
+    *child.lock().expect("MIDI reader lock poisoned") = Some(spawned);
+    for line in BufReader::new(stdout).lines() {
+        let line = line.wrap_err("reading aseqdump")?;
+        match parse(&line) {
+            Some(change) => {
+                if sender.send(change).is_err() {
+                    break;
+                }
+            }
+            None => debug!(line, "skipping MIDI event"),
+        }
+    }
+    Ok(())
+}
+
+/// Changes of a control while learning map it, others run what they're mapped to.
+fn handle(state: AppState, dispatcher: &Dispatcher, control: Control, value: u8) {
+    if let Some(target) = state.midi_learning.get_untracked() {
+        info!(?control, ?target, "learned a MIDI control");
+        dispatcher.dispatch(Action::MapMidiControl {
+            control,
+            target: Some(target),
+        });
+        return dispatcher.dispatch(Action::LearnMidi(None));
+    }
+    let target = state.device_settings.with_untracked(|store| {
+        store
+            .midi_mappings
+            .iter()
+            .find(|mapping| mapping.control == control)
+            .map(|mapping| mapping.target.clone())
+    });
+    if let Some(action) = target.and_then(|target| target.action(value, state)) {
+        dispatcher.dispatch(action);
+    }
+}
+
+/// Listens to the picked port, and to another one once it's picked.
+pub fn spawn(cx: Scope, state: AppState, dispatcher: Dispatcher) {
+    let port = create_memo(cx, move |_| {
+        state.device_settings.with(|store| store.midi_port.clone())
+    });
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    // the last value of each control moved since the last change went out
+    let moved = Rc::new(RefCell::new(BTreeMap::<Control, u8>::new()));
+    receiver.attach(None, move |(control, value)| {
+        let first = moved.borrow().is_empty();
+        moved.borrow_mut().insert(control, value);
+        if first {
+            let moved = moved.clone();
+            let dispatcher = dispatcher.clone();
+            glib::timeout_add_local_once(SETTLE, move || {
+                for (control, value) in std::mem::take(&mut *moved.borrow_mut()) {
+                    handle(state, &dispatcher, control, value);
+                }
+            });
+        }
+        glib::Continue(true)
+    });
+    let reader = Arc::new(Mutex::new(None::<Child>));
+    create_effect(cx, move |_| {
+        if let Some(mut child) = reader.lock().expect("MIDI reader lock poisoned").take() {
+            child.kill().ok();
+            child.wait().ok();
+        }
+        let Some(port) = port.get() else {
+            return;
+        };
+        info!(port, "listening to MIDI controls");
+        let reader = reader.clone();
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            if let Err(error) = follow(&port, &reader, &sender) {
+                warn!(?error, port, "reading MIDI controls");
+            }
+        });
+    });
+}
+
+/// Targets to learn, the defaults first, then each output and each application
+/// that's playing.
+fn targets(state: AppState) -> Vec<MidiTarget> {
+    let mut targets = Vec::new();
+    for kind in [DeviceKind::Sink, DeviceKind::Source] {
+        let device = kind.default_alias().to_owned();
+        targets.push(MidiTarget::Volume {
+            kind,
+            device: device.clone(),
+        });
+        targets.push(MidiTarget::Mute { kind, device });
+    }
+    state.sinks.with_untracked(|sinks| {
+        for sink in sinks {
+            targets.push(MidiTarget::Volume {
+                kind: DeviceKind::Sink,
+                device: sink.name.clone(),
+            });
+            targets.push(MidiTarget::Mute {
+                kind: DeviceKind::Sink,
+                device: sink.name.clone(),
+            });
+        }
+    });
+    let mut applications = Stream::list()
+        .map_err(|error| warn!(?error, "listing streams to map MIDI controls to"))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|stream| stream.properties.get("application.name").cloned())
+        .collect::<Vec<_>>();
+    applications.sort();
+    applications.dedup();
+    targets.extend(
+        applications
+            .into_iter()
+            .map(|application| MidiTarget::StreamVolume { application }),
+    );
+    targets
+}
+
+/// Expander picking the port to listen to, learning mappings and listing them.
+pub fn midi_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let port_row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(&tr("Control surface")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    port_row.append(&label);
+    let port = gtk::DropDown::from_strings(&[tr("Not listening").as_str()]);
+    port_row.append(&port);
+    layout.append(&port_row);
+
+    let learn_row = gtk::Box::new(Orientation::Horizontal, 6);
+    let target = gtk::DropDown::from_strings(&[]);
+    target.set_hexpand(true);
+    learn_row.append(&target);
+    let learn = gtk::Button::with_label(&tr("Learn"));
+    learn.set_tooltip_text(Some(&tr("Maps the next control moved on the surface")));
+    learn_row.append(&learn);
+    layout.append(&learn_row);
+    let hint = gtk::Label::new(Some(&tr("Move a fader, knob or button on the surface…")));
+    hint.add_css_class("dim-label");
+    hint.set_halign(gtk::Align::Start);
+    layout.append(&hint);
+
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&rows);
+
+    let listed = Rc::new(RefCell::new((
+        Vec::<MidiPort>::new(),
+        Vec::<MidiTarget>::new(),
+    )));
+    // replacing the ports moves the selection, which isn't the user picking
+    let refreshing = Rc::new(Cell::new(false));
+    // ports and streams aren't followed, they're read whenever the expander opens
+    let refresh = {
+        let (port, target, listed) = (port.clone(), target.clone(), listed.clone());
+        let refreshing = refreshing.clone();
+        move || {
+            let ports = ports()
+                .map_err(|error| warn!(?error, "listing MIDI ports"))
+                .unwrap_or_default();
+            let picked = state
+                .device_settings
+                .with_untracked(|store| store.midi_port.clone());
+            let mut labels = vec![tr("Not listening")];
+            labels.extend(ports.iter().map(|port| port.label.clone()));
+            // the picked surface stays listed while it's unplugged
+            let mut ports = ports;
+            if let Some(picked) = &picked {
+                if !ports.iter().any(|port| port.address == *picked) {
+                    labels.push(picked.clone());
+                    ports.push(MidiPort {
+                        address: picked.clone(),
+                        label: picked.clone(),
+                    });
+                }
+            }
+            let position = picked
+                .and_then(|picked| ports.iter().position(|port| port.address == picked))
+                .map_or(0, |position| position as u32 + 1);
+            let targets = targets(state);
+            let target_labels = targets
+                .iter()
+                .map(|target| target.label(state))
+                .collect::<Vec<_>>();
+            *listed.borrow_mut() = (ports, targets);
+            let strings = |labels: &[String]| {
+                gtk::StringList::new(&labels.iter().map(String::as_str).collect::<Vec<_>>())
+            };
+            refreshing.set(true);
+            port.set_model(Some(&strings(&labels)));
+            port.set_selected(position);
+            refreshing.set(false);
+            target.set_model(Some(&strings(&target_labels)));
+        }
+    };
+    port.connect_selected_notify({
+        let (dispatcher, listed) = (dispatcher.clone(), listed.clone());
+        move |port| {
+            if refreshing.get() {
+                return;
+            }
+            let picked = match port.selected() {
+                0 => None,
+                position => listed
+                    .borrow()
+                    .0
+                    .get(position as usize - 1)
+                    .map(|port| port.address.clone()),
+            };
+            let current = state
+                .device_settings
+                .with_untracked(|store| store.midi_port.clone());
+            if picked != current {
+                dispatcher.dispatch(Action::SetMidiPort(picked));
+            }
+        }
+    });
+    learn.connect_clicked({
+        let (dispatcher, target) = (dispatcher.clone(), target.clone());
+        move |_| {
+            let learning = match state.midi_learning.get_untracked() {
+                Some(_) => None,
+                None => listed.borrow().1.get(target.selected() as usize).cloned(),
+            };
+            dispatcher.dispatch(Action::LearnMidi(learning));
+        }
+    });
+    create_effect(cx, move |_| {
+        let learning = state.midi_learning.with(Option::is_some);
+        learn.set_label(match learning {
+            true => "Cancel",
+            false => "Learn",
+        });
+        hint.set_visible(learning);
+        target.set_sensitive(!learning);
+    });
+
+    let mappings = create_memo(cx, move |_| {
+        state
+            .device_settings
+            .with(|store| store.midi_mappings.clone())
+    });
+    create_effect(cx, move |_| {
+        let mappings = mappings.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        for mapping in mappings {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            let control = gtk::Label::new(Some(&mapping.control.label()));
+            control.add_css_class("dim-label");
+            row.append(&control);
+            let target = gtk::Label::new(Some(&mapping.target.label(state)));
+            target.set_hexpand(true);
+            target.set_halign(gtk::Align::Start);
+            target.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            row.append(&target);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            remove.set_tooltip_text(Some(&tr("Forget this control")));
+            let dispatcher = dispatcher.clone();
+            remove.connect_clicked(move |_| {
+                dispatcher.dispatch(Action::MapMidiControl {
+                    control: mapping.control,
+                    target: None,
+                })
+            });
+            row.append(&remove);
+            rows.append(&row);
+        }
+    });
+
+    let expander = gtk::Expander::builder()
+        .label("MIDI controls")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    expander.connect_expanded_notify(move |expander| {
+        if expander.is_expanded() {
+            refresh();
+        }
+    });
+    expander
+}
@@ -0,0 +1,252 @@
+//! Upgrades of the files pipeweld reads, for formats that change. Each file tells
+//! its version in a top-level `version` key, files from before there were versions
+//! count as version 0. Loading runs every step from the file's version on and
+//! writes the result back, keeping the old file next to it as
+//! `<name>.v<version>.bak`. A file the steps leave as it was isn't touched, so
+//! hand-written ones keep their comments.
+//!
+//! A format changing adds a step at the end of its list, taking the table from the
+//! version before to its own:
+//!
+//! ```no_run
+//! # use pipeweld::migrations::Format;
+//! const FORMAT: Format = Format {
+//!     name: "example.toml",
+//!     // version 1 calls `mute` `muted`
+//!     steps: &[|table| {
+//!         if let Some(mute) = table.remove("mute") {
+//!             table.insert("muted".to_owned(), mute);
+//!         }
+//!         Ok(())
+//!     }],
+//! };
+//! ```
+use std::path::Path;
+
+use eyre::{bail, Result, WrapErr};
+use tracing::info;
+
+/// Takes a file's table from the version before to the next one.
+pub type Step = fn(&mut toml::Table) -> Result<()>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Format {
+    /// Name of the file, for messages.
+    pub name: &'static str,
+    /// Step `n` upgrades version `n` to `n + 1`.
+    pub steps: &'static [Step],
+}
+
+impl Format {
+    /// The version this pipeweld writes.
+    pub fn current(&self) -> u32 {
+        self.steps.len() as u32
+    }
+
+    fn version(&self, table: &toml::Table) -> Result<u32> {
+        match table.get("version") {
+            None => Ok(0),
+            Some(version) => version
+                .as_integer()
+                .and_then(|version| u32::try_from(version).ok())
+                .map_or_else(|| bail!("version of {} is not a number", self.name), Ok),
+        }
+    }
+
+    /// Runs the steps from the version of `table` on, returning whether they
+    /// changed anything. The version is set to [`Self::current`] either way.
+    pub fn upgrade(&self, table: &mut toml::Table) -> Result<bool> {
+        let version = self.version(table)?;
+        if version > self.current() {
+            bail!(
+                "{} is of version {version}, written by a newer pipeweld than this one \
+                 (version {})",
+                self.name,
+                self.current()
+            );
+        }
+        let before = table.clone();
+        for (step, upgrade) in self.steps.iter().enumerate().skip(version as usize) {
+            upgrade(table)
+                .wrap_err_with(|| format!("upgrading {} to version {}", self.name, step + 1))?;
+        }
+        let changed = *table != before;
+        table.insert("version".to_owned(), i64::from(self.current()).into());
+        Ok(changed)
+    }
+
+    /// Upgrades `contents`, read from `path`, writing the result back with a backup
+    /// of the old file, and returns it. Contents that aren't TOML come back as they
+    /// are, for reading them to tell what's wrong.
+    pub fn upgrade_file(&self, path: &Path, contents: String) -> Result<String> {
+        let Ok(mut table) = toml::from_str::<toml::Table>(&contents) else {
+            return Ok(contents);
+        };
+        let version = self.version(&table)?;
+        if !self.upgrade(&mut table)? {
+            return Ok(contents);
+        }
+        let backup = path.with_file_name(format!("{}.v{version}.bak", self.name));
+        std::fs::copy(path, &backup)
+            .wrap_err_with(|| format!("backing up {} to {}", path.display(), backup.display()))?;
+        let upgraded = toml::to_string_pretty(&table)
+            .wrap_err_with(|| format!("serializing the upgraded {}", self.name))?;
+        std::fs::write(path, &upgraded).wrap_err_with(|| format!("writing {}", path.display()))?;
+        info!(
+            "upgraded {} from version {version} to {}, the old one is in {}",
+            path.display(),
+            self.current(),
+            backup.display()
+        );
+        Ok(upgraded)
+    }
+
+    /// `value` serialized with the current version, for files pipeweld writes.
+    pub fn serialize(&self, value: &impl serde::Serialize) -> Result<String> {
+        let mut table =
+            toml::Table::try_from(value).wrap_err_with(|| format!("serializing {}", self.name))?;
+        if self.current() > 0 {
+            table.insert("version".to_owned(), i64::from(self.current()).into());
+        }
+        toml::to_string_pretty(&table).wrap_err_with(|| format!("serializing {}", self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Each step leaves its number behind in `ran`, to tell which of them ran.
+    fn ran(table: &mut toml::Table, step: i64) -> Result<()> {
+        let ran = table
+            .entry("ran")
+            .or_insert_with(|| toml::Value::Array(Vec::new()));
+        ran.as_array_mut().expect("ran is a list").push(step.into());
+        Ok(())
+    }
+
+    const STEPS: Format = Format {
+        name: "steps.toml",
+        steps: &[
+            |table| ran(table, 1),
+            |table| ran(table, 2),
+            |table| ran(table, 3),
+        ],
+    };
+
+    fn table(contents: &str) -> toml::Table {
+        toml::from_str(contents).unwrap()
+    }
+
+    fn ran_steps(table: &toml::Table) -> Vec<i64> {
+        table
+            .get("ran")
+            .and_then(toml::Value::as_array)
+            .map_or_else(Vec::new, |ran| {
+                ran.iter().filter_map(toml::Value::as_integer).collect()
+            })
+    }
+
+    /// A directory of its own for each test, as they run side by side.
+    fn directory(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("pipeweld-migrations-{}-{test}", std::process::id()));
+        std::fs::remove_dir_all(&directory).ok();
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn files_without_a_version_run_every_step() {
+        let mut table = table("volume = 40");
+        assert!(STEPS.upgrade(&mut table).unwrap());
+        assert_eq!(ran_steps(&table), [1, 2, 3]);
+        assert_eq!(table["version"].as_integer(), Some(3));
+    }
+
+    #[test]
+    fn files_of_a_middle_version_run_the_steps_after_it() {
+        let mut table = table("version = 2");
+        assert!(STEPS.upgrade(&mut table).unwrap());
+        assert_eq!(ran_steps(&table), [3]);
+        assert_eq!(table["version"].as_integer(), Some(3));
+    }
+
+    #[test]
+    fn current_files_stay_as_they_are() {
+        let mut table = table("version = 3\nvolume = 40");
+        assert!(!STEPS.upgrade(&mut table).unwrap());
+        assert_eq!(table, self::table("version = 3\nvolume = 40"));
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let error = STEPS.upgrade(&mut table("version = 4")).unwrap_err();
+        assert!(error.to_string().contains("newer pipeweld"), "{error}");
+        assert!(STEPS.upgrade(&mut table("version = \"two\"")).is_err());
+    }
+
+    #[test]
+    fn config_step_becomes_a_list_of_steps() {
+        let mut upgraded = table("step = 5");
+        assert!(crate::config::FORMAT.upgrade(&mut upgraded).unwrap());
+        assert_eq!(upgraded, table("version = 1\nsteps = [5]"));
+        // steps given already win over the old step
+        let mut both = table("step = 5\nsteps = [2, 10]");
+        crate::config::FORMAT.upgrade(&mut both).unwrap();
+        assert_eq!(both, table("version = 1\nsteps = [2, 10]"));
+    }
+
+    #[test]
+    fn devices_never_suspending_get_a_suspend_policy() {
+        let mut upgraded = table(
+            "[devices.usb]\nnever_suspend = true\n\
+             [devices.hdmi]\nnever_suspend = false\nhidden = true",
+        );
+        assert!(crate::device_settings::FORMAT
+            .upgrade(&mut upgraded)
+            .unwrap());
+        assert_eq!(
+            upgraded,
+            table(
+                "version = 1\n\
+                 [devices.usb]\nsuspend = \"never\"\n\
+                 [devices.hdmi]\nhidden = true"
+            )
+        );
+    }
+
+    #[test]
+    fn upgraded_files_are_written_back_with_a_backup() {
+        let directory = directory("upgraded");
+        let path = directory.join("steps.toml");
+        let old = "# by hand\nvolume = 40\n";
+        std::fs::write(&path, old).unwrap();
+        let upgraded = STEPS.upgrade_file(&path, old.to_owned()).unwrap();
+        assert_eq!(ran_steps(&table(&upgraded)), [1, 2, 3]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), upgraded);
+        assert_eq!(
+            std::fs::read_to_string(directory.join("steps.toml.v0.bak")).unwrap(),
+            old
+        );
+        std::fs::remove_dir_all(directory).ok();
+    }
+
+    #[test]
+    fn unchanged_and_broken_files_are_left_alone() {
+        let directory = directory("unchanged");
+        let path = directory.join("steps.toml");
+        for contents in ["# kept\nversion = 3 # as written\n", "volume = [unclosed"] {
+            std::fs::write(&path, contents).unwrap();
+            assert_eq!(
+                STEPS.upgrade_file(&path, contents.to_owned()).unwrap(),
+                contents
+            );
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+        }
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+        std::fs::remove_dir_all(directory).ok();
+    }
+}
@@ -0,0 +1,87 @@
+//! Loading and unloading server modules, and telling apart the ones pipeweld owns.
+use eyre::{eyre, Result, WrapErr};
+use tracing::instrument;
+
+use crate::AudioControls;
+
+/// Property tagged onto whatever a pipeweld-loaded module creates. Modules reject
+/// unknown arguments, so it has to ride along in one of their property lists.
+pub const OBJECT_TAG: &str = "pipeweld.object";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    pub index: u32,
+    pub name: String,
+    pub argument: String,
+}
+
+impl Module {
+    /// Lists modules through `pactl list short modules`, one tab-separated line each.
+    pub fn list() -> Result<Vec<Self>> {
+        AudioControls::pactl(["list", "short", "modules"])?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut columns = line.splitn(3, '\t');
+                let index = columns
+                    .next()
+                    .and_then(|index| index.trim().parse().ok())
+                    .ok_or_else(|| eyre!("bad module line {line:?}"))?;
+                let name = columns.next().unwrap_or_default().to_owned();
+                let argument = columns.next().unwrap_or_default().trim().to_owned();
+                Ok(Self {
+                    index,
+                    name,
+                    argument,
+                })
+            })
+            .collect()
+    }
+
+    /// The property marking something as pipeweld's `object`.
+    pub fn tag(object: &str) -> String {
+        format!("{OBJECT_TAG}={object}")
+    }
+
+    /// The option of `parec` and `paplay` tagging their stream as pipeweld's
+    /// `object`, so it's told apart from the applications' as modules' streams are.
+    pub fn stream_tag(object: &str) -> String {
+        format!("--property={}", Self::tag(object))
+    }
+
+    /// Loads `name`, returning the new module's index.
+    #[instrument(ret, err)]
+    pub fn load(name: &str, arguments: &[String]) -> Result<u32> {
+        AudioControls::pactl(
+            ["load-module", name]
+                .into_iter()
+                .map(str::to_owned)
+                .chain(arguments.iter().cloned()),
+        )
+        .and_then(|index| {
+            index
+                .trim()
+                .parse()
+                .wrap_err_with(|| format!("reading index of loaded {name}"))
+        })
+    }
+
+    #[instrument(ret, err)]
+    pub fn unload(index: u32) -> Result<()> {
+        AudioControls::pactl(["unload-module", &index.to_string()]).map(drop)
+    }
+
+    /// Value of `key=value` in the module arguments, quotes stripped.
+    pub fn argument(&self, key: &str) -> Option<&str> {
+        let prefix = format!("{key}=");
+        self.argument
+            .split_whitespace()
+            .map(|part| part.trim_matches(|c| c == '"' || c == '\''))
+            .find_map(|part| part.strip_prefix(&prefix))
+    }
+
+    /// The pipeweld object this module implements, if pipeweld loaded it.
+    pub fn object(&self) -> Option<&str> {
+        self.argument(OBJECT_TAG)
+    }
+}
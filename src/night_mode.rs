@@ -0,0 +1,90 @@
+//! Night mode: a compressor followed by a limiter in front of the default output,
+//! so dialogue stays audible without explosions waking anybody up. The plugins
+//! come from the SWH LADSPA collection.
+use gtk::{gio, prelude::*, Application};
+use leptos::*;
+use tracing::info;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    easyeffects,
+    filter_chain::{self, Filter},
+    state::AppState,
+};
+
+/// Name of the application action toggling night mode, for binding to a key:
+/// `gapplication action it.niedzwiedz.pipeweld toggle-night-mode`.
+pub const ACTION: &str = "toggle-night-mode";
+
+const GRAPH: &str = r#"{
+    nodes = [
+        { type = ladspa name = compressor plugin = sc4m_1916 label = sc4m
+          control = {
+              "Attack time (ms)" = 10 "Release time (ms)" = 250
+              "Threshold level (dB)" = -30 "Ratio (1:n)" = 6
+              "Knee radius (dB)" = 6 "Makeup gain (dB)" = 12
+          } }
+        { type = ladspa name = limiter plugin = hard_limiter_1413 label = hardLimiter
+          control = { "dB limit" = -3 "Wet level" = 1 "Residue level" = 0 } }
+    ]
+    links = [ { output = "compressor:Output" input = "limiter:Input" } ]
+}"#;
+
+fn object(master: &str) -> String {
+    format!("night-mode:{master}")
+}
+
+fn filter(master: &str, description: &str) -> Filter {
+    Filter {
+        object: object(master),
+        description: format!("{description} (night mode)"),
+        master: master.to_owned(),
+        graph: GRAPH.to_owned(),
+    }
+}
+
+/// Exposes [`ACTION`] on the application, reachable over D-Bus while pipeweld runs.
+pub fn register(app: &Application, dispatcher: Dispatcher) {
+    let action = gio::SimpleAction::new(ACTION, None);
+    action.connect_activate(move |_, _| dispatcher.dispatch(Action::ToggleNightMode));
+    app.add_action(&action);
+}
+
+/// Keeps the filter running in front of whichever real sink is behind the default output.
+pub fn spawn(cx: Scope, state: AppState) {
+    let master = create_memo(cx, move |_| {
+        if !state.night_mode.get() {
+            return None;
+        }
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                // EasyEffects has compressors of its own
+                .filter(|sink| !easyeffects::is_virtual(sink))
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    create_effect(cx, move |previous: Option<Option<String>>| {
+        let current = master.get();
+        if previous.as_ref() == Some(&current) {
+            return current;
+        }
+        state.filters.with_value(|host| {
+            let host = &mut *host.lock();
+            if let Some(previous) = previous.flatten() {
+                host.stop(&object(&previous));
+            }
+            let Some(master) = &current else {
+                return;
+            };
+            // the night mode sink itself is the default while it runs, describe the real one
+            let description = Device::list(DeviceKind::Sink)
+                .ok()
+                .and_then(|sinks| sinks.into_iter().find(|sink| sink.name == *master))
+                .map_or_else(|| master.clone(), |sink| sink.description);
+            info!("night mode on for {master}");
+            host.start(&filter(master, &description)).ok();
+        });
+        current
+    });
+}
@@ -0,0 +1,138 @@
+//! Noise suppression for the microphone: a `module-echo-cancel` in front of the
+//! default input, running WebRTC's noise suppression, whose filtered source then
+//! becomes the default. The module carries a [`crate::modules::OBJECT_TAG`] and is found
+//! again by it, so switching it on twice, or again after restarting pipeweld,
+//! doesn't load a second one. Like other modules it goes away with the server.
+use std::{cell::Cell, rc::Rc};
+
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, instrument};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    modules::Module,
+    state::AppState,
+    AudioControls,
+};
+
+/// Object name of the module.
+const OBJECT: &str = "noise-suppression";
+
+/// The loaded filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoiseSuppression {
+    pub module: u32,
+    /// Name of the source it filters.
+    pub master: String,
+}
+
+/// Name of the filtered source.
+pub fn source_name() -> String {
+    format!("{}.{OBJECT}", clap::crate_name!())
+}
+
+impl NoiseSuppression {
+    /// The filter pipeweld loaded, including one from an earlier run.
+    pub fn find() -> Result<Option<Self>> {
+        Ok(Module::list()?
+            .into_iter()
+            .find(|module| module.object() == Some(OBJECT))
+            .map(|module| Self {
+                module: module.index,
+                master: module
+                    .argument("source_master")
+                    .unwrap_or_default()
+                    .to_owned(),
+            }))
+    }
+
+    /// Filters the default source and makes the filtered one the default.
+    #[instrument(err)]
+    fn start() -> Result<()> {
+        let master = AudioControls::default_device(DeviceKind::Source)?;
+        let description = Device::list(DeviceKind::Source)?
+            .into_iter()
+            .find(|source| source.name == master)
+            .map_or_else(|| master.clone(), |source| source.description)
+            .replace(['\'', '"'], "");
+        let tag = Module::tag(OBJECT);
+        let module = Module::load(
+            "module-echo-cancel",
+            &[
+                "aec_method=webrtc".to_owned(),
+                "aec_args=webrtc.noise_suppression=true".to_owned(),
+                format!("source_master={master}"),
+                format!("source_name={}", source_name()),
+                format!(
+                    "source_properties=\"device.description='{description} (noise suppressed)' {tag}\""
+                ),
+                // echo cancelling needs an output to take the echo of
+                format!("sink_name={}.sink", source_name()),
+                format!("sink_properties=\"device.description='Echo cancelled output' {tag}\""),
+            ],
+        )?;
+        info!(module, master, "noise suppression on");
+        AudioControls::set_default(DeviceKind::Source, &source_name())
+    }
+
+    /// Unloads the filter, handing the default input back to its master.
+    #[instrument(err)]
+    fn stop(&self) -> Result<()> {
+        if !self.master.is_empty()
+            && AudioControls::default_device(DeviceKind::Source)? == source_name()
+        {
+            AudioControls::set_default(DeviceKind::Source, &self.master)?;
+        }
+        Module::unload(self.module)
+    }
+}
+
+/// Loads the filter or unloads it, doing nothing when it's as asked already.
+pub fn set(enabled: bool) -> Result<()> {
+    match (NoiseSuppression::find()?, enabled) {
+        (None, true) => NoiseSuppression::start(),
+        (Some(loaded), false) => loaded.stop(),
+        _ => Ok(()),
+    }
+}
+
+/// Switch loading the filter in front of the default input, or unloading it.
+pub fn noise_suppression_switch(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let row = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let label = gtk::Label::new(Some(&tr("Noise suppression")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    row.append(&label);
+    let enabled = gtk::Switch::new();
+    enabled.set_tooltip_text(Some(
+        "Filter the microphone and make the filtered one the default",
+    ));
+    row.append(&enabled);
+    // set while following the server, which notifies too
+    let syncing = Rc::new(Cell::new(false));
+    enabled.connect_active_notify({
+        let syncing = syncing.clone();
+        move |enabled| {
+            if !syncing.get() {
+                dispatcher.dispatch(Action::SetNoiseSuppression(enabled.is_active()));
+            }
+        }
+    });
+    create_effect(cx, move |_| {
+        let loaded = state.noise_suppression.with(Option::is_some);
+        syncing.set(true);
+        enabled.set_active(loaded);
+        syncing.set(false);
+    });
+    row
+}
@@ -0,0 +1,301 @@
+//! Client for the freedesktop `org.freedesktop.Notifications` service, and the
+//! notifications about devices turned on in the configuration's `[notifications]`.
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use eyre::{Result, WrapErr};
+use gtk::{
+    gio,
+    glib::{ToVariant, Variant},
+};
+use leptos::*;
+use tracing::warn;
+
+use crate::{
+    config,
+    devices::{Device, DeviceKind},
+    events::{self, EventKind},
+    filter_chain,
+    i18n::{tr, tr_with},
+    state::AppState,
+};
+
+const SERVICE: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+
+#[derive(Debug, Clone, Default)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    pub icon: String,
+    pub hints: HashMap<String, Variant>,
+    /// Buttons, by key and label, see [`Notifier::show_then`].
+    pub actions: Vec<(String, String)>,
+    /// Milliseconds, `-1` leaves it to the notification daemon.
+    pub timeout: i32,
+}
+
+impl Notification {
+    pub fn new(summary: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            timeout: -1,
+            ..Default::default()
+        }
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.icon = icon.into();
+        self
+    }
+
+    pub fn hint(mut self, name: &str, value: impl ToVariant) -> Self {
+        self.hints.insert(name.to_owned(), value.to_variant());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: i32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn action(mut self, key: impl Into<String>, label: impl Into<String>) -> Self {
+        self.actions.push((key.into(), label.into()));
+        self
+    }
+}
+
+type OnAction = Rc<dyn Fn(&str)>;
+
+/// Sends notifications that replace the previous one instead of piling up.
+#[derive(Clone)]
+pub struct Notifier {
+    connection: gio::DBusConnection,
+    last_id: Rc<Cell<u32>>,
+    /// What to do about an action picked, by the id of the notification showing it.
+    on_action: Rc<RefCell<HashMap<u32, OnAction>>>,
+    subscribed: Rc<Cell<bool>>,
+}
+
+impl Notifier {
+    pub fn new() -> Result<Self> {
+        gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)
+            .wrap_err("connecting to the session bus")
+            .map(|connection| Self {
+                connection,
+                last_id: Rc::default(),
+                on_action: Rc::default(),
+                subscribed: Rc::default(),
+            })
+    }
+
+    pub fn show(&self, notification: Notification) {
+        self.send(notification, None);
+    }
+
+    /// Shows `notification`, calling `on_action` with the key of the action picked on
+    /// it, if one is.
+    pub fn show_then(&self, notification: Notification, on_action: impl Fn(&str) + 'static) {
+        self.subscribe();
+        self.send(notification, Some(Rc::new(on_action)));
+    }
+
+    fn subscribe(&self) {
+        if self.subscribed.replace(true) {
+            return;
+        }
+        let on_action = Rc::downgrade(&self.on_action);
+        self.connection.signal_subscribe(
+            Some(SERVICE),
+            Some(SERVICE),
+            None,
+            Some(PATH),
+            None,
+            gio::DBusSignalFlags::NONE,
+            move |_, _, _, _, signal, parameters| {
+                let Some(on_action) = on_action.upgrade() else {
+                    return;
+                };
+                match signal {
+                    "ActionInvoked" => {
+                        let Some((id, key)) = parameters.get::<(u32, String)>() else {
+                            return;
+                        };
+                        // taken out first, it may well show another notification
+                        let handler = on_action.borrow().get(&id).cloned();
+                        if let Some(handler) = handler {
+                            handler(&key);
+                        }
+                    }
+                    "NotificationClosed" => {
+                        if let Some((id, _)) = parameters.get::<(u32, u32)>() {
+                            on_action.borrow_mut().remove(&id);
+                        }
+                    }
+                    _ => {}
+                }
+            },
+        );
+    }
+
+    fn send(&self, notification: Notification, on_action: Option<OnAction>) {
+        let actions = notification
+            .actions
+            .into_iter()
+            .flat_map(|(key, label)| [key, label])
+            .collect::<Vec<_>>();
+        let parameters = (
+            clap::crate_name!(),
+            self.last_id.get(),
+            notification.icon,
+            notification.summary,
+            notification.body,
+            actions,
+            notification.hints,
+            notification.timeout,
+        )
+            .to_variant();
+        let last_id = self.last_id.clone();
+        let handlers = self.on_action.clone();
+        self.connection.call(
+            Some(SERVICE),
+            PATH,
+            SERVICE,
+            "Notify",
+            Some(&parameters),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            move |result| match result.map(|reply| reply.get::<(u32,)>()) {
+                Ok(Some((id,))) => {
+                    last_id.set(id);
+                    match on_action {
+                        Some(on_action) => handlers.borrow_mut().insert(id, on_action),
+                        // a replaced notification takes its buttons along
+                        None => handlers.borrow_mut().remove(&id),
+                    };
+                }
+                Ok(None) => warn!("unexpected reply from the notification daemon"),
+                Err(error) => warn!(%error, "sending notification"),
+            },
+        );
+    }
+}
+
+pub(crate) fn icon_name(kind: DeviceKind) -> &'static str {
+    match kind {
+        DeviceKind::Sink => "audio-speakers-symbolic",
+        DeviceKind::Source => "audio-input-microphone-symbolic",
+    }
+}
+
+/// Notifies about what [`config::Notifications`] turns on: another default device,
+/// a device connecting, and the output reaching its volume limit. Each kind of
+/// notification replaces the previous one of its kind.
+pub fn spawn(cx: Scope, state: AppState) {
+    let settings = &config::get().notifications;
+    let notifier = || {
+        Notifier::new()
+            .map_err(|error| warn!(?error, "device notifications won't be shown"))
+            .ok()
+    };
+    if settings.default_changed {
+        for kind in [DeviceKind::Sink, DeviceKind::Source] {
+            let Some(notifier) = notifier() else {
+                return;
+            };
+            let default = match kind {
+                DeviceKind::Sink => state.default_sink,
+                DeviceKind::Source => state.default_source,
+            };
+            create_effect(cx, move |previous: Option<Option<String>>| {
+                let name = default.with(|device| device.as_ref().map(|device| device.name.clone()));
+                // the first run only records the starting point, and a server
+                // coming back isn't a switch
+                let switched =
+                    previous.is_some_and(|previous| previous.is_some() && previous != name);
+                if let Some(device) = default.get_untracked().filter(|_| switched) {
+                    notifier.show(
+                        Notification::new(match kind {
+                            DeviceKind::Sink => tr("Output switched"),
+                            DeviceKind::Source => tr("Input switched"),
+                        })
+                        .body(device.description)
+                        .icon(icon_name(kind)),
+                    );
+                }
+                name
+            });
+        }
+    }
+    if settings.device_added {
+        let Some(notifier) = notifier() else {
+            return;
+        };
+        events::subscribe(move |event| {
+            let kind = match (event.kind, event.facility) {
+                (EventKind::New, events::Facility::Sink) => DeviceKind::Sink,
+                (EventKind::New, events::Facility::Source) => DeviceKind::Source,
+                _ => return,
+            };
+            let added = Device::list(kind)
+                .map_err(|error| warn!(?error, "listing devices to notify about"))
+                .ok()
+                .and_then(|devices| {
+                    devices
+                        .into_iter()
+                        .find(|device| Some(device.index) == event.index)
+                })
+                // pipeweld's own filters come and go with their settings
+                .filter(|device| filter_chain::master_of(device).is_none());
+            if let Some(device) = added {
+                notifier.show(
+                    Notification::new(tr("Connected"))
+                        .body(device.description)
+                        .icon(icon_name(kind)),
+                );
+            }
+        });
+    }
+    if settings.volume_capped {
+        let Some(notifier) = notifier() else {
+            return;
+        };
+        create_effect(cx, move |previous: Option<Option<(String, u32)>>| {
+            let current = state.default_sink.with(|sink| {
+                sink.as_ref()
+                    .map(|sink| (sink.name.clone(), sink.volume_percent()))
+            });
+            let Some(sink) = state.default_sink.get_untracked() else {
+                return current;
+            };
+            let max = state
+                .device_settings
+                .with_untracked(|store| store.max_volume(&sink));
+            if let (Some(Some((before, was))), Some((name, percent)), Some(max)) =
+                (&previous, &current, max)
+            {
+                if before == name && *was < max && *percent >= max {
+                    notifier.show(
+                        Notification::new(tr("Volume limit reached"))
+                            .body(tr_with(
+                                "{device} goes up to {max}%",
+                                &[("device", &sink.description), ("max", &max.to_string())],
+                            ))
+                            .icon("audio-volume-high-symbolic"),
+                    );
+                }
+            }
+            current
+        });
+    }
+}
@@ -0,0 +1,621 @@
+//! Transient on-screen display for volume changes of the default sink. The popup
+//! fades out once it's been up for a moment, and changes coming in quick
+//! succession, like a slider being dragged, are shown at a steady pace rather
+//! than redrawing it for every one.
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, time::Duration};
+
+use eyre::{eyre, Result};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    compositor::{self, Output},
+    devices::Device,
+    easyeffects,
+    i18n::tr,
+    notifications::{Notification, Notifier},
+    state::AppState,
+    timeline::Source,
+};
+
+const TITLE: &str = "pipeweld OSD";
+const VISIBLE_FOR: Duration = Duration::from_millis(1500);
+/// How long fading out takes, after [`VISIBLE_FOR`].
+const FADE: Duration = Duration::from_millis(300);
+const FRAME: Duration = Duration::from_millis(16);
+/// Changes within this long of a shown one are held back, the latest is shown
+/// once it's over.
+const SETTLE: Duration = Duration::from_millis(80);
+/// Distance kept from the edges of the display.
+const MARGIN: i32 = 48;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OsdStyle {
+    /// Device name above a labelled level bar.
+    #[default]
+    Bar,
+    /// GNOME Shell's pill: a volume icon next to a thin bar.
+    Pill,
+    /// A desktop notification carrying the volume as a `value` hint,
+    /// which most notification daemons render as a bar.
+    Notification,
+}
+
+impl OsdStyle {
+    pub const ALL: [Self; 3] = [Self::Bar, Self::Pill, Self::Notification];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Bar => "Default",
+            Self::Pill => "GNOME",
+            Self::Notification => "Notification",
+        }
+    }
+}
+
+/// Which display the popup goes on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OsdOutput {
+    /// Wherever the window manager puts it.
+    #[default]
+    Anywhere,
+    Focused,
+    FollowCursor,
+    Named(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    Top,
+    TopRight,
+    Center,
+    BottomLeft,
+    #[default]
+    Bottom,
+    BottomRight,
+}
+
+impl Corner {
+    pub const ALL: [Self; 7] = [
+        Self::TopLeft,
+        Self::Top,
+        Self::TopRight,
+        Self::Center,
+        Self::BottomLeft,
+        Self::Bottom,
+        Self::BottomRight,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::TopLeft => "Top left",
+            Self::Top => "Top",
+            Self::TopRight => "Top right",
+            Self::Center => "Center",
+            Self::BottomLeft => "Bottom left",
+            Self::Bottom => "Bottom",
+            Self::BottomRight => "Bottom right",
+        }
+    }
+}
+
+/// Where the popup goes, placed through the window system since neither X11 nor
+/// Wayland let GTK 4 position its windows.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OsdPlacement {
+    pub output: OsdOutput,
+    pub corner: Corner,
+    /// Corners for single displays, by name, instead of `corner`.
+    pub overrides: BTreeMap<String, Corner>,
+}
+
+impl OsdPlacement {
+    /// Top left corner of a popup of `size` on one of `outputs`, falling back to
+    /// the focused display when the chosen one isn't connected.
+    pub fn position(
+        &self,
+        outputs: &[Output],
+        cursor: Option<(i32, i32)>,
+        (width, height): (i32, i32),
+    ) -> Option<(i32, i32)> {
+        let output = match &self.output {
+            OsdOutput::Anywhere => return None,
+            OsdOutput::Focused => None,
+            OsdOutput::FollowCursor => {
+                cursor.and_then(|cursor| outputs.iter().find(|output| output.contains(cursor)))
+            }
+            OsdOutput::Named(name) => outputs.iter().find(|output| output.name == *name),
+        }
+        .or_else(|| outputs.iter().find(|output| output.focused))
+        .or_else(|| outputs.first())?;
+        let corner = self
+            .overrides
+            .get(&output.name)
+            .copied()
+            .unwrap_or(self.corner);
+        let left = output.x + MARGIN;
+        let right = output.x + output.width - width - MARGIN;
+        let center = output.x + (output.width - width) / 2;
+        let top = output.y + MARGIN;
+        let bottom = output.y + output.height - height - MARGIN;
+        let middle = output.y + (output.height - height) / 2;
+        Some(match corner {
+            Corner::TopLeft => (left, top),
+            Corner::Top => (center, top),
+            Corner::TopRight => (right, top),
+            Corner::Center => (center, middle),
+            Corner::BottomLeft => (left, bottom),
+            Corner::Bottom => (center, bottom),
+            Corner::BottomRight => (right, bottom),
+        })
+    }
+}
+
+/// What a popup shows, independent of its style.
+#[derive(Debug, Clone, PartialEq)]
+struct Content {
+    device: String,
+    percent: u32,
+    muted: bool,
+    microphone: bool,
+}
+
+impl Content {
+    fn new(sink: &Device) -> Self {
+        Self {
+            device: sink.description.clone(),
+            percent: sink.volume_percent(),
+            muted: sink.mute,
+            microphone: false,
+        }
+    }
+
+    fn microphone(source: &Device) -> Self {
+        Self {
+            device: format!("Microphone: {}", easyeffects::label(source)),
+            percent: source.volume_percent(),
+            muted: source.mute,
+            microphone: true,
+        }
+    }
+
+    fn icon_name(&self) -> &'static str {
+        match (self.microphone, self.muted, self.percent) {
+            (false, true, _) | (false, _, 0) => "audio-volume-muted-symbolic",
+            (false, _, 1..=33) => "audio-volume-low-symbolic",
+            (false, _, 34..=66) => "audio-volume-medium-symbolic",
+            (false, ..) => "audio-volume-high-symbolic",
+            (true, true, _) | (true, _, 0) => "microphone-sensitivity-muted-symbolic",
+            (true, _, 1..=33) => "microphone-sensitivity-low-symbolic",
+            (true, _, 34..=66) => "microphone-sensitivity-medium-symbolic",
+            (true, ..) => "microphone-sensitivity-high-symbolic",
+        }
+    }
+
+    fn fraction(&self) -> f64 {
+        (f64::from(self.percent) / 100.).min(1.)
+    }
+
+    fn bar(&self) -> gtk::Widget {
+        let layout = gtk::Box::new(Orientation::Vertical, 6);
+        layout.set_margin_top(12);
+        layout.set_margin_bottom(12);
+        layout.set_margin_start(12);
+        layout.set_margin_end(12);
+        layout.append(&gtk::Label::new(Some(&self.device)));
+        let level = gtk::ProgressBar::builder()
+            .fraction(self.fraction())
+            .show_text(true)
+            .text(match self.muted {
+                true => "Muted".to_owned(),
+                false => format!("{}%", self.percent),
+            })
+            .build();
+        layout.append(&level);
+        layout.upcast()
+    }
+
+    fn pill(&self) -> gtk::Widget {
+        let layout = gtk::Box::new(Orientation::Horizontal, 12);
+        layout.set_margin_top(8);
+        layout.set_margin_bottom(8);
+        layout.set_margin_start(18);
+        layout.set_margin_end(18);
+        let icon = gtk::Image::from_icon_name(self.icon_name());
+        icon.set_pixel_size(24);
+        layout.append(&icon);
+        let level = gtk::ProgressBar::builder()
+            .fraction(if self.muted { 0. } else { self.fraction() })
+            .valign(gtk::Align::Center)
+            .hexpand(true)
+            .width_request(160)
+            .build();
+        layout.append(&level);
+        layout.upcast()
+    }
+
+    fn notification(&self) -> Notification {
+        Notification::new(&self.device)
+            .body(match self.muted {
+                true => "Muted".to_owned(),
+                false => format!("{}%", self.percent),
+            })
+            .icon(self.icon_name())
+            .hint("value", self.percent.min(100) as i32)
+            .hint("transient", true)
+            // notify-osd and friends update a synchronous notification in place
+            .hint("x-canonical-private-synchronous", "volume")
+            .timeout(VISIBLE_FOR.as_millis() as i32)
+    }
+}
+
+/// Whether a plain window can be placed as an overlay on this display.
+///
+/// Anywhere but X11 the compositor decides where windows go, and keeping the
+/// popup on top would take layer-shell, so those fall back to notifications.
+fn can_place_windows() -> bool {
+    gtk::gdk::Display::default().is_some_and(|display| display.type_().name() == "GdkX11Display")
+}
+
+/// The borderless window every style is drawn into, faded out after a moment.
+struct Popup {
+    window: gtk::Window,
+    /// The timeout hiding the window, or fading it out.
+    hide: RefCell<Option<glib::SourceId>>,
+}
+
+impl Popup {
+    fn new() -> Rc<Self> {
+        let window = gtk::Window::builder()
+            .title(TITLE)
+            .decorated(false)
+            .resizable(false)
+            .focusable(false)
+            .can_target(false)
+            .build();
+        window.add_css_class("osd");
+        Rc::new(Self {
+            window,
+            hide: RefCell::default(),
+        })
+    }
+
+    fn show(self: &Rc<Self>, child: &gtk::Widget, pill: bool, placement: &OsdPlacement) {
+        self.window.set_child(Some(child));
+        match pill {
+            true => self.window.add_css_class("pill"),
+            false => self.window.remove_css_class("pill"),
+        }
+        self.window.present();
+        if placement.output != OsdOutput::Anywhere {
+            self.place(placement.clone());
+        }
+        if let Some(pending) = self.hide.take() {
+            pending.remove();
+        }
+        self.window.set_opacity(1.);
+        let popup = self.clone();
+        let pending = glib::timeout_add_local_once(VISIBLE_FOR, move || {
+            let fading = popup.clone();
+            let fade = glib::timeout_add_local(FRAME, move || {
+                let opacity = fading.window.opacity() - FRAME.as_secs_f64() / FADE.as_secs_f64();
+                if opacity > 0. {
+                    fading.window.set_opacity(opacity);
+                    return glib::Continue(true);
+                }
+                fading.hide.take();
+                fading.window.set_visible(false);
+                fading.window.set_opacity(1.);
+                glib::Continue(false)
+            });
+            popup.hide.replace(Some(fade));
+        });
+        self.hide.replace(Some(pending));
+    }
+
+    /// Moves the window where `placement` has it, off the main loop since it takes
+    /// a few round trips to the window system.
+    fn place(&self, placement: OsdPlacement) {
+        let (_, width, _, _) = self.window.measure(Orientation::Horizontal, -1);
+        let (_, height, _, _) = self.window.measure(Orientation::Vertical, width);
+        std::thread::spawn(move || {
+            let placed = || -> Result<()> {
+                let system = compositor::detect()
+                    .ok_or_else(|| eyre!("no window system to place the popup with"))?;
+                let cursor = match placement.output {
+                    OsdOutput::FollowCursor => system.cursor()?,
+                    _ => None,
+                };
+                match placement.position(&system.outputs()?, cursor, (width, height)) {
+                    Some((x, y)) => system.move_window(TITLE, x, y),
+                    None => Ok(()),
+                }
+            };
+            if let Err(error) = placed() {
+                warn!(?error, "placing the volume popup");
+            }
+        });
+    }
+}
+
+/// Pops up whenever the default sink's volume, mute state or identity changes,
+/// and when another microphone becomes the default.
+pub fn spawn(cx: Scope, state: AppState) {
+    let popup = Popup::new();
+    let notifier = Notifier::new()
+        .map_err(|error| warn!(?error, "volume notifications won't be available"))
+        .ok();
+    let placeable = can_place_windows();
+    // the compositors pipeweld talks to can float and place the popup on top
+    let movable = compositor::detect().is_some();
+    if !placeable && !movable {
+        info!("volume popups will be shown as notifications on this display");
+    }
+    let show = move |content: &Content| {
+        if state.do_not_disturb.get_untracked()
+            || state
+                .device_settings
+                .with_untracked(|store| store.osd_hidden_for.contains(&Source::guess(state)))
+        {
+            return;
+        }
+        let placement = state
+            .device_settings
+            .with_untracked(|store| store.osd_placement.clone());
+        let placed = placeable || (movable && placement.output != OsdOutput::Anywhere);
+        match (state.osd_style.get_untracked(), &notifier) {
+            (OsdStyle::Notification, Some(notifier)) => notifier.show(content.notification()),
+            (_, Some(notifier)) if !placed => notifier.show(content.notification()),
+            (OsdStyle::Pill, _) => popup.show(&content.pill(), true, &placement),
+            _ => popup.show(&content.bar(), false, &placement),
+        }
+    };
+    // shown right away, then held back until things settle
+    let held = Rc::new(RefCell::new(None::<Content>));
+    let settling = Rc::new(RefCell::new(None::<glib::SourceId>));
+    let show = Rc::new(show);
+    let present = Rc::new(move |content: &Content| {
+        if settling.borrow().is_some() {
+            held.replace(Some(content.clone()));
+            return;
+        }
+        show(content);
+        let (held, settled, show) = (held.clone(), settling.clone(), show.clone());
+        settling.replace(Some(glib::timeout_add_local_once(SETTLE, move || {
+            settled.take();
+            if let Some(content) = held.take() {
+                show(&content);
+            }
+        })));
+    });
+    create_effect(cx, {
+        let present = present.clone();
+        move |previous: Option<Option<Content>>| {
+            let content = state
+                .default_sink
+                .with(|sink| sink.as_ref().map(Content::new));
+            // the first run only records the starting point
+            let changed = previous.is_some_and(|previous| previous != content);
+            if let Some(content) = content.as_ref().filter(|_| changed) {
+                present(content);
+            }
+            content
+        }
+    });
+    // microphone levels change all the time, only a different one is worth a popup
+    create_effect(cx, move |previous: Option<Option<String>>| {
+        let name = state
+            .default_source
+            .with(|source| source.as_ref().map(|source| source.name.clone()));
+        if previous.is_some_and(|previous| previous != name) {
+            state.default_source.with_untracked(|source| {
+                if let Some(source) = source {
+                    present(&Content::microphone(source));
+                }
+            });
+        }
+        name
+    });
+}
+
+/// A labelled drop down, calling `picked` with positions the user picks.
+fn picker_row(
+    layout: &gtk::Box,
+    label: &str,
+    choices: &[&str],
+    selected: u32,
+    picked: impl Fn(u32) + 'static,
+) {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(label));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    row.append(&label);
+    let picker = gtk::DropDown::from_strings(choices);
+    picker.set_selected(selected);
+    picker.connect_selected_notify(move |picker| {
+        if picker.selected() != gtk::INVALID_LIST_POSITION {
+            picked(picker.selected());
+        }
+    });
+    row.append(&picker);
+    layout.append(&row);
+}
+
+/// Expander choosing the display and corner the volume popup goes on, corners for
+/// single displays, and whose changes it stays hidden for. The displays are listed
+/// again whenever it's expanded.
+pub fn osd_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let placement_rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&placement_rows);
+    let hidden_for = gtk::Label::new(Some(&tr("Don't show for changes by")));
+    hidden_for.set_halign(gtk::Align::Start);
+    hidden_for.set_margin_top(6);
+    hidden_for.add_css_class("heading");
+    layout.append(&hidden_for);
+    for source in Source::ALL {
+        let hidden = move || {
+            state
+                .device_settings
+                .with_untracked(|store| store.osd_hidden_for.contains(&source))
+        };
+        let check = gtk::CheckButton::with_label(source.label());
+        check.set_active(hidden());
+        let dispatcher = dispatcher.clone();
+        check.connect_toggled(move |check| {
+            if check.is_active() != hidden() {
+                dispatcher.dispatch(Action::HideOsdFor {
+                    source,
+                    hidden: check.is_active(),
+                });
+            }
+        });
+        layout.append(&check);
+    }
+    let expander = gtk::Expander::builder()
+        .label("Volume popup")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let current = move || {
+        state
+            .device_settings
+            .with_untracked(|store| store.osd_placement.clone())
+    };
+    let apply = Rc::new(move |placement: OsdPlacement| {
+        if placement != current() {
+            dispatcher.dispatch(Action::SetOsdPlacement(placement));
+        }
+    });
+    expander.connect_expanded_notify(move |expander| {
+        if !expander.is_expanded() {
+            return;
+        }
+        while let Some(child) = placement_rows.first_child() {
+            placement_rows.remove(&child);
+        }
+        let outputs = match compositor::detect().map(|system| system.outputs()) {
+            Some(Ok(outputs)) => outputs,
+            Some(Err(error)) => {
+                warn!(?error, "listing displays");
+                Vec::new()
+            }
+            None => {
+                let hint =
+                    gtk::Label::new(Some(&tr("Placing the popup needs sway, Hyprland or X11")));
+                hint.add_css_class("dim-label");
+                return placement_rows.append(&hint);
+            }
+        };
+        let placement = current();
+        let mut names = outputs
+            .into_iter()
+            .map(|output| output.name)
+            .collect::<Vec<_>>();
+        // chosen displays stay listed while they're disconnected
+        let named = match &placement.output {
+            OsdOutput::Named(name) => Some(name),
+            _ => None,
+        };
+        for name in placement.overrides.keys().chain(named) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+
+        let mut displays = vec![
+            "Where the window manager puts it",
+            "The focused display",
+            "The display under the pointer",
+        ];
+        displays.extend(names.iter().map(String::as_str));
+        let selected = match &placement.output {
+            OsdOutput::Anywhere => 0,
+            OsdOutput::Focused => 1,
+            OsdOutput::FollowCursor => 2,
+            OsdOutput::Named(name) => {
+                3 + names
+                    .iter()
+                    .position(|other| other == name)
+                    .unwrap_or_default() as u32
+            }
+        };
+        picker_row(&placement_rows, "Display", &displays, selected, {
+            let apply = apply.clone();
+            let names = names.clone();
+            move |selected| {
+                let mut placement = current();
+                placement.output = match selected {
+                    0 => OsdOutput::Anywhere,
+                    1 => OsdOutput::Focused,
+                    2 => OsdOutput::FollowCursor,
+                    selected => match names.get(selected as usize - 3) {
+                        Some(name) => OsdOutput::Named(name.clone()),
+                        None => return,
+                    },
+                };
+                apply(placement);
+            }
+        });
+
+        let corners = Corner::ALL.map(Corner::label);
+        let position = |corner: Corner| {
+            Corner::ALL
+                .iter()
+                .position(|other| *other == corner)
+                .unwrap_or_default() as u32
+        };
+        picker_row(
+            &placement_rows,
+            "Corner",
+            &corners,
+            position(placement.corner),
+            {
+                let apply = apply.clone();
+                move |selected| {
+                    let Some(corner) = Corner::ALL.get(selected as usize) else {
+                        return;
+                    };
+                    let mut placement = current();
+                    placement.corner = *corner;
+                    apply(placement);
+                }
+            },
+        );
+
+        let overrides = std::iter::once("Like the others")
+            .chain(corners)
+            .collect::<Vec<_>>();
+        for name in names {
+            let selected = placement
+                .overrides
+                .get(&name)
+                .map_or(0, |corner| position(*corner) + 1);
+            picker_row(&placement_rows, &name.clone(), &overrides, selected, {
+                let apply = apply.clone();
+                move |selected| {
+                    let mut placement = current();
+                    match selected
+                        .checked_sub(1)
+                        .and_then(|index| Corner::ALL.get(index as usize))
+                    {
+                        Some(corner) => placement.overrides.insert(name.clone(), *corner),
+                        None => placement.overrides.remove(&name),
+                    };
+                    apply(placement);
+                }
+            });
+        }
+    });
+    expander
+}
@@ -0,0 +1,182 @@
+//! Programmable pads like the Stream Deck, through whatever drives them: the
+//! companion software sends the name of the pressed key over a local socket and
+//! pipeweld looks up what it's mapped to in `pads.json`. One key per line, every
+//! line answered with `ok` or what went wrong, so a key can run
+//!
+//! ```sh
+//! echo mute | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/pipeweld.sock
+//! ```
+//!
+//! The mapping is read again for every connection, edits apply on the next press.
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+};
+
+use eyre::{Result, WrapErr};
+use gtk::glib;
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::DeviceKind,
+    filter_chain,
+    state::AppState,
+    DiffValue,
+};
+
+/// What a key can be mapped to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum PadAction {
+    ToggleMute {
+        kind: DeviceKind,
+    },
+    SetDefault {
+        kind: DeviceKind,
+        device: String,
+    },
+    /// Makes the next device of the kind the default.
+    CycleDefault {
+        kind: DeviceKind,
+    },
+    /// Nudges the default output's volume, in percent.
+    ChangeVolume {
+        percent: i32,
+    },
+    /// Plays `device`, the default output if left out, through an imported preset,
+    /// or without one if `preset` is left out.
+    SetPreset {
+        #[serde(default)]
+        device: Option<String>,
+        #[serde(default)]
+        preset: Option<String>,
+    },
+}
+
+impl PadAction {
+    fn action(self, state: AppState) -> Option<Action> {
+        Some(match self {
+            Self::ToggleMute { kind } => Action::ToggleMute(kind),
+            Self::SetDefault { kind, device } => Action::SetDefault(kind, device),
+            Self::CycleDefault { kind } => Action::CycleDefault(kind),
+            Self::ChangeVolume { percent } => Action::ChangeVolume(DiffValue(percent)),
+            Self::SetPreset { device, preset } => Action::SetPreset {
+                device: match device {
+                    Some(device) => device,
+                    None => state.default_sink.with_untracked(|sink| {
+                        sink.as_ref()
+                            .map(|sink| filter_chain::master_name(sink).to_owned())
+                    })?,
+                },
+                preset,
+            },
+        })
+    }
+}
+
+/// Keys by the name the companion software sends, like
+///
+/// ```json
+/// { "keys": {
+///     "mute": { "action": "toggle-mute", "kind": "sink" },
+///     "headphones": { "action": "set-default", "kind": "sink", "device": "alsa_output.usb" },
+///     "music": { "action": "set-preset", "preset": "Loudness" }
+/// } }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mapping {
+    pub keys: BTreeMap<String, PadAction>,
+}
+
+impl Mapping {
+    pub fn path() -> PathBuf {
+        glib::user_config_dir()
+            .join(clap::crate_name!())
+            .join("pads.json")
+    }
+
+    /// The mapping as saved, empty while there is none.
+    #[instrument(err)]
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .wrap_err_with(|| format!("parsing {}", path.display())),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error).wrap_err_with(|| format!("reading {}", path.display())),
+        }
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    glib::user_runtime_dir().join(format!("{}.sock", clap::crate_name!()))
+}
+
+fn serve(client: UnixStream, sender: &glib::Sender<PadAction>) -> Result<()> {
+    let mapping = Mapping::load();
+    let mut reply = client.try_clone().wrap_err("cloning the connection")?;
+    for key in BufReader::new(client).lines() {
+        let key = key.wrap_err("reading the key")?;
+        let key = key.trim();
+        if key.is_empty() {
+            continue;
+        }
+        let answer = match &mapping {
+            Err(error) => format!("{error:#}"),
+            Ok(mapping) => match mapping.keys.get(key) {
+                None => format!("no action for key {key}"),
+                Some(action) => {
+                    info!(key, ?action, "pad key");
+                    match sender.send(action.clone()) {
+                        Ok(()) => "ok".to_owned(),
+                        Err(_) => "shutting down".to_owned(),
+                    }
+                }
+            },
+        };
+        writeln!(reply, "{answer}").wrap_err("answering")?;
+    }
+    Ok(())
+}
+
+/// Listens for key presses for the lifetime of the process.
+pub fn spawn(state: AppState, dispatcher: Dispatcher) {
+    let path = socket_path();
+    // left over from an instance that didn't get to clean up
+    std::fs::remove_file(&path).ok();
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(error) => return warn!(%error, path = %path.display(), "listening for pad keys"),
+    };
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, move |action: PadAction| {
+        match action.clone().action(state) {
+            Some(action) => dispatcher.dispatch(action),
+            None => warn!(?action, "no default output for the pad key"),
+        }
+        glib::Continue(true)
+    });
+    std::thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = match client {
+                Ok(client) => client,
+                Err(error) => {
+                    warn!(%error, "accepting pad connection");
+                    continue;
+                }
+            };
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                if let Err(error) = serve(client, &sender) {
+                    info!(?error, "pad connection gone");
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,315 @@
+//! `--panel`: a slim strip docked to a screen edge in place of the window, with the
+//! output's mute button, its volume and a switcher for the default output, for
+//! wlroots compositors without a desktop's audio applet. Built with the
+//! `layer-shell` feature, the strip is a layer surface through gtk4-layer-shell,
+//! spanning the edge and keeping windows clear of it like a bar does. Without it, or
+//! where the compositor doesn't offer layer surfaces, it's a plain undecorated
+//! window, floated and pinned to the middle of the edge under the compositors
+//! pipeweld can talk to, see [`crate::compositor`].
+//!
+//! ```sh
+//! pipeweld --panel --panel-edge=bottom
+//! ```
+use std::{str::FromStr, sync::OnceLock};
+
+use eyre::{bail, eyre, Result};
+use gtk::{glib, prelude::*, Application, Orientation};
+use leptos::*;
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    alsa, compositor,
+    devices::{self, DeviceKind},
+    extensions::{watch, InScope},
+    i18n::tr,
+    state::AppState,
+    AudioControls,
+};
+
+const OPTION: &str = "panel";
+const EDGE_OPTION: &str = "panel-edge";
+/// Title of the strip, for the compositor to find it by.
+const TITLE: &str = "pipeweld panel";
+/// Length of the volume slider along the strip.
+const SLIDER_LENGTH: i32 = 160;
+
+/// The edge picked on the command line, set when pipeweld runs as a panel.
+static EDGE: OnceLock<Edge> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Edge {
+    #[default]
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Edge {
+    fn orientation(self) -> Orientation {
+        match self {
+            Self::Top | Self::Bottom => Orientation::Horizontal,
+            Self::Left | Self::Right => Orientation::Vertical,
+        }
+    }
+
+    /// Top left corner of a strip of `size` in the middle of this edge of `output`.
+    fn position(self, output: &compositor::Output, (width, height): (i32, i32)) -> (i32, i32) {
+        let center = output.x + (output.width - width) / 2;
+        let middle = output.y + (output.height - height) / 2;
+        match self {
+            Self::Top => (center, output.y),
+            Self::Bottom => (center, output.y + output.height - height),
+            Self::Left => (output.x, middle),
+            Self::Right => (output.x + output.width - width, middle),
+        }
+    }
+}
+
+impl FromStr for Edge {
+    type Err = eyre::Report;
+
+    fn from_str(edge: &str) -> Result<Self> {
+        Ok(match edge {
+            "top" => Self::Top,
+            "bottom" => Self::Bottom,
+            "left" => Self::Left,
+            "right" => Self::Right,
+            _ => bail!("expected top, bottom, left or right, not {edge:?}"),
+        })
+    }
+}
+
+/// Adds `--panel` and `--panel-edge`, remembering what was asked for [`edge`].
+pub fn add_options(app: &Application) {
+    app.add_main_option(
+        OPTION,
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Dock a slim strip to a screen edge instead of opening the window",
+        None,
+    );
+    app.add_main_option(
+        EDGE_OPTION,
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Edge the panel docks to: top, bottom, left or right",
+        Some("EDGE"),
+    );
+    app.connect_handle_local_options(|_, options| {
+        if !options.contains(OPTION) {
+            return -1;
+        }
+        let edge = match options.lookup::<String>(EDGE_OPTION) {
+            Ok(Some(edge)) => match edge.parse() {
+                Ok(edge) => edge,
+                Err(error) => {
+                    eprintln!("[ERROR] --{EDGE_OPTION}: {error}");
+                    return 1;
+                }
+            },
+            _ => Edge::default(),
+        };
+        EDGE.set(edge).ok();
+        -1
+    });
+}
+
+/// Where the panel docks, if pipeweld runs as one.
+pub fn edge() -> Option<Edge> {
+    EDGE.get().copied()
+}
+
+#[cfg(feature = "layer-shell")]
+mod layer_shell {
+    use std::ffi::{c_char, c_int};
+
+    use gtk::{ffi::GtkWindow, glib::ffi::gboolean, glib::translate::ToGlibPtr, prelude::*};
+
+    use super::Edge;
+
+    #[link(name = "gtk4-layer-shell")]
+    extern "C" {
+        fn gtk_layer_is_supported() -> gboolean;
+        fn gtk_layer_init_for_window(window: *mut GtkWindow);
+        fn gtk_layer_set_namespace(window: *mut GtkWindow, name_space: *const c_char);
+        fn gtk_layer_set_layer(window: *mut GtkWindow, layer: c_int);
+        fn gtk_layer_set_anchor(window: *mut GtkWindow, edge: c_int, anchor: gboolean);
+        fn gtk_layer_auto_exclusive_zone_enable(window: *mut GtkWindow);
+    }
+
+    // GtkLayerShellLayer and GtkLayerShellEdge
+    const LAYER_TOP: c_int = 2;
+    const EDGE_LEFT: c_int = 0;
+    const EDGE_RIGHT: c_int = 1;
+    const EDGE_TOP: c_int = 2;
+    const EDGE_BOTTOM: c_int = 3;
+
+    pub fn is_supported() -> bool {
+        // SAFETY: only asks the display, which GTK has opened by now
+        unsafe { gtk_layer_is_supported() != 0 }
+    }
+
+    /// Makes `window`, not mapped yet, a layer surface along `edge`, returning
+    /// whether the compositor offers them.
+    pub fn dock(window: &impl IsA<gtk::Window>, edge: Edge) -> bool {
+        if !is_supported() {
+            return false;
+        }
+        // SAFETY: the window outlives the calls, which only set up its surface
+        unsafe {
+            let window: *mut GtkWindow = window.as_ref().to_glib_none().0;
+            gtk_layer_init_for_window(window);
+            gtk_layer_set_namespace(window, c"pipeweld".as_ptr());
+            gtk_layer_set_layer(window, LAYER_TOP);
+            let (anchored, across) = match edge {
+                Edge::Top => (EDGE_TOP, [EDGE_LEFT, EDGE_RIGHT]),
+                Edge::Bottom => (EDGE_BOTTOM, [EDGE_LEFT, EDGE_RIGHT]),
+                Edge::Left => (EDGE_LEFT, [EDGE_TOP, EDGE_BOTTOM]),
+                Edge::Right => (EDGE_RIGHT, [EDGE_TOP, EDGE_BOTTOM]),
+            };
+            for edge in across.into_iter().chain([anchored]) {
+                gtk_layer_set_anchor(window, edge, 1);
+            }
+            gtk_layer_auto_exclusive_zone_enable(window);
+        }
+        true
+    }
+}
+
+#[cfg(not(feature = "layer-shell"))]
+mod layer_shell {
+    use super::Edge;
+
+    pub fn is_supported() -> bool {
+        false
+    }
+
+    pub fn dock(_: &impl gtk::prelude::IsA<gtk::Window>, _: Edge) -> bool {
+        false
+    }
+}
+
+/// Whether the strip can dock as a layer surface: pipeweld was built with the
+/// `layer-shell` feature and the compositor offers them.
+pub fn docks_as_layer() -> bool {
+    layer_shell::is_supported()
+}
+
+/// Moves the strip to the middle of `edge` of the focused display, off the main
+/// loop like the volume popup.
+fn place(window: &gtk::ApplicationWindow, edge: Edge) {
+    let (_, width, _, _) = window.measure(Orientation::Horizontal, -1);
+    let (_, height, _, _) = window.measure(Orientation::Vertical, width);
+    std::thread::spawn(move || {
+        let placed = || -> Result<()> {
+            let system =
+                compositor::detect().ok_or_else(|| eyre!("no window system to dock the panel"))?;
+            let outputs = system.outputs()?;
+            let output = outputs
+                .iter()
+                .find(|output| output.focused)
+                .or_else(|| outputs.first())
+                .ok_or_else(|| eyre!("no display to dock the panel to"))?;
+            let (x, y) = edge.position(output, (width, height));
+            system.move_window(TITLE, x, y)
+        };
+        if let Err(error) = placed() {
+            warn!(?error, "docking the panel");
+        }
+    });
+}
+
+/// Opens the strip along `edge`, in place of the window.
+pub fn open(cx: Scope, app: &Application, state: AppState, dispatcher: Dispatcher, edge: Edge) {
+    if !AudioControls::server_available() {
+        return alsa::build_fallback_ui(app, dispatcher);
+    }
+    let orientation = edge.orientation();
+    let strip = gtk::Box::new(orientation, 6);
+    strip.set_margin_start(6);
+    strip.set_margin_end(6);
+    strip.set_margin_top(6);
+    strip.set_margin_bottom(6);
+
+    let mute = gtk::Button::new();
+    mute.add_css_class("flat");
+    let sink = state.default_sink;
+    watch(cx, &mute, move |mute| {
+        let muted = sink.with(|sink| sink.as_ref().is_some_and(|sink| sink.mute));
+        mute.set_icon_name(match muted {
+            true => "audio-volume-muted-symbolic",
+            false => "audio-volume-high-symbolic",
+        });
+        mute.set_tooltip_text(Some(&match muted {
+            true => tr("Unmute"),
+            false => tr("Mute"),
+        }));
+        mute.set_sensitive(sink.with(Option::is_some));
+    });
+    mute.connect_clicked({
+        let dispatcher = dispatcher.clone();
+        move |_| dispatcher.dispatch(Action::ToggleMute(DeviceKind::Sink))
+    });
+    strip.append(&mute);
+
+    let max = f64::from(AudioControls::max_percent());
+    let volume = gtk::Scale::in_scope(cx)
+        .constant(|scale| {
+            scale.set_orientation(orientation);
+            scale.set_range(0., max);
+            scale.set_round_digits(0);
+            match orientation {
+                Orientation::Horizontal => scale.set_size_request(SLIDER_LENGTH, -1),
+                _ => {
+                    // up for louder
+                    scale.set_inverted(true);
+                    scale.set_size_request(-1, SLIDER_LENGTH);
+                }
+            }
+        })
+        .model(
+            move || {
+                sink.with(|sink| {
+                    sink.as_ref()
+                        .map_or(0., |sink| sink.volume_percent().into())
+                })
+            },
+            {
+                let dispatcher = dispatcher.clone();
+                move |picked| {
+                    dispatcher.dispatch(Action::SetVolume(
+                        DeviceKind::Sink,
+                        DeviceKind::Sink.default_alias().to_owned(),
+                        picked.round() as u32,
+                    ));
+                }
+            },
+        )
+        // the server ignores volume changes of compressed streams
+        .bind_property("sensitive", move || {
+            sink.with(Option::is_some) && state.passthrough.with(Option::is_none)
+        })
+        .as_ref()
+        .clone();
+    volume.set_tooltip_text(Some(&tr("Volume")));
+    strip.append(&volume);
+    strip.append(&devices::sink_picker(cx, state, dispatcher));
+
+    let window = gtk::ApplicationWindow::builder()
+        .application(app)
+        .title(TITLE)
+        .decorated(false)
+        .resizable(false)
+        .child(&strip)
+        .build();
+    let docked = layer_shell::dock(&window, edge);
+    window.present();
+    match docked {
+        true => info!(?edge, "docked the panel as a layer surface"),
+        false => place(&window, edge),
+    }
+}
@@ -0,0 +1,80 @@
+//! Compressed audio sent as-is over HDMI or S/PDIF (IEC 61937), for receivers that
+//! decode it themselves. The server only passes through what a sink's format list
+//! allows, and can't change the volume of such a stream.
+use serde::{Deserialize, Serialize};
+
+use crate::{devices::Device, streams::Stream};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Codec {
+    Ac3,
+    Eac3,
+    Dts,
+    DtsHd,
+    /// Also carries Atmos, as does E-AC3.
+    TrueHd,
+}
+
+impl Codec {
+    pub const ALL: [Self; 5] = [Self::Ac3, Self::Eac3, Self::Dts, Self::DtsHd, Self::TrueHd];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ac3 => "Dolby Digital (AC3)",
+            Self::Eac3 => "Dolby Digital Plus (E-AC3)",
+            Self::Dts => "DTS",
+            Self::DtsHd => "DTS-HD",
+            Self::TrueHd => "Dolby TrueHD",
+        }
+    }
+
+    /// Encoding name as used in server format lists.
+    pub fn format(self) -> &'static str {
+        match self {
+            Self::Ac3 => "ac3-iec61937",
+            Self::Eac3 => "eac3-iec61937",
+            Self::Dts => "dts-iec61937",
+            Self::DtsHd => "dtshd-iec61937",
+            Self::TrueHd => "truehd-iec61937",
+        }
+    }
+
+    fn from_format(format: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|codec| encoding(format) == codec.format())
+    }
+}
+
+/// The encoding of a format as printed by `pactl`, e.g. `ac3-iec61937, format.rate = "48000"`.
+fn encoding(format: &str) -> &str {
+    format.split(',').next().unwrap_or_default().trim()
+}
+
+/// Whether the sink goes out over a digital link that can carry compressed audio.
+pub fn is_capable(sink: &Device) -> bool {
+    let name = sink.name.to_lowercase();
+    name.contains("hdmi") || name.contains("iec958") || name.contains("spdif")
+}
+
+/// Codecs the sink is currently allowed to pass through.
+pub fn enabled(sink: &Device) -> Vec<Codec> {
+    sink.formats
+        .iter()
+        .filter_map(|format| Codec::from_format(format))
+        .collect()
+}
+
+/// Format list for `pactl set-sink-formats`, PCM always stays allowed.
+pub fn formats(codecs: &[Codec]) -> String {
+    std::iter::once("pcm")
+        .chain(codecs.iter().map(|codec| codec.format()))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The codec `stream` is passing through, `None` for ordinary PCM.
+pub fn of_stream(stream: &Stream) -> Option<Codec> {
+    Codec::from_format(&stream.format)
+}
@@ -0,0 +1,784 @@
+//! Port-level patching, with ports named and grouped the way JACK applications see
+//! them through PipeWire's JACK layer, so DAW ports can be wired up from here too.
+//! Listed per client, or drawn out as a graph to drag wires across.
+use std::{cell::RefCell, collections::HashMap, process::Command, rc::Rc, time::Duration};
+
+use eyre::Result;
+use gtk::{glib, prelude::*, Orientation};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    checked_output,
+    i18n::{tr, tr_with},
+    registry::{Context, Panel},
+    run_change, tool_output,
+};
+
+/// How long after patching to read the graph again.
+const RELOAD_DELAY: Duration = Duration::from_millis(300);
+
+/// Measures of the graph canvas, in pixels.
+const NODE_WIDTH: f64 = 200.;
+const HEADER: f64 = 24.;
+const ROW: f64 = 18.;
+const COLUMN_GAP: f64 = 120.;
+const NODE_GAP: f64 = 16.;
+const PORT_RADIUS: f64 = 4.;
+/// How close a press has to be to a port or wire to grab it.
+const REACH: f64 = 8.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub id: u32,
+    pub name: String,
+    pub description: Option<String>,
+    /// Like `Audio/Sink` or `Stream/Output/Audio`.
+    pub media_class: Option<String>,
+    /// Whether the node is a JACK client rather than something JACK sees through PipeWire.
+    pub jack: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub id: u32,
+    pub node: u32,
+    pub name: String,
+    pub direction: Direction,
+    pub midi: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub output: u32,
+    pub input: u32,
+}
+
+/// One object of `pw-dump`, with only what the patchbay looks at.
+#[derive(Debug, Deserialize)]
+struct Object {
+    id: u32,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    info: Option<Info>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Info {
+    #[serde(default)]
+    props: HashMap<String, Value>,
+    #[serde(rename = "output-port-id")]
+    output_port: Option<u32>,
+    #[serde(rename = "input-port-id")]
+    input_port: Option<u32>,
+}
+
+impl Info {
+    fn text(&self, key: &str) -> Option<String> {
+        match self.props.get(key)? {
+            Value::String(text) => Some(text.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn id(&self, key: &str) -> Option<u32> {
+        self.props.get(key)?.as_u64()?.try_into().ok()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub ports: Vec<Port>,
+    pub links: Vec<Link>,
+}
+
+impl Graph {
+    /// Reads the whole graph through `pw-dump`.
+    pub fn dump() -> Result<Self> {
+        let output = checked_output(&mut Command::new("pw-dump"))?;
+        let objects: Vec<Object> = tool_output::json_list_bytes(&output, "pw-dump objects")?;
+        let mut graph = Self::default();
+        for object in objects {
+            let info = object.info.unwrap_or_default();
+            match object.kind.as_str() {
+                "PipeWire:Interface:Node" => graph.nodes.push(Node {
+                    id: object.id,
+                    name: info.text("node.name").unwrap_or_default(),
+                    description: info
+                        .text("node.description")
+                        .or_else(|| info.text("node.nick")),
+                    media_class: info.text("media.class"),
+                    jack: info.text("client.api").as_deref() == Some("jack"),
+                }),
+                "PipeWire:Interface:Port" => {
+                    let (Some(node), Some(name)) = (info.id("node.id"), info.text("port.name"))
+                    else {
+                        continue;
+                    };
+                    graph.ports.push(Port {
+                        id: object.id,
+                        node,
+                        name,
+                        direction: match info.text("port.direction").as_deref() {
+                            Some("in") => Direction::In,
+                            _ => Direction::Out,
+                        },
+                        midi: info
+                            .text("format.dsp")
+                            .is_some_and(|format| format.contains("midi")),
+                    })
+                }
+                "PipeWire:Interface:Link" => {
+                    if let (Some(output), Some(input)) = (info.output_port, info.input_port) {
+                        graph.links.push(Link { output, input });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(graph)
+    }
+
+    pub fn node(&self, id: u32) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    /// The client name JACK applications see: JACK clients by the name they
+    /// registered, everything else by its description.
+    pub fn client_name(&self, node: &Node) -> String {
+        match node.jack {
+            true => node.name.clone(),
+            false => node
+                .description
+                .clone()
+                .unwrap_or_else(|| node.name.clone()),
+        }
+    }
+
+    /// `client:port`, as in `jack_lsp`.
+    pub fn jack_name(&self, port: &Port) -> String {
+        let client = self
+            .node(port.node)
+            .map_or_else(|| port.node.to_string(), |node| self.client_name(node));
+        format!("{client}:{}", port.name)
+    }
+
+    /// Ports on the other end of links from or to `port`.
+    pub fn peers<'a>(&'a self, port: &'a Port) -> impl Iterator<Item = &'a Port> + 'a {
+        self.links.iter().filter_map(move |link| {
+            let peer = match port.direction {
+                Direction::Out if link.output == port.id => link.input,
+                Direction::In if link.input == port.id => link.output,
+                _ => return None,
+            };
+            self.ports.iter().find(|candidate| candidate.id == peer)
+        })
+    }
+}
+
+fn pw_link(args: &[String]) -> Result<()> {
+    run_change(Command::new("pw-link").args(args)).map(drop)
+}
+
+#[instrument(ret, err)]
+pub fn link(output: u32, input: u32) -> Result<()> {
+    pw_link(&[output.to_string(), input.to_string()])
+}
+
+#[instrument(ret, err)]
+pub fn unlink(output: u32, input: u32) -> Result<()> {
+    pw_link(&["-d".to_owned(), output.to_string(), input.to_string()])
+}
+
+struct Patchbay {
+    rows: gtk::Box,
+    canvas: Canvas,
+    jack_only: gtk::CheckButton,
+    dispatcher: Dispatcher,
+}
+
+impl Patchbay {
+    fn load(self: &Rc<Self>) {
+        match Graph::dump() {
+            Ok(graph) => self.fill(&graph),
+            Err(error) => warn!(?error, "reading the graph"),
+        }
+    }
+
+    /// Patches `action` and shows the result, once the main loop got to it.
+    fn patch(self: &Rc<Self>, action: Action) {
+        self.dispatcher.dispatch(action);
+        let patchbay = self.clone();
+        glib::timeout_add_local_once(RELOAD_DELAY, move || patchbay.load());
+    }
+
+    /// Output ports grouped per client, JACK clients first.
+    fn fill(self: &Rc<Self>, graph: &Graph) {
+        while let Some(row) = self.rows.first_child() {
+            self.rows.remove(&row);
+        }
+        let jack_only = self.jack_only.is_active();
+        let shown = |node: &Node| node.jack || !jack_only;
+        self.canvas.show(graph, &shown);
+        let mut clients = graph
+            .nodes
+            .iter()
+            .filter(|node| shown(node))
+            .collect::<Vec<_>>();
+        clients.sort_by_key(|node| (!node.jack, graph.client_name(node)));
+        for client in clients {
+            let outputs = graph
+                .ports
+                .iter()
+                .filter(|port| port.node == client.id && port.direction == Direction::Out)
+                .collect::<Vec<_>>();
+            if outputs.is_empty() {
+                continue;
+            }
+            let title = gtk::Label::new(Some(&match client.jack {
+                true => tr_with("{client} (JACK)", &[("client", &graph.client_name(client))]),
+                false => graph.client_name(client),
+            }));
+            title.add_css_class("heading");
+            title.set_halign(gtk::Align::Start);
+            self.rows.append(&title);
+            for output in outputs {
+                self.rows.append(&self.port_row(graph, output, &shown));
+            }
+        }
+    }
+
+    fn port_row(
+        self: &Rc<Self>,
+        graph: &Graph,
+        output: &Port,
+        shown: &impl Fn(&Node) -> bool,
+    ) -> gtk::Box {
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        let name = gtk::Label::new(Some(&output.name));
+        name.set_halign(gtk::Align::Start);
+        name.set_hexpand(true);
+        row.append(&name);
+        for peer in graph.peers(output) {
+            let unlink = gtk::Button::with_label(&format!("→ {} ✕", graph.jack_name(peer)));
+            unlink.set_tooltip_text(Some(&tr("Disconnect")));
+            let patchbay = self.clone();
+            let (output, input) = (output.id, peer.id);
+            unlink.connect_clicked(move |_| patchbay.patch(Action::Unlink { output, input }));
+            row.append(&unlink);
+        }
+        // audio goes to audio and MIDI to MIDI, and nothing back into itself
+        let inputs = graph
+            .ports
+            .iter()
+            .filter(|input| {
+                input.direction == Direction::In
+                    && input.midi == output.midi
+                    && input.node != output.node
+                    && !graph.links.contains(&Link {
+                        output: output.id,
+                        input: input.id,
+                    })
+                    && graph.node(input.node).is_some_and(shown)
+            })
+            .collect::<Vec<_>>();
+        if inputs.is_empty() {
+            return row;
+        }
+        let names = inputs
+            .iter()
+            .map(|input| graph.jack_name(input))
+            .collect::<Vec<_>>();
+        let targets =
+            gtk::DropDown::from_strings(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        row.append(&targets);
+        let connect = gtk::Button::from_icon_name("list-add-symbolic");
+        connect.set_tooltip_text(Some(&tr("Connect")));
+        let patchbay = self.clone();
+        let output = output.id;
+        let inputs = inputs.iter().map(|input| input.id).collect::<Vec<_>>();
+        connect.connect_clicked(move |_| {
+            if let Some(input) = inputs.get(targets.selected() as usize).copied() {
+                patchbay.patch(Action::Link { output, input });
+            }
+        });
+        row.append(&connect);
+        row
+    }
+}
+
+/// What a drag on the canvas is doing.
+enum Drag {
+    /// Moving a node, from where it was.
+    Node { node: u32, from: (f64, f64) },
+    /// Pulling a wire out of a port, to where the pointer is.
+    Wire { port: u32, to: (f64, f64) },
+}
+
+/// The graph as drawn: nodes as boxes with inputs on the left and outputs on the
+/// right, links as wires between them.
+#[derive(Default)]
+struct Drawing {
+    graph: Graph,
+    /// Top left corners of the nodes, by id, kept over reloads.
+    positions: HashMap<u32, (f64, f64)>,
+    drag: Option<Drag>,
+}
+
+impl Drawing {
+    /// Takes over the shown part of `graph`, nodes without ports left out. New nodes
+    /// go in a column by what they do: sources, filters, then sinks.
+    fn set_graph(&mut self, graph: &Graph, shown: &impl Fn(&Node) -> bool) {
+        let has = |node: &Node, direction| {
+            graph
+                .ports
+                .iter()
+                .any(|port| port.node == node.id && port.direction == direction)
+        };
+        let nodes = graph
+            .nodes
+            .iter()
+            .filter(|node| shown(node) && (has(node, Direction::In) || has(node, Direction::Out)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let ids = nodes.iter().map(|node| node.id).collect::<Vec<_>>();
+        let ports = graph
+            .ports
+            .iter()
+            .filter(|port| ids.contains(&port.node))
+            .cloned()
+            .collect::<Vec<_>>();
+        let port_ids = ports.iter().map(|port| port.id).collect::<Vec<_>>();
+        self.graph = Graph {
+            links: graph
+                .links
+                .iter()
+                .filter(|link| port_ids.contains(&link.output) && port_ids.contains(&link.input))
+                .copied()
+                .collect(),
+            nodes,
+            ports,
+        };
+        self.positions.retain(|id, _| ids.contains(id));
+        let mut bottoms = [0.; 3];
+        for (id, (_, y)) in &self.positions {
+            let Some(node) = self.graph.node(*id) else {
+                continue;
+            };
+            let column = self.column(node);
+            bottoms[column] = f64::max(bottoms[column], y + self.size(node).1);
+        }
+        for node in &self.graph.nodes {
+            if self.positions.contains_key(&node.id) {
+                continue;
+            }
+            let column = self.column(node);
+            let x = NODE_GAP + column as f64 * (NODE_WIDTH + COLUMN_GAP);
+            let y = bottoms[column] + NODE_GAP;
+            bottoms[column] = y + self.size(node).1;
+            self.positions.insert(node.id, (x, y));
+        }
+    }
+
+    fn column(&self, node: &Node) -> usize {
+        let has = |direction| self.ports_of(node.id, direction).next().is_some();
+        match (has(Direction::In), has(Direction::Out)) {
+            (false, _) => 0,
+            (true, true) => 1,
+            (true, false) => 2,
+        }
+    }
+
+    fn ports_of(&self, node: u32, direction: Direction) -> impl Iterator<Item = &Port> {
+        self.graph
+            .ports
+            .iter()
+            .filter(move |port| port.node == node && port.direction == direction)
+    }
+
+    fn size(&self, node: &Node) -> (f64, f64) {
+        let rows = usize::max(
+            self.ports_of(node.id, Direction::In).count(),
+            self.ports_of(node.id, Direction::Out).count(),
+        );
+        (NODE_WIDTH, HEADER + rows as f64 * ROW + PORT_RADIUS)
+    }
+
+    /// How big the canvas has to be to show every node.
+    fn extent(&self) -> (i32, i32) {
+        self.graph
+            .nodes
+            .iter()
+            .filter_map(|node| {
+                let (x, y) = self.positions.get(&node.id)?;
+                let (width, height) = self.size(node);
+                Some((
+                    (x + width + NODE_GAP) as i32,
+                    (y + height + NODE_GAP) as i32,
+                ))
+            })
+            .fold((0, 0), |(width, height), (right, bottom)| {
+                (width.max(right), height.max(bottom))
+            })
+    }
+
+    fn port_position(&self, port: &Port) -> Option<(f64, f64)> {
+        let (x, y) = self.positions.get(&port.node)?;
+        let index = self
+            .ports_of(port.node, port.direction)
+            .position(|other| other.id == port.id)?;
+        let x = match port.direction {
+            Direction::In => *x,
+            Direction::Out => x + NODE_WIDTH,
+        };
+        Some((x, y + HEADER + (index as f64 + 0.5) * ROW))
+    }
+
+    fn port_at(&self, (x, y): (f64, f64)) -> Option<&Port> {
+        self.graph.ports.iter().find(|port| {
+            self.port_position(port)
+                .is_some_and(|(px, py)| (px - x).hypot(py - y) <= REACH)
+        })
+    }
+
+    fn node_at(&self, (x, y): (f64, f64)) -> Option<&Node> {
+        // the last drawn is on top
+        self.graph.nodes.iter().rev().find(|node| {
+            let Some((left, top)) = self.positions.get(&node.id) else {
+                return false;
+            };
+            let (width, height) = self.size(node);
+            (*left..left + width).contains(&x) && (*top..top + height).contains(&y)
+        })
+    }
+
+    /// Control points of a wire, bulging out of the ports sideways.
+    fn wire(from: (f64, f64), to: (f64, f64)) -> [(f64, f64); 4] {
+        let bulge = f64::max(40., (to.0 - from.0).abs() / 2.);
+        [from, (from.0 + bulge, from.1), (to.0 - bulge, to.1), to]
+    }
+
+    fn link_at(&self, (x, y): (f64, f64)) -> Option<Link> {
+        self.graph.links.iter().copied().find(|link| {
+            let ends = self.port(link.output).zip(self.port(link.input));
+            let Some((Some(from), Some(to))) =
+                ends.map(|(output, input)| (self.port_position(output), self.port_position(input)))
+            else {
+                return false;
+            };
+            let [p0, p1, p2, p3] = Self::wire(from, to);
+            (0..=32).any(|step| {
+                let t = f64::from(step) / 32.;
+                let s = 1. - t;
+                let point = |a: f64, b: f64, c: f64, d: f64| {
+                    s * s * s * a + 3. * s * s * t * b + 3. * s * t * t * c + t * t * t * d
+                };
+                let (px, py) = (point(p0.0, p1.0, p2.0, p3.0), point(p0.1, p1.1, p2.1, p3.1));
+                (px - x).hypot(py - y) <= REACH
+            })
+        })
+    }
+
+    fn port(&self, id: u32) -> Option<&Port> {
+        self.graph.ports.iter().find(|port| port.id == id)
+    }
+
+    fn draw(&self, cairo: &gtk::cairo::Context, foreground: gtk::gdk::RGBA) {
+        let ink = |cairo: &gtk::cairo::Context, alpha: f64| {
+            cairo.set_source_rgba(
+                foreground.red().into(),
+                foreground.green().into(),
+                foreground.blue().into(),
+                alpha,
+            )
+        };
+        let stroke_wire = |from, to, midi: bool| {
+            let [from, first, second, to] = Self::wire(from, to);
+            match midi {
+                true => cairo.set_source_rgb(0.85, 0.35, 0.35),
+                false => cairo.set_source_rgb(0.3, 0.55, 0.9),
+            }
+            cairo.set_line_width(2.);
+            cairo.move_to(from.0, from.1);
+            cairo.curve_to(first.0, first.1, second.0, second.1, to.0, to.1);
+            cairo.stroke().ok();
+        };
+        for node in &self.graph.nodes {
+            let Some((x, y)) = self.positions.get(&node.id).copied() else {
+                continue;
+            };
+            let (width, height) = self.size(node);
+            ink(cairo, 0.08);
+            cairo.rectangle(x, y, width, height);
+            cairo.fill().ok();
+            ink(cairo, 0.4);
+            cairo.set_line_width(1.);
+            cairo.rectangle(x, y, width, height);
+            cairo.stroke().ok();
+            cairo.save().ok();
+            cairo.rectangle(x, y, width, height);
+            cairo.clip();
+            ink(cairo, 1.);
+            cairo.set_font_size(12.);
+            cairo.move_to(x + 6., y + 16.);
+            cairo.show_text(&self.graph.client_name(node)).ok();
+            cairo.set_font_size(10.);
+            for port in self.graph.ports.iter().filter(|port| port.node == node.id) {
+                let Some((px, py)) = self.port_position(port) else {
+                    continue;
+                };
+                let label = match port.direction {
+                    Direction::In => px + PORT_RADIUS + 4.,
+                    Direction::Out => {
+                        let extents = cairo.text_extents(&port.name).ok();
+                        px - PORT_RADIUS - 4. - extents.map_or(0., |extents| extents.x_advance())
+                    }
+                };
+                ink(cairo, 0.8);
+                cairo.move_to(label, py + 3.5);
+                cairo.show_text(&port.name).ok();
+            }
+            cairo.restore().ok();
+            for port in self.graph.ports.iter().filter(|port| port.node == node.id) {
+                if let Some((px, py)) = self.port_position(port) {
+                    ink(cairo, 0.9);
+                    cairo.arc(px, py, PORT_RADIUS, 0., std::f64::consts::TAU);
+                    cairo.fill().ok();
+                }
+            }
+        }
+        for link in &self.graph.links {
+            let (Some(output), Some(input)) = (self.port(link.output), self.port(link.input))
+            else {
+                continue;
+            };
+            if let (Some(from), Some(to)) = (self.port_position(output), self.port_position(input))
+            {
+                stroke_wire(from, to, output.midi);
+            }
+        }
+        if let Some(Drag::Wire { port, to }) = &self.drag {
+            let Some(port) = self.port(*port) else {
+                return;
+            };
+            if let Some(at) = self.port_position(port) {
+                match port.direction {
+                    Direction::Out => stroke_wire(at, *to, port.midi),
+                    Direction::In => stroke_wire(*to, at, port.midi),
+                }
+            }
+        }
+    }
+
+    /// The link a wire dropped at `at` makes, audio to audio and MIDI to MIDI.
+    fn dropped(&self, port: u32, at: (f64, f64)) -> Option<Link> {
+        let from = self.port(port)?;
+        let to = self.port_at(at)?;
+        if to.direction == from.direction || to.midi != from.midi || to.node == from.node {
+            return None;
+        }
+        let link = match from.direction {
+            Direction::Out => Link {
+                output: from.id,
+                input: to.id,
+            },
+            Direction::In => Link {
+                output: to.id,
+                input: from.id,
+            },
+        };
+        (!self.graph.links.contains(&link)).then_some(link)
+    }
+}
+
+/// The graph drawn out, wired up by dragging from port to port. Nodes move by
+/// dragging them, wires go away with a right click.
+struct Canvas {
+    area: gtk::DrawingArea,
+    drawing: Rc<RefCell<Drawing>>,
+}
+
+impl Canvas {
+    fn new(patch: impl Fn(Action) + Clone + 'static) -> Self {
+        let area = gtk::DrawingArea::new();
+        let drawing = Rc::new(RefCell::new(Drawing::default()));
+        area.set_draw_func({
+            let drawing = drawing.clone();
+            move |area, cairo, _, _| drawing.borrow().draw(cairo, area.style_context().color())
+        });
+
+        let drag = gtk::GestureDrag::new();
+        drag.connect_drag_begin({
+            let drawing = drawing.clone();
+            move |_, x, y| {
+                let mut drawing = drawing.borrow_mut();
+                drawing.drag = match drawing.port_at((x, y)) {
+                    Some(port) => Some(Drag::Wire {
+                        port: port.id,
+                        to: (x, y),
+                    }),
+                    None => drawing.node_at((x, y)).map(|node| Drag::Node {
+                        node: node.id,
+                        from: drawing.positions[&node.id],
+                    }),
+                };
+            }
+        });
+        drag.connect_drag_update({
+            let drawing = drawing.clone();
+            let area = area.clone();
+            move |drag, dx, dy| {
+                let Some((x, y)) = drag.start_point() else {
+                    return;
+                };
+                let mut drawing = drawing.borrow_mut();
+                let drawing = &mut *drawing;
+                match &mut drawing.drag {
+                    Some(Drag::Wire { to, .. }) => *to = (x + dx, y + dy),
+                    Some(Drag::Node { node, from }) => {
+                        let moved = ((from.0 + dx).max(0.), (from.1 + dy).max(0.));
+                        drawing.positions.insert(*node, moved);
+                        let (width, height) = drawing.extent();
+                        area.set_content_width(width);
+                        area.set_content_height(height);
+                    }
+                    None => return,
+                }
+                area.queue_draw();
+            }
+        });
+        drag.connect_drag_end({
+            let drawing = drawing.clone();
+            let area = area.clone();
+            let patch = patch.clone();
+            move |drag, dx, dy| {
+                let Some((x, y)) = drag.start_point() else {
+                    return;
+                };
+                let mut drawing = drawing.borrow_mut();
+                if let Some(Drag::Wire { port, .. }) = drawing.drag.take() {
+                    if let Some(Link { output, input }) = drawing.dropped(port, (x + dx, y + dy)) {
+                        patch(Action::Link { output, input });
+                    }
+                }
+                area.queue_draw();
+            }
+        });
+        area.add_controller(drag);
+
+        let unlink = gtk::GestureClick::new();
+        unlink.set_button(gtk::gdk::BUTTON_SECONDARY);
+        unlink.connect_pressed({
+            let drawing = drawing.clone();
+            move |_, _, x, y| {
+                let link = drawing.borrow().link_at((x, y));
+                if let Some(Link { output, input }) = link {
+                    patch(Action::Unlink { output, input });
+                }
+            }
+        });
+        area.add_controller(unlink);
+        Self { area, drawing }
+    }
+
+    fn show(&self, graph: &Graph, shown: &impl Fn(&Node) -> bool) {
+        let mut drawing = self.drawing.borrow_mut();
+        drawing.set_graph(graph, shown);
+        let (width, height) = drawing.extent();
+        self.area.set_content_width(width);
+        self.area.set_content_height(height);
+        self.area.queue_draw();
+    }
+}
+
+/// The patchbay as one of the window's panels.
+pub struct PatchbayPanel;
+
+impl Panel for PatchbayPanel {
+    fn title(&self) -> &'static str {
+        "Patchbay"
+    }
+
+    fn build(&self, context: &Context) -> gtk::Widget {
+        patchbay_panel(context.dispatcher.clone()).upcast()
+    }
+}
+
+/// Expander listing output ports per client, with their connections, or drawing
+/// the graph to patch by dragging. Read when opened.
+pub fn patchbay_panel(dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let controls = gtk::Box::new(Orientation::Horizontal, 6);
+    let jack_only = gtk::CheckButton::with_label(&tr("JACK clients only"));
+    jack_only.set_hexpand(true);
+    controls.append(&jack_only);
+    let as_graph = gtk::CheckButton::with_label(&tr("Graph"));
+    as_graph.set_tooltip_text(Some(&tr(
+        "Drag from port to port to connect, right click a wire to disconnect",
+    )));
+    controls.append(&as_graph);
+    let refresh = gtk::Button::from_icon_name("view-refresh-symbolic");
+    controls.append(&refresh);
+    layout.append(&controls);
+    let rows = gtk::Box::new(Orientation::Vertical, 4);
+    let views = gtk::Stack::new();
+    views.add_named(&rows, Some("list"));
+    layout.append(&views);
+    let expander = gtk::Expander::builder()
+        .label("Patchbay")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+
+    let patchbay = Rc::new_cyclic(|patchbay: &std::rc::Weak<Patchbay>| {
+        let patchbay = patchbay.clone();
+        let canvas = Canvas::new(move |action| {
+            if let Some(patchbay) = patchbay.upgrade() {
+                patchbay.patch(action);
+            }
+        });
+        let scrolled = gtk::ScrolledWindow::builder()
+            .child(&canvas.area)
+            .min_content_height(400)
+            .build();
+        views.add_named(&scrolled, Some("graph"));
+        Patchbay {
+            rows,
+            canvas,
+            jack_only: jack_only.clone(),
+            dispatcher,
+        }
+    });
+    as_graph.connect_toggled(move |as_graph| {
+        views.set_visible_child_name(match as_graph.is_active() {
+            true => "graph",
+            false => "list",
+        })
+    });
+    refresh.connect_clicked({
+        let patchbay = patchbay.clone();
+        move |_| patchbay.load()
+    });
+    jack_only.connect_toggled({
+        let patchbay = patchbay.clone();
+        move |_| patchbay.load()
+    });
+    expander.connect_expanded_notify(move |expander| {
+        if expander.is_expanded() {
+            patchbay.load();
+        }
+    });
+    expander
+}
@@ -0,0 +1,283 @@
+//! Saving power on battery, as UPower tells: the graph runs at a larger quantum so
+//! it wakes up less often, idle devices are suspended sooner than the session
+//! manager would, and effects and night mode are switched off. Each of them is up
+//! to the user, and all of it is put back on AC.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, Result};
+use gtk::{
+    gio,
+    glib::{self, ToVariant, Variant},
+    prelude::*,
+    Orientation,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::DeviceKind,
+    errors::OrReport,
+    extensions::watch,
+    game_mode,
+    i18n::tr,
+    priorities::Suspend,
+    state::AppState,
+};
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+
+/// Smallest quantum the graph runs at, raised on battery.
+const MIN_QUANTUM: &str = "clock.min-quantum";
+/// How often idle devices are looked for.
+const IDLE_CHECK: Duration = Duration::from_secs(10);
+
+/// What changes on battery, `None` and `false` leaving it as it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatterySaving {
+    /// Smallest quantum, in frames, like 1024 for about 21ms at 48kHz.
+    pub quantum: Option<u32>,
+    /// Seconds a device idles before it's suspended.
+    pub suspend_after_secs: Option<u64>,
+    /// Bypass effects presets and night mode.
+    pub bypass_effects: bool,
+}
+
+impl BatterySaving {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn system_bus() -> Result<gio::DBusConnection> {
+    gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE)
+        .map_err(|error| eyre!("connecting to the system bus: {error}"))
+}
+
+/// Whether the machine runs on battery, `false` without UPower.
+#[instrument(skip(connection), ret, err)]
+fn on_battery(connection: &gio::DBusConnection) -> Result<bool> {
+    let reply = connection
+        .call_sync(
+            Some(UPOWER),
+            UPOWER_PATH,
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            Some(&(UPOWER, "OnBattery").to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|error| eyre!("asking UPower: {error}"))?;
+    reply
+        .get::<(Variant,)>()
+        .and_then(|(value,)| value.get::<bool>())
+        .ok_or_else(|| eyre!("unexpected OnBattery {reply:?}"))
+}
+
+/// What applying the settings changed, to undo.
+struct Saved {
+    saving: BatterySaving,
+    min_quantum: u32,
+    night_mode: bool,
+    /// The timer looking for idle devices.
+    idle: Option<glib::SourceId>,
+}
+
+/// Suspends devices idle for longer than `after`, apart from those the user keeps awake.
+fn suspend_idle(state: AppState, dispatcher: Dispatcher, after: Duration) -> glib::SourceId {
+    // when each device was first seen idle
+    let idle_since = Rc::new(RefCell::new(HashMap::<String, Instant>::new()));
+    glib::timeout_add_local(IDLE_CHECK, move || {
+        let mut idle_since = idle_since.borrow_mut();
+        for kind in [DeviceKind::Sink, DeviceKind::Source] {
+            let devices = state.devices(kind).get_untracked();
+            for device in devices {
+                let awake = state.device_settings.with_untracked(|store| {
+                    store
+                        .get(&device)
+                        .is_some_and(|settings| settings.suspend == Some(Suspend::Never))
+                });
+                if device.state != "IDLE" || awake {
+                    idle_since.remove(&device.name);
+                    continue;
+                }
+                let since = *idle_since
+                    .entry(device.name.clone())
+                    .or_insert_with(Instant::now);
+                if since.elapsed() >= after {
+                    info!("suspending idle {}", device.name);
+                    dispatcher.dispatch(Action::SetSuspended {
+                        kind,
+                        device: device.name.clone(),
+                        suspended: true,
+                    });
+                    idle_since.remove(&device.name);
+                }
+            }
+        }
+        glib::Continue(true)
+    })
+}
+
+fn apply(state: AppState, dispatcher: &Dispatcher, saving: BatterySaving) -> Saved {
+    info!(?saving, "saving power on battery");
+    let saved = Saved {
+        saving,
+        min_quantum: game_mode::setting(MIN_QUANTUM)
+            .map_err(|error| warn!(?error, "reading the smallest quantum"))
+            .unwrap_or(0),
+        night_mode: state.night_mode.get_untracked(),
+        idle: saving
+            .suspend_after_secs
+            .map(|seconds| suspend_idle(state, dispatcher.clone(), Duration::from_secs(seconds))),
+    };
+    if let Some(quantum) = saving.quantum {
+        game_mode::set_setting(MIN_QUANTUM, quantum).or_report(state, "raising the quantum");
+    }
+    if saving.bypass_effects {
+        state.night_mode.set(false);
+        game_mode::bypass_presets(state, true);
+    }
+    saved
+}
+
+fn restore(state: AppState, saved: Saved) {
+    info!("back on AC");
+    if saved.saving.quantum.is_some() {
+        game_mode::set_setting(MIN_QUANTUM, saved.min_quantum)
+            .or_report(state, "restoring the quantum");
+    }
+    if saved.saving.bypass_effects {
+        if saved.night_mode {
+            state.night_mode.set(true);
+        }
+        game_mode::bypass_presets(state, false);
+    }
+    if let Some(idle) = saved.idle {
+        idle.remove();
+    }
+}
+
+/// Follows UPower, and applies what the user picked while on battery.
+pub fn spawn(cx: Scope, state: AppState, dispatcher: Dispatcher) {
+    match system_bus() {
+        Ok(bus) => {
+            let read = {
+                let bus = bus.clone();
+                move || {
+                    let battery = on_battery(&bus).unwrap_or(false);
+                    if state.on_battery.get_untracked() != battery {
+                        state.on_battery.set(battery);
+                    }
+                }
+            };
+            read();
+            bus.signal_subscribe(
+                Some(UPOWER),
+                Some("org.freedesktop.DBus.Properties"),
+                Some("PropertiesChanged"),
+                Some(UPOWER_PATH),
+                Some(UPOWER),
+                gio::DBusSignalFlags::NONE,
+                move |_, _, _, _, _, _| read(),
+            );
+        }
+        Err(error) => info!(?error, "the power supply won't be followed"),
+    }
+    let active = create_memo(cx, move |_| {
+        let saving = state.device_settings.with(|store| store.battery_saving);
+        (state.on_battery.get() && !saving.is_empty()).then_some(saving)
+    });
+    create_effect(cx, move |saved: Option<Option<Saved>>| {
+        let saving = active.get();
+        let saved = saved.flatten();
+        // settings picked anew on battery are applied from scratch
+        if saved.as_ref().map(|saved| saved.saving) == saving {
+            return saved;
+        }
+        if let Some(saved) = saved {
+            restore(state, saved);
+        }
+        saving.map(|saving| apply(state, &dispatcher, saving))
+    });
+}
+
+/// Expander picking what changes on battery, and telling whether it's on battery.
+pub fn battery_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let current = state
+        .device_settings
+        .with_untracked(|store| store.battery_saving);
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let status = gtk::Label::new(None);
+    status.set_halign(gtk::Align::Start);
+    status.add_css_class("dim-label");
+    watch(cx, &status, move |status| {
+        status.set_text(&match state.on_battery.get() {
+            true => tr("Running on battery."),
+            false => tr("Running on AC."),
+        })
+    });
+    layout.append(&status);
+
+    let spin_row = |label: &str, range: (f64, f64, f64), value: Option<f64>, default: f64| {
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        let check = gtk::CheckButton::with_label(label);
+        check.set_active(value.is_some());
+        row.append(&check);
+        let (lowest, highest, step) = range;
+        let spin = gtk::SpinButton::with_range(lowest, highest, step);
+        spin.set_hexpand(true);
+        spin.set_value(value.unwrap_or(default));
+        check
+            .bind_property("active", &spin, "sensitive")
+            .sync_create()
+            .build();
+        row.append(&spin);
+        layout.append(&row);
+        (check, spin)
+    };
+    let (larger, quantum) = spin_row(
+        &tr("Run at a quantum of at least (frames)"),
+        (256., 8192., 256.),
+        current.quantum.map(f64::from),
+        1024.,
+    );
+    larger.set_tooltip_text(Some(&tr("Wakes up less often, at the cost of latency")));
+    let (suspend, seconds) = spin_row(
+        &tr("Suspend idle devices after (s)"),
+        (1., 60., 1.),
+        current.suspend_after_secs.map(|seconds| seconds as f64),
+        3.,
+    );
+    let bypass = gtk::CheckButton::with_label(&tr("Turn effects and night mode off"));
+    bypass.set_active(current.bypass_effects);
+    layout.append(&bypass);
+
+    let apply = gtk::Button::with_label(&tr("Apply"));
+    apply.set_halign(gtk::Align::End);
+    apply.connect_clicked(move |_| {
+        dispatcher.dispatch(Action::SetBatterySaving(BatterySaving {
+            quantum: larger.is_active().then(|| quantum.value() as u32),
+            suspend_after_secs: suspend.is_active().then(|| seconds.value() as u64),
+            bypass_effects: bypass.is_active(),
+        }));
+    });
+    layout.append(&apply);
+    gtk::Expander::builder()
+        .label("On battery")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
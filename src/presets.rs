@@ -0,0 +1,604 @@
+//! EasyEffects (and PulseEffects) output presets, played through a filter chain.
+//! Of their plugins the equalizer, compressor and limiter carry over: the equalizer
+//! as biquads, the other two as the SWH LADSPA plugins night mode uses as well.
+//! Imported presets are kept, as they came, in `$XDG_CONFIG_HOME/pipeweld/presets`.
+//! A device's trim plays in front of its preset, and on its own without one, so EQ
+//! boosts can be made up for without touching the volume.
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Write,
+    path::PathBuf,
+    rc::Rc,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::{gio, prelude::*, Orientation};
+use leptos::*;
+use serde_json::Value;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    easyeffects,
+    equalizer::Equalizer,
+    filter_chain::{self, quote, Filter, Host},
+    i18n::tr,
+    state::AppState,
+};
+
+/// One equalizer band, a biquad of the filter chain's builtin ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub kind: &'static str,
+    pub frequency: f64,
+    pub gain: f64,
+    pub q: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Overall gain in dB and the bands.
+    Equalizer {
+        gain: f64,
+        bands: Vec<Band>,
+    },
+    Compressor {
+        attack_ms: f64,
+        release_ms: f64,
+        threshold_db: f64,
+        ratio: f64,
+        knee_db: f64,
+        makeup_db: f64,
+    },
+    Limiter {
+        threshold_db: f64,
+    },
+}
+
+/// Lowest and highest trim offered, in dB.
+pub const TRIM_RANGE_DB: (i32, i32) = (-24, 12);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub effects: Vec<Effect>,
+    /// Plugins of the preset pipeweld has no counterpart for.
+    pub skipped: Vec<String>,
+}
+
+/// Filter-chain biquad standing in for an EasyEffects band type.
+fn biquad(band_type: &str) -> Option<&'static str> {
+    Some(match band_type {
+        "Bell" => "bq_peaking",
+        "Lo-shelf" => "bq_lowshelf",
+        "Hi-shelf" => "bq_highshelf",
+        "Lo-pass" => "bq_lowpass",
+        "Hi-pass" => "bq_highpass",
+        "Notch" => "bq_notch",
+        "Bandpass" => "bq_bandpass",
+        "Allpass" => "bq_allpass",
+        _ => return None,
+    })
+}
+
+fn number(settings: &Value, key: &str, default: f64) -> f64 {
+    settings.get(key).and_then(Value::as_f64).unwrap_or(default)
+}
+
+fn equalizer(settings: &Value) -> Effect {
+    // split channels are rare, the left one stands for both
+    let bands = settings
+        .get("left")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter(|(_, band)| !band.get("mute").and_then(Value::as_bool).unwrap_or(false))
+        .filter_map(|(_, band)| {
+            Some(Band {
+                kind: biquad(band.get("type")?.as_str()?)?,
+                frequency: number(band, "frequency", 1000.),
+                gain: number(band, "gain", 0.),
+                q: number(band, "q", 1.),
+            })
+        })
+        .collect();
+    Effect::Equalizer {
+        gain: number(settings, "input-gain", 0.) + number(settings, "output-gain", 0.),
+        bands,
+    }
+}
+
+fn biquad_node(
+    index: usize,
+    kind: &str,
+    frequency: f64,
+    gain: f64,
+    q: f64,
+) -> (String, String, &'static str, &'static str) {
+    let name = format!("eq{index}");
+    let node = format!(
+        r#"{{ type = builtin name = {name} label = {kind} control = {{ "Freq" = {frequency} "Q" = {q} "Gain" = {gain} }} }}"#
+    );
+    (name, node, "In", "Out")
+}
+
+impl Preset {
+    /// Reads the output chain of an EasyEffects preset.
+    pub fn parse(name: &str, json: &str) -> Result<Self> {
+        let preset: Value = serde_json::from_str(json).wrap_err("parsing the preset")?;
+        let output = preset
+            .get("output")
+            .ok_or_else(|| eyre!("{name} has no output effects"))?;
+        let order = output
+            .get("plugins_order")
+            .and_then(Value::as_array)
+            .ok_or_else(|| eyre!("{name} doesn't list its plugins"))?;
+        let mut effects = Vec::new();
+        let mut skipped = Vec::new();
+        for plugin in order.iter().filter_map(Value::as_str) {
+            let settings = output.get(plugin).unwrap_or(&Value::Null);
+            if settings.get("bypass").and_then(Value::as_bool) == Some(true) {
+                continue;
+            }
+            // newer versions number the instances, as in `equalizer#0`
+            match plugin.split('#').next().unwrap_or(plugin) {
+                "equalizer" => effects.push(equalizer(settings)),
+                "compressor" => effects.push(Effect::Compressor {
+                    attack_ms: number(settings, "attack", 20.),
+                    release_ms: number(settings, "release", 100.),
+                    threshold_db: number(settings, "threshold", -12.),
+                    ratio: number(settings, "ratio", 4.),
+                    knee_db: number(settings, "knee", -6.).abs(),
+                    makeup_db: number(settings, "makeup", 0.),
+                }),
+                "limiter" => effects.push(Effect::Limiter {
+                    threshold_db: number(settings, "threshold", -1.),
+                }),
+                _ => skipped.push(plugin.to_owned()),
+            }
+        }
+        if !skipped.is_empty() {
+            info!(?skipped, "{name} has effects pipeweld can't play");
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            effects,
+            skipped,
+        })
+    }
+
+    /// SPA-JSON `filter.graph` running the effects one after another, with
+    /// `trim_db` of gain in front and a limiter at `limit_db` behind.
+    pub fn graph(&self, trim_db: Option<i32>, limit_db: Option<i32>) -> String {
+        // node, its input and its output port
+        let mut chain = Vec::<(String, String, &str, &str)>::new();
+        if let Some(trim_db) = trim_db.filter(|trim_db| *trim_db != 0) {
+            let mult = 10f64.powf(f64::from(trim_db) / 20.);
+            let node = format!(
+                r#"{{ type = builtin name = trim label = linear control = {{ "Mult" = {mult} }} }}"#
+            );
+            chain.push(("trim".to_owned(), node, "In", "Out"));
+        }
+        for effect in &self.effects {
+            match effect {
+                Effect::Equalizer { gain, bands } => {
+                    if *gain != 0. {
+                        // a shelf from 0 Hz up is plain gain
+                        chain.push(biquad_node(chain.len(), "bq_highshelf", 0., *gain, 1.));
+                    }
+                    for band in bands {
+                        chain.push(biquad_node(
+                            chain.len(),
+                            band.kind,
+                            band.frequency,
+                            band.gain,
+                            band.q,
+                        ));
+                    }
+                }
+                Effect::Compressor {
+                    attack_ms,
+                    release_ms,
+                    threshold_db,
+                    ratio,
+                    knee_db,
+                    makeup_db,
+                } => {
+                    let name = format!("compressor{}", chain.len());
+                    let node = format!(
+                        r#"{{ type = ladspa name = {name} plugin = sc4m_1916 label = sc4m control = {{ "Attack time (ms)" = {attack_ms} "Release time (ms)" = {release_ms} "Threshold level (dB)" = {threshold_db} "Ratio (1:n)" = {ratio} "Knee radius (dB)" = {knee_db} "Makeup gain (dB)" = {makeup_db} }} }}"#
+                    );
+                    chain.push((name, node, "Input", "Output"));
+                }
+                Effect::Limiter { threshold_db } => {
+                    let name = format!("limiter{}", chain.len());
+                    let node = format!(
+                        r#"{{ type = ladspa name = {name} plugin = hard_limiter_1413 label = hardLimiter control = {{ "dB limit" = {threshold_db} "Wet level" = 1 "Residue level" = 0 }} }}"#
+                    );
+                    chain.push((name, node, "Input", "Output"));
+                }
+            }
+        }
+        if let Some(limit_db) = limit_db {
+            let node = format!(
+                r#"{{ type = ladspa name = ceiling plugin = hard_limiter_1413 label = hardLimiter control = {{ "dB limit" = {limit_db} "Wet level" = 1 "Residue level" = 0 }} }}"#
+            );
+            chain.push(("ceiling".to_owned(), node, "Input", "Output"));
+        }
+        if chain.is_empty() {
+            // nothing to do, but a graph needs a node
+            chain.push(biquad_node(0, "bq_highshelf", 0., 0., 1.));
+        }
+        let mut graph = String::from("{\n    nodes = [\n");
+        for (_, node, _, _) in &chain {
+            writeln!(graph, "        {node}").ok();
+        }
+        graph.push_str("    ]\n    links = [\n");
+        for pair in chain.windows(2) {
+            let ((from, _, _, output), (to, _, input, _)) = (&pair[0], &pair[1]);
+            writeln!(
+                graph,
+                "        {{ output = {} input = {} }}",
+                quote(&format!("{from}:{output}")),
+                quote(&format!("{to}:{input}"))
+            )
+            .ok();
+        }
+        graph.push_str("    ]\n}");
+        graph
+    }
+}
+
+pub fn directory() -> PathBuf {
+    gtk::glib::user_config_dir()
+        .join(clap::crate_name!())
+        .join("presets")
+}
+
+/// Names of the imported presets, sorted.
+pub fn list() -> Vec<String> {
+    let mut names = std::fs::read_dir(directory())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            (path.extension()? == "json").then(|| path.file_stem()?.to_str().map(str::to_owned))?
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+pub fn load(name: &str) -> Result<Preset> {
+    let path = directory().join(format!("{name}.json"));
+    let json =
+        std::fs::read_to_string(&path).wrap_err_with(|| format!("reading {}", path.display()))?;
+    Preset::parse(name, &json)
+}
+
+/// Copies the preset at `path` over, returning its name.
+#[instrument(ret, err)]
+pub fn import(path: &std::path::Path) -> Result<String> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| eyre!("{} has no usable name", path.display()))?
+        .to_owned();
+    let json =
+        std::fs::read_to_string(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+    // only keep what can be played
+    Preset::parse(&name, &json)?;
+    let directory = directory();
+    std::fs::create_dir_all(&directory)
+        .wrap_err_with(|| format!("creating {}", directory.display()))?;
+    let target = directory.join(format!("{name}.json"));
+    std::fs::write(&target, json).wrap_err_with(|| format!("writing {}", target.display()))?;
+    Ok(name)
+}
+
+fn object(master: &str) -> String {
+    format!("preset:{master}")
+}
+
+/// Plays everything on `master` through preset `preset` and `equalizer` after
+/// `trim_db` of gain and into a limiter at `limit_db`, or stops doing so when there's
+/// none of them.
+pub fn apply(
+    host: &mut Host,
+    master: &Device,
+    preset: Option<&str>,
+    equalizer: Option<&Equalizer>,
+    trim_db: Option<i32>,
+    limit_db: Option<i32>,
+) -> Result<()> {
+    let trim_db = trim_db.filter(|trim_db| *trim_db != 0);
+    if preset.is_none() && equalizer.is_none() && trim_db.is_none() && limit_db.is_none() {
+        host.stop(&object(&master.name));
+        return Ok(());
+    }
+    if (preset.is_some() || equalizer.is_some()) && easyeffects::is_virtual(master) {
+        return Err(eyre!(
+            "EasyEffects processes {} already, load the preset there",
+            master.name
+        ));
+    }
+    let mut preset = preset.map(load).transpose()?.unwrap_or_default();
+    if let Some(equalizer) = equalizer {
+        preset.effects.push(equalizer.effect());
+    }
+    let description = [
+        Some(preset.name.clone()).filter(|name| !name.is_empty()),
+        equalizer.map(Equalizer::describe),
+        trim_db.map(|trim_db| format!("trimmed {trim_db:+} dB")),
+        limit_db.map(|limit_db| format!("limited to {limit_db} dB")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(", ");
+    let filter = Filter {
+        object: object(&master.name),
+        description: format!("{} ({description})", master.description),
+        master: master.name.clone(),
+        graph: preset.graph(trim_db, limit_db),
+    };
+    if host.get(&filter.object) == Some(&filter) {
+        return Ok(());
+    }
+    host.start(&filter)
+}
+
+/// Spin button for the trim of the real sink behind the default output.
+pub fn trim_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let panel = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let label = gtk::Label::new(Some(&tr("Trim before effects (dB)")));
+    label.set_tooltip_text(Some(
+        "Gain in front of the preset, to make up for its boosts without touching the volume",
+    ));
+    panel.append(&label);
+    let (lowest, highest) = TRIM_RANGE_DB;
+    let trim = gtk::SpinButton::with_range(lowest.into(), highest.into(), 1.);
+    trim.set_hexpand(true);
+    panel.append(&trim);
+    let apply = gtk::Button::with_label(&tr("Apply"));
+    panel.append(&apply);
+
+    let master = create_memo(cx, move |_| {
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    create_effect(cx, {
+        let trim = trim.clone();
+        let apply = apply.clone();
+        move |_| {
+            let master = master.get();
+            apply.set_sensitive(master.is_some());
+            let current = master.and_then(|master| {
+                state.device_settings.with(|store| {
+                    store
+                        .devices
+                        .get(&master)
+                        .and_then(|settings| settings.trim_db)
+                })
+            });
+            trim.set_value(current.unwrap_or_default().into());
+        }
+    });
+    apply.connect_clicked(move |_| {
+        if let Some(device) = master.get_untracked() {
+            let trim_db = trim.value() as i32;
+            dispatcher.dispatch(Action::SetTrim {
+                device,
+                trim_db: (trim_db != 0).then_some(trim_db),
+            });
+        }
+    });
+    panel
+}
+
+/// Drop down with the imported presets for the real sink behind the default output,
+/// and a button importing more.
+pub fn preset_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let panel = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let label = gtk::Label::new(Some(&tr("Effects preset")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    panel.append(&label);
+    let choice = gtk::DropDown::from_strings(&[]);
+    panel.append(&choice);
+    let per_port = gtk::CheckButton::new();
+    per_port.set_tooltip_text(Some(
+        "Switches presets along with the port, like headphones and speakers of one card",
+    ));
+    panel.append(&per_port);
+    let import = gtk::Button::with_label(&tr("Import EasyEffects preset…"));
+    panel.append(&import);
+
+    let master = create_memo(cx, move |_| {
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    let switched = create_memo(cx, move |_| {
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| (sink.name.clone(), sink.active_port.clone()))
+        })
+    });
+    // name and description of the port the real sink plays through
+    let port = create_memo(cx, move |_| {
+        switched.track();
+        let master = master.get()?;
+        let sink = Device::list(DeviceKind::Sink)
+            .map_err(|error| warn!(?error, "looking up the port for presets"))
+            .ok()?
+            .into_iter()
+            .find(|sink| sink.name == master)?;
+        let name = sink.active_port?;
+        let description = sink
+            .ports
+            .into_iter()
+            .find(|port| port.name == name)
+            .map_or_else(|| name.clone(), |port| port.description);
+        Some((name, description))
+    });
+    let current = create_memo(cx, move |_| {
+        let port = port.get().map(|(name, _)| name);
+        master.get().map(|master| {
+            state.device_settings.with(|store| {
+                store
+                    .devices
+                    .get(&master)
+                    .and_then(|settings| settings.preset_for(port.as_deref()).map(str::to_owned))
+            })
+        })
+    });
+    let port_has_own = create_memo(cx, move |_| {
+        let (Some(master), Some((port, _))) = (master.get(), port.get()) else {
+            return false;
+        };
+        state.device_settings.with(|store| {
+            store
+                .devices
+                .get(&master)
+                .is_some_and(|settings| settings.port_presets.contains_key(&port))
+        })
+    });
+    // the names behind the drop down entries, after "None"
+    let names = Rc::new(RefCell::new(Vec::<String>::new()));
+    let syncing = Rc::new(Cell::new(false));
+    choice.connect_selected_notify({
+        let names = names.clone();
+        let syncing = syncing.clone();
+        let dispatcher = dispatcher.clone();
+        let per_port = per_port.clone();
+        move |choice| {
+            if syncing.get() {
+                return;
+            }
+            let Some(device) = master.get_untracked() else {
+                return;
+            };
+            let preset = match choice.selected() {
+                0 | gtk::INVALID_LIST_POSITION => None,
+                selected => names.borrow().get(selected as usize - 1).cloned(),
+            };
+            dispatcher.dispatch(match port.get_untracked() {
+                Some((port, _)) if per_port.is_active() => Action::SetPortPreset {
+                    device,
+                    port,
+                    // "None" for this port alone
+                    preset: Some(preset.unwrap_or_default()),
+                },
+                _ => Action::SetPreset { device, preset },
+            });
+        }
+    });
+    per_port.connect_toggled({
+        let dispatcher = dispatcher.clone();
+        move |per_port| {
+            if per_port.is_active() == port_has_own.get_untracked() {
+                return;
+            }
+            let (Some(device), Some((port, _))) = (master.get_untracked(), port.get_untracked())
+            else {
+                return;
+            };
+            // starting out with what plays now
+            let preset = per_port
+                .is_active()
+                .then(|| current.get_untracked().flatten().unwrap_or_default());
+            dispatcher.dispatch(Action::SetPortPreset {
+                device,
+                port,
+                preset,
+            });
+        }
+    });
+    create_effect(cx, {
+        let per_port = per_port.clone();
+        move |_| {
+            per_port.set_visible(port.with(Option::is_some));
+            if let Some((_, description)) = port.get() {
+                per_port.set_label(Some(&format!("Only for {description}")));
+            }
+            per_port.set_sensitive(master.with(Option::is_some));
+            per_port.set_active(port_has_own.get());
+        }
+    });
+    create_effect(cx, {
+        let choice = choice.clone();
+        move |_| {
+            let current = current.get();
+            choice.set_sensitive(current.is_some());
+            // importing picks the new preset, which also lands here
+            let listed = list();
+            let strings = std::iter::once("None")
+                .chain(listed.iter().map(String::as_str))
+                .collect::<Vec<_>>();
+            let selected = current
+                .flatten()
+                .and_then(|preset| listed.iter().position(|name| *name == preset))
+                .map_or(0, |position| position + 1);
+            syncing.set(true);
+            choice.set_model(Some(&gtk::StringList::new(&strings)));
+            choice.set_selected(selected as u32);
+            syncing.set(false);
+            names.replace(listed);
+        }
+    });
+
+    // GTK doesn't keep native dialogs alive by itself
+    let open = RefCell::new(None::<gtk::FileChooserNative>);
+    import.connect_clicked(move |button| {
+        let parent = button.root().and_downcast::<gtk::Window>();
+        let dialog = gtk::FileChooserNative::new(
+            Some("Import EasyEffects preset"),
+            parent.as_ref(),
+            gtk::FileChooserAction::Open,
+            Some("Import"),
+            Some("Cancel"),
+        );
+        let json = gtk::FileFilter::new();
+        json.set_name(Some("EasyEffects presets"));
+        json.add_suffix("json");
+        dialog.add_filter(&json);
+        if let Ok(home) = std::env::var("HOME") {
+            let presets = PathBuf::from(home).join(".config/easyeffects/output");
+            if presets.is_dir() {
+                dialog
+                    .set_current_folder(Some(&gio::File::for_path(presets)))
+                    .map_err(|error| warn!(%error, "opening the EasyEffects presets"))
+                    .ok();
+            }
+        }
+        let dispatcher = dispatcher.clone();
+        dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    dispatcher.dispatch(Action::ImportPreset(path));
+                }
+            }
+        });
+        dialog.show();
+        open.replace(Some(dialog));
+    });
+    panel
+}
@@ -0,0 +1,442 @@
+//! Session and driver priorities of devices, and when they suspend while idle. The
+//! session manager reads them when a device appears, so they are written out as
+//! WirePlumber rules and take effect once WirePlumber restarts.
+use std::{cell::Cell, collections::BTreeMap, path::PathBuf, rc::Rc};
+
+use eyre::{Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    device_settings::DeviceStore,
+    devices::{self, Device},
+    i18n::tr,
+    run_change,
+    state::AppState,
+    ui_state,
+};
+
+const WARNING: &str = "The driver sets the timing of everything linked to it: a device that \
+isn't best at keeping time, or one that sleeps, can cause crackling and dropouts in the \
+whole graph. Session priority decides which device becomes the default when several are \
+connected. Changes apply after WirePlumber restarts, which interrupts all audio.";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Priority {
+    /// `priority.session`, higher wins when picking the default device.
+    pub session: Option<u32>,
+    /// `priority.driver`, higher wins when picking what drives the graph.
+    pub driver: Option<u32>,
+}
+
+impl Priority {
+    /// What the device currently runs with, as reported by the server.
+    pub fn of(device: &Device) -> Self {
+        let read = |key: &str| {
+            device
+                .properties
+                .get(key)
+                .and_then(|value| value.parse().ok())
+        };
+        Self {
+            session: read("priority.session"),
+            driver: read("priority.driver"),
+        }
+    }
+}
+
+/// Node property WirePlumber suspends idle devices by, 0 keeping them awake.
+const SUSPEND_TIMEOUT: &str = "session.suspend-timeout-seconds";
+/// Longest timeout the panel offers, an hour.
+const MAX_SUSPEND_SECS: u32 = 3600;
+
+/// When a device is suspended after it goes idle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Suspend {
+    /// Kept awake, for DACs that pop when they wake up.
+    Never,
+    /// As soon as the session manager looks, a second after going idle.
+    Immediately,
+    /// After that many seconds.
+    After(u32),
+}
+
+impl Suspend {
+    /// The timeout as the session manager takes it.
+    pub fn timeout_seconds(self) -> u32 {
+        match self {
+            Self::Never => 0,
+            Self::Immediately => 1,
+            Self::After(seconds) => seconds.max(1),
+        }
+    }
+
+    pub fn from_timeout_seconds(seconds: u32) -> Self {
+        match seconds {
+            0 => Self::Never,
+            1 => Self::Immediately,
+            seconds => Self::After(seconds),
+        }
+    }
+
+    /// What the device currently runs with, `None` for the session manager's default.
+    pub fn of(device: &Device) -> Option<Self> {
+        device
+            .properties
+            .get(SUSPEND_TIMEOUT)
+            .and_then(|value| value.parse().ok())
+            .map(Self::from_timeout_seconds)
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Self::Never => "never suspends".to_owned(),
+            Self::Immediately => "suspends as soon as it's idle".to_owned(),
+            Self::After(seconds) => format!("suspends after {seconds}s idle"),
+        }
+    }
+}
+
+/// Session priority of excluded devices, below anything WirePlumber hands out itself.
+const EXCLUDED_PRIORITY: u32 = 1;
+
+/// Devices that should never become the default by themselves, only when picked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exclusion {
+    pub description: String,
+    pub enabled: bool,
+    /// Node properties, any one of which excludes a node. Values starting with `~` are
+    /// patterns, as in every WirePlumber rule.
+    pub matches: Vec<BTreeMap<String, String>>,
+}
+
+/// The exclusions, shipped with some for the usual offenders.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Exclusions(pub Vec<Exclusion>);
+
+impl Default for Exclusions {
+    fn default() -> Self {
+        let matching = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, value)| BTreeMap::from([((*key).to_owned(), (*value).to_owned())]))
+                .collect()
+        };
+        Self(vec![
+            Exclusion {
+                description: "Webcam microphones".to_owned(),
+                enabled: true,
+                matches: matching(&[
+                    ("device.form-factor", "webcam"),
+                    ("node.name", "~alsa_input.usb-*[Ww]ebcam*"),
+                    ("node.name", "~alsa_input.usb-*[Cc]amera*"),
+                ]),
+            },
+            Exclusion {
+                description: "HDMI and DisplayPort inputs".to_owned(),
+                enabled: true,
+                matches: matching(&[("node.name", "~alsa_input.*hdmi*")]),
+            },
+        ])
+    }
+}
+
+pub fn rules_path() -> PathBuf {
+    glib::user_config_dir()
+        .join("wireplumber")
+        .join("wireplumber.conf.d")
+        .join(format!("51-{}-priorities.conf", clap::crate_name!()))
+}
+
+fn rule(matches: &str, props: &str) -> String {
+    format!("  {{\n    matches = [ {matches} ]\n    actions = {{ update-props = {{ {props} }} }}\n  }}\n")
+}
+
+fn rules(store: &DeviceStore) -> String {
+    // first, so priorities picked for single devices still win over them
+    let exclusions = store
+        .exclusions
+        .0
+        .iter()
+        .filter(|exclusion| exclusion.enabled && !exclusion.matches.is_empty())
+        .map(|exclusion| {
+            let matches = exclusion
+                .matches
+                .iter()
+                .map(|properties| {
+                    let properties = properties
+                        .iter()
+                        .map(|(key, value)| format!("{key} = \"{value}\""))
+                        .collect::<Vec<_>>();
+                    format!("{{ {} }}", properties.join(" "))
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "  # {}\n{}",
+                exclusion.description,
+                rule(
+                    &matches.join(" "),
+                    &format!("priority.session = {EXCLUDED_PRIORITY}")
+                )
+            )
+        })
+        .collect::<String>();
+    let rules = store
+        .devices
+        .iter()
+        .filter_map(|(name, settings)| {
+            let priority = settings.priority.unwrap_or_default();
+            let props = [
+                priority
+                    .session
+                    .map(|session| format!("priority.session = {session}")),
+                priority
+                    .driver
+                    .map(|driver| format!("priority.driver = {driver}")),
+                settings
+                    .suspend
+                    .map(|suspend| format!("{SUSPEND_TIMEOUT} = {}", suspend.timeout_seconds())),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+            (!props.is_empty())
+                .then(|| rule(&format!("{{ node.name = \"{name}\" }}"), &props.join(" ")))
+        })
+        .collect::<String>();
+    format!(
+        "# Written by {}, changes here are overwritten.\nmonitor.alsa.rules = [\n{exclusions}{rules}]\n",
+        clap::crate_name!()
+    )
+}
+
+/// Writes the priorities of `store` as WirePlumber rules.
+#[instrument(skip(store), ret, err)]
+pub fn write_rules(store: &DeviceStore) -> Result<PathBuf> {
+    let path = rules_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .wrap_err_with(|| format!("creating {}", parent.display()))?;
+    }
+    std::fs::write(&path, rules(store)).wrap_err_with(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+/// Writes the rules when they changed, say with the exclusions pipeweld ships.
+/// WirePlumber picks them up the next time it starts.
+pub fn sync_rules(store: &DeviceStore) {
+    if std::fs::read_to_string(rules_path()).ok() == Some(rules(store)) {
+        return;
+    }
+    if let Err(error) = write_rules(store) {
+        warn!(?error, "updating WirePlumber rules");
+    }
+}
+
+#[instrument(ret, err)]
+pub fn restart_session_manager() -> Result<()> {
+    run_change(std::process::Command::new("systemctl").args(["--user", "restart", "wireplumber"]))
+        .wrap_err("restarting wireplumber")
+        .map(drop)
+}
+
+/// Expander for picking a device and its priorities, with the consequences spelled out.
+pub fn priority_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let warning = gtk::Label::new(Some(WARNING));
+    warning.set_wrap(true);
+    warning.set_max_width_chars(60);
+    warning.add_css_class("warning");
+    layout.append(&warning);
+
+    let picker = gtk::DropDown::from_strings(&[]);
+    let devices = devices::keep_listed(
+        cx,
+        &picker,
+        move || [state.sinks.get(), state.sources.get()].concat(),
+        |device| (device.name.clone(), device.description.clone()),
+        move |devices| {
+            ui_state::last_picked(state, devices.iter().map(|device| device.name.as_str()))
+                .map(|position| position as usize)
+        },
+    );
+    layout.append(&picker);
+    let spin = |label: &str| {
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        let label = gtk::Label::new(Some(label));
+        label.set_hexpand(true);
+        label.set_halign(gtk::Align::Start);
+        row.append(&label);
+        let spin = gtk::SpinButton::with_range(0., 10_000., 10.);
+        row.append(&spin);
+        layout.append(&row);
+        spin
+    };
+    let session = spin(&tr("Session priority"));
+    let driver = spin(&tr("Driver priority"));
+    let suspend_row = gtk::Box::new(Orientation::Horizontal, 6);
+    let suspend_label = gtk::Label::new(Some(&tr("Suspend while idle")));
+    suspend_label.set_hexpand(true);
+    suspend_label.set_halign(gtk::Align::Start);
+    suspend_row.append(&suspend_label);
+    // in the order of `suspend_choice`
+    let choices = [
+        tr("By default"),
+        tr("Never"),
+        tr("Immediately"),
+        tr("After (s)"),
+    ];
+    let suspend = gtk::DropDown::from_strings(&choices.each_ref().map(String::as_str));
+    suspend.set_tooltip_text(Some(&tr(
+        "Never for DACs that pop when they wake up, immediately to save power",
+    )));
+    suspend_row.append(&suspend);
+    let suspend_after = gtk::SpinButton::with_range(2., f64::from(MAX_SUSPEND_SECS), 1.);
+    suspend_after.set_value(5.);
+    suspend_row.append(&suspend_after);
+    layout.append(&suspend_row);
+    let suspend_hint = gtk::Label::new(None);
+    suspend_hint.set_halign(gtk::Align::Start);
+    suspend_hint.add_css_class("dim-label");
+    layout.append(&suspend_hint);
+    let never = gtk::Label::new(Some(&tr("Never make these the default by themselves:")));
+    never.set_halign(gtk::Align::Start);
+    layout.append(&never);
+    let exclusions = state
+        .device_settings
+        .with_untracked(|store| store.exclusions.0.clone());
+    for exclusion in exclusions {
+        let check = gtk::CheckButton::with_label(&exclusion.description);
+        check.set_active(exclusion.enabled);
+        let dispatcher = dispatcher.clone();
+        check.connect_toggled(move |check| {
+            dispatcher.dispatch(Action::SetExclusion {
+                description: exclusion.description.clone(),
+                enabled: check.is_active(),
+            })
+        });
+        layout.append(&check);
+    }
+    let buttons = gtk::Box::new(Orientation::Horizontal, 6);
+    let apply = gtk::Button::with_label(&tr("Save"));
+    let restart = gtk::Button::with_label(&tr("Restart WirePlumber"));
+    restart.add_css_class("destructive-action");
+    buttons.append(&apply);
+    buttons.append(&restart);
+    layout.append(&buttons);
+
+    let selected = create_rw_signal(cx, picker.selected());
+    picker.connect_selected_notify(move |picker| {
+        let index = picker.selected() as usize;
+        if let Some(device) = devices.with_untracked(|devices| devices.get(index).cloned()) {
+            ui_state::set_last_picked(state, &device.name);
+        }
+        selected.set(picker.selected());
+    });
+    // set while showing the picked device, which notifies too
+    let syncing = Rc::new(Cell::new(false));
+    create_effect(cx, {
+        let session = session.clone();
+        let driver = driver.clone();
+        let suspend = suspend.clone();
+        let suspend_after = suspend_after.clone();
+        let syncing = syncing.clone();
+        move |_| {
+            let index = selected.get() as usize;
+            let Some(device) = devices.with_untracked(|devices| devices.get(index).cloned()) else {
+                return;
+            };
+            let settings = state
+                .device_settings
+                .with(|store| store.get(&device).cloned())
+                .unwrap_or_default();
+            let priority = settings.priority.unwrap_or_else(|| Priority::of(&device));
+            session.set_value(priority.session.unwrap_or_default().into());
+            driver.set_value(priority.driver.unwrap_or_default().into());
+            syncing.set(true);
+            if let Some(Suspend::After(seconds)) = settings.suspend {
+                suspend_after.set_value(f64::from(seconds));
+            }
+            suspend.set_selected(match settings.suspend {
+                None => 0,
+                Some(Suspend::Never) => 1,
+                Some(Suspend::Immediately) => 2,
+                Some(Suspend::After(_)) => 3,
+            });
+            syncing.set(false);
+            suspend_after.set_sensitive(suspend.selected() == 3);
+            suspend_hint.set_text(&match Suspend::of(&device) {
+                Some(running) => format!("Right now it {}.", running.describe()),
+                None => "Right now it suspends as the session manager sees fit.".to_owned(),
+            });
+        }
+    });
+    let suspend_choice = {
+        let (suspend, suspend_after) = (suspend.clone(), suspend_after.clone());
+        move || match suspend.selected() {
+            1 => Some(Suspend::Never),
+            2 => Some(Suspend::Immediately),
+            3 => Some(Suspend::After(suspend_after.value() as u32)),
+            _ => None,
+        }
+    };
+    let pick_suspend = {
+        let dispatcher = dispatcher.clone();
+        let suspend_after = suspend_after.clone();
+        move || {
+            let index = selected.get_untracked() as usize;
+            let Some(device) = devices.with_untracked(|devices| devices.get(index).cloned()) else {
+                return;
+            };
+            if syncing.get() {
+                return;
+            }
+            let picked = suspend_choice();
+            suspend_after.set_sensitive(matches!(picked, Some(Suspend::After(_))));
+            let saved = state
+                .device_settings
+                .with_untracked(|store| store.get(&device).and_then(|settings| settings.suspend));
+            if picked != saved {
+                dispatcher.dispatch(Action::SetSuspend {
+                    device: device.name,
+                    suspend: picked,
+                });
+            }
+        }
+    };
+    suspend.connect_selected_notify({
+        let pick_suspend = pick_suspend.clone();
+        move |_| pick_suspend()
+    });
+    suspend_after.connect_value_changed(move |_| pick_suspend());
+    apply.connect_clicked({
+        let dispatcher = dispatcher.clone();
+        move |_| {
+            let index = selected.get_untracked() as usize;
+            if let Some(device) = devices.with_untracked(|devices| devices.get(index).cloned()) {
+                dispatcher.dispatch(Action::SetPriority {
+                    device: device.name,
+                    priority: Some(Priority {
+                        session: Some(session.value() as u32),
+                        driver: Some(driver.value() as u32),
+                    }),
+                });
+            }
+        }
+    });
+    restart.connect_clicked(move |_| dispatcher.dispatch(Action::RestartSessionManager));
+
+    gtk::Expander::builder()
+        .label("Scheduling priorities")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,215 @@
+//! The graph's clock for recording sessions: forcing a small quantum, the frames
+//! processed per cycle, drops the latency, forcing a rate keeps the devices at the
+//! one the session records at. Both are runtime settings, the `settings` metadata,
+//! that last until PipeWire restarts or they're put back to automatic.
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use eyre::Result;
+use gtk::{glib, prelude::*, Orientation};
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    bit_perfect, game_mode,
+    i18n::{tr, tr_with},
+};
+
+/// Quantums offered, in frames.
+pub const QUANTUMS: [u32; 7] = [32, 64, 128, 256, 512, 1024, 2048];
+/// Rates offered, in Hz.
+pub const RATES: [u32; 6] = [44_100, 48_000, 88_200, 96_000, 176_400, 192_000];
+
+/// How often the panel reads the clock back while expanded.
+const REFRESH: Duration = Duration::from_secs(2);
+
+/// The graph's clock as the metadata has it, 0 for what's unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Clock {
+    /// What the graph runs at unless forced.
+    pub quantum: u32,
+    pub rate: u32,
+    pub force_quantum: u32,
+    pub force_rate: u32,
+}
+
+impl Clock {
+    pub fn read() -> Result<Self> {
+        Ok(Self {
+            quantum: game_mode::setting("clock.quantum")?,
+            rate: game_mode::setting("clock.rate")?,
+            force_quantum: game_mode::force_quantum()?,
+            force_rate: bit_perfect::force_rate()?,
+        })
+    }
+
+    fn active(forced: u32, default: u32) -> u32 {
+        match forced {
+            0 => default,
+            forced => forced,
+        }
+    }
+
+    pub fn active_quantum(&self) -> u32 {
+        Self::active(self.force_quantum, self.quantum)
+    }
+
+    pub fn active_rate(&self) -> u32 {
+        Self::active(self.force_rate, self.rate)
+    }
+
+    /// Latency of one cycle, if both are known.
+    pub fn latency_ms(&self) -> Option<f64> {
+        match (self.active_quantum(), self.active_rate()) {
+            (0, _) | (_, 0) => None,
+            (quantum, rate) => Some(f64::from(quantum) * 1000. / f64::from(rate)),
+        }
+    }
+
+    /// Like "128 frames at 48 kHz, 2.7 ms (forced)".
+    pub fn describe(&self) -> String {
+        let quantum = match self.active_quantum() {
+            0 => tr("Unknown quantum"),
+            quantum => frames_label(quantum),
+        };
+        let rate = match self.active_rate() {
+            0 => tr("an unknown rate"),
+            rate => rate_label(rate),
+        };
+        let mut described = tr_with(
+            "{quantum} at {rate}",
+            &[("quantum", &quantum), ("rate", &rate)],
+        );
+        if let Some(latency) = self.latency_ms() {
+            described = tr_with(
+                "{setup}, {latency} ms",
+                &[("setup", &described), ("latency", &format!("{latency:.1}"))],
+            );
+        }
+        match (self.force_quantum, self.force_rate) {
+            (0, 0) => described,
+            _ => tr_with("{setup} (forced)", &[("setup", &described)]),
+        }
+    }
+}
+
+fn frames_label(quantum: u32) -> String {
+    tr_with("{quantum} frames", &[("quantum", &quantum.to_string())])
+}
+
+fn rate_label(rate: u32) -> String {
+    tr_with(
+        "{rate} kHz",
+        &[("rate", &(f64::from(rate) / 1000.).to_string())],
+    )
+}
+
+/// "Automatic" followed by `values` labelled by `label`.
+fn picker(values: &[u32], label: impl Fn(u32) -> String) -> gtk::DropDown {
+    let labels = std::iter::once(tr("Automatic"))
+        .chain(values.iter().copied().map(label))
+        .collect::<Vec<_>>();
+    gtk::DropDown::from_strings(&labels.iter().map(String::as_str).collect::<Vec<_>>())
+}
+
+/// Selects `current` of `values`, leaving values forced from elsewhere that
+/// aren't offered as they are.
+fn select(picker: &gtk::DropDown, values: &[u32], current: u32) {
+    let position = match current {
+        0 => Some(0),
+        current => values
+            .iter()
+            .position(|value| *value == current)
+            .map(|position| position as u32 + 1),
+    };
+    if let Some(position) = position.filter(|position| picker.selected() != *position) {
+        picker.set_selected(position);
+    }
+}
+
+/// What `picker` has selected, 0 for automatic.
+fn selected(picker: &gtk::DropDown, values: &[u32]) -> u32 {
+    match picker.selected() {
+        0 => 0,
+        position => values.get(position as usize - 1).copied().unwrap_or(0),
+    }
+}
+
+/// Expander with the active quantum and rate, pickers forcing them and a button
+/// putting both back to automatic.
+pub fn pro_audio_panel(dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let active = gtk::Label::new(None);
+    active.set_halign(gtk::Align::Start);
+    active.add_css_class("heading");
+    layout.append(&active);
+    let quantum = picker(&QUANTUMS, frames_label);
+    let rate = picker(&RATES, rate_label);
+    for (label, picker) in [(tr("Buffer size"), &quantum), (tr("Sample rate"), &rate)] {
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        let label = gtk::Label::new(Some(&label));
+        label.set_hexpand(true);
+        label.set_halign(gtk::Align::Start);
+        row.append(&label);
+        row.append(picker);
+        layout.append(&row);
+    }
+    // reading back selects what's forced already, which isn't worth setting again
+    let reading = Rc::new(Cell::new(false));
+    quantum.connect_selected_notify({
+        let dispatcher = dispatcher.clone();
+        let reading = reading.clone();
+        move |quantum| {
+            if !reading.get() {
+                dispatcher.dispatch(Action::ForceQuantum(selected(quantum, &QUANTUMS)));
+            }
+        }
+    });
+    rate.connect_selected_notify({
+        let dispatcher = dispatcher.clone();
+        let reading = reading.clone();
+        move |rate| {
+            if !reading.get() {
+                dispatcher.dispatch(Action::ForceRate(selected(rate, &RATES)));
+            }
+        }
+    });
+    let restore = gtk::Button::with_label(&tr("Restore defaults"));
+    restore.set_halign(gtk::Align::Start);
+    restore.set_tooltip_text(Some(&tr("Let the graph pick its quantum and rate again")));
+    restore.connect_clicked(move |_| {
+        dispatcher.dispatch(Action::ForceQuantum(0));
+        dispatcher.dispatch(Action::ForceRate(0));
+    });
+    layout.append(&restore);
+
+    let refresh = move || {
+        let clock = match Clock::read() {
+            Ok(clock) => clock,
+            Err(error) => return warn!(?error, "reading the clock"),
+        };
+        active.set_text(&clock.describe());
+        reading.set(true);
+        select(&quantum, &QUANTUMS, clock.force_quantum);
+        select(&rate, &RATES, clock.force_rate);
+        reading.set(false);
+    };
+    refresh();
+    let expander = gtk::Expander::builder()
+        .label("Pro audio")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let weak = expander.downgrade();
+    glib::timeout_add_local(REFRESH, move || {
+        let Some(expander) = weak.upgrade() else {
+            return glib::Continue(false);
+        };
+        if expander.is_expanded() {
+            refresh();
+        }
+        glib::Continue(true)
+    });
+    expander
+}
@@ -0,0 +1,291 @@
+//! Per-node processing load from the PipeWire profiler, as sampled by `pw-top`:
+//! the latency each driver runs at, the share of each cycle nodes take and the
+//! xruns they ran into, and the load of the graph as a whole. While the panel is
+//! open `pw-top` keeps running and every table it prints shows, about once a
+//! second, for telling what crackles while it crackles.
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use tracing::{instrument, warn};
+
+use crate::{
+    checked_output,
+    i18n::{tr, tr_with},
+};
+
+/// Share of the quantum above which a node is highlighted.
+const HEAVY: f64 = 0.3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeLoad {
+    pub id: u32,
+    pub name: String,
+    /// Whether the node is driven by another one rather than driving the graph.
+    pub follower: bool,
+    /// Frames per cycle and rate, 0 for nodes that don't run at their own.
+    pub quantum: u32,
+    pub rate: u32,
+    /// Time waiting to be scheduled as a share of the quantum, the `W/Q` column.
+    pub wait: f64,
+    /// Processing time as a share of the quantum, the `B/Q` column.
+    pub busy: f64,
+    pub errors: u32,
+}
+
+impl NodeLoad {
+    /// Takes two samples with `pw-top` and reads the second, the first has no timings yet.
+    pub fn sample() -> Result<Vec<Self>> {
+        let output = checked_output(
+            std::process::Command::new("pw-top").args(["--batch-mode", "--iterations=2"]),
+        )?;
+        let output = String::from_utf8_lossy(&output);
+        let header = output
+            .rfind("NAME")
+            .ok_or_else(|| eyre!("no header in pw-top output"))?;
+        let last = output[..header]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1);
+        Self::parse(&output[last..])
+    }
+
+    /// Parses one `pw-top` table. Format and name hold spaces, so the name is cut
+    /// out at the column its header starts in.
+    fn parse(table: &str) -> Result<Vec<Self>> {
+        let mut lines = table.lines();
+        let header = lines.next().ok_or_else(|| eyre!("empty pw-top table"))?;
+        let name_column = header
+            .find("NAME")
+            .ok_or_else(|| eyre!("no NAME column in {header:?}"))?;
+        Ok(lines
+            .filter_map(|line| {
+                let columns = line.split_whitespace().collect::<Vec<_>>();
+                let name = line.get(name_column..)?.trim();
+                let (follower, name) = match name.strip_prefix('+') {
+                    Some(name) => (true, name.trim()),
+                    None => (false, name),
+                };
+                Some(Self {
+                    id: columns.get(1)?.parse().ok()?,
+                    name: name.to_owned(),
+                    follower,
+                    quantum: columns.get(2)?.parse().unwrap_or_default(),
+                    rate: columns.get(3)?.parse().unwrap_or_default(),
+                    wait: columns.get(6)?.parse().unwrap_or_default(),
+                    busy: columns.get(7)?.parse().unwrap_or_default(),
+                    errors: columns.get(8)?.parse().unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    /// Length of the node's cycle, for nodes running at their own quantum.
+    pub fn latency_ms(&self) -> Option<f64> {
+        match (self.quantum, self.rate) {
+            (0, _) | (_, 0) => None,
+            (quantum, rate) => Some(f64::from(quantum) * 1000. / f64::from(rate)),
+        }
+    }
+}
+
+/// Share of the cycle the busiest driver and its followers take together, the load
+/// of the graph. Followers come right after their driver in `pw-top`'s tables.
+pub fn graph_load(loads: &[NodeLoad]) -> f64 {
+    let mut busiest = 0f64;
+    let mut current = 0f64;
+    for load in loads {
+        if !load.follower {
+            busiest = busiest.max(current);
+            current = 0.;
+        }
+        current += load.busy;
+    }
+    busiest.max(current)
+}
+
+/// A running `pw-top`, stopped when dropped.
+pub struct Sampler {
+    child: Child,
+}
+
+impl Sampler {
+    /// Hands every table `pw-top` prints but the first, which has no timings yet, to
+    /// `on_sample` on the main loop.
+    #[instrument(skip(on_sample), err)]
+    pub fn start(mut on_sample: impl FnMut(Vec<NodeLoad>) + 'static) -> Result<Self> {
+        let mut child = Command::new("pw-top")
+            .arg("--batch-mode")
+            .stdout(Stdio::piped())
+            .spawn()
+            .wrap_err("starting pw-top")?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| eyre!("pw-top has no stdout"))?;
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        std::thread::spawn(move || {
+            let mut table = String::new();
+            let mut tables = 0usize;
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                // every table starts with its header
+                if line.contains("NAME") && !table.is_empty() {
+                    tables += 1;
+                    if tables > 1 {
+                        match NodeLoad::parse(&table) {
+                            Ok(loads) => {
+                                if sender.send(loads).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(error) => warn!(?error, "parsing pw-top"),
+                        }
+                    }
+                    table.clear();
+                }
+                table.push_str(&line);
+                table.push('\n');
+            }
+        });
+        receiver.attach(None, move |loads| {
+            on_sample(loads);
+            glib::Continue(true)
+        });
+        Ok(Self { child })
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        if let Err(error) = self.child.kill().and_then(|_| self.child.wait()) {
+            warn!(?error, "stopping pw-top");
+        }
+    }
+}
+
+/// `new` is how many xruns the node ran into since the last sample.
+fn row(load: &NodeLoad, new: u32) -> gtk::Box {
+    let row = gtk::Box::new(Orientation::Horizontal, 12);
+    let mut name = load.name.clone();
+    if name.starts_with(&format!("{}.", clap::crate_name!())) {
+        name.push_str(" (pipeweld filter)");
+    }
+    let label = gtk::Label::new(Some(&match load.follower {
+        true => format!("  {name}"),
+        false => name,
+    }));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    row.append(&label);
+    if let Some(latency) = load.latency_ms() {
+        let latency = gtk::Label::new(Some(&format!("{latency:.1} ms")));
+        latency.set_tooltip_text(Some(&tr_with(
+            "{quantum} frames at {rate} Hz",
+            &[
+                ("quantum", &load.quantum.to_string()),
+                ("rate", &load.rate.to_string()),
+            ],
+        )));
+        latency.add_css_class("dim-label");
+        row.append(&latency);
+    }
+    let busy = gtk::Label::new(Some(&format!("{:.1}%", load.busy * 100.)));
+    busy.set_tooltip_text(Some(&tr_with(
+        "Waits for {wait}% of the cycle, processes for {busy}%",
+        &[
+            ("wait", &format!("{:.1}", load.wait * 100.)),
+            ("busy", &format!("{:.1}", load.busy * 100.)),
+        ],
+    )));
+    if load.busy >= HEAVY {
+        busy.add_css_class("error");
+    }
+    row.append(&busy);
+    if load.errors > 0 {
+        let errors = gtk::Label::new(Some(&tr_with(
+            "{xruns} xruns",
+            &[("xruns", &load.errors.to_string())],
+        )));
+        // red while they keep coming
+        errors.add_css_class(match new {
+            0 => "warning",
+            _ => "error",
+        });
+        row.append(&errors);
+    }
+    row
+}
+
+/// Expander with the graph's load and xruns, and a row per node with its latency,
+/// the share of each cycle it takes and its xruns, live while open.
+pub fn load_panel() -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let summary = gtk::Label::new(Some(&tr("Sampling…")));
+    summary.set_halign(gtk::Align::Start);
+    summary.add_css_class("heading");
+    layout.append(&summary);
+    let rows = gtk::Box::new(Orientation::Vertical, 2);
+    layout.append(&rows);
+    let expander = gtk::Expander::builder()
+        .label("DSP load")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let sampler = std::cell::RefCell::new(None::<Sampler>);
+    expander.connect_expanded_notify(move |expander| {
+        if !expander.is_expanded() {
+            sampler.replace(None);
+            return;
+        }
+        summary.set_text(&tr("Sampling…"));
+        // errors of each node when last sampled, and when opened
+        let mut last = HashMap::<u32, u32>::new();
+        let mut opened = None::<HashMap<u32, u32>>;
+        let (heading, rows) = (summary.clone(), rows.clone());
+        let started = Sampler::start(move |loads| {
+            let errors = loads
+                .iter()
+                .map(|load| (load.id, load.errors))
+                .collect::<HashMap<_, _>>();
+            let opened = opened.get_or_insert_with(|| errors.clone());
+            let since = |before: &HashMap<u32, u32>, load: &NodeLoad| {
+                load.errors
+                    .saturating_sub(before.get(&load.id).copied().unwrap_or(load.errors))
+            };
+            let xruns = loads.iter().map(|load| since(opened, load)).sum::<u32>();
+            heading.set_text(&tr_with(
+                "Graph load {load}%, {xruns} xruns since opening",
+                &[
+                    ("load", &format!("{:.1}", graph_load(&loads) * 100.)),
+                    ("xruns", &xruns.to_string()),
+                ],
+            ));
+            while let Some(row) = rows.first_child() {
+                rows.remove(&row);
+            }
+            for load in &loads {
+                rows.append(&row(load, since(&last, load)));
+            }
+            last = errors;
+        });
+        match started {
+            Ok(started) => {
+                sampler.replace(Some(started));
+            }
+            Err(error) => {
+                warn!(?error, "sampling node load");
+                summary.set_text(&tr("pw-top couldn't be started"));
+            }
+        }
+    });
+    expander
+}
@@ -0,0 +1,362 @@
+//! The window's panels, each a feature area building its own widget, in the order
+//! they show: bars on top, the output and input columns side by side under them,
+//! then everything else. Any of them can be left out in `config.toml`, by the name
+//! it's styled by, see [`crate::theme`]:
+//!
+//! ```toml
+//! [panels]
+//! hidden = ["patchbay", "jack-transport", "dsp-load"]
+//! ```
+//!
+//! A panel may also have work to do in the background, started with the
+//! application whether or not the window is open, and not at all while it's hidden.
+use gtk::{prelude::*, Application, Orientation};
+use leptos::*;
+use tracing::warn;
+
+use crate::{
+    actions::Dispatcher, auto_pause, av_sync, bit_perfect, bluetooth, captures, cards, compat,
+    config, delay, devices, diagnostics, easyeffects, equalizer::EqualizerPanel, errors, export,
+    gain_staging, grace, groups, headset, health, hold, http_stream, i18n::tr, input_routing, jack,
+    levels, loopback, media_players, midi, osd, patchbay::PatchbayPanel, power, presets,
+    priorities, pro_audio, profiler, routing, rules, shortcuts, smart_defaults, speaker_test,
+    state::AppState, switch_on_connect, talkback, theme, transport, troubleshoot, upmix,
+    virtual_devices, volume_panels, workspaces, zones,
+};
+
+/// Where in the window a panel goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// Across the top, for what needs attention.
+    Bar,
+    /// Side by side with the other columns.
+    Column,
+    /// One under another, below the columns.
+    Section,
+}
+
+/// What panels are built with.
+#[derive(Clone)]
+pub struct Context {
+    pub cx: Scope,
+    pub app: Application,
+    pub state: AppState,
+    pub dispatcher: Dispatcher,
+}
+
+pub trait Panel {
+    /// Its label, in English, which it's named after for styling and hiding.
+    fn title(&self) -> &'static str;
+
+    fn slot(&self) -> Slot {
+        Slot::Section
+    }
+
+    fn build(&self, context: &Context) -> gtk::Widget;
+
+    /// Starts its work in the background, once, when the application starts.
+    fn spawn(&self, _context: &Context) {}
+}
+
+/// A panel made of functions, for those which are a single one.
+pub struct Simple {
+    title: &'static str,
+    slot: Slot,
+    build: fn(&Context) -> gtk::Widget,
+    spawn: Option<fn(&Context)>,
+}
+
+impl Simple {
+    pub const fn section(title: &'static str, build: fn(&Context) -> gtk::Widget) -> Self {
+        Self {
+            title,
+            slot: Slot::Section,
+            build,
+            spawn: None,
+        }
+    }
+
+    pub const fn bar(title: &'static str, build: fn(&Context) -> gtk::Widget) -> Self {
+        Self {
+            title,
+            slot: Slot::Bar,
+            build,
+            spawn: None,
+        }
+    }
+
+    pub const fn with_task(self, spawn: fn(&Context)) -> Self {
+        Self {
+            spawn: Some(spawn),
+            ..self
+        }
+    }
+}
+
+impl Panel for Simple {
+    fn title(&self) -> &'static str {
+        self.title
+    }
+
+    fn slot(&self) -> Slot {
+        self.slot
+    }
+
+    fn build(&self, context: &Context) -> gtk::Widget {
+        (self.build)(context)
+    }
+
+    fn spawn(&self, context: &Context) {
+        if let Some(spawn) = self.spawn {
+            spawn(context);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Registry {
+    panels: Vec<Box<dyn Panel>>,
+}
+
+impl Registry {
+    /// Adds `panel` after the others of its slot.
+    pub fn register(&mut self, panel: impl Panel + 'static) -> &mut Self {
+        self.panels.push(Box::new(panel));
+        self
+    }
+
+    /// Those not hidden in `config.toml`, warning about the hidden ones there's no
+    /// panel by.
+    fn shown(&self) -> Vec<&dyn Panel> {
+        let hidden = &config::get().panels.hidden;
+        let names = self
+            .panels
+            .iter()
+            .map(|panel| theme::slug(panel.title()))
+            .collect::<Vec<_>>();
+        for name in hidden.iter().filter(|name| !names.contains(name)) {
+            warn!(name, "no panel to hide by that name");
+        }
+        self.panels
+            .iter()
+            .zip(names)
+            .filter(|(_, name)| !hidden.contains(name))
+            .map(|(panel, _)| panel.as_ref())
+            .collect()
+    }
+
+    /// Starts the background work of the panels shown.
+    pub fn spawn(&self, context: &Context) {
+        for panel in self.shown() {
+            panel.spawn(context);
+        }
+    }
+
+    /// The window's contents, the panels shown in their slots.
+    pub fn build(&self, context: &Context) -> gtk::Box {
+        let shown = self.shown();
+        let layout = gtk::Box::new(Orientation::Vertical, 0);
+        let columns = gtk::Box::new(Orientation::Horizontal, 0);
+        columns.set_homogeneous(true);
+        for slot in [Slot::Bar, Slot::Column, Slot::Section] {
+            if slot == Slot::Column {
+                layout.append(&columns);
+            }
+            for panel in shown.iter().filter(|panel| panel.slot() == slot) {
+                match slot {
+                    Slot::Column => columns.append(&panel.build(context)),
+                    Slot::Bar | Slot::Section => layout.append(&panel.build(context)),
+                }
+            }
+        }
+        layout
+    }
+}
+
+fn delay_panel(context: &Context) -> gtk::Widget {
+    let Context {
+        cx,
+        ref app,
+        state,
+        ref dispatcher,
+    } = *context;
+    let delay = delay::delay_panel(cx, state, dispatcher.clone());
+    let open = gtk::Button::with_label(&tr("Test A/V sync…"));
+    open.connect_clicked({
+        let app = app.clone();
+        let dispatcher = dispatcher.clone();
+        move |_| av_sync::open(cx, &app, state, dispatcher.clone())
+    });
+    delay.append(&open);
+    delay.upcast()
+}
+
+fn trim_panel(context: &Context) -> gtk::Widget {
+    let Context {
+        cx,
+        ref app,
+        state,
+        ref dispatcher,
+    } = *context;
+    let trim = presets::trim_panel(cx, state, dispatcher.clone());
+    let open = gtk::Button::with_label(&tr("Gain staging…"));
+    open.connect_clicked({
+        let app = app.clone();
+        let dispatcher = dispatcher.clone();
+        move |_| gain_staging::open(cx, &app, state, dispatcher.clone())
+    });
+    trim.append(&open);
+    trim.upcast()
+}
+
+/// Every panel pipeweld has, in the order they've always shown.
+pub fn builtin() -> Registry {
+    let mut registry = Registry::default();
+    registry
+        .register(Simple::bar("Errors", |c| {
+            errors::error_bar(c.cx, c.state).upcast()
+        }))
+        .register(Simple::bar("Audio setup", |c| {
+            health::health_bar(c.cx).upcast()
+        }))
+        .register(Simple::bar("Recording indicator", |c| {
+            captures::indicator(c.cx, c.state).upcast()
+        }))
+        .register(Simple::bar("Rule loops", |c| {
+            rules::loop_warning(c.cx, c.state).upcast()
+        }))
+        .register(Simple::bar("Hold", |c| {
+            hold::hold_bar(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(
+            Simple::bar("Clipping warning", |c| {
+                levels::clipping_warning(c.cx, c.state).upcast()
+            })
+            .with_task(|c| levels::watch_clipping(c.cx, c.state)),
+        )
+        .register(Simple::bar("Output picker", |c| {
+            devices::output_picker(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::bar("Departed outputs", |c| {
+            grace::departed_list(c.cx, c.state).upcast()
+        }))
+        .register(volume_panels::Outputs)
+        .register(volume_panels::Inputs)
+        .register(volume_panels::Applications)
+        .register(Simple::section("Cards", |c| {
+            cards::card_panel(c.cx, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Bluetooth", |c| {
+            bluetooth::bluetooth_panel(c.cx, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("EasyEffects", |c| {
+            easyeffects::route_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Delay", delay_panel))
+        .register(Simple::section("Advanced", |c| {
+            upmix::upmix_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Bit-perfect playback", |c| {
+            bit_perfect::bit_perfect_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Pro audio", |c| {
+            pro_audio::pro_audio_panel(c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Effects preset", |c| {
+            presets::preset_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(EqualizerPanel)
+        .register(Simple::section("Trim", trim_panel))
+        .register(Simple::section("Mirrors", |c| {
+            loopback::mirror_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Volume groups", |c| {
+            groups::group_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Zones", |c| {
+            zones::zone_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Talkback", |c| {
+            talkback::talkback_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Virtual devices", |c| {
+            virtual_devices::virtual_devices_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Input routing", |c| {
+            input_routing::input_routing_panel(c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Routing presets", |c| {
+            routing::routing_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Listen on another device", |c| {
+            http_stream::stream_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Levels", |c| {
+            levels::level_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Test speakers", |c| {
+            speaker_test::speaker_test_panel(c.cx, c.state).upcast()
+        }))
+        .register(PatchbayPanel)
+        .register(Simple::section("Media players", |c| {
+            media_players::player_panel(c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("JACK transport", |c| {
+            transport::transport_panel(c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("DSP load", |_| {
+            profiler::load_panel().upcast()
+        }))
+        .register(Simple::section("Realtime scheduling", |_| {
+            diagnostics::rt_panel().upcast()
+        }))
+        .register(Simple::section("About", |c| {
+            compat::about_panel(c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("On battery", |c| {
+            power::battery_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Troubleshoot", |c| {
+            troubleshoot::troubleshoot_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Scheduling priorities", |c| {
+            priorities::priority_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Headset buttons", |c| {
+            headset::button_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("MIDI controls", |c| {
+            midi::midi_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Volume popup", |c| {
+            osd::osd_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Appearance", |c| {
+            theme::appearance_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Pause when the output goes away", |c| {
+            auto_pause::pause_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Disconnected outputs", |c| {
+            grace::grace_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Headphones plugged in", |c| {
+            jack::jack_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Switch on connect", |c| {
+            switch_on_connect::switch_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Media keys", |c| {
+            shortcuts::shortcuts_panel(c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Workspaces", |c| {
+            workspaces::workspace_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Smart defaults", |c| {
+            smart_defaults::smart_defaults_panel(c.cx, c.state, c.dispatcher.clone()).upcast()
+        }))
+        .register(Simple::section("Export", |c| {
+            export::export_button(c.dispatcher.clone()).upcast()
+        }));
+    registry
+}
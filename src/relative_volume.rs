@@ -0,0 +1,39 @@
+//! Application volumes kept as a share of the volume of the output they play on,
+//! like flat volumes but only for the applications picked. Turning the output up
+//! or down takes their streams along, and changing one of them picks its new
+//! share. Shares are percent of the output's volume, kept by `application.name`,
+//! and applied by the `relative-volume` rule. Locked applications stay locked.
+use eyre::Result;
+
+use crate::{
+    devices::{Device, DeviceKind},
+    streams::Stream,
+};
+
+/// The stream volume `share` percent of `output` percent comes to.
+pub fn target(output: u32, share: u32) -> u32 {
+    ((u64::from(output) * u64::from(share) + 50) / 100) as u32
+}
+
+/// The share of `output` that `stream` percent is, none at all for a silent output.
+pub fn share(stream: u32, output: u32) -> Option<u32> {
+    (output > 0)
+        .then(|| ((u64::from(stream) * 100 + u64::from(output) / 2) / u64::from(output)) as u32)
+}
+
+/// The share the first stream of `application` plays at right now, if it plays.
+pub fn current_share(application: &str) -> Result<Option<u32>> {
+    let sinks = Device::list(DeviceKind::Sink)?;
+    Ok(Stream::list()?.iter().find_map(|stream| {
+        if stream
+            .properties
+            .get("application.name")
+            .map(String::as_str)
+            != Some(application)
+        {
+            return None;
+        }
+        let sink = sinks.iter().find(|sink| sink.index == stream.sink)?;
+        share(stream.volume_percent(), sink.volume_percent())
+    }))
+}
@@ -0,0 +1,428 @@
+//! Session recordings for bug reports. `--record-session=FILE` writes down, a JSON
+//! line each, every event from the server, every action and every command pipeweld
+//! runs along with what it answered. `--replay-session=FILE` starts pipeweld against
+//! the recording rather than the server: events arrive as long after starting as
+//! they did, commands get the answer they got at that point of the recording and
+//! nothing is changed for real. Rules react to the events over again, so routing
+//! going wrong goes wrong the same way, and what the user did is logged when they
+//! did it.
+//!
+//! The home directory, user name and host name are replaced before anything is
+//! written, so recordings can be attached to a report as they are.
+//!
+//! ```sh
+//! pipeweld --record-session=session.jsonl
+//! pipeweld --replay-session=session.jsonl
+//! ```
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{LineWriter, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use gtk::{glib, prelude::*, Application};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    backend::{self, AudioBackend, Pactl, Wpctl},
+    devices::{Device, DeviceKind},
+    events::Event,
+    session, DiffValue,
+};
+
+const RECORD_OPTION: &str = "record-session";
+const REPLAY_OPTION: &str = "replay-session";
+
+/// What a command printed, or how it failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Answer {
+    Stdout(String),
+    Error(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "recorded", rename_all = "snake_case")]
+pub enum Recorded {
+    /// The first line, with what the session ran with.
+    Started {
+        version: String,
+        backend: String,
+    },
+    Command {
+        program: String,
+        args: Vec<String>,
+        answer: Answer,
+    },
+    Event {
+        event: Event,
+    },
+    Action {
+        action: String,
+        automated: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Milliseconds since the session started.
+    pub at: u64,
+    #[serde(flatten)]
+    pub recorded: Recorded,
+}
+
+/// A command by its program and arguments.
+type Key = (String, Vec<String>);
+
+struct Recorder {
+    started: Instant,
+    file: Mutex<LineWriter<File>>,
+}
+
+/// A recording being played back.
+struct Replay {
+    started: Instant,
+    /// Milliseconds into the recording, set by hand rather than going by `started`,
+    /// see [`script`].
+    clock: Option<AtomicU64>,
+    backend: String,
+    /// Answers to each command, in the order they were given.
+    answers: HashMap<Key, Vec<(u64, Answer)>>,
+    events: Vec<(u64, Event)>,
+    actions: Vec<(u64, String)>,
+}
+
+static RECORDER: OnceLock<Recorder> = OnceLock::new();
+static REPLAY: OnceLock<Replay> = OnceLock::new();
+
+fn millis(since: Instant) -> u64 {
+    since.elapsed().as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+impl Replay {
+    fn now(&self) -> u64 {
+        match &self.clock {
+            Some(clock) => clock.load(Ordering::Relaxed),
+            None => millis(self.started),
+        }
+    }
+}
+
+/// `text` without the home directory, user name and host name in it.
+pub fn sanitize(text: &str) -> String {
+    static PRIVATE: OnceLock<Vec<(String, &'static str)>> = OnceLock::new();
+    let private = PRIVATE.get_or_init(|| {
+        [
+            (glib::home_dir().to_string_lossy().into_owned(), "~"),
+            (glib::user_name().to_string_lossy().into_owned(), "user"),
+            (glib::host_name().to_string(), "host"),
+        ]
+        .into_iter()
+        // short names would replace bits of everything else
+        .filter(|(name, _)| name.len() > 2 && name != "/")
+        .collect()
+    });
+    private
+        .iter()
+        .fold(text.to_owned(), |text, (name, replacement)| {
+            text.replace(name, replacement)
+        })
+}
+
+/// The program and arguments of `command`, as they're recorded.
+fn key(command: &Command) -> Key {
+    (
+        sanitize(&command.get_program().to_string_lossy()),
+        command
+            .get_args()
+            .map(|arg| sanitize(&arg.to_string_lossy()))
+            .collect(),
+    )
+}
+
+fn write(recorder: &Recorder, recorded: Recorded) {
+    let entry = Entry {
+        at: millis(recorder.started),
+        recorded,
+    };
+    let written = serde_json::to_string(&entry)
+        .wrap_err("serializing a session entry")
+        .and_then(|line| {
+            let mut file = recorder.file.lock().unwrap_or_else(PoisonError::into_inner);
+            writeln!(file, "{line}").wrap_err("writing the session recording")
+        });
+    if let Err(error) = written {
+        warn!(?error, "recording the session");
+    }
+}
+
+pub fn record_command(command: &Command, output: &Result<Vec<u8>>) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+    let (program, args) = key(command);
+    let answer = match output {
+        Ok(stdout) => Answer::Stdout(sanitize(&String::from_utf8_lossy(stdout))),
+        Err(error) => Answer::Error(sanitize(&format!("{error:#}"))),
+    };
+    write(
+        recorder,
+        Recorded::Command {
+            program,
+            args,
+            answer,
+        },
+    );
+}
+
+pub fn record_event(event: Event) {
+    if let Some(recorder) = RECORDER.get() {
+        write(recorder, Recorded::Event { event });
+    }
+}
+
+pub fn record_action(action: &str, automated: bool) {
+    if let Some(recorder) = RECORDER.get() {
+        let action = sanitize(action);
+        write(recorder, Recorded::Action { action, automated });
+    }
+}
+
+pub fn replaying() -> bool {
+    REPLAY.get().is_some()
+}
+
+/// What `command` answered in the recording being replayed, `None` when there is
+/// none. The answer is the last one given before the next event is due, so it
+/// tells how things were after the events replayed so far. Commands `changing`
+/// the setup that the recording doesn't have succeed without doing anything.
+pub fn answer(command: &Command, changing: bool) -> Option<Result<Vec<u8>>> {
+    let replay = REPLAY.get()?;
+    let key = key(command);
+    let now = replay.now();
+    let horizon = replay
+        .events
+        .iter()
+        .map(|(at, _)| *at)
+        .find(|at| *at > now)
+        .unwrap_or(u64::MAX);
+    let answer = replay.answers.get(&key).and_then(|answers| {
+        answers
+            .iter()
+            .rev()
+            .find(|(at, _)| *at < horizon)
+            .or_else(|| answers.first())
+    });
+    Some(match answer {
+        Some((_, Answer::Stdout(stdout))) => Ok(stdout.clone().into_bytes()),
+        Some((_, Answer::Error(error))) => Err(eyre!("{error}")),
+        None if changing => {
+            info!(command = ?key, "replaying, not running");
+            Ok(Vec::new())
+        }
+        None => Err(eyre!("{} {:?} isn't in the recording", key.0, key.1)),
+    })
+}
+
+/// Answers from the recording being replayed, through the backend it was recorded
+/// with.
+pub struct Replayed;
+
+impl Replayed {
+    fn recorded(&self) -> &'static dyn AudioBackend {
+        match REPLAY.get().map(|replay| replay.backend.as_str()) {
+            Some("wpctl") => &Wpctl,
+            _ => &Pactl,
+        }
+    }
+}
+
+impl AudioBackend for Replayed {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn list(&self, kind: DeviceKind) -> Result<Vec<Device>> {
+        self.recorded().list(kind)
+    }
+
+    fn default_of(&self, kind: DeviceKind) -> Result<Option<Device>> {
+        self.recorded().default_of(kind)
+    }
+
+    fn set_volume(&self, kind: DeviceKind, device: &str, percent: u32) -> Result<()> {
+        self.recorded().set_volume(kind, device, percent)
+    }
+
+    fn set_mute(&self, kind: DeviceKind, device: &str, mute: bool) -> Result<()> {
+        self.recorded().set_mute(kind, device, mute)
+    }
+
+    fn toggle_mute(&self, kind: DeviceKind) -> Result<()> {
+        self.recorded().toggle_mute(kind)
+    }
+
+    fn change_volume(&self, kind: DeviceKind, diff: DiffValue, max: Option<u32>) -> Result<()> {
+        self.recorded().change_volume(kind, diff, max)
+    }
+
+    /// Sends the recorded events when they're due, ending with the recording.
+    fn follow(&self, sender: &glib::Sender<Event>) -> Result<()> {
+        let replay = REPLAY
+            .get()
+            .ok_or_else(|| eyre!("no recording to replay"))?;
+        for (at, event) in &replay.events {
+            let due = replay.started + Duration::from_millis(*at);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            if sender.send(*event).is_err() {
+                return Ok(());
+            }
+        }
+        info!("replayed the last event of the recording");
+        Ok(())
+    }
+}
+
+fn replay_of(entries: impl IntoIterator<Item = Entry>) -> Replay {
+    let mut replay = Replay {
+        started: Instant::now(),
+        clock: None,
+        backend: Pactl.name().to_owned(),
+        answers: HashMap::new(),
+        events: Vec::new(),
+        actions: Vec::new(),
+    };
+    for entry in entries {
+        match entry.recorded {
+            Recorded::Started { version, backend } => {
+                info!(version, backend, "replaying a session");
+                replay.backend = backend;
+            }
+            Recorded::Command {
+                program,
+                args,
+                answer,
+            } => replay
+                .answers
+                .entry((program, args))
+                .or_default()
+                .push((entry.at, answer)),
+            Recorded::Event { event } => replay.events.push((entry.at, event)),
+            Recorded::Action {
+                action,
+                automated: false,
+            } => replay.actions.push((entry.at, action)),
+            // pipeweld's own come about again
+            Recorded::Action { .. } => {}
+        }
+    }
+    replay
+}
+
+fn load(path: &Path) -> Result<Replay> {
+    let contents =
+        std::fs::read_to_string(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+    let entries = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .wrap_err_with(|| format!("parsing line {} of {}", number + 1, path.display()))
+        })
+        .collect::<Result<Vec<Entry>>>()?;
+    Ok(replay_of(entries))
+}
+
+/// Answers commands from `entries` from now on, as if replaying them, with the
+/// clock standing still until [`set_clock`] moves it. It's for benchmarks, which
+/// hand the events over themselves as fast as they go.
+pub fn script(entries: impl IntoIterator<Item = Entry>) -> Result<()> {
+    let mut replay = replay_of(entries);
+    replay.clock = Some(AtomicU64::new(0));
+    REPLAY
+        .set(replay)
+        .map_err(|_| eyre!("a session is replayed already"))
+}
+
+/// Moves the clock of a [`script`] to `at` milliseconds into it.
+pub fn set_clock(at: u64) {
+    if let Some(clock) = REPLAY.get().and_then(|replay| replay.clock.as_ref()) {
+        clock.store(at, Ordering::Relaxed);
+    }
+}
+
+/// Starts recording or replaying the session, as asked for among `args`. Like the
+/// server, this has to be settled before anything talks to it.
+pub fn start(args: &[String]) -> Result<()> {
+    let record = session::argument(args, RECORD_OPTION).map(PathBuf::from);
+    let replay = session::argument(args, REPLAY_OPTION).map(PathBuf::from);
+    match (record, replay) {
+        (Some(_), Some(_)) => bail!("a session can't be recorded and replayed at once"),
+        (Some(path), None) => {
+            let file =
+                File::create(&path).wrap_err_with(|| format!("creating {}", path.display()))?;
+            let recorder = RECORDER.get_or_init(|| Recorder {
+                started: Instant::now(),
+                file: Mutex::new(LineWriter::new(file)),
+            });
+            info!(path = %path.display(), "recording the session");
+            write(
+                recorder,
+                Recorded::Started {
+                    version: clap::crate_version!().to_owned(),
+                    backend: backend::current().name().to_owned(),
+                },
+            );
+        }
+        (None, Some(path)) => {
+            let loaded = load(&path)?;
+            let replay = REPLAY.get_or_init(|| loaded);
+            // what the user did doesn't come about by itself
+            let actions = replay.actions.clone();
+            std::thread::spawn(move || {
+                for (at, action) in actions {
+                    let due = replay.started + Duration::from_millis(at);
+                    if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                        std::thread::sleep(wait);
+                    }
+                    info!(action, "the user, in the recording");
+                }
+            });
+        }
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+/// Declares `--record-session` and `--replay-session`, which [`start`] reads
+/// before GTK gets to the command line.
+pub fn add_options(app: &Application) {
+    app.add_main_option(
+        RECORD_OPTION,
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Filename,
+        "Record events, actions and commands into a file for a bug report",
+        Some("FILE"),
+    );
+    app.add_main_option(
+        REPLAY_OPTION,
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Filename,
+        "Replay a recorded session instead of talking to the server",
+        Some("FILE"),
+    );
+}
@@ -0,0 +1,124 @@
+//! What pipeweld ships besides its code, compiled from `data/` into a GResource
+//! bundle by `build.rs`: its own styles, its icon and its desktop entry. With the
+//! bundle registered before the application starts, GTK finds the icons under the
+//! application's resource path by itself, so the window shows pipeweld's icon even
+//! when nothing is installed. Launchers and the tray look the icon and the entry up
+//! in the data directories instead, where `pipeweld install-desktop-files` puts
+//! them, see [`install`].
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use eyre::{Result, WrapErr};
+use gtk::{gio, glib};
+use tracing::{info, warn};
+
+use crate::{app_id, checked_output};
+
+const BUNDLE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/pipeweld.gresource"));
+
+/// Where the bundle's files are, the application's resource base path.
+fn base() -> String {
+    format!("/{}", app_id().replace('.', "/"))
+}
+
+fn lookup(name: &str) -> Result<glib::Bytes> {
+    let path = format!("{}/{name}", base());
+    gio::resources_lookup_data(&path, gio::ResourceLookupFlags::NONE)
+        .wrap_err_with(|| format!("looking up {path} among the resources"))
+}
+
+/// Makes the bundle's files available, before the application is built.
+pub fn register() -> Result<()> {
+    let resource = gio::Resource::from_data(&glib::Bytes::from_static(BUNDLE))
+        .wrap_err("loading the bundled resources")?;
+    gio::resources_register(&resource);
+    Ok(())
+}
+
+/// Loads pipeweld's own styles, under the user's, and names the windows' icon.
+pub fn apply() {
+    gtk::Window::set_default_icon_name(&app_id());
+    let Some(display) = gtk::gdk::Display::default() else {
+        return warn!("no display to style");
+    };
+    let provider = gtk::CssProvider::new();
+    provider.load_from_resource(&format!("{}/style.css", base()));
+    gtk::style_context_add_provider_for_display(
+        &display,
+        &provider,
+        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
+}
+
+/// The user's data directory, `$XDG_DATA_HOME` or `~/.local/share`.
+pub fn default_prefix() -> PathBuf {
+    glib::user_data_dir()
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .wrap_err_with(|| format!("creating {}", directory.display()))?;
+    }
+    std::fs::write(path, contents).wrap_err_with(|| format!("writing {}", path.display()))
+}
+
+/// Writes the desktop entry and the icons into `prefix`, a data directory like
+/// `~/.local/share` or `/usr/share`, returning the files written. The entry starts
+/// this very binary, as `~/.cargo/bin` is rarely on the desktop's `PATH`.
+pub fn install(prefix: &Path) -> Result<Vec<PathBuf>> {
+    let id = app_id();
+    let executable = std::env::current_exe().wrap_err("finding pipeweld's executable")?;
+    let entry = lookup(&format!("{id}.desktop"))?;
+    let entry = String::from_utf8_lossy(&entry)
+        .lines()
+        .map(|line| match line.starts_with("Exec=") {
+            true => format!("Exec={}", executable.display()),
+            false => line.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let icons = prefix.join("icons").join("hicolor");
+    let files = [
+        (
+            prefix.join("applications").join(format!("{id}.desktop")),
+            format!("{entry}\n").into_bytes(),
+        ),
+        (
+            icons
+                .join("scalable")
+                .join("apps")
+                .join(format!("{id}.svg")),
+            lookup(&format!("icons/scalable/apps/{id}.svg"))?.to_vec(),
+        ),
+        (
+            icons
+                .join("symbolic")
+                .join("apps")
+                .join(format!("{id}-symbolic.svg")),
+            lookup(&format!("icons/symbolic/apps/{id}-symbolic.svg"))?.to_vec(),
+        ),
+    ];
+    let mut written = Vec::new();
+    for (path, contents) in files {
+        write(&path, &contents)?;
+        info!(path = %path.display(), "installed");
+        written.push(path);
+    }
+    // hicolor lists its icons in a cache where there is one, which new ones would
+    // be missing from until it's updated
+    if icons.join("icon-theme.cache").exists() {
+        let updated = checked_output(
+            Command::new("gtk-update-icon-cache")
+                .arg("--ignore-theme-index")
+                .arg(&icons),
+        );
+        if let Err(error) = updated {
+            warn!(?error, "updating the icon cache in {}", icons.display());
+        }
+    }
+    Ok(written)
+}
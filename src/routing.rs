@@ -0,0 +1,327 @@
+//! Routing presets: the default devices, the devices' volumes, where each
+//! application plays and how loud, and the links patched by hand, saved under a
+//! name like "Streaming" or "Meetings" and put back with one click or from the
+//! command line:
+//!
+//! ```sh
+//! pipeweld preset save Meetings
+//! pipeweld preset apply Meetings
+//! ```
+//!
+//! They're kept in `$XDG_CONFIG_HOME/pipeweld/routing`, a file each. Streams are
+//! matched by application, as their indices don't outlive them, and devices or
+//! applications that aren't around are left out when applying.
+use std::{collections::BTreeMap, path::PathBuf};
+
+use eyre::{bail, Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    graph_file, hold,
+    i18n::tr,
+    patchbay::{self, Graph, Port},
+    schedule,
+    state::AppState,
+    streams::Stream,
+    AudioControls,
+};
+
+/// Volume and mute of a device or stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Level {
+    pub volume: u32,
+    pub mute: bool,
+}
+
+/// Where an application plays.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Route {
+    pub sink: String,
+    #[serde(flatten)]
+    pub level: Level,
+}
+
+/// A link by the names of its ends, `node:port`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NamedLink {
+    pub output: String,
+    pub input: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub default_sink: Option<String>,
+    pub default_source: Option<String>,
+    /// Outputs and inputs by name.
+    pub devices: BTreeMap<String, Level>,
+    /// Streams by `application.name`, the first of an application's streams when it
+    /// plays several.
+    pub streams: BTreeMap<String, Route>,
+    /// Links between devices and clients, apart from the ones of streams, which the
+    /// session manager makes after `streams`.
+    pub links: Vec<NamedLink>,
+}
+
+pub fn directory() -> PathBuf {
+    glib::user_config_dir()
+        .join(clap::crate_name!())
+        .join("routing")
+}
+
+fn path(name: &str) -> Result<PathBuf> {
+    if name.trim().is_empty() || name.contains('/') || name.starts_with('.') {
+        bail!("{name:?} can't be the name of a preset");
+    }
+    Ok(directory().join(format!("{name}.toml")))
+}
+
+/// Names of the saved presets, in order.
+pub fn saved() -> Result<Vec<String>> {
+    let directory = directory();
+    let entries = match std::fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error).wrap_err_with(|| format!("listing {}", directory.display()))
+        }
+    };
+    let mut names = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            match path.extension()? == "toml" {
+                true => Some(path.file_stem()?.to_string_lossy().into_owned()),
+                false => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+#[instrument(err)]
+pub fn remove(name: &str) -> Result<()> {
+    let path = path(name)?;
+    std::fs::remove_file(&path).wrap_err_with(|| format!("removing {}", path.display()))
+}
+
+pub(crate) fn port_name(graph: &Graph, port: &Port) -> Option<String> {
+    Some(format!("{}:{}", graph.node(port.node)?.name, port.name))
+}
+
+fn is_stream(graph: &Graph, port: &Port) -> bool {
+    graph
+        .node(port.node)
+        .and_then(|node| node.media_class.as_deref())
+        .is_some_and(|class| class.starts_with("Stream/"))
+}
+
+impl Snapshot {
+    /// The routing as it is now.
+    pub fn take() -> Result<Self> {
+        let sinks = Device::list(DeviceKind::Sink)?;
+        let sources = Device::list(DeviceKind::Source)?;
+        let mut snapshot = Self {
+            default_sink: Some(AudioControls::default_device(DeviceKind::Sink)?),
+            default_source: Some(AudioControls::default_device(DeviceKind::Source)?),
+            ..Self::default()
+        };
+        for device in sinks.iter().chain(&sources) {
+            snapshot.devices.insert(
+                device.name.clone(),
+                Level {
+                    volume: device.volume_percent(),
+                    mute: device.mute,
+                },
+            );
+        }
+        for stream in Stream::list()? {
+            let (Some(application), Some(sink)) = (
+                stream.properties.get("application.name"),
+                sinks.iter().find(|sink| sink.index == stream.sink),
+            ) else {
+                continue;
+            };
+            snapshot
+                .streams
+                .entry(application.clone())
+                .or_insert_with(|| Route {
+                    sink: sink.name.clone(),
+                    level: Level {
+                        volume: stream.volume_percent(),
+                        mute: stream.mute,
+                    },
+                });
+        }
+        let graph = Graph::dump()?;
+        for port in graph
+            .ports
+            .iter()
+            .filter(|port| port.direction == patchbay::Direction::Out)
+        {
+            for peer in graph.peers(port) {
+                if is_stream(&graph, port) || is_stream(&graph, peer) {
+                    continue;
+                }
+                if let (Some(output), Some(input)) =
+                    (port_name(&graph, port), port_name(&graph, peer))
+                {
+                    snapshot.links.push(NamedLink { output, input });
+                }
+            }
+        }
+        snapshot.links.sort();
+        snapshot.links.dedup();
+        Ok(snapshot)
+    }
+
+    #[instrument(skip(self), err)]
+    pub fn save(&self, name: &str) -> Result<()> {
+        let path = path(name)?;
+        std::fs::create_dir_all(directory())
+            .wrap_err_with(|| format!("creating {}", directory().display()))?;
+        let contents = toml::to_string_pretty(self).wrap_err("serializing the routing preset")?;
+        std::fs::write(&path, contents).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+
+    #[instrument(err)]
+    pub fn load(name: &str) -> Result<Self> {
+        let path = path(name)?;
+        let contents = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("reading {}", path.display()))?;
+        toml::from_str(&contents).wrap_err_with(|| format!("parsing {}", path.display()))
+    }
+
+    /// Puts the routing back as it was, adding the links that are missing and
+    /// leaving others alone.
+    #[instrument(skip(self), err)]
+    pub fn apply(&self) -> Result<()> {
+        for kind in [DeviceKind::Sink, DeviceKind::Source] {
+            let default = match kind {
+                DeviceKind::Sink => &self.default_sink,
+                DeviceKind::Source => &self.default_source,
+            };
+            for device in Device::list(kind)? {
+                if let Some(level) = self.devices.get(&device.name) {
+                    AudioControls::set_volume_percent(kind, &device.name, level.volume)?;
+                    AudioControls::set_mute(kind, &device.name, level.mute)?;
+                }
+                if default.as_ref() == Some(&device.name) {
+                    AudioControls::set_default(kind, &device.name)?;
+                }
+            }
+        }
+        let sinks = Device::list(DeviceKind::Sink)?;
+        for stream in Stream::list()? {
+            let Some(route) = stream
+                .properties
+                .get("application.name")
+                .and_then(|application| self.streams.get(application))
+            else {
+                continue;
+            };
+            match sinks.iter().find(|sink| sink.name == route.sink) {
+                Some(sink) if sink.index != stream.sink => {
+                    AudioControls::move_stream(stream.index, &route.sink)?
+                }
+                Some(_) => {}
+                None => info!("{} isn't around to play {} on", route.sink, stream.title()),
+            }
+            AudioControls::set_stream_volume(stream.index, route.level.volume)?;
+            AudioControls::set_stream_mute(stream.index, route.level.mute)?;
+        }
+        if self.links.is_empty() {
+            return Ok(());
+        }
+        let graph = Graph::dump()?;
+        let id = |name: &str| {
+            graph
+                .ports
+                .iter()
+                .find(|port| port_name(&graph, port).as_deref() == Some(name))
+        };
+        for link in &self.links {
+            let (Some(output), Some(input)) = (id(&link.output), id(&link.input)) else {
+                info!(?link, "leaving out a link to something that isn't around");
+                continue;
+            };
+            if graph.peers(output).all(|peer| peer.id != input.id) {
+                patchbay::link(output.id, input.id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Expander with a row per saved preset, to apply or remove it, and an entry saving
+/// the current routing under a new name.
+pub fn routing_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&rows);
+
+    let form = gtk::Box::new(Orientation::Horizontal, 6);
+    let name = gtk::Entry::builder()
+        .placeholder_text(tr("Preset name, like Meetings"))
+        .hexpand(true)
+        .build();
+    form.append(&name);
+    let save = gtk::Button::with_label(&tr("Save current routing"));
+    form.append(&save);
+    layout.append(&form);
+    layout.append(&graph_file::graph_buttons(dispatcher.clone()));
+    layout.append(&hold::hold_picker(dispatcher.clone()));
+    layout.append(&schedule::schedule_editor(cx, state, dispatcher.clone()));
+    save.connect_clicked({
+        let dispatcher = dispatcher.clone();
+        move |_| {
+            let preset = name.text().trim().to_owned();
+            if preset.is_empty() {
+                return warn!("a routing preset needs a name");
+            }
+            name.set_text("");
+            dispatcher.dispatch(Action::SaveRouting(preset));
+        }
+    });
+
+    create_effect(cx, move |_| {
+        let saved = state.routings.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        for preset in saved {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            let label = gtk::Label::new(Some(&preset));
+            label.set_hexpand(true);
+            label.set_halign(gtk::Align::Start);
+            row.append(&label);
+            let apply = gtk::Button::with_label(&tr("Apply"));
+            apply.connect_clicked({
+                let dispatcher = dispatcher.clone();
+                let preset = preset.clone();
+                move |_| dispatcher.dispatch(Action::ApplyRouting(preset.clone()))
+            });
+            row.append(&apply);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            remove.connect_clicked({
+                let dispatcher = dispatcher.clone();
+                move |_| dispatcher.dispatch(Action::RemoveRouting(preset.clone()))
+            });
+            row.append(&remove);
+            rows.append(&row);
+        }
+    });
+
+    gtk::Expander::builder()
+        .label("Routing presets")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,1127 @@
+//! Automatic reactions to server events. Rules only decide what should happen,
+//! the resulting actions go through the dispatcher like any user request.
+//!
+//! A rule's actions cause events of their own, which may trigger another rule whose
+//! actions trigger the first one again. Rules firing suspiciously often are taken to
+//! be caught in such a loop and disabled until the user enables them again.
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    bit_perfect,
+    cards::Card,
+    device_settings::DeviceSettings,
+    devices::{Device, DeviceKind},
+    events::{Event, EventKind, Facility},
+    extensions::watch,
+    filter_chain,
+    hold::Hold,
+    i18n::{tr, tr_with},
+    jack,
+    notifications::{Notification, Notifier},
+    relative_volume,
+    smart_defaults::{self, SmartDefault},
+    state::AppState,
+    streams::Stream,
+    timeline::Source,
+    virtual_devices, workspaces, AudioControls,
+};
+
+pub trait Rule {
+    fn name(&self) -> &'static str;
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action>;
+
+    /// Whether the rule switches devices or profiles on its own, which holding the
+    /// routing leaves out, see [`crate::hold`].
+    fn switches(&self) -> bool {
+        false
+    }
+}
+
+/// Gives freshly connected devices the volume the user picked for them.
+pub struct ConnectVolume;
+
+impl Rule for ConnectVolume {
+    fn name(&self) -> &'static str {
+        "connect-volume"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        let kind = match (event.kind, event.facility) {
+            (EventKind::New, Facility::Sink) => DeviceKind::Sink,
+            (EventKind::New, Facility::Source) => DeviceKind::Source,
+            _ => return Vec::new(),
+        };
+        let Some(device) = event.index.and_then(|index| find(state, kind, index)) else {
+            return Vec::new();
+        };
+        state.device_settings.with_untracked(|store| {
+            store
+                .get(&device)
+                .and_then(|settings| settings.connect_volume)
+                .map(|volume| vec![Action::SetVolume(kind, device.name.clone(), volume)])
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Puts back what the user had pipeweld put on top of a sink that reconnected.
+pub struct RestoreOnConnect;
+
+impl Rule for RestoreOnConnect {
+    fn name(&self) -> &'static str {
+        "restore-on-connect"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if (event.kind, event.facility) != (EventKind::New, Facility::Sink) {
+            return Vec::new();
+        }
+        let Some(sink) = event
+            .index
+            .and_then(|index| find(state, DeviceKind::Sink, index))
+        else {
+            return Vec::new();
+        };
+        state.device_settings.with_untracked(|store| {
+            store
+                .get(&sink)
+                .map(|settings| restore_actions(&sink, settings))
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// What puts the settings of `sink` back on top of it, once it's there anew.
+fn restore_actions(sink: &Device, settings: &DeviceSettings) -> Vec<Action> {
+    let mut actions = Vec::new();
+    if settings.bit_perfect {
+        actions.push(Action::SetBitPerfect {
+            device: sink.name.clone(),
+            bit_perfect: true,
+        });
+    }
+    if let Some(mix) = settings.channel_mix.filter(|_| !settings.bit_perfect) {
+        actions.push(Action::SetChannelMix {
+            device: sink.name.clone(),
+            mix: Some(mix),
+        });
+    }
+    if let Some(codecs) = &settings.passthrough {
+        actions.push(Action::SetPassthrough {
+            device: sink.name.clone(),
+            codecs: codecs.clone(),
+        });
+    }
+    if let Some(delay_ms) = settings.delay_ms.filter(|_| !settings.bit_perfect) {
+        actions.push(Action::SetDelay {
+            device: sink.name.clone(),
+            delay_ms: Some(delay_ms),
+        });
+    }
+    let preset = settings.preset_for(sink.active_port.as_deref());
+    // the equalizer, trim and limiter go along with the preset, or play without one
+    if !settings.bit_perfect
+        && (preset.is_some()
+            || settings.equalizer().is_some()
+            || settings.trim_db.is_some()
+            || settings.limit_db.is_some())
+    {
+        actions.push(Action::ApplyPreset(sink.name.clone()));
+    }
+    actions
+}
+
+/// Volume that devices come back from resetting at, 0 dB on the hardware.
+const RESET_VOLUME: u32 = 100;
+
+/// How an opted-in device was last seen, when it wasn't resetting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Seen {
+    volume: u32,
+    port: Option<String>,
+}
+
+/// Puts the volume, port and filters back on devices the user opted in, for USB DACs
+/// that reset themselves when they power-cycle without going away. The signature of
+/// a reset is the volume snapping to [`RESET_VOLUME`], or changing along with the
+/// port, in one go and without pipeweld, the volume keys or a script having asked.
+#[derive(Default)]
+pub struct ResetRecovery {
+    seen: RefCell<HashMap<String, Seen>>,
+}
+
+impl Rule for ResetRecovery {
+    fn name(&self) -> &'static str {
+        "reset-recovery"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        let kind = match (event.kind, event.facility) {
+            (EventKind::New | EventKind::Change, Facility::Sink) => DeviceKind::Sink,
+            (EventKind::New | EventKind::Change, Facility::Source) => DeviceKind::Source,
+            _ => return Vec::new(),
+        };
+        let Some(device) = event.index.and_then(|index| find(state, kind, index)) else {
+            return Vec::new();
+        };
+        let Some(settings) = state.device_settings.with_untracked(|store| {
+            store
+                .get(&device)
+                .filter(|settings| settings.recover_resets)
+                .cloned()
+        }) else {
+            return Vec::new();
+        };
+        let now = Seen {
+            volume: device.volume_percent(),
+            port: device.active_port.clone(),
+        };
+        let mut seen = self.seen.borrow_mut();
+        // reconnecting devices get their settings on connecting
+        let Some(before) = seen
+            .get(&device.name)
+            .filter(|_| event.kind == EventKind::Change)
+        else {
+            seen.insert(device.name.clone(), now);
+            return Vec::new();
+        };
+        let moved = now.port != before.port;
+        let reset = now.volume != before.volume
+            && (now.volume == RESET_VOLUME || moved)
+            && Source::guess(state) == Source::OtherApp;
+        if !reset {
+            seen.insert(device.name.clone(), now);
+            return Vec::new();
+        }
+        info!(?before, ?now, "{} reset itself", device.name);
+        let mut actions = Vec::new();
+        if let (true, Some(port)) = (moved, &before.port) {
+            actions.push(Action::SetPort {
+                kind,
+                device: device.name.clone(),
+                port: port.clone(),
+            });
+        }
+        actions.push(Action::SetVolume(kind, device.name.clone(), before.volume));
+        if kind == DeviceKind::Sink {
+            actions.extend(restore_actions(&device, &settings));
+        }
+        actions
+    }
+}
+
+/// Moves the default output onto a filter as soon as it shows up in front of it.
+pub struct FollowFilter;
+
+impl Rule for FollowFilter {
+    fn name(&self) -> &'static str {
+        "follow-filter"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if (event.kind, event.facility) != (EventKind::New, Facility::Sink) {
+            return Vec::new();
+        }
+        let Some(sink) = event
+            .index
+            .and_then(|index| find(state, DeviceKind::Sink, index))
+        else {
+            return Vec::new();
+        };
+        let Some(master) = sink.properties.get(filter_chain::MASTER_TAG) else {
+            return Vec::new();
+        };
+        match AudioControls::default_device(DeviceKind::Sink) {
+            Ok(default) if default == *master => {
+                vec![Action::SetDefault(DeviceKind::Sink, sink.name)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Hands a new stream the upmix settings of the sink it plays on.
+pub struct UpmixOnPlay;
+
+impl Rule for UpmixOnPlay {
+    fn name(&self) -> &'static str {
+        "upmix-on-play"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if (event.kind, event.facility) != (EventKind::New, Facility::SinkInput) {
+            return Vec::new();
+        }
+        let Some(stream) = event.index.and_then(|index| {
+            Stream::list()
+                .map_err(|error| warn!(?error, "looking up new stream"))
+                .ok()?
+                .into_iter()
+                .find(|stream| stream.index == index)
+        }) else {
+            return Vec::new();
+        };
+        let Some(sink) = find(state, DeviceKind::Sink, stream.sink) else {
+            return Vec::new();
+        };
+        let master = filter_chain::master_name(&sink).to_owned();
+        state.device_settings.with_untracked(|store| {
+            store
+                .devices
+                .get(&master)
+                .and_then(|settings| settings.upmix)
+                .map(|upmix| vec![Action::UpmixStream(stream.index, upmix)])
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Runs the graph at the rate of each new stream playing on a sink set to bit-perfect.
+pub struct BitPerfectRate;
+
+impl Rule for BitPerfectRate {
+    fn name(&self) -> &'static str {
+        "bit-perfect-rate"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if (event.kind, event.facility) != (EventKind::New, Facility::SinkInput) {
+            return Vec::new();
+        }
+        let Some(stream) = event.index.and_then(|index| {
+            Stream::list()
+                .map_err(|error| warn!(?error, "looking up new stream"))
+                .ok()?
+                .into_iter()
+                .find(|stream| stream.index == index)
+        }) else {
+            return Vec::new();
+        };
+        let Some(sink) = find(state, DeviceKind::Sink, stream.sink) else {
+            return Vec::new();
+        };
+        let master = filter_chain::master_name(&sink).to_owned();
+        let bit_perfect = state.device_settings.with_untracked(|store| {
+            store
+                .devices
+                .get(&master)
+                .is_some_and(|settings| settings.bit_perfect)
+        });
+        match stream.sample_spec() {
+            Some(spec) if bit_perfect && bit_perfect::force_rate().ok() != Some(spec.rate) => {
+                vec![Action::ForceRate(spec.rate)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn is_hdmi(profile: &str) -> bool {
+    profile.contains("hdmi")
+}
+
+/// Switches over to a TV or monitor as it is plugged in, like TVs are expected to work,
+/// and back to what was playing before once it is unplugged.
+#[derive(Default)]
+pub struct HdmiAutoSwitch {
+    /// HDMI profiles of each card that had a display connected, when last seen.
+    available: RefCell<HashMap<String, HashSet<String>>>,
+    /// Profile each card was on before it got switched to HDMI.
+    switched_from: RefCell<HashMap<String, String>>,
+    /// Cards just switched, whose HDMI sink is to become the default once it shows up.
+    pending: RefCell<HashSet<u32>>,
+}
+
+impl HdmiAutoSwitch {
+    fn on_card(&self, card: &Card) -> Vec<Action> {
+        let available = card
+            .profiles()
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| is_hdmi(name))
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+        let Some(previous) = self
+            .available
+            .borrow_mut()
+            .insert(card.name.clone(), available.clone())
+        else {
+            // first sighting, nothing got plugged in or out yet
+            return Vec::new();
+        };
+        let active = card.active_profile.clone().unwrap_or_default();
+        match (previous.is_empty(), available.is_empty()) {
+            (true, false) if !is_hdmi(&active) => {
+                // best first
+                let Some((profile, _)) = card
+                    .profiles()
+                    .into_iter()
+                    .find(|(name, _)| available.contains(*name))
+                else {
+                    return Vec::new();
+                };
+                info!("display connected to {}", card.name);
+                self.switched_from
+                    .borrow_mut()
+                    .insert(card.name.clone(), active);
+                self.pending.borrow_mut().insert(card.index);
+                vec![Action::SetCardProfile {
+                    card: card.name.clone(),
+                    profile: profile.to_owned(),
+                }]
+            }
+            (false, true) => {
+                let Some(profile) = self.switched_from.borrow_mut().remove(&card.name) else {
+                    return Vec::new();
+                };
+                info!("display disconnected from {}", card.name);
+                vec![Action::SetCardProfile {
+                    card: card.name.clone(),
+                    profile,
+                }]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Rule for HdmiAutoSwitch {
+    fn name(&self) -> &'static str {
+        "hdmi-auto-switch"
+    }
+
+    fn switches(&self) -> bool {
+        true
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if !state
+            .device_settings
+            .with_untracked(|store| store.hdmi_auto_switch)
+        {
+            return Vec::new();
+        }
+        match (event.kind, event.facility) {
+            (EventKind::New | EventKind::Change, Facility::Card) => {
+                let Some(card) = event.index.and_then(|index| {
+                    Card::list()
+                        .map_err(|error| warn!(?error, "looking up changed card"))
+                        .ok()?
+                        .into_iter()
+                        .find(|card| card.index == index)
+                }) else {
+                    return Vec::new();
+                };
+                self.on_card(&card)
+            }
+            (EventKind::New, Facility::Sink) => {
+                let Some(sink) = event
+                    .index
+                    .and_then(|index| find(state, DeviceKind::Sink, index))
+                else {
+                    return Vec::new();
+                };
+                let cards = Card::list().unwrap_or_default();
+                let Some(card) = Card::owning(&sink, &cards) else {
+                    return Vec::new();
+                };
+                match self.pending.borrow_mut().remove(&card.index) {
+                    true => vec![Action::SetDefault(DeviceKind::Sink, sink.name)],
+                    false => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Switches to the default devices of the focused workspace, and back to the ones
+/// from before on workspaces without any.
+#[derive(Default)]
+pub struct WorkspaceProfiles {
+    /// Sink and source that were the defaults outside of profiled workspaces.
+    outside: RefCell<Option<(Option<String>, Option<String>)>>,
+}
+
+impl Rule for WorkspaceProfiles {
+    fn name(&self) -> &'static str {
+        "workspace-profiles"
+    }
+
+    fn switches(&self) -> bool {
+        true
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if event.facility != Facility::Workspace {
+            return Vec::new();
+        }
+        let Some(workspace) = state.workspace.get_untracked() else {
+            return Vec::new();
+        };
+        let name = |device: RwSignal<Option<Device>>| {
+            device.with_untracked(|device| device.as_ref().map(|device| device.name.clone()))
+        };
+        let current = (name(state.default_sink), name(state.default_source));
+        let profile = state.device_settings.with_untracked(|store| {
+            workspaces::profile_for(&store.workspaces, &workspace).cloned()
+        });
+        let (sink, source) = match profile {
+            Some(profile) => {
+                self.outside
+                    .borrow_mut()
+                    .get_or_insert_with(|| current.clone());
+                (profile.sink, profile.source)
+            }
+            None => match self.outside.borrow_mut().take() {
+                Some(outside) => outside,
+                None => return Vec::new(),
+            },
+        };
+        [
+            (DeviceKind::Sink, sink, current.0),
+            (DeviceKind::Source, source, current.1),
+        ]
+        .into_iter()
+        .filter_map(|(kind, target, current)| {
+            target
+                .filter(|target| Some(target) != current.as_ref())
+                .map(|target| Action::SetDefault(kind, target))
+        })
+        .collect()
+    }
+}
+
+/// How long after the hardware changed a new default is taken for the session
+/// manager's own pick, rather than the user's, and isn't learned.
+const SMART_DEFAULT_SETTLE: Duration = Duration::from_secs(3);
+
+/// Picks the default devices learned for the hardware connected once it changes,
+/// and learns the ones picked while it stays the same, see [`smart_defaults`].
+#[derive(Default)]
+pub struct SmartDefaults {
+    /// The hardware when last seen, `None` before the devices were first listed.
+    hardware: RefCell<Option<BTreeSet<String>>>,
+    changed_at: Cell<Option<Instant>>,
+    /// Sink and source that were the defaults when last seen.
+    defaults: RefCell<Option<(Option<String>, Option<String>)>>,
+}
+
+impl Rule for SmartDefaults {
+    fn name(&self) -> &'static str {
+        "smart-defaults"
+    }
+
+    fn switches(&self) -> bool {
+        true
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        let name = |device: RwSignal<Option<Device>>| {
+            device.with_untracked(|device| device.as_ref().map(|device| device.name.clone()))
+        };
+        let current = (name(state.default_sink), name(state.default_source));
+        match (event.facility, event.kind) {
+            (Facility::Sink | Facility::Source, EventKind::New | EventKind::Remove) => {
+                let hardware = smart_defaults::hardware(state);
+                let before = self.hardware.replace(Some(hardware.clone()));
+                if before.as_ref().is_none_or(|before| *before == hardware) {
+                    return Vec::new();
+                }
+                self.changed_at.set(Some(Instant::now()));
+                let Some(learned) = state.device_settings.with_untracked(|store| {
+                    smart_defaults::learned(&store.smart_defaults, &hardware).cloned()
+                }) else {
+                    return Vec::new();
+                };
+                info!(?hardware, "picking the defaults learned for the hardware");
+                [
+                    (DeviceKind::Sink, learned.sink, current.0),
+                    (DeviceKind::Source, learned.source, current.1),
+                ]
+                .into_iter()
+                .filter_map(|(kind, target, current)| {
+                    target
+                        .filter(|target| Some(target) != current.as_ref())
+                        .map(|target| Action::SetDefault(kind, target))
+                })
+                .collect()
+            }
+            (Facility::Server, EventKind::Change) => {
+                let before = self.defaults.replace(Some(current.clone()));
+                let Some(hardware) = self.hardware.borrow().clone() else {
+                    return Vec::new();
+                };
+                let settling = self
+                    .changed_at
+                    .get()
+                    .is_some_and(|at| at.elapsed() < SMART_DEFAULT_SETTLE);
+                // what pipeweld's rules pick is theirs, not the user's
+                if before.is_none_or(|before| before == current)
+                    || hardware.is_empty()
+                    || settling
+                    || Source::guess(state) == Source::Rules
+                {
+                    return Vec::new();
+                }
+                let learned = SmartDefault {
+                    hardware,
+                    sink: current.0,
+                    source: current.1,
+                };
+                let known = state.device_settings.with_untracked(|store| {
+                    smart_defaults::learned(&store.smart_defaults, &learned.hardware)
+                        == Some(&learned)
+                });
+                match known {
+                    true => Vec::new(),
+                    false => vec![Action::LearnSmartDefault(learned)],
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Does what the user picked for headphones plugged into a jack, see [`jack`], and
+/// puts the sink's own preset back once they're unplugged.
+#[derive(Default)]
+pub struct HeadphoneJack {
+    /// Jack ports of each sink that had something plugged in, when last seen.
+    plugged: RefCell<HashMap<String, HashSet<String>>>,
+    /// Preset each sink had before plugging in replaced it.
+    preset_before: RefCell<HashMap<String, Option<String>>>,
+}
+
+impl HeadphoneJack {
+    fn on_sink(&self, sink: &Device, state: AppState) -> Vec<Action> {
+        let plugged = jack::plugged(sink);
+        let Some(previous) = self
+            .plugged
+            .borrow_mut()
+            .insert(sink.name.clone(), plugged.clone())
+        else {
+            // first sighting, nothing got plugged in or out yet
+            return Vec::new();
+        };
+        if let Some(port) = plugged.difference(&previous).next() {
+            info!(port, "headphones plugged into {}", sink.name);
+            return state.device_settings.with_untracked(|store| {
+                let actions = jack::on_plug(store, sink, port);
+                if store.headphone_jack.preset.is_some() {
+                    let own = store.get(sink).and_then(|settings| settings.preset.clone());
+                    self.preset_before
+                        .borrow_mut()
+                        .entry(sink.name.clone())
+                        .or_insert(own);
+                }
+                actions
+            });
+        }
+        if !plugged.is_empty() || previous.is_empty() {
+            return Vec::new();
+        }
+        info!("headphones unplugged from {}", sink.name);
+        match self.preset_before.borrow_mut().remove(&sink.name) {
+            Some(preset) => vec![Action::SetPreset {
+                device: sink.name.clone(),
+                preset,
+            }],
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Rule for HeadphoneJack {
+    fn name(&self) -> &'static str {
+        "headphone-jack"
+    }
+
+    fn switches(&self) -> bool {
+        true
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        // plugging in changes the port's availability on the card and on the sink
+        if !matches!(
+            (event.kind, event.facility),
+            (
+                EventKind::New | EventKind::Change,
+                Facility::Sink | Facility::Card
+            )
+        ) {
+            return Vec::new();
+        }
+        let sinks = match Device::list(DeviceKind::Sink) {
+            Ok(sinks) => sinks,
+            Err(error) => {
+                warn!(?error, "looking up jacks");
+                return Vec::new();
+            }
+        };
+        sinks
+            .iter()
+            // pipeweld's own filters have no ports
+            .filter(|sink| filter_chain::master_of(sink).is_none())
+            .flat_map(|sink| self.on_sink(sink, state))
+            .collect()
+    }
+}
+
+/// Switches presets along with the port of a sink, for sinks with presets of their
+/// ports.
+#[derive(Default)]
+pub struct PortPresets {
+    /// Active port of each sink, when last seen.
+    ports: RefCell<HashMap<String, Option<String>>>,
+}
+
+impl Rule for PortPresets {
+    fn name(&self) -> &'static str {
+        "port-presets"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if !matches!(
+            (event.kind, event.facility),
+            (EventKind::New | EventKind::Change, Facility::Sink)
+        ) {
+            return Vec::new();
+        }
+        let Some(sink) = event
+            .index
+            .and_then(|index| find(state, DeviceKind::Sink, index))
+        else {
+            return Vec::new();
+        };
+        let previous = self
+            .ports
+            .borrow_mut()
+            .insert(sink.name.clone(), sink.active_port.clone());
+        // a new sink gets its preset restored on connecting
+        if previous.is_none() || previous == Some(sink.active_port.clone()) {
+            return Vec::new();
+        }
+        let switches = state.device_settings.with_untracked(|store| {
+            store
+                .get(&sink)
+                .is_some_and(|settings| !settings.bit_perfect && !settings.port_presets.is_empty())
+        });
+        match switches {
+            true => {
+                info!(port = ?sink.active_port, "{} switched ports", sink.name);
+                vec![Action::ApplyPreset(sink.name)]
+            }
+            false => Vec::new(),
+        }
+    }
+}
+
+/// Puts back the volume of streams of applications whose volume the user locked,
+/// whenever something else changes it.
+pub struct VolumeLock;
+
+impl Rule for VolumeLock {
+    fn name(&self) -> &'static str {
+        "volume-lock"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if !matches!(
+            (event.kind, event.facility),
+            (EventKind::New | EventKind::Change, Facility::SinkInput)
+        ) || state
+            .device_settings
+            .with_untracked(|store| store.locked_volumes.is_empty())
+        {
+            return Vec::new();
+        }
+        let Some(stream) = event.index.and_then(|index| {
+            Stream::list()
+                .map_err(|error| warn!(?error, "looking up locked stream"))
+                .ok()?
+                .into_iter()
+                .find(|stream| stream.index == index)
+        }) else {
+            return Vec::new();
+        };
+        let locked = stream
+            .properties
+            .get("application.name")
+            .and_then(|application| {
+                state
+                    .device_settings
+                    .with_untracked(|store| store.locked_volumes.get(application).copied())
+            });
+        match locked {
+            Some(percent) if stream.volume_percent() != percent => {
+                vec![Action::SetStreamVolume(stream.index, percent)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Trims each side of a stereo pair, once its loopback's stream shows up, see
+/// [`crate::virtual_devices::Side`].
+pub struct StereoPairTrim;
+
+impl Rule for StereoPairTrim {
+    fn name(&self) -> &'static str {
+        "stereo-pair-trim"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if (event.kind, event.facility) != (EventKind::New, Facility::SinkInput) {
+            return Vec::new();
+        }
+        let Some(stream) = event.index.and_then(|index| {
+            Stream::list()
+                .map_err(|error| warn!(?error, "looking up new stream"))
+                .ok()?
+                .into_iter()
+                .find(|stream| stream.index == index)
+        }) else {
+            return Vec::new();
+        };
+        let trim = state
+            .device_settings
+            .with_untracked(|store| virtual_devices::side_volume(&store.virtual_devices, &stream));
+        match trim {
+            Some(percent) if stream.volume_percent() != percent => {
+                vec![Action::SetStreamVolume(stream.index, percent)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Takes the streams of applications following the output's volume along with it,
+/// and picks a new share for them when something else changes one.
+pub struct RelativeVolume;
+
+impl RelativeVolume {
+    /// The share `stream` should play at, leaving locked applications to their lock.
+    fn share(stream: &Stream, state: AppState) -> Option<(String, u32)> {
+        let application = stream.properties.get("application.name")?;
+        state.device_settings.with_untracked(|store| {
+            match store.locked_volumes.contains_key(application) {
+                true => None,
+                false => store
+                    .relative_volumes
+                    .get(application)
+                    .map(|share| (application.clone(), *share)),
+            }
+        })
+    }
+}
+
+impl Rule for RelativeVolume {
+    fn name(&self) -> &'static str {
+        "relative-volume"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        if state
+            .device_settings
+            .with_untracked(|store| store.relative_volumes.is_empty())
+        {
+            return Vec::new();
+        }
+        let Some(index) = event.index else {
+            return Vec::new();
+        };
+        let streams = || {
+            Stream::list()
+                .map_err(|error| warn!(?error, "looking up streams following the output"))
+                .unwrap_or_default()
+        };
+        match (event.kind, event.facility) {
+            (EventKind::Change, Facility::Sink) => {
+                let Some(sink) = find(state, DeviceKind::Sink, index) else {
+                    return Vec::new();
+                };
+                streams()
+                    .iter()
+                    .filter(|stream| stream.sink == sink.index)
+                    .filter_map(|stream| {
+                        let (_, share) = Self::share(stream, state)?;
+                        let target = relative_volume::target(sink.volume_percent(), share);
+                        (stream.volume_percent() != target)
+                            .then_some(Action::SetStreamVolume(stream.index, target))
+                    })
+                    .collect()
+            }
+            (EventKind::New | EventKind::Change, Facility::SinkInput) => {
+                let Some(stream) = streams().into_iter().find(|stream| stream.index == index)
+                else {
+                    return Vec::new();
+                };
+                let (Some((application, share)), Some(sink)) = (
+                    Self::share(&stream, state),
+                    find(state, DeviceKind::Sink, stream.sink),
+                ) else {
+                    return Vec::new();
+                };
+                let volume = stream.volume_percent();
+                if volume == relative_volume::target(sink.volume_percent(), share) {
+                    return Vec::new();
+                }
+                match event.kind {
+                    // a new stream starts out at its share
+                    EventKind::New => vec![Action::SetStreamVolume(
+                        stream.index,
+                        relative_volume::target(sink.volume_percent(), share),
+                    )],
+                    _ => relative_volume::share(volume, sink.volume_percent())
+                        .filter(|changed| *changed != share)
+                        .map(|changed| {
+                            vec![Action::SetRelativeVolume {
+                                application,
+                                share: Some(changed),
+                            }]
+                        })
+                        .unwrap_or_default(),
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Sends each new stream of an application with an output forced on it there, and
+/// runs the graph at its rate while any of them plays.
+#[derive(Default)]
+pub struct ForcedOutputs {
+    /// Streams playing at a forced rate.
+    forced: RefCell<HashSet<u32>>,
+    /// The rate the graph was forced to before them, to put back.
+    rate_before: Cell<Option<u32>>,
+}
+
+impl Rule for ForcedOutputs {
+    fn name(&self) -> &'static str {
+        "forced-output"
+    }
+
+    fn react(&self, event: Event, state: AppState) -> Vec<Action> {
+        let Some(index) = event.index else {
+            return Vec::new();
+        };
+        match (event.kind, event.facility) {
+            (EventKind::New, Facility::SinkInput) => {
+                let Some(stream) = Stream::list()
+                    .map_err(|error| warn!(?error, "looking up new stream"))
+                    .ok()
+                    .and_then(|streams| streams.into_iter().find(|stream| stream.index == index))
+                else {
+                    return Vec::new();
+                };
+                let Some(forced) =
+                    stream
+                        .properties
+                        .get("application.name")
+                        .and_then(|application| {
+                            state.device_settings.with_untracked(|store| {
+                                store.forced_outputs.get(application).cloned()
+                            })
+                        })
+                else {
+                    return Vec::new();
+                };
+                let mut actions = Vec::new();
+                match Device::list(DeviceKind::Sink)
+                    .unwrap_or_default()
+                    .iter()
+                    .find(|sink| sink.name == forced.sink)
+                {
+                    Some(sink) if sink.index != stream.sink => actions.push(Action::MoveStream {
+                        stream: index,
+                        sink: forced.sink,
+                    }),
+                    Some(_) => (),
+                    None => info!(
+                        sink = forced.sink,
+                        "the output forced on a stream isn't there"
+                    ),
+                }
+                if let Some(rate) = forced.rate {
+                    let current = bit_perfect::force_rate().unwrap_or(0);
+                    let mut playing = self.forced.borrow_mut();
+                    if playing.is_empty() {
+                        self.rate_before.set(Some(current));
+                    }
+                    playing.insert(index);
+                    if current != rate {
+                        actions.push(Action::ForceRate(rate));
+                    }
+                }
+                actions
+            }
+            (EventKind::Remove, Facility::SinkInput) => {
+                let mut playing = self.forced.borrow_mut();
+                if !playing.remove(&index) || !playing.is_empty() {
+                    return Vec::new();
+                }
+                self.rate_before
+                    .take()
+                    .map(|rate| vec![Action::ForceRate(rate)])
+                    .unwrap_or_default()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The device of `kind` at `index`, as the state has it, which events update
+/// before rules see them.
+fn find(state: AppState, kind: DeviceKind, index: u32) -> Option<Device> {
+    state
+        .devices(kind)
+        .with_untracked(|devices| devices.iter().find(|device| device.index == index).cloned())
+}
+
+/// A rule firing more often than this within [`LOOP_WINDOW`] is disabled. Plugging
+/// things in and out doesn't get anywhere near it.
+const LOOP_LIMIT: usize = 10;
+
+const LOOP_WINDOW: Duration = Duration::from_secs(10);
+
+pub struct Engine {
+    rules: Vec<Box<dyn Rule>>,
+    dispatcher: Dispatcher,
+    /// When each rule fired within the last [`LOOP_WINDOW`].
+    fired: RefCell<HashMap<&'static str, VecDeque<Instant>>>,
+    notifier: Option<Notifier>,
+}
+
+impl Engine {
+    pub fn new(dispatcher: Dispatcher) -> Self {
+        Self {
+            rules: vec![
+                Box::new(ConnectVolume),
+                Box::new(RestoreOnConnect),
+                Box::new(FollowFilter),
+                Box::new(UpmixOnPlay),
+                Box::new(BitPerfectRate),
+                Box::<HdmiAutoSwitch>::default(),
+                Box::<WorkspaceProfiles>::default(),
+                Box::<SmartDefaults>::default(),
+                Box::<HeadphoneJack>::default(),
+                Box::<PortPresets>::default(),
+                Box::<ResetRecovery>::default(),
+                Box::new(VolumeLock),
+                Box::new(StereoPairTrim),
+                Box::new(RelativeVolume),
+                Box::<ForcedOutputs>::default(),
+            ],
+            dispatcher: dispatcher.automated(),
+            fired: RefCell::default(),
+            notifier: Notifier::new()
+                .map_err(|error| warn!(?error, "rule loops won't be notified about"))
+                .ok(),
+        }
+    }
+
+    /// Notes that `rule` fired, returning whether it did so too often.
+    fn looping(&self, rule: &'static str) -> bool {
+        let now = Instant::now();
+        let mut fired = self.fired.borrow_mut();
+        let times = fired.entry(rule).or_default();
+        while times
+            .front()
+            .is_some_and(|time| now.duration_since(*time) > LOOP_WINDOW)
+        {
+            times.pop_front();
+        }
+        times.push_back(now);
+        if times.len() <= LOOP_LIMIT {
+            return false;
+        }
+        // so it starts over when enabled again
+        fired.remove(rule);
+        true
+    }
+
+    #[instrument(skip(self, state))]
+    pub fn apply(&self, event: Event, state: AppState) {
+        for rule in &self.rules {
+            if state
+                .disabled_rules
+                .with_untracked(|disabled| disabled.contains(&rule.name()))
+            {
+                continue;
+            }
+            let actions = rule.react(event, state);
+            if actions.is_empty() {
+                continue;
+            }
+            // the rule still follows what happens, so it's up to date once released
+            if rule.switches() && state.hold.with_untracked(Hold::is_on) {
+                info!(rule = rule.name(), ?actions, "routing held, leaving out");
+                continue;
+            }
+            if self.looping(rule.name()) {
+                warn!(
+                    rule = rule.name(),
+                    "fired more than {LOOP_LIMIT} times in {LOOP_WINDOW:?}, disabling it"
+                );
+                state
+                    .disabled_rules
+                    .update(|disabled| disabled.push(rule.name()));
+                if let Some(notifier) = &self.notifier {
+                    notifier.show(
+                        Notification::new(tr("Rule disabled due to loop"))
+                            .icon("dialog-warning-symbolic")
+                            .body(tr_with(
+                                "{rule} kept reacting to its own changes or to another rule's.",
+                                &[("rule", rule.name())],
+                            )),
+                    );
+                }
+                continue;
+            }
+            for action in actions {
+                info!(rule = rule.name(), ?action, "rule triggered");
+                self.dispatcher.dispatch(action);
+            }
+        }
+    }
+}
+
+/// Warning about rules disabled for looping, hidden while there are none.
+pub fn loop_warning(cx: Scope, state: AppState) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Horizontal, 6);
+    layout.set_margin_start(12);
+    layout.set_margin_end(12);
+    layout.set_margin_top(12);
+    let label = gtk::Label::new(None);
+    label.set_wrap(true);
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    label.add_css_class("warning");
+    layout.append(&label);
+    let enable = gtk::Button::with_label(&tr("Enable again"));
+    enable.connect_clicked(move |_| state.disabled_rules.set(Vec::new()));
+    layout.append(&enable);
+    watch(cx, &layout, move |layout| {
+        state.disabled_rules.with(|disabled| {
+            layout.set_visible(!disabled.is_empty());
+            label.set_text(&tr_with(
+                "Rule disabled due to loop: {rules}",
+                &[("rules", &disabled.join(", "))],
+            ));
+        })
+    });
+    layout
+}
@@ -0,0 +1,317 @@
+//! Routing presets put back on a schedule, like "Office" on weekdays from 9 to 17,
+//! kept with the device settings:
+//!
+//! ```toml
+//! [[schedules]]
+//! preset = "Office"
+//! days = ["monday", "tuesday", "wednesday", "thursday", "friday"]
+//! start = "09:00"
+//! end = "17:00"
+//! ```
+//!
+//! A schedule applies its preset once, as its time starts or pipeweld starts within
+//! it, so whatever the user picks meanwhile stands until the next one starts; while
+//! the routing is held, see [`crate::hold`], starting schedules are skipped. Times
+//! ending before they start run overnight, into the next day.
+use std::{collections::BTreeSet, fmt, str::FromStr, time::Duration};
+
+use eyre::{eyre, Result};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    hold::Hold,
+    i18n::{marked, tr, tr_with},
+    state::AppState,
+};
+
+/// How often schedules are checked.
+const TICK: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Day {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Day {
+    pub const ALL: [Self; 7] = [
+        Self::Monday,
+        Self::Tuesday,
+        Self::Wednesday,
+        Self::Thursday,
+        Self::Friday,
+        Self::Saturday,
+        Self::Sunday,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Monday => marked("Mon"),
+            Self::Tuesday => marked("Tue"),
+            Self::Wednesday => marked("Wed"),
+            Self::Thursday => marked("Thu"),
+            Self::Friday => marked("Fri"),
+            Self::Saturday => marked("Sat"),
+            Self::Sunday => marked("Sun"),
+        }
+    }
+
+    /// The day before, for schedules running overnight.
+    fn previous(self) -> Self {
+        let index = Self::ALL.iter().position(|day| *day == self).unwrap_or(0);
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// Time of day, written like `09:00`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    /// Minutes since midnight.
+    pub minutes: u32,
+}
+
+impl FromStr for TimeOfDay {
+    type Err = eyre::Report;
+
+    fn from_str(time: &str) -> Result<Self> {
+        let parse = || {
+            let (hour, minute) = time.trim().split_once(':')?;
+            let (hour, minute) = (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?);
+            (hour < 24 && minute < 60).then_some(Self {
+                minutes: hour * 60 + minute,
+            })
+        };
+        parse().ok_or_else(|| eyre!("expected a time like 09:00, not {time:?}"))
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = eyre::Report;
+
+    fn try_from(time: String) -> Result<Self> {
+        time.parse()
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{:02}:{:02}",
+            self.minutes / 60,
+            self.minutes % 60
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    /// Name of the routing preset, see [`crate::routing`].
+    pub preset: String,
+    pub days: BTreeSet<Day>,
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl Schedule {
+    /// Whether the schedule's time is on at `time` of `day`.
+    pub fn covers(&self, day: Day, time: TimeOfDay) -> bool {
+        match self.start <= self.end {
+            true => self.days.contains(&day) && self.start <= time && time < self.end,
+            // overnight, started today or yesterday
+            false => {
+                (self.days.contains(&day) && time >= self.start)
+                    || (self.days.contains(&day.previous()) && time < self.end)
+            }
+        }
+    }
+
+    /// Like "Office on Mon, Tue from 09:00 to 17:00".
+    pub fn describe(&self) -> String {
+        let days = self
+            .days
+            .iter()
+            .map(|day| tr(day.label()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tr_with(
+            "{preset} on {days} from {start} to {end}",
+            &[
+                ("preset", &self.preset),
+                ("days", &days),
+                ("start", &self.start.to_string()),
+                ("end", &self.end.to_string()),
+            ],
+        )
+    }
+}
+
+/// The day and time it is now.
+fn now() -> Result<(Day, TimeOfDay)> {
+    let now = glib::DateTime::now_local().map_err(|error| eyre!("reading the time: {error}"))?;
+    // 1 for Monday
+    let day = Day::ALL[(now.day_of_week() as usize).clamp(1, 7) - 1];
+    let minutes = (now.hour() * 60 + now.minute()) as u32;
+    Ok((day, TimeOfDay { minutes }))
+}
+
+/// Checks the schedules every [`TICK`], applying the preset of one that's started
+/// since, the first listed winning when several start together.
+pub fn spawn(state: AppState, dispatcher: Dispatcher) {
+    let dispatcher = dispatcher.automated();
+    // schedules that were on when last checked
+    let mut on = Vec::<Schedule>::new();
+    let mut check = move || {
+        let (day, time) = match now() {
+            Ok(now) => now,
+            Err(error) => return warn!(?error, "checking the schedules"),
+        };
+        let current = state.device_settings.with_untracked(|store| {
+            store
+                .schedules
+                .iter()
+                .filter(|schedule| schedule.covers(day, time))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        let started = current.iter().find(|schedule| !on.contains(schedule));
+        if let Some(schedule) = started {
+            match state.hold.with_untracked(Hold::is_on) {
+                true => info!(schedule = schedule.describe(), "routing held, not applying"),
+                false => {
+                    info!(schedule = schedule.describe(), "schedule started");
+                    dispatcher.dispatch(Action::ApplyRouting(schedule.preset.clone()));
+                }
+            }
+        }
+        on = current;
+    };
+    check();
+    glib::timeout_add_local(TICK, move || {
+        check();
+        glib::Continue(true)
+    });
+}
+
+/// Section of the routing presets listing the schedules, with a form adding one.
+pub fn schedule_editor(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let heading = gtk::Label::new(Some(&tr("Schedules")));
+    heading.set_halign(gtk::Align::Start);
+    heading.add_css_class("heading");
+    layout.append(&heading);
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&rows);
+
+    let form = gtk::Box::new(Orientation::Horizontal, 6);
+    let preset = gtk::DropDown::from_strings(&[]);
+    preset.set_tooltip_text(Some(&tr("Routing preset to apply")));
+    form.append(&preset);
+    let time = |placeholder: &str, tooltip: &str| {
+        let entry = gtk::Entry::builder()
+            .placeholder_text(placeholder)
+            .tooltip_text(tooltip)
+            .width_chars(5)
+            .build();
+        form.append(&entry);
+        entry
+    };
+    let start = time("09:00", &tr("When the schedule starts"));
+    let end = time("17:00", &tr("When it ends, before the start for overnight"));
+    let add = gtk::Button::from_icon_name("list-add-symbolic");
+    form.append(&add);
+    layout.append(&form);
+    let week = gtk::Box::new(Orientation::Horizontal, 0);
+    week.add_css_class("linked");
+    let days = Day::ALL.map(|day| {
+        let check = gtk::ToggleButton::with_label(&tr(day.label()));
+        check.set_active(!matches!(day, Day::Saturday | Day::Sunday));
+        week.append(&check);
+        (day, check)
+    });
+    layout.append(&week);
+
+    add.connect_clicked({
+        let (preset, dispatcher) = (preset.clone(), dispatcher.clone());
+        move |_| {
+            let Some(name) = state
+                .routings
+                .with_untracked(|saved| saved.get(preset.selected() as usize).cloned())
+            else {
+                return warn!("a schedule needs a routing preset to apply");
+            };
+            let time = |entry: &gtk::Entry| {
+                let text = entry.text();
+                let text = match text.is_empty() {
+                    true => entry.placeholder_text().unwrap_or_default(),
+                    false => text,
+                };
+                text.parse::<TimeOfDay>()
+            };
+            let (start, end) = match (time(&start), time(&end)) {
+                (Ok(start), Ok(end)) if start != end => (start, end),
+                (Err(error), _) | (_, Err(error)) => return warn!(?error, "adding a schedule"),
+                _ => return warn!("a schedule needs to end at another time than it starts"),
+            };
+            let days = days
+                .iter()
+                .filter(|(_, check)| check.is_active())
+                .map(|(day, _)| *day)
+                .collect::<BTreeSet<_>>();
+            if days.is_empty() {
+                return warn!("a schedule needs a day to run on");
+            }
+            dispatcher.dispatch(Action::AddSchedule(Schedule {
+                preset: name,
+                days,
+                start,
+                end,
+            }));
+        }
+    });
+    create_effect(cx, move |_| {
+        let labels = state.routings.get();
+        let labels = labels.iter().map(String::as_str).collect::<Vec<_>>();
+        preset.set_model(Some(&gtk::StringList::new(&labels)));
+    });
+    // settings change for much else, the list is only built again for its own
+    let schedules = create_memo(cx, move |_| {
+        state.device_settings.with(|store| store.schedules.clone())
+    });
+    create_effect(cx, move |_| {
+        let schedules = schedules.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        for (index, schedule) in schedules.iter().enumerate() {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            let label = gtk::Label::new(Some(&schedule.describe()));
+            label.set_hexpand(true);
+            label.set_halign(gtk::Align::Start);
+            row.append(&label);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            let dispatcher = dispatcher.clone();
+            remove.connect_clicked(move |_| dispatcher.dispatch(Action::RemoveSchedule(index)));
+            row.append(&remove);
+            rows.append(&row);
+        }
+    });
+    layout
+}
@@ -0,0 +1,126 @@
+//! Volume control over D-Bus for desktops, scripts and bars like waybar, on the
+//! connection and name the application already holds, as in
+//!
+//! ```sh
+//! busctl --user call it.niedzwiedz.pipeweld /it/niedzwiedz/pipeweld it.niedzwiedz.pipeweld.Volume VolumeUp
+//! ```
+//!
+//! `VolumeChanged` tells about every change of the default output, whoever made it.
+use gtk::{
+    gio,
+    glib::{ToVariant, Variant},
+    prelude::*,
+    Application,
+};
+use leptos::*;
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::DeviceKind,
+    state::AppState,
+    DiffValue,
+};
+
+const INTERFACE: &str = "it.niedzwiedz.pipeweld.Volume";
+
+/// Step of `VolumeUp` and `VolumeDown`, as the keys take.
+const STEP: i32 = 5;
+
+const INTROSPECTION: &str = r#"
+<node>
+  <interface name="it.niedzwiedz.pipeweld.Volume">
+    <method name="VolumeUp"/>
+    <method name="VolumeDown"/>
+    <method name="Mute"/>
+    <method name="SetVolume">
+      <arg name="percent" type="u" direction="in"/>
+    </method>
+    <signal name="VolumeChanged">
+      <arg name="percent" type="u"/>
+      <arg name="muted" type="b"/>
+    </signal>
+    <property name="Volume" type="u" access="read"/>
+    <property name="Muted" type="b" access="read"/>
+  </interface>
+</node>
+"#;
+
+fn action(method: &str, parameters: &Variant) -> Option<Action> {
+    match method {
+        "VolumeUp" => Some(Action::ChangeVolume(DiffValue(STEP))),
+        "VolumeDown" => Some(Action::ChangeVolume(DiffValue(-STEP))),
+        "Mute" => Some(Action::ToggleMute(DeviceKind::Sink)),
+        "SetVolume" => parameters.get::<(u32,)>().map(|(percent,)| {
+            Action::SetVolume(
+                DeviceKind::Sink,
+                DeviceKind::Sink.default_alias().to_owned(),
+                percent,
+            )
+        }),
+        _ => None,
+    }
+}
+
+/// Volume and mute of the default output, 0 and unmuted without one.
+fn volume(state: AppState) -> (u32, bool) {
+    state.default_sink.with(|sink| {
+        sink.as_ref()
+            .map_or((0, false), |sink| (sink.volume_percent(), sink.mute))
+    })
+}
+
+/// Exports the interface next to the application's own, once it's registered on the
+/// session bus, and announces changes of the default output while it runs.
+pub fn register(cx: Scope, app: &Application, state: AppState, dispatcher: Dispatcher) {
+    let (Some(connection), Some(path)) = (app.dbus_connection(), app.dbus_object_path()) else {
+        return warn!("not on the session bus, there'll be no D-Bus volume control");
+    };
+    let node = match gio::DBusNodeInfo::for_xml(INTROSPECTION) {
+        Ok(node) => node,
+        Err(error) => return warn!(%error, "parsing volume control introspection"),
+    };
+    let interface = node
+        .lookup_interface(INTERFACE)
+        .expect("interface is declared");
+    let registered = connection.register_object(
+        &path,
+        &interface,
+        move |_, _, _, _, method, parameters, invocation| match action(method, &parameters) {
+            Some(action) => {
+                info!(method, ?action, "D-Bus volume control");
+                dispatcher.dispatch(action);
+                invocation.return_value(None);
+            }
+            None => invocation.return_dbus_error(
+                "org.freedesktop.DBus.Error.InvalidArgs",
+                &format!("{method} takes different arguments"),
+            ),
+        },
+        move |_, _, _, _, property| {
+            let (percent, muted) = volume(state);
+            match property {
+                "Muted" => muted.to_variant(),
+                _ => percent.to_variant(),
+            }
+        },
+        |_, _, _, _, _, _| false,
+    );
+    if let Err(error) = registered {
+        return warn!(%error, "exporting volume control");
+    }
+    let volume = create_memo(cx, move |_| volume(state));
+    create_effect(cx, move |_| {
+        let (percent, muted) = volume.get();
+        connection
+            .emit_signal(
+                None,
+                &path,
+                INTERFACE,
+                "VolumeChanged",
+                Some(&(percent, muted).to_variant()),
+            )
+            .map_err(|error| warn!(%error, "announcing volume change"))
+            .ok();
+    });
+}
@@ -0,0 +1,77 @@
+//! Making sure pipeweld controls the audio of the user running it. Over SSH, under
+//! sudo or from another seat, the server pactl finds by default may be somebody
+//! else's, or there may be none to find.
+use std::{os::unix::fs::MetadataExt, path::Path};
+
+use eyre::{eyre, Result};
+use gtk::{glib, prelude::*, Application};
+use tracing::info;
+
+const OPTION: &str = "server";
+
+fn owner(path: &Path) -> Option<u32> {
+    std::fs::metadata(path).ok().map(|metadata| metadata.uid())
+}
+
+/// Value of `--option` among `args`, as `--option VALUE` or `--option=VALUE`.
+pub(crate) fn argument(args: &[String], option: &str) -> Option<String> {
+    let flag = format!("--{option}");
+    args.iter()
+        .enumerate()
+        .find_map(|(position, arg)| match arg.strip_prefix(&flag)? {
+            "" => args.get(position + 1).cloned(),
+            value => value.strip_prefix('=').map(str::to_owned),
+        })
+}
+
+/// Picks the server to control: the one passed with `--server`, the one in
+/// `PULSE_SERVER`, or the one in the runtime directory, as long as it belongs to
+/// whoever runs pipeweld. Client programs learn the choice from the environment,
+/// so this has to run before any of them starts.
+pub fn select_server(args: &[String]) -> Result<()> {
+    if let Some(server) = argument(args, OPTION) {
+        info!(server, "controlling the server passed on the command line");
+        std::env::set_var("PULSE_SERVER", server);
+        return Ok(());
+    }
+    if let Ok(server) = std::env::var("PULSE_SERVER") {
+        info!(server, "controlling the server in PULSE_SERVER");
+        return Ok(());
+    }
+    let over_ssh = std::env::var_os("SSH_CONNECTION").is_some();
+    let Some(runtime) = std::env::var_os("XDG_RUNTIME_DIR") else {
+        return match over_ssh {
+            true => Err(eyre!(
+                "This SSH login has no runtime directory, so there is no audio to control. \
+                 Pass --{OPTION}, as in --{OPTION} unix:/run/user/1000/pulse/native."
+            )),
+            // without a server, the ALSA mixer takes over
+            false => Ok(()),
+        };
+    };
+    let uid = owner(Path::new("/proc/self"));
+    let runtime = Path::new(&runtime);
+    for path in [runtime.to_owned(), runtime.join("pulse").join("native")] {
+        if owner(&path).is_some_and(|owner| Some(owner) != uid) {
+            return Err(eyre!(
+                "{} belongs to another user, whose audio pipeweld would control. Run it as \
+                 that user, or pass --{OPTION} to pick a server.",
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Declares `--server`, which [`select_server`] reads before GTK gets to the
+/// command line.
+pub fn add_option(app: &Application) {
+    app.add_main_option(
+        OPTION,
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Sound server to control, as in PULSE_SERVER",
+        Some("ADDRESS"),
+    );
+}
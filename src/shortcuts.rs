@@ -0,0 +1,434 @@
+//! The media keys, taken through the desktop portal's global shortcuts so they work
+//! while another window has focus, for desktops where nothing else turns them into
+//! volume changes. Binding them asks the desktop, which may have the user confirm
+//! or pick other keys, and they stay with pipeweld's portal session until it's
+//! closed again.
+use std::{cell::RefCell, rc::Rc};
+
+use eyre::eyre;
+use gtk::{
+    gio,
+    glib::{variant::ObjectPath, ToVariant, Variant, VariantDict, VariantTy},
+    prelude::*,
+    Orientation,
+};
+use leptos::*;
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    config,
+    devices::DeviceKind,
+    errors,
+    i18n::tr,
+    state::AppState,
+    DiffValue,
+};
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+const REGISTRY_INTERFACE: &str = "org.freedesktop.host.portal.Registry";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+const SESSION_INTERFACE: &str = "org.freedesktop.portal.Session";
+
+const WHAT: &str = "binding the media keys";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shortcut {
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    MicrophoneMute,
+}
+
+impl Shortcut {
+    pub const ALL: [Self; 4] = [
+        Self::VolumeUp,
+        Self::VolumeDown,
+        Self::Mute,
+        Self::MicrophoneMute,
+    ];
+
+    /// Identifier within pipeweld's session.
+    fn id(self) -> &'static str {
+        match self {
+            Self::VolumeUp => "volume-up",
+            Self::VolumeDown => "volume-down",
+            Self::Mute => "mute",
+            Self::MicrophoneMute => "microphone-mute",
+        }
+    }
+
+    fn of_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shortcut| shortcut.id() == id)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::VolumeUp => "Volume up",
+            Self::VolumeDown => "Volume down",
+            Self::Mute => "Mute the output",
+            Self::MicrophoneMute => "Mute the microphone",
+        }
+    }
+
+    /// The key asked for, the desktop may bind another one.
+    fn trigger(self) -> &'static str {
+        match self {
+            Self::VolumeUp => "XF86AudioRaiseVolume",
+            Self::VolumeDown => "XF86AudioLowerVolume",
+            Self::Mute => "XF86AudioMute",
+            Self::MicrophoneMute => "XF86AudioMicMute",
+        }
+    }
+
+    pub fn action(self) -> Action {
+        let step = config::get().steps.iter().min().copied().unwrap_or(5) as i32;
+        match self {
+            Self::VolumeUp => Action::ChangeVolume(DiffValue(step)),
+            Self::VolumeDown => Action::ChangeVolume(DiffValue(-step)),
+            Self::Mute => Action::ToggleMute(DeviceKind::Sink),
+            Self::MicrophoneMute => Action::ToggleMute(DeviceKind::Source),
+        }
+    }
+}
+
+/// The portal session while the shortcuts are bound, to close again.
+#[derive(Default)]
+struct Session {
+    connection: Option<gio::DBusConnection>,
+    /// Object path of the session, once the portal created it.
+    handle: Option<String>,
+    activated: Option<gio::SignalSubscriptionId>,
+    /// Bumped by every close, replies meant for an older session are dropped.
+    generation: u32,
+}
+
+impl Session {
+    fn close(&mut self) {
+        self.generation += 1;
+        let Some(connection) = self.connection.take() else {
+            return;
+        };
+        if let Some(activated) = self.activated.take() {
+            connection.signal_unsubscribe(activated);
+        }
+        let Some(handle) = self.handle.take() else {
+            return;
+        };
+        info!("giving the media keys back");
+        connection.call(
+            Some(PORTAL),
+            &handle,
+            SESSION_INTERFACE,
+            "Close",
+            None,
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            |result| {
+                if let Err(error) = result {
+                    warn!(%error, "closing the global shortcuts session");
+                }
+            },
+        );
+    }
+}
+
+/// Calls `method` of the portal, which answers with a Response signal on a request
+/// object named after `token`. `done` gets the results, unless the desktop refused
+/// or the session was closed in the meantime.
+fn request(
+    connection: &gio::DBusConnection,
+    session: &Rc<RefCell<Session>>,
+    state: AppState,
+    method: &str,
+    token: &str,
+    parameters: Variant,
+    done: impl FnOnce(VariantDict) + 'static,
+) {
+    let generation = session.borrow().generation;
+    let sender = connection
+        .unique_name()
+        .map(|name| name.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+    let path = format!("{PATH}/request/{sender}/{token}");
+    // subscribed before calling, the response may come before the reply does
+    let subscription = Rc::new(RefCell::new(None));
+    let done = RefCell::new(Some(done));
+    let id = connection.signal_subscribe(
+        Some(PORTAL),
+        Some(REQUEST_INTERFACE),
+        Some("Response"),
+        Some(&path),
+        None,
+        gio::DBusSignalFlags::NONE,
+        {
+            let subscription = subscription.clone();
+            let session = session.clone();
+            move |connection, _, _, _, _, parameters| {
+                if let Some(id) = subscription.borrow_mut().take() {
+                    connection.signal_unsubscribe(id);
+                }
+                let Some(done) = done.borrow_mut().take() else {
+                    return;
+                };
+                if session.borrow().generation != generation {
+                    return;
+                }
+                let response = parameters.child_value(0).get::<u32>();
+                let results = VariantDict::new(Some(&parameters.child_value(1)));
+                match response {
+                    Some(0) => done(results),
+                    // 1 is the user cancelling, 2 anything else
+                    response => errors::report(
+                        state,
+                        WHAT,
+                        &eyre!("the desktop refused, with response {response:?}"),
+                    ),
+                }
+            }
+        },
+    );
+    *subscription.borrow_mut() = Some(id);
+    let caller = connection.clone();
+    connection.call(
+        Some(PORTAL),
+        PATH,
+        INTERFACE,
+        method,
+        Some(&parameters),
+        VariantTy::new("(o)").ok(),
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+        move |result| {
+            if let Err(error) = result {
+                if let Some(id) = subscription.borrow_mut().take() {
+                    caller.signal_unsubscribe(id);
+                }
+                errors::report(state, WHAT, &eyre!(error));
+            }
+        },
+    );
+}
+
+/// Names pipeweld to the portal, which otherwise goes by the desktop file of the
+/// process. Older portals don't know how, and it has to come before any other call
+/// on the connection, so failing is only logged.
+fn register(connection: &gio::DBusConnection) {
+    let parameters =
+        Variant::tuple_from_iter([crate::app_id().to_variant(), VariantDict::new(None).end()]);
+    connection.call(
+        Some(PORTAL),
+        PATH,
+        REGISTRY_INTERFACE,
+        "Register",
+        Some(&parameters),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+        |result| {
+            if let Err(error) = result {
+                info!(%error, "not registered with the portal, it goes by the desktop file");
+            }
+        },
+    );
+}
+
+fn bind(
+    connection: gio::DBusConnection,
+    session: Rc<RefCell<Session>>,
+    state: AppState,
+    dispatcher: Dispatcher,
+) {
+    register(&connection);
+    let generation = session.borrow().generation;
+    let activated = connection.signal_subscribe(
+        Some(PORTAL),
+        Some(INTERFACE),
+        Some("Activated"),
+        Some(PATH),
+        None,
+        gio::DBusSignalFlags::NONE,
+        {
+            let session = session.clone();
+            move |_, _, _, _, _, parameters| {
+                let handle = parameters.child_value(0);
+                let ours = session.borrow().handle.as_deref() == handle.str();
+                let id = parameters.child_value(1);
+                let Some(shortcut) = id.str().and_then(Shortcut::of_id).filter(|_| ours) else {
+                    return;
+                };
+                info!(?shortcut, "global shortcut");
+                dispatcher.dispatch(shortcut.action());
+            }
+        },
+    );
+    {
+        let mut session = session.borrow_mut();
+        session.connection = Some(connection.clone());
+        session.activated = Some(activated);
+    }
+    let token = format!("pipeweld_{}_{generation}", std::process::id());
+    let options = VariantDict::new(None);
+    options.insert("handle_token", token.as_str());
+    options.insert("session_handle_token", token.as_str());
+    let parameters = Variant::tuple_from_iter([options.end()]);
+    request(
+        &connection,
+        &session,
+        state,
+        "CreateSession",
+        &token,
+        parameters,
+        {
+            let connection = connection.clone();
+            let session = session.clone();
+            move |results| {
+                let Ok(Some(handle)) = results.lookup::<String>("session_handle") else {
+                    return errors::report(state, WHAT, &eyre!("the portal made no session"));
+                };
+                session.borrow_mut().handle = Some(handle.clone());
+                bind_shortcuts(&connection, &session, state, &handle);
+            }
+        },
+    );
+}
+
+fn bind_shortcuts(
+    connection: &gio::DBusConnection,
+    session: &Rc<RefCell<Session>>,
+    state: AppState,
+    handle: &str,
+) {
+    let Ok(handle) = ObjectPath::try_from(handle) else {
+        return errors::report(
+            state,
+            WHAT,
+            &eyre!("the portal's session {handle:?} is no object path"),
+        );
+    };
+    let shortcuts = Shortcut::ALL.into_iter().map(|shortcut| {
+        let properties = VariantDict::new(None);
+        properties.insert("description", shortcut.label());
+        properties.insert("preferred_trigger", shortcut.trigger());
+        Variant::tuple_from_iter([shortcut.id().to_variant(), properties.end()])
+    });
+    let shortcuts = Variant::array_from_iter_with_type(
+        VariantTy::new("(sa{sv})").expect("valid type"),
+        shortcuts,
+    );
+    let token = format!("{}_bind", handle.rsplit('/').next().unwrap_or("pipeweld"));
+    let options = VariantDict::new(None);
+    options.insert("handle_token", token.as_str());
+    let parameters = Variant::tuple_from_iter([
+        handle.to_variant(),
+        shortcuts,
+        // no parent window, it may not be open
+        "".to_variant(),
+        options.end(),
+    ]);
+    request(
+        connection,
+        session,
+        state,
+        "BindShortcuts",
+        &token,
+        parameters,
+        |results| {
+            let bound = results
+                .lookup_value("shortcuts", None)
+                .map(|shortcuts| {
+                    shortcuts
+                        .iter()
+                        .filter_map(|shortcut| {
+                            let id = shortcut.child_value(0).str()?.to_owned();
+                            let trigger = VariantDict::new(Some(&shortcut.child_value(1)))
+                                .lookup::<String>("trigger_description")
+                                .ok()
+                                .flatten()
+                                .unwrap_or_default();
+                            Some((id, trigger))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            info!(?bound, "took the media keys");
+        },
+    );
+}
+
+/// Binds the media keys while [`DeviceStore::global_shortcuts`] is on, giving them
+/// back when it's turned off.
+///
+/// [`DeviceStore::global_shortcuts`]: crate::device_settings::DeviceStore::global_shortcuts
+pub fn spawn(cx: Scope, state: AppState, dispatcher: Dispatcher) {
+    let enabled = create_memo(cx, move |_| {
+        state.device_settings.with(|store| store.global_shortcuts)
+    });
+    let session = Rc::new(RefCell::new(Session::default()));
+    create_effect(cx, move |_| {
+        session.borrow_mut().close();
+        if !enabled.get() {
+            return;
+        }
+        let generation = session.borrow().generation;
+        let session = session.clone();
+        let dispatcher = dispatcher.clone();
+        gio::bus_get(
+            gio::BusType::Session,
+            gio::Cancellable::NONE,
+            move |connection| match connection {
+                Ok(connection) if session.borrow().generation == generation => {
+                    bind(connection, session, state, dispatcher)
+                }
+                Ok(_) => {}
+                Err(error) => errors::report(state, WHAT, &eyre!(error)),
+            },
+        );
+    });
+}
+
+/// Expander turning the media keys through the portal on or off.
+pub fn shortcuts_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let hint = gtk::Label::new(Some(
+        "The desktop may ask which keys to hand over. Leave this off where the desktop or \
+         another daemon already changes the volume with them.",
+    ));
+    hint.set_wrap(true);
+    hint.set_max_width_chars(60);
+    hint.add_css_class("dim-label");
+    layout.append(&hint);
+    let enabled = gtk::CheckButton::with_label(&tr("Handle the media keys while unfocused"));
+    enabled.set_active(
+        state
+            .device_settings
+            .with_untracked(|store| store.global_shortcuts),
+    );
+    enabled.connect_toggled(move |enabled| {
+        dispatcher.dispatch(Action::SetGlobalShortcuts(enabled.is_active()));
+    });
+    layout.append(&enabled);
+    let keys = gtk::Label::new(Some(
+        &Shortcut::ALL
+            .iter()
+            .map(|shortcut| format!("{}: {}", shortcut.trigger(), shortcut.label()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    ));
+    keys.set_halign(gtk::Align::Start);
+    keys.add_css_class("dim-label");
+    layout.append(&keys);
+    gtk::Expander::builder()
+        .label("Media keys")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,70 @@
+//! What pipeweld leaves behind when it quits, from the tray, on `SIGTERM` as from
+//! `systemctl --user stop`, or on `SIGINT` in a terminal. The filter processes are
+//! pipeweld's own and always stop, as do talking and listening to inputs. The
+//! modules it loaded into the server, tagged with [`crate::modules::OBJECT_TAG`],
+//! are unloaded too unless the configuration keeps them:
+//!
+//! ```toml
+//! [shutdown]
+//! remove_objects = false
+//! ```
+//!
+//! Kept ones go on playing, and are taken over rather than loaded twice when
+//! pipeweld starts again.
+use gtk::{glib, prelude::*, Application};
+use tracing::{info, warn};
+
+use crate::{config, loopback, modules::Module, state::AppState, talkback};
+
+/// Linux's numbers for them, which glib takes as they are.
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+
+/// Quits `app` on `SIGTERM` and `SIGINT`, going through its shutdown like quitting
+/// from the tray, rather than ending the process then and there.
+pub fn spawn(app: &Application) {
+    for (number, name) in [(SIGTERM, "SIGTERM"), (SIGINT, "SIGINT")] {
+        let app = app.downgrade();
+        glib::unix_signal_add_local(number, move || {
+            info!(signal = name, "quitting");
+            if let Some(app) = app.upgrade() {
+                app.quit();
+            }
+            glib::Continue(true)
+        });
+    }
+}
+
+/// Unloads pipeweld's modules, the last loaded first, as those may play into the
+/// ones before.
+fn remove_objects() {
+    let mut owned = match Module::list() {
+        Ok(modules) => modules
+            .into_iter()
+            .filter(|module| module.object().is_some())
+            .collect::<Vec<_>>(),
+        Err(error) => return warn!(?error, "listing the modules to unload"),
+    };
+    owned.sort_by_key(|module| std::cmp::Reverse(module.index));
+    for module in owned {
+        info!(object = module.object(), "unloading {}", module.name);
+        if let Err(error) = Module::unload(module.index) {
+            warn!(?error, "unloading module {}", module.index);
+        }
+    }
+}
+
+/// Cleans up on the way out, see the module's documentation.
+pub fn clean_up(state: AppState) {
+    if let Err(error) = talkback::stop(state) {
+        warn!(?error, "stopping talkback");
+    }
+    if let Err(error) = loopback::stop_listening() {
+        warn!(?error, "stopping listening to inputs");
+    }
+    state.filters.with_value(|host| host.lock().stop_all());
+    match config::get().shutdown.remove_objects {
+        true => remove_objects(),
+        false => info!("leaving pipeweld's modules loaded"),
+    }
+}
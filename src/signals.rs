@@ -0,0 +1,87 @@
+//! `SIGUSR1` and `SIGUSR2` as quick controls, for integrations that can send a
+//! signal and nothing more:
+//!
+//! ```sh
+//! pkill -USR1 pipeweld
+//! ```
+//!
+//! By default the first toggles mute and the second the window, the
+//! configuration's `[signals]` maps them to something else:
+//!
+//! ```toml
+//! [signals]
+//! usr1 = "volume-up"
+//! usr2 = "volume-down"
+//! ```
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    config,
+    devices::DeviceKind,
+    DiffValue,
+};
+
+/// Linux's numbers for them, which glib takes as they are.
+const SIGUSR1: i32 = 10;
+const SIGUSR2: i32 = 12;
+
+/// What a signal can be mapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignalAction {
+    ToggleMute,
+    ToggleMicrophoneMute,
+    VolumeUp,
+    VolumeDown,
+    NextOutput,
+    ToggleWindow,
+}
+
+impl SignalAction {
+    /// The names the configuration takes.
+    pub const NAMES: &'static [&'static str] = &[
+        "toggle-mute",
+        "toggle-microphone-mute",
+        "volume-up",
+        "volume-down",
+        "next-output",
+        "toggle-window",
+    ];
+
+    pub fn action(self) -> Action {
+        let step = config::get().steps.iter().min().copied().unwrap_or(5) as i32;
+        match self {
+            Self::ToggleMute => Action::ToggleMute(DeviceKind::Sink),
+            Self::ToggleMicrophoneMute => Action::ToggleMute(DeviceKind::Source),
+            Self::VolumeUp => Action::ChangeVolume(DiffValue(step)),
+            Self::VolumeDown => Action::ChangeVolume(DiffValue(-step)),
+            Self::NextOutput => Action::CycleDefault(DeviceKind::Sink),
+            Self::ToggleWindow => Action::ToggleWindow,
+        }
+    }
+}
+
+/// Handles both signals for the lifetime of the process. An unmapped one is only
+/// logged, rather than ending pipeweld as it would by default.
+pub fn spawn(dispatcher: Dispatcher) {
+    let signals = &config::get().signals;
+    for (number, name, mapped) in [
+        (SIGUSR1, "SIGUSR1", signals.usr1),
+        (SIGUSR2, "SIGUSR2", signals.usr2),
+    ] {
+        let dispatcher = dispatcher.clone();
+        glib::unix_signal_add_local(number, move || {
+            match mapped {
+                Some(mapped) => {
+                    info!(signal = name, ?mapped, "signal");
+                    dispatcher.dispatch(mapped.action());
+                }
+                None => warn!(signal = name, "nothing is mapped to the signal"),
+            }
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,156 @@
+//! Default devices per set of connected hardware: picking an output or an input
+//! while, say, the dock is connected remembers it for when exactly that hardware is
+//! around, and it's the default again whenever the hardware comes back together,
+//! like the speakers once only the laptop's own card is left. Devices count as
+//! hardware when a sound card or Bluetooth is behind them, so pipeweld's filters
+//! and virtual devices don't split the sets. The switching itself is
+//! [`crate::rules::SmartDefaults`].
+use std::collections::BTreeSet;
+
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::Device,
+    i18n::{tr, tr_with},
+    state::AppState,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartDefault {
+    /// Names of the hardware sinks and sources connected.
+    pub hardware: BTreeSet<String>,
+    pub sink: Option<String>,
+    pub source: Option<String>,
+}
+
+/// Whether a sound card or Bluetooth is behind `device`.
+pub fn is_hardware(device: &Device) -> bool {
+    device.properties.contains_key("device.api")
+}
+
+/// The hardware sinks and sources there are now.
+pub fn hardware(state: AppState) -> BTreeSet<String> {
+    let names = |devices: RwSignal<Vec<Device>>| {
+        devices.with_untracked(|devices| {
+            devices
+                .iter()
+                .filter(|device| is_hardware(device))
+                .map(|device| device.name.clone())
+                .collect::<Vec<_>>()
+        })
+    };
+    names(state.sinks)
+        .into_iter()
+        .chain(names(state.sources))
+        .collect()
+}
+
+/// What was learned for exactly `hardware`.
+pub fn learned<'a>(
+    smart_defaults: &'a [SmartDefault],
+    hardware: &BTreeSet<String>,
+) -> Option<&'a SmartDefault> {
+    smart_defaults
+        .iter()
+        .find(|learned| learned.hardware == *hardware)
+}
+
+/// Expander listing the learned defaults, to review and delete them.
+pub fn smart_defaults_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let hint = gtk::Label::new(Some(&tr(
+        "Picking an output or an input remembers it for the hardware connected, and \
+         picks it again once the same hardware is connected.",
+    )));
+    hint.set_wrap(true);
+    hint.set_max_width_chars(60);
+    hint.set_halign(gtk::Align::Start);
+    hint.add_css_class("dim-label");
+    layout.append(&hint);
+    let rows = gtk::Box::new(Orientation::Vertical, 4);
+    layout.append(&rows);
+
+    let learned = create_memo(cx, move |_| {
+        state
+            .device_settings
+            .with(|store| store.smart_defaults.clone())
+    });
+    create_effect(cx, move |_| {
+        while let Some(child) = rows.first_child() {
+            rows.remove(&child);
+        }
+        let learned = learned.get();
+        if learned.is_empty() {
+            let empty = gtk::Label::new(Some(&tr("Nothing learned yet.")));
+            empty.set_halign(gtk::Align::Start);
+            rows.append(&empty);
+            return;
+        }
+        // descriptions of what's around, names of the rest
+        let describe = |name: &str| {
+            [state.sinks, state.sources]
+                .into_iter()
+                .find_map(|devices| {
+                    devices.with_untracked(|devices| {
+                        devices
+                            .iter()
+                            .find(|device| device.name == name)
+                            .map(|device| device.description.clone())
+                    })
+                })
+                .unwrap_or_else(|| name.to_owned())
+        };
+        for entry in learned {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            let text = gtk::Box::new(Orientation::Vertical, 2);
+            text.set_hexpand(true);
+            let picked = [entry.sink.as_deref(), entry.source.as_deref()]
+                .into_iter()
+                .flatten()
+                .map(describe)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let label = gtk::Label::new(Some(&picked));
+            label.set_halign(gtk::Align::Start);
+            label.set_wrap(true);
+            text.append(&label);
+            let hardware = entry
+                .hardware
+                .iter()
+                .map(|name| describe(name))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let with = gtk::Label::new(Some(&tr_with(
+                "With {hardware}",
+                &[("hardware", &hardware)],
+            )));
+            with.set_halign(gtk::Align::Start);
+            with.set_wrap(true);
+            with.add_css_class("dim-label");
+            with.add_css_class("caption");
+            text.append(&with);
+            row.append(&text);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            remove.set_tooltip_text(Some(&tr("Forget")));
+            remove.set_valign(gtk::Align::Center);
+            let dispatcher = dispatcher.clone();
+            remove.connect_clicked(move |_| {
+                dispatcher.dispatch(Action::ForgetSmartDefault(entry.hardware.clone()))
+            });
+            row.append(&remove);
+            rows.append(&row);
+        }
+    });
+
+    gtk::Expander::builder()
+        .label("Smart defaults")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,265 @@
+//! Speaker test for checking surround wiring: a short burst of pink noise plays on
+//! one channel of a sink at a time, front left, front right and so on, by handing
+//! `paplay` a mono file mapped to that single position.
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    process::{Child, Command},
+    rc::Rc,
+    time::Duration,
+};
+
+use eyre::{Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::instrument;
+
+use crate::{
+    av_sync::{self, SAMPLE_RATE},
+    devices,
+    i18n::{tr, tr_with},
+    modules::Module,
+    state::AppState,
+    ui_state,
+};
+
+/// Length and level of a burst, in dBFS.
+const BURST: Duration = Duration::from_millis(1200);
+const BURST_DB: f64 = -18.;
+/// Silence between channels when testing them all.
+const GAP: Duration = Duration::from_millis(400);
+/// Fade in and out, against clicks.
+const FADE: Duration = Duration::from_millis(20);
+
+/// A mono WAV of [`BURST`] of pink noise at [`BURST_DB`], white noise from an
+/// xorshift filtered down by 3 dB an octave after Paul Kellet.
+fn burst() -> Vec<u8> {
+    let length = (f64::from(SAMPLE_RATE) * BURST.as_secs_f64()) as usize;
+    let fade = (f64::from(SAMPLE_RATE) * FADE.as_secs_f64()) as usize;
+    let amplitude = 10f64.powf(BURST_DB / 20.) * f64::from(i16::MAX);
+    let mut seed = 0x2545_f491_u32;
+    let mut poles = [0f64; 3];
+    let samples = (0..length)
+        .map(|sample| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let white = f64::from(seed) / f64::from(u32::MAX) * 2. - 1.;
+            poles[0] = 0.99765 * poles[0] + white * 0.0990460;
+            poles[1] = 0.96300 * poles[1] + white * 0.2965164;
+            poles[2] = 0.57000 * poles[2] + white * 1.0526913;
+            // the filter's gain comes to about 4 for full-scale noise
+            let pink = (poles.iter().sum::<f64>() + white * 0.1848) / 4.;
+            let envelope = (sample.min(length - sample) as f64 / fade as f64).min(1.);
+            (pink.clamp(-1., 1.) * envelope * amplitude) as i16
+        })
+        .collect::<Vec<_>>();
+    av_sync::wav(&samples)
+}
+
+fn burst_path() -> PathBuf {
+    glib::user_runtime_dir()
+        .join(clap::crate_name!())
+        .join("speaker-test.wav")
+}
+
+/// Plays the burst on `sink`'s channel at `position`, like "front-left".
+#[instrument(ret, err)]
+pub fn play(sink: &str, position: &str) -> Result<Child> {
+    let path = burst_path();
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&path, burst()).wrap_err_with(|| format!("writing {}", path.display()))?;
+    }
+    Command::new("paplay")
+        .arg(format!("--device={sink}"))
+        .arg(format!("--channel-map={position}"))
+        .arg(Module::stream_tag("speaker-test"))
+        .arg(&path)
+        .spawn()
+        .wrap_err_with(|| format!("playing the test burst on {position}"))
+}
+
+/// Like "Front left" for "front-left".
+pub fn position_label(position: &str) -> String {
+    let spaced = position.replace('-', " ");
+    let mut characters = spaced.chars();
+    characters
+        .next()
+        .map(|first| first.to_uppercase().chain(characters).collect())
+        .unwrap_or_default()
+}
+
+/// The burst now playing, and the channels still to go when testing them all.
+#[derive(Default)]
+struct Playing {
+    child: Option<Child>,
+    queue: Vec<String>,
+    /// Counts bursts and stops, for telling whether a burst is still the latest.
+    round: u32,
+}
+
+impl Playing {
+    fn stop(&mut self) {
+        self.round += 1;
+        self.queue.clear();
+        if let Some(mut child) = self.child.take() {
+            child.kill().and_then(|_| child.wait()).ok();
+        }
+    }
+}
+
+/// Plays the first channel of the queue on `sink`, then, after [`GAP`], the next.
+fn play_next(playing: &Rc<RefCell<Playing>>, sink: &str, status: &gtk::Label) {
+    let Some(position) = ({
+        let mut playing = playing.borrow_mut();
+        (!playing.queue.is_empty()).then(|| playing.queue.remove(0))
+    }) else {
+        return status.set_text("");
+    };
+    let round = match play(sink, &position) {
+        Ok(child) => {
+            let mut playing = playing.borrow_mut();
+            playing.child = Some(child);
+            playing.round += 1;
+            status.set_text(&tr_with(
+                "Playing on {position}…",
+                &[("position", &position_label(&position))],
+            ));
+            playing.round
+        }
+        Err(_) => {
+            playing.borrow_mut().stop();
+            return status.set_text(&tr("The test burst couldn't be played."));
+        }
+    };
+    let weak = Rc::downgrade(playing);
+    let (sink, status) = (sink.to_owned(), status.clone());
+    glib::timeout_add_local_once(BURST + GAP, move || {
+        let Some(playing) = weak.upgrade() else {
+            return;
+        };
+        // stopped, or another test took over, in the meantime
+        if playing.borrow().round != round {
+            return;
+        }
+        if let Some(mut child) = playing.borrow_mut().child.take() {
+            child.kill().and_then(|_| child.wait()).ok();
+        }
+        play_next(&playing, &sink, &status);
+    });
+}
+
+/// Expander with a sink picker and a button per channel of the sink, plus one
+/// going through them all. Sinks are kept listed as they come and go, the one tested
+/// last picked to begin with, or else the default.
+pub fn speaker_test_panel(cx: Scope, state: AppState) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let sink = gtk::DropDown::from_strings(&[]);
+    sink.set_tooltip_text(Some(&tr("Output to test")));
+    layout.append(&sink);
+    let buttons = gtk::FlowBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .max_children_per_line(4)
+        .column_spacing(6)
+        .row_spacing(6)
+        .build();
+    layout.append(&buttons);
+    let all = gtk::Button::with_label(&tr("Test all channels"));
+    all.set_halign(gtk::Align::Start);
+    layout.append(&all);
+    let status = gtk::Label::new(None);
+    status.set_halign(gtk::Align::Start);
+    status.add_css_class("dim-label");
+    layout.append(&status);
+
+    let listed = devices::keep_listed(
+        cx,
+        &sink,
+        move || state.sinks.get(),
+        |device| (device.name.clone(), device.description.clone()),
+        move |found| {
+            ui_state::last_picked(state, found.iter().map(|device| device.name.as_str()))
+                .map(|position| position as usize)
+                .or_else(|| {
+                    let default = state
+                        .default_sink
+                        .with_untracked(|sink| sink.as_ref().map(|sink| sink.name.clone()))?;
+                    found.iter().position(|device| device.name == default)
+                })
+        },
+    );
+    let playing = Rc::new(RefCell::new(Playing::default()));
+    // starts over on the sink picked with `positions`
+    let start = {
+        let playing = playing.clone();
+        let (sink, status) = (sink.clone(), status.clone());
+        move |positions: Vec<String>| {
+            let Some(device) =
+                listed.with_untracked(|listed| listed.get(sink.selected() as usize).cloned())
+            else {
+                return;
+            };
+            ui_state::set_last_picked(state, &device.name);
+            let mut stopped = playing.borrow_mut();
+            stopped.stop();
+            stopped.queue = positions;
+            drop(stopped);
+            play_next(&playing, &device.name, &status);
+        }
+    };
+    let rebuild = {
+        let start = start.clone();
+        move |sink: &gtk::DropDown| {
+            while let Some(child) = buttons.first_child() {
+                buttons.remove(&child);
+            }
+            let Some(device) =
+                listed.with_untracked(|listed| listed.get(sink.selected() as usize).cloned())
+            else {
+                return;
+            };
+            for (position, _) in device.channels() {
+                let button = gtk::Button::with_label(&position_label(&position));
+                let start = start.clone();
+                button.connect_clicked(move |_| start(vec![position.clone()]));
+                buttons.insert(&button, -1);
+            }
+        }
+    };
+    sink.connect_selected_notify(rebuild);
+    all.connect_clicked({
+        let sink = sink.clone();
+        move |_| {
+            let positions = listed
+                .with_untracked(|listed| listed.get(sink.selected() as usize).cloned())
+                .map(|device| {
+                    device
+                        .channels()
+                        .into_iter()
+                        .map(|(position, _)| position)
+                        .collect()
+                })
+                .unwrap_or_default();
+            start(positions);
+        }
+    });
+
+    let expander = gtk::Expander::builder()
+        .label("Test speakers")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    expander.connect_expanded_notify(move |expander| {
+        if !expander.is_expanded() {
+            playing.borrow_mut().stop();
+            status.set_text("");
+        }
+    });
+    expander
+}
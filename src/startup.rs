@@ -0,0 +1,131 @@
+//! Actions to run whenever pipeweld starts, listed in the device settings, like
+//! the default sink or a few virtual sinks of one's own:
+//!
+//! ```toml
+//! [[startup]]
+//! action = "create-sink"
+//! name = "music"
+//! description = "Music"
+//!
+//! [[startup]]
+//! action = "set-card-profile"
+//! card = "alsa_card.pci-0000_00_1f.3"
+//! profile = "output:analog-stereo"
+//!
+//! [[startup]]
+//! action = "set-default"
+//! kind = "sink"
+//! device = "music"
+//! ```
+use std::time::Duration;
+
+use eyre::Result;
+use gtk::glib;
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    modules::Module,
+    state::AppState,
+    AudioControls,
+};
+
+/// Prefix of the object name of sinks made at startup, `sink:<name>`.
+const SINK: &str = "sink";
+
+/// How often to look for the server while it isn't up yet, as when pipewire and
+/// pipeweld start together with the session.
+const RETRY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum StartupAction {
+    SetDefault {
+        kind: DeviceKind,
+        device: String,
+    },
+    SetVolume {
+        kind: DeviceKind,
+        device: String,
+        percent: u32,
+    },
+    SetMute {
+        kind: DeviceKind,
+        device: String,
+        mute: bool,
+    },
+    SetCardProfile {
+        card: String,
+        profile: String,
+    },
+    /// A virtual sink called `name`, to route applications to.
+    CreateSink {
+        name: String,
+        description: Option<String>,
+    },
+}
+
+impl StartupAction {
+    pub fn action(&self) -> Action {
+        match self.clone() {
+            Self::SetDefault { kind, device } => Action::SetDefault(kind, device),
+            Self::SetVolume {
+                kind,
+                device,
+                percent,
+            } => Action::SetVolume(kind, device, percent),
+            Self::SetMute { kind, device, mute } => Action::SetMute(kind, device, mute),
+            Self::SetCardProfile { card, profile } => Action::SetCardProfile { card, profile },
+            Self::CreateSink { name, description } => Action::CreateSink { name, description },
+        }
+    }
+}
+
+/// Loads a null sink called `name`, unless there already is a sink called that.
+#[instrument(ret, err)]
+pub fn create_sink(name: &str, description: Option<&str>) -> Result<()> {
+    if Device::list(DeviceKind::Sink)?
+        .iter()
+        .any(|sink| sink.name == name)
+    {
+        return Ok(());
+    }
+    let description = description.unwrap_or(name).replace(['\'', '"'], "");
+    Module::load(
+        "module-null-sink",
+        &[
+            format!("sink_name={name}"),
+            format!(
+                "sink_properties=\"device.description='{description}' {}\"",
+                Module::tag(&format!("{SINK}:{name}"))
+            ),
+        ],
+    )
+    .map(drop)
+}
+
+/// Dispatches the startup actions as soon as the server answers.
+pub fn spawn(state: AppState, dispatcher: Dispatcher) {
+    let actions = state
+        .device_settings
+        .with_untracked(|store| store.startup.clone());
+    if actions.is_empty() {
+        return;
+    }
+    let run = move || {
+        if !AudioControls::server_available() {
+            return glib::Continue(true);
+        }
+        info!(count = actions.len(), "running startup actions");
+        for action in &actions {
+            dispatcher.dispatch(action.action());
+        }
+        glib::Continue(false)
+    };
+    if run().0 {
+        glib::timeout_add_local(RETRY, run);
+    }
+}
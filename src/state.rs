@@ -0,0 +1,418 @@
+use std::time::Duration;
+
+use eyre::Result;
+
+use gtk::glib;
+use leptos::*;
+use tracing::warn;
+
+use crate::{
+    audible::Finding,
+    backend,
+    captures::Capture,
+    compositor::{Window, Workspace},
+    device_settings::DeviceStore,
+    devices::{Device, DeviceKind},
+    easyeffects,
+    errors::Failure,
+    events::{Event, Facility},
+    filter_chain::SharedHost,
+    grace::Departed,
+    hold::Hold,
+    http_stream::HttpStream,
+    levels::Clipping,
+    loopback::Loopback,
+    midi::MidiTarget,
+    noise_suppression::NoiseSuppression,
+    osd::OsdStyle,
+    passthrough::{self, Codec},
+    routing,
+    streams::Stream,
+    talkback::Talkback,
+    timeline::Recording,
+    ui_state::UiState,
+    AudioControls,
+};
+
+/// How often the state is checked against the server, see [`AppState::reconcile`].
+pub const RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Prints a piece of state as it is now.
+pub type Printer = Box<dyn Fn() -> String>;
+
+/// Application-wide reactive state, shared by the window, the tray and the action dispatcher.
+#[derive(Debug, Clone, Copy)]
+pub struct AppState {
+    /// Suppresses volume popups and notifications while set.
+    pub do_not_disturb: RwSignal<bool>,
+    /// Compresses the default output's dynamic range while set.
+    pub night_mode: RwSignal<bool>,
+    pub osd_style: RwSignal<OsdStyle>,
+    pub default_sink: RwSignal<Option<Device>>,
+    pub default_source: RwSignal<Option<Device>>,
+    /// Every sink and every source, as they come and go and change.
+    pub sinks: RwSignal<Vec<Device>>,
+    pub sources: RwSignal<Vec<Device>>,
+    pub device_settings: RwSignal<DeviceStore>,
+    pub loopbacks: RwSignal<Vec<Loopback>>,
+    /// Applications recording from a microphone, see [`crate::captures`].
+    pub captures: RwSignal<Vec<Capture>>,
+    /// The filter in front of the microphone, see [`crate::noise_suppression`].
+    pub noise_suppression: RwSignal<Option<NoiseSuppression>>,
+    /// Names of the saved routing presets, see [`crate::routing`].
+    pub routings: RwSignal<Vec<String>>,
+    pub filters: StoredValue<SharedHost>,
+    /// Set while the default sink passes compressed audio through, which has no volume.
+    pub passthrough: RwSignal<Option<Codec>>,
+    pub http_stream: StoredValue<Option<HttpStream>>,
+    /// Whether EasyEffects' virtual devices are present.
+    pub easyeffects: RwSignal<bool>,
+    /// Port the output is served on over HTTP, if it is.
+    pub http_stream_port: RwSignal<Option<u16>>,
+    /// Focused workspace, under compositors pipeweld can talk to.
+    pub workspace: RwSignal<Option<Workspace>>,
+    /// Whether the focused window covers its whole display, like games do.
+    pub fullscreen: RwSignal<bool>,
+    pub focused_window: RwSignal<Option<Window>>,
+    pub recording: StoredValue<Recording>,
+    /// What plays, as found on request, see [`crate::audible`].
+    pub finding: RwSignal<Finding>,
+    /// Rules caught reacting to each other in a loop, see [`crate::rules`].
+    pub disabled_rules: RwSignal<Vec<&'static str>>,
+    /// Sustained clipping on the default output, see [`crate::levels::watch_clipping`].
+    pub clipping: RwSignal<Option<Clipping>>,
+    /// Whether rules are kept from switching devices, see [`crate::hold`].
+    pub hold: RwSignal<Hold>,
+    /// Outputs that went away within their grace period, see [`crate::grace`].
+    pub departed: RwSignal<Vec<Departed>>,
+    /// Recent failures shown to the user, see [`crate::errors`].
+    pub errors: RwSignal<Vec<Failure>>,
+    /// Whether the machine runs on battery, see [`crate::power`].
+    pub on_battery: RwSignal<bool>,
+    /// Talking through the talkback button, see [`crate::talkback`].
+    pub talkback: RwSignal<Option<Talkback>>,
+    /// How the window was left, see [`crate::ui_state`].
+    pub ui: RwSignal<UiState>,
+    /// What the next MIDI control moved gets mapped to, see [`crate::midi`].
+    pub midi_learning: RwSignal<Option<MidiTarget>>,
+}
+
+impl AppState {
+    pub fn new(cx: Scope) -> Self {
+        let state = Self {
+            do_not_disturb: create_rw_signal(cx, false),
+            night_mode: create_rw_signal(cx, false),
+            osd_style: create_rw_signal(cx, OsdStyle::default()),
+            default_sink: create_rw_signal(cx, None),
+            default_source: create_rw_signal(cx, None),
+            sinks: create_rw_signal(cx, Vec::new()),
+            sources: create_rw_signal(cx, Vec::new()),
+            device_settings: create_rw_signal(
+                cx,
+                DeviceStore::load()
+                    .map_err(|error| warn!(?error, "starting without device settings"))
+                    .unwrap_or_default(),
+            ),
+            loopbacks: create_rw_signal(cx, Vec::new()),
+            captures: create_rw_signal(cx, Vec::new()),
+            noise_suppression: create_rw_signal(cx, None),
+            routings: create_rw_signal(
+                cx,
+                routing::saved()
+                    .map_err(|error| warn!(?error, "listing routing presets"))
+                    .unwrap_or_default(),
+            ),
+            filters: store_value(cx, SharedHost::default()),
+            passthrough: create_rw_signal(cx, None),
+            http_stream: store_value(cx, None),
+            easyeffects: create_rw_signal(cx, false),
+            http_stream_port: create_rw_signal(cx, None),
+            workspace: create_rw_signal(cx, None),
+            fullscreen: create_rw_signal(cx, false),
+            focused_window: create_rw_signal(cx, None),
+            recording: store_value(cx, Recording::default()),
+            finding: create_rw_signal(cx, Finding::default()),
+            disabled_rules: create_rw_signal(cx, Vec::new()),
+            clipping: create_rw_signal(cx, None),
+            hold: create_rw_signal(cx, Hold::Off),
+            departed: create_rw_signal(cx, Vec::new()),
+            errors: create_rw_signal(cx, Vec::new()),
+            on_battery: create_rw_signal(cx, false),
+            talkback: create_rw_signal(cx, None),
+            ui: create_rw_signal(
+                cx,
+                UiState::load()
+                    .map_err(|error| warn!(?error, "starting without the window's state"))
+                    .unwrap_or_default(),
+            ),
+            midi_learning: create_rw_signal(cx, None),
+        };
+        state.refresh_now(&Part::ALL);
+        state
+    }
+
+    pub fn default_device(&self, kind: DeviceKind) -> RwSignal<Option<Device>> {
+        match kind {
+            DeviceKind::Sink => self.default_sink,
+            DeviceKind::Source => self.default_source,
+        }
+    }
+
+    pub fn devices(&self, kind: DeviceKind) -> RwSignal<Vec<Device>> {
+        match kind {
+            DeviceKind::Sink => self.sinks,
+            DeviceKind::Source => self.sources,
+        }
+    }
+
+    /// Every signal and stored value by name, with a way to print its current value
+    /// for [`crate::inspector`], apart from the recording of it. Printing a signal
+    /// subscribes the running effect to it, stored values have to be printed again
+    /// to see them change.
+    pub fn inspect(&self) -> Vec<(&'static str, Printer)> {
+        let state = *self;
+        macro_rules! fields {
+            (signals: $($signal:ident),*; stored: $($stored:ident),*) => {
+                vec![
+                    $((
+                        stringify!($signal),
+                        Box::new(move || state.$signal.with(|value| format!("{value:#?}")))
+                            as Printer,
+                    ),)*
+                    $((
+                        stringify!($stored),
+                        Box::new(move || state.$stored.with_value(|value| format!("{value:#?}")))
+                            as Printer,
+                    ),)*
+                ]
+            };
+        }
+        fields!(
+            signals: do_not_disturb, night_mode, osd_style, default_sink, default_source, sinks, sources,
+                device_settings, loopbacks, captures, noise_suppression, routings, passthrough, easyeffects, http_stream_port, workspace,
+                fullscreen, focused_window, finding, disabled_rules, clipping, hold, departed, on_battery, talkback, ui,
+                midi_learning;
+            stored: filters, http_stream
+        )
+    }
+
+    /// The device of `kind` called `name`, or the default one for
+    /// [`DeviceKind::default_alias`].
+    pub fn device_named(&self, kind: DeviceKind, name: &str) -> Option<Device> {
+        match name == kind.default_alias() {
+            true => self.default_device(kind).get_untracked(),
+            false => self.devices(kind).with_untracked(|devices| {
+                devices.iter().find(|device| device.name == name).cloned()
+            }),
+        }
+    }
+
+    /// Volume limit of the current default sink, if the user set one.
+    pub fn default_sink_max_volume(&self) -> Option<u32> {
+        self.default_sink.with_untracked(|sink| {
+            sink.as_ref().and_then(|sink| {
+                self.device_settings
+                    .with_untracked(|store| store.max_volume(sink))
+            })
+        })
+    }
+
+    /// Re-reads `parts` from the server without blocking the main loop, then calls
+    /// `done` with the names of those that changed.
+    pub fn refresh(&self, parts: &'static [Part], done: impl FnOnce(Vec<&'static str>) + 'static) {
+        let state = *self;
+        let sink = self.default_sink_index();
+        AudioControls::in_background(
+            move || Ok(read(parts, sink)),
+            move |readings| {
+                let changed = readings
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(part, reading)| state.store(part, reading).then_some(part.name()))
+                    .collect();
+                done(changed);
+            },
+        );
+    }
+
+    /// [`Self::refresh`] on the spot, for before the main loop runs.
+    pub fn refresh_now(&self, parts: &[Part]) -> Vec<&'static str> {
+        read(parts, self.default_sink_index())
+            .into_iter()
+            .filter_map(|(part, reading)| self.store(part, reading).then_some(part.name()))
+            .collect()
+    }
+
+    fn default_sink_index(&self) -> Option<u32> {
+        self.default_sink
+            .with_untracked(|sink| sink.as_ref().map(|sink| sink.index))
+    }
+
+    /// Keeps what was read, returning whether it changed anything. Events arrive
+    /// for every little change, only dependents of real ones get woken up.
+    fn store(&self, part: Part, reading: Result<Reading>) -> bool {
+        fn update<T: PartialEq>(signal: RwSignal<T>, value: T) -> bool {
+            let changed = signal.with_untracked(|current| *current != value);
+            if changed {
+                signal.set(value);
+            }
+            changed
+        }
+        match reading {
+            Ok(Reading::Default(kind, device)) => update(self.default_device(kind), device),
+            Ok(Reading::Devices(kind, devices)) => update(self.devices(kind), devices),
+            Ok(Reading::Loopbacks(loopbacks)) => update(self.loopbacks, loopbacks),
+            Ok(Reading::Captures(captures)) => update(self.captures, captures),
+            Ok(Reading::NoiseSuppression(found)) => update(self.noise_suppression, found),
+            Ok(Reading::Passthrough(codec)) => update(self.passthrough, codec),
+            Ok(Reading::EasyEffects(running)) => update(self.easyeffects, running),
+            Err(error) => {
+                warn!(?error, "refreshing {}", part.name());
+                false
+            }
+        }
+    }
+
+    /// Re-reads everything events keep up to date, in case some went missing, say
+    /// while the subscription was down. What was out of date gets logged.
+    pub fn reconcile(&self) {
+        self.refresh(&Part::ALL, |stale| {
+            if !stale.is_empty() {
+                warn!(?stale, "state was out of date, events got lost");
+            }
+        });
+    }
+
+    /// Runs [`Self::reconcile`] every [`RECONCILE_INTERVAL`].
+    pub fn spawn_reconciliation(self) {
+        glib::timeout_add_local(RECONCILE_INTERVAL, move || {
+            self.reconcile();
+            glib::Continue(true)
+        });
+    }
+
+    /// Brings the state up to date after a server event, then calls `done`. Events
+    /// are read in the order they came, so `done` sees the state as of `event`.
+    pub fn apply(&self, event: Event, done: impl FnOnce() + 'static) {
+        self.refresh(Part::changed_by(event), move |_| done());
+    }
+}
+
+/// A piece of the state that mirrors the server, see [`AppState::refresh`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    Default(DeviceKind),
+    Devices(DeviceKind),
+    Loopbacks,
+    Captures,
+    NoiseSuppression,
+    Passthrough,
+    EasyEffects,
+}
+
+impl Part {
+    /// Every part, the default sink ahead of the passthrough that goes by it.
+    pub const ALL: [Self; 9] = [
+        Self::Default(DeviceKind::Sink),
+        Self::Default(DeviceKind::Source),
+        Self::Devices(DeviceKind::Sink),
+        Self::Devices(DeviceKind::Source),
+        Self::Loopbacks,
+        Self::Captures,
+        Self::NoiseSuppression,
+        Self::Passthrough,
+        Self::EasyEffects,
+    ];
+
+    /// What `event` may have changed.
+    pub fn changed_by(event: Event) -> &'static [Self] {
+        match event.facility {
+            Facility::Sink => &[
+                Self::Default(DeviceKind::Sink),
+                Self::Devices(DeviceKind::Sink),
+                Self::Passthrough,
+                Self::EasyEffects,
+            ],
+            Facility::Source => &[
+                Self::Default(DeviceKind::Source),
+                Self::Devices(DeviceKind::Source),
+                Self::Captures,
+            ],
+            Facility::SourceOutput => &[Self::Captures],
+            // the default devices changed, or a profile switch replaced them
+            Facility::Server | Facility::Card => &[
+                Self::Default(DeviceKind::Sink),
+                Self::Default(DeviceKind::Source),
+                Self::Devices(DeviceKind::Sink),
+                Self::Devices(DeviceKind::Source),
+                Self::Passthrough,
+            ],
+            Facility::Module => &[Self::Loopbacks, Self::NoiseSuppression],
+            Facility::SinkInput => &[Self::Loopbacks, Self::Passthrough],
+            _ => &[],
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Default(DeviceKind::Sink) => "default sink",
+            Self::Default(DeviceKind::Source) => "default source",
+            Self::Devices(DeviceKind::Sink) => "sinks",
+            Self::Devices(DeviceKind::Source) => "sources",
+            Self::Loopbacks => "loopbacks",
+            Self::Captures => "recordings",
+            Self::NoiseSuppression => "noise suppression",
+            Self::Passthrough => "passthrough",
+            Self::EasyEffects => "EasyEffects",
+        }
+    }
+}
+
+/// A [`Part`] as read from the server, only around until it's stored.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+enum Reading {
+    Default(DeviceKind, Option<Device>),
+    Devices(DeviceKind, Vec<Device>),
+    Loopbacks(Vec<Loopback>),
+    Captures(Vec<Capture>),
+    NoiseSuppression(Option<NoiseSuppression>),
+    Passthrough(Option<Codec>),
+    EasyEffects(bool),
+}
+
+/// Reads `parts`, which blocks on the server. Passthrough goes by the default
+/// sink read along with it, or `sink` when that isn't.
+fn read(parts: &[Part], mut sink: Option<u32>) -> Vec<(Part, Result<Reading>)> {
+    let passthrough = |sink: Option<u32>| -> Result<Option<Codec>> {
+        let Some(sink) = sink else {
+            return Ok(None);
+        };
+        Ok(Stream::list()?
+            .iter()
+            .filter(|stream| stream.sink == sink)
+            .find_map(passthrough::of_stream))
+    };
+    parts
+        .iter()
+        .map(|&part| {
+            let reading = match part {
+                Part::Default(kind) => backend::current().default_of(kind).map(|device| {
+                    if kind == DeviceKind::Sink {
+                        sink = device.as_ref().map(|device| device.index);
+                    }
+                    Reading::Default(kind, device)
+                }),
+                Part::Devices(kind) => backend::current()
+                    .list(kind)
+                    .map(|devices| Reading::Devices(kind, devices)),
+                Part::Loopbacks => Loopback::list().map(Reading::Loopbacks),
+                Part::Captures => Capture::list().map(Reading::Captures),
+                Part::NoiseSuppression => NoiseSuppression::find().map(Reading::NoiseSuppression),
+                Part::Passthrough => passthrough(sink).map(Reading::Passthrough),
+                Part::EasyEffects => easyeffects::is_running().map(Reading::EasyEffects),
+            };
+            (part, reading)
+        })
+        .collect()
+}
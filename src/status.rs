@@ -0,0 +1,137 @@
+//! `pipeweld status`, the default device's volume as a JSON line shaped for the
+//! custom modules of status bars, and with `--follow` another line whenever it
+//! changes, driven by the server's events rather than polling:
+//!
+//! ```json
+//! "custom/volume": {
+//!     "exec": "pipeweld status --follow",
+//!     "return-type": "json",
+//!     "format": "{icon} {}",
+//!     "format-icons": { "muted": "", "low": "", "medium": "", "high": "" }
+//! }
+//! ```
+//!
+//! Waybar's modules read `text`, `alt`, `tooltip`, `class` and `percentage`,
+//! i3status-rust's `text`, `icon` and `state`, see [`Shape`].
+use std::io::Write;
+
+use clap::ValueEnum;
+use eyre::Result;
+use gtk::glib;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::{
+    devices::{Device, DeviceKind},
+    events::{self, Facility},
+};
+
+/// Which status bar a line is for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Shape {
+    /// Also read by most bars taking the same JSON, like ironbar's.
+    #[default]
+    Waybar,
+    I3status,
+}
+
+/// How loud, as the volume icons go.
+fn level(device: &Device) -> &'static str {
+    match (device.mute, device.volume_percent()) {
+        (true, _) | (_, 0) => "muted",
+        (_, 1..=33) => "low",
+        (_, 34..=66) => "medium",
+        _ => "high",
+    }
+}
+
+fn line(kind: DeviceKind, device: Option<&Device>, shape: Shape) -> Value {
+    let Some(device) = device else {
+        let tooltip = format!("There is no default {}", kind.noun());
+        return match shape {
+            Shape::Waybar => json!({ "text": "", "tooltip": tooltip, "class": "unavailable" }),
+            Shape::I3status => json!({ "text": "", "icon": "", "state": "Idle" }),
+        };
+    };
+    let percent = device.volume_percent();
+    let level = level(device);
+    let text = match device.mute {
+        true => "muted".to_owned(),
+        false => format!("{percent}%"),
+    };
+    match shape {
+        Shape::Waybar => json!({
+            "text": text,
+            "alt": level,
+            "tooltip": format!("{}: {text}", device.description),
+            "class": level,
+            "percentage": percent,
+        }),
+        Shape::I3status => {
+            let icon = match (kind, level) {
+                (DeviceKind::Sink, "muted") => "volume_muted",
+                (DeviceKind::Sink, "low") => "volume_empty",
+                (DeviceKind::Sink, "medium") => "volume_half",
+                (DeviceKind::Sink, _) => "volume_full",
+                (DeviceKind::Source, "muted") => "microphone_muted",
+                (DeviceKind::Source, _) => "microphone",
+            };
+            json!({
+                "text": text,
+                "icon": icon,
+                "state": match device.mute {
+                    true => "Warning",
+                    false => "Idle",
+                },
+            })
+        }
+    }
+}
+
+/// Prints the line for the default device of `kind`, then with `follow` a line
+/// each time it changes, until the bar goes away.
+pub fn run(kind: DeviceKind, shape: Shape, follow: bool) -> Result<()> {
+    let current = move || Device::default_of(kind).map(|device| line(kind, device.as_ref(), shape));
+    let first = current()?;
+    println!("{first}");
+    if !follow {
+        return Ok(());
+    }
+    let own = match kind {
+        DeviceKind::Sink => Facility::Sink,
+        DeviceKind::Source => Facility::Source,
+    };
+    let main_loop = glib::MainLoop::new(None, false);
+    let mut last = first.to_string();
+    events::subscribe({
+        let main_loop = main_loop.clone();
+        move |event| {
+            // the server's events are about the defaults changing
+            if event.facility != own && event.facility != Facility::Server {
+                return;
+            }
+            let next = match current() {
+                Ok(next) => next.to_string(),
+                // a server going away leaves the bar empty until it's back
+                Err(error) => {
+                    warn!(?error, "reading the default {}", kind.noun());
+                    line(kind, None, shape).to_string()
+                }
+            };
+            if next == last {
+                return;
+            }
+            let mut stdout = std::io::stdout().lock();
+            // stdout is gone along with the bar reading it
+            if writeln!(stdout, "{next}")
+                .and_then(|()| stdout.flush())
+                .is_err()
+            {
+                main_loop.quit();
+            }
+            last = next;
+        }
+    });
+    main_loop.run();
+    Ok(())
+}
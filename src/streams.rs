@@ -0,0 +1,189 @@
+//! Playback streams, i.e. what `pactl` calls sink inputs.
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    devices::{ChannelVolume, VOLUME_NORM},
+    tool_output, AudioControls,
+};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Stream {
+    pub index: u32,
+    pub sink: u32,
+    pub mute: bool,
+    #[serde(default)]
+    pub volume: BTreeMap<String, ChannelVolume>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+    #[serde(default)]
+    pub format: String,
+    /// As printed by `pactl`, like `float32le 2ch 48000Hz`.
+    #[serde(default)]
+    pub sample_specification: String,
+    #[serde(default)]
+    pub channel_map: String,
+}
+
+/// The format a stream negotiated with the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleSpec {
+    /// Like `s16le` or `float32le`.
+    pub format: String,
+    pub channels: u32,
+    pub rate: u32,
+}
+
+impl FromStr for SampleSpec {
+    type Err = eyre::Report;
+
+    /// Parses what `pactl` prints, like `s16le 2ch 44100Hz`.
+    fn from_str(spec: &str) -> Result<Self> {
+        let parse = || {
+            let mut parts = spec.split_whitespace();
+            let format = parts.next()?.to_owned();
+            let channels = parts.next()?.strip_suffix("ch")?.parse().ok()?;
+            let rate = parts.next()?.strip_suffix("Hz")?.parse().ok()?;
+            Some(SampleSpec {
+                format,
+                channels,
+                rate,
+            })
+        };
+        parse().ok_or_else(|| eyre!("unexpected sample specification {spec:?}"))
+    }
+}
+
+impl std::fmt::Display for SampleSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, {} channels, {} kHz",
+            self.format,
+            self.channels,
+            f64::from(self.rate) / 1000.
+        )
+    }
+}
+
+impl Stream {
+    pub fn list() -> Result<Vec<Self>> {
+        let output = AudioControls::pactl(["--format=json", "list", "sink-inputs"])?;
+        tool_output::json_list(&output, "sink inputs")
+    }
+
+    /// What the stream is called: the application, and what it plays.
+    pub fn title(&self) -> String {
+        let property = |key| self.properties.get(key).map(String::as_str);
+        match (property("application.name"), property("media.name")) {
+            (Some(application), Some(media)) if application != media => {
+                format!("{application}: {media}")
+            }
+            (Some(name), _) | (None, Some(name)) => name.to_owned(),
+            (None, None) => format!("Stream #{}", self.index),
+        }
+    }
+
+    pub fn sample_spec(&self) -> Option<SampleSpec> {
+        self.sample_specification.parse().ok()
+    }
+
+    /// Format, channels and rate, and the channel layout, for showing.
+    pub fn format_description(&self) -> String {
+        match (self.sample_spec(), self.channel_map.as_str()) {
+            (None, _) => "Format unknown".to_owned(),
+            (Some(spec), "") => spec.to_string(),
+            (Some(spec), map) => format!("{spec}\n{}", map.replace(',', ", ")),
+        }
+    }
+
+    /// Volume of the loudest channel, in percent.
+    pub fn volume_percent(&self) -> u32 {
+        let loudest = self
+            .volume
+            .values()
+            .map(|channel| channel.value)
+            .max()
+            .unwrap_or(0);
+        ((u64::from(loudest) * 100 + u64::from(VOLUME_NORM) / 2) / u64::from(VOLUME_NORM)) as u32
+    }
+}
+
+/// What a [`Transaction`] changes about a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamChange {
+    Volume(u32),
+    Mute(bool),
+    /// To a sink, by name or index.
+    Move(String),
+}
+
+impl StreamChange {
+    fn apply(&self, stream: u32) -> Result<()> {
+        match self {
+            Self::Volume(percent) => AudioControls::set_stream_volume(stream, *percent),
+            Self::Mute(mute) => AudioControls::set_stream_mute(stream, *mute),
+            Self::Move(sink) => AudioControls::move_stream(stream, sink),
+        }
+    }
+}
+
+/// Changes to several streams made together, like the mixer's bulk actions: they're
+/// made in turn, and when one fails those made already are undone, leaving the
+/// streams all changed or as they were.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    /// Each stream with its change, and the change undoing it.
+    steps: Vec<(u32, StreamChange, StreamChange)>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `change` of `stream`, leaving it out when there's nothing to change.
+    pub fn change(&mut self, stream: &Stream, change: StreamChange) -> &mut Self {
+        let undo = match change {
+            StreamChange::Volume(_) => StreamChange::Volume(stream.volume_percent()),
+            StreamChange::Mute(_) => StreamChange::Mute(stream.mute),
+            StreamChange::Move(_) => StreamChange::Move(stream.sink.to_string()),
+        };
+        let unchanged = match &change {
+            StreamChange::Move(sink) => *sink == stream.sink.to_string(),
+            change => *change == undo,
+        };
+        if !unchanged {
+            self.steps.push((stream.index, change, undo));
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Makes the changes, undoing them all if one fails.
+    pub fn commit(self) -> Result<()> {
+        for (made, (stream, change, _)) in self.steps.iter().enumerate() {
+            let Err(error) = change.apply(*stream) else {
+                continue;
+            };
+            for (stream, _, undo) in self.steps[..made].iter().rev() {
+                if let Err(error) = undo.apply(*stream) {
+                    warn!(?error, "undoing a change of stream {stream}");
+                }
+            }
+            return Err(error).wrap_err_with(|| {
+                format!("changing stream {stream}, {made} changes before it were undone")
+            });
+        }
+        Ok(())
+    }
+}
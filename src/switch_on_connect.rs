@@ -0,0 +1,231 @@
+//! What connecting a device changes on its own: devices connecting, like a
+//! Bluetooth headset or a USB DAC, can become the default through the pulse
+//! server's `module-switch-on-connect`, and whether playing streams follow a new
+//! default is WirePlumber's `linking.follow-default-target` setting. Headphones
+//! plugged into a jack are pipeweld's own, see [`crate::jack`]. What the user
+//! picked is kept with the device settings and brought back on startup, since the
+//! module goes away with the server.
+use std::{cell::Cell, rc::Rc};
+
+use eyre::{Result, WrapErr};
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    checked_output,
+    i18n::{tr, tr_with},
+    modules::Module,
+    run_change,
+    state::AppState,
+};
+
+const MODULE: &str = "module-switch-on-connect";
+const FOLLOW_SETTING: &str = "linking.follow-default-target";
+
+/// What the user picked, `None` leaving it to the server's own configuration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwitchOnConnect {
+    /// Devices connecting become the default.
+    pub devices: Option<bool>,
+    /// Playing streams move along to a new default.
+    pub streams: Option<bool>,
+}
+
+/// How the server behaves now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    pub devices: bool,
+    /// `None` when the session manager has no such setting, like before WirePlumber 0.5.
+    pub streams: Option<bool>,
+}
+
+impl Status {
+    pub fn read() -> Result<Self> {
+        Ok(Self {
+            devices: !loaded()?.is_empty(),
+            streams: follows_default(),
+        })
+    }
+}
+
+/// The module, loaded by pipeweld or by the server's configuration.
+fn loaded() -> Result<Vec<u32>> {
+    Ok(Module::list()?
+        .into_iter()
+        .filter(|module| module.name == MODULE)
+        .map(|module| module.index)
+        .collect())
+}
+
+/// Loads the module or unloads it, doing nothing when it's as asked already.
+#[instrument(err)]
+pub fn set_devices(enabled: bool) -> Result<()> {
+    let loaded = loaded()?;
+    match (loaded.is_empty(), enabled) {
+        (true, true) => {
+            // pipeweld's own filters and virtual devices stay out of it
+            let module = Module::load(MODULE, &["ignore_virtual=true".to_owned()])?;
+            info!(module, "switching to devices as they connect");
+            Ok(())
+        }
+        (false, false) => loaded.into_iter().try_for_each(Module::unload),
+        _ => Ok(()),
+    }
+}
+
+/// WirePlumber's setting, `None` when it can't be read.
+fn follows_default() -> Option<bool> {
+    let output =
+        checked_output(std::process::Command::new("wpctl").args(["settings", FOLLOW_SETTING]))
+            .map_err(|error| warn!(?error, "reading {FOLLOW_SETTING}"))
+            .ok()?;
+    // like `Value: true`, the wording differs between versions
+    String::from_utf8_lossy(&output)
+        .split_whitespace()
+        .rev()
+        .find_map(|word| word.trim_matches(|c: char| !c.is_alphabetic()).parse().ok())
+}
+
+/// Sets WirePlumber's setting, saved so it outlasts WirePlumber restarting.
+#[instrument(err)]
+pub fn set_streams(follow: bool) -> Result<()> {
+    run_change(std::process::Command::new("wpctl").args([
+        "settings",
+        "--save",
+        FOLLOW_SETTING,
+        &follow.to_string(),
+    ]))
+    .wrap_err_with(|| format!("setting {FOLLOW_SETTING}"))
+    .map(drop)
+}
+
+/// Brings the server in line with what the user picked.
+pub fn apply(picked: SwitchOnConnect) -> Result<()> {
+    if let Some(enabled) = picked.devices {
+        set_devices(enabled)?;
+    }
+    if let Some(follow) = picked.streams {
+        set_streams(follow)?;
+    }
+    Ok(())
+}
+
+/// Expander with a switch each for connected devices, headphones plugged into a jack
+/// and playing streams, following the server whenever it's expanded.
+pub fn switch_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let devices = gtk::CheckButton::with_label(&tr("Connected devices become the default"));
+    devices.set_tooltip_text(Some(&tr(
+        "Like a Bluetooth headset or a USB DAC, through module-switch-on-connect",
+    )));
+    layout.append(&devices);
+    let headphones =
+        gtk::CheckButton::with_label(&tr("Headphones plugged into a jack become the default"));
+    layout.append(&headphones);
+    let streams = gtk::CheckButton::with_label(&tr("Playing streams move to the new default"));
+    streams.set_tooltip_text(Some(&tr_with(
+        "WirePlumber's {setting}",
+        &[("setting", FOLLOW_SETTING)],
+    )));
+    layout.append(&streams);
+    let hint = gtk::Label::new(None);
+    hint.set_wrap(true);
+    hint.set_xalign(0.);
+    hint.add_css_class("dim-label");
+    layout.append(&hint);
+
+    // set while following the server, which notifies too
+    let syncing = Rc::new(Cell::new(false));
+    let pick = {
+        let syncing = syncing.clone();
+        let dispatcher = dispatcher.clone();
+        move |update: fn(&mut SwitchOnConnect, bool), active: bool| {
+            if syncing.get() {
+                return;
+            }
+            let mut picked = state
+                .device_settings
+                .with_untracked(|store| store.switch_on_connect);
+            update(&mut picked, active);
+            dispatcher.dispatch(Action::SetSwitchOnConnect(picked));
+        }
+    };
+    devices.connect_toggled({
+        let pick = pick.clone();
+        move |check| {
+            pick(
+                |picked, active| picked.devices = Some(active),
+                check.is_active(),
+            )
+        }
+    });
+    streams.connect_toggled(move |check| {
+        pick(
+            |picked, active| picked.streams = Some(active),
+            check.is_active(),
+        )
+    });
+    headphones.connect_toggled({
+        let syncing = syncing.clone();
+        move |check| {
+            if syncing.get() {
+                return;
+            }
+            let mut actions = state
+                .device_settings
+                .with_untracked(|store| store.headphone_jack.clone());
+            actions.switch = check.is_active();
+            dispatcher.dispatch(Action::SetJackActions(actions));
+        }
+    });
+    create_effect(cx, {
+        let syncing = syncing.clone();
+        move |_| {
+            let switch = state
+                .device_settings
+                .with(|store| store.headphone_jack.switch);
+            syncing.set(true);
+            headphones.set_active(switch);
+            syncing.set(false);
+        }
+    });
+
+    let refresh = move || {
+        let status = match Status::read() {
+            Ok(status) => status,
+            Err(error) => {
+                warn!(?error, "reading what connecting switches");
+                return hint.set_text(&tr("The server couldn't be asked."));
+            }
+        };
+        syncing.set(true);
+        devices.set_active(status.devices);
+        streams.set_active(status.streams.unwrap_or(false));
+        streams.set_sensitive(status.streams.is_some());
+        syncing.set(false);
+        hint.set_text(match status.streams {
+            Some(_) => "",
+            None => {
+                "Moving streams is up to the session manager, which needs to be WirePlumber 0.5 \
+                 or newer."
+            }
+        });
+    };
+    let expander = gtk::Expander::builder()
+        .label("Switch on connect")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    expander.connect_expanded_notify(move |expander| {
+        if expander.is_expanded() {
+            refresh();
+        }
+    });
+    expander
+}
@@ -0,0 +1,122 @@
+//! Aligned tables for the terminal, as `pipeweld list` prints them. Columns are
+//! padded to their widest cell, numbers align to the right, and rows can stand out
+//! or fade with ANSI colors when printing to a terminal.
+use std::io::IsTerminal;
+
+use clap::ValueEnum;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// When to color tables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Color {
+    /// When printing to a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// How a row shows when colored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Emphasis {
+    #[default]
+    Plain,
+    /// Like the default device.
+    Highlight,
+    /// Like muted or unavailable devices.
+    Faded,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    headers: Vec<(String, Align)>,
+    rows: Vec<(Vec<String>, Emphasis)>,
+}
+
+impl Table {
+    pub fn new(headers: impl IntoIterator<Item = (String, Align)>) -> Self {
+        Self {
+            headers: headers.into_iter().collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, with a cell for each header.
+    pub fn push(&mut self, cells: Vec<String>, emphasis: Emphasis) {
+        debug_assert_eq!(cells.len(), self.headers.len(), "a cell for each header");
+        self.rows.push((cells, emphasis));
+    }
+
+    /// The table with a line for the headers and one for each row, colored or not.
+    pub fn render(&self, color: bool) -> String {
+        let widths = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(column, (header, _))| {
+                self.rows
+                    .iter()
+                    .map(|(cells, _)| cells[column].chars().count())
+                    .chain([header.chars().count()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let line = |cells: &[String]| {
+            let last = cells.len().saturating_sub(1);
+            cells
+                .iter()
+                .zip(&self.headers)
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, ((cell, (_, align)), width))| match align {
+                    Align::Right => format!("{cell:>width$}"),
+                    // no trailing spaces after the last column
+                    Align::Left if column == last => cell.clone(),
+                    Align::Left => format!("{cell:<width$}"),
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        };
+        let paint = |text: String, style: &str| match color && !style.is_empty() {
+            true => format!("{style}{text}{RESET}"),
+            false => text,
+        };
+        let headers = self
+            .headers
+            .iter()
+            .map(|(header, _)| header.clone())
+            .collect::<Vec<_>>();
+        std::iter::once(paint(line(&headers), BOLD))
+            .chain(self.rows.iter().map(|(cells, emphasis)| {
+                let style = match emphasis {
+                    Emphasis::Plain => "",
+                    Emphasis::Highlight => GREEN,
+                    Emphasis::Faded => DIM,
+                };
+                paint(line(cells), style)
+            }))
+            .map(|line| line + "\n")
+            .collect()
+    }
+}
@@ -0,0 +1,202 @@
+//! Talkback, for speaking to a room through its output while holding a button: a
+//! `module-loopback` from a chosen microphone to a chosen sink, like the speakers
+//! of another zone, with what plays on that sink ducked meanwhile. Letting go
+//! unloads the loopback and gives the streams their volumes back. The loopback
+//! carries a [`crate::modules::OBJECT_TAG`], so one left over from a crash is
+//! found and unloaded the next time.
+use eyre::Result;
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{self, Device, DeviceKind},
+    extensions::watch,
+    i18n::tr,
+    modules::Module,
+    state::AppState,
+    streams::Stream,
+    AudioControls,
+};
+
+/// Object name of the loopback.
+const OBJECT: &str = "talkback";
+/// Share of their volume streams on the sink keep while talking, in percent.
+const DUCK_PERCENT: u32 = 25;
+/// Latency of the loopback, short so the voice isn't heard late in the same room.
+const LATENCY_MS: u32 = 30;
+
+/// Talking, with the volumes of the streams ducked meanwhile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Talkback {
+    pub module: u32,
+    pub source: String,
+    pub sink: String,
+    /// Stream indices and their volumes before ducking, in percent.
+    pub ducked: Vec<(u32, u32)>,
+}
+
+/// Talkback loopbacks loaded, normally none or the one talking.
+fn loaded() -> Result<Vec<u32>> {
+    Ok(Module::list()?
+        .into_iter()
+        .filter(|module| module.object() == Some(OBJECT))
+        .map(|module| module.index)
+        .collect())
+}
+
+/// Routes `source` to `sink` and ducks what plays there, stopping talking first.
+#[instrument(skip(state), err)]
+pub fn start(state: AppState, source: &str, sink: &str) -> Result<()> {
+    stop(state)?;
+    let index = Device::list(DeviceKind::Sink)?
+        .into_iter()
+        .find(|device| device.name == sink)
+        .map(|device| device.index);
+    let ducked = Stream::list()?
+        .into_iter()
+        .filter(|stream| Some(stream.sink) == index && !stream.mute)
+        .filter_map(|stream| {
+            let percent = stream.volume_percent();
+            let lowered = percent * DUCK_PERCENT / 100;
+            match AudioControls::set_stream_volume(stream.index, lowered) {
+                Ok(()) => Some((stream.index, percent)),
+                Err(error) => {
+                    warn!(?error, "ducking stream {}", stream.index);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let loaded = Module::load(
+        "module-loopback",
+        &[
+            format!("source={source}"),
+            format!("sink={sink}"),
+            "source_dont_move=true".to_owned(),
+            "sink_dont_move=true".to_owned(),
+            format!("latency_msec={LATENCY_MS}"),
+            format!(
+                "sink_input_properties=\"media.name=Talkback {}\"",
+                Module::tag(OBJECT)
+            ),
+        ],
+    );
+    let module = match loaded {
+        Ok(module) => module,
+        Err(error) => {
+            restore(&ducked);
+            return Err(error);
+        }
+    };
+    info!(module, "talking");
+    state.talkback.set(Some(Talkback {
+        module,
+        source: source.to_owned(),
+        sink: sink.to_owned(),
+        ducked,
+    }));
+    Ok(())
+}
+
+fn restore(ducked: &[(u32, u32)]) {
+    for (stream, percent) in ducked {
+        // streams that ended meanwhile are gone
+        if let Err(error) = AudioControls::set_stream_volume(*stream, *percent) {
+            warn!(?error, "restoring the volume of stream {stream}");
+        }
+    }
+}
+
+/// Stops talking, unloading any talkback loopback and restoring what was ducked.
+#[instrument(skip(state), err)]
+pub fn stop(state: AppState) -> Result<()> {
+    if let Some(talkback) = state.talkback.get_untracked() {
+        state.talkback.set(None);
+        restore(&talkback.ducked);
+    }
+    loaded()?.into_iter().try_for_each(Module::unload)
+}
+
+/// Expander picking a microphone and an output, with a button talking for as long
+/// as it's held down. Devices are kept listed as they come and go.
+pub fn talkback_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let source = gtk::DropDown::from_strings(&[]);
+    source.set_tooltip_text(Some(&tr("Microphone to talk through")));
+    layout.append(&source);
+    let sink = gtk::DropDown::from_strings(&[]);
+    sink.set_tooltip_text(Some(&tr("Output to talk to")));
+    layout.append(&sink);
+    let talk = gtk::Button::with_label(&tr("Hold to talk"));
+    talk.set_halign(gtk::Align::Start);
+    layout.append(&talk);
+    let status = gtk::Label::new(None);
+    status.set_halign(gtk::Align::Start);
+    status.add_css_class("dim-label");
+    layout.append(&status);
+
+    let entry = |device: &Device| (device.name.clone(), device.description.clone());
+    // the default microphone to begin with, the output is up to the user
+    let sources = devices::keep_listed(
+        cx,
+        &source,
+        move || state.sources.get(),
+        entry,
+        move |sources| {
+            let default = state
+                .default_source
+                .with_untracked(|default| default.as_ref().map(|default| default.name.clone()))?;
+            sources.iter().position(|source| source.name == default)
+        },
+    );
+    let sinks = devices::keep_listed(cx, &sink, move || state.sinks.get(), entry, |_| None);
+
+    // pressed and released rather than clicked, for as long as it's held down
+    let press = gtk::GestureClick::new();
+    // ahead of the button's own gesture, which claims the press
+    press.set_propagation_phase(gtk::PropagationPhase::Capture);
+    press.connect_pressed({
+        let dispatcher = dispatcher.clone();
+        move |_, _, _, _| {
+            let picked = sources.with_untracked(|sources| {
+                let source = sources.get(source.selected() as usize)?.name.clone();
+                let sink = sinks.with_untracked(|sinks| {
+                    sinks
+                        .get(sink.selected() as usize)
+                        .map(|sink| sink.name.clone())
+                })?;
+                Some((source, sink))
+            });
+            match picked {
+                Some((source, sink)) => dispatcher.dispatch(Action::StartTalkback { source, sink }),
+                None => warn!("talkback takes a microphone and an output"),
+            }
+        }
+    });
+    press.connect_released({
+        let dispatcher = dispatcher.clone();
+        move |_, _, _, _| dispatcher.dispatch(Action::StopTalkback)
+    });
+    // the pointer leaving the button, or the window losing focus
+    press.connect_cancel(move |_, _| dispatcher.dispatch(Action::StopTalkback));
+    talk.add_controller(press);
+    watch(cx, &status, move |status| {
+        status.set_text(&state.talkback.with(|talkback| match talkback {
+            Some(talkback) => format!(
+                "Talking, {} streams ducked to {DUCK_PERCENT}%",
+                talkback.ducked.len()
+            ),
+            None => String::new(),
+        }))
+    });
+
+    gtk::Expander::builder()
+        .label("Talkback")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
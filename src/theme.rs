@@ -0,0 +1,198 @@
+//! Styling pipeweld to taste: `$XDG_CONFIG_HOME/pipeweld/style.css` is loaded over
+//! GTK's theme and pipeweld's own styles, and loaded again whenever it's saved. The
+//! window's pieces have names and classes to pick them by:
+//!
+//! ```css
+//! #outputs .volume-slider trough { min-height: 12px; }
+//! #inputs .mute { color: @error_color; }
+//! .panel#routing-presets > title { font-weight: bold; }
+//! ```
+//!
+//! - `#outputs` and `#inputs`, the output's and the input's columns;
+//! - `.volume-bar`, `.volume-slider`, `.volume-step` and `.mute` in them;
+//! - `.panel` on every expander, named after its label, as in `#routing-presets`.
+//!
+//! The window can also be light or dark regardless of the desktop, which GTK tells
+//! the theme as `gtk-application-prefer-dark-theme`.
+use std::{cell::Cell, path::PathBuf};
+
+use gtk::{gio, glib, prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    i18n::{tr, tr_with},
+    state::AppState,
+    ui_state,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorScheme {
+    /// Whatever the desktop prefers.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    pub const ALL: [Self; 3] = [Self::System, Self::Light, Self::Dark];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::System => "Follow the system",
+            Self::Light => "Light",
+            Self::Dark => "Dark",
+        }
+    }
+}
+
+pub fn path() -> PathBuf {
+    glib::user_config_dir()
+        .join(clap::crate_name!())
+        .join("style.css")
+}
+
+/// `label` as a widget name, like `routing-presets` for "Routing presets".
+pub(crate) fn slug(label: &str) -> String {
+    label
+        .split(|char: char| !char.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Gives every expander of `window` the `panel` class and a name after its label.
+pub fn name_panels(window: &gtk::ApplicationWindow) {
+    let mut found = Vec::new();
+    ui_state::expanders(window.upcast_ref(), &mut found);
+    for expander in found {
+        expander.add_css_class("panel");
+        if let Some(label) = expander.label() {
+            expander.set_widget_name(&slug(&label));
+        }
+    }
+}
+
+fn load(provider: &gtk::CssProvider) {
+    let path = path();
+    match path.exists() {
+        true => {
+            info!(path = %path.display(), "loading the user's styles");
+            provider.load_from_path(&path);
+        }
+        false => provider.load_from_data(""),
+    }
+}
+
+/// Loads the user's styles and keeps them up to date, and follows the picked color
+/// scheme.
+pub fn spawn(cx: Scope, state: AppState) {
+    let Some(display) = gtk::gdk::Display::default() else {
+        return warn!("no display to style");
+    };
+    let provider = gtk::CssProvider::new();
+    provider.connect_parsing_error(|_, section, error| {
+        warn!(%error, location = %section.to_str(), "in style.css");
+    });
+    load(&provider);
+    gtk::style_context_add_provider_for_display(
+        &display,
+        &provider,
+        gtk::STYLE_PROVIDER_PRIORITY_USER,
+    );
+    match gio::File::for_path(path())
+        .monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+    {
+        Ok(monitor) => {
+            monitor.connect_changed(move |_, _, _, event| {
+                if matches!(
+                    event,
+                    gio::FileMonitorEvent::ChangesDoneHint
+                        | gio::FileMonitorEvent::Created
+                        | gio::FileMonitorEvent::Deleted
+                ) {
+                    load(&provider);
+                }
+            });
+            // the monitor stops once dropped
+            store_value(cx, monitor);
+        }
+        Err(error) => warn!(%error, "style.css won't be loaded again when saved"),
+    }
+
+    let settings = gtk::Settings::for_display(&display);
+    // what the desktop asked for, to go back to
+    let system = Cell::new(settings.is_gtk_application_prefer_dark_theme());
+    let scheme = create_memo(cx, move |_| {
+        state.device_settings.with(|store| store.color_scheme)
+    });
+    create_effect(cx, move |previous: Option<ColorScheme>| {
+        let scheme = scheme.get();
+        if previous.is_none() || previous == Some(ColorScheme::System) {
+            system.set(settings.is_gtk_application_prefer_dark_theme());
+        }
+        settings.set_gtk_application_prefer_dark_theme(match scheme {
+            ColorScheme::System => system.get(),
+            ColorScheme::Light => false,
+            ColorScheme::Dark => true,
+        });
+        scheme
+    });
+}
+
+/// Expander picking the color scheme, and telling where styles go.
+pub fn appearance_panel(state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(&tr("Colors")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    row.append(&label);
+    let labels = ColorScheme::ALL
+        .iter()
+        .map(|scheme| scheme.label())
+        .collect::<Vec<_>>();
+    let picker = gtk::DropDown::from_strings(&labels);
+    let current = move || {
+        state
+            .device_settings
+            .with_untracked(|store| store.color_scheme)
+    };
+    picker.set_selected(
+        ColorScheme::ALL
+            .iter()
+            .position(|scheme| *scheme == current())
+            .unwrap_or(0) as u32,
+    );
+    picker.connect_selected_notify(move |picker| {
+        let Some(scheme) = ColorScheme::ALL.get(picker.selected() as usize).copied() else {
+            return;
+        };
+        if scheme != current() {
+            dispatcher.dispatch(Action::SetColorScheme(scheme));
+        }
+    });
+    row.append(&picker);
+    layout.append(&row);
+    let hint = gtk::Label::new(Some(&tr_with(
+        "Styles in {path} apply as it's saved.",
+        &[("path", &path().display().to_string())],
+    )));
+    hint.set_wrap(true);
+    hint.set_max_width_chars(60);
+    hint.set_halign(gtk::Align::Start);
+    hint.add_css_class("dim-label");
+    layout.append(&hint);
+    gtk::Expander::builder()
+        .label("Appearance")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,186 @@
+//! Recordings of the state, action by action and event by event, for reproducing
+//! UI bugs. The inspector records them, steps back and forth through them and
+//! exports them to attach to bug reports. Stepping only shows the state as it was,
+//! it doesn't put the audio server back the way it was.
+//!
+//! Volume changes the server reports are put down to whoever likely made them,
+//! for finding out what keeps resetting a volume, see [`Source`].
+use std::{
+    collections::VecDeque,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    events::{Event, EventKind, Facility},
+    state::AppState,
+};
+
+/// Entries beyond this many push out the oldest.
+const CAPACITY: usize = 1000;
+
+/// How long after something happened a change is still put down to it.
+const CORRELATION: Duration = Duration::from_millis(500);
+
+/// Who made a change the server reported. The server doesn't say, so it's guessed
+/// from what happened right before: pipeweld knows when it changed something, and
+/// keys bound in the compositor run a tool like `pactl` or `wpctl`, which connects as
+/// a client just for the change. Desktops handling the keys in a daemon that stays
+/// connected show up as another application, and so does ducking by the session
+/// manager or a call application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    /// Asked for in pipeweld, through its window, tray, keys or pads.
+    Pipeweld,
+    /// Pipeweld's rules and limits, see [`crate::automated`].
+    Rules,
+    Keys,
+    OtherApp,
+}
+
+impl Source {
+    pub const ALL: [Self; 4] = [Self::Pipeweld, Self::Rules, Self::Keys, Self::OtherApp];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Pipeweld => "pipeweld",
+            Self::Rules => "pipeweld's rules",
+            Self::Keys => "volume keys or a script",
+            Self::OtherApp => "another application",
+        }
+    }
+
+    /// Who made the change the server just reported.
+    pub fn guess(state: AppState) -> Self {
+        let now = Instant::now();
+        let recent = |at: Instant| now.saturating_duration_since(at) < CORRELATION;
+        match crate::last_change() {
+            Some((at, true)) if recent(at) => Self::Rules,
+            Some((at, false)) if recent(at) => Self::Pipeweld,
+            _ if state
+                .recording
+                .with_value(|recording| recording.client_connected.is_some_and(recent)) =>
+            {
+                Self::Keys
+            }
+            _ => Self::OtherApp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Since the recording started.
+    pub at_ms: u64,
+    /// The action or event, as printed for debugging.
+    pub cause: String,
+    /// Who made the change, for volume changes of devices and streams.
+    #[serde(default)]
+    pub source: Option<Source>,
+    /// Every piece of state right after, by name.
+    pub state: Vec<(String, String)>,
+}
+
+impl Entry {
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.state
+            .iter()
+            .find(|(recorded, _)| recorded == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Recording {
+    started: Option<Instant>,
+    /// When the last client connected, see [`Source`].
+    client_connected: Option<Instant>,
+    pub entries: VecDeque<Entry>,
+}
+
+impl Recording {
+    pub fn is_recording(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Starts over, dropping what was recorded before.
+    pub fn start(&mut self) {
+        self.entries.clear();
+        self.started = Some(Instant::now());
+    }
+
+    pub fn stop(&mut self) {
+        self.started = None;
+    }
+
+    #[instrument(skip(self), err)]
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        std::fs::write(path, json).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+
+    /// A recording exported before, to step through.
+    #[instrument(err)]
+    pub fn import(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("reading {}", path.display()))?;
+        Ok(Self {
+            started: None,
+            client_connected: None,
+            entries: serde_json::from_str(&json)
+                .wrap_err_with(|| format!("parsing {}", path.display()))?,
+        })
+    }
+}
+
+/// Adds an entry for `cause` with the state as it is now, while recording.
+pub fn record(state: AppState, cause: String) {
+    push(state, cause, None);
+}
+
+/// Like [`record`] for an event of the server, telling who made the change.
+pub fn record_event(state: AppState, event: Event) {
+    // kept up even while not recording, for telling sources apart elsewhere
+    if (event.kind, event.facility) == (EventKind::New, Facility::Client) {
+        state
+            .recording
+            .update_value(|recording| recording.client_connected = Some(Instant::now()));
+    }
+    if !state.recording.with_value(Recording::is_recording) {
+        return;
+    }
+    let volume_change = event.kind == EventKind::Change
+        && matches!(
+            event.facility,
+            Facility::Sink | Facility::Source | Facility::SinkInput | Facility::SourceOutput
+        );
+    let source = volume_change.then(|| Source::guess(state));
+    push(state, format!("{event:?}"), source);
+}
+
+fn push(state: AppState, cause: String, source: Option<Source>) {
+    let Some(started) = state.recording.with_value(|recording| recording.started) else {
+        return;
+    };
+    let entry = Entry {
+        at_ms: started.elapsed().as_millis() as u64,
+        cause,
+        source,
+        state: state
+            .inspect()
+            .into_iter()
+            .map(|(name, print)| (name.to_owned(), print()))
+            .collect(),
+    };
+    state.recording.update_value(|recording| {
+        if recording.entries.len() == CAPACITY {
+            recording.entries.pop_front();
+        }
+        recording.entries.push_back(entry);
+    });
+}
@@ -0,0 +1,274 @@
+//! Reading what `pactl`, `pw-dump` and `wpctl` print, across their versions and
+//! whatever locale the user runs. Commands run under `LC_ALL=C`, see
+//! [`crate::checked_output`], as pactl translates its text output and, under some
+//! locales, prints decimal commas into its JSON. Beyond that, lists are read an
+//! object at a time, so one object a newer version prints differently costs that
+//! object and not the whole list, and `Label: value` lines are matched loosely.
+use eyre::{eyre, Result, WrapErr};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::warn;
+
+/// `output` as JSON, with control characters, which some pactl versions leave
+/// unescaped in property values, read as spaces if it doesn't parse as it is.
+fn json(output: &str) -> serde_json::Result<Value> {
+    serde_json::from_str(output).or_else(|error| {
+        let cleaned = output
+            .chars()
+            .map(|char| match char.is_control() {
+                true => ' ',
+                false => char,
+            })
+            .collect::<String>();
+        serde_json::from_str(&cleaned).map_err(|_| error)
+    })
+}
+
+/// The objects of a JSON array, like `pactl --format=json list sinks` prints,
+/// leaving out those that don't read as `T`.
+pub fn json_list<T: DeserializeOwned>(output: &str, what: &str) -> Result<Vec<T>> {
+    let trimmed = output.trim_start();
+    if !trimmed.is_empty() && !trimmed.starts_with('[') {
+        // pactl before version 16 has no JSON, and says so in plain text
+        let first = trimmed.lines().next().unwrap_or_default();
+        return Err(eyre!("expected a JSON list of {what}, got {first:?}"));
+    }
+    let Value::Array(objects) = json(output).wrap_err_with(|| format!("parsing {what}"))? else {
+        return Err(eyre!("expected a JSON list of {what}"));
+    };
+    Ok(objects
+        .into_iter()
+        .filter_map(|object| {
+            serde_json::from_value(object)
+                .map_err(|error| warn!(%error, what, "leaving out an object"))
+                .ok()
+        })
+        .collect())
+}
+
+/// Like [`json_list`], for bytes as a command printed them.
+pub fn json_list_bytes<T: DeserializeOwned>(output: &[u8], what: &str) -> Result<Vec<T>> {
+    json_list(&String::from_utf8_lossy(output), what)
+}
+
+/// The value of the first `label: value` line of `output`, with the label matched
+/// regardless of case and whitespace around either.
+pub fn field<'a>(output: &'a str, label: &str) -> Option<&'a str> {
+    output.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case(label)
+            .then_some(value.trim())
+    })
+}
+
+/// `yes`, `no` and the other ways tools print a flag.
+pub fn flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "yes" | "true" | "on" | "1" => Some(true),
+        "no" | "false" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// A decimal number, with a comma for the point too.
+pub fn decimal(value: &str) -> Option<f64> {
+    value.trim().replace(',', ".").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{devices::Device, streams::Stream};
+
+    /// Trimmed from `pactl --format=json list sinks` of pactl 16.1.
+    const SINKS: &str = r#"[{"index":56,"state":"RUNNING","name":"alsa_output.pci-0000_00_1f.3.analog-stereo","description":"Built-in Audio Analog Stereo","driver":"PipeWire","sample_specification":"s32le 2ch 48000Hz","channel_map":"front-left,front-right","owner_module":4294967295,"mute":false,"volume":{"front-left":{"value":26214,"value_percent":"40%","db":"-23.88 dB"},"front-right":{"value":26214,"value_percent":"40%","db":"-23.88 dB"}},"balance":0.000000,"base_volume":{"value":65536,"value_percent":"100%","db":"0.00 dB"},"monitor_source":"alsa_output.pci-0000_00_1f.3.analog-stereo.monitor","latency":{"actual":0.000000,"configured":0.000000},"flags":["HARDWARE","DECIBEL_VOLUME","LATENCY"],"properties":{"alsa.card":"0","device.description":"Built-in Audio Analog Stereo","node.name":"alsa_output.pci-0000_00_1f.3.analog-stereo"},"ports":[{"name":"analog-output-speaker","description":"Speakers","type":"Speaker","priority":10000,"availability_group":"Legacy 1","availability":"availability unknown"},{"name":"analog-output-headphones","description":"Headphones","type":"Headphones","priority":9900,"availability_group":"Legacy 2","availability":"not available"}],"active_port":"analog-output-speaker","formats":["pcm"]},{"index":61,"state":"SUSPENDED","name":"bluez_output.00_1B_66_AA_BB_CC.1","description":"Headset","driver":"PipeWire","sample_specification":"s16le 2ch 48000Hz","channel_map":"front-left,front-right","owner_module":4294967295,"mute":true,"volume":{"front-left":{"value":65536,"value_percent":"100%","db":"0.00 dB"},"front-right":{"value":65536,"value_percent":"100%","db":"0.00 dB"}},"balance":0.000000,"properties":{"device.description":"Headset"},"ports":[],"active_port":null,"formats":["pcm"]}]"#;
+
+    /// Trimmed from `pactl --format=json list sink-inputs`, with a tab and a line
+    /// break left unescaped in the media name as pactl 16.0 prints them.
+    const STREAMS: &str = "[{\"index\":120,\"driver\":\"PipeWire\",\"owner_module\":\"\",\"client\":\"93\",\"sink\":56,\"sample_specification\":\"float32le 2ch 48000Hz\",\"channel_map\":\"front-left,front-right\",\"format\":\"pcm, format.sample_format = \\\"\\\\\\\"float32le\\\\\\\"\\\"\",\"corked\":false,\"mute\":false,\"volume\":{\"front-left\":{\"value\":65536,\"value_percent\":\"100%\",\"db\":\"0.00 dB\"},\"front-right\":{\"value\":65536,\"value_percent\":\"100%\",\"db\":\"0.00 dB\"}},\"balance\":0.000000,\"buffer_latency\":0.000000,\"sink_latency\":0.000000,\"resample_method\":\"PipeWire\",\"properties\":{\"application.name\":\"Firefox\",\"media.name\":\"Song\tby\nBand\"}}]";
+
+    /// Trimmed from `pw-dump`, a node and a link.
+    const DUMP: &str = r#"[
+      {
+        "id": 56,
+        "type": "PipeWire:Interface:Node",
+        "version": 3,
+        "permissions": [ "r", "w", "x", "m" ],
+        "info": {
+          "max-input-ports": 0,
+          "state": "running",
+          "props": {
+            "media.class": "Audio/Sink",
+            "node.name": "alsa_output.pci-0000_00_1f.3.analog-stereo",
+            "object.id": 56
+          }
+        }
+      },
+      {
+        "id": 80,
+        "type": "PipeWire:Interface:Link",
+        "version": 3,
+        "info": { "output-node-id": 57, "input-node-id": 56, "state": "active" }
+      }
+    ]"#;
+
+    /// `pactl info` as pactl 16 prints it.
+    const INFO: &str = "Server String: /run/user/1000/pulse/native\n\
+        Library Protocol Version: 35\n\
+        Server Protocol Version: 35\n\
+        Is Local: yes\n\
+        Server Name: PulseAudio (on PipeWire 0.3.65)\n\
+        Server Version: 15.0.0\n\
+        Default Sink: alsa_output.pci-0000_00_1f.3.analog-stereo\n";
+
+    #[derive(Debug, Deserialize)]
+    struct Object {
+        id: u32,
+        #[serde(rename = "type")]
+        kind: String,
+    }
+
+    #[test]
+    fn reads_pactl_sinks() {
+        let sinks = json_list::<Device>(SINKS, "sinks").unwrap();
+        assert_eq!(
+            sinks.iter().map(|sink| sink.index).collect::<Vec<_>>(),
+            [56, 61]
+        );
+        assert_eq!(sinks[0].volume_percent(), 40);
+        assert_eq!(
+            sinks[0].active_port.as_deref(),
+            Some("analog-output-speaker")
+        );
+        assert!(sinks[0].ports[1].is_unavailable());
+        assert!(sinks[1].mute);
+        assert_eq!(sinks[1].active_port, None);
+    }
+
+    #[test]
+    fn reads_pactl_streams_with_unescaped_control_characters() {
+        let streams = json_list::<Stream>(STREAMS, "sink inputs").unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].title(), "Firefox: Song by Band");
+        assert_eq!(streams[0].sink, 56);
+    }
+
+    #[test]
+    fn reads_pw_dump_objects() {
+        let objects = json_list::<Object>(DUMP, "pw-dump objects").unwrap();
+        assert_eq!(
+            objects
+                .iter()
+                .map(|object| (object.id, object.kind.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (56, "PipeWire:Interface:Node"),
+                (80, "PipeWire:Interface:Link")
+            ]
+        );
+        assert!(json_list_bytes::<Object>(DUMP.as_bytes(), "pw-dump objects").is_ok());
+    }
+
+    #[test]
+    fn one_bad_object_costs_only_itself() {
+        // the second sink has no name, as a version printing it elsewhere would
+        let output = SINKS.replacen(r#""name":"bluez_output.00_1B_66_AA_BB_CC.1","#, "", 1);
+        let sinks = json_list::<Device>(&output, "sinks").unwrap();
+        assert_eq!(
+            sinks.iter().map(|sink| sink.index).collect::<Vec<_>>(),
+            [56]
+        );
+    }
+
+    #[test]
+    fn plain_text_and_broken_output_fails_with_what_it_got() {
+        // pactl before 16 ignores --format and prints text
+        let error = json_list::<Device>("Sink #0\n\tState: RUNNING\n", "sinks").unwrap_err();
+        assert!(error.to_string().contains("\"Sink #0\""), "{error}");
+        assert!(json_list::<Device>("{\"index\": 1}", "sinks").is_err());
+        assert!(json_list::<Device>("[{\"index\": 1", "sinks").is_err());
+        assert!(json_list::<Device>("", "sinks").is_err());
+        assert!(json_list::<Device>("  []\n", "sinks").unwrap().is_empty());
+    }
+
+    #[test]
+    fn every_cut_of_the_output_reads_or_fails_without_panicking() {
+        for output in [SINKS, STREAMS, DUMP] {
+            for (end, _) in output.char_indices() {
+                json_list::<Device>(&output[..end], "sinks").ok();
+                json_list::<Object>(&output[..end], "pw-dump objects").ok();
+            }
+        }
+    }
+
+    #[test]
+    fn control_characters_anywhere_in_a_value_read_as_spaces() {
+        for control in (0u8..0x20).chain([0x7f]).map(char::from) {
+            let output = format!(r#"[{{"id": 1, "type": "a{control}b"}}]"#);
+            let objects = json_list::<Object>(&output, "objects").unwrap();
+            let expected = match control {
+                // escaped or not, DEL is allowed in JSON strings
+                '\x7f' => "a\x7fb",
+                _ => "a b",
+            };
+            assert_eq!(objects[0].kind, expected, "{control:?}");
+        }
+    }
+
+    #[test]
+    fn fields_match_loosely() {
+        let cases = [
+            ("Server Name", Some("PulseAudio (on PipeWire 0.3.65)")),
+            ("server name", Some("PulseAudio (on PipeWire 0.3.65)")),
+            ("SERVER VERSION", Some("15.0.0")),
+            ("Is Local", Some("yes")),
+            ("Default Source", None),
+            // labels match whole, not by how they start
+            ("Server", None),
+        ];
+        for (label, expected) in cases {
+            assert_eq!(field(INFO, label), expected, "{label}");
+        }
+        for line in ["Mute: yes", "  Mute:yes", "\tmute :  yes  ", "MUTE:\tyes"] {
+            assert_eq!(field(line, "Mute").and_then(flag), Some(true), "{line:?}");
+        }
+        assert_eq!(field("Volume: 0.40: odd", "Volume"), Some("0.40: odd"));
+        assert_eq!(field("no labels here", "Volume"), None);
+    }
+
+    #[test]
+    fn flags_read_every_spelling() {
+        for (value, expected) in [
+            ("yes", Some(true)),
+            (" On ", Some(true)),
+            ("TRUE", Some(true)),
+            ("1", Some(true)),
+            ("no", Some(false)),
+            ("off", Some(false)),
+            ("False", Some(false)),
+            ("0", Some(false)),
+            ("maybe", None),
+            ("", None),
+        ] {
+            assert_eq!(flag(value), expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn decimals_read_with_a_point_or_a_comma() {
+        assert_eq!(decimal("0,40"), Some(0.4));
+        assert_eq!(decimal(" 1.5 "), Some(1.5));
+        assert_eq!(decimal("x"), None);
+        assert_eq!(decimal(""), None);
+        for hundredths in 0..=200 {
+            let point = format!("{}.{:02}", hundredths / 100, hundredths % 100);
+            let comma = point.replace('.', ",");
+            assert_eq!(decimal(&point), decimal(&comma), "{comma}");
+            let read = decimal(&comma).unwrap();
+            assert_eq!((read * 100.).round() as u32, hundredths, "{comma}");
+        }
+    }
+}
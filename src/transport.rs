@@ -0,0 +1,161 @@
+//! The JACK transport as PipeWire's JACK layer runs it, through the
+//! `jack_showtime` and `jack_transport` tools of jack-example-tools.
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use eyre::{eyre, Result, WrapErr};
+use gtk::{glib, prelude::*, Orientation};
+use tracing::{instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    i18n::tr_with,
+};
+
+const REFRESH: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportCommand {
+    Play,
+    Stop,
+    /// Back to the very beginning.
+    Rewind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub frame: u64,
+    /// `Rolling`, `Stopped` or `Starting`.
+    pub state: String,
+    /// Bar, beat and tick, when a timebase master provides them.
+    pub bbt: Option<String>,
+}
+
+impl Position {
+    /// Reads the first line `jack_showtime` prints, which it then keeps printing.
+    pub fn sample() -> Result<Self> {
+        let mut child = Command::new("jack_showtime")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .wrap_err("running jack_showtime")?;
+        let mut line = String::new();
+        let read = child
+            .stdout
+            .take()
+            .map(|stdout| BufReader::new(stdout).read_line(&mut line));
+        child.kill().and_then(|_| child.wait()).ok();
+        read.ok_or_else(|| eyre!("jack_showtime has no stdout"))?
+            .wrap_err("reading jack_showtime")?;
+        Self::parse(&line)
+    }
+
+    /// Parses `frame: 1234 state: Rolling BBT: 1|1|0000`, tab separated.
+    pub fn parse(line: &str) -> Result<Self> {
+        let field = |name: &str| {
+            line.split('\t')
+                .find_map(|field| field.trim().strip_prefix(name))
+                .map(str::trim)
+        };
+        Ok(Self {
+            frame: field("frame:")
+                .and_then(|frame| frame.parse().ok())
+                .ok_or_else(|| eyre!("no frame in {line:?}"))?,
+            state: field("state:").unwrap_or("[unknown]").to_owned(),
+            bbt: field("BBT:").map(str::to_owned),
+        })
+    }
+}
+
+#[instrument(ret, err)]
+pub fn send(command: TransportCommand) -> Result<()> {
+    let command = match command {
+        TransportCommand::Play => "play",
+        TransportCommand::Stop => "stop",
+        TransportCommand::Rewind => "locate 0",
+    };
+    let mut child = Command::new("jack_transport")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .wrap_err("running jack_transport")?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| eyre!("jack_transport has no stdin"))?
+        .write_all(format!("{command}\nquit\n").as_bytes())
+        .wrap_err("talking to jack_transport")?;
+    child.wait().wrap_err("waiting for jack_transport")?;
+    Ok(())
+}
+
+/// Expander with the transport position and buttons, polled while open.
+pub fn transport_panel(dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let position = gtk::Label::new(None);
+    position.set_halign(gtk::Align::Start);
+    position.set_selectable(true);
+    layout.append(&position);
+    let buttons = gtk::Box::new(Orientation::Horizontal, 6);
+    for (icon, command) in [
+        ("media-skip-backward-symbolic", TransportCommand::Rewind),
+        ("media-playback-start-symbolic", TransportCommand::Play),
+        ("media-playback-stop-symbolic", TransportCommand::Stop),
+    ] {
+        let button = gtk::Button::from_icon_name(icon);
+        let dispatcher = dispatcher.clone();
+        button.connect_clicked(move |_| dispatcher.dispatch(Action::Transport(command)));
+        buttons.append(&button);
+    }
+    layout.append(&buttons);
+    let expander = gtk::Expander::builder()
+        .label("JACK transport")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    receiver.attach(None, move |sampled: Result<Position, String>| {
+        match sampled {
+            Ok(sampled) => {
+                buttons.set_sensitive(true);
+                position.set_text(&match sampled.bbt {
+                    Some(bbt) => format!("{}, frame {}, bar {bbt}", sampled.state, sampled.frame),
+                    None => format!("{}, frame {}", sampled.state, sampled.frame),
+                });
+            }
+            Err(error) => {
+                buttons.set_sensitive(false);
+                position.set_text(&tr_with(
+                    "The transport isn't reachable ({error}). It needs jack_showtime and \
+                     jack_transport, from jack-example-tools.",
+                    &[("error", &error.to_string())],
+                ));
+            }
+        }
+        glib::Continue(true)
+    });
+    let weak = expander.downgrade();
+    glib::timeout_add_local(REFRESH, move || {
+        let Some(expander) = weak.upgrade() else {
+            return glib::Continue(false);
+        };
+        if expander.is_expanded() {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                let sampled = Position::sample().map_err(|error| {
+                    warn!(?error, "reading the transport");
+                    error.to_string()
+                });
+                sender.send(sampled).ok();
+            });
+        }
+        glib::Continue(true)
+    });
+    expander
+}
@@ -0,0 +1,1024 @@
+//! StatusNotifierItem tray icon with a `com.canonical.dbusmenu` context menu,
+//! spoken directly over gio's D-Bus bindings. The icon follows the output's volume,
+//! scrolling over it changes the volume and clicking it opens the window.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use eyre::Result;
+use gtk::{
+    gio,
+    glib::{self, ToVariant, Variant},
+};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    captures::Capture,
+    cards::Card,
+    channel_mix::ChannelMix,
+    config,
+    devices::{Device, DeviceKind},
+    easyeffects,
+    events::{self, Facility},
+    filter_chain,
+    hold::{self, Hold},
+    i18n::{tr, tr_with},
+    osd::OsdStyle,
+    passthrough::{self, Codec},
+    state::AppState,
+    streams::Stream,
+    AudioControls, DiffValue,
+};
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+
+const INTROSPECTION: &str = r#"
+<node>
+  <interface name="org.kde.StatusNotifierItem">
+    <property name="Category" type="s" access="read"/>
+    <property name="Id" type="s" access="read"/>
+    <property name="Title" type="s" access="read"/>
+    <property name="Status" type="s" access="read"/>
+    <property name="IconName" type="s" access="read"/>
+    <property name="OverlayIconName" type="s" access="read"/>
+    <property name="ToolTip" type="(sa(iiay)ss)" access="read"/>
+    <property name="ItemIsMenu" type="b" access="read"/>
+    <property name="Menu" type="o" access="read"/>
+    <method name="ContextMenu">
+      <arg name="x" type="i" direction="in"/>
+      <arg name="y" type="i" direction="in"/>
+    </method>
+    <method name="Activate">
+      <arg name="x" type="i" direction="in"/>
+      <arg name="y" type="i" direction="in"/>
+    </method>
+    <method name="SecondaryActivate">
+      <arg name="x" type="i" direction="in"/>
+      <arg name="y" type="i" direction="in"/>
+    </method>
+    <method name="Scroll">
+      <arg name="delta" type="i" direction="in"/>
+      <arg name="orientation" type="s" direction="in"/>
+    </method>
+    <signal name="NewTitle"/>
+    <signal name="NewIcon"/>
+    <signal name="NewOverlayIcon"/>
+    <signal name="NewToolTip"/>
+    <signal name="NewStatus">
+      <arg name="status" type="s"/>
+    </signal>
+  </interface>
+  <interface name="com.canonical.dbusmenu">
+    <property name="Version" type="u" access="read"/>
+    <property name="TextDirection" type="s" access="read"/>
+    <property name="Status" type="s" access="read"/>
+    <property name="IconThemePath" type="as" access="read"/>
+    <method name="GetLayout">
+      <arg name="parentId" type="i" direction="in"/>
+      <arg name="recursionDepth" type="i" direction="in"/>
+      <arg name="propertyNames" type="as" direction="in"/>
+      <arg name="revision" type="u" direction="out"/>
+      <arg name="layout" type="(ia{sv}av)" direction="out"/>
+    </method>
+    <method name="GetGroupProperties">
+      <arg name="ids" type="ai" direction="in"/>
+      <arg name="propertyNames" type="as" direction="in"/>
+      <arg name="properties" type="a(ia{sv})" direction="out"/>
+    </method>
+    <method name="GetProperty">
+      <arg name="id" type="i" direction="in"/>
+      <arg name="name" type="s" direction="in"/>
+      <arg name="value" type="v" direction="out"/>
+    </method>
+    <method name="Event">
+      <arg name="id" type="i" direction="in"/>
+      <arg name="eventId" type="s" direction="in"/>
+      <arg name="data" type="v" direction="in"/>
+      <arg name="timestamp" type="u" direction="in"/>
+    </method>
+    <method name="EventGroup">
+      <arg name="events" type="a(isvu)" direction="in"/>
+      <arg name="idErrors" type="ai" direction="out"/>
+    </method>
+    <method name="AboutToShow">
+      <arg name="id" type="i" direction="in"/>
+      <arg name="needUpdate" type="b" direction="out"/>
+    </method>
+    <method name="AboutToShowGroup">
+      <arg name="ids" type="ai" direction="in"/>
+      <arg name="updatesNeeded" type="ai" direction="out"/>
+      <arg name="idErrors" type="ai" direction="out"/>
+    </method>
+    <signal name="ItemsPropertiesUpdated">
+      <arg name="updatedProps" type="a(ia{sv})"/>
+      <arg name="removedProps" type="a(ias)"/>
+    </signal>
+    <signal name="LayoutUpdated">
+      <arg name="revision" type="u"/>
+      <arg name="parent" type="i"/>
+    </signal>
+    <signal name="ItemActivationRequested">
+      <arg name="id" type="i"/>
+      <arg name="timestamp" type="u"/>
+    </signal>
+  </interface>
+</node>
+"#;
+
+enum Toggle {
+    Check(bool),
+    Radio(bool),
+}
+
+/// A tray menu entry, before it gets flattened into dbusmenu ids.
+enum Entry {
+    Item {
+        label: String,
+        toggle: Option<Toggle>,
+        action: Action,
+    },
+    Submenu {
+        label: String,
+        children: Vec<Entry>,
+    },
+    /// Greyed out, nothing to click.
+    Disabled(String),
+    Separator,
+}
+
+impl Entry {
+    fn item(label: impl Into<String>, action: Action) -> Self {
+        Self::Item {
+            label: label.into(),
+            toggle: None,
+            action,
+        }
+    }
+
+    fn toggle(label: impl Into<String>, toggle: Toggle, action: Action) -> Self {
+        Self::Item {
+            label: label.into(),
+            toggle: Some(toggle),
+            action,
+        }
+    }
+}
+
+struct Node {
+    properties: HashMap<String, Variant>,
+    children: Vec<i32>,
+    action: Option<Action>,
+}
+
+/// Flattened menu, where a node's dbusmenu id is its index and `0` is the root.
+#[derive(Default)]
+struct Menu {
+    nodes: Vec<Node>,
+}
+
+impl Menu {
+    fn new(entries: Vec<Entry>) -> Self {
+        let mut menu = Self::default();
+        menu.push(HashMap::new(), None, entries);
+        menu
+    }
+
+    fn push(
+        &mut self,
+        properties: HashMap<String, Variant>,
+        action: Option<Action>,
+        children: Vec<Entry>,
+    ) -> i32 {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            properties,
+            children: Vec::new(),
+            action,
+        });
+        let children = children
+            .into_iter()
+            .map(|child| self.push_entry(child))
+            .collect();
+        self.nodes[id].children = children;
+        id as i32
+    }
+
+    fn push_entry(&mut self, entry: Entry) -> i32 {
+        let mut properties = HashMap::new();
+        match entry {
+            Entry::Item {
+                label,
+                toggle,
+                action,
+            } => {
+                properties.insert("label".to_owned(), label.to_variant());
+                if let Some(toggle) = toggle {
+                    let (toggle_type, state) = match toggle {
+                        Toggle::Check(state) => ("checkmark", state),
+                        Toggle::Radio(state) => ("radio", state),
+                    };
+                    properties.insert("toggle-type".to_owned(), toggle_type.to_variant());
+                    properties.insert("toggle-state".to_owned(), (state as i32).to_variant());
+                }
+                self.push(properties, Some(action), Vec::new())
+            }
+            Entry::Submenu { label, children } => {
+                properties.insert("label".to_owned(), label.to_variant());
+                properties.insert("enabled".to_owned(), (!children.is_empty()).to_variant());
+                properties.insert("children-display".to_owned(), "submenu".to_variant());
+                self.push(properties, None, children)
+            }
+            Entry::Disabled(label) => {
+                properties.insert("label".to_owned(), label.to_variant());
+                properties.insert("enabled".to_owned(), false.to_variant());
+                self.push(properties, None, Vec::new())
+            }
+            Entry::Separator => {
+                properties.insert("type".to_owned(), "separator".to_variant());
+                self.push(properties, None, Vec::new())
+            }
+        }
+    }
+
+    fn node(&self, id: i32) -> Option<&Node> {
+        usize::try_from(id).ok().and_then(|id| self.nodes.get(id))
+    }
+
+    fn layout(&self, id: i32) -> Variant {
+        let (properties, children) = self
+            .node(id)
+            .map(|node| (node.properties.clone(), node.children.as_slice()))
+            .unwrap_or_default();
+        let children = children
+            .iter()
+            .map(|child| Variant::from_variant(&self.layout(*child)))
+            .collect::<Vec<_>>();
+        (id, properties, children).to_variant()
+    }
+}
+
+/// What the icon tells about the default devices, whether anything records from a
+/// microphone and whether the routing is held, without opening the menu.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Status {
+    title: String,
+    description: String,
+    icon: &'static str,
+    overlay_icon: &'static str,
+}
+
+impl Status {
+    fn new(
+        sink: Option<&Device>,
+        source: Option<&Device>,
+        captures: &[Capture],
+        hold: &Hold,
+    ) -> Self {
+        let title = sink
+            .map(easyeffects::label)
+            .unwrap_or_else(|| tr("No output device"));
+        let title = match hold.is_on() {
+            true => tr_with("{output} (routing held)", &[("output", &title)]),
+            false => title,
+        };
+        let volume = sink
+            .map(|sink| {
+                let percent = sink.volume_percent().to_string();
+                match sink.mute {
+                    true => tr_with("Volume: {percent}% (muted)", &[("percent", &percent)]),
+                    false => tr_with("Volume: {percent}%", &[("percent", &percent)]),
+                }
+            })
+            .unwrap_or_default();
+        let microphone = source
+            .map(|source| {
+                let device = easyeffects::label(source);
+                match source.mute {
+                    true => tr_with("Microphone: {device} (muted)", &[("device", &device)]),
+                    false => tr_with("Microphone: {device}", &[("device", &device)]),
+                }
+            })
+            .unwrap_or_else(|| tr("No microphone"));
+        let recording = match captures.len() {
+            0 => String::new(),
+            1 => tr_with(
+                "In use by {application}",
+                &[("application", &captures[0].title())],
+            ),
+            count => tr_with(
+                "In use by {count} applications",
+                &[("count", &count.to_string())],
+            ),
+        };
+        let icon = match sink.map(|sink| (sink.mute, sink.volume_percent())) {
+            None | Some((true, _)) | Some((false, 0)) => "audio-volume-muted",
+            Some((false, 1..=33)) => "audio-volume-low",
+            Some((false, 34..=66)) => "audio-volume-medium",
+            Some((false, _)) => "audio-volume-high",
+        };
+        Self {
+            title,
+            icon,
+            description: [
+                volume,
+                microphone,
+                recording,
+                hold.describe().unwrap_or_default(),
+            ]
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+            // the microphone matters more than the routing
+            overlay_icon: match (
+                source.is_some_and(|source| source.mute),
+                captures.is_empty(),
+                hold.is_on(),
+            ) {
+                (true, _, _) => "microphone-sensitivity-muted-symbolic",
+                (false, false, _) => "microphone-sensitivity-high-symbolic",
+                (false, true, true) => "changes-prevent-symbolic",
+                (false, true, false) => "",
+            },
+        }
+    }
+}
+
+/// What the menu shows that [`AppState`] doesn't keep, listed off the main loop so
+/// a hung server never holds up the host asking for the menu.
+#[derive(Default)]
+struct Listing {
+    cards: Vec<Card>,
+    streams: Vec<Stream>,
+}
+
+impl Listing {
+    fn read() -> Self {
+        Self {
+            cards: Tray::or_empty(Card::list()),
+            streams: Tray::or_empty(Stream::list()),
+        }
+    }
+}
+
+struct Tray {
+    state: AppState,
+    dispatcher: Dispatcher,
+    menu: Mutex<Menu>,
+    listing: Mutex<Listing>,
+    revision: AtomicU32,
+    status: Mutex<Status>,
+    connection: Mutex<Option<gio::DBusConnection>>,
+}
+
+impl Tray {
+    fn entries(&self) -> Vec<Entry> {
+        let sinks = self.state.sinks.get_untracked();
+        let sources = self.state.sources.get_untracked();
+        let default_sink = Self::default_device(self.state.default_sink, &sinks);
+        let default_source = Self::default_device(self.state.default_source, &sources);
+        let listing = self.listing.lock().expect("tray listing lock poisoned");
+
+        let outputs = sinks
+            .iter()
+            .map(|sink| {
+                Entry::toggle(
+                    easyeffects::label(sink),
+                    Toggle::Radio(Some(sink) == default_sink),
+                    Action::SetDefault(DeviceKind::Sink, sink.name.clone()),
+                )
+            })
+            .chain(self.state.departed.with_untracked(|departed| {
+                departed
+                    .iter()
+                    .map(|departed| {
+                        Entry::Disabled(tr_with(
+                            "{device} (disconnected)",
+                            &[("device", &departed.device.description)],
+                        ))
+                    })
+                    .collect::<Vec<_>>()
+            }))
+            .collect();
+        let profiles = default_sink
+            .and_then(|sink| {
+                Card::owning(sink, &listing.cards).map(|card| {
+                    card.profiles()
+                        .into_iter()
+                        .map(|(name, profile)| {
+                            Entry::toggle(
+                                &profile.description,
+                                Toggle::Radio(card.active_profile.as_deref() == Some(name)),
+                                Action::SetCardProfile {
+                                    card: card.name.clone(),
+                                    profile: name.to_owned(),
+                                },
+                            )
+                        })
+                        .collect()
+                })
+            })
+            .unwrap_or_default();
+        // where each application plays, like the mixer's output pickers
+        let streams = &listing.streams;
+        let finding = [
+            Entry::item(
+                tr("Find what's playing"),
+                Action::FindPlaying { one_by_one: false },
+            ),
+            Entry::item(
+                tr("Mute one by one"),
+                Action::FindPlaying { one_by_one: true },
+            ),
+            Entry::Separator,
+        ];
+        let applications = finding
+            .into_iter()
+            .filter(|_| !streams.is_empty())
+            .chain(streams.iter().map(|stream| {
+                Entry::Submenu {
+                    label: stream.title(),
+                    children: sinks
+                        .iter()
+                        .map(|sink| {
+                            Entry::toggle(
+                                easyeffects::label(sink),
+                                Toggle::Radio(sink.index == stream.sink),
+                                Action::MoveStream {
+                                    stream: stream.index,
+                                    sink: sink.name.clone(),
+                                },
+                            )
+                        })
+                        .collect(),
+                }
+            }))
+            .collect();
+
+        vec![
+            Entry::Submenu {
+                label: tr("Output device"),
+                children: outputs,
+            },
+            Entry::Submenu {
+                label: tr("Applications"),
+                children: applications,
+            },
+            Entry::toggle(
+                tr("Mute"),
+                Toggle::Check(default_sink.is_some_and(|sink| sink.mute)),
+                Action::ToggleMute(DeviceKind::Sink),
+            ),
+            Entry::toggle(
+                tr("Mute microphone"),
+                Toggle::Check(default_source.is_some_and(|source| source.mute)),
+                Action::ToggleMute(DeviceKind::Source),
+            ),
+            self.channel_mix_toggle(default_sink, ChannelMix::Mono, &tr("Mono audio")),
+            self.channel_mix_toggle(default_sink, ChannelMix::Swap, &tr("Swap left and right")),
+            Entry::Submenu {
+                label: tr("Profile"),
+                children: profiles,
+            },
+            Entry::Submenu {
+                label: tr("Passthrough"),
+                children: default_sink
+                    .map(|sink| {
+                        sinks
+                            .iter()
+                            .find(|real| real.name == filter_chain::master_name(sink))
+                            .unwrap_or(sink)
+                    })
+                    .filter(|sink| passthrough::is_capable(sink))
+                    .map(Self::passthrough_toggles)
+                    .unwrap_or_default(),
+            },
+            Entry::Separator,
+            Entry::toggle(
+                tr("Do not disturb"),
+                Toggle::Check(self.state.do_not_disturb.get_untracked()),
+                Action::ToggleDoNotDisturb,
+            ),
+            Entry::toggle(
+                tr("Night mode"),
+                Toggle::Check(self.state.night_mode.get_untracked()),
+                Action::ToggleNightMode,
+            ),
+            Entry::toggle(
+                tr("Noise suppression"),
+                Toggle::Check(self.state.noise_suppression.with_untracked(Option::is_some)),
+                Action::SetNoiseSuppression(
+                    self.state.noise_suppression.with_untracked(Option::is_none),
+                ),
+            ),
+            Entry::toggle(
+                tr("Switch to displays when connected"),
+                Toggle::Check(
+                    self.state
+                        .device_settings
+                        .with_untracked(|store| store.hdmi_auto_switch),
+                ),
+                Action::ToggleHdmiAutoSwitch,
+            ),
+            match self.state.hold.get_untracked() {
+                Hold::Off => Entry::Submenu {
+                    label: tr("Hold current routing"),
+                    children: hold::DURATIONS
+                        .into_iter()
+                        .map(|(label, minutes)| {
+                            Entry::item(tr(label), Action::HoldRouting { minutes })
+                        })
+                        .collect(),
+                },
+                held => Entry::toggle(
+                    held.describe().unwrap_or_default(),
+                    Toggle::Check(true),
+                    Action::ReleaseRouting,
+                ),
+            },
+            Entry::toggle(
+                tr("Game mode for fullscreen windows"),
+                Toggle::Check(
+                    self.state
+                        .device_settings
+                        .with_untracked(|store| store.game_mode),
+                ),
+                Action::ToggleGameMode,
+            ),
+            Entry::Submenu {
+                label: tr("Volume popup style"),
+                children: OsdStyle::ALL
+                    .into_iter()
+                    .map(|style| {
+                        Entry::toggle(
+                            style.label(),
+                            Toggle::Radio(self.state.osd_style.get_untracked() == style),
+                            Action::SetOsdStyle(style),
+                        )
+                    })
+                    .collect(),
+            },
+            Entry::Submenu {
+                label: tr("Skip when cycling"),
+                children: self.hidden_toggles(&sinks, &sources),
+            },
+            Entry::Submenu {
+                label: tr("Recover after resets"),
+                children: self.recovery_toggles(&sinks, &sources),
+            },
+            Entry::Submenu {
+                label: tr("Forget device"),
+                children: self.remembered_devices(&sinks, &sources),
+            },
+            Entry::toggle(
+                tr("Close to tray"),
+                Toggle::Check(
+                    self.state
+                        .device_settings
+                        .with_untracked(|store| store.close_to_tray),
+                ),
+                Action::ToggleCloseToTray,
+            ),
+            Entry::Separator,
+            Entry::item(tr("Open window"), Action::ShowWindow),
+            Entry::item(tr("Quit"), Action::Quit),
+        ]
+    }
+
+    /// Check item switching `mix` on the real sink behind the default output.
+    fn channel_mix_toggle(&self, sink: Option<&Device>, mix: ChannelMix, label: &str) -> Entry {
+        let master = sink.map_or_else(String::new, |sink| {
+            filter_chain::master_name(sink).to_owned()
+        });
+        let enabled = self.state.device_settings.with_untracked(|store| {
+            store
+                .devices
+                .get(&master)
+                .and_then(|settings| settings.channel_mix)
+                == Some(mix)
+        });
+        Entry::toggle(
+            label,
+            Toggle::Check(enabled),
+            Action::SetChannelMix {
+                device: master,
+                mix: (!enabled).then_some(mix),
+            },
+        )
+    }
+
+    /// Check items for the codecs `sink` may pass through.
+    fn passthrough_toggles(sink: &Device) -> Vec<Entry> {
+        let enabled = passthrough::enabled(sink);
+        Codec::ALL
+            .into_iter()
+            .map(|codec| {
+                let on = enabled.contains(&codec);
+                let codecs = enabled
+                    .iter()
+                    .copied()
+                    .filter(|enabled| *enabled != codec)
+                    .chain((!on).then_some(codec))
+                    .collect();
+                Entry::toggle(
+                    codec.label(),
+                    Toggle::Check(on),
+                    Action::SetPassthrough {
+                        device: sink.name.clone(),
+                        codecs,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Check items leaving devices out of `cycle-sink` and `cycle-source`.
+    fn hidden_toggles(&self, sinks: &[Device], sources: &[Device]) -> Vec<Entry> {
+        self.state.device_settings.with_untracked(|store| {
+            sinks
+                .iter()
+                .chain(sources)
+                .map(|device| {
+                    Entry::toggle(
+                        easyeffects::label(device),
+                        Toggle::Check(store.get(device).is_some_and(|settings| settings.hidden)),
+                        Action::ToggleHidden(device.name.clone()),
+                    )
+                })
+                .collect()
+        })
+    }
+
+    /// Check items putting devices back as they were after they reset themselves, like
+    /// USB DACs power-cycling.
+    fn recovery_toggles(&self, sinks: &[Device], sources: &[Device]) -> Vec<Entry> {
+        self.state.device_settings.with_untracked(|store| {
+            sinks
+                .iter()
+                .chain(sources)
+                .map(|device| {
+                    Entry::toggle(
+                        easyeffects::label(device),
+                        Toggle::Check(
+                            store
+                                .get(device)
+                                .is_some_and(|settings| settings.recover_resets),
+                        ),
+                        Action::ToggleRecoverResets(device.name.clone()),
+                    )
+                })
+                .collect()
+        })
+    }
+
+    /// Devices pipeweld stores anything for, named after the connected ones where possible.
+    fn remembered_devices(&self, sinks: &[Device], sources: &[Device]) -> Vec<Entry> {
+        self.state.device_settings.with_untracked(|store| {
+            store
+                .devices
+                .keys()
+                .map(|name| {
+                    let label = sinks
+                        .iter()
+                        .chain(sources)
+                        .find(|device| &device.name == name)
+                        .map_or(name, |device| &device.description);
+                    Entry::item(label, Action::ForgetDevice(name.clone()))
+                })
+                .collect()
+        })
+    }
+
+    fn or_empty<T>(items: Result<Vec<T>>) -> Vec<T> {
+        items
+            .map_err(|error| warn!(?error, "leaving tray menu section empty"))
+            .unwrap_or_default()
+    }
+
+    /// The entry of `devices` that `default` is, so its radio item is the one on.
+    fn default_device(default: RwSignal<Option<Device>>, devices: &[Device]) -> Option<&Device> {
+        default.with_untracked(|default| {
+            let name = &default.as_ref()?.name;
+            devices.iter().find(|device| &device.name == name)
+        })
+    }
+
+    /// Lists cards and streams anew in the background, and makes the host fetch the
+    /// menu again once they're in.
+    fn refresh(self: &Arc<Self>) {
+        let tray = self.clone();
+        AudioControls::in_background(
+            || Ok(Listing::read()),
+            move |listing: Result<Listing>| {
+                *tray.listing.lock().expect("tray listing lock poisoned") =
+                    listing.unwrap_or_default();
+                let connection = tray
+                    .connection
+                    .lock()
+                    .expect("tray connection lock poisoned")
+                    .clone();
+                if let Some(connection) = connection {
+                    tray.announce(&connection);
+                }
+            },
+        );
+    }
+
+    fn announce(&self, connection: &gio::DBusConnection) {
+        let revision = self.revision.fetch_add(1, Ordering::Relaxed) + 1;
+        connection
+            .emit_signal(
+                None,
+                MENU_PATH,
+                MENU_INTERFACE,
+                "LayoutUpdated",
+                Some(&(revision, 0i32).to_variant()),
+            )
+            .map_err(|error| warn!(?error, "announcing tray menu update"))
+            .ok();
+    }
+
+    fn get_layout(&self, parent: i32) -> Variant {
+        let mut menu = self.menu.lock().expect("tray menu lock poisoned");
+        // the menu is rebuilt whenever the host asks for it from the top, so it always
+        // shows live devices, from what's known already rather than asking the server
+        if parent == 0 {
+            *menu = Menu::new(self.entries());
+            self.revision.fetch_add(1, Ordering::Relaxed);
+        }
+        (self.revision.load(Ordering::Relaxed), menu.layout(parent)).to_variant()
+    }
+
+    fn get_group_properties(&self, ids: Vec<i32>) -> Variant {
+        let menu = self.menu.lock().expect("tray menu lock poisoned");
+        let properties = ids
+            .into_iter()
+            .filter_map(|id| menu.node(id).map(|node| (id, node.properties.clone())))
+            .collect::<Vec<_>>();
+        (properties,).to_variant()
+    }
+
+    #[instrument(skip(self, connection))]
+    fn event(&self, connection: &gio::DBusConnection, id: i32, event: &str) {
+        if event != "clicked" {
+            return;
+        }
+        let action = self
+            .menu
+            .lock()
+            .expect("tray menu lock poisoned")
+            .node(id)
+            .and_then(|node| node.action.clone());
+        if let Some(action) = action {
+            self.dispatcher.dispatch(action);
+            // toggles and radio items changed state, make the host fetch the menu again
+            self.announce(connection);
+        }
+    }
+
+    fn handle_menu_call(
+        &self,
+        connection: &gio::DBusConnection,
+        method: &str,
+        parameters: &Variant,
+    ) -> Option<Variant> {
+        match method {
+            "GetLayout" => parameters
+                .get::<(i32, i32, Vec<String>)>()
+                .map(|(parent, _depth, _properties)| self.get_layout(parent)),
+            "GetGroupProperties" => parameters
+                .get::<(Vec<i32>, Vec<String>)>()
+                .map(|(ids, _properties)| self.get_group_properties(ids)),
+            "GetProperty" => parameters.get::<(i32, String)>().map(|(id, name)| {
+                let menu = self.menu.lock().expect("tray menu lock poisoned");
+                let value = menu
+                    .node(id)
+                    .and_then(|node| node.properties.get(&name).cloned())
+                    .unwrap_or_else(|| "".to_variant());
+                (value,).to_variant()
+            }),
+            "Event" => parameters
+                .get::<(i32, String, Variant, u32)>()
+                .map(|(id, event, _, _)| {
+                    self.event(connection, id, &event);
+                    ().to_variant()
+                }),
+            "EventGroup" => {
+                parameters
+                    .get::<(Vec<(i32, String, Variant, u32)>,)>()
+                    .map(|(events,)| {
+                        for (id, event, _, _) in events {
+                            self.event(connection, id, &event);
+                        }
+                        (Vec::<i32>::new(),).to_variant()
+                    })
+            }
+            "AboutToShow" => Some((true,).to_variant()),
+            "AboutToShowGroup" => parameters
+                .get::<(Vec<i32>,)>()
+                .map(|(ids,)| (ids, Vec::<i32>::new()).to_variant()),
+            _ => None,
+        }
+    }
+
+    fn set_status(&self, status: Status) {
+        let mut current = self.status.lock().expect("tray status lock poisoned");
+        if *current == status {
+            return;
+        }
+        *current = status;
+        let connection = self
+            .connection
+            .lock()
+            .expect("tray connection lock poisoned");
+        for signal in ["NewToolTip", "NewIcon", "NewOverlayIcon"] {
+            connection
+                .iter()
+                .try_for_each(|connection| {
+                    connection.emit_signal(None, ITEM_PATH, ITEM_INTERFACE, signal, None)
+                })
+                .map_err(|error| warn!(?error, signal, "announcing tray status"))
+                .ok();
+        }
+    }
+
+    fn item_property(&self, property: &str) -> Variant {
+        let status = self.status.lock().expect("tray status lock poisoned");
+        match property {
+            "OverlayIconName" => status.overlay_icon.to_variant(),
+            "ToolTip" => (
+                crate::app_id(),
+                Vec::<(i32, i32, Vec<u8>)>::new(),
+                status.title.as_str(),
+                status.description.as_str(),
+            )
+                .to_variant(),
+            "Category" => "Hardware".to_variant(),
+            "Id" | "Title" => clap::crate_name!().to_variant(),
+            "Status" => "Active".to_variant(),
+            "IconName" => status.icon.to_variant(),
+            // clicks open the window, the menu is for the other button
+            "ItemIsMenu" => false.to_variant(),
+            "Menu" => glib::variant::ObjectPath::try_from(MENU_PATH.to_owned())
+                .expect("menu path is a valid object path")
+                .to_variant(),
+            _ => "".to_variant(),
+        }
+    }
+
+    fn handle_item_call(&self, method: &str, parameters: &Variant) {
+        match method {
+            "Activate" => self.dispatcher.dispatch(Action::ShowWindow),
+            "Scroll" => {
+                let Some((delta, orientation)) = parameters.get::<(i32, String)>() else {
+                    return;
+                };
+                if orientation.eq_ignore_ascii_case("vertical") && delta != 0 {
+                    let step = config::get().steps.iter().min().copied().unwrap_or(5) as i32;
+                    let diff = DiffValue(step * delta.signum());
+                    self.dispatcher.dispatch(Action::ChangeVolume(diff));
+                }
+            }
+            // hosts show the menu themselves
+            _ => {}
+        }
+    }
+
+    fn menu_property(property: &str) -> Variant {
+        match property {
+            "Version" => 3u32.to_variant(),
+            "TextDirection" => "ltr".to_variant(),
+            "Status" => "normal".to_variant(),
+            "IconThemePath" => Vec::<String>::new().to_variant(),
+            _ => "".to_variant(),
+        }
+    }
+
+    fn register(self: &Arc<Self>, connection: &gio::DBusConnection) -> Result<(), glib::Error> {
+        let node = gio::DBusNodeInfo::for_xml(INTROSPECTION)?;
+        let item = node
+            .lookup_interface(ITEM_INTERFACE)
+            .expect("item interface is declared");
+        let menu = node
+            .lookup_interface(MENU_INTERFACE)
+            .expect("menu interface is declared");
+        *self
+            .connection
+            .lock()
+            .expect("tray connection lock poisoned") = Some(connection.clone());
+        let tray = self.clone();
+        connection.register_object(
+            ITEM_PATH,
+            &item,
+            {
+                let tray = tray.clone();
+                move |_, _, _, _, method, parameters, invocation| {
+                    tray.handle_item_call(method, &parameters);
+                    invocation.return_value(None);
+                }
+            },
+            move |_, _, _, _, property| tray.item_property(property),
+            |_, _, _, _, _, _| false,
+        )?;
+        let tray = self.clone();
+        connection.register_object(
+            MENU_PATH,
+            &menu,
+            move |connection, _, _, _, method, parameters, invocation| match tray.handle_menu_call(
+                &connection,
+                method,
+                &parameters,
+            ) {
+                Some(reply) => invocation.return_value(Some(&reply)),
+                None => invocation.return_dbus_error(
+                    "org.freedesktop.DBus.Error.InvalidArgs",
+                    &format!("bad call to {method}"),
+                ),
+            },
+            |_, _, _, _, property| Self::menu_property(property),
+            |_, _, _, _, _, _| false,
+        )?;
+        Ok(())
+    }
+}
+
+fn register_with_watcher(connection: &gio::DBusConnection, name: &str) {
+    connection.call(
+        Some("org.kde.StatusNotifierWatcher"),
+        "/StatusNotifierWatcher",
+        "org.kde.StatusNotifierWatcher",
+        "RegisterStatusNotifierItem",
+        Some(&(name,).to_variant()),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+        |result| match result {
+            Ok(_) => info!("tray icon registered"),
+            Err(error) => warn!(%error, "no StatusNotifierWatcher, tray icon won't show up"),
+        },
+    );
+}
+
+/// Publishes the tray icon on the session bus for the lifetime of the process.
+pub fn spawn(cx: Scope, state: AppState, dispatcher: Dispatcher) {
+    let tray = Arc::new(Tray {
+        state,
+        dispatcher,
+        menu: Mutex::default(),
+        listing: Mutex::default(),
+        revision: AtomicU32::new(0),
+        status: Mutex::default(),
+        connection: Mutex::default(),
+    });
+    create_effect(cx, {
+        let tray = tray.clone();
+        move |_| {
+            state.default_sink.with(|sink| {
+                state.default_source.with(|source| {
+                    state.captures.with(|captures| {
+                        state.hold.with(|hold| {
+                            tray.set_status(Status::new(
+                                sink.as_ref(),
+                                source.as_ref(),
+                                captures,
+                                hold,
+                            ))
+                        })
+                    })
+                })
+            })
+        }
+    });
+    tray.refresh();
+    events::subscribe({
+        let tray = tray.clone();
+        move |event| {
+            if matches!(event.facility, Facility::Card | Facility::SinkInput) {
+                tray.refresh();
+            }
+        }
+    });
+    let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+    gio::bus_own_name(
+        gio::BusType::Session,
+        &name,
+        gio::BusNameOwnerFlags::NONE,
+        move |connection, _| {
+            tray.register(&connection)
+                .map_err(|error| warn!(%error, "exporting tray icon"))
+                .ok();
+        },
+        |connection, name| register_with_watcher(&connection, name),
+        |_, name| warn!(name, "lost tray icon bus name"),
+    );
+}
@@ -0,0 +1,321 @@
+//! The usual reasons a device stays silent, checked one after another, each with
+//! the fix when there is one to make from here.
+use std::time::Duration;
+
+use eyre::Result;
+use gtk::{glib, prelude::*, Orientation};
+use leptos::*;
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    alsa,
+    cards::Card,
+    devices::{self, Device, DeviceKind},
+    i18n::tr,
+    state::AppState,
+    ui_state,
+};
+
+/// How long after fixing something to check again.
+const RECHECK_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub problem: String,
+    /// Button label and what it does.
+    pub fix: Option<(String, Action)>,
+}
+
+impl Finding {
+    fn new(problem: impl Into<String>, fix: Option<(&str, Action)>) -> Self {
+        Self {
+            problem: problem.into(),
+            fix: fix.map(|(label, action)| (label.to_owned(), action)),
+        }
+    }
+}
+
+pub fn is_bluetooth(device: &Device) -> bool {
+    device.properties.get("device.api").map(String::as_str) == Some("bluez5")
+        || device.properties.get("device.bus").map(String::as_str) == Some("bluetooth")
+}
+
+/// Bluetooth profiles meant for listening, rather than calls.
+fn is_high_quality(profile: &str) -> bool {
+    profile.contains("a2dp")
+}
+
+fn check_profile(device: &Device, card: &Card, findings: &mut Vec<Finding>) {
+    let profiles = card.profiles();
+    let Some(active) = card.active_profile.as_deref() else {
+        return;
+    };
+    if is_bluetooth(device) {
+        let best = profiles.iter().find(|(name, _)| is_high_quality(name));
+        match best {
+            Some((name, profile)) if !is_high_quality(active) => findings.push(Finding::new(
+                "The headset is in call mode, which sounds muffled.",
+                Some((
+                    &format!("Switch to {}", profile.description),
+                    Action::SetCardProfile {
+                        card: card.name.clone(),
+                        profile: (*name).to_owned(),
+                    },
+                )),
+            )),
+            None => findings.push(Finding::new(
+                "There is no high quality (A2DP) profile. Support for the headset's codecs \
+                 may be missing: install your distribution's PipeWire Bluetooth codec \
+                 packages (e.g. libldac, fdk-aac) and reconnect the headset.",
+                None,
+            )),
+            _ => {}
+        }
+        return;
+    }
+    if let (Some((best, profile)), Some(current)) = (profiles.first(), card.profiles.get(active)) {
+        if *best != active && profile.priority > current.priority {
+            findings.push(Finding::new(
+                format!(
+                    "The card uses the profile {}, {} is usually the better one.",
+                    current.description, profile.description
+                ),
+                Some((
+                    &format!("Switch to {}", profile.description),
+                    Action::SetCardProfile {
+                        card: card.name.clone(),
+                        profile: (*best).to_owned(),
+                    },
+                )),
+            ));
+        }
+    }
+}
+
+fn check_port(kind: DeviceKind, device: &Device, findings: &mut Vec<Finding>) {
+    let active = device
+        .active_port
+        .as_ref()
+        .and_then(|active| device.ports.iter().find(|port| port.name == *active));
+    let alternative = device.ports.iter().find(|port| !port.is_unavailable());
+    match (active, alternative) {
+        (Some(active), Some(alternative)) if active.is_unavailable() => {
+            findings.push(Finding::new(
+                format!("Nothing is plugged into {}.", active.description),
+                Some((
+                    &format!("Use {}", alternative.description),
+                    Action::SetPort {
+                        kind,
+                        device: device.name.clone(),
+                        port: alternative.name.clone(),
+                    },
+                )),
+            ))
+        }
+        (None, Some(alternative)) => findings.push(Finding::new(
+            "No port is selected.",
+            Some((
+                &format!("Use {}", alternative.description),
+                Action::SetPort {
+                    kind,
+                    device: device.name.clone(),
+                    port: alternative.name.clone(),
+                },
+            )),
+        )),
+        _ => {}
+    }
+}
+
+/// Hardware switches of the card behind `device`, like a muted `Master` or `Speaker`.
+fn check_switches(device: &Device, findings: &mut Vec<Finding>) {
+    let Some(card) = device
+        .properties
+        .get("alsa.card")
+        .and_then(|card| card.parse().ok())
+    else {
+        return;
+    };
+    let controls = match alsa::Control::list(card) {
+        Ok(controls) => controls,
+        Err(error) => return warn!(?error, "reading the mixer of ALSA card {card}"),
+    };
+    for control in controls
+        .into_iter()
+        .filter(|control| control.on == Some(false))
+    {
+        let name = control
+            .name
+            .trim_end_matches(",0")
+            .trim_matches('\'')
+            .to_owned();
+        findings.push(Finding::new(
+            format!("The card's {name} switch is off."),
+            Some((
+                &format!("Turn {name} on"),
+                Action::ToggleAlsaMute {
+                    card,
+                    control: control.name,
+                },
+            )),
+        ));
+    }
+}
+
+pub fn check(kind: DeviceKind, device: &Device, cards: &[Card]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if device.mute {
+        findings.push(Finding::new(
+            format!("The {} is muted.", kind.noun()),
+            Some(("Unmute", Action::SetMute(kind, device.name.clone(), false))),
+        ));
+    }
+    if device.volume_percent() == 0 {
+        findings.push(Finding::new(
+            "The volume is all the way down.",
+            Some((
+                "Set to 50%",
+                Action::SetVolume(kind, device.name.clone(), 50),
+            )),
+        ));
+    }
+    if device.state == "SUSPENDED" {
+        findings.push(Finding::new(
+            "The device is suspended. That is normal while nothing plays, but some \
+             hardware doesn't wake up again.",
+            Some((
+                "Wake it up",
+                Action::SetSuspended {
+                    kind,
+                    device: device.name.clone(),
+                    suspended: false,
+                },
+            )),
+        ));
+    }
+    check_port(kind, device, &mut findings);
+    if let Some(card) = Card::owning(device, cards) {
+        check_profile(device, card, &mut findings);
+    }
+    check_switches(device, &mut findings);
+    findings
+}
+
+fn devices() -> Result<Vec<(DeviceKind, Device)>> {
+    let sinks = Device::list(DeviceKind::Sink)?;
+    let sources = Device::list(DeviceKind::Source)?;
+    Ok(sinks
+        .into_iter()
+        .map(|sink| (DeviceKind::Sink, sink))
+        .chain(
+            sources
+                .into_iter()
+                .map(|source| (DeviceKind::Source, source)),
+        )
+        .collect())
+}
+
+fn fill(results: &gtk::Box, name: &str, dispatcher: &Dispatcher) {
+    while let Some(child) = results.first_child() {
+        results.remove(&child);
+    }
+    let findings = devices()
+        .ok()
+        .and_then(|devices| devices.into_iter().find(|(_, device)| device.name == name))
+        .map(|(kind, device)| check(kind, &device, &Card::list().unwrap_or_default()));
+    let Some(findings) = findings else {
+        return results.append(&gtk::Label::new(Some(&tr("The device is gone."))));
+    };
+    if findings.is_empty() {
+        let label = gtk::Label::new(Some(&tr("Found nothing wrong with this device.")));
+        label.add_css_class("success");
+        label.set_halign(gtk::Align::Start);
+        return results.append(&label);
+    }
+    for finding in findings {
+        let row = gtk::Box::new(Orientation::Horizontal, 6);
+        let problem = gtk::Label::new(Some(&finding.problem));
+        problem.set_wrap(true);
+        problem.set_max_width_chars(50);
+        problem.set_hexpand(true);
+        problem.set_halign(gtk::Align::Start);
+        row.append(&problem);
+        if let Some((label, action)) = finding.fix {
+            let fix = gtk::Button::with_label(&label);
+            let dispatcher = dispatcher.clone();
+            let results = results.clone();
+            let name = name.to_owned();
+            fix.connect_clicked(move |_| {
+                dispatcher.dispatch(action.clone());
+                let (results, name, dispatcher) =
+                    (results.clone(), name.clone(), dispatcher.clone());
+                glib::timeout_add_local_once(RECHECK_DELAY, move || {
+                    fill(&results, &name, &dispatcher)
+                });
+            });
+            row.append(&fix);
+        }
+        results.append(&row);
+    }
+}
+
+/// Expander checking the picked device for common problems, the one checked last
+/// picked to begin with.
+pub fn troubleshoot_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let controls = gtk::Box::new(Orientation::Horizontal, 6);
+    let picker = gtk::DropDown::from_strings(&[]);
+    picker.set_hexpand(true);
+    controls.append(&picker);
+    let run = gtk::Button::with_label(&tr("Check"));
+    controls.append(&run);
+    layout.append(&controls);
+    let results = gtk::Box::new(Orientation::Vertical, 4);
+    layout.append(&results);
+
+    let devices = devices::keep_listed(
+        cx,
+        &picker,
+        move || {
+            let sinks = state.sinks.get().into_iter();
+            let sources = state.sources.get().into_iter();
+            sinks
+                .map(|sink| (DeviceKind::Sink, sink))
+                .chain(sources.map(|source| (DeviceKind::Source, source)))
+                .collect()
+        },
+        |(kind, device)| {
+            let label = match kind {
+                DeviceKind::Sink => format!("Output: {}", device.description),
+                DeviceKind::Source => format!("Input: {}", device.description),
+            };
+            (device.name.clone(), label)
+        },
+        move |devices| {
+            ui_state::last_picked(
+                state,
+                devices.iter().map(|(_, device)| device.name.as_str()),
+            )
+            .map(|position| position as usize)
+        },
+    );
+    run.connect_clicked(move |_| {
+        let name = devices.with_untracked(|devices| {
+            devices
+                .get(picker.selected() as usize)
+                .map(|(_, device)| device.name.clone())
+        });
+        if let Some(name) = name {
+            ui_state::set_last_picked(state, &name);
+            fill(&results, &name, &dispatcher);
+        }
+    });
+    gtk::Expander::builder()
+        .label("Troubleshoot")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,516 @@
+//! `pipeweld tui`, a mixer in the terminal for SSH sessions and whoever lives in
+//! one: the outputs, inputs and applications playing, each with a volume bar. It
+//! goes through the same devices, streams and volume limits as the window. The
+//! terminal is put in raw mode with `stty`, the same way the audio is left to
+//! `pactl`, and drawn with plain ANSI escapes. A library like `ratatui` would bring
+//! a terminal backend and its widgets along for one screen of lines, redrawn whole
+//! every second, so the mixer keeps to what every terminal understands.
+//!
+//! Up and down, or `k` and `j`, pick a row. Left and right, `h` and `l` or `-` and
+//! `+`, change its volume, `m` mutes it, `d` or enter makes a device the default
+//! and `q` quits.
+use std::{
+    io::{IsTerminal, Read, Write},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use eyre::{bail, Result, WrapErr};
+
+use crate::{
+    config,
+    device_settings::DeviceStore,
+    devices::{Device, DeviceKind},
+    streams::Stream,
+    AudioControls,
+};
+
+/// How often the lists are read again, for changes made elsewhere.
+const REFRESH: Duration = Duration::from_secs(1);
+/// Longest wait for the rest of an escape sequence, a lone escape quits.
+const ESCAPE: Duration = Duration::from_millis(30);
+/// Highest volume the bars go to, as the window's sliders.
+const TOP: u32 = 100;
+
+const HELP: &str = "↑↓ pick  ←→ volume  m mute  d default  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Quieter,
+    Louder,
+    Mute,
+    Default,
+    Quit,
+}
+
+fn stty(args: &[&str]) -> Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .wrap_err("running stty")?;
+    if !output.status.success() {
+        bail!(
+            "stty {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// The terminal in raw mode on the alternate screen, put back as it was once dropped.
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn enter() -> Result<Self> {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            bail!("the mixer needs a terminal");
+        }
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        // alternate screen, no cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        std::io::stdout().flush().ok();
+        Ok(Self { saved })
+    }
+
+    /// Lines and columns.
+    fn size() -> (usize, usize) {
+        stty(&["size"])
+            .ok()
+            .and_then(|size| {
+                let (lines, columns) = size.split_once(' ')?;
+                Some((lines.parse().ok()?, columns.parse().ok()?))
+            })
+            .unwrap_or((24, 80))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        std::io::stdout().flush().ok();
+        stty(&[&self.saved]).ok();
+    }
+}
+
+/// Bytes typed, read on a thread of their own so the lists refresh meanwhile.
+fn typed() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for byte in std::io::stdin().lock().bytes() {
+            let Ok(byte) = byte else {
+                break;
+            };
+            if sender.send(byte).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn key(first: u8, typed: &Receiver<u8>) -> Option<Key> {
+    match first {
+        // ctrl-c doesn't interrupt in raw mode
+        b'q' | 3 => Some(Key::Quit),
+        b'k' => Some(Key::Up),
+        b'j' => Some(Key::Down),
+        b'h' | b'-' => Some(Key::Quieter),
+        b'l' | b'+' | b'=' => Some(Key::Louder),
+        b'm' => Some(Key::Mute),
+        b'd' | b'\r' | b'\n' => Some(Key::Default),
+        0x1b => match typed.recv_timeout(ESCAPE) {
+            Err(_) => Some(Key::Quit),
+            Ok(b'[' | b'O') => match typed.recv_timeout(ESCAPE) {
+                Ok(b'A') => Some(Key::Up),
+                Ok(b'B') => Some(Key::Down),
+                Ok(b'C') => Some(Key::Louder),
+                Ok(b'D') => Some(Key::Quieter),
+                _ => None,
+            },
+            Ok(_) => None,
+        },
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Row {
+    Heading(&'static str),
+    Device(DeviceKind, Device),
+    Stream(Stream),
+}
+
+/// What a row is about, to keep it picked while the lists change around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Picked {
+    Device(DeviceKind, String),
+    Stream(u32),
+}
+
+impl Row {
+    fn picked(&self) -> Option<Picked> {
+        match self {
+            Self::Heading(_) => None,
+            Self::Device(kind, device) => Some(Picked::Device(*kind, device.name.clone())),
+            Self::Stream(stream) => Some(Picked::Stream(stream.index)),
+        }
+    }
+}
+
+struct Mixer {
+    rows: Vec<Row>,
+    selected: usize,
+    /// Default output and input.
+    defaults: (Option<String>, Option<String>),
+    store: DeviceStore,
+    step: u32,
+    /// The last failure, shown at the bottom.
+    status: Option<String>,
+}
+
+/// A bar `width` wide, filled up to `percent` of [`TOP`].
+fn bar(percent: u32, width: usize) -> String {
+    let filled = (percent.min(TOP) as usize * width + TOP as usize / 2) / TOP as usize;
+    "█".repeat(filled) + &"░".repeat(width - filled)
+}
+
+/// `text` cut or padded to `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    match text.chars().count() > width {
+        true => {
+            text.chars()
+                .take(width.saturating_sub(1))
+                .collect::<String>()
+                + "…"
+        }
+        false => format!("{text:<width$}"),
+    }
+}
+
+impl Mixer {
+    fn refresh(&mut self) -> Result<()> {
+        let picked = self.rows.get(self.selected).and_then(Row::picked);
+        let mut rows = Vec::new();
+        for (kind, heading) in [
+            (DeviceKind::Sink, "Outputs"),
+            (DeviceKind::Source, "Inputs"),
+        ] {
+            rows.push(Row::Heading(heading));
+            rows.extend(
+                Device::list(kind)?
+                    .into_iter()
+                    .map(|device| Row::Device(kind, device)),
+            );
+        }
+        rows.push(Row::Heading("Applications"));
+        rows.extend(Stream::list()?.into_iter().map(Row::Stream));
+        self.defaults = (
+            AudioControls::default_device(DeviceKind::Sink).ok(),
+            AudioControls::default_device(DeviceKind::Source).ok(),
+        );
+        self.selected = picked
+            .and_then(|picked| {
+                rows.iter()
+                    .position(|row| row.picked() == Some(picked.clone()))
+            })
+            .or_else(|| rows.iter().position(|row| row.picked().is_some()))
+            .unwrap_or(0);
+        self.rows = rows;
+        Ok(())
+    }
+
+    fn is_default(&self, kind: DeviceKind, device: &Device) -> bool {
+        let default = match kind {
+            DeviceKind::Sink => &self.defaults.0,
+            DeviceKind::Source => &self.defaults.1,
+        };
+        default.as_deref() == Some(device.name.as_str())
+    }
+
+    /// Picks the next row that isn't a heading, in `direction`.
+    fn pick(&mut self, direction: isize) {
+        let mut index = self.selected;
+        while let Some(next) = index.checked_add_signed(direction) {
+            let Some(row) = self.rows.get(next) else {
+                return;
+            };
+            index = next;
+            if row.picked().is_some() {
+                self.selected = index;
+                return;
+            }
+        }
+    }
+
+    fn change_volume(&self, louder: bool) -> Result<()> {
+        let change = |current: u32, max: u32| match louder {
+            true => current.saturating_add(self.step).min(max),
+            false => current.saturating_sub(self.step),
+        };
+        match self.rows.get(self.selected) {
+            Some(Row::Device(kind, device)) => {
+                // the limits set in the window hold here too
+                let target = change(device.volume_percent(), TOP);
+                AudioControls::set_volume_percent(
+                    *kind,
+                    &device.name,
+                    self.store.limit_volume(device, target),
+                )
+            }
+            Some(Row::Stream(stream)) => {
+                AudioControls::set_stream_volume(stream.index, change(stream.volume_percent(), TOP))
+            }
+            Some(Row::Heading(_)) | None => Ok(()),
+        }
+    }
+
+    fn press(&mut self, key: Key) -> Result<()> {
+        match (key, self.rows.get(self.selected)) {
+            (Key::Up, _) => self.pick(-1),
+            (Key::Down, _) => self.pick(1),
+            (Key::Quieter | Key::Louder, _) => self.change_volume(key == Key::Louder)?,
+            (Key::Mute, Some(Row::Device(kind, device))) => {
+                AudioControls::set_mute(*kind, &device.name, !device.mute)?
+            }
+            (Key::Mute, Some(Row::Stream(stream))) => {
+                AudioControls::set_stream_mute(stream.index, !stream.mute)?
+            }
+            (Key::Default, Some(Row::Device(kind, device))) => {
+                AudioControls::set_default(*kind, &device.name)?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn line(&self, row: &Row, columns: usize) -> String {
+        let name_width = (columns / 3).max(12);
+        let bar_width = columns.saturating_sub(name_width + 16).max(5);
+        let (marker, name, percent, mute, output) = match row {
+            Row::Heading(heading) => return (*heading).to_owned(),
+            Row::Device(kind, device) => (
+                match self.is_default(*kind, device) {
+                    true => '*',
+                    false => ' ',
+                },
+                device.description.clone(),
+                device.volume_percent(),
+                device.mute,
+                None,
+            ),
+            Row::Stream(stream) => (
+                ' ',
+                stream.title(),
+                stream.volume_percent(),
+                stream.mute,
+                self.rows.iter().find_map(|row| match row {
+                    Row::Device(DeviceKind::Sink, sink) if sink.index == stream.sink => {
+                        Some(sink.description.clone())
+                    }
+                    _ => None,
+                }),
+            ),
+        };
+        let mut line = format!(
+            " {marker} {} {} {percent:>3}%",
+            fit(&name, name_width),
+            bar(percent, bar_width)
+        );
+        if mute {
+            line += " muted";
+        }
+        if let Some(output) = output {
+            line += &format!(" → {output}");
+        }
+        line
+    }
+
+    fn draw(&self, (lines, columns): (usize, usize)) -> Result<()> {
+        // a line for the help on top, and one for failures at the bottom
+        let room = lines.saturating_sub(2).max(1);
+        let first = self.selected.saturating_sub(room - 1);
+        let mut screen = format!("\x1b[H\x1b[2J\x1b[1mpipeweld\x1b[0m  {HELP}\r\n");
+        for (index, row) in self.rows.iter().enumerate().skip(first).take(room) {
+            let line = fit(&self.line(row, columns), columns);
+            match (index == self.selected, row) {
+                // reversed, for the picked row
+                (true, _) => screen += &format!("\x1b[7m{line}\x1b[0m\r\n"),
+                (false, Row::Heading(_)) => {
+                    screen += &format!("\x1b[1m{}\x1b[0m\r\n", line.trim_end())
+                }
+                (false, _) => screen += &format!("{}\r\n", line.trim_end()),
+            }
+        }
+        if let Some(status) = &self.status {
+            screen += &format!("\x1b[{lines};1H\x1b[31m{}\x1b[0m", fit(status, columns));
+        }
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Runs the mixer until `q` is pressed.
+pub fn run() -> Result<()> {
+    let mut mixer = Mixer {
+        rows: Vec::new(),
+        selected: 0,
+        defaults: (None, None),
+        store: DeviceStore::load()?,
+        step: config::get().steps.iter().min().copied().unwrap_or(5),
+        status: None,
+    };
+    // before taking over the terminal, so a server that isn't there fails as usual
+    mixer.refresh()?;
+    let terminal = Terminal::enter()?;
+    let typed = typed();
+    loop {
+        mixer.draw(Terminal::size())?;
+        let pressed = match typed.recv_timeout(REFRESH) {
+            Ok(byte) => key(byte, &typed),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Key::Quit),
+        };
+        match pressed {
+            Some(Key::Quit) => break,
+            Some(key) => mixer.status = mixer.press(key).err().map(|error| format!("{error:#}")),
+            None => {}
+        }
+        if let Err(error) = mixer.refresh() {
+            mixer.status = Some(format!("{error:#}"));
+        }
+    }
+    drop(terminal);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::devices::VOLUME_NORM;
+
+    fn device(index: u32, name: &str, percent: u32, mute: bool) -> Device {
+        serde_json::from_value(json!({
+            "index": index,
+            "name": name,
+            "description": name,
+            "mute": mute,
+            "volume": { "mono": { "value": percent * VOLUME_NORM / 100 } },
+            "active_port": null,
+        }))
+        .unwrap()
+    }
+
+    fn stream(index: u32, sink: u32, application: &str) -> Stream {
+        serde_json::from_value(json!({
+            "index": index,
+            "sink": sink,
+            "mute": false,
+            "volume": { "mono": { "value": VOLUME_NORM / 2 } },
+            "properties": { "application.name": application },
+        }))
+        .unwrap()
+    }
+
+    fn mixer() -> Mixer {
+        Mixer {
+            rows: vec![
+                Row::Heading("Outputs"),
+                Row::Device(DeviceKind::Sink, device(1, "Speakers", 40, false)),
+                Row::Device(DeviceKind::Sink, device(2, "Headphones", 75, true)),
+                Row::Heading("Inputs"),
+                Row::Heading("Applications"),
+                Row::Stream(stream(7, 2, "Music")),
+            ],
+            selected: 1,
+            defaults: (Some("Headphones".to_owned()), None),
+            store: DeviceStore::default(),
+            step: 5,
+            status: None,
+        }
+    }
+
+    /// The key `bytes` make, as if typed all at once.
+    fn typed_key(bytes: &[u8]) -> Option<Key> {
+        let (sender, typed) = mpsc::channel();
+        for byte in &bytes[1..] {
+            sender.send(*byte).unwrap();
+        }
+        key(bytes[0], &typed)
+    }
+
+    #[test]
+    fn keys_and_escape_sequences() {
+        let cases: &[(&[u8], Option<Key>)] = &[
+            (b"k", Some(Key::Up)),
+            (b"j", Some(Key::Down)),
+            (b"-", Some(Key::Quieter)),
+            (b"=", Some(Key::Louder)),
+            (b"m", Some(Key::Mute)),
+            (b"\r", Some(Key::Default)),
+            (b"q", Some(Key::Quit)),
+            (&[3], Some(Key::Quit)),
+            (b"\x1b[A", Some(Key::Up)),
+            (b"\x1bOB", Some(Key::Down)),
+            (b"\x1b[C", Some(Key::Louder)),
+            (b"\x1b[D", Some(Key::Quieter)),
+            // a lone escape quits, other sequences do nothing
+            (b"\x1b", Some(Key::Quit)),
+            (b"\x1b[H", None),
+            (b"x", None),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(typed_key(bytes), *expected, "{bytes:?}");
+        }
+    }
+
+    #[test]
+    fn bars_fill_up_to_the_top() {
+        assert_eq!(bar(0, 4), "░░░░");
+        assert_eq!(bar(50, 4), "██░░");
+        assert_eq!(bar(TOP + 50, 4), "████");
+    }
+
+    #[test]
+    fn text_fits_its_width_in_characters() {
+        assert_eq!(fit("żółw", 6), "żółw  ");
+        assert_eq!(fit("Headphones", 5), "Head…");
+        assert_eq!(fit("", 0), "");
+    }
+
+    #[test]
+    fn picking_skips_headings_and_stops_at_the_ends() {
+        let mut mixer = mixer();
+        mixer.pick(-1);
+        assert_eq!(mixer.selected, 1);
+        mixer.pick(1);
+        assert_eq!(mixer.selected, 2);
+        mixer.pick(1);
+        assert_eq!(mixer.selected, 5);
+        mixer.pick(1);
+        assert_eq!(mixer.selected, 5);
+    }
+
+    #[test]
+    fn lines_tell_the_default_mute_and_output() {
+        let mixer = mixer();
+        let line = |row| mixer.line(&mixer.rows[row], 60);
+        assert!(line(1).starts_with("   Speakers"), "{}", line(1));
+        assert!(line(1).ends_with(" 40%"), "{}", line(1));
+        assert!(line(2).starts_with(" * Headphones"), "{}", line(2));
+        assert!(line(2).ends_with(" 75% muted"), "{}", line(2));
+        assert!(line(5).ends_with(" 50% → Headphones"), "{}", line(5));
+        assert_eq!(line(0), "Outputs");
+    }
+}
@@ -0,0 +1,147 @@
+//! How the window was left, brought back the next time it opens: its size and
+//! whether it was maximized, which panels were expanded, by their label, and the
+//! device last picked in panels checking one device at a time, like
+//! [`crate::troubleshoot`]. It's kept apart from the configuration, in the XDG state
+//! directory, and written as pipeweld closes its window or quits. GTK 4 leaves where
+//! the window goes to the compositor, so its position isn't kept.
+use std::{collections::BTreeSet, path::PathBuf};
+
+use eyre::{Result, WrapErr};
+use gtk::{glib, prelude::*};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{config::WindowSize, state::AppState};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    /// Size of the window when it wasn't maximized.
+    pub size: Option<WindowSize>,
+    pub maximized: bool,
+    /// Labels of the expanded panels.
+    pub expanded: BTreeSet<String>,
+    /// Name of the device last picked.
+    pub last_device: Option<String>,
+}
+
+/// `$XDG_STATE_HOME/pipeweld`, for what pipeweld keeps that isn't settings.
+pub fn state_directory() -> PathBuf {
+    // glib only knows the state directory from 2.72 on
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| glib::home_dir().join(".local").join("state"))
+        .join(clap::crate_name!())
+}
+
+impl UiState {
+    pub fn path() -> PathBuf {
+        state_directory().join("ui.toml")
+    }
+
+    /// Reads the state, starting out empty when there is none yet.
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).wrap_err_with(|| format!("parsing {}", path.display()))
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error).wrap_err_with(|| format!("reading {}", path.display())),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+        }
+        let contents = toml::to_string(self).wrap_err("serializing the window's state")?;
+        std::fs::write(&path, contents).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+}
+
+/// Writes the state as it is now, only logging when it can't be.
+pub fn save(state: AppState) {
+    if let Err(error) = state.ui.with_untracked(UiState::save) {
+        warn!(?error, "saving the window's state");
+    }
+}
+
+/// Position of the device last picked among `names`, if it's there.
+pub fn last_picked<'a>(state: AppState, names: impl IntoIterator<Item = &'a str>) -> Option<u32> {
+    let last = state.ui.with_untracked(|ui| ui.last_device.clone())?;
+    names
+        .into_iter()
+        .position(|name| name == last)
+        .map(|position| position as u32)
+}
+
+pub fn set_last_picked(state: AppState, name: &str) {
+    if state
+        .ui
+        .with_untracked(|ui| ui.last_device.as_deref() != Some(name))
+    {
+        state.ui.update(|ui| ui.last_device = Some(name.to_owned()));
+    }
+}
+
+pub(crate) fn expanders(widget: &gtk::Widget, found: &mut Vec<gtk::Expander>) {
+    if let Some(expander) = widget.downcast_ref::<gtk::Expander>() {
+        found.push(expander.clone());
+    }
+    let mut child = widget.first_child();
+    while let Some(widget) = child {
+        expanders(&widget, found);
+        child = widget.next_sibling();
+    }
+}
+
+/// Gives the window its size and expands its panels as they were left, then follows
+/// the user changing them. Expanding a panel runs what it does on opening, like
+/// listing devices, as if the user had.
+pub fn restore(state: AppState, window: &gtk::ApplicationWindow) {
+    let ui = state.ui.get_untracked();
+    if let Some(size) = ui.size {
+        window.set_default_size(size.width, size.height);
+    }
+    if ui.maximized {
+        window.maximize();
+    }
+    let follow_size = move |window: &gtk::ApplicationWindow| {
+        // the default size follows resizing, but not maximizing
+        if window.is_maximized() {
+            return;
+        }
+        let (width, height) = window.default_size();
+        let size = (width > 0 && height > 0).then_some(WindowSize { width, height });
+        state.ui.update(|ui| ui.size = size);
+    };
+    window.connect_default_width_notify(follow_size);
+    window.connect_default_height_notify(follow_size);
+    window.connect_maximized_notify(move |window| {
+        state.ui.update(|ui| ui.maximized = window.is_maximized());
+    });
+
+    let mut found = Vec::new();
+    expanders(window.upcast_ref(), &mut found);
+    for expander in found {
+        let Some(label) = expander.label().map(|label| label.to_string()) else {
+            continue;
+        };
+        if ui.expanded.contains(&label) {
+            expander.set_expanded(true);
+        }
+        expander.connect_expanded_notify(move |expander| {
+            state.ui.update(|ui| {
+                match expander.is_expanded() {
+                    true => ui.expanded.insert(label.clone()),
+                    false => ui.expanded.remove(&label),
+                };
+            });
+        });
+    }
+}
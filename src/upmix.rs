@@ -0,0 +1,183 @@
+//! Spreading stereo over surround speakers. PipeWire upmixes in the converter of
+//! each playback stream, so a sink's upmix settings are pushed onto every stream
+//! that plays on it.
+use std::{cell::Cell, process::Command, rc::Rc};
+
+use eyre::{eyre, Result};
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    filter_chain,
+    i18n::tr,
+    run_change,
+    state::AppState,
+    streams::Stream,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpmixMethod {
+    /// Copies front channels to the rear ones.
+    #[default]
+    Simple,
+    /// Extracts a center and ambience from the stereo image.
+    Psd,
+}
+
+impl UpmixMethod {
+    pub const ALL: [Self; 2] = [Self::Simple, Self::Psd];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Simple => "Simple",
+            Self::Psd => "Phase-based (PSD)",
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            Self::Simple => "simple",
+            Self::Psd => "psd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Upmix {
+    pub enabled: bool,
+    pub method: UpmixMethod,
+    /// Low-pass for the generated LFE channel, in Hz, `None` leaves LFE silent.
+    pub lfe_cutoff: Option<u32>,
+}
+
+impl Upmix {
+    /// `Props` parameters carrying these settings, in SPA-JSON.
+    fn params(&self) -> String {
+        format!(
+            r#"{{ params = [ "channelmix.upmix" {} "channelmix.upmix-method" "{}" "channelmix.lfe-cutoff" {} ] }}"#,
+            self.enabled,
+            self.method.key(),
+            self.lfe_cutoff.unwrap_or_default()
+        )
+    }
+}
+
+/// Reconfigures the converter of `stream`.
+#[instrument(skip(stream), fields(stream = stream.index), ret, err)]
+pub fn apply(stream: &Stream, upmix: &Upmix) -> Result<()> {
+    let node = stream
+        .properties
+        .get("object.id")
+        .ok_or_else(|| eyre!("stream {} has no node id", stream.index))?;
+    run_change(Command::new("pw-cli").args(["set-param", node, "Props", &upmix.params()])).map(drop)
+}
+
+/// Switch for the real sink behind the default output, with the details in an expander.
+pub fn upmix_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Box {
+    let panel = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build();
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    let label = gtk::Label::new(Some(&tr("Upmix stereo to surround")));
+    label.set_hexpand(true);
+    label.set_halign(gtk::Align::Start);
+    row.append(&label);
+    let enabled = gtk::Switch::new();
+    row.append(&enabled);
+    panel.append(&row);
+
+    let advanced = gtk::Box::new(Orientation::Horizontal, 6);
+    let method = gtk::DropDown::from_strings(&UpmixMethod::ALL.map(UpmixMethod::label));
+    advanced.append(&method);
+    advanced.append(&gtk::Label::new(Some(&tr("LFE cutoff (Hz, 0 for none)"))));
+    let lfe_cutoff = gtk::SpinButton::with_range(0., 500., 10.);
+    advanced.append(&lfe_cutoff);
+    let expander = gtk::Expander::builder()
+        .label("Advanced")
+        .child(&advanced)
+        .build();
+    panel.append(&expander);
+
+    let master = create_memo(cx, move |_| {
+        state.default_sink.with(|sink| {
+            sink.as_ref()
+                .map(|sink| filter_chain::master_name(sink).to_owned())
+        })
+    });
+    let current = create_memo(cx, move |_| {
+        master.get().map(|master| {
+            state.device_settings.with(|store| {
+                store
+                    .devices
+                    .get(&master)
+                    .and_then(|settings| settings.upmix)
+                    .unwrap_or_default()
+            })
+        })
+    });
+    let read = {
+        let enabled = enabled.clone();
+        let method = method.clone();
+        let lfe_cutoff = lfe_cutoff.clone();
+        move || Upmix {
+            enabled: enabled.is_active(),
+            method: UpmixMethod::ALL
+                .get(method.selected() as usize)
+                .copied()
+                .unwrap_or_default(),
+            lfe_cutoff: Some(lfe_cutoff.value() as u32).filter(|cutoff| *cutoff > 0),
+        }
+    };
+    // set while the widgets are being filled in from the store, which notifies too
+    let syncing = Rc::new(Cell::new(false));
+    let changed = {
+        let syncing = syncing.clone();
+        move || {
+            if syncing.get() {
+                return;
+            }
+            if let Some(device) = master.get_untracked() {
+                dispatcher.dispatch(Action::SetUpmix {
+                    device,
+                    upmix: Some(read()),
+                });
+            }
+        }
+    };
+    enabled.connect_active_notify({
+        let changed = changed.clone();
+        move |_| changed()
+    });
+    method.connect_selected_notify({
+        let changed = changed.clone();
+        move |_| changed()
+    });
+    lfe_cutoff.connect_value_changed(move |_| changed());
+
+    create_effect(cx, move |_| {
+        let upmix = current.get();
+        enabled.set_sensitive(upmix.is_some());
+        expander.set_sensitive(upmix.is_some());
+        let upmix = upmix.unwrap_or_default();
+        syncing.set(true);
+        enabled.set_active(upmix.enabled);
+        method.set_selected(
+            UpmixMethod::ALL
+                .iter()
+                .position(|method| *method == upmix.method)
+                .unwrap_or_default() as u32,
+        );
+        lfe_cutoff.set_value(upmix.lfe_cutoff.unwrap_or_default().into());
+        syncing.set(false);
+    });
+    panel
+}
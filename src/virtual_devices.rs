@@ -0,0 +1,508 @@
+//! Virtual devices the user puts together: null sinks for streaming software to
+//! record, microphones looped back to an output to hear oneself, outputs
+//! combining two others, sources made of some of an interface's inputs, see
+//! [`crate::input_routing`], and stereo outputs made of two mono speakers, or of
+//! channels of two devices. They're server modules tagged with
+//! [`crate::modules::OBJECT_TAG`], which don't outlive the server, so they're
+//! kept in the device store and loaded again on startup.
+use std::collections::BTreeMap;
+
+use eyre::{bail, Result};
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    delay::MAX_DELAY_MS,
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    modules::{self, Module},
+    state::AppState,
+    streams::Stream,
+};
+
+/// Prefix of the object name of virtual devices, `virtual:<name>`, followed by the
+/// side for the modules making each side of a stereo pair.
+const VIRTUAL: &str = "virtual";
+/// Latency of the loopbacks playing each side of a stereo pair, which their delay
+/// comes on top of.
+const PAIR_LATENCY_MS: u32 = 20;
+
+/// One side of a stereo pair: `channel`, as in the channel map, of the sink `sink`,
+/// `mono` for mono speakers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Side {
+    pub sink: String,
+    pub channel: String,
+    /// Gain in dB, below 0 to turn the side down.
+    #[serde(default)]
+    pub trim_db: i32,
+    /// Holds the side back, for speakers further away than the other one.
+    #[serde(default)]
+    pub delay_ms: u32,
+}
+
+impl Side {
+    /// Volume of the side's stream, in percent, which goes by the cube of the gain.
+    pub fn volume_percent(&self) -> u32 {
+        (100. * 10f64.powf(f64::from(self.trim_db) / 60.)).round() as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum VirtualDevice {
+    /// An output playing nowhere, whose monitor streaming software records.
+    NullSink,
+    /// Microphone `source` played on `sink`, for monitoring.
+    Loopback { source: String, sink: String },
+    /// An output playing to each of `outputs` at once.
+    Combine { outputs: Vec<String> },
+    /// The channels `inputs` of `source`, a mono source for one and a stereo one
+    /// for two.
+    Remap { source: String, inputs: Vec<String> },
+    /// A stereo output playing its left channel on `left` and its right one on
+    /// `right`, boxed as they'd make every action as large as both.
+    StereoPair { left: Box<Side>, right: Box<Side> },
+}
+
+/// `name` with anything but letters and digits replaced, so it fits in module arguments.
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Name of the sink made for virtual device `name`, null sinks and combined ones.
+pub fn sink_name(name: &str) -> String {
+    format!("{}.virtual.{}", clap::crate_name!(), slug(name))
+}
+
+/// Name of the source made for virtual device `name`, remapped inputs.
+pub fn source_name(name: &str) -> String {
+    sink_name(name)
+}
+
+/// Modules of virtual devices, with the slug of their name.
+fn loaded() -> Result<Vec<(String, Module)>> {
+    Ok(Module::list()?
+        .into_iter()
+        .filter_map(|module| {
+            let slug = module
+                .object()?
+                .strip_prefix(VIRTUAL)?
+                .strip_prefix(':')?
+                .split(':')
+                .next()?
+                .to_owned();
+            Some((slug, module))
+        })
+        .collect())
+}
+
+impl VirtualDevice {
+    /// Loads the module making the device called `name`.
+    #[instrument(ret, err)]
+    pub fn load(&self, name: &str) -> Result<u32> {
+        let tag = Module::tag(&format!("{VIRTUAL}:{}", slug(name)));
+        let description = name.replace(['\'', '"'], "");
+        let sink_properties =
+            format!("sink_properties=\"device.description='{description}' {tag}\"");
+        match self {
+            Self::NullSink => Module::load(
+                "module-null-sink",
+                &[format!("sink_name={}", sink_name(name)), sink_properties],
+            ),
+            Self::Loopback { source, sink } => Module::load(
+                "module-loopback",
+                &[
+                    format!("source={source}"),
+                    format!("sink={sink}"),
+                    "source_dont_move=true".to_owned(),
+                    "sink_dont_move=true".to_owned(),
+                    format!("sink_input_properties={tag}"),
+                ],
+            ),
+            Self::Combine { outputs } => {
+                if outputs.len() < 2 {
+                    bail!("combining takes two outputs or more");
+                }
+                Module::load(
+                    "module-combine-sink",
+                    &[
+                        format!("sink_name={}", sink_name(name)),
+                        format!("slaves={}", outputs.join(",")),
+                        sink_properties,
+                    ],
+                )
+            }
+            Self::Remap { source, inputs } => {
+                let channel_map = match inputs.len() {
+                    1 => "mono",
+                    2 => "front-left,front-right",
+                    _ => bail!("a remapped source takes one input or two"),
+                };
+                Module::load(
+                    "module-remap-source",
+                    &[
+                        format!("source_name={}", source_name(name)),
+                        format!("master={source}"),
+                        format!("channels={}", inputs.len()),
+                        format!("master_channel_map={}", inputs.join(",")),
+                        format!("channel_map={channel_map}"),
+                        "remix=no".to_owned(),
+                        format!("source_properties=\"device.description='{description}' {tag}\""),
+                    ],
+                )
+            }
+            Self::StereoPair { left, right } => {
+                let module = Module::load(
+                    "module-null-sink",
+                    &[
+                        format!("sink_name={}", sink_name(name)),
+                        "channels=2".to_owned(),
+                        "channel_map=front-left,front-right".to_owned(),
+                        sink_properties,
+                    ],
+                )?;
+                load_side(name, "front-left", left)?;
+                load_side(name, "front-right", right)?;
+                Ok(module)
+            }
+        }
+    }
+
+    /// What the device does, for showing next to its name.
+    fn describe(&self, sources: &[Device], sinks: &[Device]) -> String {
+        let description = |devices: &[Device], name: &str| {
+            devices
+                .iter()
+                .find(|device| device.name == name)
+                .map_or_else(|| name.to_owned(), |device| device.description.clone())
+        };
+        match self {
+            Self::NullSink => "Null output".to_owned(),
+            Self::Loopback { source, sink } => format!(
+                "{} → {}",
+                description(sources, source),
+                description(sinks, sink)
+            ),
+            Self::Combine { outputs } => outputs
+                .iter()
+                .map(|output| description(sinks, output))
+                .collect::<Vec<_>>()
+                .join(" + "),
+            Self::Remap { source, inputs } => {
+                format!("{}: {}", description(sources, source), inputs.join(" + "))
+            }
+            Self::StereoPair { left, right } => {
+                let side = |side: &Side| {
+                    let mut described =
+                        format!("{} ({})", description(sinks, &side.sink), side.channel);
+                    if side.trim_db != 0 {
+                        described.push_str(&format!(", {:+} dB", side.trim_db));
+                    }
+                    if side.delay_ms != 0 {
+                        described.push_str(&format!(", {} ms later", side.delay_ms));
+                    }
+                    described
+                };
+                format!("Left: {}, right: {}", side(left), side(right))
+            }
+        }
+    }
+}
+
+/// Plays `position` of stereo pair `name` on its side: a one-channel sink remapped
+/// onto the side's channel, and a loopback from the pair's monitor into it, tagged
+/// so [`side_volume`] finds it.
+fn load_side(name: &str, position: &str, side: &Side) -> Result<()> {
+    let object = format!("{VIRTUAL}:{}:{position}", slug(name));
+    let remapped = format!("{}.{position}", sink_name(name));
+    let description = format!("{} ({position})", name.replace(['\'', '"'], ""));
+    Module::load(
+        "module-remap-sink",
+        &[
+            format!("sink_name={remapped}"),
+            format!("master={}", side.sink),
+            "channels=1".to_owned(),
+            format!("channel_map={position}"),
+            format!("master_channel_map={}", side.channel),
+            "remix=no".to_owned(),
+            format!(
+                "sink_properties=\"device.description='{description}' {}\"",
+                Module::tag(&object)
+            ),
+        ],
+    )?;
+    Module::load(
+        "module-loopback",
+        &[
+            format!("source={}.monitor", sink_name(name)),
+            format!("sink={remapped}"),
+            "channels=1".to_owned(),
+            format!("channel_map={position}"),
+            "remix=no".to_owned(),
+            format!(
+                "latency_msec={}",
+                PAIR_LATENCY_MS + side.delay_ms.min(MAX_DELAY_MS)
+            ),
+            "source_dont_move=true".to_owned(),
+            "sink_dont_move=true".to_owned(),
+            format!("sink_input_properties={}", Module::tag(&object)),
+        ],
+    )
+    .map(drop)
+}
+
+/// The volume trimming the side of a stereo pair of `devices` that `stream` plays,
+/// `None` for other streams.
+pub fn side_volume(devices: &BTreeMap<String, VirtualDevice>, stream: &Stream) -> Option<u32> {
+    let mut parts = stream
+        .properties
+        .get(modules::OBJECT_TAG)?
+        .strip_prefix(VIRTUAL)?
+        .strip_prefix(':')?
+        .split(':');
+    let (pair, position) = (parts.next()?, parts.next()?);
+    let (left, right) = devices.iter().find_map(|(name, device)| match device {
+        VirtualDevice::StereoPair { left, right } if slug(name) == pair => Some((left, right)),
+        _ => None,
+    })?;
+    match position {
+        "front-left" => Some(left.volume_percent()),
+        "front-right" => Some(right.volume_percent()),
+        _ => None,
+    }
+}
+
+pub fn unload(name: &str) -> Result<()> {
+    loaded()?
+        .into_iter()
+        .filter(|(loaded, _)| *loaded == slug(name))
+        .try_for_each(|(_, module)| Module::unload(module.index))
+}
+
+/// Loads every device of `devices` that isn't yet, null sinks first as the others
+/// may play to them.
+pub fn restore<'a>(devices: impl IntoIterator<Item = (&'a String, &'a VirtualDevice)>) {
+    let present = match loaded() {
+        Ok(loaded) => loaded.into_iter().map(|(slug, _)| slug).collect::<Vec<_>>(),
+        Err(error) => return warn!(?error, "restoring virtual devices"),
+    };
+    let mut missing = devices
+        .into_iter()
+        .filter(|(name, _)| !present.contains(&slug(name)))
+        .collect::<Vec<_>>();
+    missing.sort_by_key(|(_, device)| **device != VirtualDevice::NullSink);
+    for (name, device) in missing {
+        info!("restoring virtual device {name}");
+        device.load(name).ok();
+    }
+}
+
+fn picker(devices: &[Device]) -> gtk::DropDown {
+    gtk::DropDown::from_strings(
+        &devices
+            .iter()
+            .map(|device| device.description.as_str())
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// A row picking one side of a stereo pair: an output, one of its channels, a trim
+/// and a delay. Returns the row and a way to read the side picked.
+fn side_form(label: &str, sinks: &[Device]) -> (gtk::Box, impl Fn() -> Option<Side>) {
+    let row = gtk::Box::new(Orientation::Horizontal, 6);
+    row.append(&gtk::Label::new(Some(label)));
+    let sink = picker(sinks);
+    sink.set_hexpand(true);
+    row.append(&sink);
+    let channel = gtk::DropDown::from_strings(&[]);
+    row.append(&channel);
+    let trim = gtk::SpinButton::with_range(-20., 20., 1.);
+    row.append(&trim);
+    row.append(&gtk::Label::new(Some(&tr("dB"))));
+    let delay = gtk::SpinButton::with_range(0., MAX_DELAY_MS.into(), 1.);
+    row.append(&delay);
+    row.append(&gtk::Label::new(Some(&tr("ms"))));
+    let channels = sinks
+        .iter()
+        .map(|sink| {
+            sink.channels()
+                .into_iter()
+                .map(|(channel, _)| channel)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let fill = {
+        let channel = channel.clone();
+        let channels = channels.clone();
+        move |sink: &gtk::DropDown| {
+            let names = channels
+                .get(sink.selected() as usize)
+                .map(|names| names.iter().map(String::as_str).collect::<Vec<_>>())
+                .unwrap_or_default();
+            channel.set_model(Some(&gtk::StringList::new(&names)));
+        }
+    };
+    fill(&sink);
+    sink.connect_selected_notify(fill);
+    let names = sinks
+        .iter()
+        .map(|sink| sink.name.clone())
+        .collect::<Vec<_>>();
+    let read = move || {
+        let picked = sink.selected() as usize;
+        Some(Side {
+            sink: names.get(picked)?.clone(),
+            channel: channels
+                .get(picked)?
+                .get(channel.selected() as usize)?
+                .clone(),
+            trim_db: trim.value() as i32,
+            delay_ms: delay.value() as u32,
+        })
+    };
+    (row, read)
+}
+
+/// Expander listing the virtual devices with a remove button each, and a form
+/// creating new ones.
+pub fn virtual_devices_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&rows);
+
+    let sources = Device::list(DeviceKind::Source)
+        .map_err(|error| warn!(?error, "listing sources for virtual devices"))
+        .unwrap_or_default();
+    let sinks = Device::list(DeviceKind::Sink)
+        .map_err(|error| warn!(?error, "listing sinks for virtual devices"))
+        .unwrap_or_default();
+    let form = gtk::Box::new(Orientation::Vertical, 4);
+    let kind = gtk::DropDown::from_strings(&[
+        "Null output",
+        "Microphone loopback",
+        "Combined output",
+        "Stereo pair",
+    ]);
+    form.append(&kind);
+    let name = gtk::Entry::builder()
+        .placeholder_text(tr("Device name"))
+        .build();
+    form.append(&name);
+    // the loopback takes a source and an output, combining two outputs
+    let source = picker(&sources);
+    let first = picker(&sinks);
+    let second = picker(&sinks);
+    form.append(&source);
+    form.append(&first);
+    form.append(&second);
+    // a stereo pair takes an output and one of its channels for each side
+    let (left, read_left) = side_form("Left", &sinks);
+    let (right, read_right) = side_form("Right", &sinks);
+    form.append(&left);
+    form.append(&right);
+    let shown = {
+        let (source, first, second) = (source.clone(), first.clone(), second.clone());
+        move |kind: &gtk::DropDown| {
+            source.set_visible(kind.selected() == 1);
+            first.set_visible(matches!(kind.selected(), 1 | 2));
+            second.set_visible(kind.selected() == 2);
+            left.set_visible(kind.selected() == 3);
+            right.set_visible(kind.selected() == 3);
+        }
+    };
+    shown(&kind);
+    kind.connect_selected_notify(shown);
+    let create = gtk::Button::with_label(&tr("Create device"));
+    create.set_halign(gtk::Align::Start);
+    form.append(&create);
+    layout.append(&form);
+    create.connect_clicked({
+        let dispatcher = dispatcher.clone();
+        let sources = sources.clone();
+        let sinks = sinks.clone();
+        move |_| {
+            let device_name = name.text().trim().to_owned();
+            let picked = |devices: &[Device], picker: &gtk::DropDown| {
+                devices
+                    .get(picker.selected() as usize)
+                    .map(|device| device.name.clone())
+            };
+            let device = match kind.selected() {
+                0 => Some(VirtualDevice::NullSink),
+                1 => picked(&sources, &source)
+                    .zip(picked(&sinks, &first))
+                    .map(|(source, sink)| VirtualDevice::Loopback { source, sink }),
+                2 => picked(&sinks, &first)
+                    .zip(picked(&sinks, &second))
+                    .filter(|(first, second)| first != second)
+                    .map(|(first, second)| VirtualDevice::Combine {
+                        outputs: vec![first, second],
+                    }),
+                _ => read_left()
+                    .zip(read_right())
+                    .filter(|(left, right)| {
+                        (&left.sink, &left.channel) != (&right.sink, &right.channel)
+                    })
+                    .map(|(left, right)| VirtualDevice::StereoPair {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    }),
+            };
+            match device {
+                Some(device) if !device_name.is_empty() => {
+                    name.set_text("");
+                    dispatcher.dispatch(Action::CreateVirtualDevice {
+                        name: device_name,
+                        device,
+                    });
+                }
+                _ => warn!(
+                    "a virtual device needs a name, and combining two different outputs or channels"
+                ),
+            }
+        }
+    });
+
+    let devices = create_memo(cx, move |_| {
+        state
+            .device_settings
+            .with(|store| store.virtual_devices.clone())
+    });
+    create_effect(cx, move |_| {
+        let devices = devices.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        for (name, device) in devices {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            row.append(&gtk::Label::new(Some(&name)));
+            let description = gtk::Label::new(Some(&device.describe(&sources, &sinks)));
+            description.add_css_class("dim-label");
+            description.set_hexpand(true);
+            description.set_halign(gtk::Align::Start);
+            description.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            row.append(&description);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            remove.connect_clicked({
+                let dispatcher = dispatcher.clone();
+                move |_| dispatcher.dispatch(Action::RemoveVirtualDevice(name.clone()))
+            });
+            row.append(&remove);
+            rows.append(&row);
+        }
+    });
+
+    gtk::Expander::builder()
+        .label("Virtual devices")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,163 @@
+//! Locking the volume of applications, for browsers and games that reset their
+//! streams to 100% on their own. Locks are kept by `application.name` and the
+//! `volume-lock` rule puts back whatever else changes, the window's own sliders
+//! included, until the lock is lifted. Next to the locks are the switches for
+//! following the output's volume, see [`crate::relative_volume`].
+use std::collections::BTreeMap;
+
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::warn;
+
+use crate::{
+    actions::{Action, Dispatcher},
+    events::{self, Facility},
+    i18n::tr,
+    relative_volume,
+    state::AppState,
+    streams::Stream,
+};
+
+/// Applications that are playing, with the volume of their first stream.
+fn playing() -> BTreeMap<String, u32> {
+    let mut playing = BTreeMap::new();
+    for stream in Stream::list()
+        .map_err(|error| warn!(?error, "listing streams to lock"))
+        .unwrap_or_default()
+    {
+        if let Some(application) = stream.properties.get("application.name") {
+            playing
+                .entry(application.clone())
+                .or_insert_with(|| stream.volume_percent());
+        }
+    }
+    playing
+}
+
+/// Expander with a row for each application that is playing, has its volume
+/// locked or follows the output, to lock it at a volume or have it follow.
+pub fn lock_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    let current = create_rw_signal(cx, playing());
+    events::subscribe(move |event| {
+        if event.facility == Facility::SinkInput {
+            current.set(playing());
+        }
+    });
+    // only applications coming and going, locks changing or starting to follow
+    // rebuild the rows, so the volume picked isn't reset while picking it
+    let listed = create_memo(cx, move |_| {
+        let (locked, following) = state.device_settings.with(|store| {
+            (
+                store.locked_volumes.clone(),
+                store.relative_volumes.keys().cloned().collect::<Vec<_>>(),
+            )
+        });
+        let mut applications = current.with(|current| current.keys().cloned().collect::<Vec<_>>());
+        applications.extend(locked.keys().cloned());
+        applications.extend(following.iter().cloned());
+        applications.sort();
+        applications.dedup();
+        applications
+            .into_iter()
+            .map(|application| {
+                let percent = locked.get(&application).copied();
+                let follows = following.contains(&application);
+                (application, percent, follows)
+            })
+            .collect::<Vec<_>>()
+    });
+    let layout = rows.clone();
+    create_effect(cx, move |_| {
+        let listed = listed.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        if listed.is_empty() {
+            let empty = gtk::Label::new(Some(&tr("Nothing is playing.")));
+            empty.add_css_class("dim-label");
+            rows.append(&empty);
+        }
+        for (application, locked, follows) in listed {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            let label = gtk::Label::new(Some(&application));
+            label.set_hexpand(true);
+            label.set_halign(gtk::Align::Start);
+            label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            row.append(&label);
+            let volume = gtk::SpinButton::with_range(0., 100., 1.);
+            volume.set_tooltip_text(Some(&tr("Volume, in percent")));
+            let shown = locked
+                .or_else(|| current.with_untracked(|current| current.get(&application).copied()));
+            volume.set_value(shown.unwrap_or(100).into());
+            row.append(&volume);
+            let lock = gtk::ToggleButton::new();
+            lock.set_active(locked.is_some());
+            lock.set_icon_name(match locked {
+                Some(_) => "changes-prevent-symbolic",
+                None => "changes-allow-symbolic",
+            });
+            lock.set_tooltip_text(Some(&match locked {
+                Some(_) => tr("Unlock the volume"),
+                None => tr("Lock the volume"),
+            }));
+            row.append(&lock);
+            let follow = gtk::ToggleButton::new();
+            follow.set_icon_name("insert-link-symbolic");
+            follow.set_tooltip_text(Some(&tr("Follow the output's volume")));
+            follow.set_active(follows);
+            follow.set_sensitive(locked.is_none());
+            follow.connect_toggled({
+                let dispatcher = dispatcher.clone();
+                let application = application.clone();
+                move |follow| {
+                    let share = match follow.is_active() {
+                        // at the share it plays at now, all of it when it's not playing
+                        true => Some(
+                            relative_volume::current_share(&application)
+                                .map_err(|error| warn!(?error, "reading the share to follow at"))
+                                .ok()
+                                .flatten()
+                                .unwrap_or(100),
+                        ),
+                        false => None,
+                    };
+                    dispatcher.dispatch(Action::SetRelativeVolume {
+                        application: application.clone(),
+                        share,
+                    });
+                }
+            });
+            row.append(&follow);
+            let set = {
+                let dispatcher = dispatcher.clone();
+                move |percent: Option<u32>| {
+                    dispatcher.dispatch(Action::SetVolumeLock {
+                        application: application.clone(),
+                        percent,
+                    })
+                }
+            };
+            volume.connect_value_changed({
+                let set = set.clone();
+                let lock = lock.clone();
+                move |volume| {
+                    if lock.is_active() {
+                        set(Some(volume.value() as u32));
+                    }
+                }
+            });
+            lock.connect_toggled(move |lock| {
+                set(lock.is_active().then(|| volume.value() as u32));
+            });
+            rows.append(&row);
+        }
+    });
+    gtk::Expander::builder()
+        .label("Application volumes")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
@@ -0,0 +1,346 @@
+//! The panels the window has always been built around: the default output's and
+//! input's volume side by side, and the applications' volumes under them. Their
+//! `[panels]` switches still hide them, as `hidden` does any other panel.
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+
+use crate::{
+    a11y,
+    actions::{Action, Dispatcher},
+    app_outputs, audible, balance, captures, config,
+    devices::DeviceKind,
+    extensions::{watch, InScope},
+    i18n::{tr, tr_with},
+    levels, loopback, noise_suppression,
+    registry::{Context, Panel, Slot},
+    state::AppState,
+    volume_lock, widgets, AudioControls, DiffValue,
+};
+
+fn diff_volume_button(
+    cx: Scope,
+    state: AppState,
+    dispatcher: Dispatcher,
+    kind: DeviceKind,
+    diff: DiffValue,
+) -> gtk::Button {
+    crate::view! { cx,
+        gtk::Button(
+            label: diff.to_string(),
+            margin_top: 12,
+            margin_bottom: 12,
+            margin_start: 12,
+            margin_end: 12,
+        ) {
+            add_css_class("volume-step"),
+            on connect_clicked: move |_| {
+                dispatcher.dispatch(match kind {
+                    // accelerated when clicked in quick succession, like keys
+                    DeviceKind::Sink => Action::ChangeVolume(diff),
+                    DeviceKind::Source => Action::ChangeInputVolume(diff),
+                })
+            },
+            // the server ignores volume changes of compressed streams
+            bind set_sensitive: kind == DeviceKind::Source
+                || state.passthrough.with(Option::is_none),
+        }
+    }
+}
+
+/// A pair for each configured step, the biggest steps outermost.
+fn step_buttons(cx: Scope, state: AppState, dispatcher: &Dispatcher, kind: DeviceKind) -> gtk::Box {
+    let buttons = gtk::Box::new(Orientation::Vertical, 0);
+    let mut steps = config::get().steps.clone();
+    steps.sort_unstable();
+    steps.dedup();
+    let diffs = steps
+        .iter()
+        .rev()
+        .map(|step| -(*step as i32))
+        .chain(steps.iter().map(|step| *step as i32));
+    for diff in diffs {
+        buttons.append(&diff_volume_button(
+            cx,
+            state,
+            dispatcher.clone(),
+            kind,
+            DiffValue(diff),
+        ));
+    }
+    buttons
+}
+
+/// Styles `widget` as a warning while amplifying, past 100%.
+fn amplified(cx: Scope, state: AppState, widget: &gtk::Widget, kind: DeviceKind) {
+    let device = state.default_device(kind);
+    watch(cx, widget, move |widget| {
+        match device.with(|device| {
+            device
+                .as_ref()
+                .is_some_and(|device| device.volume_percent() > 100)
+        }) {
+            true => widget.add_css_class("warning"),
+            false => widget.remove_css_class("warning"),
+        }
+    });
+}
+
+/// Follows the server's events, so changes made elsewhere show too.
+fn volume_bar(cx: Scope, state: AppState, kind: DeviceKind) -> gtk::ProgressBar {
+    let max = f64::from(AudioControls::max_percent());
+    let device = state.default_device(kind);
+    let bar = crate::view! { cx,
+        gtk::ProgressBar(show_text: true, margin_start: 12, margin_end: 12) {
+            add_css_class("volume-bar"),
+            bind set_fraction: device.with(|device| {
+                device.as_ref().map_or(0., |device| {
+                    (f64::from(device.volume_percent()) / max).min(1.)
+                })
+            }),
+            bind set_text: Some(&device.with(|device| match device {
+                None if kind == DeviceKind::Sink => tr("No output"),
+                None => tr("No input"),
+                Some(device) if device.mute => {
+                    tr_with("{device}: muted", &[("device", &device.description)])
+                }
+                Some(device) => tr_with(
+                    "{device}: {percent}%",
+                    &[
+                        ("device", &device.description),
+                        ("percent", &device.volume_percent().to_string()),
+                    ],
+                ),
+            })),
+        }
+    };
+    amplified(cx, state, bar.upcast_ref(), kind);
+    a11y::follow_volume(cx, bar.upcast_ref(), state, kind);
+    bar
+}
+
+fn volume_slider(
+    cx: Scope,
+    state: AppState,
+    dispatcher: Dispatcher,
+    kind: DeviceKind,
+) -> gtk::Scale {
+    let max = f64::from(AudioControls::max_percent());
+    let device = state.default_device(kind);
+    let slider = gtk::Scale::in_scope(cx)
+        .constant(|scale| {
+            scale.set_range(0., max);
+            if max > 100. {
+                scale.add_mark(100., gtk::PositionType::Bottom, None);
+            }
+            scale.set_round_digits(0);
+            scale.add_css_class("volume-slider");
+            scale.set_margin_start(12);
+            scale.set_margin_end(12);
+        })
+        .model(
+            move || {
+                device.with(|device| {
+                    device
+                        .as_ref()
+                        .map_or(0., |device| device.volume_percent().into())
+                })
+            },
+            move |picked| {
+                dispatcher.dispatch(Action::SetVolume(
+                    kind,
+                    kind.default_alias().to_owned(),
+                    picked.round() as u32,
+                ));
+            },
+        )
+        // the server ignores volume changes of compressed streams
+        .bind_property("sensitive", move || {
+            device.with(Option::is_some)
+                && (kind == DeviceKind::Source || state.passthrough.with(Option::is_none))
+        })
+        .as_ref()
+        .clone();
+    amplified(cx, state, slider.upcast_ref(), kind);
+    a11y::follow_volume(cx, slider.upcast_ref(), state, kind);
+    slider
+}
+
+fn heading(label: &str) -> gtk::Label {
+    let heading = gtk::Label::builder()
+        .label(tr(label))
+        .margin_top(12)
+        .margin_bottom(6)
+        .build();
+    heading.add_css_class("heading");
+    heading
+}
+
+/// The default output's volume, mute and balance.
+pub struct Outputs;
+
+impl Panel for Outputs {
+    fn title(&self) -> &'static str {
+        "Outputs"
+    }
+
+    fn slot(&self) -> Slot {
+        Slot::Column
+    }
+
+    fn build(&self, context: &Context) -> gtk::Widget {
+        let Context {
+            cx,
+            state,
+            ref dispatcher,
+            ..
+        } = *context;
+        let muted = move || {
+            state
+                .default_sink
+                .with(|sink| sink.as_ref().is_some_and(|sink| sink.mute))
+        };
+        let column = crate::view! { cx,
+            gtk::Box(orientation: Orientation::Vertical, name: "outputs", visible: config::get().panels.outputs) [
+                (heading("Output")),
+                (volume_bar(cx, state, DeviceKind::Sink)),
+                (levels::vu_meter(cx, state)),
+                (volume_slider(cx, state, dispatcher.clone(), DeviceKind::Sink)),
+                (balance::balance_panel(cx, state, dispatcher.clone())),
+                gtk::Label {
+                    bind set_visible: state.passthrough.with(Option::is_some),
+                    bind set_label: &state
+                        .passthrough
+                        .get()
+                        .map(|codec| tr_with("Passing through {codec}", &[("codec", codec.label())]))
+                        .unwrap_or_default(),
+                },
+                (step_buttons(cx, state, dispatcher, DeviceKind::Sink)),
+                gtk::Button(margin_bottom: 12, margin_start: 12, margin_end: 12) {
+                    add_css_class("mute"),
+                    bind set_icon_name: match muted() {
+                        true => "audio-volume-muted-symbolic",
+                        false => "audio-volume-high-symbolic",
+                    },
+                    bind set_tooltip_text: Some(&tr(match muted() {
+                        true => "Unmute",
+                        false => "Mute",
+                    })),
+                    bind set_sensitive: state.default_sink.with(Option::is_some),
+                    on connect_clicked: {
+                        let dispatcher = dispatcher.clone();
+                        move |_| dispatcher.dispatch(Action::ToggleMute(DeviceKind::Sink))
+                    },
+                },
+            ]
+        };
+        column.upcast()
+    }
+}
+
+/// The default input's volume and mute, and what listens to it.
+pub struct Inputs;
+
+impl Panel for Inputs {
+    fn title(&self) -> &'static str {
+        "Inputs"
+    }
+
+    fn slot(&self) -> Slot {
+        Slot::Column
+    }
+
+    fn build(&self, context: &Context) -> gtk::Widget {
+        let Context {
+            cx,
+            state,
+            ref dispatcher,
+            ..
+        } = *context;
+        let muted = move || {
+            state
+                .default_source
+                .with(|source| source.as_ref().is_some_and(|source| source.mute))
+        };
+        let column = crate::view! { cx,
+            gtk::Box(orientation: Orientation::Vertical, name: "inputs", visible: config::get().panels.inputs) [
+                (heading("Input")),
+                (volume_bar(cx, state, DeviceKind::Source)),
+                (volume_slider(cx, state, dispatcher.clone(), DeviceKind::Source)),
+                (step_buttons(cx, state, dispatcher, DeviceKind::Source)),
+                gtk::ToggleButton(
+                    label: &tr("Mute microphone"),
+                    margin_bottom: 12,
+                    margin_start: 12,
+                    margin_end: 12,
+                ) {
+                    add_css_class("mute"),
+                    bind set_active: muted(),
+                    bind set_sensitive: state.default_source.with(Option::is_some),
+                    on connect_toggled: {
+                        let dispatcher = dispatcher.clone();
+                        move |button| {
+                            // following the server toggles it too
+                            if button.is_active() != muted() {
+                                dispatcher.dispatch(Action::SetMute(
+                                    DeviceKind::Source,
+                                    DeviceKind::Source.default_alias().to_owned(),
+                                    button.is_active(),
+                                ));
+                            }
+                        }
+                    },
+                },
+                (noise_suppression::noise_suppression_switch(cx, state, dispatcher.clone())),
+                (loopback::listen_panel(cx, state, dispatcher.clone())),
+                (captures::capture_panel(cx, state, dispatcher.clone())),
+            ]
+        };
+        column.upcast()
+    }
+}
+
+/// Expander with the volume of every stream, highlighting the audible ones.
+pub struct Applications;
+
+impl Panel for Applications {
+    fn title(&self) -> &'static str {
+        "Applications"
+    }
+
+    fn build(&self, context: &Context) -> gtk::Widget {
+        let Context {
+            cx,
+            state,
+            ref dispatcher,
+            ..
+        } = *context;
+        let mixer = widgets::AppMixer::new();
+        create_effect(cx, {
+            let mixer = mixer.clone();
+            move |_| {
+                mixer.highlight(&state.finding.with(|finding| {
+                    finding
+                        .audible
+                        .iter()
+                        .flatten()
+                        .map(|audible| audible.stream)
+                        .collect::<Vec<_>>()
+                }))
+            }
+        });
+        let layout = gtk::Box::new(Orientation::Vertical, 0);
+        layout.append(&mixer);
+        layout.append(&audible::finder_panel(cx, state, dispatcher.clone()));
+        layout.append(&volume_lock::lock_panel(cx, state, dispatcher.clone()));
+        layout.append(&app_outputs::output_panel(cx, state, dispatcher.clone()));
+        let expander = gtk::Expander::builder()
+            .label("Applications")
+            .child(&layout)
+            .visible(config::get().panels.applications)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_bottom(12)
+            .build();
+        expander.upcast()
+    }
+}
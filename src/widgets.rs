@@ -0,0 +1,684 @@
+//! pipeweld's mixer as widgets for other GTK applications, like bars and settings
+//! tools, to embed. They talk to the server on their own and follow its events, so
+//! they work without the rest of pipeweld:
+//!
+//! ```no_run
+//! # use gtk::prelude::*;
+//! # fn embed(panel: &gtk::Box) {
+//! use pipeweld::{devices::DeviceKind, widgets::{AppMixer, DeviceSlider}};
+//!
+//! panel.append(&DeviceSlider::new(DeviceKind::Sink));
+//! panel.append(&AppMixer::new());
+//! # }
+//! ```
+use std::{cell::Cell, rc::Rc};
+
+use gtk::{glib, prelude::*, subclass::prelude::*};
+use tracing::warn;
+
+use crate::{
+    backend,
+    devices::{Device, DeviceKind},
+    events::{self, Facility},
+    flat_volumes,
+    i18n::{tr, tr_with},
+    streams::{Stream, StreamChange, Transaction},
+    AudioControls,
+};
+
+mod imp {
+    use std::{
+        cell::{Cell, OnceCell, RefCell},
+        collections::HashMap,
+        rc::Rc,
+    };
+
+    use gtk::{glib, prelude::*, subclass::prelude::*, Orientation};
+
+    use crate::{devices::DeviceKind, i18n::tr};
+
+    #[derive(Default)]
+    pub struct DeviceSlider {
+        pub kind: OnceCell<DeviceKind>,
+        pub label: gtk::Label,
+        pub scale: gtk::Scale,
+        pub mute: gtk::ToggleButton,
+        pub suspend: gtk::ToggleButton,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for DeviceSlider {
+        const NAME: &'static str = "PipeweldDeviceSlider";
+        type Type = super::DeviceSlider;
+        type ParentType = gtk::Box;
+    }
+
+    impl ObjectImpl for DeviceSlider {
+        fn constructed(&self) {
+            self.parent_constructed();
+            let slider = self.obj();
+            slider.set_orientation(Orientation::Vertical);
+            slider.set_spacing(2);
+            self.label.set_halign(gtk::Align::Start);
+            self.label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            slider.append(&self.label);
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            self.scale
+                .set_range(0., crate::AudioControls::max_percent().into());
+            self.scale.set_increments(1., 5.);
+            self.scale.set_hexpand(true);
+            row.append(&self.scale);
+            self.mute.set_icon_name("audio-volume-muted-symbolic");
+            self.mute.set_tooltip_text(Some(&tr("Mute")));
+            row.append(&self.mute);
+            self.suspend.set_icon_name("system-suspend-symbolic");
+            row.append(&self.suspend);
+            slider.append(&row);
+        }
+    }
+
+    impl WidgetImpl for DeviceSlider {}
+    impl BoxImpl for DeviceSlider {}
+
+    /// One row of the mixer.
+    pub struct Row {
+        pub stream: u32,
+        pub label: gtk::Label,
+        pub scale: gtk::Scale,
+        pub mute: gtk::ToggleButton,
+        pub solo: gtk::ToggleButton,
+        pub output: gtk::DropDown,
+        /// Index of the sink the stream plays on, as last seen.
+        pub sink: Rc<Cell<u32>>,
+        pub format: gtk::Label,
+    }
+
+    /// The stream playing on its own, and whether each of the others was muted before.
+    pub struct Solo {
+        pub stream: u32,
+        pub muted: HashMap<u32, bool>,
+    }
+
+    #[derive(Default)]
+    pub struct AppMixer {
+        pub rows: RefCell<Vec<Row>>,
+        pub solo: RefCell<Option<Solo>>,
+        /// Streams whose titles stand out, see [`super::AppMixer::highlight`].
+        pub highlighted: RefCell<Vec<u32>>,
+        /// Sinks the rows' output pickers list, by name.
+        pub sinks: RefCell<Vec<String>>,
+        /// What flat volumes mean for the sliders, while the server uses them.
+        pub flat: gtk::Label,
+        pub empty: gtk::Label,
+        /// The rows, picked with Ctrl and Shift clicks for the bulk actions.
+        pub list: gtk::ListBox,
+        /// Bulk actions on the picked rows, shown while there's more than one.
+        pub bulk: gtk::Box,
+        pub picked: gtk::Label,
+        pub mute: gtk::Button,
+        pub output: gtk::DropDown,
+        pub move_to: gtk::Button,
+        pub volume: gtk::SpinButton,
+        pub set_volume: gtk::Button,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for AppMixer {
+        const NAME: &'static str = "PipeweldAppMixer";
+        type Type = super::AppMixer;
+        type ParentType = gtk::Box;
+    }
+
+    impl ObjectImpl for AppMixer {
+        fn constructed(&self) {
+            self.parent_constructed();
+            let mixer = self.obj();
+            mixer.set_orientation(Orientation::Vertical);
+            mixer.set_spacing(6);
+            self.flat.set_wrap(true);
+            self.flat.set_max_width_chars(60);
+            self.flat.set_halign(gtk::Align::Start);
+            self.flat.add_css_class("dim-label");
+            self.flat.set_visible(false);
+            mixer.append(&self.flat);
+            self.empty.set_text(&tr("Nothing is playing."));
+            self.empty.add_css_class("dim-label");
+            mixer.append(&self.empty);
+            self.list.set_selection_mode(gtk::SelectionMode::Multiple);
+            self.list.set_activate_on_single_click(false);
+            mixer.append(&self.list);
+
+            self.bulk.set_orientation(Orientation::Horizontal);
+            self.bulk.set_spacing(6);
+            self.bulk.set_visible(false);
+            self.picked.set_hexpand(true);
+            self.picked.set_halign(gtk::Align::Start);
+            self.bulk.append(&self.picked);
+            self.mute.set_icon_name("audio-volume-muted-symbolic");
+            self.bulk.append(&self.mute);
+            self.output
+                .set_tooltip_text(Some(&tr("Output to move them to")));
+            self.bulk.append(&self.output);
+            self.move_to.set_label(&tr("Move"));
+            self.bulk.append(&self.move_to);
+            self.volume.set_range(0., 100.);
+            self.volume.set_increments(1., 10.);
+            self.volume.set_value(100.);
+            self.volume
+                .set_tooltip_text(Some(&tr("Volume, in percent")));
+            self.bulk.append(&self.volume);
+            self.set_volume.set_label(&tr("Set"));
+            self.set_volume
+                .set_tooltip_text(Some(&tr("Set them all to this volume")));
+            self.bulk.append(&self.set_volume);
+            mixer.append(&self.bulk);
+        }
+    }
+
+    impl WidgetImpl for AppMixer {}
+    impl BoxImpl for AppMixer {}
+}
+
+glib::wrapper! {
+    /// Volume and mute of the default sink or source, following it as it changes.
+    pub struct DeviceSlider(ObjectSubclass<imp::DeviceSlider>)
+        @extends gtk::Box, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::Orientable;
+}
+
+impl DeviceSlider {
+    pub fn new(kind: DeviceKind) -> Self {
+        let slider: Self = glib::Object::new();
+        let imp = slider.imp();
+        imp.kind.set(kind).ok();
+        // change-value and clicked only come from the user, not from refreshing
+        imp.scale.connect_change_value(move |_, _, value| {
+            let percent = value.round() as u32;
+            AudioControls::detach(move || {
+                backend::current().set_volume(kind, kind.default_alias(), percent)
+            });
+            glib::signal::Inhibit(false)
+        });
+        imp.mute.connect_clicked(move |mute| {
+            let mute = mute.is_active();
+            AudioControls::detach(move || {
+                backend::current().set_mute(kind, kind.default_alias(), mute)
+            });
+        });
+        imp.suspend.connect_clicked(move |suspend| {
+            let suspend = suspend.is_active();
+            AudioControls::detach(move || {
+                AudioControls::set_suspended(kind, kind.default_alias(), suspend)
+            });
+        });
+        slider.refresh();
+        let weak = slider.downgrade();
+        events::subscribe_while(move |event| {
+            let Some(slider) = weak.upgrade() else {
+                return glib::Continue(false);
+            };
+            let relevant = match kind {
+                DeviceKind::Sink => Facility::Sink,
+                DeviceKind::Source => Facility::Source,
+            };
+            if matches!(event.facility, Facility::Server | Facility::Card)
+                || event.facility == relevant
+            {
+                slider.refresh();
+            }
+            glib::Continue(true)
+        });
+        slider
+    }
+
+    pub fn kind(&self) -> DeviceKind {
+        *self.imp().kind.get().expect("set on construction")
+    }
+
+    /// Reads the device anew, which events do on their own. It's read off the main
+    /// loop and shown once read, so a hung server never freezes the widget.
+    pub fn refresh(&self) {
+        let kind = self.kind();
+        let weak = self.downgrade();
+        AudioControls::in_background(
+            move || backend::current().default_of(kind),
+            move |device| {
+                let Some(slider) = weak.upgrade() else {
+                    return;
+                };
+                match device {
+                    Ok(device) => slider.show(device),
+                    Err(error) => warn!(?error, "refreshing the {} slider", kind.noun()),
+                }
+            },
+        );
+    }
+
+    fn show(&self, device: Option<Device>) {
+        let imp = self.imp();
+        self.set_sensitive(device.is_some());
+        let Some(device) = device else {
+            return imp.label.set_text(&match self.kind() {
+                DeviceKind::Sink => tr("No sink"),
+                DeviceKind::Source => tr("No source"),
+            });
+        };
+        let suspended = device.state == "SUSPENDED";
+        imp.label.set_text(&match suspended {
+            true => tr_with("{device} (suspended)", &[("device", &device.description)]),
+            false => device.description.clone(),
+        });
+        imp.scale.set_value(device.volume_percent().into());
+        imp.mute.set_active(device.mute);
+        imp.suspend.set_active(suspended);
+        imp.suspend.set_tooltip_text(Some(&match suspended {
+            true => tr("Wake up"),
+            false => tr("Suspend"),
+        }));
+    }
+}
+
+glib::wrapper! {
+    /// A volume slider and mute button for every application that plays something,
+    /// and a solo button muting all the others while it's down. Rows picked with
+    /// Ctrl and Shift clicks are muted, moved or set to a volume all at once.
+    pub struct AppMixer(ObjectSubclass<imp::AppMixer>)
+        @extends gtk::Box, gtk::Widget,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget, gtk::Orientable;
+}
+
+impl Default for AppMixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where `sinks` lists the one `stream` plays on.
+fn position(sinks: &[Device], stream: &Stream) -> u32 {
+    sinks
+        .iter()
+        .position(|sink| sink.index == stream.sink)
+        .map_or(gtk::INVALID_LIST_POSITION, |position| position as u32)
+}
+
+/// Puts `stream`'s volume on its slider, marked when moving it moves the output too,
+/// see [`flat_volumes`].
+fn show_volume(scale: &gtk::Scale, stream: &Stream, sinks: &[Device]) {
+    scale.set_value(flat_volumes::shown(stream, sinks).into());
+    match flat_volumes::moves_output(stream, sinks) {
+        true => {
+            scale.add_css_class("warning");
+            scale.set_tooltip_text(Some(&tr("Moving this moves the output's volume too")));
+        }
+        false => {
+            scale.remove_css_class("warning");
+            scale.set_tooltip_text(None);
+        }
+    }
+}
+
+impl AppMixer {
+    pub fn new() -> Self {
+        let mixer: Self = glib::Object::new();
+        let imp = mixer.imp();
+        let weak = mixer.downgrade();
+        imp.list.connect_selected_rows_changed(move |_| {
+            if let Some(mixer) = weak.upgrade() {
+                mixer.follow_picked();
+            }
+        });
+        let weak = mixer.downgrade();
+        imp.mute.connect_clicked(move |_| {
+            let Some(mixer) = weak.upgrade() else {
+                return;
+            };
+            // all of them muted unmutes them, otherwise they're all muted
+            let mute = !mixer
+                .picked_rows(|row| row.mute.is_active())
+                .all(|muted| muted);
+            mixer.change_picked(|_| StreamChange::Mute(mute));
+        });
+        let weak = mixer.downgrade();
+        imp.move_to.connect_clicked(move |_| {
+            let Some(mixer) = weak.upgrade() else {
+                return;
+            };
+            let imp = mixer.imp();
+            let Some(sink) = imp
+                .sinks
+                .borrow()
+                .get(imp.output.selected() as usize)
+                .cloned()
+            else {
+                return;
+            };
+            mixer.change_picked(|_| StreamChange::Move(sink.clone()));
+        });
+        let weak = mixer.downgrade();
+        imp.set_volume.connect_clicked(move |_| {
+            let Some(mixer) = weak.upgrade() else {
+                return;
+            };
+            let percent = mixer.imp().volume.value() as u32;
+            let sinks = match flat_volumes::sinks() {
+                Ok(sinks) => sinks,
+                Err(error) => return warn!(?error, "listing the outputs to set volumes on"),
+            };
+            mixer.change_picked(|stream| {
+                StreamChange::Volume(flat_volumes::to_server(stream, percent, &sinks))
+            });
+        });
+        mixer.refresh();
+        let weak = mixer.downgrade();
+        events::subscribe_while(move |event| {
+            let Some(mixer) = weak.upgrade() else {
+                return glib::Continue(false);
+            };
+            if matches!(event.facility, Facility::SinkInput | Facility::Sink) {
+                mixer.refresh();
+            }
+            glib::Continue(true)
+        });
+        mixer
+    }
+
+    /// Reads the streams anew, which events do on their own. Rows are only
+    /// rebuilt when streams or sinks come or go, so a dragged slider stays under the
+    /// pointer. Like [`DeviceSlider::refresh`], it lists them off the main loop.
+    pub fn refresh(&self) {
+        let weak = self.downgrade();
+        AudioControls::in_background(
+            || Ok((Stream::list()?, Device::list(DeviceKind::Sink)?)),
+            move |listed| {
+                let Some(mixer) = weak.upgrade() else {
+                    return;
+                };
+                match listed {
+                    Ok((streams, sinks)) => mixer.show(streams, sinks),
+                    Err(error) => warn!(?error, "refreshing the mixer"),
+                }
+            },
+        );
+    }
+
+    fn show(&self, streams: Vec<Stream>, sinks: Vec<Device>) {
+        let imp = self.imp();
+        let unchanged = imp
+            .rows
+            .borrow()
+            .iter()
+            .map(|row| row.stream)
+            .eq(streams.iter().map(|stream| stream.index))
+            && imp
+                .sinks
+                .borrow()
+                .iter()
+                .eq(sinks.iter().map(|sink| &sink.name));
+        self.follow_solo(&streams);
+        let explainer = flat_volumes::explainer();
+        imp.flat.set_visible(explainer.is_some());
+        imp.flat.set_text(explainer.as_deref().unwrap_or_default());
+        if unchanged {
+            for (row, stream) in imp.rows.borrow().iter().zip(&streams) {
+                show_volume(&row.scale, stream, &sinks);
+                row.mute.set_active(stream.mute);
+                row.sink.set(stream.sink);
+                row.output.set_selected(position(&sinks, stream));
+                row.format.set_text(&stream.format_description());
+            }
+            return;
+        }
+        // streams picked before stay picked
+        let picked = self.picked_rows(|row| row.stream).collect::<Vec<_>>();
+        while let Some(child) = imp.list.first_child() {
+            imp.list.remove(&child);
+        }
+        imp.empty.set_visible(streams.is_empty());
+        imp.list.set_visible(!streams.is_empty());
+        let rows = streams
+            .iter()
+            .map(|stream| self.row(stream, &sinks))
+            .collect::<Vec<_>>();
+        for (position, row) in rows.iter().enumerate() {
+            if picked.contains(&row.stream) {
+                imp.list
+                    .select_row(imp.list.row_at_index(position as i32).as_ref());
+            }
+        }
+        imp.rows.replace(rows);
+        let labels = sinks
+            .iter()
+            .map(|sink| sink.description.as_str())
+            .collect::<Vec<_>>();
+        imp.output.set_model(Some(&gtk::StringList::new(&labels)));
+        imp.sinks
+            .replace(sinks.into_iter().map(|sink| sink.name).collect());
+        self.follow_picked();
+    }
+
+    /// What `read` tells of each row picked for the bulk actions.
+    fn picked_rows<T>(&self, read: impl Fn(&imp::Row) -> T) -> impl Iterator<Item = T> {
+        let imp = self.imp();
+        let rows = imp.rows.borrow();
+        imp.list
+            .selected_rows()
+            .iter()
+            .filter_map(|picked| rows.get(picked.index() as usize).map(&read))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Shows the bulk actions while several rows are picked.
+    fn follow_picked(&self) {
+        let imp = self.imp();
+        let picked = imp.list.selected_rows().len();
+        imp.bulk.set_visible(picked > 1);
+        imp.picked.set_text(&tr_with(
+            "{picked} streams picked",
+            &[("picked", &picked.to_string())],
+        ));
+        let all_muted = self
+            .picked_rows(|row| row.mute.is_active())
+            .all(|muted| muted);
+        imp.mute.set_tooltip_text(Some(&match all_muted {
+            true => tr("Unmute them all"),
+            false => tr("Mute them all"),
+        }));
+    }
+
+    /// Makes `change` of every picked stream, together in one transaction.
+    fn change_picked(&self, change: impl Fn(&Stream) -> StreamChange) {
+        let picked = self.picked_rows(|row| row.stream).collect::<Vec<_>>();
+        let streams = match Stream::list() {
+            Ok(streams) => streams,
+            Err(error) => return warn!(?error, "listing the picked streams"),
+        };
+        let mut transaction = Transaction::new();
+        for stream in streams
+            .iter()
+            .filter(|stream| picked.contains(&stream.index))
+        {
+            transaction.change(stream, change(stream));
+        }
+        if !transaction.is_empty() {
+            AudioControls::detach(move || transaction.commit());
+        }
+    }
+
+    /// Makes the titles of `streams` stand out, and only theirs.
+    pub fn highlight(&self, streams: &[u32]) {
+        let imp = self.imp();
+        imp.highlighted.replace(streams.to_vec());
+        for row in imp.rows.borrow().iter() {
+            match streams.contains(&row.stream) {
+                true => row.label.add_css_class("accent"),
+                false => row.label.remove_css_class("accent"),
+            }
+        }
+    }
+
+    /// Plays `solo` on its own, muting all other streams, or puts back how they were
+    /// muted before for `None`.
+    pub fn set_solo(&self, solo: Option<u32>) {
+        let streams = match Stream::list() {
+            Ok(streams) => streams,
+            Err(error) => return warn!(?error, "listing streams to solo"),
+        };
+        let imp = self.imp();
+        // switching from one solo to another keeps how they were before either
+        let mut muted = imp.solo.take().map(|solo| solo.muted).unwrap_or_default();
+        for stream in &streams {
+            muted.entry(stream.index).or_insert(stream.mute);
+        }
+        let changes = streams
+            .iter()
+            .filter_map(|stream| {
+                let mute = match solo {
+                    Some(solo) => solo != stream.index,
+                    None => muted[&stream.index],
+                };
+                (mute != stream.mute).then_some((stream.index, mute))
+            })
+            .collect::<Vec<_>>();
+        AudioControls::detach(move || {
+            changes
+                .into_iter()
+                .try_for_each(|(stream, mute)| AudioControls::set_stream_mute(stream, mute))
+        });
+        if let Some(solo) = solo {
+            muted.retain(|stream, _| streams.iter().any(|listed| listed.index == *stream));
+            imp.solo.replace(Some(imp::Solo {
+                stream: solo,
+                muted,
+            }));
+        }
+        for row in imp.rows.borrow().iter() {
+            row.solo.set_active(Some(row.stream) == solo);
+        }
+    }
+
+    /// Mutes streams that started during a solo, and ends it once its stream is gone.
+    fn follow_solo(&self, streams: &[Stream]) {
+        let imp = self.imp();
+        let mut solo = imp.solo.borrow_mut();
+        let Some(current) = solo.as_mut() else {
+            return;
+        };
+        if !streams.iter().any(|stream| stream.index == current.stream) {
+            drop(solo);
+            return self.set_solo(None);
+        }
+        for stream in streams {
+            if stream.index == current.stream || current.muted.contains_key(&stream.index) {
+                continue;
+            }
+            current.muted.insert(stream.index, stream.mute);
+            if !stream.mute {
+                let index = stream.index;
+                AudioControls::detach(move || AudioControls::set_stream_mute(index, true));
+            }
+        }
+    }
+
+    fn row(&self, stream: &Stream, sinks: &[Device]) -> imp::Row {
+        let layout = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        let icon = stream
+            .properties
+            .get("application.icon_name")
+            .map_or("audio-x-generic-symbolic", String::as_str);
+        header.append(&gtk::Image::from_icon_name(icon));
+        let label = gtk::Label::new(Some(&stream.title()));
+        label.set_halign(gtk::Align::Start);
+        label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        if self.imp().highlighted.borrow().contains(&stream.index) {
+            label.add_css_class("accent");
+        }
+        header.append(&label);
+        layout.append(&header);
+        let controls = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        let scale = gtk::Scale::with_range(gtk::Orientation::Horizontal, 0., 100., 1.);
+        scale.set_hexpand(true);
+        show_volume(&scale, stream, sinks);
+        let index = stream.index;
+        scale.connect_change_value(move |_, _, value| {
+            let percent = value.round() as u32;
+            AudioControls::detach(move || flat_volumes::set_stream_volume(index, percent));
+            glib::signal::Inhibit(false)
+        });
+        controls.append(&scale);
+        let mute = gtk::ToggleButton::new();
+        mute.set_icon_name("audio-volume-muted-symbolic");
+        mute.set_tooltip_text(Some(&tr("Mute")));
+        mute.set_active(stream.mute);
+        mute.connect_clicked(move |mute| {
+            let mute = mute.is_active();
+            AudioControls::detach(move || AudioControls::set_stream_mute(index, mute));
+        });
+        controls.append(&mute);
+        let solo = gtk::ToggleButton::with_label(&tr("Solo"));
+        solo.set_tooltip_text(Some(&tr("Mute everything else")));
+        solo.set_active(
+            self.imp()
+                .solo
+                .borrow()
+                .as_ref()
+                .is_some_and(|solo| solo.stream == index),
+        );
+        let mixer = self.downgrade();
+        solo.connect_clicked(move |solo| {
+            if let Some(mixer) = mixer.upgrade() {
+                mixer.set_solo(solo.is_active().then_some(index));
+            }
+        });
+        controls.append(&solo);
+        layout.append(&controls);
+        let output = gtk::DropDown::from_strings(
+            &sinks
+                .iter()
+                .map(|sink| sink.description.as_str())
+                .collect::<Vec<_>>(),
+        );
+        output.set_tooltip_text(Some(&tr("Plays on")));
+        output.set_selected(position(sinks, stream));
+        let sink = Rc::new(Cell::new(stream.sink));
+        output.connect_selected_notify({
+            let sinks = sinks
+                .iter()
+                .map(|sink| (sink.index, sink.name.clone()))
+                .collect::<Vec<_>>();
+            let sink = sink.clone();
+            move |output| {
+                let Some((picked, name)) = sinks.get(output.selected() as usize) else {
+                    return;
+                };
+                // refreshing selects the sink it plays on already
+                if *picked != sink.get() {
+                    sink.set(*picked);
+                    let name = name.clone();
+                    AudioControls::detach(move || AudioControls::move_stream(index, &name));
+                }
+            }
+        });
+        layout.append(&output);
+        // what it negotiated, to check bit-perfect setups against
+        let format = gtk::Label::new(Some(&stream.format_description()));
+        format.set_halign(gtk::Align::Start);
+        format.set_selectable(true);
+        format.add_css_class("dim-label");
+        let details = gtk::Expander::new(Some(&tr("Format")));
+        details.set_child(Some(&format));
+        layout.append(&details);
+        self.imp().list.append(&layout);
+        imp::Row {
+            stream: index,
+            label,
+            scale,
+            mute,
+            solo,
+            output,
+            sink,
+            format,
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Volume from anywhere over the window: scrolling changes the output's volume by
+//! the smallest configured step, like scrolling over the tray icon, the arrow keys
+//! nudge it and `m` mutes it. Widgets of their own that scroll or take keys, like
+//! sliders and text entries, keep them.
+use gtk::{gdk, glib, prelude::*};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    config,
+    devices::DeviceKind,
+    DiffValue,
+};
+
+fn step() -> i32 {
+    config::get().steps.iter().min().copied().unwrap_or(5) as i32
+}
+
+/// Whether the focused widget takes the arrow keys and letters itself.
+fn takes_keys(window: &gtk::Window) -> bool {
+    let Some(focus) = GtkWindowExt::focus(window) else {
+        return false;
+    };
+    focus.is::<gtk::Text>()
+        || focus.is::<gtk::TextView>()
+        || focus.is::<gtk::Range>()
+        || focus.is::<gtk::SpinButton>()
+        || focus.is::<gtk::DropDown>()
+        || focus.is::<gtk::ListView>()
+        || focus.ancestor(gtk::ListBox::static_type()).is_some()
+        || focus.ancestor(gtk::Popover::static_type()).is_some()
+}
+
+/// Handles scrolling and volume keys over `window`.
+pub fn attach(window: &gtk::Window, dispatcher: Dispatcher) {
+    let scroll = gtk::EventControllerScroll::new(
+        gtk::EventControllerScrollFlags::VERTICAL | gtk::EventControllerScrollFlags::DISCRETE,
+    );
+    scroll.connect_scroll({
+        let dispatcher = dispatcher.clone();
+        move |_, _, dy| {
+            if dy == 0. {
+                return glib::signal::Inhibit(false);
+            }
+            // scrolling down turns it down
+            let diff = DiffValue(-step() * dy.signum() as i32);
+            dispatcher.dispatch(Action::ChangeVolume(diff));
+            glib::signal::Inhibit(true)
+        }
+    });
+    window.add_controller(scroll);
+
+    let keys = gtk::EventControllerKey::new();
+    // ahead of the window's own focus moving, which takes the arrows too
+    keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+    keys.connect_key_pressed(move |keys, key, _, modifiers| {
+        let modified = modifiers.intersects(
+            gdk::ModifierType::CONTROL_MASK
+                | gdk::ModifierType::ALT_MASK
+                | gdk::ModifierType::SUPER_MASK,
+        );
+        if modified || keys.widget().downcast_ref().is_some_and(takes_keys) {
+            return glib::signal::Inhibit(false);
+        }
+        let action = match key {
+            gdk::Key::Up | gdk::Key::KP_Up | gdk::Key::Right | gdk::Key::KP_Right => {
+                Action::ChangeVolume(DiffValue(step()))
+            }
+            gdk::Key::Down | gdk::Key::KP_Down | gdk::Key::Left | gdk::Key::KP_Left => {
+                Action::ChangeVolume(DiffValue(-step()))
+            }
+            gdk::Key::m | gdk::Key::M => Action::ToggleMute(DeviceKind::Sink),
+            _ => return glib::signal::Inhibit(false),
+        };
+        dispatcher.dispatch(action);
+        glib::signal::Inhibit(true)
+    });
+    window.add_controller(keys);
+}
@@ -0,0 +1,159 @@
+//! Default devices per compositor workspace, e.g. the TV for a workspace called
+//! `media`. The switching itself is [`crate::rules::WorkspaceProfiles`].
+use std::collections::BTreeMap;
+
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    compositor::Workspace,
+    devices::{Device, DeviceKind},
+    extensions::{resource, suspense},
+    i18n::{tr, tr_with},
+    state::AppState,
+};
+
+/// Profiles are keyed by workspace name, or by this and the display's name
+/// for every workspace on the display.
+const OUTPUT_PREFIX: &str = "output:";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceProfile {
+    pub sink: Option<String>,
+    pub source: Option<String>,
+}
+
+pub fn output_key(output: &str) -> String {
+    format!("{OUTPUT_PREFIX}{output}")
+}
+
+/// The workspace's own profile, or else the one of the display it's on.
+pub fn profile_for<'a>(
+    profiles: &'a BTreeMap<String, WorkspaceProfile>,
+    workspace: &Workspace,
+) -> Option<&'a WorkspaceProfile> {
+    profiles
+        .get(&workspace.name)
+        .or_else(|| profiles.get(&output_key(&workspace.output)))
+}
+
+fn describe(devices: &[Device], name: &str) -> String {
+    devices
+        .iter()
+        .find(|device| device.name == name)
+        .map_or_else(|| name.to_owned(), |device| device.description.clone())
+}
+
+/// Expander pinning the current defaults to the focused workspace or display.
+pub fn workspace_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let current = gtk::Label::new(None);
+    current.set_halign(gtk::Align::Start);
+    current.set_wrap(true);
+    layout.append(&current);
+    let buttons = gtk::Box::new(Orientation::Horizontal, 6);
+    let pin_workspace = gtk::Button::with_label(&tr("Use these devices on this workspace"));
+    buttons.append(&pin_workspace);
+    let pin_output = gtk::Button::with_label(&tr("…on this display"));
+    buttons.append(&pin_output);
+    layout.append(&buttons);
+
+    for (button, by_output) in [(&pin_workspace, false), (&pin_output, true)] {
+        let dispatcher = dispatcher.clone();
+        button.connect_clicked(move |_| {
+            let Some(workspace) = state.workspace.get_untracked() else {
+                return;
+            };
+            let name = |device: RwSignal<Option<Device>>| {
+                device.with_untracked(|device| device.as_ref().map(|device| device.name.clone()))
+            };
+            dispatcher.dispatch(Action::SetWorkspaceProfile {
+                key: match by_output {
+                    true => output_key(&workspace.output),
+                    false => workspace.name,
+                },
+                profile: Some(WorkspaceProfile {
+                    sink: name(state.default_sink),
+                    source: name(state.default_source),
+                }),
+            });
+        });
+    }
+    create_effect(cx, move |_| match state.workspace.get() {
+        Some(workspace) => {
+            buttons.set_sensitive(true);
+            current.set_text(&tr_with(
+                "Workspace {workspace} on {output}",
+                &[
+                    ("workspace", &workspace.name),
+                    ("output", &workspace.output),
+                ],
+            ));
+        }
+        None => {
+            buttons.set_sensitive(false);
+            current.set_text(&tr(
+                "Workspace profiles need sway, Hyprland or an X11 window manager.",
+            ));
+        }
+    });
+
+    let profiles = create_memo(cx, move |_| {
+        state.device_settings.with(|store| store.workspaces.clone())
+    });
+    // listing devices takes a while on a busy server
+    let devices = resource(
+        cx,
+        move || profiles.track(),
+        |()| {
+            Ok((
+                Device::list(DeviceKind::Sink)?,
+                Device::list(DeviceKind::Source)?,
+            ))
+        },
+    );
+    layout.append(&suspense(cx, devices, move |_, (sinks, sources)| {
+        let rows = gtk::Box::new(Orientation::Vertical, 4);
+        for (key, profile) in profiles.get() {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            let devices = [
+                profile.sink.map(|sink| describe(sinks, &sink)),
+                profile.source.map(|source| describe(sources, &source)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(", ");
+            let target = match key.strip_prefix(OUTPUT_PREFIX) {
+                Some(output) => tr_with("Display {output}", &[("output", output)]),
+                None => tr_with("Workspace {workspace}", &[("workspace", &key)]),
+            };
+            let label = gtk::Label::new(Some(&format!("{target}: {devices}")));
+            label.set_hexpand(true);
+            label.set_halign(gtk::Align::Start);
+            row.append(&label);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            let dispatcher = dispatcher.clone();
+            remove.connect_clicked(move |_| {
+                dispatcher.dispatch(Action::SetWorkspaceProfile {
+                    key: key.clone(),
+                    profile: None,
+                })
+            });
+            row.append(&remove);
+            rows.append(&row);
+        }
+        rows
+    }));
+
+    gtk::Expander::builder()
+        .label("Workspaces")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}
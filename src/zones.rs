@@ -0,0 +1,214 @@
+//! Multi-room zones: named sets of sinks, local or network ones alike, played to
+//! through a combine sink of their own. Combine sinks don't outlive the server,
+//! so zones are loaded again on startup.
+use eyre::{eyre, Result};
+use gtk::{prelude::*, Orientation};
+use leptos::*;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    actions::{Action, Dispatcher},
+    devices::{Device, DeviceKind},
+    i18n::tr,
+    modules::{self, Module},
+    state::AppState,
+    streams::Stream,
+    AudioControls,
+};
+
+/// Prefix of the object name of zone sinks, `zone:<name>`.
+const ZONE: &str = "zone";
+
+/// `name` with anything but letters and digits replaced, so it fits in module arguments.
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Name of the combine sink playing to zone `name`.
+pub fn sink_name(name: &str) -> String {
+    format!("{}.zone.{}", clap::crate_name!(), slug(name))
+}
+
+/// Combine sink modules of zones, with the slug of their zone.
+fn loaded() -> Result<Vec<(String, Module)>> {
+    Ok(Module::list()?
+        .into_iter()
+        .filter_map(|module| {
+            let slug = module
+                .object()?
+                .strip_prefix(ZONE)?
+                .strip_prefix(':')?
+                .to_owned();
+            Some((slug, module))
+        })
+        .collect())
+}
+
+#[instrument(ret, err)]
+pub fn load(name: &str, members: &[String]) -> Result<u32> {
+    let description = format!("Zone: {name}").replace(['\'', '"'], "");
+    Module::load(
+        "module-combine-sink",
+        &[
+            format!("sink_name={}", sink_name(name)),
+            format!("slaves={}", members.join(",")),
+            format!(
+                "sink_properties=\"device.description='{description}' {}\"",
+                Module::tag(&format!("{ZONE}:{}", slug(name)))
+            ),
+        ],
+    )
+}
+
+pub fn unload(name: &str) -> Result<()> {
+    loaded()?
+        .into_iter()
+        .filter(|(loaded, _)| *loaded == slug(name))
+        .try_for_each(|(_, module)| Module::unload(module.index))
+}
+
+/// Loads every zone of `zones` that isn't yet.
+pub fn restore<'a>(zones: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>) {
+    let present = match loaded() {
+        Ok(loaded) => loaded.into_iter().map(|(slug, _)| slug).collect::<Vec<_>>(),
+        Err(error) => return warn!(?error, "restoring zones"),
+    };
+    for (zone, members) in zones {
+        if !present.contains(&slug(zone)) {
+            info!("restoring zone {zone}");
+            load(zone, members).ok();
+        }
+    }
+}
+
+/// Moves everything that is playing over to zone `name`, and makes it the default. Streams of modules,
+/// like the ones a combine sink feeds its members with, stay where they are.
+#[instrument(ret, err)]
+pub fn send_playback(name: &str) -> Result<()> {
+    let sinks = Device::list(DeviceKind::Sink)?;
+    let zone = sink_name(name);
+    let Some(target) = sinks.iter().find(|sink| sink.name == zone) else {
+        return Err(eyre!("zone {name} isn't loaded"));
+    };
+    Stream::list()?
+        .iter()
+        .filter(|stream| stream.sink != target.index)
+        .filter(|stream| {
+            !stream.properties.contains_key("pulse.module.id")
+                && !stream.properties.contains_key(modules::OBJECT_TAG)
+        })
+        .try_for_each(|stream| AudioControls::move_stream(stream.index, &zone))?;
+    // and whatever starts playing next
+    AudioControls::set_default(DeviceKind::Sink, &zone)
+}
+
+/// Expander with a row per zone, volume and a button sending playback there,
+/// and a form for defining new ones.
+pub fn zone_panel(cx: Scope, state: AppState, dispatcher: Dispatcher) -> gtk::Expander {
+    let layout = gtk::Box::new(Orientation::Vertical, 6);
+    let rows = gtk::Box::new(Orientation::Vertical, 6);
+    layout.append(&rows);
+
+    let sinks = Device::list(DeviceKind::Sink)
+        .map_err(|error| warn!(?error, "listing sinks for zones"))
+        .unwrap_or_default();
+    let form = gtk::Box::new(Orientation::Vertical, 4);
+    let name = gtk::Entry::builder()
+        .placeholder_text(tr("Zone name"))
+        .build();
+    form.append(&name);
+    let choices = sinks
+        .iter()
+        // zones are made of devices, not each other
+        .filter(|sink| {
+            !sink
+                .name
+                .starts_with(&format!("{}.zone.", clap::crate_name!()))
+        })
+        .map(|sink| {
+            let choice = gtk::CheckButton::with_label(&sink.description);
+            form.append(&choice);
+            (sink.name.clone(), choice)
+        })
+        .collect::<Vec<_>>();
+    let create = gtk::Button::with_label(&tr("Create zone"));
+    create.set_halign(gtk::Align::Start);
+    form.append(&create);
+    layout.append(&form);
+    create.connect_clicked({
+        let dispatcher = dispatcher.clone();
+        move |_| {
+            let members = choices
+                .iter()
+                .filter(|(_, choice)| choice.is_active())
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            let zone = name.text().trim().to_owned();
+            if zone.is_empty() || members.is_empty() {
+                return warn!("a zone needs a name and at least one output");
+            }
+            choices
+                .iter()
+                .for_each(|(_, choice)| choice.set_active(false));
+            name.set_text("");
+            dispatcher.dispatch(Action::CreateZone { zone, members });
+        }
+    });
+
+    let zones = create_memo(cx, move |_| {
+        state.device_settings.with(|store| store.zones.clone())
+    });
+    create_effect(cx, move |_| {
+        let zones = zones.get();
+        while let Some(row) = rows.first_child() {
+            rows.remove(&row);
+        }
+        let sinks = Device::list(DeviceKind::Sink).unwrap_or_default();
+        for zone in zones.into_keys() {
+            let row = gtk::Box::new(Orientation::Horizontal, 6);
+            row.append(&gtk::Label::new(Some(&zone)));
+            let volume = gtk::Scale::with_range(Orientation::Horizontal, 0., 100., 1.);
+            volume.set_hexpand(true);
+            let sink = sink_name(&zone);
+            match sinks.iter().find(|candidate| candidate.name == sink) {
+                Some(sink) => volume.set_value(sink.volume_percent().into()),
+                None => volume.set_sensitive(false),
+            }
+            volume.connect_value_changed({
+                let dispatcher = dispatcher.clone();
+                move |volume| {
+                    dispatcher.dispatch(Action::SetVolume(
+                        DeviceKind::Sink,
+                        sink.clone(),
+                        volume.value() as u32,
+                    ))
+                }
+            });
+            row.append(&volume);
+            let play = gtk::Button::with_label(&tr("Play here"));
+            play.connect_clicked({
+                let dispatcher = dispatcher.clone();
+                let zone = zone.clone();
+                move |_| dispatcher.dispatch(Action::SendToZone(zone.clone()))
+            });
+            row.append(&play);
+            let remove = gtk::Button::from_icon_name("list-remove-symbolic");
+            remove.connect_clicked({
+                let dispatcher = dispatcher.clone();
+                move |_| dispatcher.dispatch(Action::RemoveZone(zone.clone()))
+            });
+            row.append(&remove);
+            rows.append(&row);
+        }
+    });
+
+    gtk::Expander::builder()
+        .label("Zones")
+        .child(&layout)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(12)
+        .build()
+}